@@ -13,7 +13,7 @@ main() {
   curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | sh -s -- -y
   HOME="/root" source "/root/.cargo/env"
   sudo apt-get install -y m4 make gcc
-  rustup toolchain install nightly-2022-03-08
+  rustup toolchain install 1.67.0
   echo $(date -Is) prereqs >> "$TMPDIR/output/timings"
 
 
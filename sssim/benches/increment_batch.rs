@@ -0,0 +1,64 @@
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
+use sssim::accumulator::rsa::Accumulator as RsaAccumulator;
+use sssim::accumulator::{Accumulator, BatchAccumulator};
+use sssim::hash_to_prime::hash_to_prime;
+use sssim::multiset::MultiSet;
+use sssim::primitives::{Domain, Prime, RsaGroup};
+
+type Acc = RsaAccumulator<RsaGroup>;
+
+/// Each `increment` updates every cached proof, so calling it `k` times
+/// costs about `k * size` group operations; skip runs bigger than this.
+const MAX_SEQUENTIAL_WORK: usize = 100_000;
+
+fn primes(prefix: &str, count: usize) -> Vec<Prime> {
+    (0..count)
+        .map(|i| hash_to_prime(Domain::PackagePrime, format!("{prefix}{i}").as_bytes()))
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("increment_batch");
+    group.sample_size(10);
+
+    for size in [10, 100, 1000] {
+        let accumulator = Acc::import(MultiSet::from(primes("package", size)));
+        for k in [1, 10, 100, 1000] {
+            let members = primes("new", k);
+            let id = format!("{size}/{k}");
+            group.throughput(Throughput::Elements(k as u64));
+
+            if size * k <= MAX_SEQUENTIAL_WORK {
+                group.bench_function(BenchmarkId::new("increment", &id), |b| {
+                    b.iter_batched(
+                        || (accumulator.clone(), members.clone()),
+                        |(mut accumulator, members)| {
+                            for member in members {
+                                accumulator.increment(member);
+                            }
+                            accumulator
+                        },
+                        BatchSize::LargeInput,
+                    )
+                });
+            }
+            group.bench_function(BenchmarkId::new("increment_batch", &id), |b| {
+                b.iter_batched(
+                    || (accumulator.clone(), members.clone()),
+                    |(mut accumulator, members)| {
+                        black_box(accumulator.increment_batch(members));
+                        accumulator
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+    }
+    group.finish()
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
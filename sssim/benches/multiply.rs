@@ -2,13 +2,14 @@ use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use rug::Integer;
 use sssim::accumulator::rsa::{multiply_stuff, multiply_stuff2};
 use sssim::hash_to_prime::hash_to_prime;
+use sssim::primitives::Domain;
 use sssim::primitives::Prime;
 use std::iter::successors;
 
 pub fn criterion_benchmark(c: &mut Criterion) {
     let values: Vec<Integer> = (0..100u32)
         .into_iter()
-        .map(|x| hash_to_prime(&format!("{x}").as_bytes()))
+        .map(|x| hash_to_prime(Domain::PackagePrime, format!("{x}").as_bytes()))
         .collect::<Result<Vec<Prime>, _>>()
         .unwrap()
         .into_iter()
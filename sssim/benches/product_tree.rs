@@ -0,0 +1,52 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rug::{rand::RandState, Integer};
+use sssim::primitives::ProductTree;
+
+/// Roughly the size of a hashed package (see `hash_to_prime`).
+const LEAF_BITS: u32 = 256;
+
+fn leaves(count: usize) -> Vec<Integer> {
+    let mut rand = RandState::new();
+    (0..count)
+        .map(|_| Integer::from(Integer::random_bits(LEAF_BITS, &mut rand)) | 1u32)
+        .collect()
+}
+
+fn sequential_product(leaves: &[Integer]) -> Integer {
+    leaves
+        .iter()
+        .fold(Integer::from(1u8), |acc, leaf| acc * leaf)
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("product_tree");
+    group.sample_size(10);
+
+    // The running product is quadratic, so only try it on the small set.
+    let small = leaves(10_000);
+    group.bench_function(BenchmarkId::new("sequential", small.len()), |b| {
+        b.iter(|| sequential_product(black_box(&small)))
+    });
+
+    for count in [10_000, 1_000_000] {
+        let leaves = leaves(count);
+        group.bench_function(BenchmarkId::new("build", count), |b| {
+            b.iter(|| ProductTree::new(black_box(&leaves)))
+        });
+
+        // `exponent / member^k`, as for stateless proofs: dividing the whole
+        // product vs. multiplying the siblings back up.
+        let tree = ProductTree::new(&leaves);
+        let index = count / 3;
+        group.bench_function(BenchmarkId::new("divide", count), |b| {
+            b.iter(|| Integer::from(black_box(tree.product()) / &leaves[index]))
+        });
+        group.bench_function(BenchmarkId::new("product_without", count), |b| {
+            b.iter(|| black_box(&tree).product_without(index))
+        });
+    }
+    group.finish()
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -17,7 +17,7 @@
         pkgs = import nixpkgs { inherit system; };
         rust = fenix.packages.${system}.fromToolchainFile {
           dir = ./.;
-          sha256 = "sha256-uYy74UmBBs4KaKyv2mc2cfgdbKDAEcC0CCBS6I2jeX0=";
+          sha256 = "sha256-kO4mCJSbptmQBUpCIAgvFZFIl6TzCXX97QM48RaZgyY=";
         };
         naersk-lib = naersk.lib.${system}.override {
           rustc = rust;
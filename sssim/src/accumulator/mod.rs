@@ -5,7 +5,14 @@ pub mod rsa;
 
 //pub use rsa_optimized::CachingAccumulator;
 
-use crate::{multiset::MultiSet, primitives::Prime, util::Information};
+use rug::Integer;
+
+use crate::{
+    authenticator::{CdnObjects, InvariantError},
+    multiset::MultiSet,
+    primitives::Prime,
+    util::Information,
+};
 
 pub trait Accumulator {
     type Digest: Clone + Debug;
@@ -13,18 +20,39 @@ pub trait Accumulator {
     type AppendOnlyWitness;
     type NonMembershipWitness;
 
+    /// Name of the authenticator built on this accumulator.
+    const NAME: &'static str;
+
+    /// Whether [`Self::prove`] serves precomputed proofs (which could sit on a
+    /// CDN) rather than computing them per request.
+    const PRECOMPUTED_PROOFS: bool;
+
     fn digest(&self) -> &Self::Digest;
 
     fn increment(&mut self, member: Prime);
 
+    /// Add all of `members` as a single step of history (one new digest), if
+    /// that's faster than an [`Self::increment`] each.
+    ///
+    /// Returns `false`, having added nothing, if this accumulator can't.
+    fn increment_step(&mut self, _members: &[Prime]) -> bool {
+        false
+    }
+
     #[must_use]
     fn prove_append_only(&self, other: &Self::Digest) -> Self::AppendOnlyWitness;
 
+    /// How many precomputed steps `witness` strings together.
+    fn append_only_hops(witness: &Self::AppendOnlyWitness) -> usize;
+
+    /// Takes `&self` so several threads can prove at once; accumulators that
+    /// cache proofs as they go keep the cache behind a lock (see
+    /// [`crate::util::Cache`]).
     #[must_use]
-    fn prove(&mut self, member: &Prime, revision: u32) -> Option<Self::Witness>;
+    fn prove(&self, member: &Prime, revision: u32) -> Option<Self::Witness>;
 
     #[must_use]
-    fn prove_nonmember(&mut self, value: &Prime) -> Option<Self::NonMembershipWitness>;
+    fn prove_nonmember(&self, value: &Prime) -> Option<Self::NonMembershipWitness>;
 
     #[must_use]
     fn get(&self, member: &Prime) -> u32;
@@ -35,6 +63,37 @@ pub trait Accumulator {
     fn verify(digest: &Self::Digest, member: &Prime, revision: u32, witness: Self::Witness)
         -> bool;
 
+    /// Check that `value` isn't in the accumulator at `digest` at all.
+    #[must_use]
+    fn verify_nonmember(
+        digest: &Self::Digest,
+        value: &Prime,
+        witness: Self::NonMembershipWitness,
+    ) -> bool;
+
+    /// Bring a client-held `witness` for `member` up to date (client-side).
+    ///
+    /// `witness` must be valid against `digest`; afterwards, it's valid against
+    /// `digest` with `added` accumulated (and the revision of `member` goes up
+    /// by the number of times it appears in `added`).
+    fn update_witness(
+        digest: &Self::Digest,
+        member: &Prime,
+        witness: &mut Self::Witness,
+        added: &[Prime],
+    );
+
+    /// Like [`Self::update_witness`], but given only the product of `added`.
+    ///
+    /// Returns how many copies of `member` the product had (so the client
+    /// knows its new revision).
+    fn update_witness_from_product(
+        digest: &Self::Digest,
+        member: &Prime,
+        witness: &mut Self::Witness,
+        product: &Integer,
+    ) -> u32;
+
     #[must_use]
     fn verify_append_only(
         digest: &Self::Digest,
@@ -42,7 +101,40 @@ pub trait Accumulator {
         new_state: &Self::Digest,
     ) -> bool;
 
+    /// Like [`Self::verify_append_only`], but may check all the steps of a
+    /// long proof at once (with a small, randomized soundness error).
+    #[must_use]
+    fn verify_append_only_batch(
+        digest: &Self::Digest,
+        proof: &Self::AppendOnlyWitness,
+        new_state: &Self::Digest,
+    ) -> bool {
+        Self::verify_append_only(digest, proof, new_state)
+    }
+
     fn cdn_size(&self) -> Information;
+
+    /// Size of the public parameters clients need ahead of time.
+    fn parameters_size() -> Information;
+
+    /// The individual objects that add up to [`Self::cdn_size`].
+    fn cdn_objects(&self) -> CdnObjects<'_>;
+
+    /// Stop sharing state with clones (see [`crate::util::Shared`]).
+    fn unshare(&mut self) {}
+
+    /// Forget the history before the `horizon`th digest (counting the
+    /// imported digest as 0 and each increment as one more).
+    ///
+    /// Afterwards, [`Self::prove_append_only`] only works from digests at or
+    /// after the horizon.
+    fn prune_history(&mut self, _horizon: usize) {}
+
+    /// Check that cached state agrees with the members (see
+    /// [`Authenticator::check_invariants`](crate::authenticator::Authenticator::check_invariants)).
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        Ok(())
+    }
 }
 
 pub trait BatchAccumulator: Accumulator {
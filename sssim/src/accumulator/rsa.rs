@@ -1,21 +1,24 @@
 #![allow(dead_code)]
 use crate::accumulator::{Accumulator as AccumulatorTrait, BatchAccumulator};
+use crate::authenticator::{CdnObject, CdnObjects, InvariantError};
+use crate::counters::{self, Op};
+use crate::ct;
 use crate::poke;
-use crate::primitives::{Collector, Group, Prime, SkipList};
+use crate::primitives::{Collector, Group, Prime, ProductTree, SkipList};
 use crate::util::assume_data_size_for_map;
-use crate::util::{assume_data_size_for_vec, DataSized};
+use crate::util::{assume_data_size_for_vec, byte, Cache, DataSized, Shared};
 use crate::{multiset::MultiSet, util::Information};
 use rayon::prelude::*;
 use rug::Complete;
 use rug::{ops::Pow, Integer};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::iter::zip;
 use uom::ConstZero;
 
 use indicatif::ProgressBar;
 
-#[derive(Clone, Default, Debug, PartialEq, Eq, Hash, Serialize)]
+#[derive(Clone, Default, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Digest<G>(G);
 
 impl<G> DataSized for Digest<G>
@@ -27,7 +30,7 @@ where
     }
 }
 
-#[derive(Clone, Serialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 struct MembershipWitness<G>(G);
 
 impl<G: Group> MembershipWitness<G> {
@@ -45,7 +48,7 @@ where
     }
 }
 
-#[derive(Clone, Serialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct NonMembershipWitness<G> {
     exp: Integer,
     base: G,
@@ -191,18 +194,22 @@ struct Intermediate {
 
 impl Intermediate {
     fn from_members(members: &[Member]) -> Self {
-        let mut index = Integer::from(1u8);
-        let mut exponent = Integer::from(1u8);
-        for member in members {
-            index *= &member.index;
-            exponent *= &member.value;
+        let (indexes, exponents) = Self::trees(members);
+        Self {
+            index: indexes.into_product(),
+            exponent: exponents.into_product(),
         }
+    }
 
-        Self { index, exponent }
+    /// Product trees over the indexes and values of `members`.
+    fn trees(members: &[Member]) -> (ProductTree, ProductTree) {
+        let indexes: Vec<Integer> = members.iter().map(|m| m.index.clone()).collect();
+        let values: Vec<Integer> = members.iter().map(|m| m.value.clone()).collect();
+        rayon::join(|| ProductTree::new(&indexes), || ProductTree::new(&values))
     }
 }
 
-#[derive(Clone, Serialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Witness<G> {
     member: Option<MembershipWitness<G>>,
     nonmember: NonMembershipWitness<G>,
@@ -257,7 +264,7 @@ impl<G: Group + 'static> Digest<G> {
 
     fn verify_member(&self, index: &Integer, count: u32, witness: MembershipWitness<G>) -> bool {
         let member = Integer::from(index.pow(count));
-        witness.0 * &member == self.0
+        (witness.0 * &member).ct_eq(&self.0)
     }
 
     fn verify_member_option(&self, member: &Member, witness: Option<MembershipWitness<G>>) -> bool {
@@ -273,24 +280,26 @@ impl<G: Group + 'static> Digest<G> {
         // https://link.springer.com/content/pdf/10.1007/978-3-540-72738-5_17.pdf
         let l = self.0.clone() * &witness.exp;
         let r = witness.base * &member;
-        &(l + r) == G::one()
+        (l + r).ct_eq(G::one())
     }
 
     fn verify(&self, member: &Member, witness: Witness<G>) -> bool {
         match witness.member {
-            Some(mem_pf) => {
-                self.verify_member(&member.index, member.count, mem_pf.clone())
-                    && Digest(mem_pf.0).verify_nonmember(&member.index, witness.nonmember)
-            }
+            Some(mem_pf) => ct::and(
+                self.verify_member(&member.index, member.count, mem_pf.clone()),
+                || Digest(mem_pf.0).verify_nonmember(&member.index, witness.nonmember),
+            ),
             None => {
                 // Special-case: revision = 0 has no membership proof.
-                member.count == 0 && self.verify_nonmember(&member.index, witness.nonmember)
+                ct::and(member.count == 0, || {
+                    self.verify_nonmember(&member.index, witness.nonmember)
+                })
             }
         }
     }
 }
 
-#[derive(Debug, Default, Clone, Serialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct AppendOnlyWitness<G> {
     inner: Vec<(poke::Proof<G>, G)>,
 }
@@ -305,7 +314,7 @@ where
     }
 }
 
-#[derive(Clone, Serialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct BatchWitness<W> {
     inner: HashMap<Prime, W>,
 }
@@ -355,34 +364,41 @@ where
 
         // multiplicity of each member in the batch (size k)
         let mut members_hashmap = HashMap::<Prime, u32>::default();
-        // exponent: product of all members in the batch
-        let exponent = {
-            let mut exponent = Integer::from(1u8);
-            for member in members {
-                exponent *= member.inner().clone();
-                *members_hashmap.entry(member).or_insert(0) += 1;
-            }
-            Prime::new_unchecked(exponent)
-        };
+        for member in members {
+            *members_hashmap.entry(member).or_insert(0) += 1;
+        }
 
-        // TODO(meh):
-        // idea: k log k trick to multiply everything together
+        // One leaf per unique member, so that the product of the batch *but*
+        // one member comes from its siblings rather than a division:
         // [0]   [1]   [2]   [3] ->
         // [123] [023] [013] [012]
+        let batch: Vec<_> = members_hashmap.iter().collect();
+        let leaves: Vec<_> = batch
+            .iter()
+            .map(|(member, count)| Integer::from(member.inner().pow(**count)))
+            .collect();
+        let tree = ProductTree::new(&leaves);
+        let leaf_indexes: HashMap<&Prime, usize> = batch
+            .iter()
+            .enumerate()
+            .map(|(leaf, (member, _))| (*member, leaf))
+            .collect();
+        // exponent: product of all members in the batch
+        let exponent = Prime::new_unchecked(tree.product().clone());
 
         // Update all *existing* proofs for this batch (O(n), fixed-ish amount
         // of work in each iteration).
         self.proof_cache.par_iter_mut().for_each(|(value, proof)| {
-            let digest = Digest(proof.member.clone().unwrap().0);
-            proof.nonmember.update(value, exponent.clone(), digest);
-
-            // for all members that equal value: divide by member
-            let update_val = match members_hashmap.get(value) {
-                Some(count) => Prime::new_unchecked(
-                    exponent.clone().into_inner() / Integer::from(value.inner().pow(count)),
-                ),
+            // for all members that equal value: leave that member out
+            let update_val = match leaf_indexes.get(value) {
+                Some(&leaf) => Prime::new_unchecked(tree.product_without(leaf)),
                 None => exponent.clone(),
             };
+
+            // The nonmembership proof is against the membership witness (the
+            // digest without `value`), so it only sees the other members.
+            let digest = Digest(proof.member.clone().unwrap().0);
+            proof.nonmember.update(value, update_val.clone(), digest);
             proof.member.as_mut().unwrap().update(&update_val);
         });
 
@@ -390,21 +406,21 @@ where
         // RSA group multiplications. Can't think of any way to batch this.
         let newly_added = members_hashmap
             .iter()
-            .filter_map(|(member, count)| {
+            .filter_map(|(member, _)| {
                 if self.proof_cache.get_mut(member).is_none() {
                     let nonmember_proof = self
                         .nonmember_proof_cache
-                        .remove(&member)
+                        .remove(member)
                         .expect("we compute nonmembership proofs as-we-go");
-                    return Some((member.clone(), *count, nonmember_proof));
+                    return Some((member.clone(), nonmember_proof));
                 }
                 None
             })
             .collect::<Vec<_>>();
         let new_proofs: Vec<(Prime, Witness<_>)> = newly_added
             .into_par_iter()
-            .map(|(member, count, mut nonmember_proof)| {
-                let exponent = exponent.inner().clone() / member.inner().pow(count).complete();
+            .map(|(member, mut nonmember_proof)| {
+                let exponent = tree.product_without(leaf_indexes[&member]);
                 let membership_proof = MembershipWitness(self.digest.0.clone() * &exponent);
                 nonmember_proof.update(&member, Prime::new_unchecked(exponent), old_digest.clone());
                 let proof = Witness {
@@ -419,9 +435,9 @@ where
         }
 
         self.digest.0 *= exponent.inner();
-        self.exponent *= exponent.inner();
 
         for (member, count) in members_hashmap {
+            self.accumulate_exponent(&member, count);
             for _ in 0..count {
                 self.multiset.insert(member.clone());
             }
@@ -437,8 +453,8 @@ where
             .insert(self.digest.clone(), self.history.len() - 1);
 
         // Invalidate the nonmembership proof cache.
-        self.nonmember_proof_cache = Default::default();
-        debug_assert_eq!(self.digest.0, G::one().clone() * &self.exponent);
+        self.nonmember_proof_cache.clear();
+        debug_assert!(self.digest_matches_exponent());
 
         Some(self.prove_append_only(&old_digest))
     }
@@ -464,7 +480,7 @@ where
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct HistoryEntry<G> {
     exponent: Integer,
     end_digest: Digest<G>,
@@ -483,6 +499,7 @@ where
     }
 
     fn collect(&mut self, item: &Self::Item) {
+        counters::record(Op::BigIntMult);
         self.exponent *= item.exponent.clone();
     }
 
@@ -511,19 +528,28 @@ where
 }
 
 // TODO(maybe): shard storage across # cores
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "G: Serialize, SkipList<HistoryEntry<G>>: Serialize",
+    deserialize = "G: Deserialize<'de> + Eq + std::hash::Hash, \
+                   SkipList<HistoryEntry<G>>: Deserialize<'de>"
+))]
 pub struct Accumulator<G>
 where
     HistoryEntry<G>: Collector,
     SkipList<HistoryEntry<G>>: std::fmt::Debug,
 {
     digest: Digest<G>,
-    multiset: MultiSet<Prime>,
-    proof_cache: HashMap<Prime, Witness<G>>,
-    nonmember_proof_cache: HashMap<Prime, NonMembershipWitness<G>>,
-    history: SkipList<HistoryEntry<G>>,
-    digests_to_indexes: HashMap<Digest<G>, usize>,
-    exponent: Integer,
+    multiset: Shared<MultiSet<Prime>>,
+    proof_cache: Shared<HashMap<Prime, Witness<G>>>,
+    nonmember_proof_cache: Cache<Prime, NonMembershipWitness<G>>,
+    history: Shared<SkipList<HistoryEntry<G>>>,
+    digests_to_indexes: Shared<HashMap<Digest<G>, usize>>,
+    /// The product of the members, with one leaf (`member^count`) per
+    /// member, so [`Self::exponent_without`] needn't divide it.
+    exponent: Shared<ProductTree>,
+    /// Which leaf of `exponent` holds each member.
+    leaves: Shared<HashMap<Prime, usize>>,
 }
 
 impl<G> DataSized for Accumulator<G>
@@ -539,62 +565,76 @@ where
         let mut size = self.digest.size() + self.history.size() + self.exponent.size();
         size += self.multiset.size();
         size += assume_data_size_for_map(&self.proof_cache);
-        size += assume_data_size_for_map(&self.nonmember_proof_cache);
+        size += assume_data_size_for_map(&self.nonmember_proof_cache.read());
         size += assume_data_size_for_map(&self.digests_to_indexes);
+        size += assume_data_size_for_map(&self.leaves);
         size
     }
 }
 
+/// Which leaf each of `members` is in a product tree over them (see
+/// [`Intermediate::trees`]).
+fn leaf_indexes(members: &[Member]) -> HashMap<Prime, usize> {
+    members
+        .iter()
+        .enumerate()
+        .map(|(leaf, member)| (Prime::new_unchecked(member.index.clone()), leaf))
+        .collect()
+}
+
+/// `indexes` and `exponents` are product trees over `members` (see
+/// [`Intermediate::trees`]), so we don't recompute the products at each split.
 fn precompute_helper<G: Group + 'static>(
     members: &[Member],
-    foo: &Intermediate,
+    indexes: &ProductTree,
+    exponents: &ProductTree,
     proof: NonMembershipWitness<G>,
     digest: Digest<G>,
     bar: &ProgressBar,
 ) -> Vec<Witness<G>> {
     debug_assert!(!members.is_empty());
-    debug_assert_eq!(foo, &Intermediate::from_members(members));
-    debug_assert!(digest.verify_nonmember(&foo.index, proof.clone()));
+    debug_assert_eq!(indexes.len(), members.len());
+    debug_assert!(digest.verify_nonmember(indexes.product(), proof.clone()));
 
     if members.len() == 1 {
         return vec![Witness::new(MembershipWitness(digest.0), proof)];
     }
 
     let (l, r) = members.split_at(members.len() / 2);
-    let foo_l = Intermediate::from_members(&l);
-    let foo_r = Intermediate::from_members(&r);
-
-    let (digest_r, proof_l) = proof.split(&digest, &foo_l.index, &foo_r.index, &foo_r.exponent);
-    debug_assert!(digest_r.verify_nonmember(&foo_l.index.clone(), proof_l.clone()));
-    debug_assert!(digest_r.verify_member(&foo_r.exponent, 1, MembershipWitness(digest.0.clone())));
-    let (digest_l, proof_r) = proof.split(&digest, &foo_r.index, &foo_l.index, &foo_l.exponent);
-    debug_assert!(digest_l.verify_nonmember(&foo_r.index.clone(), proof_r.clone()));
-    debug_assert!(digest_l.verify_member(&foo_l.exponent, 1, MembershipWitness(digest.0.clone())));
+    let (indexes_l, indexes_r) = indexes.children().unwrap();
+    let (exponents_l, exponents_r) = exponents.children().unwrap();
+    let (index_l, index_r) = (indexes_l.product(), indexes_r.product());
+    let (exponent_l, exponent_r) = (exponents_l.product(), exponents_r.product());
+
+    let (digest_r, proof_l) = proof.split(&digest, index_l, index_r, exponent_r);
+    debug_assert!(digest_r.verify_nonmember(index_l, proof_l.clone()));
+    debug_assert!(digest_r.verify_member(exponent_r, 1, MembershipWitness(digest.0.clone())));
+    let (digest_l, proof_r) = proof.split(&digest, index_r, index_l, exponent_l);
+    debug_assert!(digest_l.verify_nonmember(index_r, proof_r.clone()));
+    debug_assert!(digest_l.verify_member(exponent_l, 1, MembershipWitness(digest.0.clone())));
 
     bar.inc(members.len().try_into().unwrap());
 
     let (mut ret, r_ret) = rayon::join(
-        || precompute_helper(&l, &foo_l, proof_l, digest_r, bar),
-        || precompute_helper(&r, &foo_r, proof_r, digest_l, bar),
+        || precompute_helper(l, indexes_l, exponents_l, proof_l, digest_r, bar),
+        || precompute_helper(r, indexes_r, exponents_r, proof_r, digest_l, bar),
     );
     ret.extend_from_slice(&r_ret);
 
     ret
 }
 
-/// Returns (Vec<Witness>, digest, exponent)
-fn precompute<G: Group + 'static>(
-    members: &[Member],
-) -> (Vec<Witness<G>>, Digest<G>, Intermediate) {
-    let foo = Intermediate::from_members(members);
+/// Returns (Vec<Witness>, digest, product tree over the member values)
+fn precompute<G: Group + 'static>(members: &[Member]) -> (Vec<Witness<G>>, Digest<G>, ProductTree) {
+    let (indexes, exponents) = Intermediate::trees(members);
     if members.len() == 0 {
-        return (vec![], Default::default(), foo);
+        return (vec![], Default::default(), exponents);
     }
 
     let exponent = Integer::from(1u8);
     let digest = Digest::for_exponent(&exponent);
-    let proof = NonMembershipWitness::prove(&exponent, &foo.exponent); // TODO: for_one()
-    debug_assert!(digest.verify_nonmember(&foo.exponent, proof.clone()));
+    let proof = NonMembershipWitness::prove(&exponent, exponents.product()); // TODO: for_one()
+    debug_assert!(digest.verify_nonmember(exponents.product(), proof.clone()));
 
     let bar = if false {
         let height: usize = members.len().ilog2().try_into().unwrap();
@@ -603,7 +643,7 @@ fn precompute<G: Group + 'static>(
         ProgressBar::hidden()
     };
 
-    let witnesses = precompute_helper(members, &foo, proof, digest, &bar);
+    let witnesses = precompute_helper(members, &indexes, &exponents, proof, digest, &bar);
     bar.finish();
 
     let digest = Digest::for_members(members);
@@ -611,23 +651,54 @@ fn precompute<G: Group + 'static>(
         zip(members, &witnesses).all(|(member, witness)| digest.verify(member, witness.clone()))
     );
 
-    (witnesses, digest, foo)
+    (witnesses, digest, exponents)
 }
 
 impl<G: Group + TryFrom<rug::Integer> + 'static> Accumulator<G> {
+    /// The cheap part of [`AccumulatorTrait::check_invariants`] (one
+    /// exponentiation), for `debug_assert`s after every change.
+    fn digest_matches_exponent(&self) -> bool {
+        self.digest.0 == G::one().clone() * self.exponent.product()
+    }
+
+    /// The accumulated exponent with `revision` copies of `member` taken out.
+    ///
+    /// Multiplies up the other members' leaves instead of dividing the whole
+    /// (multi-megabit) exponent.
+    fn exponent_without(&self, member: &Prime, revision: u32) -> Integer {
+        let rest = match self.leaves.get(member) {
+            Some(&leaf) => self.exponent.product_without(leaf),
+            None => return self.exponent.product().clone(),
+        };
+        match self.multiset.get(member) - revision {
+            0 => rest,
+            extra => rest * Integer::from(member.inner().pow(extra)),
+        }
+    }
+
+    /// Multiply `copies` more of `member` into the exponent.
+    fn accumulate_exponent(&mut self, member: &Prime, copies: u32) {
+        let factor = Integer::from(member.inner().pow(copies));
+        match self.leaves.get(member).copied() {
+            Some(leaf) => self.exponent.multiply(leaf, &factor),
+            None => {
+                let leaf = self.exponent.len();
+                self.leaves.insert(member.clone(), leaf);
+                self.exponent.push(factor);
+            }
+        }
+    }
+
+    /// O(1) exponentiations (but of an O(N)-bit exponent).
     #[must_use]
     fn prove_member(&self, member: &Prime, revision: u32) -> Option<MembershipWitness<G>> {
         debug_assert!(<Prime as AsRef<Integer>>::as_ref(member) >= &0);
         if revision > self.multiset.get(member) {
             return None;
         }
-        let mut res = G::default();
-        for (s, count) in self.multiset.iter() {
-            if s != member {
-                res *= &Integer::from(s.clone()).pow(count);
-            }
-        }
-        Some(MembershipWitness(res))
+        // The witness is the digest with `revision` copies of `member` taken out.
+        let rest = self.exponent_without(member, revision);
+        Some(MembershipWitness(G::default() * &rest))
     }
 
     #[must_use]
@@ -642,13 +713,13 @@ impl<G: Group + TryFrom<rug::Integer> + 'static> Accumulator<G> {
 
         // Bezout coefficients:
         // gcd: exp * s + value * t = 1
-        let (gcd, s, t) = Integer::extended_gcd_ref(&self.exponent, value.as_ref()).into();
+        let (gcd, s, t) = Integer::extended_gcd_ref(self.exponent.product(), value.as_ref()).into();
         if gcd != 1u8 {
             unreachable!("value should be coprime with the exponent of the accumulator");
         }
         debug_assert!(&s < value.inner()); // s should be small-ish
 
-        debug_assert_eq!(self.digest.0, G::one().clone() * &self.exponent);
+        debug_assert!(self.digest_matches_exponent());
 
         let d = G::default() * &t;
 
@@ -674,6 +745,9 @@ where
     type AppendOnlyWitness = AppendOnlyWitness<G>;
     type NonMembershipWitness = NonMembershipWitness<G>;
 
+    const NAME: &'static str = "rsa";
+    const PRECOMPUTED_PROOFS: bool = true;
+
     #[must_use]
     fn digest(&self) -> &Self::Digest {
         &self.digest
@@ -708,8 +782,7 @@ where
 
         // Update the digest to add the member.
         self.digest.0 *= member.as_ref();
-        let x: Integer = member.clone().into();
-        self.exponent *= x;
+        self.accumulate_exponent(&member, 1);
         self.multiset.insert(member.clone());
 
         self.history.add(HistoryEntry {
@@ -721,9 +794,23 @@ where
         self.digests_to_indexes
             .insert(self.digest.clone(), self.history.len() - 1);
 
-        debug_assert_eq!(self.digest.0, G::one().clone() * &self.exponent);
+        debug_assert!(self.digest_matches_exponent());
         // Invalidate the nonmembership proof cache.
-        self.nonmember_proof_cache = Default::default();
+        self.nonmember_proof_cache.clear();
+    }
+
+    /// One [`BatchAccumulator::increment_batch`], which updates each cached
+    /// proof once for the whole batch.
+    fn increment_step(&mut self, members: &[Prime]) -> bool {
+        if members.is_empty() {
+            return false;
+        }
+        for member in members {
+            // `increment_batch` expects nonmembership proofs for new members.
+            let _ = self.prove_nonmember(member);
+        }
+        let _ = self.increment_batch(members.iter().cloned());
+        true
     }
 
     #[must_use]
@@ -744,7 +831,11 @@ where
         }
     }
 
-    fn prove(&mut self, member: &Prime, revision: u32) -> Option<Witness<G>> {
+    fn append_only_hops(witness: &Self::AppendOnlyWitness) -> usize {
+        witness.inner.len()
+    }
+
+    fn prove(&self, member: &Prime, revision: u32) -> Option<Witness<G>> {
         if self.multiset.get(member) != revision {
             return None;
         }
@@ -754,28 +845,58 @@ where
         self.proof_cache.get(member).cloned()
     }
 
-    fn prove_nonmember(&mut self, value: &Prime) -> Option<NonMembershipWitness<G>> {
-        if let Some(proof) = self.nonmember_proof_cache.get(value) {
-            return Some(proof.clone());
-        }
-        self.prove_nonmember_uncached(value).map(|proof| {
-            self.nonmember_proof_cache
-                .insert(value.clone(), proof.clone());
-            proof
-        })
+    fn prove_nonmember(&self, value: &Prime) -> Option<NonMembershipWitness<G>> {
+        self.nonmember_proof_cache
+            .get_or_insert_with(value, || self.prove_nonmember_uncached(value))
     }
 
     fn get(&self, member: &Prime) -> u32 {
         self.multiset.get(member)
     }
 
+    /// The exponent is the product of the members, the digest is the
+    /// generator to the exponent, and every cached proof verifies.
+    ///
+    /// O(N) exponentiations.
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        let mut product = Integer::from(1);
+        for (member, count) in self.multiset.iter() {
+            product *= Integer::from(member.inner().pow(*count));
+        }
+        if &product != self.exponent.product() {
+            return Err(InvariantError::new(
+                Self::NAME,
+                "exponent isn't the product of the members",
+            ));
+        }
+        if !self.digest_matches_exponent() {
+            return Err(InvariantError::new(
+                Self::NAME,
+                "digest isn't the generator to the exponent",
+            ));
+        }
+        for (member, proof) in self.proof_cache.iter() {
+            let revision = self.multiset.get(member);
+            if !Self::verify(&self.digest, member, revision, proof.clone()) {
+                return Err(InvariantError::new(
+                    Self::NAME,
+                    format!("cached proof for a member at {revision} doesn't verify"),
+                ));
+            }
+        }
+        Ok(())
+    }
+
     fn import(multiset: MultiSet<Prime>) -> Self {
-        // Precompute membership proofs:
+        // Precompute membership proofs (in sorted order, so the product tree
+        // is the same every run):
         let members: Vec<_> = multiset
-            .iter()
-            .map(|(value, count)| Member::new(value.clone().into(), *count))
+            .sorted()
+            .into_iter()
+            .map(|(value, count)| Member::new(value.clone().into(), count))
             .collect();
-        let (proofs, digest, foo) = precompute(&members);
+        let (proofs, digest, exponent) = precompute(&members);
+        let leaves = leaf_indexes(&members);
 
         let mut proof_cache: HashMap<Prime, Witness<G>> = Default::default();
         for (member, witness) in zip(members, proofs) {
@@ -785,19 +906,20 @@ where
         let mut history = SkipList::<HistoryEntry<G>>::new();
         history.add(HistoryEntry {
             end_digest: digest.clone(),
-            exponent: foo.exponent.clone(),
+            exponent: exponent.product().clone(),
         });
         let mut digests_to_indexes: HashMap<Digest<G>, usize> = Default::default();
         digests_to_indexes.insert(digest.clone(), 0);
-        debug_assert_eq!(digest.0, G::default() * &foo.exponent);
+        debug_assert_eq!(digest.0, G::default() * exponent.product());
         Self {
             digest,
-            multiset,
-            proof_cache,
+            multiset: multiset.into(),
+            proof_cache: proof_cache.into(),
             nonmember_proof_cache: Default::default(),
-            history,
-            digests_to_indexes,
-            exponent: foo.exponent,
+            history: history.into(),
+            digests_to_indexes: digests_to_indexes.into(),
+            exponent: exponent.into(),
+            leaves: leaves.into(),
         }
     }
 
@@ -808,17 +930,86 @@ where
         // (b) member is NOT in the set corresponding to the membership proof for (a)
 
         match witness.member {
-            Some(mem_pf) => {
-                digest.verify_member(&index.inner(), revision, mem_pf.clone())
-                    && Digest(mem_pf.0).verify_nonmember(index.as_ref(), witness.nonmember)
-            }
+            Some(mem_pf) => ct::and(
+                digest.verify_member(&index.inner(), revision, mem_pf.clone()),
+                || Digest(mem_pf.0).verify_nonmember(index.as_ref(), witness.nonmember),
+            ),
             None => {
                 // Special-case: revision = 0 has no membership proof.
-                revision == 0 && digest.verify_nonmember(index.as_ref(), witness.nonmember)
+                ct::and(revision == 0, || {
+                    digest.verify_nonmember(index.as_ref(), witness.nonmember)
+                })
             }
         }
     }
 
+    fn verify_nonmember(
+        digest: &Self::Digest,
+        value: &Prime,
+        witness: NonMembershipWitness<G>,
+    ) -> bool {
+        digest.verify_nonmember(value.as_ref(), witness)
+    }
+
+    /// [LLX07]-style update, as in [`Self::increment`] (but for one witness).
+    fn update_witness(
+        digest: &Self::Digest,
+        member: &Prime,
+        witness: &mut Self::Witness,
+        added: &[Prime],
+    ) {
+        let mut digest = digest.clone();
+        for value in added {
+            match witness.member.as_mut() {
+                Some(membership) => {
+                    let inner = Digest(membership.0.clone());
+                    witness.nonmember.update(member, value.clone(), inner);
+                    if value != member {
+                        membership.update(value);
+                    }
+                }
+                None if value == member => {
+                    // First copy: the membership witness is the digest before it.
+                    witness.member = Some(MembershipWitness(digest.0.clone()));
+                }
+                None => {
+                    witness
+                        .nonmember
+                        .update(member, value.clone(), digest.clone());
+                }
+            }
+            digest.0 *= value.as_ref();
+        }
+    }
+
+    /// Everything in `product` other than `member` goes into both halves of
+    /// the witness at once (the same update as [`BatchAccumulator::increment_batch`]
+    /// does for cached proofs).
+    fn update_witness_from_product(
+        digest: &Self::Digest,
+        member: &Prime,
+        witness: &mut Self::Witness,
+        product: &Integer,
+    ) -> u32 {
+        let (rest, copies) = product.clone().remove_factor(member.inner());
+        // Both halves are against the digest without any copies of `member`.
+        let without_member = match &witness.member {
+            Some(membership) => Digest(membership.0.clone()),
+            None => digest.clone(),
+        };
+        if rest != 1u8 {
+            witness.nonmember.update(
+                member,
+                Prime::new_unchecked(rest.clone()),
+                without_member.clone(),
+            );
+        }
+        if witness.member.is_some() || copies > 0 {
+            witness.member = Some(MembershipWitness(without_member.0 * &rest));
+        }
+        copies
+    }
+
     #[must_use]
     fn verify_append_only(
         digest: &Self::Digest,
@@ -840,13 +1031,32 @@ where
         cur == digest.0
     }
 
+    #[must_use]
+    fn verify_append_only_batch(
+        digest: &Self::Digest,
+        proof: &Self::AppendOnlyWitness,
+        new_state: &Self::Digest,
+    ) -> bool {
+        let mut batch = Vec::with_capacity(proof.inner.len());
+        let mut cur = new_state.0.clone();
+        for (inner_proof, value) in proof.inner.iter().rev() {
+            let instance = poke::Instance {
+                w: cur,
+                u: value.clone(),
+            };
+            batch.push((instance, inner_proof.clone()));
+            cur = value.clone();
+        }
+        cur == digest.0 && poke::ZKUniverse::<G>::default().verify_batch(batch)
+    }
+
     fn cdn_size(&self) -> Information {
         let mut size = Information::ZERO;
-        for (key, value) in &self.nonmember_proof_cache {
+        for (key, value) in self.nonmember_proof_cache.read().iter() {
             size += key.size();
             size += value.size();
         }
-        for (key, value) in &self.proof_cache {
+        for (key, value) in self.proof_cache.iter() {
             size += key.size();
             size += value.size();
         }
@@ -855,6 +1065,250 @@ where
 
         size
     }
+
+    /// The modulus and generator.
+    fn parameters_size() -> Information {
+        Information::new::<byte>(2 * G::bytes())
+    }
+
+    /// One object per cached proof (named by the member's prime, in hex), plus
+    /// the history used for append-only proofs. Proofs are against the
+    /// current digest, so they all change whenever it does.
+    fn cdn_objects(&self) -> CdnObjects<'_> {
+        // Collected, since the cache can't stay locked while the caller reads.
+        let nonmember: Vec<_> = self
+            .nonmember_proof_cache
+            .read()
+            .iter()
+            .map(|(key, value)| {
+                CdnObject::new(
+                    format!("nonmember/{:x}", key.inner()),
+                    key.size() + value.size(),
+                )
+                .with_version(&self.digest)
+            })
+            .collect();
+        let member = self.proof_cache.iter().map(|(key, value)| {
+            CdnObject::new(
+                format!("member/{:x}", key.inner()),
+                key.size() + value.size(),
+            )
+            .with_version(&self.digest)
+        });
+        let history = CdnObject::new("history", self.history.size());
+        Box::new(
+            nonmember
+                .into_iter()
+                .chain(member)
+                .chain(std::iter::once(history)),
+        )
+    }
+
+    fn unshare(&mut self) {
+        self.multiset.unshare();
+        self.proof_cache.unshare();
+        self.history.unshare();
+        self.digests_to_indexes.unshare();
+        self.exponent.unshare();
+        self.leaves.unshare();
+    }
+
+    fn prune_history(&mut self, horizon: usize) {
+        self.history.prune(horizon);
+        self.digests_to_indexes.retain(|_, idx| *idx >= horizon);
+    }
+}
+
+/// An [`Accumulator`] that keeps no proof cache.
+///
+/// Publishing no longer touches every witness; instead, each witness is
+/// computed from the exponent when requested (O(N) per request). The
+/// history is still kept, for append-only proofs.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "Accumulator<G>: Serialize",
+    deserialize = "Accumulator<G>: Deserialize<'de>"
+))]
+pub struct StatelessAccumulator<G>(Accumulator<G>)
+where
+    HistoryEntry<G>: Collector,
+    SkipList<HistoryEntry<G>>: std::fmt::Debug;
+
+impl<G> DataSized for StatelessAccumulator<G>
+where
+    HistoryEntry<G>: Collector,
+    SkipList<HistoryEntry<G>>: std::fmt::Debug,
+    Accumulator<G>: DataSized,
+{
+    fn size(&self) -> Information {
+        self.0.size()
+    }
+}
+
+impl<G: Group + TryFrom<Integer> + 'static> AccumulatorTrait for StatelessAccumulator<G>
+where
+    Accumulator<G>: AccumulatorTrait<
+        Digest = Digest<G>,
+        Witness = Witness<G>,
+        AppendOnlyWitness = AppendOnlyWitness<G>,
+        NonMembershipWitness = NonMembershipWitness<G>,
+    >,
+{
+    type Digest = Digest<G>;
+    type Witness = Witness<G>;
+    type AppendOnlyWitness = AppendOnlyWitness<G>;
+    type NonMembershipWitness = NonMembershipWitness<G>;
+
+    const NAME: &'static str = "rsa_stateless";
+    const PRECOMPUTED_PROOFS: bool = false;
+
+    fn digest(&self) -> &Self::Digest {
+        &self.0.digest
+    }
+
+    /// O(1) (plus the history)
+    fn increment(&mut self, member: Prime) {
+        let acc = &mut self.0;
+        acc.digest.0 *= member.as_ref();
+        acc.accumulate_exponent(&member, 1);
+        acc.multiset.insert(member.clone());
+        acc.history.add(HistoryEntry {
+            end_digest: acc.digest.clone(),
+            exponent: member.into(),
+        });
+        acc.digests_to_indexes
+            .insert(acc.digest.clone(), acc.history.len() - 1);
+    }
+
+    fn prove_append_only(&self, prefix: &Self::Digest) -> Self::AppendOnlyWitness {
+        self.0.prove_append_only(prefix)
+    }
+
+    fn append_only_hops(witness: &Self::AppendOnlyWitness) -> usize {
+        Accumulator::<G>::append_only_hops(witness)
+    }
+
+    /// O(N): computed from scratch every time.
+    fn prove(&self, member: &Prime, revision: u32) -> Option<Self::Witness> {
+        if self.0.multiset.get(member) != revision {
+            return None;
+        }
+        if revision == 0 {
+            return self.prove_nonmember(member).map(Witness::for_zero);
+        }
+        // The membership witness is the digest without any copies of `member`.
+        let rest = self.0.exponent_without(member, revision);
+        let membership = MembershipWitness(G::default() * &rest);
+        let nonmember = NonMembershipWitness::prove(&rest, member.inner());
+        Some(Witness::new(membership, nonmember))
+    }
+
+    fn prove_nonmember(&self, value: &Prime) -> Option<Self::NonMembershipWitness> {
+        self.0.prove_nonmember_uncached(value)
+    }
+
+    fn get(&self, member: &Prime) -> u32 {
+        self.0.get(member)
+    }
+
+    fn import(multiset: MultiSet<Prime>) -> Self {
+        let members: Vec<_> = multiset
+            .sorted()
+            .into_iter()
+            .map(|(value, count)| Member::new(value.clone().into(), count))
+            .collect();
+        let (_, exponent) = Intermediate::trees(&members);
+        let digest = Digest::for_exponent(exponent.product());
+
+        let mut history = SkipList::<HistoryEntry<G>>::new();
+        history.add(HistoryEntry {
+            end_digest: digest.clone(),
+            exponent: exponent.product().clone(),
+        });
+        let mut digests_to_indexes: HashMap<Digest<G>, usize> = Default::default();
+        digests_to_indexes.insert(digest.clone(), 0);
+        Self(Accumulator {
+            digest,
+            multiset: multiset.into(),
+            proof_cache: Default::default(),
+            nonmember_proof_cache: Default::default(),
+            history: history.into(),
+            digests_to_indexes: digests_to_indexes.into(),
+            exponent: exponent.into(),
+            leaves: leaf_indexes(&members).into(),
+        })
+    }
+
+    fn verify(
+        digest: &Self::Digest,
+        member: &Prime,
+        revision: u32,
+        witness: Self::Witness,
+    ) -> bool {
+        Accumulator::<G>::verify(digest, member, revision, witness)
+    }
+
+    fn verify_nonmember(
+        digest: &Self::Digest,
+        value: &Prime,
+        witness: NonMembershipWitness<G>,
+    ) -> bool {
+        Accumulator::<G>::verify_nonmember(digest, value, witness)
+    }
+
+    fn update_witness(
+        digest: &Self::Digest,
+        member: &Prime,
+        witness: &mut Self::Witness,
+        added: &[Prime],
+    ) {
+        Accumulator::<G>::update_witness(digest, member, witness, added)
+    }
+
+    fn update_witness_from_product(
+        digest: &Self::Digest,
+        member: &Prime,
+        witness: &mut Self::Witness,
+        product: &Integer,
+    ) -> u32 {
+        Accumulator::<G>::update_witness_from_product(digest, member, witness, product)
+    }
+
+    fn verify_append_only(
+        digest: &Self::Digest,
+        proof: &Self::AppendOnlyWitness,
+        new_state: &Self::Digest,
+    ) -> bool {
+        Accumulator::<G>::verify_append_only(digest, proof, new_state)
+    }
+
+    fn verify_append_only_batch(
+        digest: &Self::Digest,
+        proof: &Self::AppendOnlyWitness,
+        new_state: &Self::Digest,
+    ) -> bool {
+        Accumulator::<G>::verify_append_only_batch(digest, proof, new_state)
+    }
+
+    fn cdn_size(&self) -> Information {
+        self.0.cdn_size()
+    }
+
+    fn parameters_size() -> Information {
+        Accumulator::<G>::parameters_size()
+    }
+
+    fn cdn_objects(&self) -> CdnObjects<'_> {
+        self.0.cdn_objects()
+    }
+
+    fn unshare(&mut self) {
+        self.0.unshare()
+    }
+
+    fn prune_history(&mut self, horizon: usize) {
+        self.0.prune_history(horizon)
+    }
 }
 
 /*
@@ -1070,7 +1524,7 @@ mod tests {
     proptest! {
         #[test]
         fn test_accumulator_members(multiset in multisets()) {
-            let mut acc = Accumulator::<G>::import(multiset.clone());
+            let acc = Accumulator::<G>::import(multiset.clone());
 
             let digest = acc.digest.clone();
             for (index, count) in multiset.iter() {
@@ -1080,4 +1534,216 @@ mod tests {
             }
         }
     }
+
+    proptest! {
+        #[test]
+        fn test_accumulator_invariants(multiset in multisets()) {
+            let mut acc = Accumulator::<G>::import(multiset.clone());
+            prop_assert_eq!(acc.check_invariants(), Ok(()));
+            for (index, _) in multiset.iter().take(3) {
+                acc.increment(index.clone());
+                prop_assert_eq!(acc.check_invariants(), Ok(()));
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_prove_member(
+            multiset in multisets(),
+            added in prop::collection::vec(any::<Prime>(), 0..3),
+        ) {
+            let mut acc = Accumulator::<G>::import(multiset);
+            for value in added {
+                acc.increment(value);
+            }
+
+            let digest = acc.digest.clone();
+            for (index, count) in acc.multiset.clone().iter() {
+                let witness = acc.prove_member(index, *count).unwrap();
+                prop_assert!(digest.verify_member(index.inner(), *count, witness.clone()));
+                // Same as the precomputed one.
+                let cached = acc.prove(index, *count).unwrap().member.unwrap();
+                prop_assert_eq!(witness.0, cached.0);
+                // Older revisions work too, but not newer ones.
+                if *count > 1 {
+                    let witness = acc.prove_member(index, count - 1).unwrap();
+                    prop_assert!(digest.verify_member(index.inner(), count - 1, witness));
+                }
+                prop_assert!(acc.prove_member(index, count + 1).is_none());
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_update_witness_from_product(
+            multiset in multisets(),
+            added in prop::collection::vec(any::<Prime>(), 0..4),
+        ) {
+            let acc = Accumulator::<G>::import(multiset);
+            let digest = acc.digest.clone();
+            let product = added
+                .iter()
+                .fold(Integer::from(1u8), |acc, value| acc * value.inner());
+            let mut new_digest = digest.clone();
+            new_digest.0 *= &product;
+
+            for (member, count) in acc.multiset.clone().iter() {
+                let expected = added.iter().filter(|value| *value == member).count();
+                let mut witness = acc.prove(member, *count).unwrap();
+                let copies = Accumulator::<G>::update_witness_from_product(
+                    &digest,
+                    member,
+                    &mut witness,
+                    &product,
+                );
+                prop_assert_eq!(copies as usize, expected);
+                let revision = count + copies;
+                prop_assert!(Accumulator::<G>::verify(&new_digest, member, revision, witness));
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_verify_append_only_batch(
+            multiset in multisets(),
+            added in prop::collection::vec(any::<Prime>(), 1..5),
+        ) {
+            let mut acc = Accumulator::<G>::import(multiset);
+            let old = acc.digest().clone();
+            for value in added {
+                acc.increment(value);
+            }
+            let new = acc.digest().clone();
+            let proof = acc.prove_append_only(&old);
+            prop_assert!(Accumulator::<G>::verify_append_only(&old, &proof, &new));
+            prop_assert!(Accumulator::<G>::verify_append_only_batch(&old, &proof, &new));
+
+            let bogus = Digest(new.0.clone() + old.0.clone());
+            prop_assert!(!Accumulator::<G>::verify_append_only(&old, &proof, &bogus));
+            prop_assert!(!Accumulator::<G>::verify_append_only_batch(&old, &proof, &bogus));
+        }
+    }
+
+    /// One call in [`test_accumulator_matches_multiset`].
+    #[derive(Debug, Clone)]
+    enum Step {
+        Increment(Prime),
+        IncrementBatch(Vec<Prime>),
+        Prove(Prime),
+        ProveNonmember(Prime),
+        ProveBatch(Vec<Prime>),
+    }
+
+    fn steps() -> impl Strategy<Value = Vec<Step>> {
+        use prop::collection::vec;
+        let step = prop_oneof![
+            any::<Prime>().prop_map(Step::Increment),
+            // An empty batch doesn't change the digest, so there's no
+            // append-only proof for it (and the pool never makes one).
+            vec(any::<Prime>(), 1..4).prop_map(Step::IncrementBatch),
+            any::<Prime>().prop_map(Step::Prove),
+            any::<Prime>().prop_map(Step::ProveNonmember),
+            vec(any::<Prime>(), 1..4).prop_map(Step::ProveBatch),
+        ];
+        vec(step, 0..16)
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        /// Differential test: run random calls against the accumulator and a
+        /// plain [`MultiSet`] of what it should hold, and check that counts
+        /// and proofs agree after every call.
+        #[test]
+        fn test_accumulator_matches_multiset(multiset in multisets(), steps in steps()) {
+            let mut acc = Accumulator::<G>::import(multiset.clone());
+            let mut oracle = multiset;
+            for step in steps {
+                let old_digest = acc.digest().clone();
+                match step.clone() {
+                    Step::Increment(member) => {
+                        acc.increment(member.clone());
+                        oracle.insert(member);
+                        let proof = acc.prove_append_only(&old_digest);
+                        prop_assert!(Accumulator::<G>::verify_append_only(
+                            &old_digest,
+                            &proof,
+                            acc.digest()
+                        ));
+                    }
+                    Step::IncrementBatch(members) => {
+                        // As the pool does on publish: new members need a
+                        // cached nonmembership proof before the batch.
+                        for member in &members {
+                            let _ = acc.prove_nonmember(member);
+                        }
+                        let proof = acc
+                            .increment_batch(members.clone())
+                            .expect("batches come with an append-only proof");
+                        for member in members {
+                            oracle.insert(member);
+                        }
+                        prop_assert!(Accumulator::<G>::verify_append_only(
+                            &old_digest,
+                            &proof,
+                            acc.digest()
+                        ));
+                    }
+                    Step::Prove(member) => {
+                        let revision = oracle.get(&member);
+                        let witness = acc.prove(&member, revision);
+                        prop_assert!(witness.is_some(), "{:?}: no proof", step);
+                        prop_assert!(Accumulator::<G>::verify(
+                            acc.digest(),
+                            &member,
+                            revision,
+                            witness.unwrap()
+                        ));
+                        prop_assert!(acc.prove(&member, revision + 1).is_none());
+                    }
+                    Step::ProveNonmember(member) => {
+                        let witness = acc.prove_nonmember(&member);
+                        prop_assert_eq!(witness.is_some(), oracle.get(&member) == 0);
+                        if let Some(witness) = witness {
+                            prop_assert!(acc.digest().verify_nonmember(member.inner(), witness));
+                        }
+                    }
+                    Step::ProveBatch(members) => {
+                        let (counts, witness) = acc.prove_batch(members.clone());
+                        for member in &members {
+                            prop_assert_eq!(counts[member], oracle.get(member));
+                        }
+                        prop_assert!(Accumulator::<G>::verify_batch(acc.digest(), &counts, witness));
+                    }
+                }
+                prop_assert_eq!(acc.multiset.len(), oracle.len(), "after {:?}", step);
+                for (member, count) in oracle.iter() {
+                    prop_assert_eq!(acc.get(member), *count, "after {:?}", step);
+                }
+                prop_assert_eq!(acc.check_invariants(), Ok(()), "after {:?}", step);
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_stateless_accumulator_members(
+            multiset in multisets(),
+            added in prop::collection::vec(any::<Prime>(), 0..3),
+        ) {
+            let mut acc = StatelessAccumulator::<G>::import(multiset);
+            for value in added {
+                acc.increment(value);
+            }
+
+            let digest = acc.digest().clone();
+            for (index, count) in acc.0.multiset.clone().iter() {
+                let proof = acc.prove(index, *count).unwrap();
+                prop_assert!(StatelessAccumulator::<G>::verify(&digest, index, *count, proof));
+            }
+        }
+    }
 }
@@ -0,0 +1,221 @@
+//! Auditing the sequence of digests a repository publishes.
+//!
+//! An append-only proof only shows that a client's new digest extends its old
+//! one. It says nothing about whether *other* clients saw the same digests: a
+//! malicious server can fork its history and show different clients different
+//! views, each internally consistent. Catching that takes someone outside the
+//! client-server pair (an auditor, or a monitor run by a third party) who
+//! fetches the full published sequence and checks that the transitions clients
+//! accept actually appear in it, in order.
+//!
+//! [`Auditor`] keeps the sequence fetched from a server (via [`Auditable`]) and
+//! checks client transitions against it.
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use serde::Serialize;
+use uom::ConstZero;
+
+use crate::util::{assume_data_size_for_vec, DataSized, Information};
+use crate::Authenticator;
+
+/// A contiguous run of published digests.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DigestHistory<D> {
+    /// Position (in the full history) of the first digest.
+    pub start: usize,
+    pub digests: Vec<D>,
+}
+
+impl<D: DataSized> DataSized for DigestHistory<D> {
+    fn size(&self) -> Information {
+        self.start.size() + assume_data_size_for_vec(&self.digests)
+    }
+}
+
+/// An authenticator whose server publishes a sequence of digests that clients
+/// move along.
+pub trait Auditable: Authenticator {
+    type Digest: Clone + Eq + Hash + DataSized;
+
+    /// Published digests from position `since` on.
+    ///
+    /// Servers that prune their history start at the oldest digest they still
+    /// have, if that's after `since`.
+    fn digest_history(&self, since: usize) -> DigestHistory<Self::Digest>;
+
+    /// Where `digest` is in the published history (if it's there and hasn't
+    /// been pruned).
+    fn position(&self, digest: &Self::Digest) -> Option<usize>;
+
+    /// The digest `snapshot` is at (if any).
+    fn digest(snapshot: &Self::ClientSnapshot) -> Option<Self::Digest>;
+}
+
+/// What an auditor concludes about a client's transition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Audit {
+    Consistent,
+    /// The client was shown a digest that was never published.
+    Unpublished,
+    /// Both digests were published, but the client moved backwards.
+    OutOfOrder,
+}
+
+/// Outcomes of the audits made so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct AuditStats {
+    /// Number of times the auditor fetched the history.
+    pub syncs: usize,
+    /// Bytes of history fetched.
+    #[serde(rename = "fetched_bytes")]
+    pub fetched: Information,
+    /// Digests pruned by the server before the auditor could fetch them.
+    pub missed: usize,
+    /// Number of client transitions checked.
+    pub checks: usize,
+    pub unpublished: usize,
+    pub out_of_order: usize,
+}
+
+impl Default for AuditStats {
+    fn default() -> Self {
+        Self {
+            syncs: 0,
+            fetched: Information::ZERO,
+            missed: 0,
+            checks: 0,
+            unpublished: 0,
+            out_of_order: 0,
+        }
+    }
+}
+
+impl AuditStats {
+    /// All transitions that don't match the published history.
+    pub fn inconsistencies(&self) -> usize {
+        self.unpublished + self.out_of_order
+    }
+}
+
+/// Checks client transitions against the published history.
+#[derive(Debug, Clone)]
+pub struct Auditor<D> {
+    /// Position of every digest we've fetched.
+    positions: HashMap<D, usize>,
+    /// Where the next fetch starts.
+    next: usize,
+    stats: AuditStats,
+}
+
+impl<D: Clone + Eq + Hash> Default for Auditor<D> {
+    fn default() -> Self {
+        Self {
+            positions: HashMap::default(),
+            next: 0,
+            stats: AuditStats::default(),
+        }
+    }
+}
+
+impl<D: Clone + Eq + Hash> Auditor<D> {
+    pub fn stats(&self) -> &AuditStats {
+        &self.stats
+    }
+
+    /// Add newly-published digests.
+    pub fn record(&mut self, history: DigestHistory<D>)
+    where
+        D: DataSized,
+    {
+        self.stats.syncs += 1;
+        self.stats.fetched += history.size();
+        self.stats.missed += history.start.saturating_sub(self.next);
+        for (offset, digest) in history.digests.into_iter().enumerate() {
+            let position = history.start + offset;
+            self.positions.entry(digest).or_insert(position);
+            self.next = self.next.max(position + 1);
+        }
+    }
+
+    /// Fetch everything `server` published since the last sync.
+    pub fn sync<A: Auditable<Digest = D>>(&mut self, server: &A)
+    where
+        D: DataSized,
+    {
+        self.record(server.digest_history(self.next));
+    }
+
+    /// Check a client's move from `old` (if it had a digest) to `new`.
+    pub fn check(&mut self, old: Option<&D>, new: &D) -> Audit {
+        self.stats.checks += 1;
+        let new = self.positions.get(new);
+        let old = old.map(|old| self.positions.get(old));
+        let audit = match (old, new) {
+            (_, None) | (Some(None), _) => Audit::Unpublished,
+            (Some(Some(old)), Some(new)) if old > new => Audit::OutOfOrder,
+            _ => Audit::Consistent,
+        };
+        match audit {
+            Audit::Unpublished => self.stats.unpublished += 1,
+            Audit::OutOfOrder => self.stats.out_of_order += 1,
+            Audit::Consistent => {}
+        }
+        audit
+    }
+
+    /// Check a client's move between two snapshots.
+    pub fn check_snapshots<A: Auditable<Digest = D>>(
+        &mut self,
+        old: &A::ClientSnapshot,
+        new: &A::ClientSnapshot,
+    ) -> Audit {
+        match A::digest(new) {
+            Some(new) => self.check(A::digest(old).as_ref(), &new),
+            // Nothing to check (and nothing the client trusts yet).
+            None => Audit::Consistent,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(start: usize, digests: Vec<u64>) -> DigestHistory<u64> {
+        DigestHistory { start, digests }
+    }
+
+    #[test]
+    fn checks_transitions() {
+        let mut auditor = Auditor::default();
+        auditor.record(history(0, vec![10, 11, 12]));
+        auditor.record(history(3, vec![13]));
+
+        assert_eq!(auditor.check(None, &10), Audit::Consistent);
+        assert_eq!(auditor.check(Some(&10), &13), Audit::Consistent);
+        assert_eq!(auditor.check(Some(&12), &12), Audit::Consistent);
+        assert_eq!(auditor.check(Some(&13), &11), Audit::OutOfOrder);
+        assert_eq!(auditor.check(Some(&11), &99), Audit::Unpublished);
+        assert_eq!(auditor.check(Some(&99), &12), Audit::Unpublished);
+
+        let stats = auditor.stats();
+        assert_eq!(stats.syncs, 2);
+        assert_eq!(stats.fetched, history(0, vec![0; 4]).size() + 0usize.size());
+        assert_eq!(stats.missed, 0);
+        assert_eq!(stats.checks, 6);
+        assert_eq!(stats.out_of_order, 1);
+        assert_eq!(stats.unpublished, 2);
+        assert_eq!(stats.inconsistencies(), 3);
+    }
+
+    #[test]
+    fn counts_pruned_digests() {
+        let mut auditor = Auditor::default();
+        auditor.record(history(0, vec![10, 11]));
+        // The server pruned 2 and 3 before we got back to it.
+        auditor.record(history(4, vec![14, 15]));
+        assert_eq!(auditor.stats().missed, 2);
+        assert_eq!(auditor.check(Some(&11), &15), Audit::Consistent);
+    }
+}
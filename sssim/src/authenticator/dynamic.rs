@@ -0,0 +1,317 @@
+//! Object-safe wrapper around [`Authenticator`], for picking a technique at runtime.
+//!
+//! Each technique has its own snapshot/diff/proof types, so we erase them: the
+//! values are boxed, and travel with function pointers that know the concrete
+//! types (to serialize them, and for the client-side operations). This lets
+//! callers keep different techniques side-by-side, e.g. in a
+//! `Vec<Box<dyn DynAuthenticator>>`.
+//!
+//! Only the protocol goes through here (the `soak`, `tune`, `growth`,
+//! `state-loss` and `dictionary` subcommands use it). The benchmarks time the
+//! generic code and save warm state, so they still need each technique's type;
+//! they get it from the registry instead (see [`super::Visitor`]).
+use std::any::Any;
+
+use serde::Serialize;
+
+use crate::authenticator::{
+    Authenticator, CdnObjects, InvariantError, PoolAuthenticator, RepositoryState, Revision,
+    TrustAnchor,
+};
+use crate::log::PackageId;
+use crate::util::{DataSized, Information};
+
+const MISMATCH: &str = "mixed up state from different authenticators";
+
+fn downcast_ref<T: 'static>(value: &dyn Any) -> &T {
+    value.downcast_ref().expect(MISMATCH)
+}
+
+fn downcast_mut<T: 'static>(value: &mut dyn Any) -> &mut T {
+    value.downcast_mut().expect(MISMATCH)
+}
+
+fn downcast<T: 'static>(value: Box<dyn Any + Send>) -> T {
+    *value.downcast().expect(MISMATCH)
+}
+
+/// A boxed diff or proof, which knows how to serialize itself.
+struct Payload {
+    value: Box<dyn Any + Send>,
+    size: Information,
+    serialize: fn(&dyn Any) -> Vec<u8>,
+}
+
+impl Payload {
+    fn new<T: Serialize + DataSized + Send + 'static>(value: T) -> Self {
+        Self {
+            size: value.size(),
+            value: Box::new(value),
+            serialize: |value| {
+                bincode::serialize(downcast_ref::<T>(value)).expect("serializing payload")
+            },
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        (self.serialize)(self.value.as_ref())
+    }
+}
+
+/// A type-erased [`Authenticator::Diff`].
+pub struct DynDiff(Payload);
+
+impl DynDiff {
+    /// The diff as it would go over the wire.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes()
+    }
+}
+
+impl DataSized for DynDiff {
+    fn size(&self) -> Information {
+        self.0.size
+    }
+}
+
+/// A type-erased [`Authenticator::Proof`].
+pub struct DynProof(Payload);
+
+impl DynProof {
+    /// The proof as it would go over the wire.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes()
+    }
+}
+
+impl DataSized for DynProof {
+    fn size(&self) -> Information {
+        self.0.size
+    }
+}
+
+/// Client-side operations for one technique, on erased values.
+#[derive(Clone, Copy)]
+struct ClientOps {
+    size: fn(&dyn Any) -> Information,
+    clone: fn(&dyn Any) -> Box<dyn Any + Send>,
+    update: fn(&mut dyn Any, Box<dyn Any + Send>),
+    check_no_rollback: fn(&dyn Any, &dyn Any) -> bool,
+    verify_membership: fn(&dyn Any, &PackageId, Revision, Box<dyn Any + Send>) -> bool,
+    verify_latest: fn(&dyn Any, &PackageId, Revision, Box<dyn Any + Send>) -> bool,
+}
+
+impl ClientOps {
+    fn of<A>() -> Self
+    where
+        A: Authenticator + 'static,
+        A::ClientSnapshot: Send,
+    {
+        Self {
+            size: |snapshot| downcast_ref::<A::ClientSnapshot>(snapshot).size(),
+            clone: |snapshot| Box::new(downcast_ref::<A::ClientSnapshot>(snapshot).clone()),
+            update: |snapshot, diff| A::update(downcast_mut(snapshot), downcast(diff)),
+            check_no_rollback: |snapshot, diff| {
+                A::check_no_rollback(downcast_ref(snapshot), downcast_ref(diff))
+            },
+            verify_membership: |snapshot, package, revision, proof| {
+                A::verify_membership(downcast_ref(snapshot), package, revision, downcast(proof))
+            },
+            verify_latest: |snapshot, package, revision, proof| {
+                A::verify_latest(downcast_ref(snapshot), package, revision, downcast(proof))
+            },
+        }
+    }
+}
+
+/// A type-erased [`Authenticator::ClientSnapshot`].
+///
+/// The client-side operations live here, since they don't need the server.
+///
+/// # Panics
+///
+/// All methods panic if given a diff or proof from a different technique.
+pub struct DynSnapshot {
+    value: Box<dyn Any + Send>,
+    ops: ClientOps,
+}
+
+impl DynSnapshot {
+    fn new<A>(snapshot: A::ClientSnapshot) -> Self
+    where
+        A: Authenticator + 'static,
+        A::ClientSnapshot: Send,
+    {
+        Self {
+            value: Box::new(snapshot),
+            ops: ClientOps::of::<A>(),
+        }
+    }
+
+    pub fn update(&mut self, diff: DynDiff) {
+        (self.ops.update)(self.value.as_mut(), diff.0.value)
+    }
+
+    /// Verify that applying `diff` doesn't roll back any targets.
+    pub fn check_no_rollback(&self, diff: &DynDiff) -> bool {
+        (self.ops.check_no_rollback)(self.value.as_ref(), diff.0.value.as_ref())
+    }
+
+    /// Verify that `package` *is* in this snapshot.
+    pub fn verify_membership(
+        &self,
+        package: &PackageId,
+        revision: Revision,
+        proof: DynProof,
+    ) -> bool {
+        (self.ops.verify_membership)(self.value.as_ref(), package, revision, proof.0.value)
+    }
+
+    /// Verify that `revision` is the latest revision of `package` in this
+    /// snapshot (see [`Authenticator::verify_latest`]).
+    pub fn verify_latest(&self, package: &PackageId, revision: Revision, proof: DynProof) -> bool {
+        (self.ops.verify_latest)(self.value.as_ref(), package, revision, proof.0.value)
+    }
+}
+
+impl Clone for DynSnapshot {
+    fn clone(&self) -> Self {
+        Self {
+            value: (self.ops.clone)(self.value.as_ref()),
+            ops: self.ops,
+        }
+    }
+}
+
+impl DataSized for DynSnapshot {
+    fn size(&self) -> Information {
+        (self.ops.size)(self.value.as_ref())
+    }
+}
+
+/// Server-side operations of an [`Authenticator`], usable as a trait object.
+///
+/// `Sync`, so one server can answer reads (`&self`) from several threads.
+pub trait DynAuthenticator: DataSized + Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn refresh_metadata(&self, snapshot: &DynSnapshot) -> Option<DynDiff>;
+
+    fn get_metadata(&self) -> DynSnapshot;
+
+    fn publish(&mut self, package: PackageId);
+
+    fn request_file(&self, snapshot: &DynSnapshot, package: &PackageId) -> (Revision, DynProof);
+
+    /// See [`Authenticator::request_latest`].
+    fn request_latest(&self, snapshot: &DynSnapshot, package: &PackageId) -> (Revision, DynProof);
+
+    /// Process any pending publishes (for [`PoolAuthenticator`]s); a no-op otherwise.
+    fn batch_process(&mut self);
+
+    fn cdn_size(&self) -> Information;
+
+    fn cdn_objects(&self) -> CdnObjects<'_>;
+
+    fn trust_anchor(&self) -> TrustAnchor;
+
+    /// See [`Authenticator::export_state`].
+    fn export_state(&self) -> Option<RepositoryState>;
+
+    /// See [`Authenticator::check_invariants`].
+    fn check_invariants(&self) -> Result<(), InvariantError>;
+}
+
+/// Adapts a concrete [`Authenticator`] to [`DynAuthenticator`].
+pub struct Erased<A> {
+    inner: A,
+    batch_process: Option<fn(&mut A)>,
+}
+
+impl<A: Authenticator> Erased<A> {
+    pub fn new(inner: A) -> Self {
+        Self {
+            inner,
+            batch_process: None,
+        }
+    }
+}
+
+impl<A: PoolAuthenticator> Erased<A> {
+    pub fn new_pool(inner: A) -> Self {
+        Self {
+            inner,
+            batch_process: Some(A::batch_process),
+        }
+    }
+}
+
+impl<A: DataSized> DataSized for Erased<A> {
+    fn size(&self) -> Information {
+        self.inner.size()
+    }
+}
+
+impl<A> DynAuthenticator for Erased<A>
+where
+    A: Authenticator + Send + Sync + 'static,
+    A::ClientSnapshot: Send,
+    A::Diff: Send,
+    A::Proof: Send,
+{
+    fn name(&self) -> &'static str {
+        A::name()
+    }
+
+    fn refresh_metadata(&self, snapshot: &DynSnapshot) -> Option<DynDiff> {
+        let id = A::id(downcast_ref(snapshot.value.as_ref()));
+        let diff = self.inner.refresh_metadata(id)?;
+        Some(DynDiff(Payload::new(diff)))
+    }
+
+    fn get_metadata(&self) -> DynSnapshot {
+        DynSnapshot::new::<A>(self.inner.get_metadata())
+    }
+
+    fn publish(&mut self, package: PackageId) {
+        self.inner.publish(package)
+    }
+
+    fn request_file(&self, snapshot: &DynSnapshot, package: &PackageId) -> (Revision, DynProof) {
+        let id = A::id(downcast_ref(snapshot.value.as_ref()));
+        let (revision, proof) = self.inner.request_file(id, package);
+        (revision, DynProof(Payload::new(proof)))
+    }
+
+    fn request_latest(&self, snapshot: &DynSnapshot, package: &PackageId) -> (Revision, DynProof) {
+        let id = A::id(downcast_ref(snapshot.value.as_ref()));
+        let (revision, proof) = self.inner.request_latest(id, package);
+        (revision, DynProof(Payload::new(proof)))
+    }
+
+    fn batch_process(&mut self) {
+        if let Some(batch_process) = self.batch_process {
+            batch_process(&mut self.inner);
+        }
+    }
+
+    fn cdn_size(&self) -> Information {
+        self.inner.cdn_size()
+    }
+
+    fn cdn_objects(&self) -> CdnObjects<'_> {
+        self.inner.cdn_objects()
+    }
+
+    fn trust_anchor(&self) -> TrustAnchor {
+        self.inner.trust_anchor()
+    }
+
+    fn export_state(&self) -> Option<RepositoryState> {
+        self.inner.export_state()
+    }
+
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        self.inner.check_invariants()
+    }
+}
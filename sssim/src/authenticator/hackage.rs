@@ -1,11 +1,16 @@
 use std::collections::HashMap;
 
 use crate::util::{DataSized, FixedDataSized};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use crate::{authenticator::Revision, log::PackageId, util::Information};
+use crate::{
+    authenticator::{InvariantError, RepositoryState, Revision},
+    config::NoConfig,
+    log::PackageId,
+    util::Information,
+};
 
-#[derive(Clone, Default, Debug, Serialize)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct Snapshot {
     package_revisions: HashMap<PackageId, Revision>,
     /// How far into the log has this client read?
@@ -18,7 +23,7 @@ impl DataSized for Snapshot {
     }
 }
 
-#[derive(Clone, Default, Debug, Serialize)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct Log(Vec<(PackageId, Revision)>);
 
 impl DataSized for Log {
@@ -30,7 +35,7 @@ impl DataSized for Log {
 /// A Hackage-style authenticator.
 ///
 /// That is, an authenticator with a
-#[derive(Clone, Default, Debug, Serialize)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct Authenticator {
     log: Log,
     package_revisions: HashMap<PackageId, Revision>,
@@ -48,6 +53,7 @@ impl super::Authenticator for Authenticator {
     type Id = usize;
     type Diff = Log;
     type Proof = ();
+    type Config = NoConfig;
 
     fn name() -> &'static str {
         "hackage"
@@ -81,11 +87,7 @@ impl super::Authenticator for Authenticator {
         self.log.0.push((package, *revision));
     }
 
-    fn request_file(
-        &mut self,
-        snapshot_id: Self::Id,
-        package: &PackageId,
-    ) -> (Revision, Self::Proof) {
+    fn request_file(&self, snapshot_id: Self::Id, package: &PackageId) -> (Revision, Self::Proof) {
         let revision = self
             .package_revisions
             .get(package)
@@ -111,7 +113,12 @@ impl super::Authenticator for Authenticator {
         }
     }
 
-    fn check_no_rollback(snapshot: &Self::ClientSnapshot, diff: &Self::Diff) -> bool {
+    /// The new log entries are signed; checking the signature would go here.
+    fn verify_diff_structure(_: &Self::ClientSnapshot, _: &Self::Diff) -> bool {
+        true
+    }
+
+    fn verify_freshness(snapshot: &Self::ClientSnapshot, diff: &Self::Diff) -> bool {
         // TODO(maybe): combine with update
         for (package_id, new_revision) in diff.0.iter() {
             let result = snapshot.package_revisions.get(package_id);
@@ -134,6 +141,51 @@ impl super::Authenticator for Authenticator {
     fn cdn_size(&self) -> Information {
         self.log.size()
     }
+
+    fn export_state(&self) -> Option<RepositoryState> {
+        Some(RepositoryState::new(self.package_revisions.clone()))
+    }
+
+    /// Each log entry bumps its package's revision by one, and replaying the
+    /// log gives the current revisions.
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        let mut replayed: HashMap<&PackageId, Revision> = HashMap::new();
+        for (index, (package, revision)) in self.log.0.iter().enumerate() {
+            let expected = replayed
+                .get(package)
+                .map_or_else(Revision::default, Revision::incremented);
+            if *revision != expected {
+                return Err(InvariantError::new(
+                    Self::name(),
+                    format!("log entry {index} has {package:?} at {revision:?}, not {expected:?}"),
+                ));
+            }
+            replayed.insert(package, expected);
+        }
+        let current: HashMap<_, _> = self
+            .package_revisions
+            .iter()
+            .map(|(p, r)| (p, *r))
+            .collect();
+        if replayed != current {
+            return Err(InvariantError::new(
+                Self::name(),
+                "replaying the log doesn't give the current revisions",
+            ));
+        }
+        Ok(())
+    }
+
+    /// The index is one append-only file; clients fetch its tail with range
+    /// requests.
+    fn diff_cacheable(_diff: &Self::Diff) -> bool {
+        true
+    }
+
+    /// The revision is in the snapshot, so there's nothing more to fetch.
+    fn download_round_trips(_proof: &Self::Proof) -> u32 {
+        0
+    }
 }
 
 /*
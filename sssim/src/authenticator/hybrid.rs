@@ -0,0 +1,411 @@
+//! A hybrid of Mercury-style diffs and an RSA accumulator.
+//!
+//! Publications are heavily skewed: a small "hot" set of packages sees most of
+//! the updates, while the long tail rarely changes. So we keep recently
+//! published packages in a small (package -> revision) map that clients track
+//! in full (as in Mercury; see mercury_diff.rs), and everything else in an RSA
+//! accumulator (as in rsa.rs).
+//!
+//! Once the hot set reaches `merge_threshold` packages, we merge it into the
+//! accumulator and start a new (empty) "generation" of the hot set.
+//!
+//! Clients know the whole hot set, so a server can't pass off a hot package as
+//! a cold one (or vice versa).
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    authenticator::{
+        Authenticator as _, CdnObject, CdnObjects, InvariantError, Revision, Rsa, TrustAnchor,
+    },
+    log::PackageId,
+    util::{byte, DataSized, Information},
+};
+
+/// Default number of hot packages before merging into the accumulator.
+pub const MERGE_THRESHOLD: usize = 1024;
+
+type ColdSnapshot = <Rsa as super::Authenticator>::ClientSnapshot;
+type ColdId = <Rsa as super::Authenticator>::Id;
+type ColdDiff = <Rsa as super::Authenticator>::Diff;
+type ColdProof = <Rsa as super::Authenticator>::Proof;
+
+/// The recently published packages.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+struct Hot {
+    /// Incremented every time we merge into the accumulator.
+    generation: u64,
+    /// Publications in this generation, in order.
+    log: Vec<PackageId>,
+    revisions: HashMap<PackageId, Revision>,
+}
+
+impl Hot {
+    fn diff(&self, generation: u64, index: usize) -> Option<HotDiff> {
+        if generation != self.generation {
+            return Some(HotDiff {
+                generation: self.generation,
+                index: self.log.len(),
+                reset: true,
+                packages: self.revisions.clone(),
+            });
+        }
+        if index == self.log.len() {
+            return None;
+        }
+        let packages = self.log[index..]
+            .iter()
+            .map(|p| (p.clone(), self.revisions[p]))
+            .collect();
+        Some(HotDiff {
+            generation,
+            index: self.log.len(),
+            reset: false,
+            packages,
+        })
+    }
+}
+
+impl DataSized for Hot {
+    fn size(&self) -> Information {
+        Information::new::<byte>(8) + self.log.size() + self.revisions.size()
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct HotDiff {
+    generation: u64,
+    index: usize,
+    /// Whether this replaces (rather than extends) the client's hot set.
+    reset: bool,
+    packages: HashMap<PackageId, Revision>,
+}
+
+impl DataSized for HotDiff {
+    fn size(&self) -> Information {
+        Information::new::<byte>(8 + 8 + 1) + self.packages.size()
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct Snapshot {
+    cold: ColdSnapshot,
+    generation: u64,
+    index: usize,
+    hot: HashMap<PackageId, Revision>,
+}
+
+impl DataSized for Snapshot {
+    fn size(&self) -> Information {
+        self.cold.size() + Information::new::<byte>(8 + 8) + self.hot.size()
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Diff {
+    cold: Option<ColdDiff>,
+    hot: Option<HotDiff>,
+}
+
+impl DataSized for Diff {
+    fn size(&self) -> Information {
+        self.cold.size() + self.hot.size()
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub enum Proof {
+    /// The package is in the hot set, which the client already has.
+    Hot,
+    Cold(ColdProof),
+}
+
+impl DataSized for Proof {
+    fn size(&self) -> Information {
+        match self {
+            Proof::Hot => Information::new::<byte>(1),
+            Proof::Cold(proof) => Information::new::<byte>(1) + proof.size(),
+        }
+    }
+}
+
+/// Settings (see [`crate::config`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Hot packages before merging into the accumulator.
+    pub merge_threshold: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            merge_threshold: MERGE_THRESHOLD,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Authenticator {
+    cold: Rsa,
+    hot: Hot,
+    merge_threshold: usize,
+}
+
+impl Authenticator {
+    pub fn with_merge_threshold(mut self, merge_threshold: usize) -> Self {
+        self.merge_threshold = merge_threshold;
+        self
+    }
+
+    /// Move the hot set into the accumulator.
+    fn merge(&mut self) {
+        for (package, revision) in self.hot.revisions.drain() {
+            for _ in u64::from(self.cold.count(&package))..revision.0.get() {
+                self.cold.publish(package.clone());
+            }
+        }
+        self.hot.log.clear();
+        self.hot.generation += 1;
+    }
+}
+
+impl DataSized for Authenticator {
+    fn size(&self) -> Information {
+        self.cold.size() + self.hot.size()
+    }
+}
+
+impl super::Authenticator for Authenticator {
+    type ClientSnapshot = Snapshot;
+    type Id = (ColdId, u64, usize);
+    type Diff = Diff;
+    type Proof = Proof;
+    type Config = Config;
+
+    fn name() -> &'static str {
+        "hybrid"
+    }
+
+    fn batch_import(packages: Vec<PackageId>) -> Self {
+        Self::batch_import_with(packages, Config::default())
+    }
+
+    fn batch_import_with(packages: Vec<PackageId>, config: Config) -> Self {
+        Self {
+            cold: Rsa::batch_import(packages),
+            hot: Hot::default(),
+            merge_threshold: config.merge_threshold,
+        }
+    }
+
+    fn refresh_metadata(&self, (cold_id, generation, index): Self::Id) -> Option<Self::Diff> {
+        let cold = self.cold.refresh_metadata(cold_id);
+        let hot = self.hot.diff(generation, index);
+        if cold.is_none() && hot.is_none() {
+            return None;
+        }
+        Some(Diff { cold, hot })
+    }
+
+    fn get_metadata(&self) -> Self::ClientSnapshot {
+        Snapshot {
+            cold: self.cold.get_metadata(),
+            generation: self.hot.generation,
+            index: self.hot.log.len(),
+            hot: self.hot.revisions.clone(),
+        }
+    }
+
+    fn publish(&mut self, package: PackageId) {
+        let revision = match self.hot.revisions.get(&package) {
+            Some(revision) => revision.incremented(),
+            None => Revision::from(self.cold.count(&package) as usize + 1),
+        };
+        self.hot.revisions.insert(package.clone(), revision);
+        self.hot.log.push(package);
+        if self.hot.revisions.len() >= self.merge_threshold {
+            self.merge();
+        }
+    }
+
+    fn request_file(
+        &self,
+        (cold_id, _, _): Self::Id,
+        package: &PackageId,
+    ) -> (Revision, Self::Proof) {
+        if let Some(revision) = self.hot.revisions.get(package) {
+            return (*revision, Proof::Hot);
+        }
+        let (revision, proof) = self.cold.request_file(cold_id, package);
+        (revision, Proof::Cold(proof))
+    }
+
+    fn id(snapshot: &Self::ClientSnapshot) -> Self::Id {
+        (Rsa::id(&snapshot.cold), snapshot.generation, snapshot.index)
+    }
+
+    fn update(snapshot: &mut Self::ClientSnapshot, diff: Self::Diff) {
+        if let Some(cold) = diff.cold {
+            Rsa::update(&mut snapshot.cold, cold);
+        }
+        if let Some(hot) = diff.hot {
+            if hot.reset {
+                snapshot.hot = hot.packages;
+            } else {
+                snapshot.hot.extend(hot.packages);
+            }
+            snapshot.generation = hot.generation;
+            snapshot.index = hot.index;
+        }
+    }
+
+    /// Only the cold half has proofs; the hot half is signed.
+    fn verify_diff_structure(snapshot: &Self::ClientSnapshot, diff: &Self::Diff) -> bool {
+        diff.cold.as_ref().map_or(true, |cold| {
+            Rsa::verify_diff_structure(&snapshot.cold, cold)
+        })
+    }
+
+    fn verify_freshness(snapshot: &Self::ClientSnapshot, diff: &Self::Diff) -> bool {
+        if let Some(cold) = &diff.cold {
+            if !Rsa::verify_freshness(&snapshot.cold, cold) {
+                return false;
+            }
+        }
+        if let Some(hot) = &diff.hot {
+            if hot.reset {
+                // Everything from the old generation went into the accumulator,
+                // which the cold half's append-only proof covers.
+                return hot.generation > snapshot.generation;
+            }
+            if hot.generation != snapshot.generation || hot.index < snapshot.index {
+                return false;
+            }
+            for (package, revision) in &hot.packages {
+                if let Some(old_revision) = snapshot.hot.get(package) {
+                    if revision < old_revision {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    fn verify_membership(
+        snapshot: &Self::ClientSnapshot,
+        package: &PackageId,
+        revision: Revision,
+        proof: Self::Proof,
+    ) -> bool {
+        match (snapshot.hot.get(package), proof) {
+            (Some(hot_revision), Proof::Hot) => *hot_revision == revision,
+            (None, Proof::Cold(proof)) => {
+                Rsa::verify_membership(&snapshot.cold, package, revision, proof)
+            }
+            _ => false,
+        }
+    }
+
+    fn cdn_size(&self) -> Information {
+        // Clients fetch the hot set (or diffs of it) alongside the accumulator.
+        self.cold.cdn_size() + self.hot.revisions.size()
+    }
+
+    fn cdn_objects(&self) -> CdnObjects<'_> {
+        let hot = CdnObject::new("hot", self.hot.revisions.size())
+            .with_version((self.hot.generation, self.hot.log.len()));
+        Box::new(self.cold.cdn_objects().chain(std::iter::once(hot)))
+    }
+
+    fn proof_cacheable(proof: &Self::Proof) -> bool {
+        match proof {
+            Proof::Hot => true,
+            Proof::Cold(proof) => Rsa::proof_cacheable(proof),
+        }
+    }
+
+    /// Hot packages are covered by the snapshot; cold ones need a proof.
+    fn download_round_trips(proof: &Self::Proof) -> u32 {
+        match proof {
+            Proof::Hot => 0,
+            Proof::Cold(proof) => Rsa::download_round_trips(proof),
+        }
+    }
+
+    /// Clients still get the whole hot set, but only proofs for the cold
+    /// packages they ask about.
+    fn targeted_refresh() -> bool {
+        true
+    }
+
+    fn unshare(&mut self) {
+        self.cold.unshare()
+    }
+
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        self.cold.check_invariants()
+    }
+
+    fn trust_anchor(&self) -> TrustAnchor {
+        self.cold.trust_anchor()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::package_ids;
+
+    fn refresh(server: &Authenticator, snapshot: &mut Snapshot) {
+        if let Some(diff) = server.refresh_metadata(Authenticator::id(snapshot)) {
+            assert!(Authenticator::check_no_rollback(snapshot, &diff));
+            Authenticator::update(snapshot, diff);
+        }
+    }
+
+    fn download(server: &Authenticator, snapshot: &Snapshot, package: &PackageId) -> Revision {
+        let (revision, proof) = server.request_file(Authenticator::id(snapshot), package);
+        assert!(Authenticator::verify_membership(
+            snapshot, package, revision, proof
+        ));
+        revision
+    }
+
+    #[test]
+    fn hot_packages_merge_into_accumulator() {
+        let packages: Vec<_> = package_ids(4);
+        let mut server = Authenticator::batch_import(packages.clone()).with_merge_threshold(2);
+        let mut snapshot = Snapshot::default();
+        refresh(&server, &mut snapshot);
+
+        // One hot package.
+        server.publish(packages[0].clone());
+        server.publish(packages[0].clone());
+        refresh(&server, &mut snapshot);
+        assert_eq!(
+            download(&server, &snapshot, &packages[0]),
+            Revision::from(3)
+        );
+        assert_eq!(
+            download(&server, &snapshot, &packages[1]),
+            Revision::from(1)
+        );
+
+        // A second hot package triggers a merge.
+        let new_package = PackageId::from("new_package".to_string());
+        server.publish(new_package.clone());
+        refresh(&server, &mut snapshot);
+        assert!(snapshot.hot.is_empty());
+        assert_eq!(
+            download(&server, &snapshot, &packages[0]),
+            Revision::from(3)
+        );
+        assert_eq!(
+            download(&server, &snapshot, &new_package),
+            Revision::from(1)
+        );
+    }
+}
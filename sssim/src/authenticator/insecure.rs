@@ -1,14 +1,15 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[cfg(test)]
 use proptest_derive::Arbitrary;
 
 use crate::{
-    authenticator::Revision, log::PackageId, util::byte, util::DataSized, util::Information,
+    authenticator::Revision, config::NoConfig, log::PackageId, util::byte, util::DataSized,
+    util::Information,
 };
 
 #[cfg_attr(test, derive(Arbitrary))]
-#[derive(Clone, Default, Debug, Serialize)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct Snapshot {}
 
 impl DataSized for Snapshot {
@@ -21,7 +22,7 @@ impl DataSized for Snapshot {
 ///
 /// Useful for testing.
 #[cfg_attr(test, derive(Arbitrary))]
-#[derive(Clone, Default, Debug, Serialize)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct Authenticator {}
 
 impl DataSized for Authenticator {
@@ -36,6 +37,7 @@ impl super::Authenticator for Authenticator {
     type Id = ();
     type Diff = ();
     type Proof = ();
+    type Config = NoConfig;
 
     fn name() -> &'static str {
         "insecure"
@@ -51,7 +53,7 @@ impl super::Authenticator for Authenticator {
 
     fn publish(&mut self, _: PackageId) {}
 
-    fn request_file(&mut self, _: Self::Id, _: &PackageId) -> (Revision, Self::Proof) {
+    fn request_file(&self, _: Self::Id, _: &PackageId) -> (Revision, Self::Proof) {
         (Revision::default(), ())
     }
 
@@ -62,7 +64,11 @@ impl super::Authenticator for Authenticator {
 
     fn update(_: &mut Self::ClientSnapshot, _: Self::Diff) {}
 
-    fn check_no_rollback(_: &Self::ClientSnapshot, _: &Self::Diff) -> bool {
+    fn verify_diff_structure(_: &Self::ClientSnapshot, _: &Self::Diff) -> bool {
+        true
+    }
+
+    fn verify_freshness(_: &Self::ClientSnapshot, _: &Self::Diff) -> bool {
         true
     }
 
@@ -78,6 +84,21 @@ impl super::Authenticator for Authenticator {
     fn cdn_size(&self) -> Information {
         Information::new::<byte>(0)
     }
+
+    /// Nothing is signed, so there's nothing to check against.
+    fn trust_anchor(&self) -> super::TrustAnchor {
+        Default::default()
+    }
+
+    /// Nothing is signed, so nothing expires.
+    fn metadata_expires() -> bool {
+        false
+    }
+
+    /// The revision is in the snapshot, so there's nothing more to fetch.
+    fn download_round_trips(_proof: &Self::Proof) -> u32 {
+        0
+    }
 }
 
 /*
@@ -55,12 +55,19 @@ use std::collections::HashMap;
 #[cfg(test)]
 use proptest_derive::Arbitrary;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use crate::{authenticator::Revision, log::PackageId, util::byte, util::Information};
+use crate::{
+    authenticator::{CdnObject, CdnObjects, RepositoryState, Revision},
+    config::NoConfig,
+    edge::EdgeWork,
+    log::PackageId,
+    util::byte,
+    util::Information,
+};
 
 #[cfg_attr(test, derive(Arbitrary))]
-#[derive(Default, Debug, Clone, Copy, Serialize)]
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Metadata {
     revision: Revision,
 }
@@ -79,7 +86,7 @@ impl FixedDataSized for Metadata {
 
 /// The mercury TUF client snapshot contains *all* the snapshot state.
 #[cfg_attr(test, derive(Arbitrary))]
-#[derive(Default, Clone, Debug, Serialize)]
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct Snapshot {
     packages: HashMap<PackageId, Metadata>,
     id: u64,
@@ -92,7 +99,7 @@ impl DataSized for Snapshot {
 }
 
 #[cfg_attr(test, derive(Arbitrary))]
-#[derive(Clone, Default, Debug, Serialize)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct Authenticator {
     // TODO(meh): replace with a skiplist
     snapshots: HashMap<u64, Snapshot>,
@@ -116,6 +123,7 @@ impl super::Authenticator for Authenticator {
     type Id = u64;
     type Diff = Snapshot;
     type Proof = ();
+    type Config = NoConfig;
 
     fn name() -> &'static str {
         "mercury_diff"
@@ -175,11 +183,7 @@ impl super::Authenticator for Authenticator {
             .or_insert_with(Metadata::default);
     }
 
-    fn request_file(
-        &mut self,
-        snapshot_id: Self::Id,
-        package: &PackageId,
-    ) -> (Revision, Self::Proof) {
+    fn request_file(&self, snapshot_id: Self::Id, package: &PackageId) -> (Revision, Self::Proof) {
         let metadata = self
             .snapshot
             .packages
@@ -207,7 +211,12 @@ impl super::Authenticator for Authenticator {
         snapshot.id = diff.id;
     }
 
-    fn check_no_rollback(snapshot: &Self::ClientSnapshot, diff: &Self::Diff) -> bool {
+    /// The diff is signed; checking the signature would go here.
+    fn verify_diff_structure(_: &Self::ClientSnapshot, _: &Self::Diff) -> bool {
+        true
+    }
+
+    fn verify_freshness(snapshot: &Self::ClientSnapshot, diff: &Self::Diff) -> bool {
         for (package_id, metadata) in &diff.packages {
             if let Some(old_metadata) = snapshot.packages.get(package_id) {
                 if metadata.revision < old_metadata.revision {
@@ -231,6 +240,13 @@ impl super::Authenticator for Authenticator {
         }
     }
 
+    fn export_state(&self) -> Option<RepositoryState> {
+        let packages = &self.snapshot.packages;
+        Some(RepositoryState::new(packages.iter().map(
+            |(package, metadata)| (package.clone(), metadata.revision),
+        )))
+    }
+
     fn cdn_size(&self) -> Information {
         // TODO(meh): consider using log data structure or immutable map
         let mut size = self.snapshot.size();
@@ -241,6 +257,28 @@ impl super::Authenticator for Authenticator {
         }
         size
     }
+
+    /// The current snapshot, plus each old one (which diffs are computed from).
+    fn cdn_objects(&self) -> CdnObjects<'_> {
+        let current =
+            CdnObject::new("snapshot", self.snapshot.size()).with_version(self.snapshot.id);
+        let old = self.snapshots.iter().map(|(id, snapshot)| {
+            CdnObject::new(format!("snapshots/{id}"), id.size() + snapshot.size())
+        });
+        Box::new(std::iter::once(current).chain(old))
+    }
+
+    /// The edge has the client's old snapshot and the current one cached, and
+    /// compares them.
+    fn diff_edge_work(&self, diff: &Self::Diff) -> Option<EdgeWork> {
+        let compared = self.snapshot.size() * 2;
+        Some(EdgeWork::new(2, compared + diff.size()))
+    }
+
+    /// The revision is in the snapshot, so there's nothing more to fetch.
+    fn download_round_trips(_proof: &Self::Proof) -> u32 {
+        0
+    }
 }
 
 /*
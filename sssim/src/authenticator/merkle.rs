@@ -1,60 +1,203 @@
 //! Merkle binary prefix tree authenticator.
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::io::{Read, Write};
 
-use derivative::Derivative;
 use digest::Output;
-use digest_hash::EndianUpdate;
-use serde::Serialize;
+use digest_hash::{EndianUpdate, Hash};
+use serde::{Deserialize, Serialize};
 
 use crate::util::DataSized;
 
-use crate::primitives::merkle::{Digest, Hasher, ObjectHasher, Proof, Tree};
+use crate::primitives::merkle::{Digest, Hasher, ObjectHasher, Privacy, Proof, Tree};
 use crate::util::FixedDataSized;
-use crate::{authenticator::Revision, log::PackageId, util::byte, util::Information};
+use crate::warm_state::WarmState;
+use crate::{
+    authenticator::{CdnObject, CdnObjects, RepositoryState, Revision, Targets},
+    config::NoConfig,
+    log::PackageId,
+    util::byte,
+    util::Information,
+};
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Default, Serialize)]
 #[serde(bound = "Output<H>: Serialize")]
 pub struct Snapshot<H: Hasher> {
     digest: Digest<PackageId, H>,
+    /// Package versions in the tree, signed with the digest so clients can
+    /// refuse to go back to an older tree.
+    version: u64,
 }
 
 impl<H: Hasher> FixedDataSized for Snapshot<H> {
     fn fixed_size() -> Information {
-        Information::new::<byte>(<H as Hasher>::output_size())
+        Information::new::<byte>(<H as Hasher>::output_size() + std::mem::size_of::<u64>())
     }
 }
 
-#[derive(Clone, Debug, Derivative)]
-#[derivative(Default(bound = "Tree<PackageId, Revision, H>: Default"))]
-pub struct Authenticator<H: Hasher> {
-    tree: Tree<PackageId, Revision, H>,
+/// A package's leaf: its latest revision, and the targets that revision was
+/// published with (if any).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Leaf {
+    revision: Revision,
+    targets: Option<Targets>,
 }
 
-impl<H: Hasher> DataSized for Authenticator<H>
+impl Leaf {
+    fn new(revision: Revision) -> Self {
+        Self {
+            revision,
+            targets: None,
+        }
+    }
+}
+
+impl Hash for Leaf {
+    fn hash<H>(&self, digest: &mut H)
+    where
+        H: EndianUpdate,
+    {
+        self.revision.hash(digest);
+        if let Some(targets) = &self.targets {
+            targets.length.hash(digest);
+            for word in targets.hash.0 {
+                word.hash(digest);
+            }
+        }
+    }
+}
+
+/// Counts the targets slot even when it's empty, so that every leaf is the
+/// same size (the tree estimates its size from one of them).
+impl FixedDataSized for Leaf {
+    fn fixed_size() -> Information {
+        Revision::fixed_size() + Targets::fixed_size()
+    }
+}
+
+/// If `PRIVATE`, proofs hide the rest of the tree (see [`Privacy`]).
+#[derive(Clone, Debug)]
+pub struct Authenticator<H: Hasher, const PRIVATE: bool = false> {
+    tree: Tree<PackageId, Leaf, H>,
+    /// Sum of the revisions (see [`Snapshot`]).
+    version: u64,
+}
+
+impl<H: Hasher, const PRIVATE: bool> Default for Authenticator<H, PRIVATE>
 where
-    Tree<PackageId, Revision, H>: DataSized,
+    ObjectHasher<H>: Hasher<OutputSize = H::OutputSize> + EndianUpdate,
+{
+    fn default() -> Self {
+        Self {
+            tree: Self::empty_tree(),
+            version: 0,
+        }
+    }
+}
+
+impl<H: Hasher, const PRIVATE: bool> Authenticator<H, PRIVATE>
+where
+    ObjectHasher<H>: Hasher<OutputSize = H::OutputSize> + EndianUpdate,
+{
+    /// A private tree gets a fresh secret each time.
+    fn empty_tree() -> Tree<PackageId, Leaf, H> {
+        if PRIVATE {
+            Tree::private(Privacy::random())
+        } else {
+            Tree::default()
+        }
+    }
+}
+
+impl<H: Hasher, const PRIVATE: bool> DataSized for Authenticator<H, PRIVATE>
+where
+    Tree<PackageId, Leaf, H>: DataSized,
 {
     fn size(&self) -> Information {
         self.tree.size()
     }
 }
 
+impl<H: Hasher, const PRIVATE: bool> Authenticator<H, PRIVATE>
+where
+    ObjectHasher<H>: Hasher<OutputSize = H::OutputSize> + EndianUpdate,
+    Output<H>: Copy,
+{
+    /// Rebuild the tree from saved leaves.
+    fn from_values(values: HashMap<PackageId, Leaf>) -> Self {
+        let mut tree = Self::empty_tree();
+        let version = values.values().map(|leaf| leaf.revision.0.get()).sum();
+        for (package, leaf) in values {
+            tree.insert(package, leaf);
+        }
+        Self { tree, version }
+    }
+
+    /// Rebuild a private tree with a fixed secret instead of a random one (its
+    /// digests depend on it), so runs are reproducible.
+    pub fn with_privacy(self, privacy: Privacy) -> Self {
+        debug_assert!(PRIVATE, "only private trees have keys");
+        let mut tree = Tree::private(privacy);
+        for (package, leaf) in self.tree.values().clone() {
+            tree.insert(package, leaf);
+        }
+        Self {
+            tree,
+            version: self.version,
+        }
+    }
+
+    /// The next revision of `package`.
+    fn next_revision(&self, package: &PackageId) -> Revision {
+        self.tree
+            .values()
+            .get(package)
+            .map(|leaf| leaf.revision.incremented())
+            .unwrap_or_default()
+    }
+}
+
+/// The tree is cheap to rebuild, so we only persist the leaves.
+impl<H: Hasher, const PRIVATE: bool> WarmState for Authenticator<H, PRIVATE>
+where
+    ObjectHasher<H>: Hasher<OutputSize = H::OutputSize> + EndianUpdate,
+    Output<H>: Copy,
+{
+    fn save_state<W: Write>(&self, writer: W) -> bincode::Result<()> {
+        bincode::serialize_into(writer, self.tree.values())
+    }
+
+    fn load_state<R: Read>(reader: R) -> bincode::Result<Self> {
+        Ok(Self::from_values(bincode::deserialize_from(reader)?))
+    }
+
+    fn load_state_from_slice(bytes: &[u8]) -> bincode::Result<Self> {
+        Ok(Self::from_values(bincode::deserialize(bytes)?))
+    }
+}
+
 #[allow(unused_variables)]
-impl<H: Hasher> super::Authenticator for Authenticator<H>
+impl<H: Hasher, const PRIVATE: bool> super::Authenticator for Authenticator<H, PRIVATE>
 where
     ObjectHasher<H>: Hasher<OutputSize = H::OutputSize> + EndianUpdate,
     Output<H>: Copy,
     H: Debug,
     Snapshot<H>: Clone + Serialize,
-    Proof<Revision, H>: Serialize + Clone + DataSized,
+    Proof<Leaf, H>: Serialize + Clone + DataSized,
 {
     type ClientSnapshot = Snapshot<H>;
     type Id = ();
     type Diff = Snapshot<H>;
-    type Proof = Proof<Revision, H>;
+    type Proof = Proof<Leaf, H>;
+    type Config = NoConfig;
 
     fn name() -> &'static str {
-        "merkle_bpt"
+        if PRIVATE {
+            "merkle_bpt_private"
+        } else {
+            "merkle_bpt"
+        }
     }
 
     fn refresh_metadata(&self, snapshot_id: Self::Id) -> Option<Self::Diff> {
@@ -63,37 +206,57 @@ where
 
     fn get_metadata(&self) -> Self::ClientSnapshot {
         let digest = self.tree.digest();
-        Snapshot { digest }
+        Snapshot {
+            digest,
+            version: self.version,
+        }
     }
 
     fn publish(&mut self, package: PackageId) {
-        let revision = self
-            .tree
-            .values()
-            .get(&package)
-            .map(Revision::incremented)
-            .unwrap_or_else(Default::default);
-        self.tree.insert(package, revision);
+        let revision = self.next_revision(&package);
+        self.tree.insert(package, Leaf::new(revision));
+        self.version += 1;
+    }
+
+    /// Puts the targets in the package's leaf.
+    fn publish_targets(&mut self, package: PackageId, targets: Targets) {
+        let leaf = Leaf {
+            revision: self.next_revision(&package),
+            targets: Some(targets),
+        };
+        self.tree.insert(package, leaf);
+        self.version += 1;
+    }
+
+    /// Rehashes each node above the new leaves once.
+    fn publish_batch(&mut self, packages: Vec<PackageId>) {
+        let mut leaves = HashMap::<PackageId, Leaf>::new();
+        for package in packages {
+            let revision = match leaves.get(&package) {
+                Some(leaf) => leaf.revision.incremented(),
+                None => self.next_revision(&package),
+            };
+            leaves.insert(package, Leaf::new(revision));
+            self.version += 1;
+        }
+        self.tree.extend(leaves);
     }
 
-    fn request_file(
-        &mut self,
-        snapshot_id: Self::Id,
-        package: &PackageId,
-    ) -> (Revision, Self::Proof) {
+    fn request_file(&self, snapshot_id: Self::Id, package: &PackageId) -> (Revision, Self::Proof) {
         let proof = self.tree.lookup(package).cloned();
-        let revision = proof
+        let leaf = proof
             .get_unverified()
             .expect("should never get a file request for a missing package");
-        (*revision, proof)
+        (leaf.revision, proof)
     }
 
     fn batch_import(packages: Vec<PackageId>) -> Self {
-        let mut tree: Tree<_, _, _> = Default::default();
+        let mut tree = Self::empty_tree();
+        let version = packages.len() as u64;
         for p in packages {
-            tree.insert(p, Revision::default());
+            tree.insert(p, Leaf::new(Revision::default()));
         }
-        Self { tree }
+        Self { tree, version }
     }
 
     fn id(snapshot: &Self::ClientSnapshot) -> Self::Id {}
@@ -102,22 +265,105 @@ where
         *snapshot = diff
     }
 
-    fn check_no_rollback(snapshot: &Self::ClientSnapshot, diff: &Self::Diff) -> bool {
+    /// The digest is signed; checking the signature would go here.
+    fn verify_diff_structure(_: &Self::ClientSnapshot, _: &Self::Diff) -> bool {
         true
     }
 
+    /// The version can't go down (or stay the same with a different digest).
+    fn verify_freshness(snapshot: &Self::ClientSnapshot, diff: &Self::Diff) -> bool {
+        match diff.version.cmp(&snapshot.version) {
+            Ordering::Less => false,
+            Ordering::Equal => diff.digest.value() == snapshot.digest.value(),
+            Ordering::Greater => true,
+        }
+    }
+
     fn verify_membership(
         snapshot: &Self::ClientSnapshot,
         package_id: &PackageId,
         revision: Revision,
         proof: Self::Proof,
     ) -> bool {
-        snapshot.digest.verify(package_id, proof) == Ok(Some(revision))
+        match snapshot.digest.verify(package_id, proof) {
+            Ok(Some(leaf)) => leaf.revision == revision,
+            _ => false,
+        }
+    }
+
+    fn proven_targets(proof: &Self::Proof) -> Option<Targets> {
+        proof.get_unverified()?.targets
     }
 
     fn cdn_size(&self) -> Information {
         self.size()
     }
+
+    /// One `snapshot` object (the whole tree), which changes with the root.
+    fn cdn_objects(&self) -> CdnObjects<'_> {
+        let root = self.tree.digest().value().to_vec();
+        Box::new(std::iter::once(
+            CdnObject::new("snapshot", self.size()).with_version(root),
+        ))
+    }
+
+    fn export_state(&self) -> Option<RepositoryState> {
+        let revisions = self.tree.values().iter();
+        Some(RepositoryState::new(
+            revisions.map(|(package, leaf)| (package.clone(), leaf.revision)),
+        ))
+    }
+
+    /// The diff is just the latest digest.
+    fn diff_cacheable(_diff: &Self::Diff) -> bool {
+        true
+    }
+
+    fn targeted_refresh() -> bool {
+        true
+    }
+
+    fn batch_proof_size(proofs: &[&Self::Proof]) -> Information {
+        Proof::batch_size(proofs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authenticator::Authenticator as _;
+    use crate::authenticator::MerkleBpt;
+
+    #[test]
+    fn binds_targets() {
+        let package = PackageId::from("libc");
+        let targets = Targets::new(1024, Default::default());
+        let mut authenticator = MerkleBpt::default();
+        authenticator.publish(package.clone());
+        let (_, plain) = authenticator.request_file((), &package);
+
+        authenticator.publish_targets(package.clone(), targets);
+        let snapshot = authenticator.get_metadata();
+        let (revision, proof) = authenticator.request_file((), &package);
+        assert!(MerkleBpt::verify_membership(
+            &snapshot,
+            &package,
+            revision,
+            proof.clone()
+        ));
+        assert_eq!(MerkleBpt::proven_targets(&proof), Some(targets));
+        // Binding the targets costs exactly their size.
+        assert_eq!(proof.size(), plain.size() + Targets::fixed_size());
+
+        // A proof for other targets doesn't check out.
+        let mut other = MerkleBpt::default();
+        other.publish(package.clone());
+        other.publish_targets(package.clone(), Targets::new(2048, Default::default()));
+        let (_, forged) = other.request_file((), &package);
+        assert!(!MerkleBpt::verify_membership(
+            &snapshot, &package, revision, forged
+        ));
+    }
 }
 
 /*
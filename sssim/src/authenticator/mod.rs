@@ -1,38 +1,88 @@
+mod dynamic;
 mod hackage;
+#[cfg(feature = "rsa")]
+mod hybrid;
 mod insecure;
 mod mercury_diff;
+#[cfg(feature = "merkle")]
 mod merkle;
+mod none;
+mod package_list;
+mod registry;
+#[cfg(feature = "rsa")]
 mod rsa;
+#[cfg(feature = "smt")]
 mod sparse_merkle;
+mod state;
 mod vanilla_tuf;
 
 use std::{collections::HashMap, num::NonZeroU64};
 
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+use time::OffsetDateTime;
+use uom::ConstZero;
 
+use crate::edge::EdgeWork;
+use crate::freshness::SignedTimestamp;
+use crate::util::{FixedDataSized, Information};
+
+#[cfg(feature = "rsa")]
 use crate::{
-    accumulator::rsa::Accumulator as RsaAccumulator,
-    util::{FixedDataSized, Information},
+    accumulator::rsa::{Accumulator as RsaAccumulator, StatelessAccumulator},
+    primitives::RsaGroup,
 };
-
-use crate::primitives::RsaGroup;
+pub use dynamic::{DynAuthenticator, DynDiff, DynProof, DynSnapshot, Erased};
 pub use hackage::Authenticator as Hackage;
+#[cfg(feature = "rsa")]
+pub use hybrid::Authenticator as Hybrid;
 pub use insecure::Authenticator as Insecure;
 pub use mercury_diff::Authenticator as MercuryDiff;
+pub use none::Authenticator as NoAuth;
 // pub use mercury_hash::Authenticator as MercuryHash;
 // pub use mercury_hash_diff::Authenticator as MercuryHashDiff;
+#[cfg(feature = "smt")]
 pub use sparse_merkle::Authenticator as SparseMerkle;
+/// [`SparseMerkle`] with a leaf per version, rather than per package.
+#[cfg(feature = "smt")]
+pub type SparseMerkleVersions = sparse_merkle::Authenticator<true>;
+/// [`SparseMerkle`] hashing with BLAKE3 rather than SHA-3.
+#[cfg(feature = "smt")]
+pub type SparseMerkleBlake3 =
+    sparse_merkle::Authenticator<false, crate::primitives::sparse_merkle::Blake3>;
+#[cfg(feature = "rsa")]
 pub type Rsa = rsa::Authenticator<RsaAccumulator<RsaGroup>>;
+#[cfg(feature = "rsa")]
 pub type RsaPool = rsa::PoolAuthenticator<RsaAccumulator<RsaGroup>>;
+/// RSA with a signed checkpoint every 250 publishes (so we see the refresh
+/// bandwidth crossover within the benchmark's elapsed releases).
+#[cfg(feature = "rsa")]
+pub type RsaCheckpoint = rsa::Authenticator<RsaAccumulator<RsaGroup>, 250>;
+/// RSA that only keeps the last 500 digests' worth of history (older clients
+/// bootstrap from a signed digest).
+#[cfg(feature = "rsa")]
+pub type RsaPruned = rsa::Authenticator<RsaAccumulator<RsaGroup>, 0, 500>;
+/// RSA without a proof cache (witnesses computed per request).
+#[cfg(feature = "rsa")]
+pub type RsaStateless = rsa::Authenticator<StatelessAccumulator<RsaGroup>>;
+#[cfg(feature = "rsa")]
+pub type RsaOutsourced = rsa::OutsourcedAuthenticator<RsaAccumulator<RsaGroup>>;
+pub use registry::{Authenticators, Technique, UnknownTechnique, Visitor};
+pub use state::{RepositoryState, StateError};
 pub use vanilla_tuf::Authenticator as VanillaTuf;
+#[cfg(feature = "merkle")]
 pub type MerkleBpt = merkle::Authenticator<sha3::Sha3_256>;
+/// [`MerkleBpt`] with CONIKS-style commitments hiding values (but not package
+/// names; see [`crate::primitives::merkle::Privacy`]).
+#[cfg(feature = "merkle")]
+pub type MerkleBptPrivate = merkle::Authenticator<sha3::Sha3_256, true>;
 
 use crate::{log::PackageId, util::byte, util::DataSized};
 
 #[cfg(test)]
 use {proptest::prelude::*, proptest_derive::Arbitrary};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Revision(pub NonZeroU64);
 
 impl FixedDataSized for Revision {
@@ -59,6 +109,7 @@ impl Default for Revision {
     }
 }
 
+#[cfg(feature = "merkle")]
 impl digest_hash::Hash for Revision {
     fn hash<H>(&self, digest: &mut H)
     where
@@ -69,9 +120,44 @@ impl digest_hash::Hash for Revision {
     }
 }
 
+/// Revisions start at 1 (the first publish), so arithmetic can fail at both
+/// ends. The operators panic; client-side code checking what a server sent
+/// should use the `checked_` versions instead.
 impl Revision {
+    /// The next revision (`None` on overflow).
+    pub fn checked_increment(self) -> Option<Self> {
+        self.checked_add(1)
+    }
+
+    /// The previous revision (`None` if this is the first one).
+    pub fn checked_decrement(self) -> Option<Self> {
+        self.checked_sub(1)
+    }
+
+    pub fn checked_add(self, rhs: usize) -> Option<Self> {
+        let rhs = u64::try_from(rhs).ok()?;
+        self.0.checked_add(rhs).map(Self)
+    }
+
+    /// `None` unless there are more than `rhs` revisions before this one.
+    pub fn checked_sub(self, rhs: usize) -> Option<Self> {
+        let rhs = u64::try_from(rhs).ok()?;
+        NonZeroU64::new(self.0.get().checked_sub(rhs)?).map(Self)
+    }
+
+    /// Stops at `u64::MAX`.
+    pub fn saturating_add(self, rhs: usize) -> Self {
+        let rhs = u64::try_from(rhs).unwrap_or(u64::MAX);
+        Self(self.0.saturating_add(rhs))
+    }
+
+    /// Stops at the first revision.
+    pub fn saturating_sub(self, rhs: usize) -> Self {
+        self.checked_sub(rhs).unwrap_or_default()
+    }
+
     fn incremented(&self) -> Self {
-        Self(NonZeroU64::try_from(self.0.get() + 1).expect("incrementing"))
+        self.checked_increment().expect("revision overflow")
     }
 
     fn increment(&mut self) {
@@ -83,7 +169,7 @@ impl std::ops::Add<usize> for Revision {
     type Output = Revision;
 
     fn add(self, rhs: usize) -> Self::Output {
-        Self::from(self.0.checked_add(rhs.try_into().unwrap()).unwrap())
+        self.checked_add(rhs).expect("revision overflow")
     }
 }
 
@@ -91,8 +177,7 @@ impl std::ops::Sub<usize> for Revision {
     type Output = Revision;
 
     fn sub(self, rhs: usize) -> Self::Output {
-        let num = self.0.get().checked_sub(rhs.try_into().unwrap()).unwrap();
-        Self::from(NonZeroU64::try_from(num).unwrap())
+        self.checked_sub(rhs).expect("revision underflow")
     }
 }
 
@@ -110,9 +195,174 @@ impl Arbitrary for Revision {
 }
 
 #[cfg_attr(test, derive(Arbitrary))]
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Hash(pub [u64; 4]);
 
+/// A package artifact's length and hash, which a client needs to check the
+/// file it downloads (TUF's "targets" metadata).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Targets {
+    pub length: u64,
+    pub hash: Hash,
+}
+
+impl Targets {
+    pub fn new(length: u64, hash: Hash) -> Self {
+        Self { length, hash }
+    }
+
+    /// Big-endian length, then the hash words.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.length.to_be_bytes().to_vec();
+        for word in self.hash.0 {
+            bytes.extend_from_slice(&word.to_be_bytes());
+        }
+        bytes
+    }
+}
+
+impl FixedDataSized for Targets {
+    fn fixed_size() -> Information {
+        Information::new::<byte>(8 + 32)
+    }
+}
+
+/// One file that clients would fetch from a CDN.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CdnObject {
+    /// Path of the object (unique within one authenticator).
+    pub name: String,
+    #[serde(rename = "size_bytes")]
+    pub size: Information,
+    /// Fingerprint of the contents, for objects that can change without
+    /// changing size (see [`crate::cdn`]). `None` means "same size, same
+    /// contents".
+    #[serde(skip)]
+    pub version: Option<u64>,
+}
+
+impl CdnObject {
+    pub fn new(name: impl Into<String>, size: Information) -> Self {
+        Self {
+            name: name.into(),
+            size,
+            version: None,
+        }
+    }
+
+    /// Tag the object with a fingerprint of whatever determines its contents
+    /// (a snapshot ID, a root, an accumulator digest).
+    pub fn with_version(mut self, contents: impl std::hash::Hash) -> Self {
+        use std::hash::Hasher;
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        contents.hash(&mut hasher);
+        self.version = Some(hasher.finish());
+        self
+    }
+}
+
+/// The objects making up an authenticator's CDN contents.
+///
+/// Boxed because trait methods can't return `impl Iterator`.
+pub type CdnObjects<'a> = Box<dyn Iterator<Item = CdnObject> + 'a>;
+
+/// What a client needs before its first refresh, obtained once and out-of-band
+/// (e.g. shipped with the package manager).
+///
+/// These are one-time costs, but they differ a lot between techniques, so a
+/// fair comparison has to count them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TrustAnchor {
+    /// The repository's root keys.
+    #[serde(rename = "keys_bytes")]
+    pub keys: Information,
+    /// Public parameters for the scheme's cryptography (e.g. an RSA modulus
+    /// and generator, or a structured reference string).
+    #[serde(rename = "parameters_bytes")]
+    pub parameters: Information,
+}
+
+impl Default for TrustAnchor {
+    fn default() -> Self {
+        Self {
+            keys: Information::ZERO,
+            parameters: Information::ZERO,
+        }
+    }
+}
+
+impl TrustAnchor {
+    /// One Ed25519 root key.
+    pub fn root_key() -> Self {
+        Self {
+            keys: Information::new::<byte>(32),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_parameters(mut self, parameters: Information) -> Self {
+        self.parameters += parameters;
+        self
+    }
+}
+
+impl DataSized for TrustAnchor {
+    fn size(&self) -> Information {
+        self.keys + self.parameters
+    }
+}
+
+/// A refresh response with the server's signed timestamp, which every
+/// technique sends (even with no diff: "nothing changed" has to be fresh too).
+///
+/// Checked by [`Authenticator::verify_timestamped_freshness`].
+#[derive(Debug, Clone)]
+pub struct Timestamped<D> {
+    pub diff: Option<D>,
+    pub timestamp: SignedTimestamp,
+}
+
+/// The response to [`Authenticator::refresh_packages`].
+#[derive(Debug, Clone)]
+pub struct PackageRefresh<A: Authenticator> {
+    /// An update to the client snapshot (as from `refresh_metadata`).
+    pub diff: Option<A::Diff>,
+    /// Proofs for the requested packages against the updated snapshot.
+    pub proofs: Vec<(PackageId, Revision, A::Proof)>,
+}
+
+/// The client already knows which packages it asked for, so we don't count
+/// their names.
+impl<A: Authenticator> DataSized for PackageRefresh<A> {
+    fn size(&self) -> Information {
+        let proofs: Vec<_> = self.proofs.iter().map(|(_, _, proof)| proof).collect();
+        self.proofs
+            .iter()
+            .fold(self.diff.size(), |acc, (_, revision, _)| {
+                acc + revision.size()
+            })
+            + A::batch_proof_size(&proofs)
+    }
+}
+
+/// An authenticator's internal state is inconsistent (see
+/// [`Authenticator::check_invariants`]).
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{technique}: {message}")]
+pub struct InvariantError {
+    pub technique: &'static str,
+    pub message: String,
+}
+
+impl InvariantError {
+    pub fn new(technique: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            technique,
+            message: message.into(),
+        }
+    }
+}
+
 // Server-side state
 pub trait Authenticator: DataSized {
     /// Client-side state
@@ -125,6 +375,9 @@ pub trait Authenticator: DataSized {
     type Diff: Serialize + DataSized + Clone;
     /// Information neeeded to verify file membership in the snapshot.
     type Proof: Serialize + DataSized + Clone;
+    /// Settings, from the technique's section of the config file (see
+    /// [`crate::config`]).
+    type Config: DeserializeOwned + Default;
 
     fn name() -> &'static str;
 
@@ -134,21 +387,87 @@ pub trait Authenticator: DataSized {
 
     fn publish(&mut self, package: PackageId);
 
+    /// The latest revision of `package`, with a proof against `snapshot_id`.
+    ///
+    /// Takes `&self` so that downloads can be served from several threads at
+    /// once (e.g. in throughput mode) while publishes wait for exclusive
+    /// access; techniques that fill caches as they serve keep them behind a
+    /// lock (see [`crate::util::Cache`]).
     // TODO(maybe): we can always assume that snapshot_id is latest
-    fn request_file(
-        &mut self,
+    fn request_file(&self, snapshot_id: Self::Id, package: &PackageId) -> (Revision, Self::Proof);
+
+    /// The latest revision of `package` as of `snapshot_id`, with a proof
+    /// that it's the latest (see [`Self::verify_latest`]).
+    ///
+    /// By default, [`Self::request_file`], which serves the latest revision.
+    fn request_latest(
+        &self,
         snapshot_id: Self::Id,
         package: &PackageId,
-    ) -> (Revision, Self::Proof);
+    ) -> (Revision, Self::Proof) {
+        self.request_file(snapshot_id, package)
+    }
 
     fn batch_import(packages: Vec<PackageId>) -> Self;
 
+    /// Like [`Self::batch_import`], with the given settings.
+    ///
+    /// Only techniques that have settings need to implement this.
+    fn batch_import_with(packages: Vec<PackageId>, config: Self::Config) -> Self
+    where
+        Self: Sized,
+    {
+        let _ = config;
+        Self::batch_import(packages)
+    }
+
     fn id(snapshot: &Self::ClientSnapshot) -> Self::Id;
 
     fn update(snapshot: &mut Self::ClientSnapshot, diff: Self::Diff);
 
-    /// Verify that applying `diff` doesn't roll back any targets.
-    fn check_no_rollback(snapshot: &Self::ClientSnapshot, diff: &Self::Diff) -> bool;
+    /// Verify that `diff` is well-formed and its proofs check out (e.g. an
+    /// append-only proof from the client's digest), as cryptography.
+    fn verify_diff_structure(snapshot: &Self::ClientSnapshot, diff: &Self::Diff) -> bool;
+
+    /// Verify that `diff` doesn't take the client backwards: no revision goes
+    /// down, and nothing the client has seen disappears.
+    ///
+    /// Metadata expiry is simulated separately (see [`crate::freshness`]),
+    /// and the signed timestamp sent with every refresh is checked by
+    /// [`Self::verify_timestamped_freshness`].
+    fn verify_freshness(snapshot: &Self::ClientSnapshot, diff: &Self::Diff) -> bool;
+
+    /// [`Self::verify_freshness`] for a refresh response with its signed
+    /// timestamp: the diff (if any) doesn't go backwards, and neither does the
+    /// timestamp. It must be no older than `held`, the last one the client
+    /// accepted, and signed no earlier than `oldest`, if the client enforces a
+    /// maximum staleness (see [`crate::freshness::Freshness::oldest_accepted`]).
+    fn verify_timestamped_freshness(
+        snapshot: &Self::ClientSnapshot,
+        response: &Timestamped<Self::Diff>,
+        held: Option<SignedTimestamp>,
+        oldest: Option<OffsetDateTime>,
+    ) -> bool {
+        let timestamp = response.timestamp;
+        held.map_or(true, |held| timestamp >= held)
+            && oldest.map_or(true, |oldest| timestamp.issued() >= oldest)
+            && response
+                .diff
+                .as_ref()
+                .map_or(true, |diff| Self::verify_freshness(snapshot, diff))
+    }
+
+    /// Verify that applying `diff` doesn't roll back any targets: both
+    /// [`Self::verify_diff_structure`] and [`Self::verify_freshness`].
+    fn check_no_rollback(snapshot: &Self::ClientSnapshot, diff: &Self::Diff) -> bool {
+        Self::verify_diff_structure(snapshot, diff) && Self::verify_freshness(snapshot, diff)
+    }
+
+    /// Like [`Self::check_no_rollback`], but batching the checks where that's
+    /// faster (e.g. a long chain of append-only proofs).
+    fn check_no_rollback_batched(snapshot: &Self::ClientSnapshot, diff: &Self::Diff) -> bool {
+        Self::check_no_rollback(snapshot, diff)
+    }
 
     /// Verify that `file` *is* in this snapshot.
     fn verify_membership(
@@ -158,7 +477,270 @@ pub trait Authenticator: DataSized {
         proof: Self::Proof,
     ) -> bool;
 
+    /// Verify that `revision` is the *latest* revision of `package` in this
+    /// snapshot, not just one that was published at some point.
+    ///
+    /// By default, [`Self::verify_membership`]. That's enough when a proof
+    /// pins down the package's current revision: the snapshot lists every
+    /// package's revision (`hackage`, `mercury`, `mercury_diff`, the hybrid's
+    /// hot set), a tree leaf holds it (`merkle_bpt`, `sparse_merkle`), a
+    /// nonmembership proof shows there's no next version
+    /// (`sparse_merkle_versions`), or the witness shows `revision + 1` copies
+    /// aren't accumulated (`rsa`). A technique whose membership proofs only
+    /// show that a revision exists must override this.
+    fn verify_latest(
+        snapshot: &Self::ClientSnapshot,
+        package: &PackageId,
+        revision: Revision,
+        proof: Self::Proof,
+    ) -> bool {
+        Self::verify_membership(snapshot, package, revision, proof)
+    }
+
+    /// Publish `package`, binding its artifact's `targets` into what clients
+    /// verify.
+    ///
+    /// By default, techniques don't authenticate targets and this is just
+    /// [`Self::publish`]. Tree techniques put them in the package's leaf. The
+    /// RSA accumulators don't bind them: a witness shows how many times the
+    /// package's prime was accumulated, so binding targets would take a second
+    /// accumulated element (and witness) per package.
+    fn publish_targets(&mut self, package: PackageId, targets: Targets) {
+        let _ = targets;
+        self.publish(package)
+    }
+
+    /// The targets `proof` binds to its package, if any.
+    ///
+    /// Only meaningful once [`Self::verify_membership`] accepts the proof.
+    fn proven_targets(_proof: &Self::Proof) -> Option<Targets> {
+        None
+    }
+
     fn cdn_size(&self) -> Information;
+
+    /// The individual objects that add up to [`Self::cdn_size`].
+    ///
+    /// By default, everything is one `snapshot` object.
+    fn cdn_objects(&self) -> CdnObjects<'_> {
+        let size = self.cdn_size();
+        if size == Information::ZERO {
+            return Box::new(std::iter::empty());
+        }
+        Box::new(std::iter::once(CdnObject::new("snapshot", size)))
+    }
+
+    /// Whether a CDN could serve `diff`, i.e., it's a static file rather than
+    /// something the origin computes for this particular client.
+    fn diff_cacheable(_diff: &Self::Diff) -> bool {
+        false
+    }
+
+    /// Whether a CDN could serve `proof` (see [`Self::diff_cacheable`]).
+    fn proof_cacheable(_proof: &Self::Proof) -> bool {
+        false
+    }
+
+    /// What a CDN edge would do to put `diff` together from cached objects
+    /// (those in [`Self::cdn_objects`]), if it can (see [`crate::edge`]).
+    ///
+    /// By default, it can't: only the origin can compute the diff.
+    fn diff_edge_work(&self, _diff: &Self::Diff) -> Option<EdgeWork> {
+        None
+    }
+
+    /// What a CDN edge would do to put `proof` together (see
+    /// [`Self::diff_edge_work`]).
+    fn proof_edge_work(&self, _proof: &Self::Proof) -> Option<EdgeWork> {
+        None
+    }
+
+    /// Request/response rounds a client needs to refresh, given the diff it
+    /// got (`None` if it was already up to date).
+    ///
+    /// By default one: ask for a diff (or learn there isn't one).
+    fn refresh_round_trips(_diff: Option<&Self::Diff>) -> u32 {
+        1
+    }
+
+    /// Request/response rounds a client needs to get `proof` before it can
+    /// trust a download. The artifact itself isn't counted (as with
+    /// bandwidth).
+    ///
+    /// By default one, to ask the server for the proof. Techniques whose
+    /// snapshot already tells the client everything override this with zero.
+    fn download_round_trips(_proof: &Self::Proof) -> u32 {
+        1
+    }
+
+    /// Whether [`Self::refresh_packages`] can skip (most of) the snapshot.
+    ///
+    /// This is sound when the snapshot commits to each package separately
+    /// (a Merkle root, an accumulator digest), so that a small diff and one
+    /// proof per package tell the client everything it needs about those
+    /// packages. When the snapshot is a signed list, a client has to see the
+    /// whole diff to know it isn't missing anything.
+    fn targeted_refresh() -> bool {
+        false
+    }
+
+    /// Refresh only what the client needs to know about `packages`.
+    ///
+    /// With [`Self::targeted_refresh`], that's the latest digest and a proof
+    /// per package; otherwise it's a full refresh.
+    fn refresh_packages(
+        &self,
+        snapshot_id: Self::Id,
+        packages: &[PackageId],
+    ) -> PackageRefresh<Self>
+    where
+        Self: Sized,
+    {
+        let diff = self.refresh_metadata(snapshot_id);
+        if !Self::targeted_refresh() {
+            return PackageRefresh {
+                diff,
+                proofs: vec![],
+            };
+        }
+        let latest = self.get_metadata();
+        let proofs = packages
+            .iter()
+            .map(|package| {
+                let (revision, proof) = self.request_file(Self::id(&latest), package);
+                (package.clone(), revision, proof)
+            })
+            .collect();
+        PackageRefresh { diff, proofs }
+    }
+
+    /// Size of `proofs` sent together (as in [`PackageRefresh`]).
+    ///
+    /// By default they're just sent one after another, but some proofs can
+    /// share parts (like the top of a Merkle tree).
+    fn batch_proof_size(proofs: &[&Self::Proof]) -> Information {
+        proofs
+            .iter()
+            .fold(Information::ZERO, |acc, proof| acc + proof.size())
+    }
+
+    /// Apply (and check) the result of [`Self::refresh_packages`].
+    fn update_packages(snapshot: &mut Self::ClientSnapshot, refresh: PackageRefresh<Self>) -> bool
+    where
+        Self: Sized,
+    {
+        if let Some(diff) = refresh.diff {
+            if !Self::check_no_rollback(snapshot, &diff) {
+                return false;
+            }
+            Self::update(snapshot, diff);
+        }
+        for (package, revision, proof) in refresh.proofs {
+            if !Self::verify_membership(snapshot, &package, revision, proof.clone()) {
+                return false;
+            }
+            Self::cache_proof(snapshot, &package, revision, proof);
+        }
+        true
+    }
+
+    /// Stop sharing state with clones of this authenticator.
+    ///
+    /// Some authenticators share their big maps between clones until one of
+    /// them writes (see [`crate::util::Shared`]). Call this after cloning and
+    /// before timing a write, so the copy isn't counted.
+    fn unshare(&mut self) {}
+
+    /// Publish several packages at once.
+    ///
+    /// By default they're published one after another; techniques that can
+    /// share work between them (like rehashing the top of a tree) override
+    /// this. [`Simulator::replay`](crate::simulator::Simulator::replay) uses it
+    /// for publishes with the same timestamp.
+    fn publish_batch(&mut self, packages: Vec<PackageId>) {
+        for package in packages {
+            self.publish(package);
+        }
+    }
+
+    /// Hash invocations on the server so far, for techniques that count them.
+    ///
+    /// Unlike wall time, this doesn't depend on the machine running the
+    /// simulation.
+    fn hash_count(&self) -> Option<u64> {
+        None
+    }
+
+    /// Check that the server's internal state is consistent (e.g. cached
+    /// hashes and proofs match what they were computed from).
+    ///
+    /// This can be as slow as rebuilding the state from scratch; it's for
+    /// tests, soak runs (see [`crate::soak`]), and
+    /// [`Simulator::with_invariant_checks`](crate::simulator::Simulator::with_invariant_checks),
+    /// not benchmarks. By default, there's nothing to check.
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        Ok(())
+    }
+
+    /// Which packages there are and their latest revisions, for moving to
+    /// another technique.
+    ///
+    /// `None` if this technique doesn't know (e.g. an accumulator only has
+    /// the packages' primes).
+    fn export_state(&self) -> Option<RepositoryState> {
+        None
+    }
+
+    /// Set up server state matching an export (possibly from another
+    /// technique).
+    ///
+    /// By default, this imports every package and then publishes each one
+    /// again until it's at its revision.
+    fn import_state(state: &RepositoryState) -> Self
+    where
+        Self: Sized,
+    {
+        state.replay()
+    }
+
+    /// What clients need ahead of time (by default, just a root key to check
+    /// signed metadata).
+    fn trust_anchor(&self) -> TrustAnchor {
+        TrustAnchor::root_key()
+    }
+
+    /// Whether clients hold signed metadata that expires (see
+    /// [`crate::freshness`]).
+    fn metadata_expires() -> bool {
+        true
+    }
+
+    /// Whether this technique needs a structured reference string (see
+    /// [`crate::primitives::Srs`]). None of ours are pairing-based yet.
+    fn uses_srs() -> bool {
+        false
+    }
+
+    /// Keep the proof for a package the client just downloaded and verified.
+    ///
+    /// Only techniques where clients hold their own witnesses need this.
+    fn cache_proof(
+        _snapshot: &mut Self::ClientSnapshot,
+        _package: &PackageId,
+        _revision: Revision,
+        _proof: Self::Proof,
+    ) {
+    }
+
+    /// How much of the client snapshot is cached witnesses.
+    fn witness_state_size(_snapshot: &Self::ClientSnapshot) -> Information {
+        Information::ZERO
+    }
+
+    /// How much of `diff` goes to updating cached witnesses.
+    fn witness_update_size(_diff: &Self::Diff) -> Information {
+        Information::ZERO
+    }
 }
 
 pub trait BatchAuthenticator: Authenticator {
@@ -180,6 +762,51 @@ pub trait PoolAuthenticator: Authenticator {
     fn batch_process(&mut self);
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn test_revision_increment_decrement(revision: Revision) {
+            if let Some(next) = revision.checked_increment() {
+                prop_assert!(next > revision);
+                prop_assert_eq!(next.checked_decrement(), Some(revision));
+            }
+            match revision.checked_decrement() {
+                Some(prev) => {
+                    prop_assert!(prev < revision);
+                    prop_assert_eq!(prev.checked_increment(), Some(revision));
+                }
+                None => prop_assert_eq!(revision, Revision::default()),
+            }
+        }
+
+        #[test]
+        fn test_revision_add_sub(revision: Revision, n in 0..1000usize) {
+            prop_assert_eq!(revision.checked_sub(n).is_some(), (n as u64) < revision.0.get());
+            if let Some(sum) = revision.checked_add(n) {
+                prop_assert_eq!(sum.checked_sub(n), Some(revision));
+                prop_assert_eq!(revision.saturating_add(n), sum);
+            }
+            let difference = revision.saturating_sub(n);
+            prop_assert!(difference >= Revision::default());
+            prop_assert!(difference <= revision);
+        }
+    }
+
+    #[test]
+    fn test_revision_bounds() {
+        assert_eq!(Revision::default().checked_decrement(), None);
+        assert_eq!(Revision::default().saturating_sub(5), Revision::default());
+        assert_eq!(Revision::from(3).checked_sub(2), Some(Revision::default()));
+        assert_eq!(Revision::from(3).checked_sub(3), None);
+        let max = Revision(NonZeroU64::new(u64::MAX).unwrap());
+        assert_eq!(max.checked_increment(), None);
+        assert_eq!(max.saturating_add(1), max);
+    }
+}
+
 /*
 #[cfg(test)]
 pub(crate) mod tests {
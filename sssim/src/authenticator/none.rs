@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+
+use crate::{
+    authenticator::Revision, config::NoConfig, log::PackageId, util::byte, util::DataSized,
+    util::Information,
+};
+
+/// A repository with no snapshot metadata at all.
+///
+/// Clients hold nothing, refreshes are free, and downloads carry no proof. This
+/// gives every results table an absolute floor to normalize overheads against.
+#[cfg_attr(test, derive(Arbitrary))]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct Authenticator {}
+
+impl DataSized for Authenticator {
+    fn size(&self) -> Information {
+        Information::new::<byte>(0)
+    }
+}
+
+#[allow(unused_variables)]
+impl super::Authenticator for Authenticator {
+    type ClientSnapshot = ();
+    type Id = ();
+    type Diff = ();
+    type Proof = ();
+    type Config = NoConfig;
+
+    fn name() -> &'static str {
+        "none"
+    }
+
+    fn refresh_metadata(&self, _: Self::Id) -> Option<Self::Diff> {
+        None
+    }
+
+    fn get_metadata(&self) -> Self::ClientSnapshot {}
+
+    fn publish(&mut self, _: PackageId) {}
+
+    fn request_file(&self, _: Self::Id, _: &PackageId) -> (Revision, Self::Proof) {
+        (Revision::default(), ())
+    }
+
+    fn batch_import(packages: Vec<PackageId>) -> Self {
+        Self {}
+    }
+
+    fn id(_: &Self::ClientSnapshot) -> Self::Id {}
+
+    fn update(_: &mut Self::ClientSnapshot, _: Self::Diff) {}
+
+    fn verify_diff_structure(_: &Self::ClientSnapshot, _: &Self::Diff) -> bool {
+        true
+    }
+
+    fn verify_freshness(_: &Self::ClientSnapshot, _: &Self::Diff) -> bool {
+        true
+    }
+
+    fn verify_membership(
+        _: &Self::ClientSnapshot,
+        _: &PackageId,
+        _: Revision,
+        _: Self::Proof,
+    ) -> bool {
+        true
+    }
+
+    fn cdn_size(&self) -> Information {
+        Information::new::<byte>(0)
+    }
+
+    /// Clients trust whatever they get.
+    fn trust_anchor(&self) -> super::TrustAnchor {
+        Default::default()
+    }
+
+    /// There's no metadata to expire.
+    fn metadata_expires() -> bool {
+        false
+    }
+
+    /// Clients don't check anything, so there's nothing to fetch.
+    fn download_round_trips(_proof: &Self::Proof) -> u32 {
+        0
+    }
+}
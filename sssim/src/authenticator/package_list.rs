@@ -0,0 +1,132 @@
+//! Compact encoding for lists of package IDs sent to clients.
+//!
+//! Pools (see `rsa_pool`) are lists of publishes, and the same package often
+//! appears in one several times. Rather than repeat each name, a
+//! [`PackageList`] sends each distinct package once (in order of first
+//! appearance) and then the list itself as indices into that table. Each
+//! index is stored as the (zigzag) difference from the previous one, as a
+//! LEB128 varint, so runs of new packages cost a byte each.
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::log::PackageId;
+use crate::util::{byte, DataSized, Information};
+
+fn zigzag(delta: i64) -> u64 {
+    ((delta << 1) ^ (delta >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Read a varint from the front of `bytes`, advancing past it.
+fn read_varint(bytes: &mut &[u8]) -> u64 {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let (first, rest) = bytes.split_first().expect("truncated varint");
+        *bytes = rest;
+        value |= u64::from(first & 0x7f) << shift;
+        if first & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+/// A list of package IDs, encoded as a table of distinct IDs plus varint
+/// index deltas.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PackageList {
+    table: Vec<PackageId>,
+    deltas: Vec<u8>,
+}
+
+impl PackageList {
+    pub fn encode(packages: &[PackageId]) -> Self {
+        let mut table = Vec::new();
+        let mut idxs: HashMap<&PackageId, usize> = HashMap::new();
+        let mut deltas = Vec::new();
+        let mut previous = 0;
+        for package in packages {
+            let idx = *idxs.entry(package).or_insert_with(|| {
+                table.push(package.clone());
+                table.len() - 1
+            });
+            let idx = i64::try_from(idx).unwrap();
+            write_varint(zigzag(idx - previous), &mut deltas);
+            previous = idx;
+        }
+        Self { table, deltas }
+    }
+
+    pub fn decode(&self) -> Vec<PackageId> {
+        let mut packages = Vec::new();
+        let mut bytes = self.deltas.as_slice();
+        let mut idx = 0;
+        while !bytes.is_empty() {
+            idx += unzigzag(read_varint(&mut bytes));
+            let idx = usize::try_from(idx).expect("negative index");
+            packages.push(self.table[idx].clone());
+        }
+        packages
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+}
+
+impl DataSized for PackageList {
+    fn size(&self) -> Information {
+        self.table.size() + Information::new::<byte>(self.deltas.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::FixedDataSized;
+    use proptest::prelude::*;
+
+    #[test]
+    fn repeats_are_small() {
+        let packages: Vec<_> = ["a", "b", "a", "a", "c", "b"]
+            .into_iter()
+            .map(PackageId::from)
+            .collect();
+        let list = PackageList::encode(&packages);
+        assert_eq!(list.decode(), packages);
+        assert!(list.size() < packages.size());
+        assert_eq!(
+            list.size(),
+            PackageId::fixed_size() * 3 + Information::new::<byte>(6)
+        );
+        assert!(PackageList::encode(&[]).is_empty());
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip(packages: Vec<PackageId>) {
+            prop_assert_eq!(PackageList::encode(&packages).decode(), packages);
+        }
+
+        #[test]
+        fn varints(value: u64, delta: i64) {
+            let mut bytes = Vec::new();
+            write_varint(value, &mut bytes);
+            prop_assert_eq!(read_varint(&mut bytes.as_slice()), value);
+            prop_assert_eq!(unzigzag(zigzag(delta)), delta);
+        }
+    }
+}
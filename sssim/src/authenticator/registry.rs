@@ -0,0 +1,601 @@
+//! Look up authenticator techniques by name.
+use std::fmt;
+
+use thiserror::Error;
+
+use super::dynamic::{DynAuthenticator, Erased};
+use crate::authenticator::{self, Authenticator, PoolAuthenticator, RepositoryState};
+use crate::config::TechniqueConfig;
+use crate::estimate::{Growth, Model, ServerScaling};
+use crate::log::PackageId;
+use crate::warm_state::WarmState;
+
+#[derive(Error, Debug)]
+#[error("unknown authenticator technique: {name}")]
+pub struct UnknownTechnique {
+    name: String,
+}
+
+/// A technique that can be instantiated at runtime.
+#[derive(Clone, Copy)]
+pub struct Technique {
+    name: &'static str,
+    import: fn(Vec<PackageId>) -> Box<dyn DynAuthenticator>,
+    import_with: ImportWith,
+    import_state: fn(&RepositoryState) -> Box<dyn DynAuthenticator>,
+    check_config: fn(&TechniqueConfig) -> Result<(), toml::de::Error>,
+    model: fn() -> Model,
+    server_scaling: Option<ServerScaling>,
+    by_default: bool,
+    uses_srs: bool,
+}
+
+type ImportWith =
+    fn(Vec<PackageId>, &TechniqueConfig) -> Result<Box<dyn DynAuthenticator>, toml::de::Error>;
+
+impl Technique {
+    fn new<A>(name: &'static str, model: fn() -> Model) -> Self
+    where
+        A: Authenticator + 'static,
+        Erased<A>: DynAuthenticator,
+    {
+        Self {
+            name,
+            import: |packages| Box::new(Erased::new(A::batch_import(packages))),
+            import_with: |packages, config| {
+                let config = config.parse()?;
+                Ok(Box::new(Erased::new(A::batch_import_with(
+                    packages, config,
+                ))))
+            },
+            import_state: |state| Box::new(Erased::new(A::import_state(state))),
+            check_config: |config| config.parse::<A::Config>().map(drop),
+            model,
+            server_scaling: None,
+            by_default: false,
+            uses_srs: A::uses_srs(),
+        }
+    }
+
+    fn pool<A>(name: &'static str, model: fn() -> Model) -> Self
+    where
+        A: PoolAuthenticator + 'static,
+        Erased<A>: DynAuthenticator,
+    {
+        Self {
+            name,
+            import: |packages| Box::new(Erased::new_pool(A::batch_import(packages))),
+            import_with: |packages, config| {
+                let config = config.parse()?;
+                Ok(Box::new(Erased::new_pool(A::batch_import_with(
+                    packages, config,
+                ))))
+            },
+            import_state: |state| {
+                let mut authenticator = Erased::new_pool(A::import_state(state));
+                authenticator.batch_process();
+                Box::new(authenticator)
+            },
+            check_config: |config| config.parse::<A::Config>().map(drop),
+            model,
+            server_scaling: None,
+            by_default: false,
+            uses_srs: A::uses_srs(),
+        }
+    }
+
+    /// The name of this technique (as passed to `--authenticators`).
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Whether a run with no `--authenticators` includes this technique.
+    pub fn by_default(&self) -> bool {
+        self.by_default
+    }
+
+    /// A rough model of what a full run of this technique costs.
+    pub fn model(&self) -> Model {
+        (self.model)()
+    }
+
+    /// Whether this technique needs a structured reference string (`--srs`).
+    pub fn uses_srs(&self) -> bool {
+        self.uses_srs
+    }
+
+    /// How this technique's server-side costs grow, if it's too slow to run
+    /// at millions of packages.
+    pub fn server_scaling(&self) -> Option<ServerScaling> {
+        self.server_scaling
+    }
+
+    /// Set up server state for an initial set of packages.
+    pub fn import(&self, packages: Vec<PackageId>) -> Box<dyn DynAuthenticator> {
+        (self.import)(packages)
+    }
+
+    /// Like [`Self::import`], with settings from the technique's section of
+    /// the config file (see [`crate::config`]).
+    pub fn import_with(
+        &self,
+        packages: Vec<PackageId>,
+        config: &TechniqueConfig,
+    ) -> Result<Box<dyn DynAuthenticator>, toml::de::Error> {
+        (self.import_with)(packages, config)
+    }
+
+    /// Check that `config` has only settings this technique knows about.
+    pub fn check_config(&self, config: &TechniqueConfig) -> Result<(), toml::de::Error> {
+        (self.check_config)(config)
+    }
+
+    /// Set up server state from another technique's export (see
+    /// [`Authenticator::import_state`]).
+    pub fn import_state(&self, state: &RepositoryState) -> Box<dyn DynAuthenticator> {
+        (self.import_state)(state)
+    }
+}
+
+impl fmt::Debug for Technique {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Technique").field(&self.name).finish()
+    }
+}
+
+/// Something to do with a technique's concrete type, for callers that can't
+/// go through [`DynAuthenticator`] (e.g. the benchmarks, which time the
+/// generic code itself).
+///
+/// See [`Authenticators::visit`].
+pub trait Visitor {
+    type Output;
+
+    fn visit<A>(&mut self, technique: Technique) -> Self::Output
+    where
+        A: Authenticator + WarmState + Clone + fmt::Debug + Send + Sync + 'static,
+        A::ClientSnapshot: Send + Sync,
+        A::Diff: Send,
+        A::Proof: Send;
+
+    /// Like [`Self::visit`], for techniques that publish in batches.
+    fn visit_pool<A>(&mut self, technique: Technique) -> Self::Output
+    where
+        A: PoolAuthenticator + WarmState + Clone + fmt::Debug + Send + Sync + 'static,
+        A::ClientSnapshot: Send + Sync,
+        A::Diff: Send,
+        A::Proof: Send;
+}
+
+/// A [`Technique`], and how to hand it (with its type) to a `V`.
+struct Entry<V: Visitor> {
+    technique: Technique,
+    visit: fn(&mut V, Technique) -> V::Output,
+}
+
+impl<V: Visitor> Entry<V> {
+    fn new<A>(name: &'static str, model: fn() -> Model) -> Self
+    where
+        A: Authenticator + WarmState + Clone + fmt::Debug + Send + Sync + 'static,
+        A::ClientSnapshot: Send + Sync,
+        A::Diff: Send,
+        A::Proof: Send,
+    {
+        Self {
+            technique: Technique::new::<A>(name, model),
+            visit: |visitor, technique| visitor.visit::<A>(technique),
+        }
+    }
+
+    fn pool<A>(name: &'static str, model: fn() -> Model) -> Self
+    where
+        A: PoolAuthenticator + WarmState + Clone + fmt::Debug + Send + Sync + 'static,
+        A::ClientSnapshot: Send + Sync,
+        A::Diff: Send,
+        A::Proof: Send,
+    {
+        Self {
+            technique: Technique::pool::<A>(name, model),
+            visit: |visitor, technique| visitor.visit_pool::<A>(technique),
+        }
+    }
+
+    /// Mark this technique's server-side phases as too slow to run at
+    /// millions of packages.
+    fn with_server_scaling(mut self, server_scaling: ServerScaling) -> Self {
+        self.technique.server_scaling = Some(server_scaling);
+        self
+    }
+
+    /// Include this technique in runs with no `--authenticators`.
+    fn by_default(mut self) -> Self {
+        self.technique.by_default = true;
+        self
+    }
+}
+
+/// Collects each [`Technique`] (for [`Authenticators::all`]).
+struct Collect;
+
+impl Visitor for Collect {
+    type Output = Technique;
+
+    fn visit<A>(&mut self, technique: Technique) -> Technique
+    where
+        A: Authenticator + WarmState + Clone + fmt::Debug + Send + Sync + 'static,
+        A::ClientSnapshot: Send + Sync,
+        A::Diff: Send,
+        A::Proof: Send,
+    {
+        technique
+    }
+
+    fn visit_pool<A>(&mut self, technique: Technique) -> Technique
+    where
+        A: PoolAuthenticator + WarmState + Clone + fmt::Debug + Send + Sync + 'static,
+        A::ClientSnapshot: Send + Sync,
+        A::Diff: Send,
+        A::Proof: Send,
+    {
+        technique
+    }
+}
+
+/// Passes the technique called `name` (only) on to `visitor`.
+struct Find<'a, V: Visitor> {
+    name: &'a str,
+    visitor: &'a mut V,
+    output: Option<V::Output>,
+}
+
+impl<V: Visitor> Visitor for Find<'_, V> {
+    type Output = ();
+
+    fn visit<A>(&mut self, technique: Technique)
+    where
+        A: Authenticator + WarmState + Clone + fmt::Debug + Send + Sync + 'static,
+        A::ClientSnapshot: Send + Sync,
+        A::Diff: Send,
+        A::Proof: Send,
+    {
+        if technique.name == self.name {
+            self.output = Some(self.visitor.visit::<A>(technique));
+        }
+    }
+
+    fn visit_pool<A>(&mut self, technique: Technique)
+    where
+        A: PoolAuthenticator + WarmState + Clone + fmt::Debug + Send + Sync + 'static,
+        A::ClientSnapshot: Send + Sync,
+        A::Diff: Send,
+        A::Proof: Send,
+    {
+        if technique.name == self.name {
+            self.output = Some(self.visitor.visit_pool::<A>(technique));
+        }
+    }
+}
+
+#[cfg(feature = "rsa")]
+/// Precomputing every witness goes through a product tree, and a publish
+/// updates every cached witness.
+const RSA_SCALING: ServerScaling = ServerScaling {
+    precompute: Growth::Linearithmic,
+    update: Growth::Linear,
+    state: Growth::Linear,
+};
+
+/// Entry point for constructing authenticators without naming their types.
+///
+/// ```no_run
+/// # use sssim::prelude::*;
+/// let technique = Authenticators::by_name("hackage")?;
+/// let server = technique.import(vec![PackageId::from("foo".to_string())]);
+/// # Ok::<(), UnknownTechnique>(())
+/// ```
+pub struct Authenticators;
+
+impl Authenticators {
+    /// Every technique enabled in this build, in order.
+    fn entries<V: Visitor>() -> Vec<Entry<V>> {
+        vec![
+            Entry::new::<authenticator::NoAuth>("none", Model::unauthenticated).by_default(),
+            Entry::new::<authenticator::Insecure>("insecure", Model::unauthenticated).by_default(),
+            Entry::new::<authenticator::Hackage>("hackage", Model::hackage).by_default(),
+            Entry::new::<authenticator::MercuryDiff>("mercury_diff", Model::mercury_diff)
+                .with_server_scaling(ServerScaling {
+                    precompute: Growth::Linear,
+                    update: Growth::Linear,
+                    state: Growth::Linear,
+                })
+                .by_default(),
+            Entry::new::<authenticator::VanillaTuf>("mercury", Model::mercury).by_default(),
+            #[cfg(feature = "smt")]
+            Entry::new::<authenticator::SparseMerkle>("sparse_merkle", Model::sparse_merkle)
+                .by_default(),
+            #[cfg(feature = "smt")]
+            Entry::new::<authenticator::SparseMerkleVersions>(
+                "sparse_merkle_versions",
+                Model::sparse_merkle,
+            ),
+            #[cfg(feature = "smt")]
+            Entry::new::<authenticator::SparseMerkleBlake3>(
+                "sparse_merkle_blake3",
+                Model::sparse_merkle,
+            ),
+            #[cfg(feature = "merkle")]
+            Entry::new::<authenticator::MerkleBpt>("merkle_bpt", Model::merkle_bpt),
+            #[cfg(feature = "merkle")]
+            Entry::new::<authenticator::MerkleBptPrivate>("merkle_bpt_private", Model::merkle_bpt),
+            #[cfg(feature = "rsa")]
+            Entry::new::<authenticator::Rsa>("rsa", Model::rsa)
+                .with_server_scaling(RSA_SCALING)
+                .by_default(),
+            #[cfg(feature = "rsa")]
+            Entry::pool::<authenticator::RsaPool>("rsa_pool", Model::rsa_pool)
+                .with_server_scaling(RSA_SCALING)
+                .by_default(),
+            #[cfg(feature = "rsa")]
+            Entry::new::<authenticator::RsaCheckpoint>("rsa_checkpoint", Model::rsa)
+                .with_server_scaling(RSA_SCALING),
+            #[cfg(feature = "rsa")]
+            Entry::new::<authenticator::RsaPruned>("rsa_pruned", Model::rsa)
+                .with_server_scaling(RSA_SCALING),
+            #[cfg(feature = "rsa")]
+            Entry::new::<authenticator::RsaOutsourced>("rsa_outsourced", Model::rsa)
+                .with_server_scaling(RSA_SCALING),
+            #[cfg(feature = "rsa")]
+            Entry::new::<authenticator::RsaStateless>("rsa_stateless", Model::rsa_stateless),
+            #[cfg(feature = "rsa")]
+            Entry::new::<authenticator::Hybrid>("hybrid", Model::rsa)
+                .with_server_scaling(RSA_SCALING),
+        ]
+    }
+
+    /// All techniques enabled in this build.
+    pub fn all() -> Vec<Technique> {
+        Self::visit_all(&mut Collect)
+    }
+
+    pub fn by_name(name: &str) -> Result<Technique, UnknownTechnique> {
+        Self::all()
+            .into_iter()
+            .find(|technique| technique.name == name)
+            .ok_or_else(|| UnknownTechnique {
+                name: name.to_string(),
+            })
+    }
+
+    /// Hand each technique enabled in this build, with its type, to `visitor`.
+    pub fn visit_all<V: Visitor>(visitor: &mut V) -> Vec<V::Output> {
+        Self::entries()
+            .into_iter()
+            .map(|entry| (entry.visit)(visitor, entry.technique))
+            .collect()
+    }
+
+    /// Hand technique `name`, with its type, to `visitor`.
+    pub fn visit<V: Visitor>(name: &str, visitor: &mut V) -> Result<V::Output, UnknownTechnique> {
+        let mut find = Find {
+            name,
+            visitor,
+            output: None,
+        };
+        Self::visit_all(&mut find);
+        find.output.ok_or_else(|| UnknownTechnique {
+            name: name.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authenticator::{Revision, TrustAnchor};
+    use crate::testing::package_ids;
+    use crate::util::{DataSized, Information};
+    use std::collections::HashMap;
+    use std::sync::{Barrier, RwLock};
+    use std::thread;
+    use uom::ConstZero;
+
+    #[test]
+    fn unknown_technique() {
+        assert!(Authenticators::by_name("nope").is_err());
+    }
+
+    #[test]
+    fn visit_by_name() {
+        /// Which technique it saw, and whether as a pool.
+        struct Seen;
+
+        impl Visitor for Seen {
+            type Output = (&'static str, bool);
+
+            fn visit<A>(&mut self, technique: Technique) -> Self::Output
+            where
+                A: Authenticator + WarmState + Clone + fmt::Debug + Send + Sync + 'static,
+                A::ClientSnapshot: Send + Sync,
+                A::Diff: Send,
+                A::Proof: Send,
+            {
+                (technique.name(), false)
+            }
+
+            fn visit_pool<A>(&mut self, technique: Technique) -> Self::Output
+            where
+                A: PoolAuthenticator + WarmState + Clone + fmt::Debug + Send + Sync + 'static,
+                A::ClientSnapshot: Send + Sync,
+                A::Diff: Send,
+                A::Proof: Send,
+            {
+                (technique.name(), true)
+            }
+        }
+
+        for technique in Authenticators::all() {
+            let name = technique.name();
+            let seen = Authenticators::visit(name, &mut Seen).unwrap();
+            assert_eq!(seen, (name, name == "rsa_pool"));
+        }
+        assert!(Authenticators::visit("nope", &mut Seen).is_err());
+    }
+
+    #[test]
+    fn server_scaling() {
+        let scaled = |name| Authenticators::by_name(name).unwrap().server_scaling();
+        assert_eq!(scaled("hackage"), None);
+        assert_eq!(
+            scaled("mercury_diff").map(|s| s.update),
+            Some(Growth::Linear)
+        );
+    }
+
+    #[test]
+    fn trust_anchors() {
+        let packages = vec![PackageId::from("package".to_string())];
+        for technique in Authenticators::all() {
+            let anchor = technique.import(packages.clone()).trust_anchor();
+            match technique.name() {
+                "none" | "insecure" => assert_eq!(anchor.size(), Information::ZERO),
+                name if name.starts_with("rsa") || name == "hybrid" => {
+                    assert!(anchor.parameters > Information::ZERO, "{name}")
+                }
+                "merkle_bpt_private" => assert!(anchor.keys > TrustAnchor::root_key().keys),
+                name => assert_eq!(anchor, TrustAnchor::root_key(), "{name}"),
+            }
+        }
+    }
+
+    #[test]
+    fn dynamic_round_trip() {
+        let packages: Vec<_> = package_ids(10);
+        let mut servers: Vec<Box<dyn DynAuthenticator>> =
+            ["none", "insecure", "hackage", "mercury_diff", "mercury"]
+                .into_iter()
+                .map(|name| Authenticators::by_name(name).unwrap())
+                .map(|technique| technique.import(packages.clone()))
+                .collect();
+        for server in servers.iter_mut() {
+            let name = server.name();
+            let mut snapshot = server.get_metadata();
+
+            let package = PackageId::from("new_package".to_string());
+            server.publish(package.clone());
+            server.batch_process();
+            if let Some(diff) = server.refresh_metadata(&snapshot) {
+                assert!(snapshot.check_no_rollback(&diff));
+                snapshot.update(diff);
+            }
+
+            for package in packages.iter().chain([&package]) {
+                let (revision, proof) = server.request_file(&snapshot, package);
+                assert!(
+                    snapshot.verify_membership(package, revision, proof),
+                    "{name}"
+                );
+            }
+        }
+    }
+
+    /// Interleave publishes and downloads from several threads.
+    ///
+    /// The server is behind a read-write lock (as a real one would be):
+    /// publishes take it exclusively, while readers share it to refresh and
+    /// request files. So this is about whether every interleaving keeps digests
+    /// and proofs consistent: clients verify proofs outside the lock while
+    /// publishes carry on. Every reader holds the lock at once before the first
+    /// publish, so the shared path definitely gets exercised.
+    #[test]
+    fn interleaved_publish_and_request() {
+        const PACKAGES: usize = 4;
+        const PUBLISHERS: usize = 2;
+        const PUBLISHES: usize = 8;
+        const READERS: usize = 2;
+        const READS: usize = 16;
+
+        let packages: Vec<_> = package_ids(PACKAGES);
+        for technique in Authenticators::all() {
+            let name = technique.name();
+            let server = RwLock::new(technique.import(packages.clone()));
+            let start = Barrier::new(PUBLISHERS + READERS);
+            thread::scope(|scope| {
+                for publisher in 0..PUBLISHERS {
+                    let (server, packages, start) = (&server, &packages, &start);
+                    scope.spawn(move || {
+                        start.wait();
+                        for i in 0..PUBLISHES {
+                            let mut server = server.write().unwrap();
+                            server.publish(packages[(publisher + i) % PACKAGES].clone());
+                            server.batch_process();
+                        }
+                    });
+                }
+                for reader in 0..READERS {
+                    let (server, packages, start) = (&server, &packages, &start);
+                    scope.spawn(move || {
+                        let mut snapshot = {
+                            let server = server.read().unwrap();
+                            start.wait();
+                            server.get_metadata()
+                        };
+                        let mut seen = HashMap::new();
+                        for i in 0..READS {
+                            let package = &packages[(reader + i) % PACKAGES];
+                            let (revision, proof) = {
+                                let server = server.read().unwrap();
+                                if let Some(diff) = server.refresh_metadata(&snapshot) {
+                                    assert!(snapshot.check_no_rollback(&diff), "{name}");
+                                    snapshot.update(diff);
+                                }
+                                server.request_file(&snapshot, package)
+                            };
+                            assert!(
+                                snapshot.verify_membership(package, revision, proof),
+                                "{name}"
+                            );
+                            let last = seen.insert(package.clone(), revision);
+                            assert!(last.map_or(true, |last| last <= revision), "{name}");
+                        }
+                    });
+                }
+            });
+
+            // Every publish landed exactly once (for techniques that track
+            // revisions at all).
+            if matches!(name, "none" | "insecure") {
+                continue;
+            }
+            let server = server.into_inner().unwrap();
+            let snapshot = server.get_metadata();
+            for (i, package) in packages.iter().enumerate() {
+                let publishes = (0..PUBLISHERS)
+                    .flat_map(|publisher| (0..PUBLISHES).map(move |j| publisher + j))
+                    .filter(|k| k % PACKAGES == i)
+                    .count();
+                let (revision, proof) = server.request_file(&snapshot, package);
+                assert_eq!(revision, Revision::from(1 + publishes), "{name}");
+                assert!(
+                    snapshot.verify_membership(package, revision, proof),
+                    "{name}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn cdn_objects_add_up() {
+        let packages: Vec<_> = package_ids(10);
+        for technique in Authenticators::all() {
+            let mut server = technique.import(packages.clone());
+            server.publish(PackageId::from("new_package".to_string()));
+            let objects: Vec<_> = server.cdn_objects().collect();
+            let total = objects
+                .iter()
+                .fold(Information::ZERO, |acc, object| acc + object.size);
+            assert_eq!(total, server.cdn_size(), "{}", technique.name());
+            let names: std::collections::HashSet<_> = objects.iter().map(|o| &o.name).collect();
+            assert_eq!(names.len(), objects.len(), "{}", technique.name());
+        }
+    }
+}
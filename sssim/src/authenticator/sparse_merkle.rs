@@ -1,208 +1,508 @@
-use crate::util::{byte, Information};
-use digest::Digest;
-use serde::{Serialize, Serializer};
-use sha3::Sha3_256;
-use smtree::index::TreeIndex;
-use smtree::node_template::HashNodeSmt;
-use smtree::pad_secret::ALL_ZEROS_SECRET;
-use smtree::proof::MerkleProof;
-use smtree::traits::{InclusionProvable, ProofExtractable};
-use smtree::tree::SparseMerkleTree;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::cmp::Ordering;
 use std::collections::HashMap;
-use uom::ConstZero;
+use std::io::{Read, Write};
+use std::num::NonZeroU64;
 
-use authenticator::Revision;
+use authenticator::{CdnObject, CdnObjects, InvariantError, RepositoryState, Revision, Targets};
 
-use crate::util::FixedDataSized;
+use crate::edge::EdgeWork;
+use crate::primitives::sparse_merkle::{self, hash_with, Blake3, Hash, Key, Sha3, Tree, TreeHash};
+use crate::primitives::Domain;
+use crate::util::{byte, Information};
+use crate::warm_state::WarmState;
 use crate::{authenticator, log::PackageId, util::DataSized};
 
-static TREE_HEIGHT: usize = 256;
-type Node = HashNodeSmt<Sha3_256>;
-type Root = <Node as ProofExtractable>::ProofNode;
-
-fn smtree_serialize<S, V>(value: &V, s: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-    V: smtree::traits::Serializable,
-{
-    s.serialize_bytes(&smtree::traits::Serializable::serialize(value))
-}
-
-/// The vanilla TUF client snapshot contains *all* the snapshot state.
+/// The root, signed along with its version (so clients can refuse to go back
+/// to an older one).
 #[derive(Default, Clone, Debug, Serialize)]
 pub struct Snapshot {
-    #[serde(serialize_with = "smtree_serialize")]
-    root: Root,
+    root: Hash,
+    /// Package versions the tree commits to, which goes up with every publish.
+    version: u64,
 }
 
 impl DataSized for Snapshot {
     fn size(&self) -> crate::util::Information {
-        Information::new::<byte>(Sha3_256::output_size())
+        Information::new::<byte>(self.root.len() + std::mem::size_of::<u64>())
     }
 }
 
 impl Snapshot {
-    pub fn new(root: Root) -> Self {
-        Self { root }
+    pub fn new(root: Hash, version: u64) -> Self {
+        Self { root, version }
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
-pub struct Proof {
-    #[serde(serialize_with = "smtree_serialize")]
-    inner: MerkleProof<Node>,
+fn package_hash<H: TreeHash>(package: &PackageId) -> Key {
+    hash_with::<H>(Domain::SparseMerkleIndex, &package.canonical_bytes())
 }
 
-impl DataSized for Proof {
-    fn size(&self) -> Information {
-        let siblings_size = self.inner.get_path_siblings().len()
-            * Information::new::<byte>(Sha3_256::output_size());
-        let indexes_size = self.inner.get_indexes().len() * Information::new::<byte>(40); // each index has a usize and 32 u8s
-        siblings_size + indexes_size
+/// The key for one version of `package`, when each version gets its own leaf.
+fn version_hash<H: TreeHash>(package: &PackageId, revision: &Revision) -> Key {
+    let mut bytes = package.canonical_bytes();
+    bytes.extend(revision.0.get().to_be_bytes());
+    hash_with::<H>(Domain::SparseMerkleIndex, &bytes)
+}
+
+fn revision_hash<H: TreeHash>(revision: &Revision) -> Hash {
+    hash_with::<H>(Domain::SparseMerkleValue, &revision.0.get().to_be_bytes())
+}
+
+/// The leaf value for `revision`, with `targets` bound in if we have them.
+fn leaf_value<H: TreeHash>(revision: &Revision, targets: Option<&Targets>) -> Hash {
+    match targets {
+        None => revision_hash::<H>(revision),
+        Some(targets) => {
+            let mut bytes = revision.0.get().to_be_bytes().to_vec();
+            bytes.extend(targets.to_bytes());
+            hash_with::<H>(Domain::SparseMerkleValue, &bytes)
+        }
     }
 }
 
-impl From<MerkleProof<Node>> for Proof {
-    fn from(inner: MerkleProof<Node>) -> Self {
-        Proof { inner }
+/// The value of a package family's leaf: a hash chain over its versions,
+/// extended by `revision` (with `targets` bound in if we have them).
+fn family_value<H: TreeHash>(
+    previous: &Hash,
+    revision: &Revision,
+    targets: Option<&Targets>,
+) -> Hash {
+    let mut bytes = previous.to_vec();
+    bytes.extend(leaf_value::<H>(revision, targets));
+    hash_with::<H>(Domain::SparseMerkleValue, &bytes)
+}
+
+/// A path to the package's leaf, plus the targets in that leaf (if any).
+///
+/// With one leaf per package, the leaf commits to the whole version list, so
+/// we also send the chain head before the latest version. With one leaf per
+/// version, we also show that the *next* version's leaf is empty, so the
+/// client knows it got the latest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(bound = "")]
+pub struct Proof<H: TreeHash = Sha3> {
+    path: sparse_merkle::Proof<H>,
+    previous: Option<Hash>,
+    next: Option<sparse_merkle::Proof<H>>,
+    targets: Option<Targets>,
+}
+
+impl<H: TreeHash> Proof<H> {
+    /// Everything but the paths (which batch together).
+    fn leaf_size(&self) -> Information {
+        Information::new::<byte>(self.previous.map_or(0, |h| h.len())) + self.targets.size()
     }
 }
 
-fn hash(data: &[u8]) -> [u8; 32] {
-    let mut hasher = Sha3_256::new();
-    hasher.update(data);
-    hasher.finalize().into()
+impl<H: TreeHash> DataSized for Proof<H> {
+    fn size(&self) -> Information {
+        self.path.size() + self.next.size() + self.leaf_size()
+    }
 }
 
-#[derive(Debug, Clone)]
-pub struct Authenticator {
-    tree: SparseMerkleTree<Node>,
-    revisions: HashMap<PackageId, Revision>,
+/// The chain over one package's versions.
+#[derive(Debug, Clone, Copy, Default)]
+struct Chain {
+    /// Before the latest version.
+    previous: Hash,
+    head: Hash,
 }
 
-impl Default for Authenticator {
+/// The most we'll risk two packages colliding at import (see
+/// [`sparse_merkle::collision_probability`]).
+pub const MAX_COLLISION_PROBABILITY: f64 = 1e-6;
+
+/// Technique names for each hash function.
+pub trait Techniques: TreeHash {
+    /// With one leaf per package, and with one per version.
+    const NAMES: [&'static str; 2];
+}
+
+impl Techniques for Sha3 {
+    const NAMES: [&'static str; 2] = ["sparse_merkle", "sparse_merkle_versions"];
+}
+
+impl Techniques for Blake3 {
+    const NAMES: [&'static str; 2] = ["sparse_merkle_blake3", "sparse_merkle_versions_blake3"];
+}
+
+/// Settings (see [`crate::config`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// How long precomputation takes on top of building the tree, in seconds
+    /// (simulating the cost of a real deployment's storage). Zero by default,
+    /// so import times only measure building the tree with each hash.
+    pub precompute_delay_secs: u64,
+    /// Tree height: keys are truncated to this many bits. Proofs that end at
+    /// another leaf carry its key, so they shrink with the height.
+    #[serde(deserialize_with = "height")]
+    pub height: usize,
+}
+
+impl Default for Config {
     fn default() -> Self {
         Self {
-            // SparseMerkleTree::default gives it a height of 0!!
-            tree: SparseMerkleTree::new(TREE_HEIGHT),
-            revisions: Default::default(),
+            precompute_delay_secs: 0,
+            height: sparse_merkle::HEIGHT,
+        }
+    }
+}
+
+fn height<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    let height = usize::deserialize(deserializer)?;
+    let range = sparse_merkle::MIN_HEIGHT..=sparse_merkle::HEIGHT;
+    if !range.contains(&height) {
+        return Err(D::Error::custom(format!(
+            "height must be between {} and {}, not {height}",
+            range.start(),
+            range.end()
+        )));
+    }
+    Ok(height)
+}
+
+/// Sparse Merkle tree over either package families (`PER_VERSION = false`:
+/// one leaf per package, committing to its version list) or individual
+/// versions (one leaf per package *and* revision), hashing with `H`.
+#[derive(Debug, Clone, Default)]
+pub struct Authenticator<const PER_VERSION: bool = false, H: TreeHash = Sha3> {
+    tree: Tree<H>,
+    revisions: HashMap<PackageId, Revision>,
+    /// Targets for the latest revision of each package published with them.
+    targets: HashMap<PackageId, Targets>,
+    /// Version chains, for package families.
+    chains: HashMap<PackageId, Chain>,
+    /// Sum of the revisions (see [`Snapshot`]).
+    version: u64,
+}
+
+impl<const PER_VERSION: bool, H: TreeHash> Authenticator<PER_VERSION, H> {
+    fn from_revisions(revisions: HashMap<PackageId, Revision>) -> Self {
+        Self::from_revisions_with_height(revisions, sparse_merkle::HEIGHT)
+    }
+
+    fn from_revisions_with_height(revisions: HashMap<PackageId, Revision>, height: usize) -> Self {
+        let mut authenticator = Self::default();
+        let mut leaves = Vec::new();
+        for (package, latest) in &revisions {
+            for revision in 1..=latest.0.get() {
+                let revision = Revision::from(NonZeroU64::new(revision).unwrap());
+                leaves.push(authenticator.leaf(package, &revision, None));
+            }
+        }
+        authenticator.tree = Tree::empty(height);
+        authenticator.tree.extend(leaves);
+        authenticator.version = revisions.values().map(|r| r.0.get()).sum();
+        authenticator.revisions = revisions;
+        authenticator
+    }
+
+    /// The leaf for `package` at (new) `revision`, extending its chain.
+    fn leaf(
+        &mut self,
+        package: &PackageId,
+        revision: &Revision,
+        targets: Option<&Targets>,
+    ) -> (Key, Hash) {
+        if PER_VERSION {
+            return (
+                version_hash::<H>(package, revision),
+                leaf_value::<H>(revision, targets),
+            );
         }
+        let chain = self.chains.entry(package.clone()).or_default();
+        chain.previous = chain.head;
+        chain.head = family_value::<H>(&chain.previous, revision, targets);
+        (package_hash::<H>(package), chain.head)
+    }
+
+    /// Bump `package`'s revision, returning the new one.
+    fn bump(&mut self, package: PackageId) -> Revision {
+        self.version += 1;
+        *self
+            .revisions
+            .entry(package)
+            .and_modify(|r| r.0 = r.0.checked_add(1).unwrap())
+            .or_insert_with(Revision::default)
     }
 }
 
 #[allow(unused_variables)]
-impl super::Authenticator for Authenticator {
+impl<const PER_VERSION: bool, H: Techniques> super::Authenticator
+    for Authenticator<PER_VERSION, H>
+{
     type ClientSnapshot = Snapshot;
-    type Id = Root;
+    type Id = Hash;
     type Diff = Snapshot;
-    type Proof = Proof;
+    type Proof = Proof<H>;
+    type Config = Config;
 
     fn name() -> &'static str {
-        "sparse_merkle"
+        H::NAMES[usize::from(PER_VERSION)]
     }
 
     fn batch_import(packages: Vec<PackageId>) -> Self {
-        let mut nodes = Vec::<(TreeIndex, Node)>::new();
-        let mut revisions = HashMap::<PackageId, Revision>::new();
-        for p in packages {
-            let idx = TreeIndex::new(TREE_HEIGHT, hash(p.0.as_bytes()));
-            let revision = Revision::default();
-            revisions.insert(p, revision);
-            let node = Node::new(hash(&revision.0.get().to_be_bytes()).to_vec());
-            nodes.push((idx, node));
-        }
-        let mut tree = SparseMerkleTree::new(TREE_HEIGHT);
-        nodes.sort_by_key(|(x, _)| *x);
-        tree.build(&nodes, &ALL_ZEROS_SECRET);
-        std::thread::sleep(std::time::Duration::from_secs(30));
-        Self { tree, revisions }
+        Self::batch_import_with(packages, Config::default())
+    }
+
+    /// Panics if the packages are too likely to collide at `config.height`.
+    fn batch_import_with(packages: Vec<PackageId>, config: Config) -> Self {
+        let collision = sparse_merkle::collision_probability(packages.len(), config.height);
+        assert!(
+            collision <= MAX_COLLISION_PROBABILITY,
+            "{} packages in a tree of height {} collide with probability {collision:.1e} \
+             (more than {MAX_COLLISION_PROBABILITY:.0e}); use a taller tree",
+            packages.len(),
+            config.height,
+        );
+        let revisions = packages
+            .into_iter()
+            .map(|p| (p, Revision::default()))
+            .collect();
+        let authenticator = Self::from_revisions_with_height(revisions, config.height);
+        std::thread::sleep(std::time::Duration::from_secs(config.precompute_delay_secs));
+        authenticator
     }
 
     fn refresh_metadata(&self, snapshot_id: Self::Id) -> Option<Self::Diff> {
-        let my_root = self.tree.get_root();
+        let my_root = self.tree.root();
         if snapshot_id == my_root {
             return None;
         }
-        Some(Snapshot::new(my_root))
+        Some(Snapshot::new(my_root, self.version))
     }
 
     fn publish(&mut self, package: PackageId) {
-        let idx = TreeIndex::new(TREE_HEIGHT, hash(package.0.as_bytes()));
-        let revision = self
-            .revisions
-            .entry(package)
-            .and_modify(|r| r.0 = r.0.checked_add(1).unwrap())
-            .or_insert_with(Revision::default);
+        self.targets.remove(&package);
+        let revision = self.bump(package.clone());
+        let (key, value) = self.leaf(&package, &revision, None);
+        self.tree.insert(key, value);
+    }
 
-        let node = Node::new(hash(&revision.0.get().to_be_bytes()).to_vec());
-        self.tree.update(&idx, node, &ALL_ZEROS_SECRET);
+    /// Puts the targets in the package's leaf.
+    fn publish_targets(&mut self, package: PackageId, targets: Targets) {
+        self.targets.insert(package.clone(), targets);
+        let revision = self.bump(package.clone());
+        let (key, value) = self.leaf(&package, &revision, Some(&targets));
+        self.tree.insert(key, value);
     }
 
-    fn request_file(
-        &mut self,
-        snapshot_id: Self::Id,
-        package: &PackageId,
-    ) -> (Revision, Self::Proof) {
+    /// Rehashes each node above the new leaves once.
+    fn publish_batch(&mut self, packages: Vec<PackageId>) {
+        let leaves: Vec<_> = packages
+            .into_iter()
+            .map(|package| {
+                self.targets.remove(&package);
+                let revision = self.bump(package.clone());
+                self.leaf(&package, &revision, None)
+            })
+            .collect();
+        self.tree.extend(leaves);
+    }
+
+    fn hash_count(&self) -> Option<u64> {
+        Some(self.tree.hash_count())
+    }
+
+    fn export_state(&self) -> Option<RepositoryState> {
+        Some(RepositoryState::new(self.revisions.clone()))
+    }
+
+    /// The snapshot version counts the package versions, there's a leaf per
+    /// package (or version) holding its chain head, and the tree's stored
+    /// hashes match its leaves.
+    fn check_invariants(&self) -> Result<(), InvariantError> {
+        let violation = |message: String| Err(InvariantError::new(Self::name(), message));
+        let versions: u64 = self.revisions.values().map(|r| r.0.get()).sum();
+        if self.version != versions {
+            return violation(format!(
+                "snapshot version {}, but {versions} package versions",
+                self.version
+            ));
+        }
+        let leaves = if PER_VERSION {
+            usize::try_from(versions).unwrap()
+        } else {
+            self.revisions.len()
+        };
+        if self.tree.len() != leaves {
+            return violation(format!("{} leaves, not {leaves}", self.tree.len()));
+        }
+        if !PER_VERSION {
+            for (package, chain) in &self.chains {
+                if self.tree.get(&package_hash::<H>(package)) != Some(&chain.head) {
+                    return violation(format!("{package:?}'s leaf isn't its chain head"));
+                }
+            }
+        }
+        if !self.tree.is_consistent() {
+            return violation("stored hashes don't match the leaves".to_string());
+        }
+        Ok(())
+    }
+
+    /// Builds the tree directly (at full height), rather than replaying
+    /// publishes.
+    fn import_state(state: &RepositoryState) -> Self {
+        Self::from_revisions(state.revisions().clone().into_iter().collect())
+    }
+
+    fn request_file(&self, snapshot_id: Self::Id, package: &PackageId) -> (Revision, Self::Proof) {
         let revision = self
             .revisions
             .get(package)
             .expect("Should never get a request for a package that's missing.");
-        let idx = TreeIndex::new(TREE_HEIGHT, hash(package.0.as_bytes()));
-        let proof = MerkleProof::<Node>::generate_inclusion_proof(&self.tree, &[idx])
-            .expect("Proof generation failed.");
-
-        (*revision, proof.into())
+        let targets = self.targets.get(package).copied();
+        let proof = if PER_VERSION {
+            Proof {
+                path: self.tree.lookup(&version_hash::<H>(package, revision)),
+                previous: None,
+                next: Some(
+                    self.tree
+                        .lookup(&version_hash::<H>(package, &(*revision + 1))),
+                ),
+                targets,
+            }
+        } else {
+            Proof {
+                path: self.tree.lookup(&package_hash::<H>(package)),
+                previous: Some(self.chains[package].previous),
+                next: None,
+                targets,
+            }
+        };
+        (*revision, proof)
     }
 
     fn get_metadata(&self) -> Snapshot {
-        Snapshot::new(self.tree.get_root())
+        Snapshot::new(self.tree.root(), self.version)
     }
 
     fn id(snapshot: &Self::ClientSnapshot) -> Self::Id {
-        snapshot.root.clone()
+        snapshot.root
     }
 
     fn update(snapshot: &mut Self::ClientSnapshot, diff: Self::Diff) {
-        snapshot.root = diff.root
+        *snapshot = diff;
     }
 
-    fn check_no_rollback(snapshot: &Self::ClientSnapshot, _diff: &Self::Diff) -> bool {
+    /// The root is signed; checking the signature would go here.
+    fn verify_diff_structure(_: &Self::ClientSnapshot, _: &Self::Diff) -> bool {
         true
     }
 
+    /// The version can't go down (or stay the same with a different root).
+    fn verify_freshness(snapshot: &Self::ClientSnapshot, diff: &Self::Diff) -> bool {
+        match diff.version.cmp(&snapshot.version) {
+            Ordering::Less => false,
+            Ordering::Equal => diff.root == snapshot.root,
+            Ordering::Greater => true,
+        }
+    }
+
     fn verify_membership(
         snapshot: &Self::ClientSnapshot,
         package_id: &PackageId,
         revision: Revision,
         proof: Self::Proof,
     ) -> bool {
-        let expected_index = TreeIndex::new(TREE_HEIGHT, hash(package_id.0.as_bytes()));
-        let leaf = Node::new(hash(&revision.0.get().to_be_bytes()).to_vec());
-        let idxs = proof.inner.get_indexes();
-        if idxs.len() != 1 {
-            return false;
+        let targets = proof.targets.as_ref();
+        if PER_VERSION {
+            let next = match proof.next {
+                Some(next) => next,
+                None => return false,
+            };
+            let key = version_hash::<H>(package_id, &revision);
+            let value = leaf_value::<H>(&revision, targets);
+            proof.path.verify(&snapshot.root, &key) == Ok(Some(value))
+                && next.verify(
+                    &snapshot.root,
+                    &version_hash::<H>(package_id, &(revision + 1)),
+                ) == Ok(None)
+        } else {
+            let previous = match proof.previous {
+                Some(previous) => previous,
+                None => return false,
+            };
+            let value = family_value::<H>(&previous, &revision, targets);
+            proof
+                .path
+                .verify(&snapshot.root, &package_hash::<H>(package_id))
+                == Ok(Some(value))
         }
-        if idxs[0] != expected_index {
-            return false;
-        }
-        if !proof.inner.verify(&leaf, &snapshot.root) {
-            return false;
-        }
-        true
+    }
+
+    fn proven_targets(proof: &Self::Proof) -> Option<Targets> {
+        proof.targets
     }
 
     fn cdn_size(&self) -> Information {
-        let hash_size = Information::new::<byte>(32);
-        let leaf_size = PackageId::fixed_size() + usize::fixed_size() + hash_size;
-        let internal_size = 3 * usize::fixed_size() + hash_size;
-        let num_leaves = self.tree.get_leaves().len();
+        self.tree.size()
+    }
+
+    /// One `snapshot` object (the whole tree), which changes with the root.
+    fn cdn_objects(&self) -> CdnObjects<'_> {
+        let snapshot = CdnObject::new("snapshot", self.tree.size()).with_version(self.tree.root());
+        Box::new(std::iter::once(snapshot))
+    }
 
-        // assume worst case: all possible internal nodes, no padding
-        leaf_size * num_leaves + internal_size * self.tree.get_nodes_num()
+    /// The diff is just the latest root.
+    fn diff_cacheable(_diff: &Self::Diff) -> bool {
+        true
+    }
+
+    /// The edge looks up each sibling on the path (and the next version's
+    /// path) among the cached tree nodes, then the leaf.
+    fn proof_edge_work(&self, proof: &Self::Proof) -> Option<EdgeWork> {
+        let siblings: usize = std::iter::once(&proof.path)
+            .chain(proof.next.as_ref())
+            .map(|path| {
+                path.shape()
+                    .siblings
+                    .into_iter()
+                    .filter(|&sent| sent)
+                    .count()
+            })
+            .sum();
+        Some(EdgeWork::new(siblings + 1, proof.size()))
+    }
+
+    fn targeted_refresh() -> bool {
+        true
+    }
+
+    fn batch_proof_size(proofs: &[&Self::Proof]) -> Information {
+        let paths: Vec<_> = proofs
+            .iter()
+            .flat_map(|proof| std::iter::once(&proof.path).chain(proof.next.as_ref()))
+            .collect();
+        proofs
+            .iter()
+            .fold(sparse_merkle::Proof::batch_size(&paths), |acc, proof| {
+                acc + proof.leaf_size()
+            })
     }
 }
 
-impl DataSized for Authenticator {
+/// The tree is cheap to rebuild, so we only persist the revisions.
+impl<const PER_VERSION: bool, H: TreeHash> WarmState for Authenticator<PER_VERSION, H> {
+    fn save_state<W: Write>(&self, writer: W) -> bincode::Result<()> {
+        bincode::serialize_into(writer, &self.revisions)
+    }
+
+    fn load_state<R: Read>(reader: R) -> bincode::Result<Self> {
+        Ok(Self::from_revisions(bincode::deserialize_from(reader)?))
+    }
+
+    fn load_state_from_slice(bytes: &[u8]) -> bincode::Result<Self> {
+        Ok(Self::from_revisions(bincode::deserialize(bytes)?))
+    }
+}
+
+impl<const PER_VERSION: bool, H: TreeHash> DataSized for Authenticator<PER_VERSION, H> {
     fn size(&self) -> Information {
         let mut snapshot_size = Information::new::<byte>(
             TryInto::try_into(std::mem::size_of::<Self>()).expect("Not that big"),
@@ -211,21 +511,289 @@ impl DataSized for Authenticator {
             snapshot_size += package_id.size();
             snapshot_size += revision.size();
         }
-
-        let mut tree_size = Information::ZERO;
-        for _ in itertools::chain!(
-            self.tree.get_leaves().into_iter(),
-            self.tree.get_internals().into_iter(),
-            self.tree.get_paddings().into_iter(),
-        ) {
-            tree_size += Information::new::<byte>(Sha3_256::output_size());
+        for package_id in self.chains.keys() {
+            snapshot_size += package_id.size();
+            snapshot_size += Information::new::<byte>(2 * std::mem::size_of::<Hash>());
         }
 
-        snapshot_size + tree_size
+        snapshot_size + self.tree.size()
     }
 }
 
 #[cfg(test)]
 mod tests {
     // TODO(test): fix tests
+    use super::*;
+    use crate::authenticator::Authenticator as _;
+    use crate::primitives::sparse_merkle::hash;
+    use crate::testing::package_ids;
+    use crate::util::FixedDataSized;
+    use uom::ConstZero;
+
+    type Authenticator = super::Authenticator;
+
+    #[test]
+    fn compressed_proofs() {
+        let packages: Vec<_> = package_ids(16);
+        let revisions = packages
+            .iter()
+            .map(|p| (p.clone(), Revision::default()))
+            .collect();
+        let authenticator = Authenticator::from_revisions(revisions);
+        let snapshot = authenticator.get_metadata();
+
+        let mut proofs = vec![];
+        for package in &packages {
+            let (revision, proof) = authenticator.request_file(snapshot.root, package);
+            // 16 leaves only fill the top few levels.
+            let sent = proof.path.shape().siblings.iter().filter(|&&s| s).count();
+            assert!(0 < sent && sent < 16, "{sent}");
+            assert!(Authenticator::verify_membership(
+                &snapshot,
+                package,
+                revision,
+                proof.clone()
+            ));
+            proofs.push(proof);
+        }
+        let proofs: Vec<_> = proofs.iter().collect();
+        let separate = proofs
+            .iter()
+            .fold(Information::ZERO, |acc, proof| acc + proof.size());
+        assert!(Authenticator::batch_proof_size(&proofs) < separate);
+    }
+
+    #[test]
+    fn publish_moves_root() {
+        let package = PackageId::from("libc");
+        let mut authenticator = Authenticator::default();
+        authenticator.publish(package.clone());
+        let old = authenticator.get_metadata();
+        authenticator.publish(package.clone());
+        let new = authenticator.get_metadata();
+        assert_ne!(old.root, new.root);
+
+        let (revision, proof) = authenticator.request_file(new.root, &package);
+        assert_eq!(revision, Revision::from(2));
+        assert!(!Authenticator::verify_membership(
+            &old,
+            &package,
+            revision,
+            proof.clone()
+        ));
+        assert!(Authenticator::verify_membership(
+            &new, &package, revision, proof
+        ));
+    }
+
+    #[test]
+    fn binds_targets() {
+        let package = PackageId::from("libc");
+        let targets = Targets::new(1024, Default::default());
+        let mut authenticator = Authenticator::default();
+        authenticator.publish(package.clone());
+        let snapshot = authenticator.get_metadata();
+        let (_, plain) = authenticator.request_file(snapshot.root, &package);
+
+        authenticator.publish_targets(package.clone(), targets);
+        let snapshot = authenticator.get_metadata();
+        let (revision, proof) = authenticator.request_file(snapshot.root, &package);
+        assert!(Authenticator::verify_membership(
+            &snapshot,
+            &package,
+            revision,
+            proof.clone()
+        ));
+        assert_eq!(Authenticator::proven_targets(&proof), Some(targets));
+        // Binding the targets costs exactly their size.
+        assert_eq!(proof.size(), plain.size() + Targets::fixed_size());
+
+        // Lying about the length breaks the proof.
+        let mut forged = proof;
+        forged.targets = Some(Targets::new(2048, Default::default()));
+        assert!(!Authenticator::verify_membership(
+            &snapshot, &package, revision, forged
+        ));
+    }
+
+    #[test]
+    fn granularities() {
+        let packages: Vec<_> = package_ids(16);
+        let libc = PackageId::from("libc");
+        let mut families = Authenticator::default();
+        let mut versions = super::Authenticator::<true>::default();
+        families.publish_batch(packages.clone());
+        versions.publish_batch(packages);
+        for _ in 0..3 {
+            families.publish(libc.clone());
+            versions.publish(libc.clone());
+        }
+        // One leaf per package, vs. one per version.
+        assert_eq!(families.tree.len(), 17);
+        assert_eq!(versions.tree.len(), 19);
+
+        let old_families = families.get_metadata();
+        let old_versions = versions.get_metadata();
+        let (_, old_family_proof) = families.request_file(old_families.root, &libc);
+        let (_, old_version_proof) = versions.request_file(old_versions.root, &libc);
+        families.publish(libc.clone());
+        versions.publish(libc.clone());
+        let families_snapshot = families.get_metadata();
+        let versions_snapshot = versions.get_metadata();
+        let (revision, family_proof) = families.request_file(families_snapshot.root, &libc);
+        let (_, version_proof) = versions.request_file(versions_snapshot.root, &libc);
+        assert_eq!(revision, Revision::from(4));
+        assert!(Authenticator::verify_membership(
+            &families_snapshot,
+            &libc,
+            revision,
+            family_proof
+        ));
+        assert!(super::Authenticator::<true>::verify_membership(
+            &versions_snapshot,
+            &libc,
+            revision,
+            version_proof.clone()
+        ));
+        // A version's leaf is still there, but the next one isn't empty.
+        assert!(!super::Authenticator::<true>::verify_membership(
+            &versions_snapshot,
+            &libc,
+            Revision::from(3),
+            old_version_proof
+        ));
+        assert!(!Authenticator::verify_membership(
+            &families_snapshot,
+            &libc,
+            Revision::from(3),
+            old_family_proof
+        ));
+        // Dropping the freshness half of a per-version proof breaks it.
+        let mut forged = version_proof;
+        forged.next = None;
+        assert!(!super::Authenticator::<true>::verify_membership(
+            &versions_snapshot,
+            &libc,
+            revision,
+            forged
+        ));
+    }
+
+    #[test]
+    fn rebuilds_chains() {
+        let package = PackageId::from("libc");
+        let mut published = Authenticator::default();
+        for _ in 0..3 {
+            published.publish(package.clone());
+        }
+        let rebuilt = Authenticator::import_state(&published.export_state().unwrap());
+        assert_eq!(rebuilt.get_metadata().root, published.get_metadata().root);
+
+        let mut published = super::Authenticator::<true>::default();
+        for _ in 0..3 {
+            published.publish(package.clone());
+        }
+        let rebuilt =
+            super::Authenticator::<true>::import_state(&published.export_state().unwrap());
+        assert_eq!(rebuilt.get_metadata().root, published.get_metadata().root);
+    }
+
+    #[test]
+    fn batched_publish_shares_hashes() {
+        let packages: Vec<_> = package_ids(64);
+        let mut one_at_a_time = Authenticator::default();
+        for package in packages.clone() {
+            one_at_a_time.publish(package);
+        }
+        let mut batched = Authenticator::default();
+        batched.publish_batch(packages);
+
+        assert_eq!(
+            batched.get_metadata().root,
+            one_at_a_time.get_metadata().root
+        );
+        let (batched, one_at_a_time) = (batched.hash_count(), one_at_a_time.hash_count());
+        assert!(batched < one_at_a_time, "{batched:?} vs. {one_at_a_time:?}");
+    }
+
+    fn import_config() -> Config {
+        Config {
+            precompute_delay_secs: 0,
+            ..Config::default()
+        }
+    }
+
+    fn import(packages: usize, height: usize) -> Authenticator {
+        let packages = package_ids(packages);
+        let config = Config {
+            height,
+            ..import_config()
+        };
+        Authenticator::batch_import_with(packages, config)
+    }
+
+    #[test]
+    fn truncated_keys() {
+        let full = import(16, sparse_merkle::HEIGHT);
+        let short = import(16, 64);
+        assert_eq!(short.tree.height(), 64);
+        let (full_snapshot, short_snapshot) = (full.get_metadata(), short.get_metadata());
+        assert_ne!(full_snapshot.root, short_snapshot.root);
+
+        for i in 0..16 {
+            let package = PackageId::from(format!("package{i}"));
+            let (revision, proof) = short.request_file(short_snapshot.root, &package);
+            assert!(Authenticator::verify_membership(
+                &short_snapshot,
+                &package,
+                revision,
+                proof.clone()
+            ));
+            let (_, full_proof) = full.request_file(full_snapshot.root, &package);
+            assert!(proof.size() <= full_proof.size());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "use a taller tree")]
+    fn rejects_likely_collisions() {
+        // 100 keys in 32 bits collide with probability about 1e-6.
+        import(100, 32);
+    }
+
+    #[test]
+    fn blake3() {
+        let packages: Vec<_> = package_ids(16);
+        let sha3 = Authenticator::batch_import_with(packages.clone(), import_config());
+        let blake3 =
+            super::Authenticator::<false, Blake3>::batch_import_with(packages, import_config());
+        assert_eq!(
+            super::Authenticator::<false, Blake3>::name(),
+            "sparse_merkle_blake3"
+        );
+        assert_ne!(sha3.get_metadata().root, blake3.get_metadata().root);
+
+        let snapshot = blake3.get_metadata();
+        let package = PackageId::from("package3");
+        let (revision, proof) = blake3.request_file(snapshot.root, &package);
+        assert!(super::Authenticator::<false, Blake3>::verify_membership(
+            &snapshot, &package, revision, proof
+        ));
+    }
+
+    #[test]
+    fn package_index_golden() {
+        // Changing this moves every package; see `PackageId::canonical_bytes`.
+        assert_eq!(
+            hash(
+                Domain::SparseMerkleIndex,
+                &PackageId::from("libc").canonical_bytes()
+            ),
+            [
+                0xad, 0x86, 0x6e, 0xeb, 0x53, 0xfa, 0x8e, 0x87, 0x06, 0xb1, 0x7b, 0x37, 0x8f, 0x41,
+                0xb3, 0x4f, 0xb9, 0x45, 0x1e, 0x47, 0x07, 0xa8, 0xac, 0x76, 0xc2, 0x46, 0xb3, 0x72,
+                0x3c, 0xde, 0xe0, 0xb5
+            ]
+        );
+    }
 }
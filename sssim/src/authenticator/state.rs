@@ -0,0 +1,159 @@
+//! Technique-agnostic repository state, for migrating between techniques.
+//!
+//! A registry switching schemes (say, from Mercury to RSA) has to carry its
+//! packages over mid-stream. What carries over is just which packages exist
+//! and their latest revisions; everything else (trees, accumulators, logs) is
+//! specific to a technique and gets rebuilt. See
+//! [`Authenticator::export_state`] and [`Authenticator::import_state`].
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{Authenticator, Revision};
+use crate::log::PackageId;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// FNV-1a of the packages (in [`PackageId::canonical_bytes`] form) and their
+/// revisions, in order.
+fn digest(revisions: &BTreeMap<PackageId, Revision>) -> u64 {
+    let mut digest = FNV_OFFSET;
+    for (package, revision) in revisions {
+        let bytes = package.canonical_bytes();
+        for byte in bytes.iter().chain(&revision.0.get().to_le_bytes()) {
+            digest ^= u64::from(*byte);
+            digest = digest.wrapping_mul(FNV_PRIME);
+        }
+    }
+    digest
+}
+
+#[derive(Error, Debug)]
+pub enum StateError {
+    #[error("reading repository state: {0}")]
+    Io(#[from] io::Error),
+    #[error("parsing repository state: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("repository state digest mismatch: expected {expected:#x}, computed {computed:#x}")]
+    DigestMismatch { expected: u64, computed: u64 },
+}
+
+/// Every package and its latest revision, plus a digest of them.
+///
+/// The digest is a checksum (to catch a corrupted or hand-edited file), not a
+/// commitment: it isn't collision-resistant, and it isn't any technique's
+/// root or accumulator digest.
+///
+/// ```json
+/// { "revisions": { "left-pad": 3, "serde": 1 }, "digest": 1234567890 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepositoryState {
+    revisions: BTreeMap<PackageId, Revision>,
+    digest: u64,
+}
+
+impl RepositoryState {
+    pub fn new(revisions: impl IntoIterator<Item = (PackageId, Revision)>) -> Self {
+        let revisions = revisions.into_iter().collect();
+        let digest = digest(&revisions);
+        Self { revisions, digest }
+    }
+
+    pub fn revisions(&self) -> &BTreeMap<PackageId, Revision> {
+        &self.revisions
+    }
+
+    pub fn digest(&self) -> u64 {
+        self.digest
+    }
+
+    /// Read a state written by [`Self::save`], checking its digest.
+    pub fn load(path: &Path) -> Result<Self, StateError> {
+        let reader = BufReader::new(File::open(path)?);
+        let state: Self = serde_json::from_reader(reader)?;
+        state.check()
+    }
+
+    fn check(self) -> Result<Self, StateError> {
+        let computed = digest(&self.revisions);
+        if computed != self.digest {
+            return Err(StateError::DigestMismatch {
+                expected: self.digest,
+                computed,
+            });
+        }
+        Ok(self)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), StateError> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    /// Rebuild `A` the slow way: import every package, then publish each one
+    /// again until it's at its revision.
+    pub(super) fn replay<A: Authenticator>(&self) -> A {
+        let mut authenticator = A::batch_import(self.revisions.keys().cloned().collect());
+        let republish = self
+            .revisions
+            .iter()
+            .flat_map(|(package, revision)| (1..revision.0.get()).map(|_| package.clone()));
+        authenticator.publish_batch(republish.collect());
+        authenticator
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authenticator::{Hackage, MercuryDiff, VanillaTuf};
+
+    fn state() -> RepositoryState {
+        RepositoryState::new([
+            (PackageId::from("a"), Revision::from(1)),
+            (PackageId::from("b"), Revision::from(3)),
+        ])
+    }
+
+    fn round_trip<A: Authenticator>() {
+        let state = state();
+        let authenticator = A::import_state(&state);
+        for (package, revision) in state.revisions() {
+            let id = A::id(&authenticator.get_metadata());
+            assert_eq!(authenticator.request_file(id, package).0, *revision);
+        }
+        assert_eq!(authenticator.export_state(), Some(state));
+    }
+
+    #[test]
+    fn migrate() {
+        round_trip::<Hackage>();
+        round_trip::<VanillaTuf>();
+        round_trip::<MercuryDiff>();
+        #[cfg(feature = "merkle")]
+        round_trip::<crate::authenticator::MerkleBpt>();
+        #[cfg(feature = "smt")]
+        round_trip::<crate::authenticator::SparseMerkle>();
+    }
+
+    #[test]
+    fn checks_digest() {
+        let json = serde_json::to_string(&state()).unwrap();
+        let parsed: RepositoryState = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.check().unwrap(), state());
+
+        let tampered = json.replace(r#""b":3"#, r#""b":4"#);
+        let parsed: RepositoryState = serde_json::from_str(&tampered).unwrap();
+        assert!(matches!(
+            parsed.check(),
+            Err(StateError::DigestMismatch { .. })
+        ));
+    }
+}
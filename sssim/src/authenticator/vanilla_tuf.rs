@@ -8,14 +8,19 @@ use std::collections::HashMap;
 #[cfg(test)]
 use proptest_derive::Arbitrary;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::util::DataSized;
 
-use crate::{authenticator::Revision, log::PackageId, util::Information};
+use crate::{
+    authenticator::{CdnObject, CdnObjects, RepositoryState, Revision},
+    config::NoConfig,
+    log::PackageId,
+    util::Information,
+};
 
 #[cfg_attr(test, derive(Arbitrary))]
-#[derive(Default, Clone, Debug, Serialize)]
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct Snapshot {
     packages: HashMap<PackageId, Revision>,
     id: u64,
@@ -31,7 +36,7 @@ impl DataSized for Snapshot {
 
 /// An authenticator as-in vanilla TUF.
 #[cfg_attr(test, derive(Arbitrary))]
-#[derive(Default, Clone, Debug, Serialize)]
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct Authenticator {
     snapshot: Snapshot,
 }
@@ -48,6 +53,7 @@ impl super::Authenticator for Authenticator {
     type Id = u64;
     type Diff = Snapshot;
     type Proof = ();
+    type Config = NoConfig;
 
     fn name() -> &'static str {
         "vanilla_tuf"
@@ -79,11 +85,7 @@ impl super::Authenticator for Authenticator {
             .or_insert_with(Revision::default);
     }
 
-    fn request_file(
-        &mut self,
-        snapshot_id: Self::Id,
-        package: &PackageId,
-    ) -> (Revision, Self::Proof) {
+    fn request_file(&self, snapshot_id: Self::Id, package: &PackageId) -> (Revision, Self::Proof) {
         let revision = self
             .snapshot
             .packages
@@ -105,7 +107,12 @@ impl super::Authenticator for Authenticator {
         snapshot.id = diff.id
     }
 
-    fn check_no_rollback(snapshot: &Self::ClientSnapshot, diff: &Self::Diff) -> bool {
+    /// The snapshot is signed; checking the signature would go here.
+    fn verify_diff_structure(_: &Self::ClientSnapshot, _: &Self::Diff) -> bool {
+        true
+    }
+
+    fn verify_freshness(snapshot: &Self::ClientSnapshot, diff: &Self::Diff) -> bool {
         for (package_id, old_revision) in &snapshot.packages {
             let new_revision = match diff.packages.get(package_id) {
                 None => {
@@ -133,6 +140,10 @@ impl super::Authenticator for Authenticator {
         }
     }
 
+    fn export_state(&self) -> Option<RepositoryState> {
+        Some(RepositoryState::new(self.snapshot.packages.clone()))
+    }
+
     fn cdn_size(&self) -> Information {
         let mut size = self.snapshot.id.size();
         for (key, value) in &self.snapshot.packages {
@@ -141,6 +152,23 @@ impl super::Authenticator for Authenticator {
         }
         size
     }
+
+    /// One `snapshot` object. Publishing bumps a revision without (usually)
+    /// changing its size, so it's versioned by the snapshot ID.
+    fn cdn_objects(&self) -> CdnObjects<'_> {
+        let snapshot = CdnObject::new("snapshot", self.cdn_size()).with_version(self.snapshot.id);
+        Box::new(std::iter::once(snapshot))
+    }
+
+    /// Clients always fetch the whole (current) snapshot.
+    fn diff_cacheable(_diff: &Self::Diff) -> bool {
+        true
+    }
+
+    /// The revision is in the snapshot, so there's nothing more to fetch.
+    fn download_round_trips(_proof: &Self::Proof) -> u32 {
+        0
+    }
 }
 
 /*
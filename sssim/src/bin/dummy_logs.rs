@@ -3,6 +3,7 @@ use std::io::{self, Write};
 use time::macros::datetime;
 
 use sssim::log::{Action, Entry, Log, Package, PackageId, UserId};
+use sssim::schema::Header;
 
 fn main() {
     let log = Log::from(vec![
@@ -52,6 +53,7 @@ fn main() {
         ),
     ]);
     let mut stdout = io::stdout();
+    Header::current().write(&stdout).expect("stdout failed");
     for action in log {
         serde_json::to_writer(&stdout, &action).expect("stdout failed");
         stdout.write_all(b"\n").expect("stdout failed");
@@ -0,0 +1,167 @@
+//! CDN invalidation: what each publish costs the edge caches.
+//!
+//! Serving metadata from a CDN is cheap only while the cached copies stay
+//! valid. When a publish changes an object that's cached (a Mercury
+//! snapshot, a Merkle root, every precomputed RSA witness), the CDN has to
+//! purge it, and a warm deployment then pushes the new version back out to
+//! the edges rather than waiting for clients to miss. How many objects churn
+//! per publish differs hugely between techniques.
+//!
+//! [`CdnCache`] remembers the objects from the last listing (see
+//! [`Authenticator::cdn_objects`](crate::Authenticator::cdn_objects)) and,
+//! given the listing after a publish, counts what had to be purged and
+//! re-seeded. An object changed if its [version](CdnObject::version) did or,
+//! for objects without one, if its size did.
+use std::collections::HashMap;
+
+use serde::Serialize;
+use uom::ConstZero;
+
+use crate::authenticator::CdnObject;
+use crate::util::Information;
+
+/// Invalidation work for one or more publishes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PurgeStats {
+    /// Cached objects that changed or went away.
+    pub purges: usize,
+    /// New objects, which nothing had cached yet.
+    pub new_objects: usize,
+    /// Bytes pushed to the edges: the new versions of purged objects, plus
+    /// new objects.
+    #[serde(rename = "reseed_bytes")]
+    pub reseed: Information,
+}
+
+impl Default for PurgeStats {
+    fn default() -> Self {
+        Self {
+            purges: 0,
+            new_objects: 0,
+            reseed: Information::ZERO,
+        }
+    }
+}
+
+impl std::ops::AddAssign for PurgeStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.purges += rhs.purges;
+        self.new_objects += rhs.new_objects;
+        self.reseed += rhs.reseed;
+    }
+}
+
+/// What the CDN has cached: each object's size and version, by name.
+#[derive(Debug, Clone, Default)]
+pub struct CdnCache {
+    objects: HashMap<String, (Information, Option<u64>)>,
+    total: PurgeStats,
+}
+
+impl CdnCache {
+    /// Start warm: everything in `objects` is already cached.
+    pub fn new(objects: impl IntoIterator<Item = CdnObject>) -> Self {
+        let objects = objects
+            .into_iter()
+            .map(|object| (object.name, (object.size, object.version)))
+            .collect();
+        Self {
+            objects,
+            total: PurgeStats::default(),
+        }
+    }
+
+    /// Move to the listing after a publish, returning what changed.
+    pub fn update(&mut self, objects: impl IntoIterator<Item = CdnObject>) -> PurgeStats {
+        let mut stats = PurgeStats::default();
+        let mut previous = std::mem::take(&mut self.objects);
+        for object in objects {
+            match previous.remove(&object.name) {
+                None => {
+                    stats.new_objects += 1;
+                    stats.reseed += object.size;
+                }
+                Some(cached) if cached != (object.size, object.version) => {
+                    stats.purges += 1;
+                    stats.reseed += object.size;
+                }
+                Some(_) => {}
+            }
+            self.objects
+                .insert(object.name, (object.size, object.version));
+        }
+        // Anything left over was deleted, and has to be purged too.
+        stats.purges += previous.len();
+        self.total += stats;
+        stats
+    }
+
+    /// Everything since [`Self::new`].
+    pub fn total(&self) -> PurgeStats {
+        self.total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authenticator::Authenticators;
+    use crate::testing::package_ids;
+    use crate::util::byte;
+
+    fn object(name: &str, size: usize, version: Option<u64>) -> CdnObject {
+        let object = CdnObject::new(name, Information::new::<byte>(size));
+        match version {
+            Some(version) => object.with_version(version),
+            None => object,
+        }
+    }
+
+    #[test]
+    fn counts_purges() {
+        let mut cache = CdnCache::new([
+            object("snapshot", 10, Some(1)),
+            object("history", 5, None),
+            object("old", 1, None),
+        ]);
+        let stats = cache.update([
+            // Same size, new version: purged.
+            object("snapshot", 10, Some(2)),
+            // Unversioned and the same size: still valid.
+            object("history", 5, None),
+            object("snapshots/1", 3, None),
+        ]);
+        assert_eq!(
+            stats,
+            PurgeStats {
+                purges: 2,
+                new_objects: 1,
+                reseed: Information::new::<byte>(13),
+            }
+        );
+        let stats = cache.update([
+            object("snapshot", 10, Some(2)),
+            object("history", 6, None),
+            object("snapshots/1", 3, None),
+        ]);
+        assert_eq!(stats.purges, 1);
+        assert_eq!(cache.total().purges, 3);
+        assert_eq!(cache.total().reseed, Information::new::<byte>(19));
+    }
+
+    #[test]
+    fn publish_purges_snapshot() {
+        let packages: Vec<_> = package_ids(10);
+        let mut server = Authenticators::by_name("mercury")
+            .unwrap()
+            .import(packages.clone());
+        let mut cache = CdnCache::new(server.cdn_objects());
+        // Bumping a revision doesn't change the snapshot's size.
+        let size = server.cdn_size();
+        server.publish(packages[0].clone());
+        assert_eq!(server.cdn_size(), size);
+        let stats = cache.update(server.cdn_objects());
+        assert_eq!(stats.purges, 1);
+        assert_eq!(stats.reseed, size);
+    }
+}
@@ -0,0 +1,432 @@
+//! Checking the paper's claims against a results db (`sssim check-claims`).
+//!
+//! The paper says things like "RSA refreshes are constant-size, however many
+//! releases a client missed", and it's easy for a code change to quietly make
+//! one of them false. A claims file writes each one down as a fit over one
+//! column of the results against another, with bounds on the fit:
+//!
+//! ```toml
+//! [[claim]]
+//! name = "rsa refresh bandwidth is O(1) in elapsed releases"
+//! table = "refresh_results"
+//! technique = "rsa"
+//! x = "elapsed_releases"
+//! y = "bandwidth_bytes"
+//! slope = { max = 0.01 }
+//!
+//! [[claim]]
+//! name = "mercury cdn_size grows quadratically"
+//! table = "precompute_results"
+//! technique = "vanilla_tuf"
+//! x = "packages"
+//! y = "cdn_size_bytes"
+//! exponent = { min = 1.8, max = 2.2 }
+//! ```
+//!
+//! A claim can bound the least-squares `slope` of `y` against `x`, the
+//! `exponent` (the slope of `ln y` against `ln x`, so 1 is linear and 2
+//! quadratic), and every `value` of `y`. A claim without enough data to check
+//! fails, rather than passing by default.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use rusqlite::Connection;
+use serde::Deserialize;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ClaimsError {
+    #[error("reading claims: {0}")]
+    Io(#[from] io::Error),
+    #[error("parsing claims: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("claim {name:?}: {reason}")]
+    Invalid { name: String, reason: &'static str },
+    #[error("claim {name:?}: {source}")]
+    Query {
+        name: String,
+        source: rusqlite::Error,
+    },
+}
+
+/// Inclusive bounds on a number (either end may be open).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bounds {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl Bounds {
+    pub fn contains(&self, value: f64) -> bool {
+        self.min.map_or(true, |min| value >= min) && self.max.map_or(true, |max| value <= max)
+    }
+}
+
+impl fmt::Display for Bounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => write!(f, "in [{min}, {max}]"),
+            (Some(min), None) => write!(f, ">= {min}"),
+            (None, Some(max)) => write!(f, "<= {max}"),
+            (None, None) => write!(f, "anything"),
+        }
+    }
+}
+
+/// One claim about the results (see the module docs).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Claim {
+    pub name: String,
+    /// Results table, e.g. `refresh_results`.
+    pub table: String,
+    /// As recorded in the `technique` column (e.g. `vanilla_tuf` for
+    /// `mercury`).
+    pub technique: String,
+    /// Column to fit against; needed for `slope` and `exponent`.
+    pub x: Option<String>,
+    pub y: String,
+    /// Extra SQL condition on the rows to use (e.g. `cores = 1`).
+    #[serde(rename = "where")]
+    pub filter: Option<String>,
+    pub slope: Option<Bounds>,
+    pub exponent: Option<Bounds>,
+    pub value: Option<Bounds>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default)]
+    claim: Vec<Claim>,
+}
+
+/// Read `--claims`.
+pub fn load(path: &Path) -> Result<Vec<Claim>, ClaimsError> {
+    parse(&fs::read_to_string(path)?)
+}
+
+/// Parse a claims file, checking each claim makes sense (though not that its
+/// columns exist).
+pub fn parse(s: &str) -> Result<Vec<Claim>, ClaimsError> {
+    let file: File = toml::from_str(s)?;
+    for claim in &file.claim {
+        claim.validate()?;
+    }
+    Ok(file.claim)
+}
+
+/// Whether `s` can go into a query as a table or column name as-is.
+fn is_identifier(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Least-squares slope of `y` against `x`; `None` unless there are at least
+/// two distinct `x`s.
+fn slope(points: &[(f64, f64)]) -> Option<f64> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (covariance, variance) =
+        points
+            .iter()
+            .fold((0.0, 0.0), |(covariance, variance), (x, y)| {
+                let dx = x - mean_x;
+                (covariance + dx * (y - mean_y), variance + dx * dx)
+            });
+    if variance > 0.0 {
+        Some(covariance / variance)
+    } else {
+        None
+    }
+}
+
+/// What one check of a claim measured.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+    /// `slope`, `exponent`, or `value`.
+    pub check: &'static str,
+    /// The fit, or for `value` whichever extreme is out of bounds (else the
+    /// largest value); `None` if there wasn't enough data.
+    pub measured: Option<f64>,
+    pub bounds: Bounds,
+}
+
+impl Measurement {
+    pub fn holds(&self) -> bool {
+        self.measured
+            .map_or(false, |measured| self.bounds.contains(measured))
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.measured {
+            Some(measured) => write!(f, "{} {measured:.4}", self.check)?,
+            None => write!(f, "{} (not enough data)", self.check)?,
+        }
+        write!(f, ", want {}", self.bounds)
+    }
+}
+
+/// How one claim fared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verdict {
+    pub name: String,
+    /// Rows the fits were over.
+    pub rows: usize,
+    pub measurements: Vec<Measurement>,
+}
+
+impl Verdict {
+    pub fn holds(&self) -> bool {
+        self.measurements.iter().all(Measurement::holds)
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = if self.holds() { "ok" } else { "FAIL" };
+        write!(f, "{status:4} {} ({} rows)", self.name, self.rows)?;
+        for measurement in &self.measurements {
+            write!(f, "; {measurement}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Claim {
+    fn invalid(&self, reason: &'static str) -> ClaimsError {
+        ClaimsError::Invalid {
+            name: self.name.clone(),
+            reason,
+        }
+    }
+
+    fn validate(&self) -> Result<(), ClaimsError> {
+        if self.slope.is_none() && self.exponent.is_none() && self.value.is_none() {
+            return Err(self.invalid("nothing to check (want slope, exponent, or value)"));
+        }
+        if (self.slope.is_some() || self.exponent.is_some()) && self.x.is_none() {
+            return Err(self.invalid("slope and exponent need an x column"));
+        }
+        let columns = [Some(&self.table), self.x.as_ref(), Some(&self.y)];
+        if !columns
+            .into_iter()
+            .flatten()
+            .all(|name| is_identifier(name))
+        {
+            return Err(self.invalid("table and column names must be plain identifiers"));
+        }
+        Ok(())
+    }
+
+    /// `(x, y)` for each matching row (`x` is 0 if there's no `x` column).
+    fn points(&self, db: &Connection) -> rusqlite::Result<Vec<(f64, f64)>> {
+        let x = self.x.as_deref().unwrap_or("0");
+        let mut sql = format!(
+            "SELECT {x}, {y} FROM {table} WHERE technique = ?1 AND {x} IS NOT NULL AND {y} IS NOT NULL",
+            y = self.y,
+            table = self.table,
+        );
+        if let Some(filter) = &self.filter {
+            sql.push_str(&format!(" AND ({filter})"));
+        }
+        let mut statement = db.prepare(&sql)?;
+        let rows = statement.query_map([&self.technique], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    /// Check this claim against the results in `db`.
+    pub fn check(&self, db: &Connection) -> Result<Verdict, ClaimsError> {
+        self.validate()?;
+        let points = self.points(db).map_err(|source| ClaimsError::Query {
+            name: self.name.clone(),
+            source,
+        })?;
+
+        let mut measurements = Vec::new();
+        if let Some(bounds) = self.slope {
+            measurements.push(Measurement {
+                check: "slope",
+                measured: slope(&points),
+                bounds,
+            });
+        }
+        if let Some(bounds) = self.exponent {
+            // Logs of non-positive numbers aren't much use for a fit.
+            let logs: Option<Vec<_>> = points
+                .iter()
+                .map(|&(x, y)| (x > 0.0 && y > 0.0).then(|| (x.ln(), y.ln())))
+                .collect();
+            measurements.push(Measurement {
+                check: "exponent",
+                measured: logs.as_deref().and_then(slope),
+                bounds,
+            });
+        }
+        if let Some(bounds) = self.value {
+            // If the extremes are in bounds, so is everything in between.
+            let ys = points.iter().map(|&(_, y)| y);
+            let largest = ys.clone().reduce(f64::max);
+            let smallest = ys.reduce(f64::min);
+            let worst = [largest, smallest]
+                .into_iter()
+                .flatten()
+                .find(|&y| !bounds.contains(y))
+                .or(largest);
+            measurements.push(Measurement {
+                check: "value",
+                measured: worst,
+                bounds,
+            });
+        }
+        Ok(Verdict {
+            name: self.name.clone(),
+            rows: points.len(),
+            measurements,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> Connection {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE refresh_results (
+                 technique TEXT, packages INTEGER, elapsed_releases INTEGER,
+                 bandwidth_bytes INTEGER, cores INTEGER
+             );",
+        )
+        .unwrap();
+        for elapsed in 1..=10 {
+            let rows: [(&str, i64, i64); 3] = [
+                ("rsa", 100, 256),
+                ("vanilla_tuf", 100, 40 * elapsed),
+                ("vanilla_tuf", 400, 10 * elapsed * elapsed),
+            ];
+            for (technique, packages, bytes) in rows {
+                db.execute(
+                    "INSERT INTO refresh_results VALUES (?1, ?2, ?3, ?4, 1)",
+                    rusqlite::params![technique, packages, elapsed, bytes],
+                )
+                .unwrap();
+            }
+        }
+        db
+    }
+
+    fn verdicts(claims: &str) -> Vec<Verdict> {
+        let db = db();
+        parse(claims)
+            .unwrap()
+            .iter()
+            .map(|claim| claim.check(&db).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn fits() {
+        let verdicts = verdicts(
+            r#"
+            [[claim]]
+            name = "rsa is constant"
+            table = "refresh_results"
+            technique = "rsa"
+            x = "elapsed_releases"
+            y = "bandwidth_bytes"
+            slope = { max = 0.01 }
+            exponent = { max = 0.01 }
+            value = { max = 256 }
+
+            [[claim]]
+            name = "mercury is linear"
+            table = "refresh_results"
+            technique = "vanilla_tuf"
+            where = "packages = 100"
+            x = "elapsed_releases"
+            y = "bandwidth_bytes"
+            slope = { min = 39.9, max = 40.1 }
+            exponent = { min = 0.99, max = 1.01 }
+
+            [[claim]]
+            name = "mercury is quadratic"
+            table = "refresh_results"
+            technique = "vanilla_tuf"
+            where = "packages = 400"
+            x = "elapsed_releases"
+            y = "bandwidth_bytes"
+            exponent = { min = 1.99, max = 2.01 }
+            "#,
+        );
+        for verdict in &verdicts {
+            assert!(verdict.holds(), "{verdict}");
+        }
+        assert_eq!(verdicts[1].rows, 10);
+    }
+
+    #[test]
+    fn failures() {
+        let verdicts = verdicts(
+            r#"
+            [[claim]]
+            name = "mercury is constant"
+            table = "refresh_results"
+            technique = "vanilla_tuf"
+            x = "elapsed_releases"
+            y = "bandwidth_bytes"
+            slope = { max = 0.01 }
+
+            [[claim]]
+            name = "rsa refreshes are small"
+            table = "refresh_results"
+            technique = "rsa"
+            y = "bandwidth_bytes"
+            value = { max = 100 }
+
+            [[claim]]
+            name = "no data"
+            table = "refresh_results"
+            technique = "hackage"
+            x = "elapsed_releases"
+            y = "bandwidth_bytes"
+            exponent = { max = 1 }
+            "#,
+        );
+        assert!(verdicts.iter().all(|verdict| !verdict.holds()));
+        assert_eq!(verdicts[1].measurements[0].measured, Some(256.0));
+        assert_eq!(verdicts[2].measurements[0].measured, None);
+    }
+
+    #[test]
+    fn invalid() {
+        let claim = |checks: &str| {
+            format!(
+                "[[claim]]\nname = \"bad\"\ntable = \"refresh_results\"\ntechnique = \"rsa\"\ny = \"bandwidth_bytes\"\n{checks}"
+            )
+        };
+        for checks in [
+            "",
+            "slope = { max = 1 }",
+            "x = \"packages; DROP TABLE refresh_results\"\nslope = { max = 1 }",
+        ] {
+            assert!(
+                matches!(parse(&claim(checks)), Err(ClaimsError::Invalid { .. })),
+                "{checks}"
+            );
+        }
+        assert!(parse(&claim("value = { min = 0 }")).is_ok());
+        let db = db();
+        let missing = parse(&claim("x = \"nope\"\nslope = { max = 1 }")).unwrap();
+        assert!(matches!(
+            missing[0].check(&db),
+            Err(ClaimsError::Query { .. })
+        ));
+    }
+}
@@ -0,0 +1,108 @@
+//! Virtual time for replaying traces.
+//!
+//! Everything time-based in the simulator (metadata expiry, de-duplication
+//! windows, epoch boundaries) goes by the trace's timestamps, never the wall
+//! clock, so a replay gives the same answer however fast it runs. By default a
+//! replay doesn't wait at all between entries. To watch a replay unfold (or to
+//! drive something that does use the wall clock), [`Pace::Accelerated`] spaces
+//! entries out at some multiple of real time: at 8760×, a year-long trace takes
+//! an hour.
+use time::{Duration, OffsetDateTime};
+
+/// How fast to replay a trace, relative to its timestamps.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Pace {
+    /// As fast as possible.
+    #[default]
+    Unpaced,
+    /// This many seconds of trace per second of wall time.
+    Accelerated(f64),
+}
+
+/// The current time in a trace.
+#[derive(Debug, Clone)]
+pub struct VirtualClock {
+    pace: Pace,
+    /// The first and latest timestamps seen.
+    start: Option<OffsetDateTime>,
+    now: Option<OffsetDateTime>,
+}
+
+impl VirtualClock {
+    pub fn new(pace: Pace) -> Self {
+        if let Pace::Accelerated(factor) = pace {
+            assert!(factor > 0.0, "acceleration must be positive");
+        }
+        Self {
+            pace,
+            start: None,
+            now: None,
+        }
+    }
+
+    /// The latest timestamp (`None` before the first entry).
+    pub fn now(&self) -> Option<OffsetDateTime> {
+        self.now
+    }
+
+    /// Trace time since the first entry.
+    pub fn elapsed(&self) -> Duration {
+        match (self.start, self.now) {
+            (Some(start), Some(now)) => now - start,
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Move to `to`, returning when (in wall time since the first entry) it
+    /// should happen, if the replay is paced.
+    ///
+    /// The clock never goes backwards: an entry older than the latest one
+    /// doesn't change the time, and is due right away.
+    pub fn advance(&mut self, to: OffsetDateTime) -> Option<std::time::Duration> {
+        let start = *self.start.get_or_insert(to);
+        let now = std::cmp::max(to, self.now.unwrap_or(to));
+        self.now = Some(now);
+        match self.pace {
+            Pace::Unpaced => None,
+            Pace::Accelerated(factor) => {
+                let seconds = (now - start).as_seconds_f64() / factor;
+                Some(std::time::Duration::from_secs_f64(seconds))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paces_replay() {
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let mut unpaced = VirtualClock::new(Pace::Unpaced);
+        assert_eq!(unpaced.advance(start), None);
+        assert_eq!(unpaced.advance(start + Duration::days(365)), None);
+        assert_eq!(unpaced.elapsed(), Duration::days(365));
+
+        let mut hourly = VirtualClock::new(Pace::Accelerated(3600.0));
+        assert_eq!(hourly.advance(start), Some(std::time::Duration::ZERO));
+        assert_eq!(
+            hourly.advance(start + Duration::hours(2)),
+            Some(std::time::Duration::from_secs(2))
+        );
+        assert_eq!(hourly.now(), Some(start + Duration::hours(2)));
+    }
+
+    #[test]
+    fn never_goes_backwards() {
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let mut clock = VirtualClock::new(Pace::Accelerated(1.0));
+        clock.advance(start);
+        clock.advance(start + Duration::seconds(2));
+        assert_eq!(
+            clock.advance(start + Duration::seconds(1)),
+            Some(std::time::Duration::from_secs(2))
+        );
+        assert_eq!(clock.now(), Some(start + Duration::seconds(2)));
+    }
+}
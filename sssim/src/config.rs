@@ -0,0 +1,181 @@
+//! Per-technique settings, from a TOML file (`--config`).
+//!
+//! Each `[technique.<name>]` section (`<name>` as passed to
+//! `--authenticators`) is deserialized into that technique's own config struct
+//! ([`Authenticator::Config`](crate::Authenticator::Config)), and passed to
+//! [`Authenticator::batch_import_with`](crate::Authenticator::batch_import_with):
+//!
+//! ```toml
+//! [technique.sparse_merkle]
+//! precompute_delay_secs = 30
+//! height = 64
+//!
+//! [technique.rsa_pruned]
+//! retention = 500
+//! ```
+//!
+//! Techniques without a section get their defaults. Unknown techniques, and
+//! settings a technique doesn't have, are errors when loading the file (rather
+//! than halfway through a run).
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::authenticator::{Authenticators, UnknownTechnique};
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("reading config: {0}")]
+    Io(#[from] io::Error),
+    #[error("parsing config: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("config: {0}")]
+    UnknownTechnique(#[from] UnknownTechnique),
+    #[error("config for {technique}: {source}")]
+    Technique {
+        technique: String,
+        source: toml::de::Error,
+    },
+}
+
+/// The config for techniques without any settings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NoConfig {}
+
+/// One technique's section of the config file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TechniqueConfig(toml::value::Table);
+
+impl TechniqueConfig {
+    /// Deserialize into a technique's config struct.
+    pub fn parse<C: DeserializeOwned>(&self) -> Result<C, toml::de::Error> {
+        toml::Value::Table(self.0.clone()).try_into()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<toml::value::Table> for TechniqueConfig {
+    fn from(table: toml::value::Table) -> Self {
+        Self(table)
+    }
+}
+
+/// As TOML (what we record in the `runs` table).
+impl fmt::Display for TechniqueConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let toml = toml::to_string(&self.0).map_err(|_| fmt::Error)?;
+        write!(f, "{}", toml.trim_end())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default)]
+    technique: BTreeMap<String, toml::value::Table>,
+}
+
+/// Settings for every technique.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    techniques: BTreeMap<String, TechniqueConfig>,
+}
+
+impl Config {
+    /// Read from `--config`.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// The settings for `technique` (empty, i.e. its defaults, if it doesn't
+    /// have a section).
+    pub fn technique(&self, technique: &str) -> TechniqueConfig {
+        self.techniques.get(technique).cloned().unwrap_or_default()
+    }
+}
+
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let file: File = toml::from_str(s)?;
+        let mut techniques = BTreeMap::new();
+        for (name, table) in file.technique {
+            let config = TechniqueConfig(table);
+            Authenticators::by_name(&name)?
+                .check_config(&config)
+                .map_err(|source| ConfigError::Technique {
+                    technique: name.clone(),
+                    source,
+                })?;
+            techniques.insert(name, config);
+        }
+        Ok(Self { techniques })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections() {
+        let config: Config = "
+            [technique.hackage]
+        "
+        .parse()
+        .unwrap();
+        assert!(config.technique("hackage").is_empty());
+        assert!(config.technique("mercury").is_empty());
+
+        assert!(matches!(
+            "[technique.nope]".parse::<Config>(),
+            Err(ConfigError::UnknownTechnique(_))
+        ));
+        assert!(matches!(
+            "[technique.hackage]\nheight = 3".parse::<Config>(),
+            Err(ConfigError::Technique { .. })
+        ));
+        assert!(matches!(
+            "[techniques.hackage]".parse::<Config>(),
+            Err(ConfigError::Toml(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "smt")]
+    fn technique_settings() {
+        use crate::authenticator::{Authenticator, SparseMerkle};
+        type Settings = <SparseMerkle as Authenticator>::Config;
+
+        let config: Config = "[technique.sparse_merkle]\nprecompute_delay_secs = 30"
+            .parse()
+            .unwrap();
+        let section = config.technique("sparse_merkle");
+        assert_eq!(section.to_string(), "precompute_delay_secs = 30");
+        let settings: Settings = section.parse().unwrap();
+        assert_eq!(settings.precompute_delay_secs, 30);
+        let defaults: Settings = config.technique("nope").parse().unwrap();
+        assert_eq!(defaults.precompute_delay_secs, 0);
+        assert_eq!(defaults.height, 256);
+
+        let config: Config = "[technique.sparse_merkle]\nheight = 64".parse().unwrap();
+        let settings: Settings = config.technique("sparse_merkle").parse().unwrap();
+        assert_eq!(settings.height, 64);
+        assert!(matches!(
+            "[technique.sparse_merkle]\nheight = 300".parse::<Config>(),
+            Err(ConfigError::Technique { .. })
+        ));
+    }
+}
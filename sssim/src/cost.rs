@@ -0,0 +1,279 @@
+//! Turning resource usage into (monthly) dollar costs.
+//!
+//! Bytes and CPU time are what we measure, but "what would this cost us per
+//! month?" is what people ask. A [`CostModel`] prices aggregated [`Usage`];
+//! [`Prices`] is the simple per-unit model, and can be loaded (along with a
+//! [`MonthlyWorkload`]) from a JSON [`CostConfig`].
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::ops::{Add, AddAssign, Mul};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use time::Duration;
+use uom::si::information::byte;
+use uom::ConstZero;
+
+use crate::simulator::ResourceUsage;
+use crate::util::Information;
+
+const BYTES_PER_GB: f64 = 1e9;
+const SECONDS_PER_HOUR: f64 = 3600.0;
+
+/// Aggregated resource usage, in the units that get billed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Usage {
+    /// Bytes served from CDN edges.
+    pub cdn_bandwidth: Information,
+    /// Bytes served from our own servers.
+    pub origin_bandwidth: Information,
+    /// Server-side CPU time.
+    pub server_compute: Duration,
+}
+
+impl Default for Usage {
+    fn default() -> Self {
+        Self {
+            cdn_bandwidth: Information::ZERO,
+            origin_bandwidth: Information::ZERO,
+            server_compute: Duration::ZERO,
+        }
+    }
+}
+
+impl Usage {
+    /// Serving a `bandwidth`-byte response (from a CDN, if `cacheable`).
+    pub fn response(bandwidth: Information, cacheable: bool, server_compute: Duration) -> Self {
+        let (cdn_bandwidth, origin_bandwidth) = match cacheable {
+            true => (bandwidth, Information::ZERO),
+            false => (Information::ZERO, bandwidth),
+        };
+        Self {
+            cdn_bandwidth,
+            origin_bandwidth,
+            server_compute,
+        }
+    }
+
+    /// The average of `samples` (zero if there are none).
+    pub fn mean(samples: &[Usage]) -> Self {
+        let n = samples.len();
+        if n == 0 {
+            return Usage::default();
+        }
+        let total = samples.iter().fold(Usage::default(), |acc, x| acc + *x);
+        Self {
+            cdn_bandwidth: total.cdn_bandwidth / n,
+            origin_bandwidth: total.origin_bandwidth / n,
+            server_compute: total.server_compute / n as f64,
+        }
+    }
+}
+
+impl Add for Usage {
+    type Output = Self;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, rhs: Self) {
+        self.cdn_bandwidth += rhs.cdn_bandwidth;
+        self.origin_bandwidth += rhs.origin_bandwidth;
+        self.server_compute += rhs.server_compute;
+    }
+}
+
+impl From<&ResourceUsage> for Usage {
+    fn from(usage: &ResourceUsage) -> Self {
+        Self {
+            cdn_bandwidth: usage.cdn_bandwidth,
+            origin_bandwidth: usage.origin_bandwidth,
+            server_compute: usage.server_compute,
+        }
+    }
+}
+
+impl AddAssign<&ResourceUsage> for Usage {
+    fn add_assign(&mut self, rhs: &ResourceUsage) {
+        *self += Usage::from(rhs);
+    }
+}
+
+/// A dollar cost, broken down by what we're paying for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Cost {
+    pub cdn: f64,
+    pub origin: f64,
+    pub cpu: f64,
+}
+
+impl Cost {
+    pub fn total(&self) -> f64 {
+        self.cdn + self.origin + self.cpu
+    }
+}
+
+impl Add for Cost {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            cdn: self.cdn + rhs.cdn,
+            origin: self.origin + rhs.origin,
+            cpu: self.cpu + rhs.cpu,
+        }
+    }
+}
+
+impl Mul<f64> for Cost {
+    type Output = Self;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        Self {
+            cdn: self.cdn * rhs,
+            origin: self.origin * rhs,
+            cpu: self.cpu * rhs,
+        }
+    }
+}
+
+/// Prices resource usage.
+pub trait CostModel {
+    fn cost(&self, usage: &Usage) -> Cost;
+}
+
+/// Flat per-unit prices (in US dollars).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Prices {
+    pub cdn_per_gb: f64,
+    pub origin_per_gb: f64,
+    pub cpu_per_core_hour: f64,
+}
+
+impl Default for Prices {
+    /// Roughly list prices for a big cloud provider, circa 2023.
+    fn default() -> Self {
+        Self {
+            cdn_per_gb: 0.085,
+            origin_per_gb: 0.09,
+            cpu_per_core_hour: 0.04,
+        }
+    }
+}
+
+impl CostModel for Prices {
+    fn cost(&self, usage: &Usage) -> Cost {
+        let gb = |information: Information| information.get::<byte>() as f64 / BYTES_PER_GB;
+        Cost {
+            cdn: gb(usage.cdn_bandwidth) * self.cdn_per_gb,
+            origin: gb(usage.origin_bandwidth) * self.origin_per_gb,
+            cpu: usage.server_compute.as_seconds_f64() / SECONDS_PER_HOUR * self.cpu_per_core_hour,
+        }
+    }
+}
+
+/// How many of each operation a registry sees in a month.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct MonthlyWorkload {
+    pub downloads: f64,
+    pub refreshes: f64,
+    pub publishes: f64,
+}
+
+/// Average usage of each operation for one technique.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OperationUsage {
+    pub download: Usage,
+    pub refresh: Usage,
+    pub publish: Usage,
+}
+
+impl OperationUsage {
+    pub fn monthly_cost<M: CostModel>(&self, model: &M, workload: &MonthlyWorkload) -> Cost {
+        model.cost(&self.download) * workload.downloads
+            + model.cost(&self.refresh) * workload.refreshes
+            + model.cost(&self.publish) * workload.publishes
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CostConfigError {
+    #[error("reading cost config: {0}")]
+    Io(#[from] io::Error),
+    #[error("parsing cost config: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Prices plus the workload to price, as read from `--cost-model`.
+///
+/// ```json
+/// {
+///   "prices": { "cdn_per_gb": 0.085, "origin_per_gb": 0.09, "cpu_per_core_hour": 0.04 },
+///   "workload": { "downloads": 3e9, "refreshes": 1e9, "publishes": 1e6 }
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct CostConfig {
+    #[serde(default)]
+    pub prices: Prices,
+    pub workload: MonthlyWorkload,
+}
+
+impl CostConfig {
+    pub fn load(path: &Path) -> Result<Self, CostConfigError> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    pub fn monthly_cost(&self, usage: &OperationUsage) -> Cost {
+        usage.monthly_cost(&self.prices, &self.workload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monthly_cost() {
+        let prices = Prices {
+            cdn_per_gb: 1.0,
+            origin_per_gb: 2.0,
+            cpu_per_core_hour: 3.0,
+        };
+        let usage = OperationUsage {
+            download: Usage {
+                origin_bandwidth: Information::new::<byte>(1_000_000_000),
+                ..Default::default()
+            },
+            refresh: Usage {
+                cdn_bandwidth: Information::new::<byte>(500_000_000),
+                ..Default::default()
+            },
+            publish: Usage {
+                server_compute: Duration::minutes(30),
+                ..Default::default()
+            },
+        };
+        let workload = MonthlyWorkload {
+            downloads: 10.0,
+            refreshes: 4.0,
+            publishes: 2.0,
+        };
+        let cost = usage.monthly_cost(&prices, &workload);
+        assert_eq!(
+            cost,
+            Cost {
+                cdn: 2.0,
+                origin: 20.0,
+                cpu: 3.0,
+            }
+        );
+        assert_eq!(cost.total(), 25.0);
+    }
+}
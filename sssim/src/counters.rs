@@ -0,0 +1,146 @@
+//! Counting expensive operations: group operations, hashes, and big-integer
+//! multiplications.
+//!
+//! Wall time depends on the machine (and on everything else running on it), so
+//! it's hard to compare across runs or to report in the paper. Operation counts
+//! don't have that problem. The primitives call [`record`] as they go, and
+//! [`count`] (or [`snapshot`]) reads off what a piece of code did.
+//!
+//! Counting only happens with the `counters` feature; otherwise [`record`]
+//! compiles to nothing and every count is zero. Counts are per-thread, so work
+//! farmed out to other threads (e.g. by rayon) isn't included.
+use serde::Serialize;
+use std::ops::{Add, Sub};
+
+/// Whether counting is compiled in.
+pub const ENABLED: bool = cfg!(feature = "counters");
+
+/// A countable operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// Composing two group elements.
+    GroupOp,
+    /// Raising a group element to a (big) power.
+    GroupExp,
+    /// One invocation of a hash function (or one read from an XOF).
+    Hash,
+    /// Multiplying two big integers.
+    BigIntMult,
+}
+
+/// Number of operations of each kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct OpCounts {
+    pub group_ops: u64,
+    pub group_exps: u64,
+    pub hashes: u64,
+    pub bigint_mults: u64,
+}
+
+impl OpCounts {
+    fn get_mut(&mut self, op: Op) -> &mut u64 {
+        match op {
+            Op::GroupOp => &mut self.group_ops,
+            Op::GroupExp => &mut self.group_exps,
+            Op::Hash => &mut self.hashes,
+            Op::BigIntMult => &mut self.bigint_mults,
+        }
+    }
+}
+
+/// Counts for doing both.
+impl Add for OpCounts {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            group_ops: self.group_ops + rhs.group_ops,
+            group_exps: self.group_exps + rhs.group_exps,
+            hashes: self.hashes + rhs.hashes,
+            bigint_mults: self.bigint_mults + rhs.bigint_mults,
+        }
+    }
+}
+
+/// Counts since an earlier snapshot.
+impl Sub for OpCounts {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self {
+            group_ops: self.group_ops - rhs.group_ops,
+            group_exps: self.group_exps - rhs.group_exps,
+            hashes: self.hashes - rhs.hashes,
+            bigint_mults: self.bigint_mults - rhs.bigint_mults,
+        }
+    }
+}
+
+#[cfg(feature = "counters")]
+thread_local! {
+    static COUNTS: std::cell::Cell<OpCounts> = std::cell::Cell::new(OpCounts::default());
+}
+
+/// Note `count` operations of kind `op` on this thread.
+#[cfg(feature = "counters")]
+pub fn record_n(op: Op, count: u64) {
+    COUNTS.with(|counts| {
+        let mut updated = counts.get();
+        *updated.get_mut(op) += count;
+        counts.set(updated);
+    });
+}
+
+#[cfg(not(feature = "counters"))]
+#[inline(always)]
+pub fn record_n(_op: Op, _count: u64) {}
+
+/// Note one operation of kind `op` on this thread.
+#[inline]
+pub fn record(op: Op) {
+    record_n(op, 1);
+}
+
+/// Everything counted on this thread so far.
+#[cfg(feature = "counters")]
+pub fn snapshot() -> OpCounts {
+    COUNTS.with(|counts| counts.get())
+}
+
+#[cfg(not(feature = "counters"))]
+pub fn snapshot() -> OpCounts {
+    OpCounts::default()
+}
+
+/// Run `f`, returning the operations it did (on this thread).
+pub fn count<T>(f: impl FnOnce() -> T) -> (OpCounts, T) {
+    let before = snapshot();
+    let value = f();
+    (snapshot() - before, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_recorded_ops() {
+        let (counts, value) = count(|| {
+            record(Op::Hash);
+            record_n(Op::BigIntMult, 3);
+            record(Op::Hash);
+            7
+        });
+        assert_eq!(value, 7);
+        let expected = if ENABLED {
+            OpCounts {
+                hashes: 2,
+                bigint_mults: 3,
+                ..OpCounts::default()
+            }
+        } else {
+            OpCounts::default()
+        };
+        assert_eq!(counts, expected);
+    }
+}
@@ -0,0 +1,131 @@
+//! Helpers for constant-time(-ish) client-side verification.
+//!
+//! With the `constant-time` feature, the client verification paths of the
+//! schemes that check attacker-supplied proofs (`merkle_bpt`, `sparse_merkle`,
+//! and the RSA family) avoid early exits and short-circuiting comparisons:
+//! every check runs, and the results are combined without branching. Without
+//! the feature these are the usual (faster) comparisons.
+//!
+//! This is "ish": the big-integer and hashing libraries underneath make no
+//! promises, and we don't try to hide anything that's a function of public
+//! values (proof lengths, revisions). The `dudect_*` tests (run with
+//! `cargo test --features constant-time -- --ignored`) check for gross leaks
+//! using the [dudect] methodology.
+//!
+//! [dudect]: https://eprint.iacr.org/2016/1123
+
+/// Whether `a` and `b` are equal, without exiting early on the first mismatch.
+///
+/// The lengths are treated as public.
+#[cfg(feature = "constant-time")]
+pub fn bytes_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    std::hint::black_box(diff) == 0
+}
+
+#[cfg(not(feature = "constant-time"))]
+pub fn bytes_eq(a: &[u8], b: &[u8]) -> bool {
+    a == b
+}
+
+/// `a && b()`, but always evaluating `b` with the `constant-time` feature.
+#[cfg(feature = "constant-time")]
+pub fn and(a: bool, b: impl FnOnce() -> bool) -> bool {
+    std::hint::black_box(a) & b()
+}
+
+#[cfg(not(feature = "constant-time"))]
+pub fn and(a: bool, b: impl FnOnce() -> bool) -> bool {
+    a && b()
+}
+
+/// Welch's t-statistic for the difference in means between two samples.
+#[cfg(test)]
+pub(crate) fn welch_t(a: &[f64], b: &[f64]) -> f64 {
+    fn mean_var(xs: &[f64]) -> (f64, f64) {
+        let n = xs.len() as f64;
+        let mean = xs.iter().sum::<f64>() / n;
+        let var = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        (mean, var)
+    }
+    let (mean_a, var_a) = mean_var(a);
+    let (mean_b, var_b) = mean_var(b);
+    let se = (var_a / a.len() as f64 + var_b / b.len() as f64).sqrt();
+    if se == 0.0 {
+        return 0.0;
+    }
+    (mean_a - mean_b) / se
+}
+
+/// dudect's threshold for "probably not constant time."
+#[cfg(test)]
+pub(crate) const DUDECT_THRESHOLD: f64 = 4.5;
+
+/// Time `f` on randomly interleaved inputs of two classes (`false`/`true`),
+/// returning the Welch t-statistic between the classes.
+///
+/// As in dudect, we drop the slowest measurements (mostly interrupts and
+/// preemption) before comparing.
+#[cfg(test)]
+pub(crate) fn dudect<F: FnMut(bool) -> bool>(samples: usize, mut f: F) -> f64 {
+    use rand::Rng;
+    use std::time::Instant;
+
+    let mut rng = rand::thread_rng();
+    let mut times = [Vec::new(), Vec::new()];
+    for _ in 0..samples {
+        let class: bool = rng.gen();
+        let start = Instant::now();
+        std::hint::black_box(f(class));
+        times[usize::from(class)].push(start.elapsed().as_nanos() as f64);
+    }
+
+    let [mut a, mut b] = times;
+    let mut all: Vec<f64> = a.iter().chain(&b).copied().collect();
+    all.sort_by(f64::total_cmp);
+    let cutoff = all[all.len() * 9 / 10];
+    a.retain(|t| *t <= cutoff);
+    b.retain(|t| *t <= cutoff);
+    welch_t(&a, &b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn welch_t_separates_distributions() {
+        let a: Vec<f64> = (0..100).map(f64::from).collect();
+        let b: Vec<f64> = (0..100).map(|x| f64::from(x) + 1000.0).collect();
+        assert_eq!(welch_t(&a, &a), 0.0);
+        assert!(welch_t(&a, &b).abs() > DUDECT_THRESHOLD);
+    }
+
+    #[test]
+    fn bytes_eq_matches_slice_eq() {
+        assert!(bytes_eq(b"abc", b"abc"));
+        assert!(!bytes_eq(b"abc", b"abd"));
+        assert!(!bytes_eq(b"abc", b"ab"));
+        assert!(and(true, || true));
+        assert!(!and(false, || true));
+    }
+
+    /// Timing is noisy, so this is opt-in.
+    #[cfg(feature = "constant-time")]
+    #[test]
+    #[ignore]
+    fn dudect_bytes_eq() {
+        let secret = [0x5au8; 4096];
+        let mut early = secret;
+        early[0] ^= 1;
+        let mut late = secret;
+        late[4095] ^= 1;
+        let t = dudect(100_000, |class| {
+            bytes_eq(&secret, if class { &late } else { &early })
+        });
+        assert!(t.abs() < DUDECT_THRESHOLD, "t = {t}");
+    }
+}
@@ -0,0 +1,135 @@
+//! De-duplicating refresh responses across users.
+//!
+//! Many users refreshing at around the same time often get byte-for-byte
+//! identical responses (e.g. everyone who was up to date an hour ago gets the
+//! same diff). A CDN only has to fetch each distinct response from the origin
+//! once per cache lifetime; the rest are served from the edge. How well this
+//! works depends on the scheme: diffs that depend only on the server state
+//! deduplicate perfectly, while per-client diffs don't deduplicate at all.
+//!
+//! [`ResponseDedup`] buckets responses into fixed windows (think: the CDN
+//! cache TTL) and tracks unique bytes at the origin vs. total bytes to clients.
+//! Responses are compared by their serialized bytes, which is what a CDN would
+//! see (so two equal `HashMap`s that iterate in different orders differ).
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::Hasher;
+
+use serde::Serialize;
+use time::{Duration, OffsetDateTime};
+use uom::ConstZero;
+
+use crate::util::{byte, DataSized, Information};
+
+/// Totals for all responses observed so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DedupStats {
+    /// Number of (non-empty) responses.
+    pub responses: usize,
+    /// Number of distinct responses (counted once per window).
+    pub unique_responses: usize,
+    /// Bytes sent to clients.
+    #[serde(rename = "total_bytes")]
+    pub total: Information,
+    /// Bytes the origin had to serve (first copy of each response per window).
+    #[serde(rename = "unique_bytes")]
+    pub unique: Information,
+}
+
+impl Default for DedupStats {
+    fn default() -> Self {
+        Self {
+            responses: 0,
+            unique_responses: 0,
+            total: Information::ZERO,
+            unique: Information::ZERO,
+        }
+    }
+}
+
+impl DedupStats {
+    /// Total bytes per unique byte (1 means no de-duplication; zero if empty).
+    pub fn ratio(&self) -> f64 {
+        if self.unique == Information::ZERO {
+            return 0.0;
+        }
+        self.total.get::<byte>() as f64 / self.unique.get::<byte>() as f64
+    }
+}
+
+/// Identifies identical responses within fixed time windows.
+#[derive(Debug, Clone)]
+pub struct ResponseDedup {
+    window: Duration,
+    /// Which window we're in.
+    current: Option<i128>,
+    /// Fingerprints of the responses seen in the current window.
+    seen: HashSet<u64>,
+    stats: DedupStats,
+}
+
+impl ResponseDedup {
+    pub fn new(window: Duration) -> Self {
+        assert!(window.is_positive(), "window must be positive");
+        Self {
+            window,
+            current: None,
+            seen: HashSet::default(),
+            stats: DedupStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> &DedupStats {
+        &self.stats
+    }
+
+    fn fingerprint<T: Serialize>(response: &T) -> u64 {
+        let bytes = bincode::serialize(response).expect("serializing a response");
+        let mut hasher = DefaultHasher::new();
+        hasher.write(&bytes);
+        hasher.finish()
+    }
+
+    /// Record a response served at `now`.
+    pub fn observe<T: Serialize + DataSized>(&mut self, now: OffsetDateTime, response: &T) {
+        let window = now.unix_timestamp_nanos() / self.window.whole_nanoseconds();
+        if self.current != Some(window) {
+            self.current = Some(window);
+            self.seen.clear();
+        }
+        let size = response.size();
+        self.stats.responses += 1;
+        self.stats.total += size;
+        if self.seen.insert(Self::fingerprint(response)) {
+            self.stats.unique_responses += 1;
+            self.stats.unique += size;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log::PackageId;
+
+    #[test]
+    fn dedups_within_window() {
+        let mut dedup = ResponseDedup::new(Duration::minutes(1));
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let a = vec![PackageId::from("a".to_string())];
+        let b = vec![PackageId::from("b".to_string())];
+
+        dedup.observe(start, &a);
+        dedup.observe(start + Duration::seconds(10), &a);
+        dedup.observe(start + Duration::seconds(20), &b);
+        // New window: `a` has to come from the origin again.
+        dedup.observe(start + Duration::minutes(1), &a);
+
+        let stats = dedup.stats();
+        assert_eq!(stats.responses, 4);
+        assert_eq!(stats.unique_responses, 3);
+        assert_eq!(stats.total, a.size() * 4);
+        assert_eq!(stats.unique, a.size() * 3);
+        assert!((stats.ratio() - 4.0 / 3.0).abs() < 1e-9);
+    }
+}
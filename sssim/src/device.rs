@@ -0,0 +1,191 @@
+//! Client compute times on end-user hardware.
+//!
+//! Client verification is timed on the benchmark machine, which is usually a
+//! server. A [`DeviceProfile`] says how much slower a class of client device
+//! is. Rather than baking that into the results, the profiles go in the
+//! results db's `device_profiles` table, and the `refresh_device_times` and
+//! `download_device_times` views apply every profile to every measurement. So
+//! the report can change a factor (or add a device) by editing that table,
+//! without rerunning anything.
+//!
+//! The built-in factors are rough: single-core throughput for hashing and
+//! big-integer arithmetic, relative to a recent server core.
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use rusqlite::Connection;
+use thiserror::Error;
+
+/// A class of client hardware.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceProfile {
+    pub name: String,
+    /// Client compute times on this device, relative to the benchmark machine.
+    pub cpu_scale: f64,
+}
+
+impl DeviceProfile {
+    pub fn new(name: impl Into<String>, cpu_scale: f64) -> Self {
+        Self {
+            name: name.into(),
+            cpu_scale,
+        }
+    }
+
+    /// The profiles every results db starts with.
+    pub fn builtin() -> Vec<Self> {
+        vec![
+            // What we benchmark on.
+            Self::new("server", 1.0),
+            // Lower clocks, and thermal limits under sustained load.
+            Self::new("laptop", 1.5),
+            // A Raspberry Pi 4 (Cortex-A72 at 1.5 GHz).
+            Self::new("rpi", 8.0),
+        ]
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum DeviceProfileError {
+    #[error("reading device profiles: {0}")]
+    Io(#[from] io::Error),
+    #[error("parsing device profiles: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("device profile {name}: CPU scale must be positive, not {cpu_scale}")]
+    InvalidScale { name: String, cpu_scale: f64 },
+}
+
+/// Read `--device-profiles`: a JSON object from device names to CPU scales,
+/// e.g. `{ "phone": 4.0, "rpi": 10.0 }`. These are added to the built-in
+/// profiles (replacing any with the same name).
+pub fn load(path: &Path) -> Result<Vec<DeviceProfile>, DeviceProfileError> {
+    let reader = BufReader::new(File::open(path)?);
+    let scales: BTreeMap<String, f64> = serde_json::from_reader(reader)?;
+    scales
+        .into_iter()
+        .map(|(name, cpu_scale)| {
+            if !(cpu_scale.is_finite() && cpu_scale > 0.0) {
+                return Err(DeviceProfileError::InvalidScale { name, cpu_scale });
+            }
+            Ok(DeviceProfile::new(name, cpu_scale))
+        })
+        .collect()
+}
+
+/// Create the `device_profiles` table (with the built-in profiles, unless
+/// they've been changed) and the views over it.
+///
+/// The views read `refresh_results` and `download_results`, so create those
+/// first.
+pub fn create_tables(db: &Connection) -> rusqlite::Result<()> {
+    db.execute(
+        "CREATE TABLE IF NOT EXISTS device_profiles (
+             name      TEXT PRIMARY KEY,
+             cpu_scale REAL NOT NULL
+         )",
+        [],
+    )?;
+    for profile in DeviceProfile::builtin() {
+        db.execute(
+            "INSERT OR IGNORE INTO device_profiles (name, cpu_scale) VALUES (?1, ?2)",
+            rusqlite::params![profile.name, profile.cpu_scale],
+        )?;
+    }
+    db.execute(
+        "CREATE VIEW IF NOT EXISTS refresh_device_times AS
+         SELECT r.id AS result_id,
+                r.technique,
+                r.packages,
+                r.elapsed_releases,
+                d.name AS device,
+                CAST(ROUND(r.user_time_ns * d.cpu_scale) AS INTEGER) AS user_time_ns,
+                CAST(ROUND(r.batched_user_time_ns * d.cpu_scale) AS INTEGER)
+                    AS batched_user_time_ns
+         FROM refresh_results r CROSS JOIN device_profiles d",
+        [],
+    )?;
+    db.execute(
+        "CREATE VIEW IF NOT EXISTS download_device_times AS
+         SELECT r.id AS result_id,
+                r.technique,
+                r.packages,
+                d.name AS device,
+                CAST(ROUND(r.user_time_ns * d.cpu_scale) AS INTEGER) AS user_time_ns
+         FROM download_results r CROSS JOIN device_profiles d",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Add (or update) `profiles` in the results db.
+pub fn record(db: &Connection, profiles: &[DeviceProfile]) -> rusqlite::Result<()> {
+    for profile in profiles {
+        db.execute(
+            "INSERT OR REPLACE INTO device_profiles (name, cpu_scale) VALUES (?1, ?2)",
+            rusqlite::params![profile.name, profile.cpu_scale],
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaled_times() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(
+            "CREATE TABLE refresh_results (
+                 id INTEGER PRIMARY KEY, technique TEXT, packages INTEGER,
+                 elapsed_releases INTEGER, user_time_ns INTEGER,
+                 batched_user_time_ns INTEGER
+             );
+             CREATE TABLE download_results (
+                 id INTEGER PRIMARY KEY, technique TEXT, packages INTEGER,
+                 user_time_ns INTEGER
+             );
+             INSERT INTO download_results (technique, packages, user_time_ns)
+                 VALUES ('hackage', 10, 1000);",
+        )
+        .unwrap();
+        create_tables(&db).unwrap();
+        // Again, as for every run into the same db.
+        create_tables(&db).unwrap();
+        record(&db, &[DeviceProfile::new("rpi", 10.0)]).unwrap();
+        record(&db, &[DeviceProfile::new("phone", 4.0)]).unwrap();
+
+        let times: Vec<(String, i64)> = db
+            .prepare("SELECT device, user_time_ns FROM download_device_times ORDER BY device")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let times: Vec<_> = times.iter().map(|(d, t)| (d.as_str(), *t)).collect();
+        assert_eq!(
+            times,
+            [
+                ("laptop", 1500),
+                ("phone", 4000),
+                ("rpi", 10000),
+                ("server", 1000)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_scales() {
+        let path = std::env::temp_dir().join(format!("sssim-devices-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{ "phone": 4.0 }"#).unwrap();
+        assert_eq!(load(&path).unwrap(), [DeviceProfile::new("phone", 4.0)]);
+        std::fs::write(&path, r#"{ "phone": 0.0 }"#).unwrap();
+        assert!(matches!(
+            load(&path),
+            Err(DeviceProfileError::InvalidScale { .. })
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,254 @@
+//! How much a trained compression dictionary shrinks diffs (`sssim dictionary`).
+//!
+//! Registry metadata is repetitive: every Mercury or Hackage diff is a list of
+//! package names and revisions, in the same layout, and popular packages show
+//! up again and again. Generic compression can't exploit much of that within
+//! one small diff, but a zstd dictionary trained on earlier diffs can (the
+//! server would ship the dictionary to clients once, and retrain now and then).
+//! That changes how the diff-based techniques compare on bandwidth, so
+//! [`evaluate`] measures it: it trains on the first part of a history of diffs
+//! (see [`collect_diffs`]) and compresses the rest, as a server could only have
+//! trained on diffs it had already sent.
+use std::io;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::authenticator::DynAuthenticator;
+use crate::log::PackageId;
+use crate::report::Fixed;
+
+/// Settings for [`collect_diffs`] and [`evaluate`].
+#[derive(Debug, Clone)]
+pub struct DictionaryOptions {
+    /// How many diffs to collect.
+    pub diffs: usize,
+    /// Publishes between a client's refreshes (so, in each diff).
+    pub publishes_per_diff: usize,
+    /// Probability that a publish is of a brand-new package.
+    pub new_package_fraction: f64,
+    /// Fraction of the diffs (the earliest) to train on; the rest are
+    /// compressed.
+    pub train_fraction: f64,
+    /// Largest dictionary to train, in bytes.
+    pub dictionary_size: usize,
+    /// zstd compression level.
+    pub level: i32,
+    /// Seed for which packages get published.
+    pub seed: u64,
+}
+
+impl Default for DictionaryOptions {
+    fn default() -> Self {
+        Self {
+            diffs: 1000,
+            publishes_per_diff: 10,
+            new_package_fraction: 0.1,
+            train_fraction: 0.5,
+            dictionary_size: 16 * 1024,
+            level: 3,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum DictionaryError {
+    #[error("need diffs both to train on and to compress (got {train} and {test})")]
+    NotEnoughDiffs { train: usize, test: usize },
+    #[error("zstd: {0}")]
+    Zstd(#[from] io::Error),
+}
+
+/// Diff sizes, in bytes, over the diffs [`evaluate`] didn't train on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CompressionReport {
+    /// Diffs trained on.
+    pub trained: usize,
+    /// Diffs compressed.
+    pub compressed: usize,
+    /// The trained dictionary (which each client downloads once).
+    pub dictionary_bytes: usize,
+    /// Uncompressed (bincode).
+    pub raw_bytes: usize,
+    /// Compressed with zstd alone.
+    pub zstd_bytes: usize,
+    /// Compressed with zstd and the dictionary.
+    pub dictionary_zstd_bytes: usize,
+}
+
+impl CompressionReport {
+    /// How many times smaller zstd alone makes the diffs.
+    pub fn zstd_ratio(&self) -> Option<Fixed> {
+        Fixed::ratio(self.raw_bytes as u128, self.zstd_bytes as u128)
+    }
+
+    /// How many times smaller zstd with the dictionary makes the diffs.
+    pub fn dictionary_ratio(&self) -> Option<Fixed> {
+        Fixed::ratio(self.raw_bytes as u128, self.dictionary_zstd_bytes as u128)
+    }
+
+    /// Diffs a client has to download before the dictionary has paid for
+    /// itself, if it helps at all.
+    pub fn break_even_diffs(&self) -> Option<usize> {
+        let saved = self
+            .zstd_bytes
+            .checked_sub(self.dictionary_zstd_bytes)
+            .filter(|saved| *saved > 0)?;
+        // The dictionary over the savings per diff, rounded up.
+        Some((self.dictionary_bytes * self.compressed + saved - 1) / saved)
+    }
+}
+
+/// Publish to `server` (freshly imported with `packages`) and collect the
+/// diffs one client sees, refreshing every `options.publishes_per_diff`
+/// publishes, as they'd go over the wire.
+///
+/// Stops early if the technique has no diffs.
+pub fn collect_diffs(
+    server: &mut dyn DynAuthenticator,
+    packages: &[PackageId],
+    options: &DictionaryOptions,
+) -> Vec<Vec<u8>> {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut packages = packages.to_vec();
+    let mut snapshot = server.get_metadata();
+    let mut diffs = Vec::with_capacity(options.diffs);
+    let mut published = 0;
+    while diffs.len() < options.diffs {
+        let mut new = Vec::new();
+        for _ in 0..options.publishes_per_diff {
+            let package = if packages.is_empty() || rng.gen_bool(options.new_package_fraction) {
+                published += 1;
+                let package = PackageId::from(format!("new_package{published}"));
+                new.push(package.clone());
+                package
+            } else {
+                packages[rng.gen_range(0..packages.len())].clone()
+            };
+            server.publish(package);
+        }
+        server.batch_process();
+        packages.append(&mut new);
+        match server.refresh_metadata(&snapshot) {
+            Some(diff) => {
+                diffs.push(diff.to_bytes());
+                snapshot.update(diff);
+            }
+            None => break,
+        }
+    }
+    diffs
+}
+
+/// Train a dictionary on the first `options.train_fraction` of `diffs`, and
+/// compare compressing the rest with and without it.
+pub fn evaluate(
+    diffs: &[Vec<u8>],
+    options: &DictionaryOptions,
+) -> Result<CompressionReport, DictionaryError> {
+    let split = (diffs.len() as f64 * options.train_fraction) as usize;
+    let (train, test) = diffs.split_at(split.min(diffs.len()));
+    if train.is_empty() || test.is_empty() {
+        return Err(DictionaryError::NotEnoughDiffs {
+            train: train.len(),
+            test: test.len(),
+        });
+    }
+    let dictionary = zstd::dict::from_samples(train, options.dictionary_size)?;
+    let mut plain = zstd::bulk::Compressor::new(options.level)?;
+    let mut with_dictionary = zstd::bulk::Compressor::with_dictionary(options.level, &dictionary)?;
+
+    let mut report = CompressionReport {
+        trained: train.len(),
+        compressed: test.len(),
+        dictionary_bytes: dictionary.len(),
+        ..CompressionReport::default()
+    };
+    for diff in test {
+        report.raw_bytes += diff.len();
+        report.zstd_bytes += plain.compress(diff)?.len();
+        report.dictionary_zstd_bytes += with_dictionary.compress(diff)?.len();
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::authenticator::Authenticators;
+    use crate::testing::package_ids;
+
+    #[test]
+    fn collects_one_diff_per_refresh() {
+        let options = DictionaryOptions {
+            diffs: 5,
+            publishes_per_diff: 3,
+            ..DictionaryOptions::default()
+        };
+        let technique = Authenticators::by_name("hackage").unwrap();
+        let mut server = technique.import(package_ids(10));
+        let diffs = collect_diffs(server.as_mut(), &package_ids(10), &options);
+        assert_eq!(diffs.len(), 5);
+        assert!(diffs.iter().all(|diff| !diff.is_empty()));
+
+        // No diffs at all: stop rather than spin.
+        let technique = Authenticators::by_name("none").unwrap();
+        let mut server = technique.import(package_ids(10));
+        assert!(collect_diffs(server.as_mut(), &package_ids(10), &options).is_empty());
+    }
+
+    #[test]
+    fn dictionary_shrinks_diffs() {
+        let options = DictionaryOptions {
+            diffs: 400,
+            publishes_per_diff: 4,
+            dictionary_size: 4 * 1024,
+            ..DictionaryOptions::default()
+        };
+        for name in ["hackage", "mercury_diff"] {
+            let technique = Authenticators::by_name(name).unwrap();
+            let mut server = technique.import(package_ids(1000));
+            let diffs = collect_diffs(server.as_mut(), &package_ids(1000), &options);
+            let report = evaluate(&diffs, &options).unwrap_or_else(|err| panic!("{name}: {err}"));
+            assert_eq!((report.trained, report.compressed), (200, 200), "{name}");
+            assert!(report.dictionary_bytes > 0, "{name}");
+            assert!(
+                report.dictionary_zstd_bytes < report.zstd_bytes,
+                "{name}: {report:?}"
+            );
+            assert!(report.dictionary_ratio() > report.zstd_ratio(), "{name}");
+            assert!(report.break_even_diffs().is_some(), "{name}");
+        }
+    }
+
+    #[test]
+    fn needs_diffs_to_train_and_test() {
+        let diffs = vec![vec![0u8; 16]];
+        assert!(matches!(
+            evaluate(&diffs, &DictionaryOptions::default()),
+            Err(DictionaryError::NotEnoughDiffs { train: 0, test: 1 })
+        ));
+    }
+
+    #[test]
+    fn break_even() {
+        let report = CompressionReport {
+            compressed: 10,
+            dictionary_bytes: 1000,
+            zstd_bytes: 500,
+            dictionary_zstd_bytes: 300,
+            ..CompressionReport::default()
+        };
+        // Saves 20 bytes per diff.
+        assert_eq!(report.break_even_diffs(), Some(50));
+        let no_help = CompressionReport {
+            dictionary_zstd_bytes: 500,
+            ..report
+        };
+        assert_eq!(no_help.break_even_diffs(), None);
+    }
+}
@@ -0,0 +1,188 @@
+//! Assembling responses at the CDN edge (`--edge-budget-us`).
+//!
+//! Whether a CDN can serve a response is usually asked as yes or no: a static
+//! file everyone gets (a Mercury snapshot, a Merkle root) is cacheable, and
+//! anything the origin computes for one client isn't. Most edges can run a
+//! little code, though, and many per-client responses are cheap to put
+//! together from objects that are cached: an RSA append-only proof is a few
+//! skip-list hops the origin already computed, a sparse Merkle proof is a
+//! path of cached nodes, and a Mercury diff compares two cached snapshots. (A
+//! Hackage log slice is what a range request already gets, so it counts as
+//! static.)
+//!
+//! Techniques say what assembling a response would take ([`EdgeWork`]; see
+//! [`Authenticator::diff_edge_work`] and [`Authenticator::proof_edge_work`]),
+//! and an [`EdgeModel`] says whether that fits in the edge's compute budget
+//! for a request. A technique whose every response is static or fits is
+//! CDN-friendly at that budget ([`EdgeTally::cdn_friendly`]).
+//!
+//! [`Authenticator::diff_edge_work`]: crate::Authenticator::diff_edge_work
+//! [`Authenticator::proof_edge_work`]: crate::Authenticator::proof_edge_work
+use std::fmt;
+
+use serde::Serialize;
+use time::Duration;
+
+use crate::util::{byte, Information};
+
+/// What it takes to assemble one response from cached objects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdgeWork {
+    /// Cached objects read.
+    pub reads: usize,
+    /// Bytes read or written along the way.
+    pub bytes: Information,
+}
+
+impl EdgeWork {
+    pub fn new(reads: usize, bytes: Information) -> Self {
+        Self { reads, bytes }
+    }
+}
+
+/// How much an edge can do per request, and what work costs there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdgeModel {
+    /// CPU time an edge will spend on one request.
+    pub budget: Duration,
+    /// Time to read one cached object (a lookup in the edge's key-value
+    /// store).
+    pub read: Duration,
+    /// Time to process a KiB (parse, compare, or copy it).
+    pub per_kib: Duration,
+}
+
+impl Default for EdgeModel {
+    /// A modest budget, about what the cheapest tiers of edge workers allow.
+    fn default() -> Self {
+        Self {
+            budget: Duration::milliseconds(5),
+            read: Duration::microseconds(100),
+            per_kib: Duration::microseconds(1),
+        }
+    }
+}
+
+impl EdgeModel {
+    pub fn with_budget(mut self, budget: Duration) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    pub fn with_read(mut self, read: Duration) -> Self {
+        self.read = read;
+        self
+    }
+
+    /// How long `work` takes at the edge.
+    pub fn time(&self, work: &EdgeWork) -> Duration {
+        let kib = work.bytes.get::<byte>() as f64 / 1024.0;
+        self.read * work.reads as u32 + self.per_kib * kib
+    }
+
+    /// Where a response gets served: from the cache if it's `cacheable`, else
+    /// at the edge if its `work` fits in the budget, else from the origin.
+    pub fn place(&self, cacheable: bool, work: Option<&EdgeWork>) -> Placement {
+        if cacheable {
+            return Placement::Static;
+        }
+        match work {
+            Some(work) if self.time(work) <= self.budget => Placement::Edge,
+            _ => Placement::Origin,
+        }
+    }
+}
+
+/// Where a response gets served.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum Placement {
+    /// A cached file, the same for everyone.
+    Static,
+    /// Assembled by the edge from cached objects.
+    Edge,
+    /// Computed by the origin.
+    Origin,
+}
+
+impl Placement {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Placement::Static => "static",
+            Placement::Edge => "edge",
+            Placement::Origin => "origin",
+        }
+    }
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// How many responses were served from where.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EdgeTally {
+    pub static_responses: usize,
+    pub edge_responses: usize,
+    pub origin_responses: usize,
+}
+
+impl EdgeTally {
+    pub fn record(&mut self, placement: Placement) {
+        match placement {
+            Placement::Static => self.static_responses += 1,
+            Placement::Edge => self.edge_responses += 1,
+            Placement::Origin => self.origin_responses += 1,
+        }
+    }
+
+    /// Whether the origin never had to answer a client.
+    pub fn cdn_friendly(&self) -> bool {
+        self.origin_responses == 0
+    }
+}
+
+impl fmt::Display for EdgeTally {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} static, {} edge, {} origin",
+            self.static_responses, self.edge_responses, self.origin_responses
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn places() {
+        let model = EdgeModel::default()
+            .with_budget(Duration::milliseconds(1))
+            .with_read(Duration::microseconds(100));
+        let cheap = EdgeWork::new(5, Information::new::<byte>(10 * 1024));
+        let expensive = EdgeWork::new(20, Information::new::<byte>(1024));
+        assert_eq!(model.time(&cheap), Duration::microseconds(510));
+
+        assert_eq!(model.place(true, Some(&expensive)), Placement::Static);
+        assert_eq!(model.place(true, None), Placement::Static);
+        assert_eq!(model.place(false, Some(&cheap)), Placement::Edge);
+        assert_eq!(model.place(false, Some(&expensive)), Placement::Origin);
+        assert_eq!(model.place(false, None), Placement::Origin);
+        let generous = model.with_budget(Duration::milliseconds(10));
+        assert_eq!(generous.place(false, Some(&expensive)), Placement::Edge);
+    }
+
+    #[test]
+    fn tallies() {
+        let mut tally = EdgeTally::default();
+        tally.record(Placement::Static);
+        tally.record(Placement::Edge);
+        assert!(tally.cdn_friendly());
+        tally.record(Placement::Origin);
+        assert!(!tally.cdn_friendly());
+        assert_eq!(tally.to_string(), "1 static, 1 edge, 1 origin");
+    }
+}
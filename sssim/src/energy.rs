@@ -0,0 +1,131 @@
+//! Estimating how much energy a client spends on an operation.
+//!
+//! For package managers on phones and laptops running on battery, "how many
+//! joules does a refresh cost?" matters more than how long it takes on our
+//! benchmark machine. We don't measure energy directly; instead, an
+//! [`EnergyModel`] multiplies the [operation counts](crate::counters) and bytes
+//! received for an operation by per-unit constants.
+//!
+//! Operation counts are only available with the `counters` feature, so
+//! without it there's nothing to estimate.
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uom::si::information::byte;
+
+use crate::counters::{self, OpCounts};
+use crate::util::Information;
+
+/// Joules per unit of work.
+///
+/// A group exponentiation costs the same no matter how big the exponent is, so
+/// this underestimates clients that raise to huge products.
+///
+/// ```json
+/// {
+///   "group_op": 2e-6,
+///   "group_exp": 5e-3,
+///   "hash": 1e-6,
+///   "bigint_mult": 2e-6,
+///   "byte_received": 5e-7
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnergyModel {
+    pub group_op: f64,
+    pub group_exp: f64,
+    pub hash: f64,
+    pub bigint_mult: f64,
+    pub byte_received: f64,
+}
+
+impl Default for EnergyModel {
+    /// Rough figures for a phone: 2048-bit arithmetic on a ~1 W core, and the
+    /// radio's cost per byte over LTE.
+    fn default() -> Self {
+        Self {
+            group_op: 2e-6,
+            group_exp: 5e-3,
+            hash: 1e-6,
+            bigint_mult: 2e-6,
+            byte_received: 5e-7,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum EnergyModelError {
+    #[error("reading energy model: {0}")]
+    Io(#[from] io::Error),
+    #[error("parsing energy model: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+impl EnergyModel {
+    /// Read from `--energy-model`; missing fields keep their defaults.
+    pub fn load(path: &Path) -> Result<Self, EnergyModelError> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Joules for doing `ops` after receiving `bandwidth`.
+    pub fn joules(&self, ops: &OpCounts, bandwidth: Information) -> f64 {
+        ops.group_ops as f64 * self.group_op
+            + ops.group_exps as f64 * self.group_exp
+            + ops.hashes as f64 * self.hash
+            + ops.bigint_mults as f64 * self.bigint_mult
+            + bandwidth.get::<byte>() as f64 * self.byte_received
+    }
+
+    /// Like [`EnergyModel::joules`], but `None` if operations aren't being
+    /// counted (so the estimate would leave out all the computation).
+    pub fn estimate(&self, ops: &OpCounts, bandwidth: Information) -> Option<f64> {
+        counters::ENABLED.then(|| self.joules(ops, bandwidth))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joules() {
+        let model = EnergyModel {
+            group_op: 1.0,
+            group_exp: 10.0,
+            hash: 100.0,
+            bigint_mult: 1000.0,
+            byte_received: 0.5,
+        };
+        let ops = OpCounts {
+            group_ops: 1,
+            group_exps: 2,
+            hashes: 3,
+            bigint_mults: 4,
+        };
+        assert_eq!(
+            model.joules(&ops, Information::new::<byte>(10)),
+            1.0 + 20.0 + 300.0 + 4000.0 + 5.0
+        );
+        assert_eq!(
+            model.estimate(&ops, Information::new::<byte>(10)).is_some(),
+            counters::ENABLED
+        );
+    }
+
+    #[test]
+    fn partial_config() {
+        let model: EnergyModel = serde_json::from_str(r#"{ "hash": 3.0 }"#).unwrap();
+        assert_eq!(
+            model,
+            EnergyModel {
+                hash: 3.0,
+                ..EnergyModel::default()
+            }
+        );
+    }
+}
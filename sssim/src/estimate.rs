@@ -0,0 +1,244 @@
+//! Back-of-the-envelope resource estimates for a run, before launching it.
+//!
+//! Each technique gets a crude analytic [`Model`] of how a full `sssim` run's
+//! wall time and peak memory grow with the number of packages. Constants for
+//! the cheap techniques are fit to runs on a laptop (10k and 100k packages);
+//! the rest are built from the cost of their core operations (e.g. an RSA
+//! publish updates every cached witness, so it's `n` exponentiations). They're
+//! good for telling a ten-minute run from a ten-day one, and not much else.
+//!
+//! Threads are ignored: the estimates are for one core.
+use std::fmt;
+
+use time::Duration;
+
+use crate::authenticator::Authenticators;
+use crate::util::{byte, Information};
+use Growth::*;
+
+/// One 2048-bit modular exponentiation by a 256-bit prime.
+const EXP_NS: f64 = 300_000.0;
+/// One SHA-3 of a couple of hashes.
+const HASH_NS: f64 = 500.0;
+/// Packages published, in total, across `run_batch`'s batch sizes.
+const BATCH_PUBLISHES: f64 = 5500.0;
+/// Depth of the sparse Merkle tree.
+const SMT_DEPTH: f64 = 256.0;
+
+/// How a cost grows with the number of packages, `n`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Growth {
+    Constant,
+    Linear,
+    /// `n log n`.
+    Linearithmic,
+}
+
+impl Growth {
+    fn at(self, n: usize) -> f64 {
+        let n = n as f64;
+        match self {
+            Self::Constant => 1.0,
+            Self::Linear => n,
+            Self::Linearithmic => n * n.max(2.0).log2(),
+        }
+    }
+
+    /// Scale a cost measured at `sample` packages up to `n` packages.
+    pub fn extrapolate(self, cost: f64, sample: usize, n: usize) -> f64 {
+        cost * self.at(n) / self.at(sample)
+    }
+}
+
+impl fmt::Display for Growth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Constant => "1",
+            Self::Linear => "n",
+            Self::Linearithmic => "n log n",
+        };
+        f.write_str(name)
+    }
+}
+
+/// How a technique's server-side phases grow with the number of packages.
+///
+/// Only techniques too slow to run at full scale have one; scale mode uses it
+/// to estimate their full-scale costs from a sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerScaling {
+    pub precompute: Growth,
+    pub update: Growth,
+    /// Server state and CDN size.
+    pub state: Growth,
+}
+
+/// A sum of terms `coefficient * growth(n)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cost(Vec<(f64, Growth)>);
+
+impl Cost {
+    pub fn new(terms: impl IntoIterator<Item = (f64, Growth)>) -> Self {
+        Self(terms.into_iter().collect())
+    }
+
+    pub fn at(&self, n: usize) -> f64 {
+        self.0
+            .iter()
+            .map(|(coefficient, growth)| coefficient * growth.at(n))
+            .sum()
+    }
+}
+
+/// How a technique's run grows with the number of packages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Model {
+    /// Wall time, in nanoseconds.
+    pub time: Cost,
+    /// Peak resident memory, in bytes.
+    pub memory: Cost,
+}
+
+impl Model {
+    fn new(time: &[(f64, Growth)], memory: &[(f64, Growth)]) -> Self {
+        Model {
+            time: Cost::new(time.iter().copied()),
+            memory: Cost::new(memory.iter().copied()),
+        }
+    }
+
+    /// The model for technique `name` (as passed to `--authenticators`), if
+    /// there is one.
+    pub fn for_technique(name: &str) -> Option<Self> {
+        Authenticators::by_name(name)
+            .ok()
+            .map(|technique| technique.model())
+    }
+
+    /// `none` and `insecure`: nothing beyond the harness.
+    pub fn unauthenticated() -> Self {
+        Self::new(&[], &[])
+    }
+
+    pub fn hackage() -> Self {
+        Self::new(&[(1_500.0, Linear)], &[(300.0, Linear)])
+    }
+
+    pub fn mercury() -> Self {
+        Self::new(&[(8_000.0, Linear)], &[(300.0, Linear)])
+    }
+
+    pub fn mercury_diff() -> Self {
+        Self::new(&[(50_000.0, Linear)], &[(33_000.0, Linear)])
+    }
+
+    /// Plus the simulated 30s precompute delay. (BLAKE3 hashes faster, but
+    /// the delay dominates.)
+    pub fn sparse_merkle() -> Self {
+        Self::new(
+            &[(30e9, Constant), (SMT_DEPTH * HASH_NS, Linear)],
+            &[(64.0, Linearithmic)],
+        )
+    }
+
+    pub fn merkle_bpt() -> Self {
+        Self::new(&[(HASH_NS, Linearithmic)], &[(200.0, Linear)])
+    }
+
+    /// Precomputing all (membership and non-membership) witnesses, then a
+    /// publish updating each.
+    pub fn rsa() -> Self {
+        Self::new(
+            &[(2.0 * EXP_NS, Linearithmic), (2.0 * EXP_NS, Linear)],
+            &[(800.0, Linear)],
+        )
+    }
+
+    /// As [`Self::rsa`], but every batch size's publishes.
+    pub fn rsa_pool() -> Self {
+        Self::new(
+            &[
+                (2.0 * EXP_NS, Linearithmic),
+                (2.0 * BATCH_PUBLISHES * EXP_NS, Linear),
+            ],
+            &[(800.0, Linear)],
+        )
+    }
+
+    /// No cache: the digest, then a witness per download.
+    pub fn rsa_stateless() -> Self {
+        Self::new(&[(2.0 * EXP_NS, Linear)], &[(100.0, Linear)])
+    }
+
+    pub fn estimate(&self, packages: usize) -> Estimate {
+        // Every run pays for the harness, and the list of package names.
+        let time = 20e6 + self.time.at(packages);
+        let memory = 8e6 + 100.0 * packages as f64 + self.memory.at(packages);
+        Estimate {
+            runtime: Duration::nanoseconds(time.min(i64::MAX as f64) as i64),
+            memory: Information::new::<byte>(memory.min(usize::MAX as f64) as usize),
+        }
+    }
+}
+
+/// The estimated cost of a run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub runtime: Duration,
+    pub memory: Information,
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = self.memory.get::<byte>() as f64;
+        let (memory, unit) = [(1e12, "TB"), (1e9, "GB"), (1e6, "MB")]
+            .into_iter()
+            .find(|(scale, _)| bytes >= *scale)
+            .map_or((bytes / 1e3, "kB"), |(scale, unit)| (bytes / scale, unit));
+        write!(f, "~{:.1}, ~{memory:.1} {unit}", self.runtime)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn models_every_technique() {
+        for technique in Authenticators::all() {
+            assert!(
+                Model::for_technique(technique.name()).is_some(),
+                "{}",
+                technique.name()
+            );
+        }
+        assert_eq!(Model::for_technique("nope"), None);
+    }
+
+    #[test]
+    fn estimates() {
+        let hackage = Model::for_technique("hackage").unwrap();
+        let small = hackage.estimate(1_000);
+        let large = hackage.estimate(1_000_000);
+        assert!(small.runtime < large.runtime);
+        assert!(small.memory < large.memory);
+
+        // The runs this is for.
+        let rsa = Model::for_technique("rsa").unwrap().estimate(1_000_000);
+        assert!(rsa.runtime > Duration::hours(1));
+
+        let estimate = Estimate {
+            runtime: Duration::seconds(90),
+            memory: Information::new::<byte>(1_500_000_000),
+        };
+        assert_eq!(estimate.to_string(), "~1.5m, ~1.5 GB");
+    }
+
+    #[test]
+    fn extrapolate() {
+        assert_eq!(Growth::Constant.extrapolate(5.0, 10, 1000), 5.0);
+        assert_eq!(Growth::Linear.extrapolate(5.0, 10, 1000), 500.0);
+        // 1024 * 10 / (16 * 4)
+        assert_eq!(Growth::Linearithmic.extrapolate(1.0, 16, 1024), 160.0);
+    }
+}
@@ -0,0 +1,240 @@
+//! Injecting storage-layer bit flips into server state (`--fault-trials`).
+//!
+//! Servers keep digests, trees, and proof caches for a long time, on disks and
+//! in memory that now and then flip a bit. Whether anyone notices depends on
+//! the technique: one that can replay its log catches a corrupted revision in
+//! its own invariant checks, a client catches one that no longer matches its
+//! signed snapshot, and one in state that nobody checks is served as if it
+//! were real. [`inject`] flips one bit of a server's saved state (see
+//! [`WarmState`]), loads it back, and sees what catches it (see [`Outcome`]).
+//!
+//! A corrupted state can also make a technique panic (say, on a revision that
+//! overflows); that counts as catching it, at whichever step panicked.
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use rand::Rng;
+use serde::Serialize;
+
+use crate::authenticator::Authenticator;
+use crate::log::PackageId;
+use crate::warm_state::WarmState;
+
+/// What caught a bit flip, if anything (earliest first).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum Outcome {
+    /// The state didn't load.
+    Unreadable,
+    /// The server's own invariant checks failed.
+    Invariant,
+    /// A client rejected a diff or proof from the corrupted server.
+    Rejected,
+    /// Nothing noticed, but the server now serves different revisions (or
+    /// packages) than were published.
+    Silent,
+    /// Nothing noticed, and nothing clients can see changed (e.g. the bit was
+    /// in a cache that gets rebuilt).
+    Harmless,
+}
+
+impl Outcome {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Outcome::Unreadable => "unreadable",
+            Outcome::Invariant => "invariant",
+            Outcome::Rejected => "rejected",
+            Outcome::Silent => "silent",
+            Outcome::Harmless => "harmless",
+        }
+    }
+
+    /// Whether the corruption was caught (rather than served).
+    pub fn detected(&self) -> bool {
+        matches!(
+            self,
+            Outcome::Unreadable | Outcome::Invariant | Outcome::Rejected
+        )
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// One bit flip and what came of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    /// Which bit of the saved state was flipped.
+    pub bit: usize,
+    /// How big the saved state was, in bytes.
+    pub state_bytes: usize,
+    pub outcome: Outcome,
+}
+
+/// `f()`, or `None` if it panics.
+fn caught<T>(f: impl FnOnce() -> T) -> Option<T> {
+    panic::catch_unwind(AssertUnwindSafe(f)).ok()
+}
+
+/// `f()`, without printing the panics it catches along the way.
+///
+/// Corrupted state panicking is one of the outcomes we're after, so wrap a run
+/// of [`inject`]s in this rather than printing every one. The previous panic
+/// hook comes back even if `f` itself panics.
+pub fn quietly<T>(f: impl FnOnce() -> T) -> T {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    panic::set_hook(hook);
+    result.unwrap_or_else(|payload| panic::resume_unwind(payload))
+}
+
+/// Flip a random bit of `server`'s saved state, and see what catches it.
+///
+/// `None` if the state is empty (so there's nothing to flip).
+pub fn inject<A>(server: &A, packages: &[PackageId], rng: &mut impl Rng) -> Option<Fault>
+where
+    A: Authenticator + WarmState,
+{
+    let mut state = Vec::new();
+    server.save_state(&mut state).expect("saving to memory");
+    if state.is_empty() {
+        return None;
+    }
+    let bit = rng.gen_range(0..state.len() * 8);
+    Some(inject_at(server, packages, bit))
+}
+
+/// Flip `bit` of `server`'s saved state, and see what catches it: loading
+/// the state, the server's invariant checks, or a client (holding the
+/// snapshot from before the flip) refreshing and downloading each of
+/// `packages`.
+///
+/// Panics if `bit` is past the end of the state.
+pub fn inject_at<A>(server: &A, packages: &[PackageId], bit: usize) -> Fault
+where
+    A: Authenticator + WarmState,
+{
+    let mut state = Vec::new();
+    server.save_state(&mut state).expect("saving to memory");
+    let state_bytes = state.len();
+    state[bit / 8] ^= 1 << (bit % 8);
+    let outcome = check(server, packages, &state);
+    Fault {
+        bit,
+        state_bytes,
+        outcome,
+    }
+}
+
+fn check<A>(server: &A, packages: &[PackageId], state: &[u8]) -> Outcome
+where
+    A: Authenticator + WarmState,
+{
+    let corrupted = match caught(|| A::load_state_from_slice(state)) {
+        Some(Ok(corrupted)) => corrupted,
+        _ => return Outcome::Unreadable,
+    };
+    if !matches!(caught(|| corrupted.check_invariants()), Some(Ok(()))) {
+        return Outcome::Invariant;
+    }
+
+    let snapshot = server.get_metadata();
+    let refreshed = caught(|| match corrupted.refresh_metadata(A::id(&snapshot)) {
+        Some(diff) => {
+            let ok = A::check_no_rollback(&snapshot, &diff);
+            A::update(&mut snapshot.clone(), diff);
+            ok
+        }
+        None => true,
+    });
+    if refreshed != Some(true) {
+        return Outcome::Rejected;
+    }
+    let mut changed = false;
+    for package in packages {
+        let accepted = caught(|| {
+            let (revision, proof) = corrupted.request_file(A::id(&snapshot), package);
+            let (expected, _) = server.request_file(A::id(&snapshot), package);
+            changed |= revision != expected;
+            A::verify_membership(&snapshot, package, revision, proof)
+        });
+        if accepted != Some(true) {
+            return Outcome::Rejected;
+        }
+    }
+
+    match caught(|| corrupted.export_state()) {
+        Some(exported) if exported != server.export_state() => changed = true,
+        Some(_) => {}
+        None => return Outcome::Rejected,
+    }
+    if changed {
+        Outcome::Silent
+    } else {
+        Outcome::Harmless
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::authenticator::{Hackage, NoAuth, VanillaTuf};
+    use crate::testing::package_ids;
+
+    /// The outcome of flipping every bit of `A`'s state, with a few publishes.
+    fn every_bit<A: Authenticator + WarmState>() -> BTreeMap<Outcome, usize> {
+        let mut server = A::batch_import(package_ids(4));
+        server.publish(PackageId::from("package0".to_string()));
+        server.publish(PackageId::from("new_package".to_string()));
+        let mut state = Vec::new();
+        server.save_state(&mut state).unwrap();
+
+        let mut outcomes = BTreeMap::new();
+        for bit in 0..state.len() * 8 {
+            let fault = inject_at(&server, &package_ids(4), bit);
+            assert_eq!(fault.state_bytes, state.len());
+            *outcomes.entry(fault.outcome).or_default() += 1;
+        }
+        outcomes
+    }
+
+    #[test]
+    fn hackage_catches_everything() {
+        // Hackage can replay its index to check its current revisions.
+        let outcomes = every_bit::<Hackage>();
+        assert!(outcomes.keys().all(Outcome::detected), "{outcomes:?}");
+    }
+
+    #[test]
+    fn mercury_misses_some() {
+        let outcomes = every_bit::<VanillaTuf>();
+        assert!(outcomes.get(&Outcome::Rejected) > Some(&0), "{outcomes:?}");
+        assert!(outcomes.get(&Outcome::Silent) > Some(&0), "{outcomes:?}");
+    }
+
+    #[test]
+    fn random_bits() {
+        let server = Hackage::batch_import(package_ids(4));
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..20 {
+            let fault = inject(&server, &package_ids(4), &mut rng).unwrap();
+            assert!(fault.bit < fault.state_bytes * 8);
+        }
+    }
+
+    #[test]
+    fn nothing_to_flip() {
+        let server = NoAuth::batch_import(package_ids(4));
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(inject(&server, &package_ids(4), &mut rng), None);
+    }
+}
@@ -0,0 +1,384 @@
+//! Client clock skew and metadata expiry.
+//!
+//! Signed metadata carries an expiry so that a client can't be kept on stale
+//! metadata forever (a freeze attack). The server re-signs whenever the
+//! metadata changes, and otherwise every `resign_interval`; each signature is
+//! good for `validity`. Clients check expiry against their *own* clocks,
+//! though, and those are off by some skew. A client whose clock runs ahead
+//! rejects metadata that hasn't actually expired; one whose clock runs behind
+//! may see metadata as "not yet valid."
+//!
+//! Every refresh response carries a [`SignedTimestamp`], whether or not the
+//! technique had a diff to send: "nothing changed" has to be fresh too, or a
+//! frozen client couldn't tell. Since it's the same for every technique, it
+//! travels in a shared [`Timestamped`](crate::authenticator::Timestamped)
+//! wrapper around the technique's diff rather than in each `Diff`, and is
+//! checked by
+//! [`Authenticator::verify_timestamped_freshness`](crate::Authenticator::verify_timestamped_freshness).
+//! Clients may also enforce their own maximum staleness, on top of the
+//! server's expiry (see [`Freshness::with_max_staleness`]); they reject
+//! refresh responses signed longer ago than that.
+//!
+//! [`Freshness`] tracks when each client's metadata was signed and counts how
+//! often a skewed client rejects metadata that is valid by the true clock.
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use serde::Serialize;
+use time::{Duration, OffsetDateTime};
+
+use crate::log::UserId;
+use crate::util::{byte, FixedDataSized, Information};
+
+/// When the server signs metadata, and for how long signatures are good.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FreshnessPolicy {
+    /// How long after signing metadata expires.
+    pub validity: Duration,
+    /// Maximum time between signatures (even if nothing changed).
+    pub resign_interval: Duration,
+}
+
+impl FreshnessPolicy {
+    /// TUF's suggested lifetime for timestamp metadata: valid for a day,
+    /// re-signed twice a day.
+    pub fn tuf_timestamp() -> Self {
+        Self {
+            validity: Duration::days(1),
+            resign_interval: Duration::hours(12),
+        }
+    }
+}
+
+/// The server's signature over "metadata is current as of `epoch`".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct SignedTimestamp {
+    /// When the server signed, in seconds since the Unix epoch.
+    epoch: u64,
+}
+
+impl SignedTimestamp {
+    pub fn new(issued: OffsetDateTime) -> Self {
+        Self {
+            epoch: issued
+                .unix_timestamp()
+                .try_into()
+                .expect("signed before the Unix epoch"),
+        }
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// When the server signed.
+    pub fn issued(&self) -> OffsetDateTime {
+        let epoch = self.epoch.try_into().expect("signed too far in the future");
+        OffsetDateTime::from_unix_timestamp(epoch).expect("signed too far in the future")
+    }
+}
+
+impl FixedDataSized for SignedTimestamp {
+    fn fixed_size() -> Information {
+        // The epoch, plus an Ed25519 signature (we don't simulate keys).
+        u64::fixed_size() + Information::new::<byte>(64)
+    }
+}
+
+/// Per-client clock skew, uniform in `[-max, max]`.
+///
+/// Each client's skew is a deterministic function of its ID, so runs are
+/// reproducible and every technique sees the same clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSkew {
+    max: Duration,
+}
+
+impl ClockSkew {
+    pub fn new(max: Duration) -> Self {
+        assert!(!max.is_negative(), "max skew must be non-negative");
+        Self { max }
+    }
+
+    /// How far ahead (positive) or behind (negative) `user`'s clock runs.
+    pub fn for_user(&self, user: &UserId) -> Duration {
+        let max_ms = self.max.whole_milliseconds() as u64;
+        if max_ms == 0 {
+            return Duration::ZERO;
+        }
+        let mut hasher = DefaultHasher::new();
+        user.hash(&mut hasher);
+        let offset = hasher.finish() % (2 * max_ms + 1);
+        Duration::milliseconds(offset as i64 - max_ms as i64)
+    }
+}
+
+/// What a client concludes about its metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Fresh,
+    Expired,
+    NotYetValid,
+}
+
+impl Verdict {
+    fn new(issued: OffsetDateTime, validity: Duration, now: OffsetDateTime) -> Self {
+        if now < issued {
+            Verdict::NotYetValid
+        } else if now >= issued + validity {
+            Verdict::Expired
+        } else {
+            Verdict::Fresh
+        }
+    }
+}
+
+/// Outcomes of the expiry checks made so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct FreshnessStats {
+    /// Number of expiry checks clients made.
+    pub checks: usize,
+    /// Metadata that was really expired (a stale client), whatever the skew.
+    pub expired: usize,
+    /// Valid metadata rejected as expired because the client clock ran ahead.
+    pub spurious_expired: usize,
+    /// Valid metadata rejected as not-yet-valid because the clock ran behind.
+    pub spurious_not_yet_valid: usize,
+    /// Metadata older than the client's maximum staleness (by its own clock).
+    pub stale: usize,
+    /// Signed timestamps sent to clients (one per refresh).
+    pub timestamps: usize,
+}
+
+impl FreshnessStats {
+    /// All rejections of metadata that was valid by the true clock.
+    pub fn spurious_rejections(&self) -> usize {
+        self.spurious_expired + self.spurious_not_yet_valid
+    }
+
+    /// Bytes spent sending signed timestamps.
+    pub fn timestamp_bandwidth(&self) -> Information {
+        SignedTimestamp::fixed_size() * self.timestamps
+    }
+}
+
+/// Expiry bookkeeping for one repository and its clients.
+#[derive(Debug, Clone)]
+pub struct Freshness {
+    policy: FreshnessPolicy,
+    skew: ClockSkew,
+    /// Clients reject metadata signed longer ago than this.
+    max_staleness: Option<Duration>,
+    /// When the server's metadata last changed (if ever).
+    last_change: Option<OffsetDateTime>,
+    /// When the metadata each client holds was signed.
+    issued: HashMap<UserId, OffsetDateTime>,
+    stats: FreshnessStats,
+}
+
+impl Freshness {
+    pub fn new(policy: FreshnessPolicy, skew: ClockSkew) -> Self {
+        Self {
+            policy,
+            skew,
+            max_staleness: None,
+            last_change: None,
+            issued: HashMap::default(),
+            stats: FreshnessStats::default(),
+        }
+    }
+
+    /// Have clients also reject metadata signed more than `max` ago by their
+    /// own clocks, even if it hasn't expired.
+    pub fn with_max_staleness(mut self, max: Duration) -> Self {
+        assert!(!max.is_negative(), "max staleness must be non-negative");
+        self.max_staleness = Some(max);
+        self
+    }
+
+    pub fn stats(&self) -> &FreshnessStats {
+        &self.stats
+    }
+
+    /// The earliest signature `user` accepts at `now` (by its own clock), if
+    /// clients enforce a maximum staleness.
+    pub fn oldest_accepted(&self, user: &UserId, now: OffsetDateTime) -> Option<OffsetDateTime> {
+        let local = now + self.skew.for_user(user);
+        self.max_staleness.map(|max| local - max)
+    }
+
+    /// When the latest signature the server has at `now` was made.
+    fn latest_signature(&self, now: OffsetDateTime) -> OffsetDateTime {
+        let last_change = match self.last_change {
+            Some(last_change) if last_change <= now => last_change,
+            _ => return now,
+        };
+        let interval = self.policy.resign_interval.whole_nanoseconds();
+        if interval <= 0 {
+            return now;
+        }
+        let resigns = (now - last_change).whole_nanoseconds() / interval;
+        last_change + Duration::nanoseconds((resigns * interval) as i64)
+    }
+
+    fn check(&mut self, user: &UserId, issued: OffsetDateTime, now: OffsetDateTime) {
+        let validity = self.policy.validity;
+        let local = now + self.skew.for_user(user);
+        self.stats.checks += 1;
+        match (
+            Verdict::new(issued, validity, now),
+            Verdict::new(issued, validity, local),
+        ) {
+            (Verdict::Fresh, Verdict::Expired) => self.stats.spurious_expired += 1,
+            (Verdict::Fresh, Verdict::NotYetValid) => self.stats.spurious_not_yet_valid += 1,
+            (Verdict::Expired, _) => self.stats.expired += 1,
+            _ => {}
+        }
+        if matches!(self.max_staleness, Some(max) if local - issued > max) {
+            self.stats.stale += 1;
+        }
+    }
+
+    /// The server's metadata changed (and was re-signed) at `now`.
+    pub fn on_publish(&mut self, now: OffsetDateTime) {
+        self.last_change = Some(now);
+    }
+
+    /// `user` fetched (freshly signed) metadata at `now`.
+    ///
+    /// Returns the timestamp the server sent with it.
+    pub fn on_refresh(&mut self, user: &UserId, now: OffsetDateTime) -> SignedTimestamp {
+        let issued = self.latest_signature(now);
+        self.issued.insert(user.clone(), issued);
+        self.stats.timestamps += 1;
+        self.check(user, issued, now);
+        SignedTimestamp::new(issued)
+    }
+
+    /// `user` checked the metadata it holds before downloading at `now`.
+    ///
+    /// Returns the timestamp the server sent, if the client had to fetch
+    /// metadata first.
+    pub fn on_download(&mut self, user: &UserId, now: OffsetDateTime) -> Option<SignedTimestamp> {
+        let issued = match self.issued.get(user) {
+            Some(issued) => *issued,
+            // First contact: the client fetches metadata along with the file.
+            None => return Some(self.on_refresh(user, now)),
+        };
+        self.check(user, issued, now);
+        None
+    }
+
+    pub fn on_goodbye(&mut self, user: &UserId) {
+        self.issued.remove(user);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(name: &str) -> UserId {
+        UserId(name.to_string())
+    }
+
+    #[test]
+    fn skew_is_bounded_and_deterministic() {
+        let skew = ClockSkew::new(Duration::minutes(5));
+        for i in 0..100 {
+            let user = user(&format!("user{i}"));
+            let offset = skew.for_user(&user);
+            assert!(offset.abs() <= Duration::minutes(5));
+            assert_eq!(offset, skew.for_user(&user));
+        }
+        assert_eq!(
+            ClockSkew::new(Duration::ZERO).for_user(&user("a")),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn resigns_on_interval() {
+        let policy = FreshnessPolicy::tuf_timestamp();
+        let mut freshness = Freshness::new(policy, ClockSkew::new(Duration::ZERO));
+        let start = OffsetDateTime::UNIX_EPOCH;
+        freshness.on_publish(start);
+        assert_eq!(
+            freshness.latest_signature(start + Duration::hours(11)),
+            start
+        );
+        assert_eq!(
+            freshness.latest_signature(start + Duration::hours(13)),
+            start + Duration::hours(12)
+        );
+    }
+
+    #[test]
+    fn skewed_clients_reject_valid_metadata() {
+        let policy = FreshnessPolicy {
+            validity: Duration::hours(1),
+            resign_interval: Duration::hours(1),
+        };
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let now = start + Duration::minutes(59);
+
+        // No skew: the metadata signed at `start` is still good.
+        let mut freshness = Freshness::new(policy, ClockSkew::new(Duration::ZERO));
+        freshness.on_publish(start);
+        freshness.on_refresh(&user("a"), start);
+        freshness.on_download(&user("a"), now);
+        assert_eq!(freshness.stats().spurious_rejections(), 0);
+        assert_eq!(freshness.stats().checks, 2);
+
+        // With enough skew, some clients run far enough ahead to reject it.
+        let mut freshness = Freshness::new(policy, ClockSkew::new(Duration::minutes(30)));
+        freshness.on_publish(start);
+        for i in 0..100 {
+            let user = user(&format!("user{i}"));
+            freshness.on_refresh(&user, start);
+            freshness.on_download(&user, now);
+        }
+        let stats = freshness.stats();
+        assert_eq!(stats.expired, 0);
+        assert!(stats.spurious_expired > 0);
+        assert!(stats.spurious_not_yet_valid > 0);
+    }
+
+    #[test]
+    fn stale_clients_are_not_spurious() {
+        let policy = FreshnessPolicy {
+            validity: Duration::hours(1),
+            resign_interval: Duration::hours(1),
+        };
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let mut freshness = Freshness::new(policy, ClockSkew::new(Duration::minutes(5)));
+        freshness.on_refresh(&user("a"), start);
+        freshness.on_download(&user("a"), start + Duration::hours(2));
+        assert_eq!(freshness.stats().expired, 1);
+    }
+
+    #[test]
+    fn max_staleness() {
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let mut freshness = Freshness::new(
+            FreshnessPolicy::tuf_timestamp(),
+            ClockSkew::new(Duration::ZERO),
+        )
+        .with_max_staleness(Duration::hours(1));
+        freshness.on_publish(start);
+        let timestamp = freshness.on_refresh(&user("a"), start + Duration::minutes(5));
+        assert_eq!(timestamp.epoch(), 0);
+        assert_eq!(
+            freshness.on_download(&user("a"), start + Duration::minutes(30)),
+            None
+        );
+        assert_eq!(freshness.stats().stale, 0);
+
+        // Still valid (for a day), but older than the client will accept.
+        freshness.on_download(&user("a"), start + Duration::hours(2));
+        let stats = freshness.stats();
+        assert_eq!((stats.expired, stats.stale), (0, 1));
+        assert_eq!(stats.timestamps, 1);
+    }
+}
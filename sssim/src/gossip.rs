@@ -0,0 +1,193 @@
+//! Detecting split views by gossip.
+//!
+//! A server that forks its history (a targeted rollback, say) can show a set of
+//! victims digests that nobody else sees; each victim's append-only proofs
+//! still check out. If clients occasionally tell a few peers what digest they
+//! have, though, a peer on the other side of the fork sees a digest that its
+//! own server never published.
+//!
+//! [`Gossip`] runs this for one honest server, an optional fork serving some
+//! of the clients, and a population of clients that refresh and then each
+//! gossip to `fanout` random peers per round. It tracks the extra bandwidth and
+//! how many rounds after the fork the first client notices.
+//!
+//! Peers only check that the digest appears in their server's history, not
+//! where (a client that's behind is fine), which is all a split view needs.
+use rand::seq::index;
+use rand::Rng;
+use serde::Serialize;
+use uom::ConstZero;
+
+use crate::audit::Auditable;
+use crate::log::PackageId;
+use crate::util::{DataSized, Information};
+
+/// Totals for all gossip rounds so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct GossipStats {
+    pub rounds: usize,
+    /// Number of digests sent between peers.
+    pub messages: usize,
+    /// Bytes of digests sent between peers.
+    #[serde(rename = "gossip_bytes")]
+    pub bandwidth: Information,
+    /// Number of messages whose digest the recipient's server never published.
+    pub detections: usize,
+    /// Rounds between the fork and the first detection (if both happened).
+    pub detection_latency: Option<usize>,
+}
+
+impl Default for GossipStats {
+    fn default() -> Self {
+        Self {
+            rounds: 0,
+            messages: 0,
+            bandwidth: Information::ZERO,
+            detections: 0,
+            detection_latency: None,
+        }
+    }
+}
+
+struct Client<A: Auditable> {
+    snapshot: A::ClientSnapshot,
+    /// Whether this client talks to the fork (if there is one).
+    victim: bool,
+}
+
+/// Clients of one repository, gossiping their digests.
+pub struct Gossip<A: Auditable> {
+    server: A,
+    /// The server's forked history, shown only to victims.
+    fork: Option<A>,
+    /// Round in which the fork happened.
+    forked_at: Option<usize>,
+    clients: Vec<Client<A>>,
+    fanout: usize,
+    stats: GossipStats,
+}
+
+impl<A: Auditable> Gossip<A> {
+    /// Start `clients` clients at `server`'s current state, each gossiping to
+    /// `fanout` peers per round.
+    pub fn new(server: A, clients: usize, fanout: usize) -> Self {
+        assert!(fanout < clients, "need more clients than fanout");
+        let clients = (0..clients)
+            .map(|_| Client {
+                snapshot: server.get_metadata(),
+                victim: false,
+            })
+            .collect();
+        Self {
+            server,
+            fork: None,
+            forked_at: None,
+            clients,
+            fanout,
+            stats: GossipStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> &GossipStats {
+        &self.stats
+    }
+
+    /// Fork the history: from now on, the first `victims` clients talk to a
+    /// server that also published `package`.
+    pub fn fork(&mut self, victims: usize, package: PackageId)
+    where
+        A: Clone,
+    {
+        assert!(self.fork.is_none(), "already forked");
+        let mut fork = self.server.clone();
+        fork.publish(package);
+        self.fork = Some(fork);
+        self.forked_at = Some(self.stats.rounds);
+        for client in self.clients.iter_mut().take(victims) {
+            client.victim = true;
+        }
+    }
+
+    /// Publish `package` to every branch of the history.
+    pub fn publish(&mut self, package: PackageId) {
+        if let Some(fork) = &mut self.fork {
+            fork.publish(package.clone());
+        }
+        self.server.publish(package);
+    }
+
+    fn server_for(&self, victim: bool) -> &A {
+        match (&self.fork, victim) {
+            (Some(fork), true) => fork,
+            _ => &self.server,
+        }
+    }
+
+    /// Every client refreshes, then gossips its digest to `fanout` peers.
+    pub fn round<R: Rng>(&mut self, rng: &mut R) {
+        for i in 0..self.clients.len() {
+            let server = self.server_for(self.clients[i].victim);
+            let diff = server.refresh_metadata(A::id(&self.clients[i].snapshot));
+            if let Some(diff) = diff {
+                let snapshot = &mut self.clients[i].snapshot;
+                assert!(A::check_no_rollback(snapshot, &diff));
+                A::update(snapshot, diff);
+            }
+        }
+
+        self.stats.rounds += 1;
+        for sender in 0..self.clients.len() {
+            let digest = match A::digest(&self.clients[sender].snapshot) {
+                Some(digest) => digest,
+                None => continue,
+            };
+            // Pick `fanout` distinct peers other than the sender.
+            for peer in index::sample(rng, self.clients.len() - 1, self.fanout) {
+                let peer = if peer >= sender { peer + 1 } else { peer };
+                self.stats.messages += 1;
+                self.stats.bandwidth += digest.size();
+                let server = self.server_for(self.clients[peer].victim);
+                if server.position(&digest).is_none() {
+                    self.stats.detections += 1;
+                    if self.stats.detection_latency.is_none() {
+                        self.stats.detection_latency = self
+                            .forked_at
+                            .map(|forked_at| self.stats.rounds - forked_at);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "rsa"))]
+mod tests {
+    use super::*;
+    use crate::authenticator::Rsa;
+    use crate::Authenticator;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn detects_fork() {
+        let packages = vec![PackageId::from("a".to_string())];
+        let mut gossip = Gossip::new(Rsa::batch_import(packages), 20, 3);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        gossip.publish(PackageId::from("b".to_string()));
+        gossip.round(&mut rng);
+        assert_eq!(gossip.stats().detections, 0);
+        assert_eq!(gossip.stats().messages, 20 * 3);
+
+        gossip.fork(5, PackageId::from("evil".to_string()));
+        for i in 0..3 {
+            gossip.publish(PackageId::from(format!("new{i}")));
+            gossip.round(&mut rng);
+        }
+        let stats = gossip.stats();
+        assert_eq!(stats.rounds, 4);
+        assert_eq!(stats.messages, 4 * 20 * 3);
+        assert!(stats.detections > 0);
+        assert_eq!(stats.detection_latency, Some(1));
+        assert!(stats.bandwidth > Information::ZERO);
+    }
+}
@@ -0,0 +1,338 @@
+//! Registries that grow while we watch (`sssim growth`).
+//!
+//! A run's trials import a fixed set of packages and then publish a linear
+//! stream of new ones, but real registries keep growing (PyPI's package count has
+//! roughly doubled every few years), and some techniques' server state grows
+//! faster than the package count. A [`GrowthModel`] says when new packages
+//! arrive: at a steady rate, compounding, or when they did in some trace.
+//! [`grow`] mixes those arrivals into a trace as publishes of new packages, and
+//! [`storage_curve`] replays it against a technique, sampling its server state
+//! as it goes. A regular run does this as its `growth` phase when given a
+//! growth model; `sssim growth` does just this, for several techniques.
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+
+use crate::log::{Action, Entry, Log, Package, PackageId};
+use crate::util::{DataSized, Information};
+
+/// When new packages arrive, as `linear:<per day>` or `exponential:<daily
+/// rate>` (e.g. `exponential:0.001` for 0.1% a day), or taken from a trace
+/// (see [`GrowthModel::from_log`]).
+#[derive(Debug, Clone, PartialEq)]
+pub enum GrowthModel {
+    /// This many new packages a day.
+    Linear { per_day: f64 },
+    /// The registry grows by this fraction a day, compounding.
+    Exponential { rate: f64 },
+    /// New packages arrive this long after the start.
+    Trace { arrivals: Vec<Duration> },
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("invalid growth model {0:?}: expected `linear:<per day>` or `exponential:<daily rate>`")]
+pub struct InvalidGrowthModel(String);
+
+impl FromStr for GrowthModel {
+    type Err = InvalidGrowthModel;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidGrowthModel(s.to_string());
+        let (kind, value) = s.split_once(':').ok_or_else(invalid)?;
+        let value: f64 = value.trim().parse().map_err(|_| invalid())?;
+        if !value.is_finite() || value < 0.0 {
+            return Err(invalid());
+        }
+        match kind.trim() {
+            "linear" => Ok(Self::Linear { per_day: value }),
+            "exponential" => Ok(Self::Exponential { rate: value }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for GrowthModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Linear { per_day } => write!(f, "linear:{per_day}"),
+            Self::Exponential { rate } => write!(f, "exponential:{rate}"),
+            Self::Trace { .. } => write!(f, "trace"),
+        }
+    }
+}
+
+const SECONDS_PER_DAY: f64 = 24.0 * 60.0 * 60.0;
+
+impl GrowthModel {
+    /// New packages arriving when they first appear in `log`, relative to its
+    /// first entry. Packages published at that very first timestamp are its
+    /// initial import, not growth.
+    pub fn from_log(log: &Log) -> Self {
+        let start = match log.entries().first() {
+            Some(entry) => entry.timestamp,
+            None => return Self::Trace { arrivals: vec![] },
+        };
+        let mut seen = HashSet::new();
+        let mut arrivals = Vec::new();
+        for entry in log.entries() {
+            if let Action::Publish { package } = &entry.action {
+                if seen.insert(&package.id) && entry.timestamp > start {
+                    arrivals.push(entry.timestamp - start);
+                }
+            }
+        }
+        Self::Trace { arrivals }
+    }
+
+    /// When (after the start) new packages arrive within `duration`, for a
+    /// registry that starts with `initial` packages.
+    pub fn arrivals(&self, initial: usize, duration: Duration) -> Vec<Duration> {
+        let days = duration.as_seconds_f64() / SECONDS_PER_DAY;
+        // The time at which the `k`th new package arrives, in days.
+        let arrival: Box<dyn Fn(usize) -> f64> = match self {
+            Self::Trace { arrivals } => {
+                return arrivals
+                    .iter()
+                    .copied()
+                    .take_while(|&arrival| arrival <= duration)
+                    .collect();
+            }
+            Self::Linear { per_day } if *per_day > 0.0 => Box::new(move |k| k as f64 / per_day),
+            Self::Exponential { rate } if *rate > 0.0 => {
+                // An empty registry can't compound, so start from one.
+                let initial = initial.max(1) as f64;
+                let rate = rate.ln_1p();
+                Box::new(move |k| ((initial + k as f64) / initial).ln() / rate)
+            }
+            _ => return Vec::new(),
+        };
+        (1..)
+            .map(arrival)
+            .take_while(|&day| day <= days)
+            .map(|day| Duration::seconds_f64(day * SECONDS_PER_DAY))
+            .collect()
+    }
+}
+
+/// `entries`, with a publish of a new package (`grown_package<i>`) at each of
+/// `arrivals` after `start`, in timestamp order (trace entries first on ties).
+pub fn grow(entries: Vec<Entry>, start: OffsetDateTime, arrivals: &[Duration]) -> Log {
+    let mut grown = arrivals.iter().enumerate().map(|(i, &arrival)| {
+        let package = Package {
+            id: PackageId::from(format!("grown_package{i}")),
+            length: None,
+        };
+        Entry::new(start + arrival, Action::Publish { package })
+    });
+    let mut merged = Vec::with_capacity(entries.len() + arrivals.len());
+    let mut next_grown = grown.next();
+    for entry in entries {
+        while let Some(grown_entry) = next_grown.take() {
+            if grown_entry.timestamp < entry.timestamp {
+                merged.push(grown_entry);
+                next_grown = grown.next();
+            } else {
+                next_grown = Some(grown_entry);
+                break;
+            }
+        }
+        merged.push(entry);
+    }
+    merged.extend(next_grown);
+    merged.extend(grown);
+    Log::from(merged)
+}
+
+/// A technique's server state at one point in a replay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoragePoint {
+    /// Time since the start.
+    pub elapsed: Duration,
+    /// Distinct packages published so far (including the initial ones).
+    pub packages: usize,
+    pub storage: Information,
+}
+
+/// Replay the publishes in `log` against `server` (imported with `initial`),
+/// sampling its size every `interval` from `start` through `end`.
+///
+/// `publish` publishes one package to `server` (and, for techniques that
+/// batch, processes it). Downloads and refreshes don't change the server's
+/// state, so they're skipped.
+pub fn storage_curve<S: DataSized + ?Sized>(
+    server: &mut S,
+    mut publish: impl FnMut(&mut S, PackageId),
+    initial: &[PackageId],
+    log: &Log,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+    interval: Duration,
+) -> Vec<StoragePoint> {
+    assert!(interval.is_positive(), "sampling interval must be positive");
+    let mut packages: HashSet<PackageId> = initial.iter().cloned().collect();
+    let mut points = Vec::new();
+    let mut next_sample = start;
+    let mut sample_until = |now: OffsetDateTime,
+                            server: &S,
+                            packages: &HashSet<PackageId>,
+                            points: &mut Vec<StoragePoint>| {
+        while next_sample <= now && next_sample <= end {
+            points.push(StoragePoint {
+                elapsed: next_sample - start,
+                packages: packages.len(),
+                storage: server.size(),
+            });
+            next_sample += interval;
+        }
+    };
+    for entry in log.entries() {
+        if entry.timestamp > end {
+            break;
+        }
+        let package = match &entry.action {
+            Action::Publish { package } => package,
+            _ => continue,
+        };
+        // Sample what was there just before this publish.
+        if entry.timestamp > start {
+            let just_before = entry.timestamp - Duration::NANOSECOND;
+            sample_until(just_before, &*server, &packages, &mut points);
+        }
+        packages.insert(package.id.clone());
+        publish(server, package.id.clone());
+    }
+    sample_until(end, &*server, &packages, &mut points);
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::authenticator::Authenticators;
+    use crate::log::UserId;
+    use crate::testing::package_ids;
+    use crate::util::byte;
+
+    fn day(days: f64) -> Duration {
+        Duration::seconds_f64(days * SECONDS_PER_DAY)
+    }
+
+    #[test]
+    fn parse() {
+        for s in ["linear:10", "exponential:0.01"] {
+            assert_eq!(s.parse::<GrowthModel>().unwrap().to_string(), s);
+        }
+        for s in ["linear", "linear:-1", "quadratic:2", "exponential:x"] {
+            assert!(s.parse::<GrowthModel>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn arrivals() {
+        let linear = GrowthModel::Linear { per_day: 2.0 };
+        let arrivals = linear.arrivals(100, day(3.0));
+        assert_eq!(arrivals.len(), 6);
+        assert_eq!(arrivals[1], day(1.0));
+
+        // 100 packages growing 10% a day: 110 after one day, 121 after two.
+        let exponential = GrowthModel::Exponential { rate: 0.1 };
+        let arrivals = exponential.arrivals(100, day(2.0) + Duration::SECOND);
+        assert_eq!(arrivals.len(), 21);
+        assert!((arrivals[9] - day(1.0)).abs() < Duration::SECOND);
+
+        assert!(GrowthModel::Linear { per_day: 0.0 }
+            .arrivals(1, day(10.0))
+            .is_empty());
+    }
+
+    fn publish(id: &str, seconds: i64) -> Entry {
+        let package = Package {
+            id: PackageId::from(id),
+            length: None,
+        };
+        Entry::new(
+            OffsetDateTime::UNIX_EPOCH + Duration::seconds(seconds),
+            Action::Publish { package },
+        )
+    }
+
+    #[test]
+    fn from_trace() {
+        let log = Log::from(vec![
+            publish("initial", 0),
+            publish("a", 10),
+            publish("initial", 20),
+            publish("a", 30),
+            publish("b", 40),
+        ]);
+        let model = GrowthModel::from_log(&log);
+        assert_eq!(
+            model,
+            GrowthModel::Trace {
+                arrivals: vec![Duration::seconds(10), Duration::seconds(40)]
+            }
+        );
+        assert_eq!(
+            model.arrivals(0, Duration::seconds(39)),
+            vec![Duration::seconds(10)]
+        );
+    }
+
+    #[test]
+    fn interleaves() {
+        let refresh = Entry::new(
+            OffsetDateTime::UNIX_EPOCH + Duration::seconds(20),
+            Action::RefreshMetadata {
+                user: UserId::from("user".to_string()),
+            },
+        );
+        let entries = vec![publish("a", 10), refresh, publish("b", 30)];
+        let arrivals = [20, 40].map(Duration::seconds);
+        let log = grow(entries, OffsetDateTime::UNIX_EPOCH, &arrivals);
+        let order: Vec<_> = log
+            .entries()
+            .iter()
+            .map(|entry| match &entry.action {
+                Action::Publish { package } => package.id.as_str().to_string(),
+                _ => "refresh".to_string(),
+            })
+            .collect();
+        assert_eq!(
+            order,
+            ["a", "refresh", "grown_package0", "b", "grown_package1"]
+        );
+    }
+
+    #[test]
+    fn curve() {
+        let initial: Vec<_> = package_ids(10);
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let arrivals = GrowthModel::Linear { per_day: 24.0 }.arrivals(10, day(1.0));
+        let log = grow(vec![publish("package0", 90)], start, &arrivals);
+        let mut server = Authenticators::by_name("hackage")
+            .unwrap()
+            .import(initial.clone());
+        let points = storage_curve(
+            server.as_mut(),
+            |server, package| server.publish(package),
+            &initial,
+            &log,
+            start,
+            start + day(1.0),
+            Duration::hours(6),
+        );
+
+        let elapsed: Vec<_> = points.iter().map(|point| point.elapsed).collect();
+        assert_eq!(elapsed, [0, 6, 12, 18, 24].map(Duration::hours));
+        // One new package an hour, arriving on the hour (and counted then).
+        let packages: Vec<_> = points.iter().map(|point| point.packages).collect();
+        assert_eq!(packages, vec![10, 16, 22, 28, 34]);
+        assert!(points
+            .windows(2)
+            .all(|pair| pair[0].storage.get::<byte>() < pair[1].storage.get::<byte>()));
+    }
+}
@@ -2,7 +2,8 @@ use digest::{ExtendableOutput, Update, XofReader};
 use rug;
 use sha3::{Shake256, Shake256Reader};
 
-use crate::primitives::Prime;
+use crate::counters::{self, Op};
+use crate::primitives::{Domain, Prime};
 use thiserror::Error;
 
 pub struct IntegerHasher {
@@ -11,13 +12,14 @@ pub struct IntegerHasher {
 }
 
 impl IntegerHasher {
-    pub fn new(data: &[u8], digits: usize) -> Self {
+    pub fn new(domain: Domain, data: &[u8], digits: usize) -> Self {
         // Here, we use Shake256 which is an "extendable output function" (XOF).
         // This is basically a hash function that gives you as many bytes of output
         // as you want. We need a weird number of bytes which depends on `digits`,
         // *and* we may need to try many times in a row, so the XOF gives us as much
         // hash data as we need.
         let mut hasher = Shake256::default();
+        hasher.update(&domain.prefix());
         hasher.update(data);
         let reader = hasher.finalize_xof();
         let result: Vec<u8> = vec![0; digits];
@@ -25,6 +27,7 @@ impl IntegerHasher {
     }
 
     pub fn hash(&mut self) -> rug::Integer {
+        counters::record(Op::Hash);
         self.reader.read(&mut self.result);
         rug::Integer::from_digits(&self.result, rug::integer::Order::Lsf)
     }
@@ -36,12 +39,12 @@ pub enum HashToPrimeError {
     TooManyIters,
 }
 
-/// Hash the value of data to a 256-bit prime number.
-pub fn hash_to_prime(data: &[u8]) -> Result<Prime, HashToPrimeError> {
+/// Hash the value of data (in `domain`) to a 256-bit prime number.
+pub fn hash_to_prime(domain: Domain, data: &[u8]) -> Result<Prime, HashToPrimeError> {
     // We want a random number with a number of bits just greater than modulus
     // has. significant_digits gives us the right number of bytes.
     let digits: usize = 32;
-    let mut bar = IntegerHasher::new(data, digits);
+    let mut bar = IntegerHasher::new(domain, data, digits);
 
     // TODO(maybe): calculate how many times we should actually do this.
     // It appears to be between 10,000 and 100,000.
@@ -61,14 +64,25 @@ mod tests {
     proptest! {
         #[test]
         fn test_hash_to_prime(data: Vec<u8>) {
-            let result: rug::Integer = hash_to_prime(&data)?.into();
+            let result: rug::Integer = hash_to_prime(Domain::PackagePrime, &data)?.into();
             prop_assert!(result.significant_bits() <= 256);
         }
 
         #[test]
         fn test_hash_to_prime_unique(data1: Vec<u8>, data2: Vec<u8>) {
             prop_assume!(data1 != data2);
-            prop_assert_ne!(hash_to_prime(&data1)?, hash_to_prime(&data2)?);
+            prop_assert_ne!(
+                hash_to_prime(Domain::PackagePrime, &data1)?,
+                hash_to_prime(Domain::PackagePrime, &data2)?
+            );
+        }
+
+        #[test]
+        fn test_hash_to_prime_domains(data: Vec<u8>) {
+            prop_assert_ne!(
+                hash_to_prime(Domain::PackagePrime, &data)?,
+                hash_to_prime(Domain::PokeChallenge, &data)?
+            );
         }
     }
 }
@@ -0,0 +1,93 @@
+//! Plugging extra analyses into the [`Simulator`](crate::simulator::Simulator).
+//!
+//! Things like CDN modeling, attack injection, or monitoring only need to see
+//! what the simulator is doing; they don't need to change how it handles a
+//! request. Rather than patching them into `process()`, implement [`Hook`] and
+//! register it with
+//! [`Simulator::with_hook`](crate::simulator::Simulator::with_hook). Hooks run
+//! in the order they were registered.
+//!
+//! Hooks are owned by the simulator, so one that collects results should keep
+//! them somewhere shared (e.g. an `Rc<RefCell<_>>`) to read them afterwards.
+use std::fmt::Debug;
+
+use time::{Duration, OffsetDateTime};
+
+use crate::log::{Entry, UserId};
+use crate::simulator::ResourceUsage;
+
+/// Callbacks for simulator events; every method does nothing by default.
+pub trait Hook: Debug {
+    /// After a (timestamped) log entry has been processed.
+    fn on_event(&mut self, _entry: &Entry, _usage: &ResourceUsage) {}
+
+    /// When the simulator moves past epoch `epoch` (numbered from zero),
+    /// which ended at `end`.
+    ///
+    /// Only called if the simulator has an epoch length (see
+    /// [`Simulator::with_epochs`](crate::simulator::Simulator::with_epochs)).
+    fn on_epoch_end(&mut self, _epoch: usize, _end: OffsetDateTime) {}
+
+    /// The first time the simulator sees `user` (or the first time since their
+    /// `Goodbye`), before handling their request.
+    fn on_user_created(&mut self, _user: &UserId) {}
+}
+
+/// Splits time into fixed-length epochs, starting from the first timestamp.
+#[derive(Debug, Clone)]
+pub(crate) struct Epochs {
+    length: Duration,
+    /// The current epoch, and when it ends.
+    current: Option<(usize, OffsetDateTime)>,
+}
+
+impl Epochs {
+    pub(crate) fn new(length: Duration) -> Self {
+        assert!(length.is_positive(), "epochs must have positive length");
+        Self {
+            length,
+            current: None,
+        }
+    }
+
+    /// Move to the epoch containing `now`, returning the epochs that ended
+    /// (with their end times).
+    pub(crate) fn advance(&mut self, now: OffsetDateTime) -> Vec<(usize, OffsetDateTime)> {
+        let (mut epoch, mut end) = *self.current.get_or_insert((0, now + self.length));
+        let mut ended = Vec::new();
+        while now >= end {
+            ended.push((epoch, end));
+            epoch += 1;
+            end += self.length;
+        }
+        self.current = Some((epoch, end));
+        ended
+    }
+
+    /// End the current epoch (if any), e.g. at the end of the log.
+    pub(crate) fn finish(&mut self) -> Option<(usize, OffsetDateTime)> {
+        self.current.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epochs() {
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let hour = Duration::hours(1);
+        let mut epochs = Epochs::new(hour);
+        assert_eq!(epochs.advance(start), vec![]);
+        assert_eq!(epochs.advance(start + Duration::minutes(59)), vec![]);
+        assert_eq!(epochs.advance(start + hour), vec![(0, start + hour)]);
+        // Empty epochs still end.
+        assert_eq!(
+            epochs.advance(start + hour * 3),
+            vec![(1, start + hour * 2), (2, start + hour * 3)]
+        );
+        assert_eq!(epochs.finish(), Some((3, start + hour * 4)));
+        assert_eq!(epochs.finish(), None);
+    }
+}
@@ -0,0 +1,103 @@
+//! Stopping a long run early (Ctrl-C) without losing what it's measured.
+//!
+//! After [`install`], the first SIGINT (or SIGTERM, which is what Slurm sends
+//! when a job runs out of time) just sets a flag: the harness checks
+//! [`requested`] between phases, so the step in flight (and its inserts) can
+//! finish before it records the run as interrupted and exits with
+//! [`EXIT_CODE`]. A second one exits immediately.
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Exit status for a run stopped by an interrupt (what shells use for SIGINT).
+pub const EXIT_CODE: i32 = 130;
+
+#[cfg(any(unix, windows))]
+const MESSAGE: &[u8] = b"\ninterrupted: finishing the current step (interrupt again to quit now)\n";
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether we've been asked to stop.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Handle interrupts as above, rather than dying on the spot.
+pub fn install() {
+    imp::install();
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::sync::atomic::Ordering;
+
+    use super::{EXIT_CODE, MESSAGE, REQUESTED};
+
+    extern "C" fn handle(_signal: libc::c_int) {
+        // Only async-signal-safe calls in here.
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            // SAFETY: `_exit` is async-signal-safe (unlike `exit`).
+            unsafe { libc::_exit(EXIT_CODE) };
+        }
+        // SAFETY: `write` is async-signal-safe, and `MESSAGE` is valid for its
+        // length. If it fails, there's nothing to do about it.
+        unsafe { libc::write(libc::STDERR_FILENO, MESSAGE.as_ptr().cast(), MESSAGE.len()) };
+    }
+
+    pub fn install() {
+        let handler = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        for signal in [libc::SIGINT, libc::SIGTERM] {
+            // SAFETY: `handle` has the signature `signal` expects, and only
+            // does async-signal-safe things.
+            unsafe { libc::signal(signal, handler) };
+        }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::io::Write;
+    use std::sync::atomic::Ordering;
+
+    use windows_sys::Win32::Foundation::BOOL;
+    use windows_sys::Win32::System::Console::{
+        SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_C_EVENT,
+    };
+
+    use super::{EXIT_CODE, MESSAGE, REQUESTED};
+
+    // Windows runs this on a thread of its own, so it can do what it likes.
+    unsafe extern "system" fn handle(event: u32) -> BOOL {
+        if event != CTRL_C_EVENT && event != CTRL_BREAK_EVENT {
+            return 0;
+        }
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(EXIT_CODE);
+        }
+        let _ = std::io::stderr().write_all(MESSAGE);
+        1
+    }
+
+    pub fn install() {
+        // SAFETY: `handle` has the signature Windows expects.
+        unsafe { SetConsoleCtrlHandler(Some(handle), 1) };
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    pub fn install() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn first_interrupt_sets_flag() {
+        install();
+        assert!(!requested());
+        // SAFETY: we've installed a handler, so this doesn't kill the tests.
+        unsafe { libc::raise(libc::SIGINT) };
+        assert!(requested());
+    }
+}
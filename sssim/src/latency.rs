@@ -0,0 +1,262 @@
+//! Latency capture for protocol operations.
+//!
+//! Mean-only summaries hide tail effects (e.g. cache invalidation right after a
+//! publish), so we keep a full [HDR histogram] per operation.
+//!
+//! Time operations with [`time_fn`], which subtracts the cost of reading the
+//! clock. Do any setup (cloning inputs, computing snapshot IDs, bookkeeping)
+//! *outside* the closure, so that only the protocol operation is measured.
+//!
+//! [HDR histogram]: http://hdrhistogram.org/
+use std::collections::HashMap;
+use std::time::Instant;
+
+use hdrhistogram::Histogram;
+use once_cell::sync::Lazy;
+use rusqlite::Connection;
+use serde::Serialize;
+use time::Duration;
+
+use crate::sink::{Record, RowBuffer};
+
+/// Significant figures kept by each histogram.
+const SIGFIG: u8 = 3;
+
+/// Number of empty measurements to take when calibrating.
+const CALIBRATION_ROUNDS: usize = 1001;
+
+/// Median time to time an empty closure.
+static TIMER_OVERHEAD: Lazy<Duration> = Lazy::new(|| {
+    let mut samples: Vec<Duration> = (0..CALIBRATION_ROUNDS).map(|_| measure(|| ()).0).collect();
+    samples.sort();
+    samples[CALIBRATION_ROUNDS / 2]
+});
+
+fn measure<T>(f: impl FnOnce() -> T) -> (Duration, T) {
+    let start = Instant::now();
+    let value = f();
+    let elapsed = start.elapsed();
+    (elapsed.try_into().expect("elapsed time fits"), value)
+}
+
+/// How long it takes just to read the clock around an operation.
+pub fn timer_overhead() -> Duration {
+    *TIMER_OVERHEAD
+}
+
+/// Time `f`, excluding the overhead of the measurement itself.
+pub fn time_fn<T>(f: impl FnOnce() -> T) -> (Duration, T) {
+    // Calibrate (once) before starting the clock.
+    let overhead = timer_overhead();
+    let (elapsed, value) = measure(f);
+    (std::cmp::max(elapsed - overhead, Duration::ZERO), value)
+}
+
+/// An empty histogram at our precision.
+pub fn new_histogram() -> Histogram<u64> {
+    Histogram::new(SIGFIG).expect("valid sigfig")
+}
+
+/// An operation whose latency we track (server-side, unless noted).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum Operation {
+    RequestFile,
+    Refresh,
+    Publish,
+    /// Client-side: checking the proof that came with a download.
+    VerifyMembership,
+}
+
+impl Operation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::RequestFile => "request_file",
+            Operation::Refresh => "refresh",
+            Operation::Publish => "publish",
+            Operation::VerifyMembership => "verify_membership",
+        }
+    }
+}
+
+/// One latency histogram (in nanoseconds) per [`Operation`].
+#[derive(Debug, Clone, Default)]
+pub struct Latencies {
+    inner: HashMap<Operation, Histogram<u64>>,
+}
+
+impl Latencies {
+    pub fn record(&mut self, operation: Operation, latency: Duration) {
+        let nanos: u64 = latency.whole_nanoseconds().try_into().unwrap_or(0);
+        self.inner
+            .entry(operation)
+            .or_insert_with(new_histogram)
+            .saturating_record(nanos);
+    }
+
+    /// Add everything `other` recorded (e.g. from another thread).
+    pub fn merge(&mut self, other: Latencies) {
+        for (operation, histogram) in other.inner {
+            self.inner
+                .entry(operation)
+                .or_insert_with(new_histogram)
+                .add(histogram)
+                .expect("histograms at the same precision");
+        }
+    }
+
+    pub fn get(&self, operation: Operation) -> Option<&Histogram<u64>> {
+        self.inner.get(&operation)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Operation, &Histogram<u64>)> {
+        self.inner.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+}
+
+/// Summary of a latency histogram, in nanoseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Percentiles {
+    pub count: u64,
+    pub p50_ns: u64,
+    pub p90_ns: u64,
+    pub p99_ns: u64,
+    pub p999_ns: u64,
+    pub max_ns: u64,
+}
+
+impl From<&Histogram<u64>> for Percentiles {
+    fn from(histogram: &Histogram<u64>) -> Self {
+        Self {
+            count: histogram.len(),
+            p50_ns: histogram.value_at_quantile(0.50),
+            p90_ns: histogram.value_at_quantile(0.90),
+            p99_ns: histogram.value_at_quantile(0.99),
+            p999_ns: histogram.value_at_quantile(0.999),
+            max_ns: histogram.max(),
+        }
+    }
+}
+
+/// Latency percentiles for one operation over one phase of a run (a row of
+/// `latency_results`).
+#[derive(Debug, Clone)]
+pub struct LatencyResult {
+    pub packages: usize,
+    pub phase: &'static str,
+    pub operation: Operation,
+    pub latency: Percentiles,
+    pub cores: usize,
+}
+
+impl LatencyResult {
+    /// One row per operation recorded in `latencies`.
+    pub fn all(
+        phase: &'static str,
+        latencies: &Latencies,
+        packages: usize,
+        cores: usize,
+    ) -> Vec<Self> {
+        latencies
+            .iter()
+            .map(|(operation, histogram)| Self {
+                packages,
+                phase,
+                operation: *operation,
+                latency: histogram.into(),
+                cores,
+            })
+            .collect()
+    }
+}
+
+impl Record for LatencyResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
+        rows.insert(
+            db,
+            "
+        INSERT INTO latency_results (
+            technique,
+            packages,
+            phase,
+            operation,
+            count,
+            p50_ns,
+            p90_ns,
+            p99_ns,
+            p999_ns,
+            max_ns,
+            cores
+        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11 ) ",
+            rusqlite::params![
+                technique,
+                self.packages,
+                self.phase,
+                self.operation.name(),
+                self.latency.count,
+                self.latency.p50_ns,
+                self.latency.p90_ns,
+                self.latency.p99_ns,
+                self.latency.p999_ns,
+                self.latency.max_ns,
+                self.cores
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let mut latencies = Latencies::default();
+        for ns in 1..=1000 {
+            latencies.record(Operation::Publish, Duration::nanoseconds(ns));
+        }
+        let percentiles = Percentiles::from(latencies.get(Operation::Publish).unwrap());
+        assert_eq!(percentiles.count, 1000);
+        assert_eq!(percentiles.p50_ns, 500);
+        assert_eq!(percentiles.max_ns, 1000);
+        assert!(latencies.get(Operation::Refresh).is_none());
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut latencies = Latencies::default();
+        latencies.record(Operation::Publish, Duration::nanoseconds(10));
+        let mut other = Latencies::default();
+        other.record(Operation::Publish, Duration::nanoseconds(20));
+        other.record(Operation::RequestFile, Duration::nanoseconds(30));
+        latencies.merge(other);
+        let publish = Percentiles::from(latencies.get(Operation::Publish).unwrap());
+        assert_eq!((publish.count, publish.max_ns), (2, 20));
+        assert_eq!(latencies.get(Operation::RequestFile).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_calibration() {
+        assert!(timer_overhead() < Duration::milliseconds(1));
+
+        // Timing nothing should take (about) no time.
+        let mut latencies = Latencies::default();
+        for _ in 0..CALIBRATION_ROUNDS {
+            latencies.record(Operation::Publish, time_fn(|| ()).0);
+        }
+        let percentiles = Percentiles::from(latencies.get(Operation::Publish).unwrap());
+        assert!(percentiles.p50_ns < 1_000, "{percentiles:?}");
+
+        // ...and we shouldn't lose anything from real work.
+        let (elapsed, _) = time_fn(|| std::thread::sleep(std::time::Duration::from_millis(5)));
+        assert!(elapsed >= Duration::milliseconds(5) - timer_overhead());
+    }
+}
@@ -1,19 +1,60 @@
-#![feature(
-    nonzero_ops,
-    iter_array_chunks,
-    trait_alias,
-    type_changing_struct_update
-)]
 #![allow(dead_code)]
+#[cfg(feature = "rsa")]
 pub mod accumulator;
+pub mod audit;
 pub mod authenticator;
+#[cfg(feature = "merkle")]
 mod bit_twiddling;
+pub mod cdn;
+pub mod claims;
+pub mod clock;
+pub mod config;
+pub mod cost;
+pub mod counters;
+pub mod ct;
+pub mod dedup;
+pub mod device;
+#[cfg(feature = "zstd")]
+pub mod dictionary;
+pub mod edge;
+pub mod energy;
+pub mod estimate;
+pub mod fault;
+pub mod freshness;
+pub mod gossip;
+pub mod growth;
+#[cfg(feature = "rsa")]
 pub mod hash_to_prime;
+pub mod hooks;
+pub mod interrupt;
+pub mod latency;
 pub mod log;
+pub mod migration;
 pub mod multiset;
+pub mod persistence;
+#[cfg(feature = "rsa")]
 mod poke;
+pub mod prelude;
 pub mod primitives;
+pub mod publish_queue;
+pub mod push;
+pub mod report;
+pub mod republish;
+pub mod schema;
+pub mod security;
+pub mod shadow;
 pub mod simulator;
+pub mod sink;
+pub mod soak;
+pub mod staleness;
+pub mod sweep;
+pub mod sysmetrics;
+pub mod targets;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod tune;
 pub mod util;
+pub mod warm_state;
+pub mod workload;
 
 pub use authenticator::{Authenticator, BatchAuthenticator, PoolAuthenticator};
@@ -8,13 +8,19 @@
 //!
 //! The TUF concepts are a little different. It's up to the Repository
 //! Simulator to translate between them.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
 use serde::Deserialize;
 use serde::Serialize;
+use thiserror::Error;
 use time::serde::format_description;
 use time::OffsetDateTime;
 
 #[cfg(test)]
-use proptest_derive::Arbitrary;
+use {proptest::prelude::*, proptest_derive::Arbitrary};
 
 use crate::util::{byte, FixedDataSized, Information};
 
@@ -36,16 +42,64 @@ impl From<String> for UserId {
     }
 }
 
-#[cfg_attr(test, derive(Arbitrary))]
-#[derive(Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq)]
-pub struct PackageId(pub String);
+/// Package names get cloned everywhere (logs, pools, snapshots, proofs), so
+/// they're shared rather than copied. Serializes as a plain string.
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct PackageId(pub Arc<str>);
+
+/// Prefix of [`PackageId::canonical_bytes`]; bump the version if the encoding
+/// ever changes.
+const PACKAGE_ID_ENCODING: &[u8] = b"sssim/package-id/v1";
+
+impl PackageId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The bytes that get hashed wherever a package needs a fixed position
+    /// (its prime in the RSA accumulators, its index in the Merkle trees).
+    ///
+    /// Unlike the serde encoding, this is fixed: `PACKAGE_ID_ENCODING`, the
+    /// length of the name as a little-endian `u64`, then the name in UTF-8.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let name = self.0.as_bytes();
+        let mut bytes = Vec::with_capacity(PACKAGE_ID_ENCODING.len() + 8 + name.len());
+        bytes.extend_from_slice(PACKAGE_ID_ENCODING);
+        bytes.extend_from_slice(&u64::try_from(name.len()).unwrap().to_le_bytes());
+        bytes.extend_from_slice(name);
+        bytes
+    }
+}
+
+impl Serialize for PackageId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
 
+impl<'de> Deserialize<'de> for PackageId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(PackageId::from)
+    }
+}
+
+#[cfg(test)]
+impl Arbitrary for PackageId {
+    type Strategy = BoxedStrategy<PackageId>;
+    type Parameters = ();
+
+    fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+        any::<String>().prop_map(PackageId::from).boxed()
+    }
+}
+
+#[cfg(feature = "merkle")]
 impl digest_hash::Hash for PackageId {
     fn hash<H>(&self, digest: &mut H)
     where
         H: digest_hash::EndianUpdate,
     {
-        for c in self.0.as_bytes() {
+        for c in self.canonical_bytes() {
             c.hash(digest);
         }
     }
@@ -60,13 +114,19 @@ impl FixedDataSized for PackageId {
 
 impl From<PackageId> for String {
     fn from(id: PackageId) -> String {
-        id.0
+        id.0.as_ref().to_owned()
     }
 }
 
 impl From<String> for PackageId {
     fn from(id: String) -> Self {
-        PackageId(id)
+        PackageId(id.into())
+    }
+}
+
+impl From<&str> for PackageId {
+    fn from(id: &str) -> Self {
+        PackageId(id.into())
     }
 }
 
@@ -108,6 +168,46 @@ impl Entry {
 #[derive(Debug)]
 pub struct Log(Vec<Entry>);
 
+#[derive(Error, Debug)]
+pub enum LogError {
+    #[error("reading log: {0}")]
+    Io(#[from] io::Error),
+    #[error("parsing log, line {line}: {source}")]
+    Json {
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("log out of order at line {line}")]
+    Unsorted { line: usize },
+}
+
+impl Log {
+    /// Read a trace: one JSON [`Entry`] per line, in timestamp order (blank
+    /// lines are skipped).
+    pub fn load(path: &Path) -> Result<Self, LogError> {
+        let mut entries: Vec<Entry> = Vec::new();
+        for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: Entry = serde_json::from_str(&line).map_err(|source| LogError::Json {
+                line: index + 1,
+                source,
+            })?;
+            if matches!(entries.last(), Some(last) if entry.timestamp < last.timestamp) {
+                return Err(LogError::Unsorted { line: index + 1 });
+            }
+            entries.push(entry);
+        }
+        Ok(Self(entries))
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.0
+    }
+}
+
 impl From<Vec<Entry>> for Log {
     fn from(entries: Vec<Entry>) -> Self {
         // Log entries must be in sorted order by timestamp.
@@ -131,3 +231,62 @@ impl IntoIterator for Log {
         self.0.into_iter()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_bytes_golden() {
+        // Changing these changes every prime and Merkle index.
+        assert_eq!(
+            PackageId::from("libc").canonical_bytes(),
+            b"sssim/package-id/v1\x04\0\0\0\0\0\0\0libc"
+        );
+        assert_eq!(
+            PackageId::from("").canonical_bytes(),
+            b"sssim/package-id/v1\0\0\0\0\0\0\0\0"
+        );
+    }
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join(format!("sssim-log-{}.jsonl", std::process::id()));
+        let refresh = r#"{"action": {"RefreshMetadata": {"user": "1"}}, "timestamp": "1970-01-01 00:00:01.0 +0000"}"#;
+        let publish = r#"{"action": {"Publish": {"package": {"id": "libc"}}}, "timestamp": "1970-01-01 00:00:02.0 +0000"}"#;
+
+        std::fs::write(&path, format!("{refresh}\n\n{publish}\n")).unwrap();
+        let log = Log::load(&path).unwrap();
+        assert_eq!(log.entries().len(), 2);
+        assert!(matches!(log.entries()[1].action, Action::Publish { .. }));
+
+        std::fs::write(&path, format!("{publish}\n{refresh}\n")).unwrap();
+        assert!(matches!(
+            Log::load(&path),
+            Err(LogError::Unsorted { line: 2 })
+        ));
+        std::fs::write(&path, "{}\n").unwrap();
+        assert!(matches!(
+            Log::load(&path),
+            Err(LogError::Json { line: 1, .. })
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    proptest! {
+        #[test]
+        fn test_canonical_bytes_unique(a: PackageId, b: PackageId) {
+            prop_assume!(a != b);
+            prop_assert_ne!(a.canonical_bytes(), b.canonical_bytes());
+        }
+
+        #[test]
+        fn test_package_id_serializes_as_string(name: String) {
+            let id = PackageId::from(name.clone());
+            // Saved logs and warm state store IDs as plain strings.
+            prop_assert_eq!(bincode::serialize(&id).unwrap(), bincode::serialize(&name).unwrap());
+            let roundtrip: PackageId = bincode::deserialize(&bincode::serialize(&id).unwrap()).unwrap();
+            prop_assert_eq!(roundtrip, id);
+        }
+    }
+}
@@ -1,51 +1,695 @@
 #![cfg_attr(feature = "strict", deny(warnings))]
 use std::collections::VecDeque;
 use std::fmt::Debug;
-use std::io;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use time::Duration;
 
-use clap::Parser;
-use rusqlite::Connection;
+use clap::{Parser, Subcommand};
+use rusqlite::{Connection, OptionalExtension};
 use uom::si::information::byte;
 
-use sssim::authenticator::Authenticator;
-use sssim::log::PackageId;
-use sssim::util::{DataSized, Information};
-use sssim::{authenticator, PoolAuthenticator};
+use sssim::authenticator;
+use sssim::authenticator::{Authenticator, Targets, Technique, Visitor};
+use sssim::cdn::{CdnCache, PurgeStats};
+use sssim::config::{Config, TechniqueConfig};
+use sssim::cost::{Cost, CostConfig, OperationUsage, Usage};
+use sssim::counters::{self, OpCounts};
+use sssim::device;
+use sssim::edge::{EdgeModel, EdgeTally, Placement};
+use sssim::energy::EnergyModel;
+use sssim::estimate::{Growth, Model, ServerScaling};
+use sssim::fault::{self, Outcome};
+use sssim::growth::{self, GrowthModel, StoragePoint};
+use sssim::interrupt;
+use sssim::latency::{time_fn, Latencies, LatencyResult, Operation, Percentiles};
+use sssim::log::{Entry, Log, PackageId};
+#[cfg(feature = "rsa")]
+use sssim::primitives::GroupParameters;
+use sssim::primitives::Srs;
+use sssim::push::Broadcast;
+use sssim::republish::{RepublishPolicy, Republishes};
+use sssim::schema::{Header, SCHEMA_VERSION};
+use sssim::security::{Parameters, SecurityLevel, SizeTable, TableFormat};
+use sssim::sink::{self, Record, ResultsSink, RowBuffer, SqliteSink};
+use sssim::soak;
+use sssim::staleness::RefreshIntervals;
+use sssim::sweep::{self, JobFormat, RunStatus};
+use sssim::sysmetrics;
+use sssim::targets::TargetsModel;
+use sssim::tune;
+use sssim::util::{DataSized, Information, SizingPolicy};
+use sssim::warm_state::WarmState;
+use sssim::workload::Popularity;
+use sssim::PoolAuthenticator;
 
 use indicatif::ProgressBar;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about)]
+#[clap(subcommand_negates_reqs = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
     /// The number of packages to simulate.
-    #[clap(long)]
-    packages: usize,
+    #[clap(long, required = true)]
+    packages: Option<usize>,
     /// Which authenticators to run (comma-separated)?
     #[clap(long)]
     authenticators: Option<String>,
     /// Path to the database to use for results (sqlite3 format).
+    #[clap(long, required_unless_present = "dry-run")]
+    results: Option<PathBuf>,
+    /// Also print each results row, as it's recorded, as a line of JSON on
+    /// stdout (mixed in with the progress messages, which aren't JSON).
     #[clap(long)]
-    results: PathBuf,
+    tee_json: bool,
+    /// RSA group parameters to use instead of RSA-2048 (see `gen-modulus`).
+    #[cfg(feature = "rsa")]
+    #[clap(long)]
+    group_params: Option<PathBuf>,
+    /// Structured reference string for pairing-based schemes (see
+    /// `sssim::primitives::srs`), with at least one power per package. Its
+    /// size and load time go in `precompute_results`. Only for techniques
+    /// that use one.
+    #[clap(long)]
+    srs: Option<PathBuf>,
     /// Number of threads
     #[clap(long, default_value = "1")]
     threads: usize,
+    /// Instead of the per-operation trials, drive each authenticator with a
+    /// sustained mixed load for this many seconds.
+    #[clap(long)]
+    throughput: Option<u64>,
+    /// In throughput mode, the fraction of operations that are publishes (the
+    /// rest are downloads).
+    #[clap(long, default_value = "0.1")]
+    publish_fraction: f64,
+    /// In throughput mode, the fraction of publishes that re-publish an
+    /// earlier package unchanged.
+    #[clap(long, default_value = "0.0")]
+    republish_fraction: f64,
+    /// Whether unchanged re-publishes count as new revisions (`count`) or are
+    /// dropped (`dedupe`).
+    #[clap(long, default_value = "count")]
+    republish_policy: RepublishPolicy,
+    /// How to size the RSA pool's diffs: by hand-written math (`manual`) or by
+    /// their encoding (`serialized`). Comparing a run of each (into separate
+    /// results dbs) cross-checks the hand-written sizes.
+    #[clap(long, default_value = "manual")]
+    sizing_policy: SizingPolicy,
+    /// For techniques whose server-side phases can't finish at this many
+    /// packages, run them on a random sample instead and record estimates
+    /// scaled up by each technique's growth (see `extrapolated_results`).
+    #[clap(long)]
+    scale_mode: bool,
+    /// In scale mode, the number of packages to sample.
+    #[clap(long, default_value = "1000")]
+    scale_sample: usize,
+    #[clap(flatten)]
+    growth: RegistryGrowthArgs,
+    /// Sample downloads from a Zipf distribution with this exponent (package 0
+    /// most popular) rather than uniformly.
+    #[clap(long)]
+    download_zipf: Option<f64>,
+    /// After the trials, replay the publish log against a freshly imported
+    /// authenticator and check that it ends up in the same state.
+    #[clap(long)]
+    verify_final_state: bool,
+    /// Estimate monthly dollar costs with the prices and workload in this JSON
+    /// file (see `sssim::cost::CostConfig`).
+    #[clap(long)]
+    cost_model: Option<PathBuf>,
+    /// Estimate client energy use with the per-operation constants in this JSON
+    /// file (see `sssim::energy::EnergyModel`; needs the `counters` feature).
+    #[clap(long)]
+    energy_model: Option<PathBuf>,
+    /// Size each download's targets metadata with the figures in this JSON
+    /// file (see `sssim::targets::TargetsModel`).
+    #[clap(long)]
+    targets_model: Option<PathBuf>,
+    /// Refresh after numbers of missed releases drawn from this JSON file
+    /// (from `refresh-intervals`), rather than 100, 200, ..., 1000.
+    #[clap(long)]
+    refresh_intervals: Option<PathBuf>,
+    /// Also report client compute times scaled for the devices in this JSON
+    /// file, on top of the built-in ones (see `sssim::device`).
+    #[clap(long)]
+    device_profiles: Option<PathBuf>,
+    /// Also simulate pushing per-epoch deltas to this many subscribers (vs.
+    /// each of them polling).
+    #[clap(long)]
+    subscribers: Option<usize>,
+    /// Also time publishing batches of these sizes in one go (comma-separated).
+    #[clap(long, value_delimiter = ',')]
+    publish_batches: Vec<u16>,
+    /// Also count the CDN purges and re-seeding caused by this many publishes
+    /// (one row per publish).
+    #[clap(long)]
+    cdn_publishes: Option<usize>,
+    /// Also flip this many random bits of the server's saved state, one at a
+    /// time, and record what catches each (see `sssim::fault`).
+    #[clap(long)]
+    fault_trials: Option<usize>,
+    /// Also check which responses a CDN edge could assemble itself within this
+    /// much CPU time per request (see `sssim::edge`).
+    #[clap(long)]
+    edge_budget_us: Option<u64>,
+    /// Time for the edge to read one cached object, for `--edge-budget-us`
+    /// (default 100).
+    #[clap(long, requires = "edge-budget-us")]
+    edge_read_us: Option<u64>,
+    /// Rather than running anything, print rough runtime and memory estimates
+    /// for each authenticator.
+    #[clap(long)]
+    dry_run: bool,
+    /// Per-technique settings, as `[technique.<name>]` sections of this TOML
+    /// file (see `sssim::config`).
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// Tag every results row with this run ID (see `sweep`).
+    #[clap(long, hide = true)]
+    run_id: Option<i64>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Generate a fresh RSA modulus (discarding the factors) for `--group-params`.
+    #[cfg(feature = "rsa")]
+    GenModulus {
+        /// Size of the modulus, in bits (e.g. 2048 or 3072).
+        #[clap(long, default_value = "2048")]
+        #[clap(value_parser = clap::value_parser!(u32).range(1024..))]
+        bits: u32,
+        /// Where to write the group parameters (JSON).
+        #[clap(long)]
+        output: PathBuf,
+    },
+    /// Run every combination of the given settings, one process each, into
+    /// one results db.
+    Sweep(SweepArgs),
+    /// Copy the results from other dbs (e.g. a Slurm sweep's per-job dbs) into
+    /// one.
+    MergeResults {
+        /// The db to merge into (for a sweep, its `--results`).
+        #[clap(long)]
+        output: PathBuf,
+        /// The dbs to merge.
+        #[clap(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Tally how many releases each user missed between refreshes in a trace,
+    /// for `--refresh-intervals` (see `sssim::staleness`).
+    RefreshIntervals {
+        /// The trace (JSON lines of log entries).
+        #[clap(long)]
+        trace: PathBuf,
+        /// Where to write the intervals (JSON).
+        #[clap(long)]
+        output: PathBuf,
+    },
+    /// Check the claims in a TOML file against a results db, failing if any
+    /// don't hold (see `sssim::claims`).
+    CheckClaims {
+        /// The results db to check.
+        #[clap(long)]
+        results: PathBuf,
+        /// The claims to check.
+        #[clap(long)]
+        claims: PathBuf,
+    },
+    /// Run a mixed workload against one authenticator for a long time,
+    /// checking its internal invariants as it goes (see `sssim::soak`).
+    Soak(SoakArgs),
+    /// Try every combination of a technique's settings on a small workload,
+    /// and print the one with the smallest P95 refreshes (see `sssim::tune`).
+    Tune(TuneArgs),
+    /// Train a zstd dictionary over each technique's earlier diffs, and report
+    /// how much it shrinks later ones (see `sssim::dictionary`).
+    #[cfg(feature = "zstd")]
+    Dictionary(DictionaryArgs),
+    /// Report how each technique's refresh bandwidth grows with the rate at
+    /// which returning users have lost their state (see
+    /// `sssim::persistence`).
+    StateLoss(StateLossArgs),
+    /// Grow the registry while replaying publishes, recording each technique's
+    /// server storage over time (see `sssim::growth`).
+    Growth(GrowthArgs),
+    /// Work out proof and diff sizes at each security level, without running
+    /// anything (see `sssim::security`).
+    SecurityTable(SecurityTableArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct SweepArgs {
+    /// Package counts to try (comma-separated; e.g. `1e3,1e4,1e5`).
+    #[clap(long, value_delimiter = ',', required = true, value_parser = sweep::parse_count)]
+    packages: Vec<usize>,
+    /// Authenticators to try (comma-separated; by default, the same ones as a
+    /// single run).
+    #[clap(long, value_delimiter = ',')]
+    authenticators: Vec<String>,
+    /// Thread counts to try (comma-separated).
+    #[clap(long, value_delimiter = ',', default_value = "1")]
+    threads: Vec<usize>,
+    /// Path to the database to use for results (sqlite3 format).
+    #[clap(long)]
+    results: PathBuf,
+    /// Rather than running anything, write one command line per run to this
+    /// file (e.g. for a cluster job array).
+    #[clap(long)]
+    job_file: Option<PathBuf>,
+    /// What to write along with `--job-file`: just the command lines
+    /// (`lines`), or also an sbatch script, with a db per run to combine with
+    /// `merge-results` afterwards (`slurm`).
+    #[clap(long, requires = "job-file")]
+    emit_jobs: Option<JobFormat>,
+    /// Rather than running anything, print rough runtime and memory estimates
+    /// for each run.
+    #[clap(long)]
+    dry_run: bool,
+    /// Per-technique settings for every run (see `sssim::config`); each run's
+    /// section is recorded in the `runs` table.
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// Options for every run (after `--`; e.g. `-- --scale-mode`).
+    #[clap(last = true)]
+    extra: Vec<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct SoakArgs {
+    /// The authenticator to soak.
+    #[clap(long)]
+    authenticator: String,
+    /// The number of packages to start with.
+    #[clap(long, default_value = "1000")]
+    packages: usize,
+    /// How long to run, in seconds.
+    #[clap(long, default_value = "3600")]
+    seconds: u64,
+    /// Stop after this many operations, if that comes first.
+    #[clap(long)]
+    operations: Option<u64>,
+    /// Relative weights of publishes, refreshes, and downloads.
+    #[clap(long, default_value = "1:10:20")]
+    mix: soak::Mix,
+    /// Number of clients.
+    #[clap(long, default_value = "16")]
+    clients: usize,
+    /// Probability that a publish is of a new package.
+    #[clap(long, default_value = "0.1")]
+    new_package_fraction: f64,
+    /// Publishes between batches (for pool authenticators).
+    #[clap(long, default_value = "1")]
+    batch: usize,
+    /// Operations between invariant checks.
+    #[clap(long, default_value = "10000")]
+    check_every: u64,
+    /// Seed for the workload (print on failure, to replay it).
+    #[clap(long, default_value = "0")]
+    seed: u64,
+    /// Per-technique settings (see `sssim::config`).
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// RSA group parameters to use instead of RSA-2048 (see `gen-modulus`).
+    #[cfg(feature = "rsa")]
+    #[clap(long)]
+    group_params: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct TuneArgs {
+    /// The authenticator to tune.
+    #[clap(long)]
+    authenticator: String,
+    /// A setting and the values to try (e.g. `checkpoint_interval=0,100,1000`;
+    /// repeat for more settings).
+    #[clap(long = "knob")]
+    knobs: Vec<tune::Knob>,
+    /// Rule out settings whose P95 publish latency is above this many
+    /// microseconds.
+    #[clap(long)]
+    max_publish_p95_us: Option<u64>,
+    /// The number of packages to start with.
+    #[clap(long, default_value = "1000")]
+    packages: usize,
+    /// Rounds of publishes and refreshes per setting.
+    #[clap(long, default_value = "200")]
+    rounds: usize,
+    /// Publishes in each round.
+    #[clap(long, default_value = "5")]
+    publishes_per_round: usize,
+    /// Number of clients.
+    #[clap(long, default_value = "8")]
+    clients: usize,
+    /// Probability that a client refreshes in a given round.
+    #[clap(long, default_value = "0.5")]
+    refresh_fraction: f64,
+    /// Probability that a publish is of a new package.
+    #[clap(long, default_value = "0.1")]
+    new_package_fraction: f64,
+    /// Seed for the workload.
+    #[clap(long, default_value = "0")]
+    seed: u64,
+    /// Write the best settings here (as a `--config` file) rather than
+    /// printing them.
+    #[clap(long)]
+    output: Option<PathBuf>,
+    /// RSA group parameters to use instead of RSA-2048 (see `gen-modulus`).
+    #[cfg(feature = "rsa")]
+    #[clap(long)]
+    group_params: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct StateLossArgs {
+    /// Techniques to compare (comma-separated).
+    #[clap(
+        long,
+        value_delimiter = ',',
+        default_value = "hackage,mercury_diff,mercury"
+    )]
+    authenticators: Vec<String>,
+    /// Probabilities that a returning user lost their state to try
+    /// (comma-separated).
+    #[clap(long, value_delimiter = ',', default_value = "0,0.01,0.05,0.1,0.25")]
+    rates: Vec<f64>,
+    /// The number of packages to start with.
+    #[clap(long, default_value = "1000")]
+    packages: usize,
+    /// Rounds of publishes and refreshes per rate.
+    #[clap(long, default_value = "100")]
+    rounds: usize,
+    /// Publishes in each round.
+    #[clap(long, default_value = "5")]
+    publishes_per_round: usize,
+    /// Number of clients.
+    #[clap(long, default_value = "32")]
+    clients: usize,
+    /// Probability that a client comes back (and refreshes) in a given round.
+    #[clap(long, default_value = "0.5")]
+    refresh_fraction: f64,
+    /// Probability that a publish is of a new package.
+    #[clap(long, default_value = "0.1")]
+    new_package_fraction: f64,
+    /// Seed for the workload.
+    #[clap(long, default_value = "0")]
+    seed: u64,
+    /// RSA group parameters to use instead of RSA-2048 (see `gen-modulus`).
+    #[cfg(feature = "rsa")]
+    #[clap(long)]
+    group_params: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct GrowthArgs {
+    /// Techniques to compare (comma-separated).
+    #[clap(
+        long,
+        value_delimiter = ',',
+        default_value = "hackage,mercury_diff,mercury"
+    )]
+    authenticators: Vec<String>,
+    /// The number of packages to start with.
+    #[clap(long, default_value = "1000")]
+    packages: usize,
+    /// How the registry grows (by default, `--growth linear:10`).
+    #[clap(flatten)]
+    growth: RegistryGrowthArgs,
+    /// Also replay the publishes in this trace (JSON lines of log entries),
+    /// starting from its first entry.
+    #[clap(long)]
+    trace: Option<PathBuf>,
+    /// Path to the database to use for results (sqlite3 format).
+    #[clap(long)]
+    results: Option<PathBuf>,
+    /// RSA group parameters to use instead of RSA-2048 (see `gen-modulus`).
+    #[cfg(feature = "rsa")]
+    #[clap(long)]
+    group_params: Option<PathBuf>,
+}
+
+/// How the registry grows, for a regular run's `growth` phase and for `sssim
+/// growth` (see `sssim::growth`).
+#[derive(clap::Args, Debug)]
+struct RegistryGrowthArgs {
+    /// Grow the registry, recording server storage over time:
+    /// `linear:<packages per day>` or `exponential:<daily rate>`.
+    #[clap(long, conflicts_with = "growth-trace")]
+    growth: Option<GrowthModel>,
+    /// Rather than `--growth`, add new packages when they first appeared in
+    /// this trace (JSON lines of log entries).
+    #[clap(long)]
+    growth_trace: Option<PathBuf>,
+    /// How long to grow for, in days.
+    #[clap(long, default_value = "365")]
+    growth_days: f64,
+    /// Days between storage samples.
+    #[clap(long, default_value = "7")]
+    growth_sample_days: f64,
+}
+
+impl RegistryGrowthArgs {
+    /// The growth asked for (or `default`, if none was).
+    fn options(&self, default: Option<GrowthModel>) -> io::Result<Option<GrowthOptions>> {
+        let model = match &self.growth_trace {
+            Some(path) => Some(GrowthModel::from_log(&load_trace(path)?)),
+            None => self.growth.clone().or(default),
+        };
+        let model = match model {
+            Some(model) => model,
+            None => return Ok(None),
+        };
+        let (days, sample_days) = (self.growth_days, self.growth_sample_days);
+        if !(days.is_finite() && days > 0.0 && sample_days > 0.0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--growth-days and --growth-sample-days must be positive",
+            ));
+        }
+        Ok(Some(GrowthOptions {
+            model,
+            duration: Duration::seconds_f64(days * 86400.0),
+            interval: Duration::seconds_f64(sample_days * 86400.0),
+        }))
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct SecurityTableArgs {
+    /// Security levels, in bits (comma-separated).
+    #[clap(long, value_delimiter = ',', default_value = "80,112,128,192,256")]
+    levels: Vec<SecurityLevel>,
+    /// The number of packages in the repository (e.g. `1e6`).
+    #[clap(long, default_value = "1e6", value_parser = sweep::parse_count)]
+    packages: usize,
+    /// Releases the refreshing client missed.
+    #[clap(long, default_value = "100")]
+    missed_releases: usize,
+    /// Use a modulus of this many bits at every level, rather than NIST's.
+    #[clap(long)]
+    modulus_bits: Option<u32>,
+    /// Use the modulus size of these RSA group parameters (see
+    /// `gen-modulus`), rather than NIST's.
+    #[cfg(feature = "rsa")]
+    #[clap(long, conflicts_with = "modulus-bits")]
+    group_params: Option<PathBuf>,
+    /// How to print the table: `text` or `latex`.
+    #[clap(long, default_value = "text")]
+    format: TableFormat,
+    /// Write the table here rather than printing it.
+    #[clap(long)]
+    output: Option<PathBuf>,
+}
+
+#[cfg(feature = "zstd")]
+#[derive(clap::Args, Debug)]
+struct DictionaryArgs {
+    /// Techniques whose diffs to compress (comma-separated).
+    #[clap(
+        long,
+        value_delimiter = ',',
+        default_value = "hackage,mercury_diff,mercury"
+    )]
+    authenticators: Vec<String>,
+    /// The number of packages to start with.
+    #[clap(long, default_value = "1000")]
+    packages: usize,
+    /// How many diffs to collect.
+    #[clap(long, default_value = "1000")]
+    diffs: usize,
+    /// Publishes between a client's refreshes.
+    #[clap(long, default_value = "10")]
+    publishes_per_diff: usize,
+    /// Probability that a publish is of a new package.
+    #[clap(long, default_value = "0.1")]
+    new_package_fraction: f64,
+    /// Fraction of the diffs (the earliest) to train on.
+    #[clap(long, default_value = "0.5")]
+    train_fraction: f64,
+    /// Largest dictionary to train, in bytes.
+    #[clap(long, default_value = "16384")]
+    dictionary_size: usize,
+    /// zstd compression level.
+    #[clap(long, default_value = "3")]
+    level: i32,
+    /// Seed for which packages get published.
+    #[clap(long, default_value = "0")]
+    seed: u64,
+}
+
+/// Settings for the sustained-load (throughput) mode.
+#[derive(Debug, Clone)]
+struct ThroughputOptions {
+    /// How long to keep issuing operations.
+    wall_time: std::time::Duration,
+    /// Probability that any given operation is a publish (vs. a download).
+    publish_fraction: f64,
+    /// Probability that a publish re-publishes an earlier package unchanged.
+    republish_fraction: f64,
+    republish_policy: RepublishPolicy,
+}
+
+/// How the registry grows, and how often to sample server storage as it does.
+#[derive(Debug, Clone)]
+struct GrowthOptions {
+    model: GrowthModel,
+    duration: Duration,
+    interval: Duration,
+}
+
+impl GrowthOptions {
+    /// `entries` with a publish mixed in for each new package, as the registry
+    /// grows from `initial` packages, and when the growth starts.
+    fn log(&self, initial: usize, entries: Vec<Entry>) -> (Log, time::OffsetDateTime) {
+        let start = entries
+            .first()
+            .map_or(time::OffsetDateTime::UNIX_EPOCH, |entry| entry.timestamp);
+        let arrivals = self.model.arrivals(initial, self.duration);
+        println!(
+            "{}: {} new packages in {} days",
+            self.model,
+            arrivals.len(),
+            self.duration.as_seconds_f64() / 86400.0
+        );
+        (growth::grow(entries, start, &arrivals), start)
+    }
 }
 
-trait Table {
+/// Settings for scale mode.
+#[derive(Debug, Clone)]
+struct ScaleOptions {
+    /// How many packages to actually run expensive techniques with.
+    sample: usize,
+    /// How many packages we're trying to estimate for.
+    packages: usize,
+}
+
+/// Settings shared by every phase of a run.
+#[derive(Debug, Clone)]
+struct RunOptions {
+    cores: usize,
+    throughput: Option<ThroughputOptions>,
+    scale: Option<ScaleOptions>,
+    /// How the technique's server-side costs grow, if scale mode samples it.
+    server_scaling: Option<ServerScaling>,
+    /// Zipf exponent for download popularity (uniform if unset).
+    download_zipf: Option<f64>,
+    /// Check the final server state against a replay of the publish log.
+    verify_final_state: bool,
+    /// Prices and workload for monthly cost estimates.
+    cost: Option<CostConfig>,
+    /// Per-operation constants for client energy estimates.
+    energy: EnergyModel,
+    /// Sizes of the targets metadata fetched with each download.
+    targets: TargetsModel,
+    /// Where to draw the refresh trials' missed releases from (if not the
+    /// fixed ladder).
+    refresh_intervals: Option<RefreshIntervals>,
+    /// Number of subscribers for the push-update trials (if any).
+    subscribers: Option<usize>,
+    /// Batch sizes for the batched-publish trials.
+    publish_batches: Vec<u16>,
+    /// Number of publishes for the CDN purge trials (if any).
+    cdn_publishes: Option<usize>,
+    /// Registry growth for the storage-over-time trials (if any).
+    growth: Option<GrowthOptions>,
+    /// Number of bit flips for the fault injection trials (if any).
+    fault_trials: Option<usize>,
+    /// Edge compute model for the edge trials (if any).
+    edge: Option<EdgeModel>,
+    /// Settings for the technique being run.
+    technique: TechniqueConfig,
+    /// The SRS from `--srs` (if any).
+    srs: Option<SrsLoad>,
+}
+
+trait Table: Record + Clone {
     fn create(db: &Connection) -> rusqlite::Result<()>;
 
-    fn insert<A: Authenticator>(&self, db: &Connection) -> rusqlite::Result<usize>;
+    /// Record this row as a result for `A`.
+    fn insert<A: Authenticator>(&self, db: &dyn ResultsSink) -> rusqlite::Result<()> {
+        db.record(A::name(), Box::new(self.clone()))
+    }
+}
+
+/// Record [`SCHEMA_VERSION`] in the results db, refusing to add to a db from
+/// another version.
+fn record_schema_version(db: &Connection) -> io::Result<()> {
+    let sqlite = |err: rusqlite::Error| io::Error::new(io::ErrorKind::Other, err.to_string());
+    db.execute(
+        "CREATE TABLE IF NOT EXISTS schema (version INTEGER NOT NULL)",
+        [],
+    )
+    .map_err(sqlite)?;
+    let found: Option<u32> = db
+        .query_row("SELECT version FROM schema", [], |row| row.get(0))
+        .optional()
+        .map_err(sqlite)?;
+    match found {
+        Some(found) => {
+            let header = Header {
+                schema_version: found,
+            };
+            header.check().map_err(|err| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("results db: {err}"))
+            })?;
+        }
+        None => {
+            db.execute("INSERT INTO schema (version) VALUES (?1)", [SCHEMA_VERSION])
+                .map_err(sqlite)?;
+        }
+    }
+    Ok(())
+}
+
+/// Set up the results db (after checking its schema version).
+fn create_tables(db: &Connection) -> io::Result<()> {
+    record_schema_version(db)?;
+    create_result_tables(db).map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))
 }
 
-fn create_tables(db: &Connection) -> rusqlite::Result<()> {
+fn create_result_tables(db: &Connection) -> rusqlite::Result<()> {
     OverallTimeResult::create(db)?;
     PrecomputeResult::create(db)?;
     UpdateResult::create(db)?;
     MergeResult::create(db)?;
     RefreshResult::create(db)?;
+    TargetedRefreshResult::create(db)?;
+    PushResult::create(db)?;
+    CdnPurgeResult::create(db)?;
+    FaultResult::create(db)?;
+    EdgeResult::create(db)?;
+    StorageCurveResult::create(db)?;
     DownloadResult::create(db)?;
+    ThroughputResult::create(db)?;
+    LatencyResult::create(db)?;
+    ExtrapolatedResult::create(db)?;
+    FinalStateResult::create(db)?;
+    CostResult::create(db)?;
+    OpCountResult::create(db)?;
+    device::create_tables(db)?;
     Ok(())
 }
 
@@ -53,11 +697,17 @@ fn duration_to_ns(duration: Duration) -> u64 {
     duration.whole_nanoseconds().try_into().unwrap()
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct OverallTimeResult {
     runtime: Duration,
     packages: usize,
     cores: usize,
+    /// CPU time across all threads (if we can measure it on this platform).
+    cpu_time: Option<Duration>,
+    /// Peak resident memory of the whole process so far (ditto).
+    peak_rss: Option<Information>,
+    /// Whether the run stopped early (see `sssim::interrupt`).
+    interrupted: bool,
 }
 
 impl Table for OverallTimeResult {
@@ -65,36 +715,115 @@ impl Table for OverallTimeResult {
         db.execute(
             "CREATE TABLE IF NOT EXISTS overall_time (
             id         INTEGER PRIMARY KEY AUTOINCREMENT,
-            technique  TEXT,
-            runtime_ns INTEGER,
-            packages   INTEGER,
-            cores      INTEGER
+            technique      TEXT,
+            runtime_ns     INTEGER,
+            packages       INTEGER,
+            cores          INTEGER,
+            cpu_time_ns    INTEGER,
+            peak_rss_bytes INTEGER,
+            status         TEXT
         )",
             [],
         )?;
         Ok(())
     }
+}
 
-    fn insert<A: Authenticator>(&self, db: &Connection) -> rusqlite::Result<usize> {
+impl Record for OverallTimeResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
         let runtime_ns: u64 = duration_to_ns(self.runtime);
-        db.execute(
+        let cpu_time_ns: Option<u64> = self.cpu_time.map(duration_to_ns);
+        let peak_rss_bytes = self.peak_rss.map(|rss| rss.get::<byte>());
+        let status = if self.interrupted {
+            RunStatus::Interrupted
+        } else {
+            RunStatus::Done
+        };
+        rows.insert(
+            db,
             "
             INSERT INTO overall_time (
                 runtime_ns,
                 technique,
                 packages,
-                cores
-            ) VALUES ( ?1, ?2, ?3, ?4 )",
-            rusqlite::params![runtime_ns, A::name(), self.packages, self.cores],
+                cores,
+                cpu_time_ns,
+                peak_rss_bytes,
+                status
+            ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7 )",
+            rusqlite::params![
+                runtime_ns,
+                technique,
+                self.packages,
+                self.cores,
+                cpu_time_ns,
+                peak_rss_bytes,
+                status.to_string()
+            ],
         )
     }
 }
 
+/// A structured reference string loaded for the run (see [`Srs`]).
+#[derive(Debug, Clone, Copy)]
+struct SrsLoad {
+    size: Information,
+    time: Duration,
+}
+
+/// Load the SRS at `path`, checking that it's big enough for `packages`.
+fn load_srs(path: &Path, packages: usize) -> io::Result<SrsLoad> {
+    let (time, srs) = time_fn(|| Srs::load(path));
+    let srs = srs
+        .and_then(|srs| srs.check(packages).map(|()| srs))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+    println!("SRS: {} powers, loaded in {time}", srs.powers());
+    Ok(SrsLoad {
+        size: srs.size(),
+        time,
+    })
+}
+
+/// Load the `--config` file, if there is one (otherwise, every technique gets
+/// its defaults).
+fn load_config(path: Option<&Path>) -> io::Result<Config> {
+    let config = path
+        .map(Config::load)
+        .transpose()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+    Ok(config.unwrap_or_default())
+}
+
+/// Load the group parameters at `path` (see `gen-modulus`).
+#[cfg(feature = "rsa")]
+fn load_group_params(path: &Path) -> io::Result<GroupParameters> {
+    GroupParameters::load(path)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))
+}
+
+/// Use the group parameters at `path` for every group operation from now on.
+#[cfg(feature = "rsa")]
+fn install_group_params(path: &Path) -> io::Result<()> {
+    load_group_params(path)?
+        .install()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))
+}
+
+#[derive(Debug, Clone)]
 struct PrecomputeResult {
     packages: usize,
     time: Duration,
     server_state: Information,
     cdn_size: Information,
+    /// What clients need out-of-band before they start (see [`authenticator::TrustAnchor`]).
+    bootstrap: Information,
+    /// The SRS loaded for the run, if any.
+    srs: Option<SrsLoad>,
     cores: usize,
 }
 
@@ -108,15 +837,26 @@ impl Table for PrecomputeResult {
              server_time_ns     INTEGER,
              server_state_bytes INTEGER,
              cdn_size_bytes     INTEGER,
+             bootstrap_bytes    INTEGER,
+             srs_bytes          INTEGER,
+             srs_load_time_ns   INTEGER,
              cores              INTEGER
         )",
             [],
         )?;
         Ok(())
     }
+}
 
-    fn insert<A: Authenticator>(&self, db: &Connection) -> rusqlite::Result<usize> {
-        db.execute(
+impl Record for PrecomputeResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
+        rows.insert(
+            db,
             "
         INSERT INTO precompute_results (
             technique,
@@ -124,26 +864,36 @@ impl Table for PrecomputeResult {
             server_time_ns,
             server_state_bytes,
             cdn_size_bytes,
+            bootstrap_bytes,
+            srs_bytes,
+            srs_load_time_ns,
             cores
-        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6 ) ",
+        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9 ) ",
             rusqlite::params![
-                A::name(),
+                technique,
                 self.packages,
                 duration_to_ns(self.time),
                 self.server_state.get::<byte>(),
                 self.cdn_size.get::<byte>(),
+                self.bootstrap.get::<byte>(),
+                self.srs.map(|srs| srs.size.get::<byte>()),
+                self.srs.map(|srs| duration_to_ns(srs.time)),
                 self.cores,
             ],
         )
     }
 }
 
+#[derive(Debug, Clone)]
 struct UpdateResult {
     packages: usize,
     time: Duration,
     server_state: Information,
     cdn_size: Information,
     batch_size: u16,
+    /// Hash invocations, for techniques that count them (see
+    /// [`Authenticator::hash_count`]).
+    hashes: Option<u64>,
     cores: usize,
 }
 
@@ -158,15 +908,24 @@ impl Table for UpdateResult {
              server_state_bytes INTEGER,
              cdn_size_bytes     INTEGER,
              batch_size         INTEGER,
+             hashes             INTEGER,
              cores              INTEGER
          )",
             [],
         )?;
         Ok(())
     }
+}
 
-    fn insert<A: Authenticator>(&self, db: &Connection) -> rusqlite::Result<usize> {
-        db.execute(
+impl Record for UpdateResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
+        rows.insert(
+            db,
             "
         INSERT INTO update_results (
             technique,
@@ -175,21 +934,31 @@ impl Table for UpdateResult {
             server_state_bytes,
             cdn_size_bytes,
             batch_size,
+            hashes,
             cores
-        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7 ) ",
+        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8 ) ",
             rusqlite::params![
-                A::name(),
+                technique,
                 self.packages,
                 duration_to_ns(self.time),
                 self.server_state.get::<byte>(),
                 self.cdn_size.get::<byte>(),
                 self.batch_size,
+                self.hashes,
                 self.cores,
             ],
         )
     }
 }
 
+/// Hash invocations while running `f` on `auth`.
+fn count_hashes<A: Authenticator>(auth: &mut A, f: impl FnOnce(&mut A)) -> Option<u64> {
+    let before = auth.hash_count();
+    f(auth);
+    Some(auth.hash_count()? - before?)
+}
+
+#[derive(Debug, Clone)]
 struct MergeResult {
     packages: usize,
     server_state: Information,
@@ -216,9 +985,17 @@ impl Table for MergeResult {
         )?;
         Ok(())
     }
+}
 
-    fn insert<A: Authenticator>(&self, db: &Connection) -> rusqlite::Result<usize> {
-        db.execute(
+impl Record for MergeResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
+        rows.insert(
+            db,
             "
         INSERT INTO merge_results (
             technique,
@@ -230,7 +1007,7 @@ impl Table for MergeResult {
             cores
         ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7 ) ",
             rusqlite::params![
-                A::name(),
+                technique,
                 self.packages,
                 self.server_state.get::<byte>(),
                 duration_to_ns(self.merge_time),
@@ -242,12 +1019,17 @@ impl Table for MergeResult {
     }
 }
 
+#[derive(Debug, Clone)]
 struct RefreshResult {
     packages: usize,
     elapsed_releases: Option<usize>,
     time: Duration,
+    /// Like `time`, but with the rollback checks batched.
+    batched_time: Duration,
     bandwidth: Information,
     user_state: Information,
+    /// Estimated client energy (see [`EnergyModel`]).
+    energy: Option<f64>,
     cores: usize,
 }
 
@@ -260,471 +1042,2843 @@ impl Table for RefreshResult {
              packages           INTEGER,
              elapsed_releases   INTEGER, -- null => initial refresh
              user_time_ns       INTEGER,
+             batched_user_time_ns INTEGER,
              bandwidth_bytes    INTEGER,
              user_state_bytes   INTEGER,
+             user_energy_j      REAL,
              cores              INTEGER
          )",
             [],
         )?;
         Ok(())
     }
+}
 
-    fn insert<A: Authenticator>(&self, db: &Connection) -> rusqlite::Result<usize> {
-        db.execute(
+impl Record for RefreshResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
+        rows.insert(
+            db,
             "
         INSERT INTO refresh_results (
             technique,
             packages,
             elapsed_releases,
             user_time_ns,
+            batched_user_time_ns,
             bandwidth_bytes,
             user_state_bytes,
+            user_energy_j,
             cores
-        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7 ) ",
+        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9 ) ",
             rusqlite::params![
-                A::name(),
+                technique,
                 self.packages,
                 self.elapsed_releases,
                 duration_to_ns(self.time),
+                duration_to_ns(self.batched_time),
                 self.bandwidth.get::<byte>(),
                 self.user_state.get::<byte>(),
+                self.energy,
                 self.cores
             ],
         )
     }
 }
 
-fn batch_update_trials<A>(
-    num_trials: u16,
+/// How many packages the user in the targeted-refresh trials has installed.
+const INSTALLED_PACKAGES: usize = 20;
+
+/// Refresh bandwidth for a user who only cares about a few installed packages.
+#[derive(Debug, Clone)]
+struct TargetedRefreshResult {
+    packages: usize,
+    elapsed_releases: usize,
+    installed: usize,
+    /// A full refresh (as in `refresh_results`).
+    full_bandwidth: Information,
+    /// [`Authenticator::refresh_packages`] for the installed packages.
+    targeted_bandwidth: Information,
+    cores: usize,
+}
+
+impl Table for TargetedRefreshResult {
+    fn create(db: &Connection) -> rusqlite::Result<()> {
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS targeted_refresh_results (
+             id                       INTEGER PRIMARY KEY AUTOINCREMENT,
+             technique                TEXT,
+             packages                 INTEGER,
+             elapsed_releases         INTEGER,
+             installed                INTEGER,
+             full_bandwidth_bytes     INTEGER,
+             targeted_bandwidth_bytes INTEGER,
+             cores                    INTEGER
+         )",
+            [],
+        )?;
+        Ok(())
+    }
+}
+
+impl Record for TargetedRefreshResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
+        rows.insert(
+            db,
+            "
+        INSERT INTO targeted_refresh_results (
+            technique,
+            packages,
+            elapsed_releases,
+            installed,
+            full_bandwidth_bytes,
+            targeted_bandwidth_bytes,
+            cores
+        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7 ) ",
+            rusqlite::params![
+                technique,
+                self.packages,
+                self.elapsed_releases,
+                self.installed,
+                self.full_bandwidth.get::<byte>(),
+                self.targeted_bandwidth.get::<byte>(),
+                self.cores
+            ],
+        )
+    }
+}
+
+/// Epochs in the push-update trials.
+const PUSH_EPOCHS: usize = 10;
+/// Publishes per epoch in the push-update trials.
+const PUSH_EPOCH_PUBLISHES: usize = 100;
+
+/// Push-based distribution after some number of epochs (see `sssim::push`).
+#[derive(Debug, Clone)]
+struct PushResult {
+    packages: usize,
+    epoch: usize,
+    subscribers: usize,
+    /// This epoch's delta.
+    delta: Information,
+    /// Everything pushed to all subscribers so far.
+    push: Information,
+    /// What all subscribers would have downloaded polling once, at this epoch.
+    poll: Information,
+    cores: usize,
+}
+
+impl Table for PushResult {
+    fn create(db: &Connection) -> rusqlite::Result<()> {
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS push_results (
+             id                 INTEGER PRIMARY KEY AUTOINCREMENT,
+             technique          TEXT,
+             packages           INTEGER,
+             epoch              INTEGER,
+             epoch_publishes    INTEGER,
+             subscribers        INTEGER,
+             delta_bytes        INTEGER,
+             push_bytes         INTEGER, -- cumulative, over all subscribers
+             poll_bytes         INTEGER, -- one catch-up poll per subscriber
+             cores              INTEGER
+         )",
+            [],
+        )?;
+        Ok(())
+    }
+}
+
+impl Record for PushResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
+        rows.insert(
+            db,
+            "
+        INSERT INTO push_results (
+            technique,
+            packages,
+            epoch,
+            epoch_publishes,
+            subscribers,
+            delta_bytes,
+            push_bytes,
+            poll_bytes,
+            cores
+        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9 ) ",
+            rusqlite::params![
+                technique,
+                self.packages,
+                self.epoch,
+                PUSH_EPOCH_PUBLISHES,
+                self.subscribers,
+                self.delta.get::<byte>(),
+                self.push.get::<byte>(),
+                self.poll.get::<byte>(),
+                self.cores
+            ],
+        )
+    }
+}
+
+fn push_trials<A: Authenticator + Clone>(
     auth: &A,
-    batch_size: u16,
+    subscribers: usize,
     num_packages: usize,
     cores: usize,
-    db: &Connection,
-) -> rusqlite::Result<()>
-where
-    A: PoolAuthenticator + Clone + Debug + DataSized,
-{
-    println!("{num_trials} publish trials");
-    for i in 0..num_trials {
-        println!("trial {i}");
-        let mut auth = auth.clone();
-        for b in 0..batch_size {
-            let package_id = PackageId::from(format!("new_package{b}"));
-            let (update_time, _) = Duration::time_fn(|| {
-                auth.publish(package_id);
-            });
-            let cdn_size = auth.cdn_size();
-            let result = UpdateResult {
-                packages: num_packages,
-                time: update_time,
-                server_state: auth.size(),
-                cdn_size,
-                batch_size: b + 1,
-                cores,
-            };
-            result.insert::<A>(db)?;
+    db: &dyn ResultsSink,
+) -> rusqlite::Result<()> {
+    let mut auth = auth.clone();
+    let mut broadcast = Broadcast::new(&auth, subscribers);
+    let bar = ProgressBar::new(PUSH_EPOCHS.try_into().unwrap());
+    for epoch in 1..=PUSH_EPOCHS {
+        bar.inc(1);
+        for idx in 0..PUSH_EPOCH_PUBLISHES {
+            auth.publish(PackageId::from(format!("push_package{epoch}_{idx}")));
         }
-
-        let (merge_time, _) = Duration::time_fn(|| {
-            auth.batch_process();
-        });
-        let cdn_size = auth.cdn_size();
-        let result = MergeResult {
+        let usage = broadcast.epoch(&auth);
+        let result = PushResult {
             packages: num_packages,
-            server_state: auth.size(),
-            cdn_size,
-            merge_time,
-            batch_size,
+            epoch,
+            subscribers,
+            delta: usage.delta,
+            push: broadcast.pushed(),
+            poll: broadcast.poll(&auth),
             cores,
         };
         result.insert::<A>(db)?;
     }
+    bar.finish();
     Ok(())
 }
 
-fn update_trials<A>(
-    num_trials: u16,
-    auth: &A,
-    num_packages: usize,
+/// CDN invalidation caused by one publish (see `sssim::cdn`).
+#[derive(Debug, Clone)]
+struct CdnPurgeResult {
+    packages: usize,
+    publish: usize,
+    stats: PurgeStats,
     cores: usize,
-    db: &Connection,
-) -> rusqlite::Result<()>
-where
-    A: Authenticator + Clone + Debug,
-{
-    println!("{num_trials} trials");
-    for i in 0..num_trials {
-        println!("trial {i}");
-        let batch_size = 1;
-        let mut auth = auth.clone();
-        let package_id = PackageId::from("new_package".to_string());
-        let (update_time, _) = Duration::time_fn(|| {
-            auth.publish(package_id);
-        });
+}
 
-        let cdn_size = auth.cdn_size();
-        let result = UpdateResult {
-            packages: num_packages,
-            time: update_time,
-            server_state: auth.size(),
-            cdn_size,
-            batch_size,
-            cores,
-        };
-        result.insert::<A>(db)?;
+impl Table for CdnPurgeResult {
+    fn create(db: &Connection) -> rusqlite::Result<()> {
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS cdn_purge_results (
+             id                 INTEGER PRIMARY KEY AUTOINCREMENT,
+             technique          TEXT,
+             packages           INTEGER,
+             publish            INTEGER,
+             purges             INTEGER,
+             new_objects        INTEGER,
+             reseed_bytes       INTEGER,
+             cores              INTEGER
+         )",
+            [],
+        )?;
+        Ok(())
     }
+}
 
-    Ok(())
+impl Record for CdnPurgeResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
+        rows.insert(
+            db,
+            "
+        INSERT INTO cdn_purge_results (
+            technique,
+            packages,
+            publish,
+            purges,
+            new_objects,
+            reseed_bytes,
+            cores
+        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7 ) ",
+            rusqlite::params![
+                technique,
+                self.packages,
+                self.publish,
+                self.stats.purges,
+                self.stats.new_objects,
+                self.stats.reseed.get::<byte>(),
+                self.cores
+            ],
+        )
+    }
 }
 
-fn precompute_trials<A>(
-    num_trials: u16,
-    db: &Connection,
+/// Publish existing packages one at a time against a warm CDN, recording what
+/// each publish invalidates.
+fn cdn_purge_trials<A: Authenticator + Clone>(
+    auth: &A,
+    publishes: usize,
     packages: &[PackageId],
     cores: usize,
-) -> rusqlite::Result<A>
-where
-    A: Authenticator + Debug,
-{
-    let mut auth = None;
-    let num_packages = packages.len();
-    println!("{num_trials} trials");
-    for i in 0..num_trials {
-        println!("trial number: {i}");
-        // TODO(maybe): more hooks for progress reporting in batch_import
-        let packages = packages.to_owned();
-        let (precompute_time, inner_auth) = Duration::time_fn(|| A::batch_import(packages));
-        let cdn_size = inner_auth.cdn_size();
-        let result = PrecomputeResult {
-            packages: num_packages,
-            time: precompute_time,
-            server_state: inner_auth.size(),
-            cdn_size,
+    db: &dyn ResultsSink,
+) -> rusqlite::Result<()> {
+    let mut auth = auth.clone();
+    let mut cache = CdnCache::new(auth.cdn_objects());
+    let bar = ProgressBar::new(publishes.try_into().unwrap());
+    for (publish, package) in packages.iter().cycle().take(publishes).enumerate() {
+        bar.inc(1);
+        auth.publish(package.clone());
+        let result = CdnPurgeResult {
+            packages: packages.len(),
+            publish,
+            stats: cache.update(auth.cdn_objects()),
             cores,
         };
         result.insert::<A>(db)?;
-        auth.replace(inner_auth);
     }
+    bar.finish();
+    let total = cache.total();
+    println!(
+        "cdn: {} purges, {} bytes re-seeded",
+        total.purges,
+        total.reseed.get::<byte>()
+    );
+    Ok(())
+}
 
-    Ok(auth.unwrap())
+/// One bit flipped in the server's saved state (see `sssim::fault`).
+#[derive(Debug, Clone)]
+struct FaultResult {
+    packages: usize,
+    bit: usize,
+    state_bytes: usize,
+    outcome: Outcome,
+    cores: usize,
 }
 
-fn create_user_state<A: Authenticator>(
-    num_trials: u16,
+impl Table for FaultResult {
+    fn create(db: &Connection) -> rusqlite::Result<()> {
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS fault_results (
+             id                 INTEGER PRIMARY KEY AUTOINCREMENT,
+             technique          TEXT,
+             packages           INTEGER,
+             bit                INTEGER,
+             state_bytes        INTEGER,
+             outcome            TEXT,
+             cores              INTEGER
+         )",
+            [],
+        )?;
+        Ok(())
+    }
+}
+
+impl Record for FaultResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
+        rows.insert(
+            db,
+            "
+        INSERT INTO fault_results (
+            technique,
+            packages,
+            bit,
+            state_bytes,
+            outcome,
+            cores
+        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6 ) ",
+            rusqlite::params![
+                technique,
+                self.packages,
+                self.bit,
+                self.state_bytes,
+                self.outcome.name(),
+                self.cores
+            ],
+        )
+    }
+}
+
+/// Packages each fault trial downloads from the corrupted server.
+static FAULT_SAMPLE: usize = 16;
+
+/// Flip `trials` random bits of the server's saved state, one at a time,
+/// recording what catches each.
+fn fault_trials<A: Authenticator + WarmState>(
     auth: &A,
-    num_packages: usize,
+    trials: usize,
+    packages: &[PackageId],
     cores: usize,
-    db: &Connection,
-) -> rusqlite::Result<A::ClientSnapshot> {
-    let mut user_state_initial: Option<A::ClientSnapshot> = None;
-    println!("{num_trials} trials");
-    for i in 0..num_trials {
-        println!("trial {i}");
-        let user_state = auth.get_metadata();
-        let result = RefreshResult {
-            packages: num_packages,
-            elapsed_releases: None,
-            time: Duration::ZERO,
-            bandwidth: user_state.size(),
-            user_state: user_state.size(),
+    db: &dyn ResultsSink,
+) -> rusqlite::Result<()> {
+    let mut rng = rand::thread_rng();
+    let sample: Vec<PackageId> =
+        rand::seq::SliceRandom::choose_multiple(packages, &mut rng, FAULT_SAMPLE)
+            .cloned()
+            .collect();
+    let bar = ProgressBar::new(trials.try_into().unwrap());
+    let faults: Vec<_> = fault::quietly(|| {
+        (0..trials)
+            .map_while(|_| {
+                bar.inc(1);
+                fault::inject(auth, &sample, &mut rng)
+            })
+            .collect()
+    });
+    bar.finish();
+    if faults.is_empty() {
+        println!("faults: {} keeps no state", A::name());
+    }
+
+    let mut outcomes = std::collections::BTreeMap::<Outcome, usize>::new();
+    for fault in faults {
+        *outcomes.entry(fault.outcome).or_default() += 1;
+        let result = FaultResult {
+            packages: packages.len(),
+            bit: fault.bit,
+            state_bytes: fault.state_bytes,
+            outcome: fault.outcome,
             cores,
         };
         result.insert::<A>(db)?;
-        user_state_initial.replace(user_state);
     }
-    let user_state_initial = user_state_initial.take().unwrap();
-    Ok(user_state_initial)
+    for (outcome, count) in outcomes {
+        println!("faults: {count} {outcome}");
+    }
+    Ok(())
 }
 
-fn refresh_user_state<A: Authenticator + Clone>(
-    refresh_trials: u16,
-    auth_ref: &A,
-    num_packages: usize,
-    db: &Connection,
-    user_state_initial: A::ClientSnapshot,
+/// Where one response got served, under the edge compute model (see
+/// `sssim::edge`).
+#[derive(Debug, Clone)]
+struct EdgeResult {
+    packages: usize,
+    /// `refresh` or `download`.
+    response: &'static str,
+    /// Releases the client missed (refreshes only).
+    missed_releases: Option<usize>,
+    placement: Placement,
+    /// Time to assemble the response at the edge (if it can).
+    edge_time: Option<Duration>,
+    bytes: Information,
+    cores: usize,
+}
+
+impl Table for EdgeResult {
+    fn create(db: &Connection) -> rusqlite::Result<()> {
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS edge_results (
+             id                 INTEGER PRIMARY KEY AUTOINCREMENT,
+             technique          TEXT,
+             packages           INTEGER,
+             response           TEXT,
+             missed_releases    INTEGER,
+             placement          TEXT,
+             edge_time_ns       INTEGER,
+             bytes              INTEGER,
+             cores              INTEGER
+         )",
+            [],
+        )?;
+        Ok(())
+    }
+}
+
+impl Record for EdgeResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
+        rows.insert(
+            db,
+            "
+        INSERT INTO edge_results (
+            technique,
+            packages,
+            response,
+            missed_releases,
+            placement,
+            edge_time_ns,
+            bytes,
+            cores
+        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8 ) ",
+            rusqlite::params![
+                technique,
+                self.packages,
+                self.response,
+                self.missed_releases,
+                self.placement.name(),
+                self.edge_time.map(duration_to_ns),
+                self.bytes.get::<byte>(),
+                self.cores
+            ],
+        )
+    }
+}
+
+/// Releases missed before each edge refresh.
+const EDGE_MISSED_RELEASES: [usize; 4] = [1, 10, 100, 1000];
+
+/// Packages downloaded in the edge trials.
+const EDGE_DOWNLOADS: usize = 16;
+
+/// Refresh after missing each of [`EDGE_MISSED_RELEASES`], and download a
+/// sample of packages, recording where `model` would serve each response.
+fn edge_trials<A: Authenticator + Clone>(
+    auth: &A,
+    model: &EdgeModel,
+    packages: &[PackageId],
     cores: usize,
+    db: &dyn ResultsSink,
 ) -> rusqlite::Result<()> {
-    println!("refresh_user_state");
-    let mut elapsed_releases =
-        VecDeque::from(vec![100, 200, 300, 400, 500, 600, 700, 800, 900, 1000]); // assume sorted
-    let max_entry: usize =
-        std::cmp::min(elapsed_releases[elapsed_releases.len() - 1], num_packages);
-    let bar = ProgressBar::new(max_entry.try_into().unwrap());
-    let mut auth = auth_ref.clone();
-    for idx in 0..=max_entry {
-        bar.inc(1);
-        if idx == elapsed_releases[0] {
-            for _ in 0..refresh_trials {
-                let mut user_state = user_state_initial.clone();
-                let maybe_diff = auth.refresh_metadata(A::id(&user_state));
-                let (bandwidth, user_time) = match maybe_diff {
-                    Some(diff) => {
-                        let bandwidth = diff.size();
-                        let (user_time, _) = Duration::time_fn(|| {
-                            assert!(A::check_no_rollback(&user_state, &diff));
-                            A::update(&mut user_state, diff);
-                        });
-                        (bandwidth, user_time)
-                    }
-                    None => (Information::new::<byte>(0), Duration::ZERO),
-                };
-                let result = RefreshResult {
-                    packages: num_packages,
-                    elapsed_releases: Some(idx),
-                    time: user_time,
-                    bandwidth,
-                    user_state: user_state.size(),
-                    cores,
-                };
-                result.insert::<A>(db)?;
-            }
-            elapsed_releases.pop_front();
-            if elapsed_releases.is_empty() {
-                break;
-            }
+    let mut auth = auth.clone();
+    let snapshot = auth.get_metadata();
+    let mut refreshes = EdgeTally::default();
+    let mut published = 0;
+    let mut to_publish = packages.iter().cycle();
+    for missed_releases in EDGE_MISSED_RELEASES {
+        for package in to_publish.by_ref().take(missed_releases - published) {
+            auth.publish(package.clone());
         }
-        let package = PackageId::from(format!("new_package{idx}"));
-        auth.publish(package);
+        published = missed_releases;
+        let diff = match auth.refresh_metadata(A::id(&snapshot)) {
+            Some(diff) => diff,
+            None => continue,
+        };
+        let work = auth.diff_edge_work(&diff);
+        let placement = model.place(A::diff_cacheable(&diff), work.as_ref());
+        refreshes.record(placement);
+        let result = EdgeResult {
+            packages: packages.len(),
+            response: "refresh",
+            missed_releases: Some(missed_releases),
+            placement,
+            edge_time: work.as_ref().map(|work| model.time(work)),
+            bytes: diff.size(),
+            cores,
+        };
+        result.insert::<A>(db)?;
     }
-    bar.finish();
+
+    let latest = auth.get_metadata();
+    let mut downloads = EdgeTally::default();
+    for package in packages.iter().take(EDGE_DOWNLOADS) {
+        let (_, proof) = auth.request_file(A::id(&latest), package);
+        let work = auth.proof_edge_work(&proof);
+        // Techniques without proofs have nothing for anyone to compute.
+        let cacheable = A::proof_cacheable(&proof) || proof.size().get::<byte>() == 0;
+        let placement = model.place(cacheable, work.as_ref());
+        downloads.record(placement);
+        let result = EdgeResult {
+            packages: packages.len(),
+            response: "download",
+            missed_releases: None,
+            placement,
+            edge_time: work.as_ref().map(|work| model.time(work)),
+            bytes: proof.size(),
+            cores,
+        };
+        result.insert::<A>(db)?;
+    }
+    println!("edge: refresh: {refreshes}; download: {downloads}");
+    let friendly = refreshes.cdn_friendly() && downloads.cdn_friendly();
+    println!(
+        "edge: {} {} CDN-friendly at a {} budget",
+        A::name(),
+        if friendly { "is" } else { "isn't" },
+        model.budget
+    );
     Ok(())
 }
 
-fn download_trials<A>(
-    download_trials: u16,
-    auth: A,
-    num_packages: usize,
-    db: &Connection,
-    packages: Vec<PackageId>,
-    cores: usize,
-) -> rusqlite::Result<()>
-where
-    A: Authenticator + Clone + Debug,
-{
-    let mut rng = rand::thread_rng();
-    println!("{download_trials} trials");
-    for i in 0..download_trials {
-        println!("trial {i}");
-        let mut auth = auth.clone();
-        let user_state = auth.get_metadata();
-        let package = rand::seq::SliceRandom::choose(packages.as_slice(), &mut rng).unwrap();
+/// A technique's server storage at one point as the registry grows (see
+/// `sssim::growth`).
+#[derive(Debug, Clone)]
+struct StorageCurveResult {
+    /// Packages at the start.
+    initial_packages: usize,
+    model: String,
+    point: StoragePoint,
+}
 
-        let (revision, proof) = auth.request_file(A::id(&user_state), package);
-        let bandwidth = proof.size();
+impl Table for StorageCurveResult {
+    fn create(db: &Connection) -> rusqlite::Result<()> {
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS storage_curve_results (
+             id                 INTEGER PRIMARY KEY AUTOINCREMENT,
+             technique          TEXT,
+             initial_packages   INTEGER,
+             model              TEXT,
+             elapsed_secs       INTEGER,
+             packages           INTEGER,
+             storage_bytes      INTEGER
+         )",
+            [],
+        )?;
+        Ok(())
+    }
+}
 
-        let (user_time, _) =
-            Duration::time_fn(|| A::verify_membership(&user_state, package, revision, proof));
+impl Record for StorageCurveResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
+        rows.insert(
+            db,
+            "
+        INSERT INTO storage_curve_results (
+            technique,
+            initial_packages,
+            model,
+            elapsed_secs,
+            packages,
+            storage_bytes
+        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6 ) ",
+            rusqlite::params![
+                technique,
+                self.initial_packages,
+                self.model,
+                self.point.elapsed.whole_seconds(),
+                self.point.packages,
+                self.point.storage.get::<byte>()
+            ],
+        )
+    }
+}
 
-        let result = DownloadResult {
-            packages: num_packages,
-            time: user_time,
-            bandwidth,
-            cores,
-        };
+/// Replay `log` (see [`GrowthOptions::log`]) against `server`, imported with
+/// `packages`, and sample its storage as the registry grows.
+fn storage_curve_trials<S: DataSized + ?Sized>(
+    name: &str,
+    growth: &GrowthOptions,
+    (log, start): (&Log, time::OffsetDateTime),
+    server: &mut S,
+    publish: impl FnMut(&mut S, PackageId),
+    packages: &[PackageId],
+) -> Vec<StorageCurveResult> {
+    let end = start + growth.duration;
+    let points = growth::storage_curve(server, publish, packages, log, start, end, growth.interval);
+    if let (Some(first), Some(last)) = (points.first(), points.last()) {
+        let (before, after) = (first.storage.get::<byte>(), last.storage.get::<byte>());
+        println!(
+            "{name}: {} -> {} packages; {before} -> {after} bytes ({:.2}x)",
+            first.packages,
+            last.packages,
+            after as f64 / before.max(1) as f64
+        );
+    }
+    points
+        .into_iter()
+        .map(|point| StorageCurveResult {
+            initial_packages: packages.len(),
+            model: growth.model.to_string(),
+            point,
+        })
+        .collect()
+}
+
+/// Grow the registry from `auth`'s state (see [`GrowthOptions`]), recording
+/// its storage over time.
+fn growth_trials<A: Authenticator>(
+    growth: &GrowthOptions,
+    mut auth: A,
+    packages: &[PackageId],
+    publish: impl FnMut(&mut A, PackageId),
+    db: &dyn ResultsSink,
+) -> rusqlite::Result<()> {
+    let (log, start) = growth.log(packages.len(), Vec::new());
+    let results = storage_curve_trials(
+        A::name(),
+        growth,
+        (&log, start),
+        &mut auth,
+        publish,
+        packages,
+    );
+    for result in results {
         result.insert::<A>(db)?;
     }
+    Ok(())
+}
 
+/// Record a [`LatencyResult`] for each operation in `latencies`.
+fn insert_latencies<A: Authenticator>(
+    db: &dyn ResultsSink,
+    phase: &'static str,
+    latencies: &Latencies,
+    packages: usize,
+    cores: usize,
+) -> rusqlite::Result<()> {
+    for result in LatencyResult::all(phase, latencies, packages, cores) {
+        result.insert::<A>(db)?;
+    }
     Ok(())
 }
 
-struct DownloadResult {
-    packages: usize,
-    time: Duration,
-    bandwidth: Information,
-    cores: usize,
-}
+impl Table for LatencyResult {
+    fn create(db: &Connection) -> rusqlite::Result<()> {
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS latency_results (
+             id              INTEGER PRIMARY KEY AUTOINCREMENT,
+             technique       TEXT,
+             packages        INTEGER,
+             phase           TEXT,
+             operation       TEXT,
+             count           INTEGER,
+             p50_ns          INTEGER,
+             p90_ns          INTEGER,
+             p99_ns          INTEGER,
+             p999_ns         INTEGER,
+             max_ns          INTEGER,
+             cores           INTEGER
+         )",
+            [],
+        )?;
+        Ok(())
+    }
+}
+
+/// Scaling a run on a sample of packages up to the full package count.
+#[derive(Debug, Clone, Copy)]
+struct Extrapolation {
+    sample: usize,
+    packages: usize,
+    scaling: ServerScaling,
+}
+
+impl Extrapolation {
+    /// The extrapolation for a run on `num_packages`, if it's a scale-mode
+    /// sample.
+    fn for_run(options: &RunOptions, num_packages: usize) -> Option<Self> {
+        let scale = options.scale.as_ref()?;
+        let scaling = options.server_scaling?;
+        (num_packages < scale.packages).then_some(Self {
+            sample: num_packages,
+            packages: scale.packages,
+            scaling,
+        })
+    }
+}
+
+/// An *estimate* of a server-side measurement at the full package count: the
+/// measurement on a sample, scaled up by the technique's asymptotic growth
+/// (see [`ServerScaling`]).
+///
+/// The growth is from each technique's cost analysis, not fit to the sample,
+/// so constant factors that matter at the sample size (but not at the full
+/// size) skew the estimate. The growths used are recorded alongside.
+#[derive(Debug, Clone)]
+struct ExtrapolatedResult {
+    packages: usize,
+    sample_packages: usize,
+    phase: &'static str,
+    time: Duration,
+    time_growth: Growth,
+    server_state: Information,
+    cdn_size: Information,
+    state_growth: Growth,
+    cores: usize,
+}
+
+impl ExtrapolatedResult {
+    fn new(
+        phase: &'static str,
+        time_growth: Growth,
+        time: Duration,
+        server_state: Information,
+        cdn_size: Information,
+        extrapolation: &Extrapolation,
+        cores: usize,
+    ) -> Self {
+        let Extrapolation {
+            sample,
+            packages,
+            scaling,
+        } = *extrapolation;
+        let extrapolate = |size: Information| {
+            let bytes = size.get::<byte>() as f64;
+            Information::new::<byte>(scaling.state.extrapolate(bytes, sample, packages) as usize)
+        };
+        let time_ns = time_growth.extrapolate(duration_to_ns(time) as f64, sample, packages);
+        Self {
+            packages,
+            sample_packages: sample,
+            phase,
+            time: Duration::nanoseconds(time_ns.min(i64::MAX as f64) as i64),
+            time_growth,
+            server_state: extrapolate(server_state),
+            cdn_size: extrapolate(cdn_size),
+            state_growth: scaling.state,
+            cores,
+        }
+    }
+}
+
+impl Table for ExtrapolatedResult {
+    fn create(db: &Connection) -> rusqlite::Result<()> {
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS extrapolated_results (
+             id                 INTEGER PRIMARY KEY AUTOINCREMENT,
+             technique          TEXT,
+             packages           INTEGER,
+             sample_packages    INTEGER,
+             phase              TEXT,
+             server_time_ns     INTEGER,
+             server_state_bytes INTEGER,
+             cdn_size_bytes     INTEGER,
+             time_growth        TEXT,
+             state_growth       TEXT,
+             cores              INTEGER
+         )",
+            [],
+        )?;
+        Ok(())
+    }
+}
+
+impl Record for ExtrapolatedResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
+        rows.insert(
+            db,
+            "
+        INSERT INTO extrapolated_results (
+            technique,
+            packages,
+            sample_packages,
+            phase,
+            server_time_ns,
+            server_state_bytes,
+            cdn_size_bytes,
+            time_growth,
+            state_growth,
+            cores
+        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10 ) ",
+            rusqlite::params![
+                technique,
+                self.packages,
+                self.sample_packages,
+                self.phase,
+                duration_to_ns(self.time),
+                self.server_state.get::<byte>(),
+                self.cdn_size.get::<byte>(),
+                self.time_growth.to_string(),
+                self.state_growth.to_string(),
+                self.cores,
+            ],
+        )
+    }
+}
+
+fn batch_update_trials<A>(
+    num_trials: u16,
+    auth: &A,
+    batch_size: u16,
+    num_packages: usize,
+    cores: usize,
+    db: &dyn ResultsSink,
+) -> rusqlite::Result<Usage>
+where
+    A: PoolAuthenticator + Clone + Debug + DataSized,
+{
+    println!("{num_trials} publish trials");
+    let mut latencies = Latencies::default();
+    let mut usages = Vec::new();
+    for i in 0..num_trials {
+        println!("trial {i}");
+        let mut auth = auth.clone();
+        auth.unshare();
+        let mut batch_time = Duration::ZERO;
+        for b in 0..batch_size {
+            let package_id = PackageId::from(format!("new_package{b}"));
+            let (update_time, hashes) =
+                time_fn(|| count_hashes(&mut auth, |auth| auth.publish(package_id)));
+            latencies.record(Operation::Publish, update_time);
+            batch_time += update_time;
+            let cdn_size = auth.cdn_size();
+            let result = UpdateResult {
+                packages: num_packages,
+                time: update_time,
+                server_state: auth.size(),
+                cdn_size,
+                batch_size: b + 1,
+                hashes,
+                cores,
+            };
+            result.insert::<A>(db)?;
+        }
+
+        let (merge_time, _) = time_fn(|| {
+            auth.batch_process();
+        });
+        // Spread the cost of the merge over the publishes in the batch.
+        usages.push(Usage {
+            server_compute: (batch_time + merge_time) / f64::from(batch_size),
+            ..Default::default()
+        });
+        let cdn_size = auth.cdn_size();
+        let result = MergeResult {
+            packages: num_packages,
+            server_state: auth.size(),
+            cdn_size,
+            merge_time,
+            batch_size,
+            cores,
+        };
+        result.insert::<A>(db)?;
+    }
+    insert_latencies::<A>(db, "batch_update", &latencies, num_packages, cores)?;
+    Ok(Usage::mean(&usages))
+}
+
+fn update_trials<A>(
+    num_trials: u16,
+    auth: &A,
+    num_packages: usize,
+    cores: usize,
+    db: &dyn ResultsSink,
+) -> rusqlite::Result<UpdateResult>
+where
+    A: Authenticator + Clone + Debug,
+{
+    println!("{num_trials} trials");
+    let mut latencies = Latencies::default();
+    let mut last_result = None;
+    for i in 0..num_trials {
+        println!("trial {i}");
+        let batch_size = 1;
+        let mut auth = auth.clone();
+        auth.unshare();
+        let package_id = PackageId::from("new_package".to_string());
+        let (update_time, hashes) =
+            time_fn(|| count_hashes(&mut auth, |auth| auth.publish(package_id)));
+        latencies.record(Operation::Publish, update_time);
+
+        let cdn_size = auth.cdn_size();
+        let result = UpdateResult {
+            packages: num_packages,
+            time: update_time,
+            server_state: auth.size(),
+            cdn_size,
+            batch_size,
+            hashes,
+            cores,
+        };
+        result.insert::<A>(db)?;
+        last_result.replace(result);
+    }
+    insert_latencies::<A>(db, "update", &latencies, num_packages, cores)?;
+
+    Ok(last_result.unwrap())
+}
+
+/// Publish `batch_size` new packages at once (see
+/// [`Authenticator::publish_batch`]), recording one row for the whole batch.
+fn publish_batch_trials<A>(
+    num_trials: u16,
+    auth: &A,
+    batch_size: u16,
+    num_packages: usize,
+    cores: usize,
+    db: &dyn ResultsSink,
+) -> rusqlite::Result<()>
+where
+    A: Authenticator + Clone + Debug,
+{
+    println!("{num_trials} trials");
+    for i in 0..num_trials {
+        println!("trial {i}");
+        let mut auth = auth.clone();
+        auth.unshare();
+        let packages = (0..batch_size)
+            .map(|b| PackageId::from(format!("new_package{b}")))
+            .collect();
+        let (time, hashes) =
+            time_fn(|| count_hashes(&mut auth, |auth| auth.publish_batch(packages)));
+        let result = UpdateResult {
+            packages: num_packages,
+            time,
+            server_state: auth.size(),
+            cdn_size: auth.cdn_size(),
+            batch_size,
+            hashes,
+            cores,
+        };
+        result.insert::<A>(db)?;
+    }
+    Ok(())
+}
+
+/// `A`'s settings, from its section of `--config` (already checked when
+/// loading it).
+fn settings<A: Authenticator>(technique: &TechniqueConfig) -> A::Config {
+    technique.parse().expect("invalid technique config")
+}
+
+fn precompute_trials<A>(
+    num_trials: u16,
+    db: &dyn ResultsSink,
+    packages: &[PackageId],
+    technique: &TechniqueConfig,
+    srs: Option<SrsLoad>,
+    cores: usize,
+) -> rusqlite::Result<(A, PrecomputeResult)>
+where
+    A: Authenticator + Debug,
+{
+    let mut auth = None;
+    let mut last_result = None;
+    let num_packages = packages.len();
+    println!("{num_trials} trials");
+    for i in 0..num_trials {
+        println!("trial number: {i}");
+        // TODO(maybe): more hooks for progress reporting in batch_import
+        let packages = packages.to_owned();
+        let config = settings::<A>(technique);
+        let (precompute_time, inner_auth) = time_fn(|| A::batch_import_with(packages, config));
+        let cdn_size = inner_auth.cdn_size();
+        let result = PrecomputeResult {
+            packages: num_packages,
+            time: precompute_time,
+            server_state: inner_auth.size(),
+            cdn_size,
+            bootstrap: inner_auth.trust_anchor().size(),
+            // Only techniques that use an SRS pay for one.
+            srs: srs.filter(|_| A::uses_srs()),
+            cores,
+        };
+        result.insert::<A>(db)?;
+        auth.replace(inner_auth);
+        last_result.replace(result);
+    }
+
+    Ok((auth.unwrap(), last_result.unwrap()))
+}
+
+fn create_user_state<A: Authenticator>(
+    num_trials: u16,
+    auth: &A,
+    num_packages: usize,
+    cores: usize,
+    db: &dyn ResultsSink,
+) -> rusqlite::Result<A::ClientSnapshot> {
+    let mut user_state_initial: Option<A::ClientSnapshot> = None;
+    println!("{num_trials} trials");
+    for i in 0..num_trials {
+        println!("trial {i}");
+        let user_state = auth.get_metadata();
+        let result = RefreshResult {
+            packages: num_packages,
+            elapsed_releases: None,
+            time: Duration::ZERO,
+            batched_time: Duration::ZERO,
+            bandwidth: user_state.size(),
+            user_state: user_state.size(),
+            energy: None,
+            cores,
+        };
+        result.insert::<A>(db)?;
+        user_state_initial.replace(user_state);
+    }
+    let user_state_initial = user_state_initial.take().unwrap();
+    Ok(user_state_initial)
+}
+
+/// Pick (distinct) packages for the user in the targeted-refresh trials.
+fn choose_installed(packages: &[PackageId], popularity: &Popularity) -> Vec<PackageId> {
+    let mut rng = rand::thread_rng();
+    let mut installed = Vec::new();
+    while installed.len() < std::cmp::min(INSTALLED_PACKAGES, packages.len()) {
+        let package = popularity.choose(packages, &mut rng).unwrap();
+        if !installed.contains(package) {
+            installed.push(package.clone());
+        }
+    }
+    installed
+}
+
+/// Bring `user_state` up to date for just the `installed` packages with
+/// [`Authenticator::refresh_packages`], returning the bandwidth used.
+fn targeted_refresh<A: Authenticator>(
+    auth: &A,
+    user_state: &A::ClientSnapshot,
+    installed: &[PackageId],
+) -> Information {
+    let refresh = auth.refresh_packages(A::id(user_state), installed);
+    let bandwidth = refresh.size();
+    let mut user_state = user_state.clone();
+    assert!(A::update_packages(&mut user_state, refresh));
+    bandwidth
+}
+
+/// Missed releases to refresh after, when not drawn from a trace.
+const ELAPSED_RELEASES: [usize; 10] = [100, 200, 300, 400, 500, 600, 700, 800, 900, 1000];
+
+/// Missed releases to refresh after, smallest first: [`ELAPSED_RELEASES`], or
+/// as many draws from `intervals` (at most `num_packages`, like the ladder).
+fn elapsed_releases(intervals: Option<&RefreshIntervals>, num_packages: usize) -> VecDeque<usize> {
+    match intervals {
+        Some(intervals) => intervals
+            .sample_sorted(ELAPSED_RELEASES.len(), &mut rand::thread_rng())
+            .into_iter()
+            .map(|elapsed| std::cmp::min(elapsed, num_packages))
+            .collect(),
+        None => VecDeque::from(ELAPSED_RELEASES.to_vec()),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn refresh_user_state<A: Authenticator + Clone>(
+    refresh_trials: u16,
+    auth_ref: &A,
+    num_packages: usize,
+    db: &dyn ResultsSink,
+    user_state_initial: A::ClientSnapshot,
+    installed: &[PackageId],
+    mut elapsed_releases: VecDeque<usize>,
+    energy: &EnergyModel,
+    cores: usize,
+) -> rusqlite::Result<(A, Vec<PackageId>, Usage)> {
+    println!("refresh_user_state");
+    let max_entry: usize =
+        std::cmp::min(elapsed_releases[elapsed_releases.len() - 1], num_packages);
+    let bar = ProgressBar::new(max_entry.try_into().unwrap());
+    let mut auth = auth_ref.clone();
+    let mut latencies = Latencies::default();
+    let mut log = Vec::new();
+    let mut usages = Vec::new();
+    for idx in 0..=max_entry {
+        bar.inc(1);
+        // Draws from a trace can repeat.
+        while elapsed_releases.front() == Some(&idx) {
+            for _ in 0..refresh_trials {
+                let mut user_state = user_state_initial.clone();
+                let id = A::id(&user_state);
+                let (server_time, maybe_diff) = time_fn(|| auth.refresh_metadata(id));
+                latencies.record(Operation::Refresh, server_time);
+                let cacheable = matches!(&maybe_diff, Some(diff) if A::diff_cacheable(diff));
+                let (bandwidth, user_time, batched_time, user_ops) = match maybe_diff {
+                    Some(diff) => {
+                        let bandwidth = diff.size();
+                        let (check_ops, (check_time, _)) = counters::count(|| {
+                            time_fn(|| {
+                                assert!(A::check_no_rollback(&user_state, &diff));
+                            })
+                        });
+                        let (batched_check_time, _) = time_fn(|| {
+                            assert!(A::check_no_rollback_batched(&user_state, &diff));
+                        });
+                        let (update_ops, (update_time, _)) =
+                            counters::count(|| time_fn(|| A::update(&mut user_state, diff)));
+                        (
+                            bandwidth,
+                            check_time + update_time,
+                            batched_check_time + update_time,
+                            check_ops + update_ops,
+                        )
+                    }
+                    None => (
+                        Information::new::<byte>(0),
+                        Duration::ZERO,
+                        Duration::ZERO,
+                        OpCounts::default(),
+                    ),
+                };
+                usages.push(Usage::response(bandwidth, cacheable, server_time));
+                let result = RefreshResult {
+                    packages: num_packages,
+                    elapsed_releases: Some(idx),
+                    time: user_time,
+                    batched_time,
+                    bandwidth,
+                    user_state: user_state.size(),
+                    energy: energy.estimate(&user_ops, bandwidth),
+                    cores,
+                };
+                result.insert::<A>(db)?;
+
+                let result = TargetedRefreshResult {
+                    packages: num_packages,
+                    elapsed_releases: idx,
+                    installed: installed.len(),
+                    full_bandwidth: bandwidth,
+                    targeted_bandwidth: targeted_refresh(&auth, &user_state_initial, installed),
+                    cores,
+                };
+                result.insert::<A>(db)?;
+            }
+            elapsed_releases.pop_front();
+        }
+        if elapsed_releases.is_empty() {
+            break;
+        }
+        let package = PackageId::from(format!("new_package{idx}"));
+        log.push(package.clone());
+        let (update_time, _) = time_fn(|| auth.publish(package));
+        latencies.record(Operation::Publish, update_time);
+    }
+    bar.finish();
+    insert_latencies::<A>(db, "refresh", &latencies, num_packages, cores)?;
+    Ok((auth, log, Usage::mean(&usages)))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn download_trials<A>(
+    download_trials: u16,
+    auth: A,
+    num_packages: usize,
+    db: &dyn ResultsSink,
+    packages: &[PackageId],
+    popularity: &Popularity,
+    energy: &EnergyModel,
+    targets: &TargetsModel,
+    cores: usize,
+) -> rusqlite::Result<Usage>
+where
+    A: Authenticator + Clone + Debug,
+{
+    let mut rng = rand::thread_rng();
+    let mut latencies = Latencies::default();
+    let mut usages = Vec::new();
+    println!("{download_trials} trials");
+    for i in 0..download_trials {
+        println!("trial {i}");
+        // A fresh copy each trial, so proofs cached by earlier trials don't
+        // make later ones look cheaper.
+        let auth = auth.clone();
+        let user_state = auth.get_metadata();
+        let package = popularity.choose(packages, &mut rng).unwrap();
+
+        let id = A::id(&user_state);
+        let (server_time, (revision, proof)) = time_fn(|| auth.request_file(id, package));
+        latencies.record(Operation::RequestFile, server_time);
+        let bandwidth = proof.size();
+        let targets = targets.download_size::<A>(revision, &proof);
+        usages.push(Usage::response(
+            bandwidth,
+            A::proof_cacheable(&proof),
+            server_time,
+        ));
+
+        let (user_ops, (user_time, _)) = counters::count(|| {
+            time_fn(|| A::verify_membership(&user_state, package, revision, proof))
+        });
+        latencies.record(Operation::VerifyMembership, user_time);
+
+        let result = DownloadResult {
+            packages: num_packages,
+            time: user_time,
+            bandwidth,
+            targets,
+            energy: energy.estimate(&user_ops, bandwidth),
+            cores,
+        };
+        result.insert::<A>(db)?;
+    }
+    insert_latencies::<A>(db, "download", &latencies, num_packages, cores)?;
+
+    Ok(Usage::mean(&usages))
+}
+
+#[derive(Debug, Clone)]
+struct DownloadResult {
+    packages: usize,
+    time: Duration,
+    /// The proof (or whatever else the technique sends).
+    bandwidth: Information,
+    /// Targets metadata (see [`TargetsModel`]), which every technique needs
+    /// on top of `bandwidth`.
+    targets: Information,
+    /// Estimated client energy (see [`EnergyModel`]).
+    energy: Option<f64>,
+    cores: usize,
+}
+
+impl Table for DownloadResult {
+    fn create(db: &Connection) -> rusqlite::Result<()> {
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS download_results (
+             id              INTEGER PRIMARY KEY AUTOINCREMENT,
+             technique       TEXT,
+             packages        INTEGER,
+             user_time_ns    INTEGER,
+             bandwidth_bytes INTEGER,
+             targets_bytes   INTEGER,
+             user_energy_j   REAL,
+             cores           INTEGER
+         )",
+            [],
+        )?;
+        Ok(())
+    }
+}
+
+impl Record for DownloadResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
+        rows.insert(
+            db,
+            "
+        INSERT INTO download_results (
+            technique,
+            packages,
+            user_time_ns,
+            bandwidth_bytes,
+            targets_bytes,
+            user_energy_j,
+            cores
+        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7 ) ",
+            rusqlite::params![
+                technique,
+                self.packages,
+                duration_to_ns(self.time),
+                self.bandwidth.get::<byte>(),
+                self.targets.get::<byte>(),
+                self.energy,
+                self.cores
+            ],
+        )
+    }
+}
+#[derive(Debug, Clone)]
+struct ThroughputResult {
+    packages: usize,
+    operation: &'static str,
+    wall_time: std::time::Duration,
+    latency: Percentiles,
+    cores: usize,
+}
+
+impl ThroughputResult {
+    fn new(
+        packages: usize,
+        latencies: &Latencies,
+        operation: Operation,
+        wall_time: std::time::Duration,
+        cores: usize,
+    ) -> Self {
+        Self {
+            packages,
+            operation: operation.name(),
+            wall_time,
+            latency: latencies
+                .get(operation)
+                .map(Percentiles::from)
+                .unwrap_or_default(),
+            cores,
+        }
+    }
+
+    fn ops_per_sec(&self) -> f64 {
+        self.latency.count as f64 / self.wall_time.as_secs_f64()
+    }
+}
+
+impl Table for ThroughputResult {
+    fn create(db: &Connection) -> rusqlite::Result<()> {
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS throughput_results (
+             id              INTEGER PRIMARY KEY AUTOINCREMENT,
+             technique       TEXT,
+             packages        INTEGER,
+             operation       TEXT,
+             operations      INTEGER,
+             wall_time_ns    INTEGER,
+             ops_per_sec     REAL,
+             p50_ns          INTEGER,
+             p90_ns          INTEGER,
+             p99_ns          INTEGER,
+             max_ns          INTEGER,
+             cores           INTEGER
+         )",
+            [],
+        )?;
+        Ok(())
+    }
+}
+
+impl Record for ThroughputResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
+        let wall_time_ns: u64 = self.wall_time.as_nanos().try_into().unwrap();
+        rows.insert(
+            db,
+            "
+        INSERT INTO throughput_results (
+            technique,
+            packages,
+            operation,
+            operations,
+            wall_time_ns,
+            ops_per_sec,
+            p50_ns,
+            p90_ns,
+            p99_ns,
+            max_ns,
+            cores
+        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11 ) ",
+            rusqlite::params![
+                technique,
+                self.packages,
+                self.operation,
+                self.latency.count,
+                wall_time_ns,
+                self.ops_per_sec(),
+                self.latency.p50_ns,
+                self.latency.p90_ns,
+                self.latency.p99_ns,
+                self.latency.max_ns,
+                self.cores
+            ],
+        )
+    }
+}
+
+/// What throughput workers share. Publishes lock it for writing; downloads
+/// lock it for reading, so they run in parallel with each other.
+struct ThroughputState<A: Authenticator> {
+    auth: A,
+    /// Clients' view of the repository, refreshed after each publish.
+    user_state: A::ClientSnapshot,
+    log: Vec<PackageId>,
+    republishes: Republishes,
+}
+
+/// One worker's share of [`throughput_trials`]: operations until the wall time
+/// is up.
+fn throughput_worker<A>(
+    options: &ThroughputOptions,
+    state: &std::sync::RwLock<ThroughputState<A>>,
+    packages: &[PackageId],
+    popularity: &Popularity,
+    start: std::time::Instant,
+) -> Latencies
+where
+    A: Authenticator,
+{
+    let mut rng = rand::thread_rng();
+    let mut latencies = Latencies::default();
+    while start.elapsed() < options.wall_time && !interrupt::requested() {
+        if rand::Rng::gen_bool(&mut rng, options.publish_fraction) {
+            let mut state = state.write().expect("a worker panicked");
+            let state = &mut *state;
+            let republish =
+                !state.log.is_empty() && rand::Rng::gen_bool(&mut rng, options.republish_fraction);
+            let package_id = if republish {
+                rand::seq::SliceRandom::choose(state.log.as_slice(), &mut rng)
+                    .unwrap()
+                    .clone()
+            } else {
+                PackageId::from(format!("new_package{}", state.log.len()))
+            };
+            // Packages are only ever published with the one (made-up)
+            // artifact, so a re-publish never changes anything.
+            if !state
+                .republishes
+                .admit(&package_id, Some(Targets::default()))
+            {
+                continue;
+            }
+            state.log.push(package_id.clone());
+            let (update_time, _) = time_fn(|| {
+                state.auth.publish(package_id);
+            });
+            latencies.record(Operation::Publish, update_time);
+            state.user_state = state.auth.get_metadata();
+        } else {
+            let package = popularity.choose(packages, &mut rng).unwrap();
+            let state = state.read().expect("a worker panicked");
+            let id = A::id(&state.user_state);
+            let (server_time, (revision, proof)) = time_fn(|| state.auth.request_file(id, package));
+            latencies.record(Operation::RequestFile, server_time);
+            let (user_time, verified) =
+                time_fn(|| A::verify_membership(&state.user_state, package, revision, proof));
+            assert!(verified);
+            latencies.record(Operation::VerifyMembership, user_time);
+        }
+    }
+    latencies
+}
+
+/// Drive `auth` with a mix of publishes and downloads for a fixed wall time,
+/// from `cores` threads at once.
+///
+/// Downloads are timed in two parts, the server (`request_file`) and the
+/// client (`verify_membership`), each with its own row; the client re-fetches
+/// metadata after each publish, outside of the timed region. Downloads only
+/// need `&auth`, so they're served concurrently; a publish waits for them to
+/// finish, and holds them off while it runs.
+///
+/// Returns the final server state and the packages published along the way.
+fn throughput_trials<A>(
+    options: &ThroughputOptions,
+    auth: A,
+    packages: &[PackageId],
+    popularity: &Popularity,
+    cores: usize,
+    db: &dyn ResultsSink,
+) -> rusqlite::Result<(A, Vec<PackageId>)>
+where
+    A: Authenticator + Clone + Debug + Send + Sync,
+    A::ClientSnapshot: Send + Sync,
+{
+    let user_state = auth.get_metadata();
+    let state = std::sync::RwLock::new(ThroughputState {
+        auth,
+        user_state,
+        log: Vec::new(),
+        republishes: Republishes::new(options.republish_policy),
+    });
+
+    let start = std::time::Instant::now();
+    let latencies = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..cores.max(1))
+            .map(|_| {
+                scope.spawn(|| throughput_worker(options, &state, packages, popularity, start))
+            })
+            .collect();
+        let mut latencies = Latencies::default();
+        for worker in workers {
+            latencies.merge(worker.join().expect("throughput worker panicked"));
+        }
+        latencies
+    });
+    let wall_time = start.elapsed();
+    let ThroughputState {
+        auth,
+        log,
+        republishes,
+        ..
+    } = state.into_inner().expect("a worker panicked");
+    if republishes.dropped() > 0 {
+        println!("dropped {} unchanged re-publishes", republishes.dropped());
+    }
+
+    let num_packages = packages.len();
+    for operation in [
+        Operation::Publish,
+        Operation::RequestFile,
+        Operation::VerifyMembership,
+    ] {
+        let result = ThroughputResult::new(num_packages, &latencies, operation, wall_time, cores);
+        result.insert::<A>(db)?;
+    }
+    Ok((auth, log))
+}
+
+/// How many of the initially imported packages to spot-check in
+/// [`verify_final_state`] (every published package is checked).
+const FINAL_STATE_SAMPLE: usize = 100;
+
+#[derive(Debug, Clone)]
+struct FinalStateResult {
+    packages: usize,
+    log_length: usize,
+    checked: usize,
+    mismatches: usize,
+    cores: usize,
+}
+
+impl Table for FinalStateResult {
+    fn create(db: &Connection) -> rusqlite::Result<()> {
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS final_state_results (
+             id         INTEGER PRIMARY KEY AUTOINCREMENT,
+             technique  TEXT,
+             packages   INTEGER,
+             log_length INTEGER,
+             checked    INTEGER,
+             mismatches INTEGER,
+             cores      INTEGER
+         )",
+            [],
+        )?;
+        Ok(())
+    }
+}
+
+impl Record for FinalStateResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
+        rows.insert(
+            db,
+            "
+        INSERT INTO final_state_results (
+            technique,
+            packages,
+            log_length,
+            checked,
+            mismatches,
+            cores
+        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6 ) ",
+            rusqlite::params![
+                technique,
+                self.packages,
+                self.log_length,
+                self.checked,
+                self.mismatches,
+                self.cores
+            ],
+        )
+    }
+}
+
+/// Estimated monthly cost of running a technique (see [`CostConfig`]).
+#[derive(Debug, Clone)]
+struct CostResult {
+    packages: usize,
+    monthly: Cost,
+    cores: usize,
+}
+
+impl Table for CostResult {
+    fn create(db: &Connection) -> rusqlite::Result<()> {
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS cost_results (
+             id             INTEGER PRIMARY KEY AUTOINCREMENT,
+             technique      TEXT,
+             packages       INTEGER,
+             cdn_dollars    REAL,
+             origin_dollars REAL,
+             cpu_dollars    REAL,
+             total_dollars  REAL,
+             cores          INTEGER
+         )",
+            [],
+        )?;
+        Ok(())
+    }
+}
+
+impl Record for CostResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
+        rows.insert(
+            db,
+            "
+        INSERT INTO cost_results (
+            technique,
+            packages,
+            cdn_dollars,
+            origin_dollars,
+            cpu_dollars,
+            total_dollars,
+            cores
+        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7 ) ",
+            rusqlite::params![
+                technique,
+                self.packages,
+                self.monthly.cdn,
+                self.monthly.origin,
+                self.monthly.cpu,
+                self.monthly.total(),
+                self.cores
+            ],
+        )
+    }
+}
+
+/// Operations done in one phase of a run (with the `counters` feature; see
+/// [`sssim::counters`]).
+#[derive(Debug, Clone)]
+struct OpCountResult {
+    phase: &'static str,
+    packages: usize,
+    counts: OpCounts,
+    cores: usize,
+}
+
+impl Table for OpCountResult {
+    fn create(db: &Connection) -> rusqlite::Result<()> {
+        db.execute(
+            "CREATE TABLE IF NOT EXISTS op_count_results (
+             id           INTEGER PRIMARY KEY AUTOINCREMENT,
+             technique    TEXT,
+             phase        TEXT,
+             packages     INTEGER,
+             group_ops    INTEGER,
+             group_exps   INTEGER,
+             hashes       INTEGER,
+             bigint_mults INTEGER,
+             cores        INTEGER
+         )",
+            [],
+        )?;
+        Ok(())
+    }
+}
+
+impl Record for OpCountResult {
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()> {
+        rows.insert(
+            db,
+            "
+        INSERT INTO op_count_results (
+            technique,
+            phase,
+            packages,
+            group_ops,
+            group_exps,
+            hashes,
+            bigint_mults,
+            cores
+        ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8 ) ",
+            rusqlite::params![
+                technique,
+                self.phase,
+                self.packages,
+                self.counts.group_ops,
+                self.counts.group_exps,
+                self.counts.hashes,
+                self.counts.bigint_mults,
+                self.cores
+            ],
+        )
+    }
+}
+
+/// Record the operations done since `since` as `phase` (if we're counting),
+/// returning the new baseline.
+fn record_ops<A: Authenticator>(
+    db: &dyn ResultsSink,
+    phase: &'static str,
+    since: OpCounts,
+    packages: usize,
+    cores: usize,
+) -> rusqlite::Result<OpCounts> {
+    let now = counters::snapshot();
+    if counters::ENABLED {
+        let result = OpCountResult {
+            phase,
+            packages,
+            counts: now - since,
+            cores,
+        };
+        result.insert::<A>(db)?;
+    }
+    Ok(now)
+}
+
+/// Replay `log` against a freshly imported authenticator and check that it
+/// agrees with `auth` (the instance we benchmarked).
+///
+/// Each checked package must have the same revision on both servers, and each
+/// server's proof must verify against the other's snapshot (which pins down
+/// the digest/root for techniques that have one). A mismatch means the
+/// technique is nondeterministic or its state got corrupted, so the results
+/// from this run can't be trusted.
+fn verify_final_state<A>(
+    auth: A,
+    packages: &[PackageId],
+    log: &[PackageId],
+    technique: &TechniqueConfig,
+    cores: usize,
+    db: &dyn ResultsSink,
+) -> rusqlite::Result<()>
+where
+    A: Authenticator + Debug,
+{
+    let mut replayed = A::batch_import_with(packages.to_vec(), settings::<A>(technique));
+    for package in log {
+        replayed.publish(package.clone());
+    }
+    let snapshot = auth.get_metadata();
+    let replayed_snapshot = replayed.get_metadata();
+
+    let mut rng = rand::thread_rng();
+    let sample = rand::seq::SliceRandom::choose_multiple(packages, &mut rng, FINAL_STATE_SAMPLE);
+    let mut checked = 0;
+    let mut mismatches = 0;
+    for package in log.iter().chain(sample) {
+        let (revision, proof) = auth.request_file(A::id(&snapshot), package);
+        let (replayed_revision, replayed_proof) =
+            replayed.request_file(A::id(&replayed_snapshot), package);
+        let ok = revision == replayed_revision
+            && A::verify_membership(&replayed_snapshot, package, revision, proof)
+            && A::verify_membership(&snapshot, package, replayed_revision, replayed_proof);
+        checked += 1;
+        if !ok {
+            println!("final state mismatch: {package:?}");
+            mismatches += 1;
+        }
+    }
+
+    let result = FinalStateResult {
+        packages: packages.len(),
+        log_length: log.len(),
+        checked,
+        mismatches,
+        cores,
+    };
+    result.insert::<A>(db)?;
+    assert_eq!(
+        mismatches,
+        0,
+        "{}: replaying the log gave a different final state",
+        A::name()
+    );
+    Ok(())
+}
+
+/// Write out the last phase's results and announce the next phase of a run,
+/// unless we've been interrupted, in which case the run should stop (keeping
+/// what it's recorded so far).
+fn next_phase(db: &dyn ResultsSink, name: &str) -> rusqlite::Result<bool> {
+    db.flush()?;
+    if interrupt::requested() {
+        return Ok(false);
+    }
+    println!("{name}");
+    Ok(true)
+}
+
+static PRECOMPUTE_TRIALS: u16 = 1;
+static UPDATE_TRIALS: u16 = 1;
+static REFRESH_TRIALS: u16 = 1;
+static DOWNLOAD_TRIALS: u16 = 1;
+
+fn run<A>(
+    packages: Vec<PackageId>,
+    db: &dyn ResultsSink,
+    options: &RunOptions,
+) -> rusqlite::Result<OverallTimeResult>
+where
+    A: Authenticator + WarmState + Clone + Debug + Send + Sync,
+    A::ClientSnapshot: Send + Sync,
+{
+    let cores = options.cores;
+    let num_packages = packages.len();
+    let update = |auth: &A, ops: &mut OpCounts, extrapolation: Option<&Extrapolation>| {
+        if !next_phase(db, "update")? {
+            return Ok(None);
+        }
+        let update = update_trials(UPDATE_TRIALS, auth, num_packages, cores, db)?;
+        if let Some(extrapolation) = extrapolation {
+            let result = ExtrapolatedResult::new(
+                "update",
+                extrapolation.scaling.update,
+                update.time,
+                update.server_state,
+                update.cdn_size,
+                extrapolation,
+                cores,
+            );
+            result.insert::<A>(db)?;
+        }
+        *ops = record_ops::<A>(db, "update", *ops, num_packages, cores)?;
+
+        if !options.publish_batches.is_empty() {
+            for &batch_size in &options.publish_batches {
+                println!("publish batch: {batch_size}");
+                publish_batch_trials(UPDATE_TRIALS, auth, batch_size, num_packages, cores, db)?;
+            }
+            *ops = record_ops::<A>(db, "publish_batch", *ops, num_packages, cores)?;
+        }
+        Ok(Some(Usage {
+            server_compute: update.time,
+            ..Default::default()
+        }))
+    };
+    let publish = |auth: &mut A, package| auth.publish(package);
+    run_phases(packages, db, options, update, publish)
+}
+
+fn run_batch<A>(
+    packages: Vec<PackageId>,
+    db: &dyn ResultsSink,
+    batch_sizes: Vec<u16>,
+    options: &RunOptions,
+) -> rusqlite::Result<OverallTimeResult>
+where
+    A: PoolAuthenticator + WarmState + Clone + Debug + Send + Sync,
+    A::ClientSnapshot: Send + Sync,
+{
+    let cores = options.cores;
+    let num_packages = packages.len();
+    let update = |auth: &A, ops: &mut OpCounts, _: Option<&Extrapolation>| {
+        let mut publishes = Vec::new();
+        for batch_size in batch_sizes {
+            if !next_phase(db, &format!("batch_size: {batch_size}"))? {
+                return Ok(None);
+            }
+            publishes.push(batch_update_trials(
+                UPDATE_TRIALS,
+                auth,
+                batch_size,
+                num_packages,
+                cores,
+                db,
+            )?);
+        }
+        *ops = record_ops::<A>(db, "update", *ops, num_packages, cores)?;
+        Ok(Some(Usage::mean(&publishes)))
+    };
+    let publish = |auth: &mut A, package| {
+        auth.publish(package);
+        auth.batch_process();
+    };
+    run_phases(packages, db, options, update, publish)
+}
+
+/// Runs (and records) every phase for whichever technique it's handed.
+struct Benchmark<'a> {
+    packages: Vec<PackageId>,
+    db: &'a dyn ResultsSink,
+    options: &'a RunOptions,
+}
+
+impl Visitor for Benchmark<'_> {
+    type Output = rusqlite::Result<()>;
 
-impl Table for DownloadResult {
-    fn create(db: &Connection) -> rusqlite::Result<()> {
-        db.execute(
-            "CREATE TABLE IF NOT EXISTS download_results (
-             id              INTEGER PRIMARY KEY AUTOINCREMENT,
-             technique       TEXT,
-             packages        INTEGER,
-             user_time_ns    INTEGER,
-             bandwidth_bytes INTEGER,
-             cores           INTEGER
-         )",
-            [],
-        )?;
-        Ok(())
+    fn visit<A>(&mut self, _: Technique) -> Self::Output
+    where
+        A: Authenticator + WarmState + Clone + Debug + Send + Sync + 'static,
+        A::ClientSnapshot: Send + Sync,
+        A::Diff: Send,
+        A::Proof: Send,
+    {
+        let packages = std::mem::take(&mut self.packages);
+        let result = run::<A>(packages, self.db, self.options)?;
+        result.insert::<A>(self.db)
     }
 
-    fn insert<A: Authenticator>(&self, db: &Connection) -> rusqlite::Result<usize> {
-        db.execute(
-            "
-        INSERT INTO download_results (
-            technique,
-            packages,
-            user_time_ns,
-            bandwidth_bytes,
-            cores
-        ) VALUES ( ?1, ?2, ?3, ?4, ?5 ) ",
-            rusqlite::params![
-                A::name(),
-                self.packages,
-                duration_to_ns(self.time),
-                self.bandwidth.get::<byte>(),
-                self.cores
-            ],
-        )
+    fn visit_pool<A>(&mut self, _: Technique) -> Self::Output
+    where
+        A: PoolAuthenticator + WarmState + Clone + Debug + Send + Sync + 'static,
+        A::ClientSnapshot: Send + Sync,
+        A::Diff: Send,
+        A::Proof: Send,
+    {
+        let packages = std::mem::take(&mut self.packages);
+        let batch_sizes = vec![100, 200, 300, 400, 500, 600, 700, 800, 900, 1000];
+        let result = run_batch::<A>(packages, self.db, batch_sizes, self.options)?;
+        result.insert::<A>(self.db)
     }
 }
-fn run<A>(
+
+/// The phases of a run, shared by [`run`] and [`run_batch`].
+///
+/// They differ only in how they publish: `update` runs the update phase(s)
+/// (recording its op counts, and returning `None` if interrupted), and
+/// `publish` publishes one package the same way (for the growth phase).
+fn run_phases<A>(
     packages: Vec<PackageId>,
-    db: &Connection,
-    cores: usize,
+    db: &dyn ResultsSink,
+    options: &RunOptions,
+    update: impl FnOnce(&A, &mut OpCounts, Option<&Extrapolation>) -> rusqlite::Result<Option<Usage>>,
+    publish: impl FnMut(&mut A, PackageId),
 ) -> rusqlite::Result<OverallTimeResult>
 where
-    A: Authenticator + Clone + Debug,
+    A: Authenticator + WarmState + Clone + Debug + Send + Sync,
+    A::ClientSnapshot: Send + Sync,
 {
+    let cores = options.cores;
     let num_packages = packages.len();
+    let popularity = Popularity::new(options.download_zipf, num_packages)
+        .expect("--download-zipf is checked up front");
+    let cpu_start = sysmetrics::cpu_time();
     let (runtime, err) = Duration::time_fn(|| {
-        static PRECOMPUTE_TRIALS: u16 = 1;
-        static UPDATE_TRIALS: u16 = 1;
-        static REFRESH_TRIALS: u16 = 1;
-        static DOWNLOAD_TRIALS: u16 = 1;
-
+        let mut ops = counters::snapshot();
         println!("precompute");
-        let auth: A = precompute_trials(PRECOMPUTE_TRIALS, db, &packages, cores)?;
+        let (auth, precompute): (A, _) = precompute_trials(
+            PRECOMPUTE_TRIALS,
+            db,
+            &packages,
+            &options.technique,
+            options.srs,
+            cores,
+        )?;
+        ops = record_ops::<A>(db, "precompute", ops, num_packages, cores)?;
+        let extrapolation = Extrapolation::for_run(options, num_packages);
+        if let Some(extrapolation) = &extrapolation {
+            let result = ExtrapolatedResult::new(
+                "precompute",
+                extrapolation.scaling.precompute,
+                precompute.time,
+                precompute.server_state,
+                precompute.cdn_size,
+                extrapolation,
+                cores,
+            );
+            result.insert::<A>(db)?;
+        }
+
+        if let Some(throughput) = &options.throughput {
+            if !next_phase(db, "throughput")? {
+                return Ok(());
+            }
+            let (auth, log) =
+                throughput_trials(throughput, auth, &packages, &popularity, cores, db)?;
+            if options.verify_final_state {
+                if !next_phase(db, "verify final state")? {
+                    return Ok(());
+                }
+                verify_final_state(auth, &packages, &log, &options.technique, cores, db)?;
+            }
+            return Ok(());
+        }
+
+        let publish_usage = match update(&auth, &mut ops, extrapolation.as_ref())? {
+            Some(usage) => usage,
+            None => return Ok(()),
+        };
 
-        println!("update");
-        update_trials(UPDATE_TRIALS, &auth, num_packages, cores, db)?;
+        if let Some(growth) = &options.growth {
+            if !next_phase(db, "growth")? {
+                return Ok(());
+            }
+            growth_trials(growth, auth.clone(), &packages, publish, db)?;
+        }
 
-        println!("refresh");
+        if !next_phase(db, "refresh")? {
+            return Ok(());
+        }
         let user_state_initial = create_user_state(REFRESH_TRIALS, &auth, num_packages, cores, db)?;
 
-        refresh_user_state(
+        let installed = choose_installed(&packages, &popularity);
+        let (final_auth, log, refresh) = refresh_user_state(
             REFRESH_TRIALS,
             &auth,
             num_packages,
             db,
             user_state_initial,
+            &installed,
+            elapsed_releases(options.refresh_intervals.as_ref(), num_packages),
+            &options.energy,
             cores,
         )?;
+        ops = record_ops::<A>(db, "refresh", ops, num_packages, cores)?;
 
-        println!("download");
-        download_trials(DOWNLOAD_TRIALS, auth, num_packages, db, packages, cores)?;
-        Ok(())
-    });
-    err.map(|_| OverallTimeResult {
-        runtime,
-        packages: num_packages,
-        cores,
-    })
-}
-
-fn run_batch<A>(
-    packages: Vec<PackageId>,
-    db: &Connection,
-    batch_sizes: Vec<u16>,
-    cores: usize,
-) -> rusqlite::Result<OverallTimeResult>
-where
-    A: PoolAuthenticator + Clone + Debug,
-{
-    let num_packages = packages.len();
-    let (runtime, err) = Duration::time_fn(|| {
-        static PRECOMPUTE_TRIALS: u16 = 1;
-        static UPDATE_TRIALS: u16 = 1;
-        static REFRESH_TRIALS: u16 = 1;
-        static DOWNLOAD_TRIALS: u16 = 1;
+        if let Some(subscribers) = options.subscribers {
+            if !next_phase(db, "push")? {
+                return Ok(());
+            }
+            push_trials(&auth, subscribers, num_packages, cores, db)?;
+            ops = record_ops::<A>(db, "push", ops, num_packages, cores)?;
+        }
 
-        println!("precompute");
-        let auth: A = precompute_trials(PRECOMPUTE_TRIALS, db, &packages, cores)?;
+        if let Some(publishes) = options.cdn_publishes {
+            if !next_phase(db, "cdn purges")? {
+                return Ok(());
+            }
+            cdn_purge_trials(&auth, publishes, &packages, cores, db)?;
+            ops = record_ops::<A>(db, "cdn_purge", ops, num_packages, cores)?;
+        }
 
-        for batch_size in batch_sizes {
-            println!("batch_size: {batch_size}");
-            batch_update_trials(UPDATE_TRIALS, &auth, batch_size, num_packages, cores, db)?;
+        if let Some(trials) = options.fault_trials {
+            if !next_phase(db, "faults")? {
+                return Ok(());
+            }
+            fault_trials(&auth, trials, &packages, cores, db)?;
+            ops = record_ops::<A>(db, "faults", ops, num_packages, cores)?;
         }
 
-        println!("refresh");
-        let user_state_initial = create_user_state(REFRESH_TRIALS, &auth, num_packages, cores, db)?;
+        if let Some(model) = &options.edge {
+            if !next_phase(db, "edge")? {
+                return Ok(());
+            }
+            edge_trials(&auth, model, &packages, cores, db)?;
+            ops = record_ops::<A>(db, "edge", ops, num_packages, cores)?;
+        }
 
-        refresh_user_state(
-            REFRESH_TRIALS,
-            &auth,
+        if !next_phase(db, "download")? {
+            return Ok(());
+        }
+        let download = download_trials(
+            DOWNLOAD_TRIALS,
+            auth,
             num_packages,
             db,
-            user_state_initial,
+            &packages,
+            &popularity,
+            &options.energy,
+            &options.targets,
             cores,
         )?;
+        record_ops::<A>(db, "download", ops, num_packages, cores)?;
 
-        println!("download");
-        download_trials(DOWNLOAD_TRIALS, auth, num_packages, db, packages, cores)?;
+        if options.verify_final_state {
+            if !next_phase(db, "verify final state")? {
+                return Ok(());
+            }
+            verify_final_state(final_auth, &packages, &log, &options.technique, cores, db)?;
+        }
 
+        if let Some(cost) = &options.cost {
+            let usage = OperationUsage {
+                download,
+                refresh,
+                publish: publish_usage,
+            };
+            let result = CostResult {
+                packages: num_packages,
+                monthly: cost.monthly_cost(&usage),
+                cores,
+            };
+            println!("estimated monthly cost: ${:.2}", result.monthly.total());
+            result.insert::<A>(db)?;
+        }
         Ok(())
     });
+    let cpu_time = sysmetrics::cpu_time()
+        .zip(cpu_start)
+        .map(|(end, start)| end - start);
     err.map(|_| OverallTimeResult {
         runtime,
         packages: num_packages,
         cores,
+        cpu_time,
+        peak_rss: sysmetrics::peak_rss(),
+        interrupted: interrupt::requested(),
+    })
+}
+
+/// Techniques to run if `--authenticators` isn't given.
+fn default_authenticators() -> Vec<String> {
+    authenticator::Authenticators::all()
+        .into_iter()
+        .filter(Technique::by_default)
+        .map(|technique| technique.name().to_string())
+        .collect()
+}
+
+/// Run (or write a job file for) every configuration in a sweep.
+fn run_sweep(args: SweepArgs) -> io::Result<()> {
+    let authenticators = if args.authenticators.is_empty() {
+        default_authenticators()
+    } else {
+        args.authenticators
+    };
+    let configs = sweep::expand(&args.packages, &authenticators, &args.threads);
+    // Check it once here, rather than in every run.
+    let settings = load_config(args.config.as_deref())?;
+    let mut extra = args.extra;
+    if let Some(path) = &args.config {
+        let path = std::fs::canonicalize(path)?;
+        extra.extend(["--config".to_string(), path.to_string_lossy().into_owned()]);
+    }
+    if args.dry_run {
+        let mut total = Duration::ZERO;
+        for config in &configs {
+            let estimate = Model::for_technique(&config.authenticator)
+                .map(|model| model.estimate(config.packages));
+            let description = format!(
+                "{} packages, {}, {} threads",
+                config.packages, config.authenticator, config.threads
+            );
+            match estimate {
+                Some(estimate) => {
+                    println!("{description}: {estimate}");
+                    total += estimate.runtime;
+                }
+                None => println!("{description}: no estimate"),
+            }
+        }
+        println!("{} runs; ~{total:.1} in all (one at a time)", configs.len());
+        return Ok(());
+    }
+    let db = sink::open(&args.results).expect("creating SQLite db");
+    create_tables(&db)?;
+    sweep::create_runs_table(&db).unwrap();
+    let sweep_id = time::OffsetDateTime::now_utc().unix_timestamp();
+    let exe = std::env::current_exe()?;
+    let mut job_file = args
+        .job_file
+        .as_deref()
+        .map(std::fs::File::create)
+        .transpose()?;
+    // For Slurm, each run writes its own db, next to the job file.
+    let job_dir = match (&args.job_file, args.emit_jobs.unwrap_or_default()) {
+        (Some(path), JobFormat::Slurm) => {
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => std::path::Path::new("."),
+            };
+            std::fs::create_dir_all(dir.join("results"))?;
+            std::fs::create_dir_all(dir.join("logs"))?;
+            Some(std::fs::canonicalize(dir)?)
+        }
+        _ => None,
+    };
+
+    if job_file.is_none() {
+        interrupt::install();
+    }
+
+    let mut failed = 0;
+    let mut jobs = 0;
+    for config in configs {
+        let technique = settings.technique(&config.authenticator);
+        let run_id = sweep::record_run(&db, sweep_id, &config, &extra, &technique).unwrap();
+        if let Some(job_file) = &mut job_file {
+            let results = match &job_dir {
+                Some(dir) => sweep::job_results(dir, run_id),
+                None => args.results.clone(),
+            };
+            let run_args = config.args(&results, run_id, &extra);
+            writeln!(job_file, "{}", sweep::command_line(&exe, &run_args))?;
+            jobs += 1;
+            continue;
+        }
+        let run_args = config.args(&args.results, run_id, &extra);
+        println!(
+            "\nrun {run_id}: {} packages, {}, {} threads",
+            config.packages, config.authenticator, config.threads
+        );
+        let status = std::process::Command::new(&exe).args(&run_args).status()?;
+        let run_status = if status.success() {
+            RunStatus::Done
+        } else if status.code() == Some(interrupt::EXIT_CODE) {
+            RunStatus::Interrupted
+        } else {
+            eprintln!("run {run_id} failed ({status})");
+            failed += 1;
+            RunStatus::Failed
+        };
+        sweep::set_status(&db, run_id, run_status).unwrap();
+        // The run got the interrupt too, so it's already stopped.
+        if interrupt::requested() {
+            println!("interrupted; stopping the sweep after run {run_id}");
+            drop(db);
+            std::process::exit(interrupt::EXIT_CODE);
+        }
+    }
+    if let Some(path) = &args.job_file {
+        println!("wrote jobs to {}", path.display());
+    }
+    if let (Some(path), Some(dir)) = (&args.job_file, &job_dir) {
+        let script = path.with_extension("sbatch");
+        let cpus = args.threads.iter().copied().max().unwrap_or(1);
+        let job_file = dir.join(path.file_name().expect("job file name"));
+        std::fs::write(
+            &script,
+            sweep::sbatch_script(sweep_id, &job_file, jobs, cpus),
+        )?;
+        println!(
+            "wrote {}; once the jobs finish, run:\n  sssim merge-results --output {} {}",
+            script.display(),
+            args.results.display(),
+            dir.join("results").join("*.db").display(),
+        );
+    }
+    if failed > 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("{failed} runs failed"),
+        ));
+    }
+    Ok(())
+}
+
+/// Tally the missed releases between each user's refreshes in the trace at
+/// `trace`, and save them to `output`.
+fn refresh_intervals(trace: &Path, output: &Path) -> io::Result<()> {
+    let log = sssim::log::Log::load(trace)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+    let intervals = RefreshIntervals::from_entries(log.entries());
+    println!("{intervals}");
+    intervals
+        .save(output)
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))
+}
+
+/// Load intervals saved by [`refresh_intervals`], for runs to draw from.
+fn load_refresh_intervals(path: &Path) -> io::Result<RefreshIntervals> {
+    let intervals = RefreshIntervals::load(path)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+    if intervals.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: no refresh intervals to draw from", path.display()),
+        ));
+    }
+    Ok(intervals)
+}
+
+/// Check every claim in `claims` against the results db at `results`.
+fn check_claims(results: &Path, claims: &Path) -> io::Result<()> {
+    let claims = sssim::claims::load(claims)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+    let db = Connection::open_with_flags(results, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                format!("{}: {err}", results.display()),
+            )
+        })?;
+    let mut failed = 0;
+    for claim in &claims {
+        let verdict = claim
+            .check(&db)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+        println!("{verdict}");
+        if !verdict.holds() {
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("{failed} of {} claims don't hold", claims.len()),
+        ));
+    }
+    println!("all {} claims hold", claims.len());
+    Ok(())
+}
+
+/// Merge each of `inputs` into the results db at `output`.
+fn merge_results(output: &PathBuf, inputs: &[PathBuf]) -> io::Result<()> {
+    let mut db = sink::open(output).expect("creating SQLite db");
+    create_tables(&db)?;
+    sweep::create_runs_table(&db).unwrap();
+    for input in inputs {
+        let rows = sweep::merge_results(&mut db, input).map_err(|err| {
+            io::Error::new(io::ErrorKind::Other, format!("{}: {err}", input.display()))
+        })?;
+        println!("merged {rows} rows from {}", input.display());
+    }
+    Ok(())
+}
+
+fn run_soak(args: SoakArgs) -> io::Result<()> {
+    #[cfg(feature = "rsa")]
+    {
+        if let Some(path) = &args.group_params {
+            install_group_params(path)?;
+        }
+    }
+    let technique = authenticator::Authenticators::by_name(&args.authenticator)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+    let settings = load_config(args.config.as_deref())?;
+    let packages: Vec<_> = (0..args.packages)
+        .map(|i| PackageId::from(format!("package{i}")))
+        .collect();
+    let server = technique
+        .import_with(packages.clone(), &settings.technique(technique.name()))
+        .expect("invalid config");
+    let options = soak::SoakOptions {
+        mix: args.mix,
+        clients: args.clients,
+        new_package_fraction: args.new_package_fraction,
+        batch: args.batch,
+        check_every: args.check_every,
+        wall_time: std::time::Duration::from_secs(args.seconds),
+        max_operations: args.operations,
+        seed: args.seed,
+    };
+    let start = std::time::Instant::now();
+    let stats = soak::run(server, packages, &options, |stats| {
+        println!(
+            "{:.0?}: {} operations ({} publishes, {} refreshes, {} downloads); invariants hold",
+            start.elapsed(),
+            stats.operations,
+            stats.publishes,
+            stats.refreshes,
+            stats.downloads
+        );
+    })
+    .map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::Other,
+            format!("{err} (replay with --seed {})", args.seed),
+        )
+    })?;
+    println!(
+        "{}: {} operations, {} checks, all passed",
+        technique.name(),
+        stats.operations,
+        stats.checks
+    );
+    Ok(())
+}
+
+fn run_tune(args: TuneArgs) -> io::Result<()> {
+    #[cfg(feature = "rsa")]
+    {
+        if let Some(path) = &args.group_params {
+            install_group_params(path)?;
+        }
+    }
+    let technique = authenticator::Authenticators::by_name(&args.authenticator)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+    let options = tune::TuneOptions {
+        rounds: args.rounds,
+        publishes_per_round: args.publishes_per_round,
+        clients: args.clients,
+        refresh_fraction: args.refresh_fraction,
+        new_package_fraction: args.new_package_fraction,
+        max_publish_p95: args
+            .max_publish_p95_us
+            .map(|us| Duration::microseconds(us as i64)),
+        seed: args.seed,
+    };
+    let packages: Vec<_> = (0..args.packages)
+        .map(|i| PackageId::from(format!("package{i}")))
+        .collect();
+    let report = tune::tune(&technique, &packages, &args.knobs, &options, |trial| {
+        println!("{trial}")
+    })
+    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+    let section = match report.best_section() {
+        Some(section) => section,
+        None => {
+            println!("no settings met the publish latency budget");
+            return Ok(());
+        }
+    };
+    match &args.output {
+        Some(path) => {
+            std::fs::write(path, section)?;
+            println!("wrote the best settings to {}", path.display());
+        }
+        None => print!("best:\n{section}"),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "zstd")]
+fn run_dictionary(args: DictionaryArgs) -> io::Result<()> {
+    use sssim::dictionary::{self, DictionaryOptions};
+
+    let options = DictionaryOptions {
+        diffs: args.diffs,
+        publishes_per_diff: args.publishes_per_diff,
+        new_package_fraction: args.new_package_fraction,
+        train_fraction: args.train_fraction,
+        dictionary_size: args.dictionary_size,
+        level: args.level,
+        seed: args.seed,
+    };
+    let packages: Vec<_> = (0..args.packages)
+        .map(|i| PackageId::from(format!("package{i}")))
+        .collect();
+    let show =
+        |ratio: Option<sssim::report::Fixed>| ratio.map_or("-".to_string(), |r| r.to_string());
+    for name in &args.authenticators {
+        let technique = authenticator::Authenticators::by_name(name)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+        let mut server = technique.import(packages.clone());
+        let diffs = dictionary::collect_diffs(server.as_mut(), &packages, &options);
+        let report = dictionary::evaluate(&diffs, &options)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, format!("{name}: {err}")))?;
+        println!(
+            "{name}: {} diffs, {} bytes raw; zstd {} bytes ({}x); with a {}-byte dictionary {} bytes ({}x; {})",
+            report.compressed,
+            report.raw_bytes,
+            report.zstd_bytes,
+            show(report.zstd_ratio()),
+            report.dictionary_bytes,
+            report.dictionary_zstd_bytes,
+            show(report.dictionary_ratio()),
+            report.break_even_diffs().map_or_else(
+                || "never pays for itself".to_string(),
+                |diffs| format!("pays for itself after {diffs} diffs")
+            ),
+        );
+    }
+    Ok(())
+}
+
+fn run_state_loss(args: StateLossArgs) -> io::Result<()> {
+    use sssim::persistence::{self, SensitivityOptions};
+
+    #[cfg(feature = "rsa")]
+    {
+        if let Some(path) = &args.group_params {
+            install_group_params(path)?;
+        }
+    }
+    if let Some(rate) = args.rates.iter().find(|rate| !(0.0..=1.0).contains(*rate)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("state loss rate {rate} isn't a probability"),
+        ));
+    }
+    let options = SensitivityOptions {
+        rounds: args.rounds,
+        publishes_per_round: args.publishes_per_round,
+        clients: args.clients,
+        refresh_fraction: args.refresh_fraction,
+        new_package_fraction: args.new_package_fraction,
+        seed: args.seed,
+    };
+    let packages: Vec<_> = (0..args.packages)
+        .map(|i| PackageId::from(format!("package{i}")))
+        .collect();
+    for name in &args.authenticators {
+        let technique = authenticator::Authenticators::by_name(name)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+        println!(
+            "{}",
+            persistence::sensitivity(&technique, &packages, &args.rates, &options)
+        );
+    }
+    Ok(())
+}
+
+fn load_trace(path: &Path) -> io::Result<Log> {
+    Log::load(path).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: {err}", path.display()),
+        )
     })
 }
 
+fn run_growth(args: GrowthArgs) -> io::Result<()> {
+    #[cfg(feature = "rsa")]
+    {
+        if let Some(path) = &args.group_params {
+            install_group_params(path)?;
+        }
+    }
+    let growth = args
+        .growth
+        .options(Some(GrowthModel::Linear { per_day: 10.0 }))?
+        .expect("a default growth model");
+    let entries: Vec<_> = match &args.trace {
+        Some(path) => load_trace(path)?.into_iter().collect(),
+        None => Vec::new(),
+    };
+    let (log, start) = growth.log(args.packages, entries);
+
+    let db = match &args.results {
+        Some(path) => {
+            let db = SqliteSink::open(path).expect("creating SQLite db");
+            create_tables(db.connection())?;
+            Some(db)
+        }
+        None => None,
+    };
+    let packages: Vec<_> = (0..args.packages)
+        .map(|i| PackageId::from(format!("package{i}")))
+        .collect();
+    for name in &args.authenticators {
+        let technique = authenticator::Authenticators::by_name(name)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+        let mut server = technique.import(packages.clone());
+        let results = storage_curve_trials(
+            name,
+            &growth,
+            (&log, start),
+            server.as_mut(),
+            |server, package| {
+                server.publish(package);
+                server.batch_process();
+            },
+            &packages,
+        );
+        if let Some(db) = &db {
+            for result in results {
+                db.record(technique.name(), Box::new(result)).unwrap();
+            }
+            db.flush().unwrap();
+        }
+    }
+    Ok(())
+}
+
+fn security_table(args: SecurityTableArgs) -> io::Result<()> {
+    let modulus_bits = args.modulus_bits;
+    #[cfg(feature = "rsa")]
+    let modulus_bits = match &args.group_params {
+        Some(path) => Some(
+            load_group_params(path)?
+                .bits()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?,
+        ),
+        None => modulus_bits,
+    };
+    let params: Vec<_> = args
+        .levels
+        .iter()
+        .map(|&level| {
+            let params = Parameters::new(level, args.packages);
+            match modulus_bits {
+                Some(bits) => params.with_modulus_bits(bits),
+                None => params,
+            }
+        })
+        .collect();
+    let table = SizeTable::new(&params, args.packages, args.missed_releases).render(args.format);
+    match &args.output {
+        Some(path) => {
+            std::fs::write(path, table)?;
+            println!("wrote the table to {}", path.display());
+        }
+        None => print!("{table}"),
+    }
+    Ok(())
+}
+
 fn main() -> io::Result<()> {
     let args: Args = Args::parse();
 
+    match args.command {
+        #[cfg(feature = "rsa")]
+        Some(Command::GenModulus { bits, output }) => {
+            let params = GroupParameters::generate(bits);
+            params
+                .save(&output)
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+            println!(
+                "wrote {}-bit modulus to {}",
+                params.bits().expect("generated moduli are valid"),
+                output.display()
+            );
+            return Ok(());
+        }
+        Some(Command::Sweep(sweep_args)) => return run_sweep(sweep_args),
+        Some(Command::MergeResults { output, inputs }) => return merge_results(&output, &inputs),
+        Some(Command::CheckClaims { results, claims }) => return check_claims(&results, &claims),
+        Some(Command::RefreshIntervals { trace, output }) => {
+            return refresh_intervals(&trace, &output)
+        }
+        Some(Command::Soak(soak_args)) => return run_soak(soak_args),
+        Some(Command::Tune(tune_args)) => return run_tune(tune_args),
+        #[cfg(feature = "zstd")]
+        Some(Command::Dictionary(dictionary_args)) => return run_dictionary(dictionary_args),
+        Some(Command::StateLoss(state_loss_args)) => return run_state_loss(state_loss_args),
+        Some(Command::Growth(growth_args)) => return run_growth(growth_args),
+        Some(Command::SecurityTable(table_args)) => return security_table(table_args),
+        None => {}
+    }
+    #[cfg(feature = "rsa")]
+    {
+        if let Some(path) = &args.group_params {
+            install_group_params(path)?;
+        }
+    }
+    let fractions = [
+        ("--publish-fraction", args.publish_fraction),
+        ("--republish-fraction", args.republish_fraction),
+    ];
+    if let Some((flag, fraction)) = fractions
+        .iter()
+        .find(|(_, fraction)| !(0.0..=1.0).contains(fraction))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{flag} {fraction} isn't a fraction"),
+        ));
+    }
+    args.sizing_policy.install().expect("setting sizing policy");
+    let num_packages = args.packages.expect("--packages is required");
+    Popularity::new(args.download_zipf, num_packages)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+    let authenticators: Vec<String> = match args.authenticators {
+        Some(authenticators) => authenticators.split(',').map(String::from).collect(),
+        None => default_authenticators(),
+    };
+    // Catch a typo now, rather than after the runs before it.
+    for authenticator in &authenticators {
+        let technique = authenticator::Authenticators::by_name(authenticator)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+        if args.srs.is_some() && !technique.uses_srs() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--srs: {authenticator} doesn't use a structured reference string"),
+            ));
+        }
+    }
+    if args.dry_run {
+        println!("estimates for {num_packages} packages:");
+        for authenticator in &authenticators {
+            match Model::for_technique(authenticator) {
+                Some(model) => println!("{authenticator}: {}", model.estimate(num_packages)),
+                None => println!("{authenticator}: no estimate"),
+            }
+        }
+        return Ok(());
+    }
+    let results = args.results.expect("--results is required");
+
     rayon::ThreadPoolBuilder::new()
         .num_threads(args.threads)
         .build_global()
         .unwrap();
 
-    let authenticators: Vec<String> = match args.authenticators {
-        Some(authenticators) => authenticators.split(',').map(String::from).collect(),
-        None => vec![
-            "insecure",
-            "hackage",
-            "mercury_diff",
-            "sparse_merkle",
-            "rsa",
-            "rsa_pool",
-            "mercury",
-        ]
-        .into_iter()
-        .map(String::from)
-        .collect(),
-    };
-    let packages: Vec<_> = (0..args.packages)
+    let packages: Vec<_> = (0..num_packages)
         .map(|i| format!("package{i}"))
         .map(PackageId::from)
         .collect();
 
-    let db = Connection::open(&args.results).expect("creating SQLite db");
-    create_tables(&db).unwrap();
+    let settings = load_config(args.config.as_deref())?;
+    let options = RunOptions {
+        cores: args.threads,
+        throughput: args.throughput.map(|secs| ThroughputOptions {
+            wall_time: std::time::Duration::from_secs(secs),
+            publish_fraction: args.publish_fraction,
+            republish_fraction: args.republish_fraction,
+            republish_policy: args.republish_policy,
+        }),
+        scale: args.scale_mode.then_some(ScaleOptions {
+            sample: args.scale_sample,
+            packages: num_packages,
+        }),
+        server_scaling: None,
+        download_zipf: args.download_zipf,
+        subscribers: args.subscribers,
+        publish_batches: args.publish_batches,
+        cdn_publishes: args.cdn_publishes,
+        growth: args.growth.options(None)?,
+        fault_trials: args.fault_trials,
+        edge: args.edge_budget_us.map(|budget| {
+            let model = EdgeModel::default()
+                .with_budget(Duration::microseconds(budget.try_into().unwrap()));
+            match args.edge_read_us {
+                Some(read) => model.with_read(Duration::microseconds(read.try_into().unwrap())),
+                None => model,
+            }
+        }),
+        technique: TechniqueConfig::default(),
+        srs: args
+            .srs
+            .as_deref()
+            .map(|path| load_srs(path, num_packages))
+            .transpose()?,
+        verify_final_state: args.verify_final_state,
+        cost: args
+            .cost_model
+            .as_deref()
+            .map(|path| {
+                CostConfig::load(path)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))
+            })
+            .transpose()?,
+        energy: args
+            .energy_model
+            .as_deref()
+            .map(|path| {
+                EnergyModel::load(path)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))
+            })
+            .transpose()?
+            .unwrap_or_default(),
+        targets: args
+            .targets_model
+            .as_deref()
+            .map(|path| {
+                TargetsModel::load(path)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))
+            })
+            .transpose()?
+            .unwrap_or_default(),
+        refresh_intervals: args
+            .refresh_intervals
+            .as_deref()
+            .map(load_refresh_intervals)
+            .transpose()?,
+    };
+
+    let devices = args
+        .device_profiles
+        .as_deref()
+        .map(|path| {
+            device::load(path)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    let mut db = SqliteSink::open(&results).expect("creating SQLite db");
+    if args.tee_json {
+        db = db.with_tee_json();
+    }
+    create_tables(db.connection())?;
+    device::record(db.connection(), &devices).unwrap();
+    if let Some(run_id) = args.run_id {
+        sweep::tag_rows(db.connection_mut(), run_id).unwrap();
+    }
+    interrupt::install();
     for authenticator in authenticators.into_iter() {
         println!("\nauthenticator: {authenticator}");
+        let server_scaling = authenticator::Authenticators::by_name(&authenticator)
+            .ok()
+            .and_then(|technique| technique.server_scaling());
+        let options = RunOptions {
+            technique: settings.technique(&authenticator),
+            server_scaling,
+            ..options.clone()
+        };
 
-        let packages = packages.clone();
-        let batch_sizes = vec![100, 200, 300, 400, 500, 600, 700, 800, 900, 1000];
-        let result = match authenticator.as_str() {
-            "insecure" => run::<authenticator::Insecure>(packages, &db, args.threads),
-            "hackage" => run::<authenticator::Hackage>(packages, &db, args.threads),
-            "mercury_diff" => run::<authenticator::MercuryDiff>(packages, &db, args.threads),
-            "sparse_merkle" => run::<authenticator::SparseMerkle>(packages, &db, args.threads),
-            "merkle_bpt" => run::<authenticator::MerkleBpt>(packages, &db, args.threads),
-            "rsa" => run::<authenticator::Rsa>(packages, &db, args.threads),
-            "rsa_pool" => {
-                run_batch::<authenticator::RsaPool>(packages, &db, batch_sizes, args.threads)
+        let sample_size = options
+            .scale
+            .as_ref()
+            .filter(|_| server_scaling.is_some())
+            .map(|scale| scale.sample)
+            .filter(|sample| *sample < packages.len());
+        let packages = match sample_size {
+            Some(sample_size) => {
+                println!("scale mode: sampling {sample_size} packages (results are estimates)");
+                let mut rng = rand::thread_rng();
+                rand::seq::SliceRandom::choose_multiple(packages.as_slice(), &mut rng, sample_size)
+                    .cloned()
+                    .collect()
             }
-            "mercury" => run::<authenticator::VanillaTuf>(packages, &db, args.threads),
-            _ => panic!("not valid"),
-        }
-        .unwrap();
-        dbg!(&result);
-        match authenticator.as_str() {
-            "insecure" => result.insert::<authenticator::Insecure>(&db),
-            "hackage" => result.insert::<authenticator::Hackage>(&db),
-            "mercury_diff" => result.insert::<authenticator::MercuryDiff>(&db),
-            "sparse_merkle" => result.insert::<authenticator::SparseMerkle>(&db),
-            "merkle_bpt" => result.insert::<authenticator::MerkleBpt>(&db),
-            "rsa" => result.insert::<authenticator::Rsa>(&db),
-            "rsa_pool" => result.insert::<authenticator::RsaPool>(&db),
-            "mercury" => result.insert::<authenticator::VanillaTuf>(&db),
-            _ => panic!("not valid"),
+            None => packages.clone(),
+        };
+        let mut benchmark = Benchmark {
+            packages,
+            db: &db,
+            options: &options,
+        };
+        authenticator::Authenticators::visit(&authenticator, &mut benchmark)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?
+            .unwrap();
+        db.flush().unwrap();
+        if interrupt::requested() {
+            println!("interrupted; results so far are in {}", results.display());
+            drop(db);
+            std::process::exit(interrupt::EXIT_CODE);
         }
-        .unwrap();
     }
 
     Ok(())
@@ -732,3 +3886,69 @@ fn main() -> io::Result<()> {
 
 #[test]
 fn test_pass() {}
+
+#[test]
+fn records_results_in_memory() {
+    let packages = (0..50)
+        .map(|i| PackageId::from(format!("package{i}")))
+        .collect();
+    let options = RunOptions {
+        cores: 1,
+        throughput: None,
+        scale: None,
+        server_scaling: None,
+        download_zipf: None,
+        verify_final_state: false,
+        cost: None,
+        energy: EnergyModel::default(),
+        targets: TargetsModel::default(),
+        refresh_intervals: None,
+        subscribers: None,
+        publish_batches: vec![],
+        cdn_publishes: None,
+        growth: None,
+        fault_trials: None,
+        edge: None,
+        technique: TechniqueConfig::default(),
+        srs: None,
+    };
+    let results = sink::MemorySink::new();
+    let overall = run::<authenticator::Hackage>(packages, &results, &options).unwrap();
+    assert_eq!(overall.packages, 50);
+
+    let precompute = results.rows::<PrecomputeResult>();
+    assert_eq!(precompute.len(), 1);
+    assert_eq!((precompute[0].0, precompute[0].1.packages), ("hackage", 50));
+    assert!(!results.rows::<DownloadResult>().is_empty());
+}
+
+#[test]
+fn records_srs_load_only_for_srs_techniques() {
+    let path = std::env::temp_dir().join(format!("sssim-main-srs-{}", std::process::id()));
+    std::fs::write(&path, vec![0; 10 * sssim::primitives::srs::POWER_BYTES]).unwrap();
+    assert!(load_srs(&path, 11).is_err());
+    let srs = load_srs(&path, 10).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(srs.size, Information::new::<byte>(480));
+
+    let packages: Vec<_> = (0..10)
+        .map(|i| PackageId::from(format!("package{i}")))
+        .collect();
+    let results = sink::MemorySink::new();
+    let technique = TechniqueConfig::default();
+    precompute_trials::<authenticator::Hackage>(1, &results, &packages, &technique, Some(srs), 1)
+        .unwrap();
+    let precompute = results.rows::<PrecomputeResult>();
+    assert!(precompute[0].1.srs.is_none());
+}
+
+#[test]
+fn rejects_results_from_another_schema() {
+    let db = Connection::open_in_memory().unwrap();
+    record_schema_version(&db).unwrap();
+    record_schema_version(&db).unwrap();
+    db.execute("UPDATE schema SET version = ?1", [SCHEMA_VERSION + 1])
+        .unwrap();
+    let err = record_schema_version(&db).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
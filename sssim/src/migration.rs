@@ -0,0 +1,214 @@
+//! Switching a live repository to another technique partway through a log.
+//!
+//! A registry adopting a new scheme pays once for the switch: exporting its
+//! state (see [`RepositoryState`]), rebuilding the new server from it, and
+//! getting every existing client onto the new technique's metadata. After
+//! that, it pays the new technique's steady-state costs instead of the old
+//! one's. [`MigrationSimulator`] runs a log against the old technique up to a
+//! given time, switches, and keeps going, recording the one-time
+//! [`MigrationCosts`] separately from each technique's [`DeploymentCosts`].
+//!
+//! [`RepositoryState`]: crate::authenticator::RepositoryState
+use serde::Serialize;
+use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+use uom::ConstZero;
+
+use crate::authenticator::{DynAuthenticator, Technique};
+use crate::latency::time_fn;
+use crate::log::{Entry, UserId};
+use crate::shadow::{Deployment, DeploymentCosts};
+use crate::simulator::serialize_ns;
+use crate::util::Information;
+
+#[derive(Error, Debug)]
+pub enum MigrationError {
+    #[error("{0} can't export its state, so there's nothing to migrate")]
+    CannotExport(&'static str),
+}
+
+/// What the switch itself cost.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MigrationCosts {
+    pub from: &'static str,
+    pub to: &'static str,
+    /// Exporting the old server's state and importing it into the new one.
+    #[serde(rename = "rebuild_ns", serialize_with = "serialize_ns")]
+    pub rebuild: Duration,
+    /// Fresh snapshots for every client the old server had.
+    #[serde(rename = "bootstrap_bandwidth_bytes")]
+    pub bootstrap_bandwidth: Information,
+    pub clients: usize,
+}
+
+/// Runs a log against one technique, then switches to another at a given time.
+pub struct MigrationSimulator {
+    deployment: Deployment,
+    /// When to switch, and to what (until we do).
+    pending: Option<(OffsetDateTime, Technique)>,
+    /// The old technique's costs, once we've switched.
+    before: Option<DeploymentCosts>,
+    migration: Option<MigrationCosts>,
+    /// Actions processed (before, after) the switch.
+    actions: (usize, usize),
+}
+
+impl MigrationSimulator {
+    /// Start on `from`, and switch to `to` at the first entry at or after `at`.
+    pub fn new(
+        from: Box<dyn DynAuthenticator>,
+        to: Technique,
+        at: OffsetDateTime,
+    ) -> Result<Self, MigrationError> {
+        if from.export_state().is_none() {
+            return Err(MigrationError::CannotExport(from.name()));
+        }
+        Ok(Self {
+            deployment: Deployment::new(from),
+            pending: Some((at, to)),
+            before: None,
+            migration: None,
+            actions: (0, 0),
+        })
+    }
+
+    fn migrate(&mut self, to: Technique) {
+        let old = &self.deployment.authenticator;
+        let (rebuild, authenticator) = time_fn(|| {
+            let state = old.export_state().expect("checked in new()");
+            to.import_state(&state)
+        });
+        let users: Vec<UserId> = self.deployment.snapshots.keys().cloned().collect();
+        let mut deployment = Deployment::new(authenticator);
+        let mut bootstrap_bandwidth = Information::ZERO;
+        for user in &users {
+            bootstrap_bandwidth += deployment.bootstrap(user);
+        }
+        let old = std::mem::replace(&mut self.deployment, deployment);
+        self.migration = Some(MigrationCosts {
+            from: old.costs.technique,
+            to: self.deployment.costs.technique,
+            rebuild,
+            bootstrap_bandwidth,
+            clients: users.len(),
+        });
+        self.before = Some(old.costs);
+    }
+
+    /// # Panics
+    ///
+    /// If a client rejects what the server sent (before or after the switch).
+    pub fn process_entry(&mut self, entry: &Entry) {
+        if matches!(&self.pending, Some((at, _)) if entry.timestamp >= *at) {
+            let (_, to) = self.pending.take().unwrap();
+            self.migrate(to);
+        }
+        if let Some(divergence) = self.deployment.process(entry.action()) {
+            panic!("client rejected a response: {divergence:?}");
+        }
+        match self.before {
+            None => self.actions.0 += 1,
+            Some(_) => self.actions.1 += 1,
+        }
+    }
+
+    /// The one-time costs, if we've switched.
+    pub fn migration(&self) -> Option<&MigrationCosts> {
+        self.migration.as_ref()
+    }
+
+    /// The old technique's costs (up to the switch, if any).
+    pub fn before(&self) -> &DeploymentCosts {
+        self.before.as_ref().unwrap_or(&self.deployment.costs)
+    }
+
+    /// The new technique's costs since the switch (not counting the switch).
+    pub fn after(&self) -> Option<&DeploymentCosts> {
+        self.before.as_ref().map(|_| &self.deployment.costs)
+    }
+
+    /// How many actions [`Self::before`] and [`Self::after`] cover, for
+    /// comparing per-action costs.
+    pub fn actions(&self) -> (usize, usize) {
+        self.actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authenticator::Authenticators;
+    use crate::log::{Action, Package};
+    use crate::testing::package_ids;
+
+    fn technique(name: &str) -> Technique {
+        Authenticators::by_name(name).unwrap()
+    }
+
+    #[test]
+    fn migrates_mid_log() {
+        let packages: Vec<_> = package_ids(5);
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let switch = start + Duration::hours(1);
+        let mut simulator = MigrationSimulator::new(
+            technique("hackage").import(packages.clone()),
+            technique("mercury_diff"),
+            switch,
+        )
+        .unwrap();
+
+        let package = Package {
+            id: packages[0].clone(),
+            length: None,
+        };
+        let users: Vec<_> = (0..3).map(|i| UserId::from(format!("user{i}"))).collect();
+        let mut entries = vec![];
+        for user in &users {
+            entries.push(Entry::new(
+                start,
+                Action::RefreshMetadata { user: user.clone() },
+            ));
+        }
+        entries.push(Entry::new(
+            start,
+            Action::Publish {
+                package: package.clone(),
+            },
+        ));
+        // After the switch, the new server has to agree that package0 is at
+        // revision 2 (or the download fails to verify).
+        entries.push(Entry::new(
+            switch,
+            Action::RefreshMetadata {
+                user: users[0].clone(),
+            },
+        ));
+        entries.push(Entry::new(
+            switch,
+            Action::Download {
+                user: users[0].clone(),
+                package,
+            },
+        ));
+        for entry in &entries {
+            simulator.process_entry(entry);
+        }
+
+        let migration = simulator.migration().unwrap();
+        assert_eq!(migration.from, "hackage");
+        assert_eq!(migration.clients, users.len());
+        assert!(migration.bootstrap_bandwidth > Information::ZERO);
+        assert_eq!(simulator.actions(), (4, 2));
+        assert_eq!(simulator.after().unwrap().technique, migration.to);
+    }
+
+    #[test]
+    fn needs_exportable_state() {
+        let result = MigrationSimulator::new(
+            technique("none").import(vec![]),
+            technique("hackage"),
+            OffsetDateTime::UNIX_EPOCH,
+        );
+        assert!(matches!(result, Err(MigrationError::CannotExport(_))));
+    }
+}
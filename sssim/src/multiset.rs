@@ -1,9 +1,12 @@
 //!Multiset
+//!
+//! Backed by a `HashMap`, so [`MultiSet::iter`] comes out in a different order
+//! every run. Anything whose result (or timing) depends on the order, like
+//! precomputing proofs, should use [`MultiSet::sorted`] instead.
 
 use std::{collections::HashMap, hash::Hash};
 
-use rug::Integer;
-use serde::{ser::SerializeMap, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::util::{assume_data_size_for_map, DataSized};
 
@@ -20,16 +23,27 @@ impl<T: Hash + Eq> Default for MultiSet<T> {
     }
 }
 
-impl Serialize for MultiSet<Integer> {
+/// A map from members to counts, smallest member first (so the same multiset
+/// always serializes the same way).
+impl<T: Serialize + Ord + Hash + Eq> Serialize for MultiSet<T> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
-        S: serde::Serializer,
+        S: Serializer,
     {
-        let mut map = serializer.serialize_map(Some(self.inner.len()))?;
-        for (k, v) in &self.inner {
-            map.serialize_entry(&k.to_string(), v)?;
+        serializer.collect_map(self.sorted())
+    }
+}
+
+impl<'de, T: Deserialize<'de> + Hash + Eq> Deserialize<'de> for MultiSet<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let inner = HashMap::<T, u32>::deserialize(deserializer)?;
+        if inner.values().any(|count| *count == 0) {
+            return Err(de::Error::custom("multiset member with a count of 0"));
         }
-        map.end()
+        Ok(Self { inner })
     }
 }
 
@@ -64,14 +78,38 @@ impl<T: Hash + Eq> MultiSet<T> {
         }
     }
 
+    /// Remove up to `n` copies of `member`, returning how many there were.
+    pub fn remove_n(&mut self, member: &T, n: u32) -> u32 {
+        let count = match self.inner.get_mut(member) {
+            Some(count) => count,
+            None => return 0,
+        };
+        if *count > n {
+            *count -= n;
+            return n;
+        }
+        self.inner.remove(member).unwrap_or(0)
+    }
+
     pub fn clear(&mut self, member: &T) -> Option<u32> {
         self.inner.remove(member)
     }
 
+    /// In arbitrary order (see [`Self::sorted`]).
     pub fn iter(&self) -> impl std::iter::Iterator<Item = (&T, &u32)> {
         self.inner.iter()
     }
 
+    /// Members and their counts, smallest member first.
+    pub fn sorted(&self) -> Vec<(&T, u32)>
+    where
+        T: Ord,
+    {
+        let mut members: Vec<_> = self.inner.iter().map(|(k, v)| (k, *v)).collect();
+        members.sort_unstable_by_key(|(k, _)| *k);
+        members
+    }
+
     pub fn is_superset(&self, other: &Self) -> bool {
         for (key, count) in other.inner.iter() {
             if self.inner.get(key).unwrap_or(&0) < count {
@@ -81,16 +119,50 @@ impl<T: Hash + Eq> MultiSet<T> {
         true
     }
 
-    pub fn difference<'a>(&'a self, other: &Self) -> Vec<(&'a T, u32)> {
-        // TODO(maybe): make it return a real iterator
-        let mut results: Vec<(&T, u32)> = vec![];
-        for (key, count) in self.inner.iter() {
-            let diff: u32 = count
-                .checked_sub(*other.inner.get(key).unwrap_or(&0))
-                .expect("not a superset");
-            results.push((key, diff))
+    /// Each member as many times as it's in `self` but not `other` (so, none
+    /// of those that `other` has more of).
+    pub fn difference(&self, other: &Self) -> Self
+    where
+        T: Clone,
+    {
+        self.zip_counts(other, |a, b| a.saturating_sub(b))
+    }
+
+    /// Each member as many times as it's in either (the larger count).
+    pub fn union(&self, other: &Self) -> Self
+    where
+        T: Clone,
+    {
+        let mut union = self.zip_counts(other, u32::max);
+        for (key, count) in other.inner.iter() {
+            union.inner.entry(key.clone()).or_insert(*count);
         }
-        results
+        union
+    }
+
+    /// Each member as many times as it's in both (the smaller count).
+    pub fn intersection(&self, other: &Self) -> Self
+    where
+        T: Clone,
+    {
+        self.zip_counts(other, u32::min)
+    }
+
+    /// Combine the counts of every member of `self` with its count in
+    /// `other`, dropping any that come out to 0.
+    fn zip_counts(&self, other: &Self, f: impl Fn(u32, u32) -> u32) -> Self
+    where
+        T: Clone,
+    {
+        let inner = self
+            .inner
+            .iter()
+            .filter_map(|(key, count)| {
+                let count = f(*count, other.get(key));
+                (count > 0).then(|| (key.clone(), count))
+            })
+            .collect();
+        Self { inner }
     }
 
     pub fn len(&self) -> usize {
@@ -111,3 +183,62 @@ impl<T: Hash + Eq + Clone> From<Vec<T>> for MultiSet<T> {
         multiset
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorted() {
+        let forward = MultiSet::from((0..100u32).chain([7, 7]).collect::<Vec<_>>());
+        let backward = MultiSet::from((0..100u32).rev().chain([7, 7]).collect::<Vec<_>>());
+        assert_eq!(forward.sorted(), backward.sorted());
+        let sorted = forward.sorted();
+        assert_eq!(sorted[0], (&0, 1));
+        assert_eq!(sorted[7], (&7, 3));
+        assert!(sorted.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    fn counts(multiset: &MultiSet<char>) -> Vec<(char, u32)> {
+        multiset
+            .sorted()
+            .into_iter()
+            .map(|(k, v)| (*k, v))
+            .collect()
+    }
+
+    #[test]
+    fn set_operations() {
+        let a = MultiSet::from("aaabbc".chars().collect::<Vec<_>>());
+        let b = MultiSet::from("abbbd".chars().collect::<Vec<_>>());
+        assert_eq!(
+            counts(&a.union(&b)),
+            [('a', 3), ('b', 3), ('c', 1), ('d', 1)]
+        );
+        assert_eq!(counts(&a.intersection(&b)), [('a', 1), ('b', 2)]);
+        assert_eq!(counts(&a.difference(&b)), [('a', 2), ('c', 1)]);
+        assert_eq!(counts(&b.difference(&a)), [('b', 1), ('d', 1)]);
+        assert!(a.difference(&a).is_empty());
+    }
+
+    #[test]
+    fn remove_n() {
+        let mut multiset = MultiSet::from("aaab".chars().collect::<Vec<_>>());
+        assert_eq!(multiset.remove_n(&'a', 2), 2);
+        assert_eq!(multiset.get(&'a'), 1);
+        assert_eq!(multiset.remove_n(&'a', 5), 1);
+        assert_eq!(multiset.get(&'a'), 0);
+        assert_eq!(multiset.len(), 1);
+        assert_eq!(multiset.remove_n(&'z', 1), 0);
+    }
+
+    #[test]
+    fn serde() {
+        let multiset = MultiSet::from(vec![3u32, 1, 3]);
+        let json = serde_json::to_string(&multiset).unwrap();
+        assert_eq!(json, r#"{"1":1,"3":2}"#);
+        let parsed: MultiSet<u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.sorted(), multiset.sorted());
+        assert!(serde_json::from_str::<MultiSet<u32>>(r#"{"1":0}"#).is_err());
+    }
+}
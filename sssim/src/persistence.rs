@@ -0,0 +1,283 @@
+//! Clients that lose their local state (`sssim state-loss`).
+//!
+//! Techniques with small incremental refreshes can have big bootstraps, and
+//! not every returning user still has their metadata: they reinstalled, moved
+//! to a new machine, or are a CI job starting from a clean image. A
+//! [`StateLoss`] decides, for each refresh by a returning user, whether they
+//! lost their state since the last one, in which case they bootstrap again
+//! from scratch rather than catching up. The [`Simulator`] takes one (see
+//! [`Simulator::with_state_loss`]), and [`sensitivity`] runs a small synthetic
+//! workload at several loss rates to see how much each technique's total
+//! refresh bandwidth depends on it.
+//!
+//! [`Simulator`]: crate::simulator::Simulator
+//! [`Simulator::with_state_loss`]: crate::simulator::Simulator::with_state_loss
+use std::fmt;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::authenticator::Technique;
+use crate::log::PackageId;
+use crate::util::{byte, DataSized};
+
+/// Whether each returning user lost their state, at a fixed rate.
+#[derive(Debug, Clone)]
+pub struct StateLoss {
+    probability: f64,
+    rng: StdRng,
+    losses: usize,
+}
+
+impl StateLoss {
+    /// Each returning user loses their state with `probability`; `seed` makes
+    /// it repeatable.
+    pub fn new(probability: f64, seed: u64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&probability),
+            "state loss probability must be in [0, 1]"
+        );
+        Self {
+            probability,
+            rng: StdRng::seed_from_u64(seed),
+            losses: 0,
+        }
+    }
+
+    pub fn probability(&self) -> f64 {
+        self.probability
+    }
+
+    /// Whether the returning user about to refresh lost their state.
+    pub fn lost(&mut self) -> bool {
+        let lost = self.rng.gen_bool(self.probability);
+        if lost {
+            self.losses += 1;
+        }
+        lost
+    }
+
+    /// Users who lost their state so far.
+    pub fn losses(&self) -> usize {
+        self.losses
+    }
+}
+
+/// Settings for [`sensitivity`].
+#[derive(Debug, Clone)]
+pub struct SensitivityOptions {
+    /// Rounds of publishes and refreshes to run at each rate.
+    pub rounds: usize,
+    /// Publishes in each round.
+    pub publishes_per_round: usize,
+    /// Number of clients, each with its own snapshot.
+    pub clients: usize,
+    /// Probability that a client comes back (and refreshes) in a given round.
+    pub refresh_fraction: f64,
+    /// Probability that a publish is of a brand-new package.
+    pub new_package_fraction: f64,
+    /// Seed for the workload (the same for every rate).
+    pub seed: u64,
+}
+
+impl Default for SensitivityOptions {
+    fn default() -> Self {
+        Self {
+            rounds: 100,
+            publishes_per_round: 5,
+            clients: 32,
+            refresh_fraction: 0.5,
+            new_package_fraction: 0.1,
+            seed: 0,
+        }
+    }
+}
+
+/// Refresh bandwidth at one loss rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RatePoint {
+    pub rate: f64,
+    /// Refreshes by returning users (incremental or not).
+    pub refreshes: usize,
+    /// How many of those bootstrapped because the user lost their state.
+    pub bootstraps: usize,
+    /// Bytes sent for all of them.
+    pub bandwidth_bytes: usize,
+}
+
+/// One technique's refresh bandwidth at each loss rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sensitivity {
+    pub technique: &'static str,
+    pub points: Vec<RatePoint>,
+}
+
+impl Sensitivity {
+    /// Extra bytes per refresh for each unit of loss rate (the least-squares
+    /// slope), if there were at least two rates.
+    pub fn bytes_per_rate(&self) -> Option<f64> {
+        let points: Vec<(f64, f64)> = self
+            .points
+            .iter()
+            .map(|point| {
+                let per_refresh = point.bandwidth_bytes as f64 / point.refreshes.max(1) as f64;
+                (point.rate, per_refresh)
+            })
+            .collect();
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let covariance: f64 = points
+            .iter()
+            .map(|(x, y)| (x - mean_x) * (y - mean_y))
+            .sum();
+        let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+        if variance > 0.0 {
+            Some(covariance / variance)
+        } else {
+            None
+        }
+    }
+
+    /// Total bandwidth at each rate relative to the first (lowest) rate.
+    pub fn relative(&self) -> Vec<f64> {
+        let baseline = match self.points.first() {
+            Some(point) => point.bandwidth_bytes.max(1) as f64,
+            None => return Vec::new(),
+        };
+        self.points
+            .iter()
+            .map(|point| point.bandwidth_bytes as f64 / baseline)
+            .collect()
+    }
+}
+
+impl fmt::Display for Sensitivity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.technique)?;
+        for (point, relative) in self.points.iter().zip(self.relative()) {
+            write!(
+                f,
+                " {}: {} bytes ({relative:.2}x, {} bootstraps);",
+                point.rate, point.bandwidth_bytes, point.bootstraps
+            )?;
+        }
+        match self.bytes_per_rate() {
+            Some(slope) => write!(f, " {slope:.0} bytes/refresh per unit rate"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Run the workload against `technique` (freshly imported with `packages`)
+/// with state loss at `rate`.
+fn run_at(
+    technique: &Technique,
+    packages: &[PackageId],
+    rate: f64,
+    options: &SensitivityOptions,
+) -> RatePoint {
+    let mut server = technique.import(packages.to_vec());
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    // Its own stream, so the workload is the same at every rate.
+    let mut loss = StateLoss::new(rate, options.seed.wrapping_add(1));
+    let mut packages = packages.to_vec();
+    let mut snapshots: Vec<_> = (0..options.clients)
+        .map(|_| server.get_metadata())
+        .collect();
+    let mut point = RatePoint {
+        rate,
+        refreshes: 0,
+        bootstraps: 0,
+        bandwidth_bytes: 0,
+    };
+    let mut published = 0;
+    for _ in 0..options.rounds {
+        for _ in 0..options.publishes_per_round {
+            let package = if packages.is_empty() || rng.gen_bool(options.new_package_fraction) {
+                published += 1;
+                let package = PackageId::from(format!("new_package{published}"));
+                packages.push(package.clone());
+                package
+            } else {
+                packages[rng.gen_range(0..packages.len())].clone()
+            };
+            server.publish(package);
+            server.batch_process();
+        }
+        for snapshot in &mut snapshots {
+            if !rng.gen_bool(options.refresh_fraction) {
+                continue;
+            }
+            point.refreshes += 1;
+            if loss.lost() {
+                // Bootstrap: the whole snapshot, as for a new user.
+                *snapshot = server.get_metadata();
+                point.bootstraps += 1;
+                point.bandwidth_bytes += snapshot.size().get::<byte>();
+            } else if let Some(diff) = server.refresh_metadata(snapshot) {
+                point.bandwidth_bytes += diff.size().get::<byte>();
+                snapshot.update(diff);
+            }
+        }
+    }
+    point
+}
+
+/// `technique`'s refresh bandwidth at each of `rates` of state loss, over the
+/// same workload.
+pub fn sensitivity(
+    technique: &Technique,
+    packages: &[PackageId],
+    rates: &[f64],
+    options: &SensitivityOptions,
+) -> Sensitivity {
+    let mut rates = rates.to_vec();
+    rates.sort_by(f64::total_cmp);
+    Sensitivity {
+        technique: technique.name(),
+        points: rates
+            .into_iter()
+            .map(|rate| run_at(technique, packages, rate, options))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::authenticator::Authenticators;
+    use crate::testing::package_ids;
+
+    #[test]
+    fn loses_at_rate() {
+        let mut loss = StateLoss::new(0.25, 0);
+        let lost = (0..10_000).filter(|_| loss.lost()).count();
+        assert_eq!(loss.losses(), lost);
+        assert!((2_000..3_000).contains(&lost), "{lost}");
+        assert!(!(0..100).any(|_| StateLoss::new(0.0, 1).lost()));
+    }
+
+    #[test]
+    fn bootstraps_cost_more() {
+        let options = SensitivityOptions {
+            rounds: 20,
+            clients: 8,
+            ..SensitivityOptions::default()
+        };
+        let technique = Authenticators::by_name("mercury_diff").unwrap();
+        let sensitivity = sensitivity(&technique, &package_ids(500), &[0.5, 0.0, 1.0], &options);
+
+        let rates: Vec<_> = sensitivity.points.iter().map(|point| point.rate).collect();
+        assert_eq!(rates, vec![0.0, 0.5, 1.0]);
+        let [none, half, all] = [0, 1, 2].map(|i| sensitivity.points[i]);
+        // Same workload, so the same refreshes at every rate.
+        assert_eq!(none.refreshes, all.refreshes);
+        assert_eq!((none.bootstraps, all.bootstraps), (0, all.refreshes));
+        assert!(none.bandwidth_bytes < half.bandwidth_bytes);
+        assert!(half.bandwidth_bytes < all.bandwidth_bytes);
+        assert!(sensitivity.bytes_per_rate().unwrap() > 0.0);
+        assert_eq!(sensitivity.relative()[0], 1.0);
+    }
+}
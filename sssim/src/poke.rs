@@ -5,12 +5,57 @@
 use std::marker::PhantomData;
 
 use crate::hash_to_prime::{hash_to_prime, IntegerHasher};
-use crate::primitives::{Group, Prime};
+use crate::primitives::{Domain, Group, Prime};
+use rand::Rng;
 use rug::Integer;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::util::{DataSized, Information};
 
+/// Window size (in bits) for [`multi_exp`].
+const WINDOW: u32 = 4;
+
+/// Compute the sum of `base * exp` over `terms` (in multiplicative notation,
+/// the product of `base^exp`).
+///
+/// Uses Straus's trick: all the terms share one chain of doublings, so it's
+/// much cheaper than exponentiating each base separately.
+fn multi_exp<G: Group + 'static>(terms: Vec<(G, Integer)>) -> G {
+    let bits = terms
+        .iter()
+        .map(|(_, exp)| exp.significant_bits())
+        .max()
+        .unwrap_or(0);
+    // tables[i][j] = base_i * (j + 1)
+    let tables: Vec<Vec<G>> = terms
+        .iter()
+        .map(|(base, _)| {
+            let mut table = vec![base.clone()];
+            for _ in 2..(1 << WINDOW) {
+                let next = table.last().unwrap().clone() + base.clone();
+                table.push(next);
+            }
+            table
+        })
+        .collect();
+
+    let mut acc = G::zero().clone();
+    for window in (0..(bits + WINDOW - 1) / WINDOW).rev() {
+        for _ in 0..WINDOW {
+            acc = acc.clone() + acc;
+        }
+        for ((_, exp), table) in terms.iter().zip(&tables) {
+            let digit = (0..WINDOW).fold(0usize, |digit, bit| {
+                digit | (usize::from(exp.get_bit(window * WINDOW + bit)) << bit)
+            });
+            if digit != 0 {
+                acc += table[digit - 1].clone();
+            }
+        }
+    }
+    acc
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Instance<G> {
     // new value
@@ -24,7 +69,7 @@ pub struct Witness {
     pub x: Integer,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Proof<G> {
     z: G,
     Q: G,
@@ -59,7 +104,7 @@ impl<G: Group + TryFrom<Integer> + 'static> ZKUniverse<G> {
     fn fiat_shamir1(&self, instance: &Instance<G>) -> G {
         let data_str = format!("{instance:?}");
         let bytes = G::bytes();
-        let mut hasher = IntegerHasher::new(data_str.as_bytes(), bytes);
+        let mut hasher = IntegerHasher::new(Domain::PokeGenerator, data_str.as_bytes(), bytes);
         loop {
             // TODO(maybe): replace with fancier rejection sampling
             if let Ok(value) = G::try_from(hasher.hash()) {
@@ -70,12 +115,12 @@ impl<G: Group + TryFrom<Integer> + 'static> ZKUniverse<G> {
 
     fn fiat_shamir2(&self, instance: &Instance<G>, g: &G, z: &G) -> Prime {
         let data_str = format!("{instance:?}{g:?}{z:?}");
-        hash_to_prime(data_str.as_bytes()).unwrap()
+        hash_to_prime(Domain::PokeChallenge, data_str.as_bytes()).unwrap()
     }
 
     fn fiat_shamir3(&self, instance: &Instance<G>, g: &G, z: &G, ell: &Prime) -> Integer {
         let data_str = format!("{instance:?}{g:?}{z:?}{ell:?}");
-        let mut hasher = IntegerHasher::new(data_str.as_bytes(), 32);
+        let mut hasher = IntegerHasher::new(Domain::PokeCoefficient, data_str.as_bytes(), 32);
         hasher.hash()
     }
 
@@ -119,7 +164,40 @@ impl<G: Group + TryFrom<Integer> + 'static> ZKUniverse<G> {
         // Verifier accepts if r < ell and Q^ell u^r g^(alpha r) = w z^(alpha).
         let lhs = Q * ell.as_ref() + u * &r + g * &(alpha.clone() * r.clone());
         let rhs = w + z * &alpha;
-        &r < ell.inner() && lhs == rhs
+        crate::ct::and(&r < ell.inner(), || lhs.ct_eq(&rhs))
+    }
+
+    /// Verify many proofs at once.
+    ///
+    /// Instead of checking each `lhs_i = rhs_i`, we pick random `rho_i` of
+    /// `lambda / 2` bits and check `sum(rho_i lhs_i) = sum(rho_i rhs_i)` with
+    /// one multi-exponentiation per side. A batch with any bad proof passes
+    /// with probability about `2^-(lambda / 2)`.
+    pub fn verify_batch(&self, batch: Vec<(Instance<G>, Proof<G>)>) -> bool {
+        let mut rng = rand::thread_rng();
+        let rho_bits = u32::try_from(self.lambda / 2).unwrap();
+        let mut in_range = true;
+        let mut lhs = Vec::with_capacity(3 * batch.len());
+        let mut rhs = Vec::with_capacity(2 * batch.len());
+        for (instance, proof) in batch {
+            let Proof { z, Q, r } = proof;
+            let g = self.fiat_shamir1(&instance);
+            let ell = self.fiat_shamir2(&instance, &g, &z);
+            let alpha = self.fiat_shamir3(&instance, &g, &z, &ell);
+            in_range &= &r < ell.inner();
+
+            let mut rho = Integer::new();
+            for bit in 0..rho_bits {
+                rho.set_bit(bit, rng.gen());
+            }
+            // Q^(ell rho) u^(r rho) g^(alpha r rho) = w^rho z^(alpha rho)
+            lhs.push((Q, Integer::from(ell.inner() * &rho)));
+            lhs.push((instance.u, Integer::from(&r * &rho)));
+            lhs.push((g, alpha.clone() * &r * &rho));
+            rhs.push((instance.w, rho.clone()));
+            rhs.push((z, alpha * &rho));
+        }
+        crate::ct::and(in_range, || multi_exp(lhs).ct_eq(&multi_exp(rhs)))
     }
 }
 
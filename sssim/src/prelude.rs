@@ -0,0 +1,13 @@
+//! Everything needed to use `sssim` as a library.
+//!
+//! ```
+//! use sssim::prelude::*;
+//! ```
+pub use crate::authenticator::{
+    Authenticators, CdnObject, DynAuthenticator, DynDiff, DynProof, DynSnapshot, Revision,
+    Technique, TrustAnchor, UnknownTechnique,
+};
+pub use crate::log::{Action, Entry, Log, Package, PackageId, UserId};
+pub use crate::simulator::{ResourceUsage, Simulator};
+pub use crate::util::{DataSized, Information};
+pub use crate::{Authenticator, BatchAuthenticator, PoolAuthenticator};
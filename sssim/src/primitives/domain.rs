@@ -0,0 +1,124 @@
+//! Domain separation for hashing.
+//!
+//! The same hash functions get used for very different things: picking a
+//! package's prime, placing it in a Merkle tree, hashing tree nodes, and
+//! deriving Fiat–Shamir challenges. Without separation, an input crafted for
+//! one use could collide with an input for another (e.g. a leaf that hashes
+//! like an interior node), and the security argument for each piece assumes
+//! that can't happen.
+//!
+//! Every hash input starts with the [`Domain::prefix`] for its use: the length
+//! of a context string followed by the string itself. The prefixes are distinct
+//! and none is a prefix of another, so inputs from different domains never
+//! coincide.
+
+/// A use of a hash function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Domain {
+    /// Package to prime, for the RSA accumulators.
+    PackagePrime,
+    /// Links in the hash chain committing to an RSA pool.
+    PoolChain,
+    /// Package to index, for the sparse Merkle tree.
+    SparseMerkleIndex,
+    /// Revisions stored in the sparse Merkle tree.
+    SparseMerkleValue,
+    SparseMerkleLeaf,
+    SparseMerkleInterior,
+    /// Key to index, for the Merkle prefix tree.
+    MerkleKey,
+    /// Values stored in the Merkle prefix tree.
+    MerkleValue,
+    /// Randomness for commitments to values in the Merkle prefix tree.
+    MerkleCommitment,
+    MerkleLeaf,
+    MerkleEmpty,
+    MerkleInterior,
+    /// PoKE: the random generator `g`.
+    PokeGenerator,
+    /// PoKE: the prime challenge `ell`.
+    PokeChallenge,
+    /// PoKE: the coefficient `alpha`.
+    PokeCoefficient,
+}
+
+impl Domain {
+    pub const ALL: [Domain; 15] = [
+        Domain::PackagePrime,
+        Domain::PoolChain,
+        Domain::SparseMerkleIndex,
+        Domain::SparseMerkleValue,
+        Domain::SparseMerkleLeaf,
+        Domain::SparseMerkleInterior,
+        Domain::MerkleKey,
+        Domain::MerkleValue,
+        Domain::MerkleCommitment,
+        Domain::MerkleLeaf,
+        Domain::MerkleEmpty,
+        Domain::MerkleInterior,
+        Domain::PokeGenerator,
+        Domain::PokeChallenge,
+        Domain::PokeCoefficient,
+    ];
+
+    /// Changing any of these changes every digest computed in that domain.
+    pub fn context(self) -> &'static str {
+        match self {
+            Domain::PackagePrime => "sssim/v1/rsa/package-prime",
+            Domain::PoolChain => "sssim/v1/rsa/pool-chain",
+            Domain::SparseMerkleIndex => "sssim/v1/smt/package-index",
+            Domain::SparseMerkleValue => "sssim/v1/smt/value",
+            Domain::SparseMerkleLeaf => "sssim/v1/smt/leaf",
+            Domain::SparseMerkleInterior => "sssim/v1/smt/interior",
+            Domain::MerkleKey => "sssim/v1/merkle/key",
+            Domain::MerkleValue => "sssim/v1/merkle/value",
+            Domain::MerkleCommitment => "sssim/v1/merkle/commitment",
+            Domain::MerkleLeaf => "sssim/v1/merkle/leaf",
+            Domain::MerkleEmpty => "sssim/v1/merkle/empty",
+            Domain::MerkleInterior => "sssim/v1/merkle/interior",
+            Domain::PokeGenerator => "sssim/v1/poke/generator",
+            Domain::PokeChallenge => "sssim/v1/poke/challenge",
+            Domain::PokeCoefficient => "sssim/v1/poke/coefficient",
+        }
+    }
+
+    /// What to feed the hash function before any data.
+    pub fn prefix(self) -> Vec<u8> {
+        let context = self.context().as_bytes();
+        let mut prefix = Vec::with_capacity(context.len() + 1);
+        prefix.push(u8::try_from(context.len()).expect("context strings are short"));
+        prefix.extend_from_slice(context);
+        prefix
+    }
+
+    /// `data`, prefixed for this domain.
+    pub fn separate(self, data: &[u8]) -> Vec<u8> {
+        let mut bytes = self.prefix();
+        bytes.extend_from_slice(data);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes_are_prefix_free() {
+        for a in Domain::ALL {
+            for b in Domain::ALL {
+                if a != b {
+                    assert!(!a.prefix().starts_with(&b.prefix()), "{a:?} vs. {b:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn separate_golden() {
+        assert_eq!(
+            Domain::PackagePrime.separate(b"x"),
+            b"\x1asssim/v1/rsa/package-primex"
+        );
+    }
+}
@@ -22,6 +22,11 @@ pub trait Group:
     fn one() -> &'static Self;
     fn max_value() -> &'static Self;
     fn bytes() -> usize;
+
+    /// Equality for client-side verification (see [`crate::ct`]).
+    fn ct_eq(&self, other: &Self) -> bool {
+        self == other
+    }
 }
 
 #[cfg(test)]
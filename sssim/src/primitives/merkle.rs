@@ -3,6 +3,11 @@
 //! Follows CONIKS.
 use crate::{
     bit_twiddling::*,
+    counters::{self, Op},
+    primitives::{
+        merkle_path::{self, PathShape},
+        Domain,
+    },
     util::{assume_data_size_for_map, byte, DataSized, FixedDataSized, Information},
 };
 use derivative::Derivative;
@@ -15,19 +20,59 @@ use thiserror::Error;
 pub use digest::Digest as Hasher;
 pub use digest_hash::LittleEndian as ObjectHasher;
 
-const NONCE: [u8; 4] = [0, 0, 0, 0];
-const NODE_TYPE_EMPTY: [u8; 4] = [0, 0, 0, 1];
-const NODE_TYPE_LEAF: [u8; 4] = [0, 0, 0, 2];
-
-fn hash<D: Hash, H: Hasher>(data: &D) -> Output<ObjectHasher<H>>
+fn hash<D: Hash, H: Hasher>(domain: Domain, data: &D) -> Output<ObjectHasher<H>>
 where
     ObjectHasher<H>: Hasher + EndianUpdate,
 {
+    counters::record(Op::Hash);
     let mut hasher = ObjectHasher::<H>::new();
+    hasher.update(domain.prefix());
     data.hash(&mut hasher);
     hasher.finalize()
 }
 
+/// `H(value)`, or a commitment to `value` if there's a `salt`.
+fn value_hash<V: Hash, H: Hasher>(value: &V, salt: Option<&Output<H>>) -> Output<ObjectHasher<H>>
+where
+    ObjectHasher<H>: Hasher + EndianUpdate,
+{
+    counters::record(Op::Hash);
+    let mut hasher = ObjectHasher::<H>::new();
+    hasher.update(Domain::MerkleValue.prefix());
+    if let Some(salt) = salt {
+        hasher.update(salt);
+    }
+    value.hash(&mut hasher);
+    hasher.finalize()
+}
+
+/// Hides the values in the rest of the tree from proofs (following CONIKS).
+///
+/// A plain proof that ends at someone else's leaf shows that leaf's
+/// `H(value)`, which anyone can check against likely values. With this,
+/// leaves hold commitments to their values instead, opened only in membership
+/// proofs. The commitment secret never leaves the server.
+///
+/// Indexes are still `H(key)`, so proofs show the key hashes along their
+/// paths, which anyone can check against a list of likely package names.
+/// Hiding those takes a VRF (as in CONIKS), which we don't have.
+#[derive(Debug, Clone)]
+pub struct Privacy {
+    /// Seeds the commitment randomness.
+    secret: [u8; 32],
+}
+
+impl Privacy {
+    /// Commit to values, with randomness derived from `secret`.
+    pub fn new(secret: [u8; 32]) -> Self {
+        Self { secret }
+    }
+
+    pub fn random() -> Self {
+        Self::new(rand::random())
+    }
+}
+
 /// A direction in the tree.
 #[derive(Debug, Clone, Copy)]
 enum Direction {
@@ -75,9 +120,9 @@ impl<H: Hasher> LeafData<H> {
     where
         ObjectHasher<H>: Hasher + EndianUpdate,
     {
+        counters::record(Op::Hash);
         let mut hasher = ObjectHasher::<H>::new();
-        hasher.update(NODE_TYPE_LEAF);
-        hasher.update(NONCE);
+        hasher.update(Domain::MerkleLeaf.prefix());
         self.key_index.hash(&mut hasher);
         u64::try_from(self.depth)
             .expect("64 bit platform")
@@ -91,7 +136,11 @@ impl<H: Hasher> LeafData<H> {
         V: Hash,
         ObjectHasher<H>: Hasher<OutputSize = H::OutputSize> + EndianUpdate,
     {
-        Self::new(hash::<_, H>(key), depth, hash::<_, H>(value))
+        Self::new(
+            hash::<_, H>(Domain::MerkleKey, key),
+            depth,
+            value_hash::<_, H>(value, None),
+        )
     }
 }
 
@@ -119,9 +168,9 @@ impl<H: Hasher> EmptyData<H> {
     where
         ObjectHasher<H>: Hasher + EndianUpdate,
     {
+        counters::record(Op::Hash);
         let mut hasher = ObjectHasher::<H>::new();
-        hasher.update(NODE_TYPE_EMPTY);
-        hasher.update(NONCE);
+        hasher.update(Domain::MerkleEmpty.prefix());
         self.prefix.hash(&mut hasher);
         u64::try_from(self.depth)
             .expect("64 bit platform")
@@ -134,7 +183,9 @@ fn hash_interior<H: Hasher>(left: &Output<H>, right: &Output<H>) -> Output<Objec
 where
     ObjectHasher<H>: Hasher + EndianUpdate,
 {
+    counters::record(Op::Hash);
     let mut hasher = ObjectHasher::<H>::new();
+    hasher.update(Domain::MerkleInterior.prefix());
     left.hash(&mut hasher);
     right.hash(&mut hasher);
     hasher.finalize()
@@ -320,6 +371,7 @@ pub struct Tree<K: Hash, V: Hash, H: Hasher> {
     /// This is where the actual keys and values are stored.
     values: HashMap<K, V>,
     node_counts: NodeCounts,
+    privacy: Option<Privacy>,
 }
 
 impl<K: Hash, V: Hash, H: Hasher> Tree<K, V, H> {
@@ -343,6 +395,20 @@ where
             root,
             values: Default::default(),
             node_counts,
+            privacy: None,
+        }
+    }
+}
+
+impl<K: Hash, V: Hash, H: Hasher> Tree<K, V, H>
+where
+    ObjectHasher<H>: Hasher<OutputSize = H::OutputSize> + EndianUpdate,
+{
+    /// An empty tree that hides its contents from proofs.
+    pub fn private(privacy: Privacy) -> Self {
+        Self {
+            privacy: Some(privacy),
+            ..Default::default()
         }
     }
 }
@@ -399,20 +465,56 @@ pub struct Proof<V, H: Hasher>
 where
     Output<H>: Serialize,
 {
-    /// Root to leaf.
-    sibling_hashes: Vec<Output<H>>,
+    /// Root to leaf. Empty siblings are left out (the client can compute
+    /// them from the key index), so this is sent as a compressed path.
+    sibling_hashes: Vec<Option<Output<H>>>,
     /// H(key)
     key_index: Output<H>,
     inner: ProofInner<V, H>,
+    /// Opens the commitment to a member's value (in a private tree).
+    salt: Option<Output<H>>,
+}
+
+impl<V, H: Hasher> Proof<V, H>
+where
+    V: DataSized,
+{
+    pub fn shape(&self) -> PathShape {
+        PathShape {
+            index: self.key_index.to_vec(),
+            siblings: self.sibling_hashes.iter().map(Option::is_some).collect(),
+        }
+    }
+
+    /// Everything but the path.
+    fn leaf_size(&self) -> Information {
+        let hash_size = Information::new::<byte>(<H as Hasher>::output_size());
+        let mut size = hash_size + self.inner.size();
+        if self.salt.is_some() {
+            size += hash_size;
+        }
+        size
+    }
+
+    /// Size of `proofs` sent together, sharing their paths.
+    pub fn batch_size(proofs: &[&Self]) -> Information {
+        let hash_size = Information::new::<byte>(<H as Hasher>::output_size());
+        let shapes: Vec<_> = proofs.iter().map(|proof| proof.shape()).collect();
+        proofs
+            .iter()
+            .fold(merkle_path::batch_size(&shapes, hash_size), |acc, proof| {
+                acc + proof.leaf_size()
+            })
+    }
 }
 
 impl<V, H: Hasher> DataSized for Proof<V, H>
 where
-    ProofInner<V, H>: DataSized,
+    V: DataSized,
 {
     fn size(&self) -> Information {
         let hash_size = Information::new::<byte>(<H as Hasher>::output_size());
-        hash_size * (self.sibling_hashes.len() + 1) + self.inner.size()
+        self.shape().compressed_size(hash_size) + self.leaf_size()
     }
 }
 
@@ -441,12 +543,19 @@ impl<V: Clone, H: Hasher> Proof<&V, H> {
                 value_hash,
             },
         };
-        Proof { inner, ..self }
+        Proof {
+            sibling_hashes: self.sibling_hashes,
+            key_index: self.key_index,
+            inner,
+            salt: self.salt,
+        }
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// Defaults to all zeros, which no tree has (for clients that haven't seen one).
+#[derive(Debug, Clone, Serialize, Derivative)]
 #[serde(bound = "Output<H>: Serialize")]
+#[derivative(Default(bound = ""))]
 pub struct Digest<K, H: Hasher> {
     value: Output<H>,
     _key: PhantomData<K>,
@@ -454,12 +563,16 @@ pub struct Digest<K, H: Hasher> {
 
 /// Insert a node recursively into the tree rooted at `current_node`.
 ///
+/// Unless `rehash`, leaves the nodes above the new leaf with stale hashes (for
+/// [`rehash_paths`] to fix).
+///
 /// Returns the *change* to the node counts.
 fn insert_recursive<H: Hasher>(
     current_node: &mut Node<H>,
     depth: usize,
     index: Output<H>,
     value_hash: Output<H>,
+    rehash: bool,
 ) -> NodeCounts
 where
     ObjectHasher<H>: Hasher<OutputSize = H::OutputSize> + EndianUpdate,
@@ -526,8 +639,13 @@ where
         NodeData::Interior(inner) => {
             // Recurse down the tree. This node is unchanged (but will need to be rehashed).
             let direction = Direction::from(get_bit_i(&index, depth));
-            delta_node_counts =
-                insert_recursive(inner.child_mut(direction), depth + 1, index, value_hash);
+            delta_node_counts = insert_recursive(
+                inner.child_mut(direction),
+                depth + 1,
+                index,
+                value_hash,
+                rehash,
+            );
             None
         }
     };
@@ -536,11 +654,36 @@ where
     if let Some(new_node) = new_node {
         *current_node = new_node;
     }
-    current_node.rehash();
+    if rehash {
+        current_node.rehash();
+    }
 
     delta_node_counts
 }
 
+/// Rehash each interior node on the paths from `current_node` (at `depth`)
+/// down to `indices`, once, bottom-up.
+fn rehash_paths<H: Hasher>(current_node: &mut Node<H>, depth: usize, indices: &[Output<H>])
+where
+    ObjectHasher<H>: Hasher<OutputSize = H::OutputSize> + EndianUpdate,
+{
+    let inner = match &mut current_node.inner {
+        NodeData::Interior(inner) => inner,
+        // Leaves are hashed when they're made.
+        _ => return,
+    };
+    let (right, left): (Vec<_>, Vec<_>) = indices
+        .iter()
+        .cloned()
+        .partition(|index| get_bit_i(index, depth));
+    for (direction, below) in [(Left, left), (Right, right)] {
+        if !below.is_empty() {
+            rehash_paths(inner.child_mut(direction), depth + 1, &below);
+        }
+    }
+    current_node.rehash();
+}
+
 impl<K: Hash, V: Hash, H: Hasher> Tree<K, V, H>
 where
     K: Eq + std::hash::Hash + Debug,
@@ -554,10 +697,45 @@ where
         }
     }
 
+    fn key_index(&self, key: &K) -> Output<H> {
+        hash::<_, H>(Domain::MerkleKey, key)
+    }
+
+    /// Randomness for the commitment to `value` at `key_index` (in a private
+    /// tree).
+    fn salt(&self, key_index: &Output<H>, value: &V) -> Option<Output<H>> {
+        let privacy = self.privacy.as_ref()?;
+        counters::record(Op::Hash);
+        let mut hasher = ObjectHasher::<H>::new();
+        hasher.update(Domain::MerkleCommitment.prefix());
+        hasher.update(privacy.secret);
+        key_index.hash(&mut hasher);
+        value.hash(&mut hasher);
+        Some(hasher.finalize())
+    }
+
+    fn proof<'a>(
+        &self,
+        sibling_hashes: Vec<Option<Output<H>>>,
+        key_index: Output<H>,
+        inner: ProofInner<&'a V, H>,
+    ) -> Proof<&'a V, H> {
+        let salt = match &inner {
+            Member(value) => self.salt(&key_index, value),
+            _ => None,
+        };
+        Proof {
+            sibling_hashes,
+            key_index,
+            inner,
+            salt,
+        }
+    }
+
     /// Look up the given key in the dictionary, along with a proof of correctness.
     pub fn lookup(&self, key: &K) -> Proof<&V, H> {
-        let key_index = hash::<_, H>(key);
-        let mut sibling_hashes = Vec::<Output<H>>::new();
+        let key_index = self.key_index(key);
+        let mut sibling_hashes = Vec::<Option<Output<H>>>::new();
         let mut depth = 0usize;
         let mut current_node = &self.root;
 
@@ -572,26 +750,22 @@ where
                             value_hash: data.value_hash,
                         }
                     };
-                    return Proof {
-                        sibling_hashes,
-                        key_index,
-                        inner,
-                    };
+                    return self.proof(sibling_hashes, key_index, inner);
                 }
                 NodeData::Empty(data) => {
                     // Terminate: a membership proof for the Empty node convinces
                     debug_assert_eq!(mask(&data.prefix, depth), mask(&key_index, depth));
                     let inner = NonMemberEmpty(data.prefix);
-                    return Proof {
-                        sibling_hashes,
-                        key_index,
-                        inner,
-                    };
+                    return self.proof(sibling_hashes, key_index, inner);
                 }
                 NodeData::Interior(inner) => {
                     // Push a new sibling hash and go depeer.
                     let direction = Direction::from(get_bit_i(&key_index, depth));
-                    sibling_hashes.push(inner.sibling(direction).hash);
+                    let sibling = inner.sibling(direction);
+                    sibling_hashes.push(match sibling.inner {
+                        NodeData::Empty(_) => None,
+                        _ => Some(sibling.hash),
+                    });
                     current_node = inner.child(direction);
                 }
             }
@@ -600,14 +774,30 @@ where
     }
 
     pub fn insert(&mut self, key: K, value: V) {
-        let index = hash::<_, H>(&key);
-        let value_hash = hash::<_, H>(&value);
+        let index = self.key_index(&key);
+        let salt = self.salt(&index, &value);
+        let value_hash = value_hash::<_, H>(&value, salt.as_ref());
 
-        let delta_node_counts = insert_recursive(&mut self.root, 0usize, index, value_hash);
+        let delta_node_counts = insert_recursive(&mut self.root, 0usize, index, value_hash, true);
         self.node_counts += delta_node_counts;
 
         self.values.insert(key, value);
     }
+
+    /// Insert several entries, rehashing each node above them once (rather
+    /// than once per entry).
+    pub fn extend(&mut self, entries: impl IntoIterator<Item = (K, V)>) {
+        let mut indices = Vec::new();
+        for (key, value) in entries {
+            let index = self.key_index(&key);
+            let salt = self.salt(&index, &value);
+            let value_hash = value_hash::<_, H>(&value, salt.as_ref());
+            self.node_counts += insert_recursive(&mut self.root, 0usize, index, value_hash, false);
+            self.values.insert(key, value);
+            indices.push(index);
+        }
+        rehash_paths(&mut self.root, 0, &indices);
+    }
 }
 
 /// Verification of a Merkle BPT proof failed.
@@ -673,6 +863,11 @@ fn check_valid_non_member_empty<H: Hasher>(
 }
 
 impl<K, H: Hasher> Digest<K, H> {
+    /// The root hash.
+    pub fn value(&self) -> &Output<H> {
+        &self.value
+    }
+
     /// Verify a lookup proof for key `key` (whether `key` is present or not), returning the result.
     pub fn verify<V>(&self, key: &K, result: Proof<V, H>) -> Result<Option<V>, VerificationError<H>>
     where
@@ -683,12 +878,13 @@ impl<K, H: Hasher> Digest<K, H> {
         H: Debug,
     {
         let mut depth = result.sibling_hashes.len();
-        let key_index = hash(key);
+        let key_index = hash::<_, H>(Domain::MerkleKey, key);
 
         // Compute the hash of the "leaf" node, and check that the purported result makes sense.
         let (mut current_hash, value) = match result.inner {
             ProofInner::Member(value) => {
-                let data = LeafData::new(key_index, depth, hash::<_, H>(&value));
+                let value_hash = value_hash::<_, H>(&value, result.salt.as_ref());
+                let data = LeafData::new(key_index, depth, value_hash);
                 (data.hash(), Some(value))
             }
             ProofInner::NonMemberLeaf {
@@ -709,15 +905,24 @@ impl<K, H: Hasher> Digest<K, H> {
         // Recompute the hash from leaf to root.
         for sibling_hash in result.sibling_hashes.iter().rev() {
             depth -= 1;
-            let direction = Direction::from(get_bit_i(&result.key_index, depth));
+            let direction = Direction::from(get_bit_i(&key_index, depth));
+            let sibling_hash = match sibling_hash {
+                Some(sibling_hash) => *sibling_hash,
+                None => {
+                    // An empty subtree just off our path.
+                    let mut prefix = mask(&key_index, depth + 1);
+                    flip_bit_i(&mut prefix, depth);
+                    EmptyData::<H>::new(depth + 1, prefix).hash()
+                }
+            };
             current_hash = match direction {
-                Left => hash_interior(&current_hash, sibling_hash),
-                Right => hash_interior(sibling_hash, &current_hash),
+                Left => hash_interior(&current_hash, &sibling_hash),
+                Right => hash_interior(&sibling_hash, &current_hash),
             };
         }
         debug_assert_eq!(depth, 0);
 
-        if current_hash != self.value {
+        if !crate::ct::bytes_eq(&current_hash, &self.value) {
             return Err(VerificationError::HashMismatch {
                 computed: current_hash,
                 expected: self.value,
@@ -760,6 +965,36 @@ mod tests {
             assert_eq!(digest.verify(&key, proof), Ok(map.get(&key)));
         }
 
+        /// Tests that private trees still give valid proofs, and that proofs
+        /// don't show the plain hashes of values.
+        #[test]
+        fn test_private_tree(insertions in insertions(), key: Key, other_key: Key) {
+            prop_assume!(key != other_key);
+            let mut tree = Tree::<Key, Value, CRHF>::private(Privacy::new([7; 32]));
+            let mut map = HashMap::<Key, Value>::default();
+
+            for (key, value) in insertions {
+                tree.insert(key, value);
+                map.insert(key, value);
+            }
+
+            let digest = tree.digest();
+            let proof = tree.lookup(&key).cloned();
+            if let NonMemberLeaf { value_hash: commitment, .. } = &proof.inner {
+                prop_assert!(!map.values().any(|v| value_hash::<_, CRHF>(v, None) == *commitment));
+            }
+
+            // The opening (for members) is extra.
+            let plain = Proof { salt: None, ..proof.clone() };
+            let extra = if map.contains_key(&key) { 32 } else { 0 };
+            prop_assert_eq!(proof.size(), plain.size() + Information::new::<byte>(extra));
+
+            prop_assert_eq!(digest.verify(&key, proof.clone()), Ok(map.get(&key).copied()));
+            if map.contains_key(&key) {
+                prop_assert!(digest.verify(&other_key, proof).is_err());
+            }
+        }
+
         /// Tests that verification fails if any bit in the tree digest is perturbed.
         #[test]
         fn test_tree_bad_digest(insertions in insertions(), key: Key, index: Index) {
@@ -864,5 +1099,53 @@ mod tests {
             assert_eq!(tree.node_counts, node_counts);
         }
 
+        /// Tests that inserting a batch at once gives the same tree as inserting
+        /// it one entry at a time.
+        #[test]
+        fn test_tree_extend(before in insertions(), batch in insertions(), key: Key) {
+            let mut one_by_one = Tree::<Key, Value, CRHF>::default();
+            for (key, value) in before.iter().chain(batch.iter()) {
+                one_by_one.insert(*key, *value);
+            }
+            let mut batched = Tree::<Key, Value, CRHF>::default();
+            for (key, value) in before {
+                batched.insert(key, value);
+            }
+            batched.extend(batch);
+
+            assert_eq!(batched.digest().value(), one_by_one.digest().value());
+            assert_eq!(batched.node_counts, one_by_one.node_counts);
+            let proof = batched.lookup(&key);
+            assert_eq!(
+                batched.digest().verify(&key, proof),
+                Ok(one_by_one.values().get(&key))
+            );
+        }
+
+    }
+
+    /// Rejecting a digest that's wrong early shouldn't be faster than
+    /// rejecting one that's wrong late. Timing is noisy, so this is opt-in.
+    #[cfg(feature = "constant-time")]
+    #[test]
+    #[ignore]
+    fn dudect_verify_bad_digest() {
+        let mut tree = Tree::<Key, Value, CRHF>::default();
+        for key in 0..=u8::MAX {
+            tree.insert(key, key);
+        }
+        let proof = tree.lookup(&0);
+
+        let bits = <CRHF as digest::Digest>::output_size() * 8;
+        let mut early = tree.digest();
+        flip_bit_i(&mut early.value, 0);
+        let mut late = tree.digest();
+        flip_bit_i(&mut late.value, bits - 1);
+
+        let t = crate::ct::dudect(100_000, |class| {
+            let digest = if class { &late } else { &early };
+            digest.verify(&0, proof.clone()).is_ok()
+        });
+        assert!(t.abs() < crate::ct::DUDECT_THRESHOLD, "t = {t}");
     }
 }
@@ -0,0 +1,135 @@
+//! Compressed encodings of Merkle authentication paths.
+//!
+//! A raw path has a sibling hash for every level, but most of them are
+//! predictable: deep in a sparse tree, the sibling is almost always an empty
+//! subtree whose hash the client can compute itself. A compressed path sends a
+//! bitmap (one bit per level) marking the siblings that are actually there, and
+//! only their hashes.
+//!
+//! Paths checked together share even more. The verifier recomputes every node
+//! on every path anyway, so a sibling that's on another path doesn't need to be
+//! sent, and a sibling of several paths only needs to be sent once.
+use std::collections::HashSet;
+
+use uom::ConstZero;
+
+use crate::util::{byte, Information};
+
+/// The shape of one authentication path.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PathShape {
+    /// The leaf's index, as bits (most significant first).
+    pub index: Vec<u8>,
+    /// From the root down: whether the sibling at each level has to be sent.
+    pub siblings: Vec<bool>,
+}
+
+/// The first `bits` bits of `index` (the rest zeroed).
+fn prefix(index: &[u8], bits: usize) -> Vec<u8> {
+    let mut prefix = index[..(bits + 7) / 8].to_vec();
+    if bits % 8 != 0 {
+        *prefix.last_mut().unwrap() &= 0xffu8 << (8 - bits % 8);
+    }
+    prefix
+}
+
+impl PathShape {
+    /// Nodes the verifier computes: `(depth, prefix)` from the root down to
+    /// the leaf.
+    fn nodes(&self) -> impl Iterator<Item = (usize, Vec<u8>)> + '_ {
+        (0..=self.siblings.len()).map(|depth| (depth, prefix(&self.index, depth)))
+    }
+
+    /// The sibling of the node at `depth + 1` on this path.
+    fn sibling(&self, depth: usize) -> (usize, Vec<u8>) {
+        let mut sibling = prefix(&self.index, depth + 1);
+        sibling[depth / 8] ^= 0x80 >> (depth % 8);
+        (depth + 1, sibling)
+    }
+
+    fn bitmap_size(&self) -> Information {
+        Information::new::<byte>((self.siblings.len() + 7) / 8)
+    }
+
+    /// Bitmap plus the siblings that have to be sent.
+    pub fn compressed_size(&self, hash_size: Information) -> Information {
+        let sent = self.siblings.iter().filter(|&&sent| sent).count();
+        self.bitmap_size() + hash_size * sent
+    }
+}
+
+/// Bitmaps plus siblings for checking `paths` together.
+pub fn batch_size(paths: &[PathShape], hash_size: Information) -> Information {
+    let on_path: HashSet<_> = paths.iter().flat_map(PathShape::nodes).collect();
+    let mut sent = HashSet::new();
+    let mut size = Information::ZERO;
+    for path in paths {
+        size += path.bitmap_size();
+        for (depth, _) in path.siblings.iter().enumerate().filter(|(_, &s)| s) {
+            let sibling = path.sibling(depth);
+            if !on_path.contains(&sibling) && sent.insert(sibling) {
+                size += hash_size;
+            }
+        }
+    }
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn hash_size() -> Information {
+        Information::new::<byte>(32)
+    }
+
+    fn path(index: u8, siblings: Vec<bool>) -> PathShape {
+        PathShape {
+            index: vec![index],
+            siblings,
+        }
+    }
+
+    #[test]
+    fn shares_siblings() {
+        // Leaves 0b00.. and 0b01.. are each other's siblings at depth 2, and
+        // share the sibling 0b1... at depth 1.
+        let a = path(0b0000_0000, vec![true, true]);
+        let b = path(0b0100_0000, vec![true, true]);
+        assert_eq!(
+            a.compressed_size(hash_size()),
+            Information::new::<byte>(1 + 64)
+        );
+        assert_eq!(
+            batch_size(&[a.clone(), b], hash_size()),
+            Information::new::<byte>(2 + 32)
+        );
+        // Only what's marked gets sent.
+        let sparse = path(0b1000_0000, vec![true, false, false]);
+        assert_eq!(
+            batch_size(&[a, sparse], hash_size()),
+            Information::new::<byte>(2 + 32)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_batch_size_bounds(
+            paths in prop::collection::vec(
+                (any::<u8>(), prop::collection::vec(any::<bool>(), 0..8)),
+                1..5,
+            )
+        ) {
+            let paths: Vec<_> = paths.into_iter().map(|(i, s)| path(i, s)).collect();
+            let separate = paths
+                .iter()
+                .fold(Information::ZERO, |acc, p| acc + p.compressed_size(hash_size()));
+            prop_assert!(batch_size(&paths, hash_size()) <= separate);
+            prop_assert_eq!(
+                batch_size(&paths[..1], hash_size()),
+                paths[0].compressed_size(hash_size())
+            );
+        }
+    }
+}
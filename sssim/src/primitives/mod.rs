@@ -1,18 +1,43 @@
+pub mod domain;
+#[cfg(feature = "rsa")]
 mod group;
+#[cfg(feature = "rsa")]
 mod group_hidden_order;
+#[cfg(feature = "merkle")]
 pub mod merkle;
+pub mod merkle_path;
+#[cfg(feature = "rsa")]
 pub mod prime;
+#[cfg(feature = "rsa")]
+mod product_tree;
+#[cfg(feature = "rsa")]
 mod refinement;
+#[cfg(feature = "rsa")]
 mod rsa_group;
 mod skip_list;
+#[cfg(feature = "smt")]
+pub mod sparse_merkle;
+pub mod srs;
 
+#[cfg(feature = "rsa")]
 pub use prime::Prime;
+#[cfg(feature = "rsa")]
+pub use product_tree::ProductTree;
+#[cfg(feature = "rsa")]
 pub use refinement::{NonNegative, NonZero, Positive};
+#[cfg(feature = "rsa")]
 pub use refinement::{NonZeroInteger, PositiveInteger};
 
+pub use domain::Domain;
 pub use skip_list::{Collector, SkipList};
+pub use srs::{Srs, SrsError};
 
+#[cfg(feature = "rsa")]
 pub use group::Group;
+#[cfg(feature = "rsa")]
 pub use group_hidden_order::AdaptiveRootAssumption;
 
+#[cfg(feature = "rsa")]
+pub use rsa_group::{GroupParameters, GroupParametersError, MIN_MODULUS_BITS};
+#[cfg(feature = "rsa")]
 pub type RsaGroup = rsa_group::Rsa2048Group;
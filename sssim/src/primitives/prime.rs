@@ -1,5 +1,5 @@
 use rug::Integer;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use super::{NonNegative, NonZero};
@@ -17,7 +17,7 @@ pub struct CompositeError {
     value: Integer,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
 pub struct Prime(Integer);
 
 impl NonNegative for Prime {}
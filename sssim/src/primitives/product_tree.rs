@@ -0,0 +1,233 @@
+//! Product trees over big integers.
+//!
+//! Multiplying `n` numbers one at a time into a running product costs
+//! `O(n^2)` (the running product keeps growing); multiplying them pairwise up
+//! a balanced tree costs about one full-width multiplication per level. The
+//! tree also keeps the product of every contiguous half, quarter, etc., which
+//! is exactly what divide-and-conquer algorithms (like precomputing every
+//! witness) need at each split, and gives the product of every leaf *but*
+//! one without dividing.
+use rug::Integer;
+use serde::{Deserialize, Serialize};
+use uom::ConstZero;
+
+use crate::counters::{self, Op};
+use crate::util::{DataSized, Information};
+
+/// A balanced binary tree where each node holds the product of its leaves.
+///
+/// Trees from [`ProductTree::new`] split their leaves at `len / 2` (so they
+/// line up with `slice::split_at(len / 2)`); [`ProductTree::push`] keeps
+/// later leaves on the right instead.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProductTree {
+    product: Integer,
+    len: usize,
+    children: Option<Box<(ProductTree, ProductTree)>>,
+}
+
+impl ProductTree {
+    /// Build the tree over `leaves` (in parallel).
+    ///
+    /// The product of no leaves is 1.
+    pub fn new(leaves: &[Integer]) -> Self {
+        match leaves {
+            [] => Self {
+                product: Integer::from(1u8),
+                len: 0,
+                children: None,
+            },
+            [leaf] => Self::leaf(leaf.clone()),
+            _ => {
+                let (l, r) = leaves.split_at(leaves.len() / 2);
+                let (l, r) = rayon::join(|| Self::new(l), || Self::new(r));
+                Self::join(l, r)
+            }
+        }
+    }
+
+    fn leaf(leaf: Integer) -> Self {
+        Self {
+            product: leaf,
+            len: 1,
+            children: None,
+        }
+    }
+
+    fn join(l: Self, r: Self) -> Self {
+        counters::record(Op::BigIntMult);
+        Self {
+            product: Integer::from(&l.product * &r.product),
+            len: l.len + r.len,
+            children: Some(Box::new((l, r))),
+        }
+    }
+
+    /// The product of all the leaves.
+    pub fn product(&self) -> &Integer {
+        &self.product
+    }
+
+    pub fn into_product(self) -> Integer {
+        self.product
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The subtrees for the first `len / 2` leaves and the rest (if not a leaf).
+    pub fn children(&self) -> Option<(&ProductTree, &ProductTree)> {
+        self.children.as_deref().map(|(l, r)| (l, r))
+    }
+
+    /// The product of every leaf but the `index`th, without dividing.
+    ///
+    /// Multiplies the siblings along the path to the leaf, smallest first, so
+    /// the multiplications stay balanced.
+    pub fn product_without(&self, index: usize) -> Integer {
+        assert!(index < self.len, "index out of bounds");
+        let mut siblings = vec![];
+        let mut node = self;
+        let mut index = index;
+        while let Some((l, r)) = node.children() {
+            if index < l.len {
+                siblings.push(r);
+                node = l;
+            } else {
+                siblings.push(l);
+                index -= l.len;
+                node = r;
+            }
+        }
+        siblings
+            .into_iter()
+            .rev()
+            .fold(Integer::from(1u8), |acc, sibling| {
+                counters::record(Op::BigIntMult);
+                acc * &sibling.product
+            })
+    }
+
+    /// Multiply the `index`th leaf (and so every product above it) by `factor`.
+    pub fn multiply(&mut self, index: usize, factor: &Integer) {
+        assert!(index < self.len, "index out of bounds");
+        counters::record(Op::BigIntMult);
+        self.product *= factor;
+        if let Some((l, r)) = self.children.as_deref_mut() {
+            if index < l.len {
+                l.multiply(index, factor);
+            } else {
+                r.multiply(index - l.len, factor);
+            }
+        }
+    }
+
+    /// Add `leaf` after the others (as leaf `len()`).
+    ///
+    /// The right subtree fills up until it's as big as the left; then the
+    /// whole tree becomes the left subtree of a new root. So the depth stays
+    /// logarithmic.
+    pub fn push(&mut self, leaf: Integer) {
+        if self.is_empty() {
+            *self = Self::leaf(leaf);
+            return;
+        }
+        if let Some((l, r)) = self.children.as_deref_mut() {
+            if r.len < l.len {
+                counters::record(Op::BigIntMult);
+                self.product *= &leaf;
+                self.len += 1;
+                r.push(leaf);
+                return;
+            }
+        }
+        let l = std::mem::take(self);
+        *self = Self::join(l, Self::leaf(leaf));
+    }
+}
+
+impl Default for ProductTree {
+    /// No leaves.
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
+
+impl DataSized for ProductTree {
+    /// Every node's product, so about `log(len)` times the size of the root's.
+    fn size(&self) -> Information {
+        let children = match self.children() {
+            Some((l, r)) => l.size() + r.size(),
+            None => Information::ZERO,
+        };
+        self.product.size() + children
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn leaves() -> impl Strategy<Value = Vec<Integer>> {
+        prop::collection::vec((1..1000u32).prop_map(Integer::from), 0..20)
+    }
+
+    fn naive_product(leaves: &[Integer]) -> Integer {
+        leaves
+            .iter()
+            .fold(Integer::from(1u8), |acc, leaf| acc * leaf)
+    }
+
+    proptest! {
+        #[test]
+        fn test_product_tree(leaves in leaves(), factor in 1..100u32) {
+            let mut tree = ProductTree::new(&leaves);
+            prop_assert_eq!(tree.len(), leaves.len());
+            prop_assert_eq!(tree.product(), &naive_product(&leaves));
+            if let Some((l, r)) = tree.children() {
+                let (expected_l, expected_r) = leaves.split_at(leaves.len() / 2);
+                prop_assert_eq!(l.product(), &naive_product(expected_l));
+                prop_assert_eq!(r.product(), &naive_product(expected_r));
+            }
+
+            for index in 0..leaves.len() {
+                let mut rest = leaves.clone();
+                rest.remove(index);
+                prop_assert_eq!(tree.product_without(index), naive_product(&rest));
+            }
+
+            if !leaves.is_empty() {
+                let index = leaves.len() / 3;
+                let factor = Integer::from(factor);
+                tree.multiply(index, &factor);
+                let mut leaves = leaves;
+                leaves[index] *= &factor;
+                prop_assert_eq!(tree, ProductTree::new(&leaves));
+            }
+        }
+
+        /// Pushing leaves one at a time gives the same products as building
+        /// the tree all at once (if not the same shape).
+        #[test]
+        fn test_product_tree_push(leaves in leaves(), more in leaves()) {
+            let mut tree = ProductTree::new(&leaves);
+            for leaf in more.iter().cloned() {
+                tree.push(leaf);
+            }
+            let all: Vec<_> = leaves.into_iter().chain(more).collect();
+            prop_assert_eq!(tree.len(), all.len());
+            prop_assert_eq!(tree.product(), &naive_product(&all));
+            for index in 0..all.len() {
+                let mut rest = all.clone();
+                rest.remove(index);
+                prop_assert_eq!(tree.product_without(index), naive_product(&rest));
+            }
+        }
+    }
+}
@@ -1,11 +1,18 @@
 use super::{AdaptiveRootAssumption, Group};
-use crate::util::{DataSized, Information};
-use once_cell::sync::Lazy;
-use rug::Integer;
-use serde::Serialize;
-use std::ops::{Add, AddAssign, Deref, Mul, MulAssign};
+use crate::counters::{self, Op};
+use crate::util::{byte, DataSized, Information};
+use once_cell::sync::{Lazy, OnceCell};
+use rand::RngCore;
+use rug::{integer::Order, rand::RandState, Integer};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::ops::{Add, AddAssign, Mul, MulAssign};
+use std::path::Path;
+use thiserror::Error;
 
-static MODULUS: Lazy<Integer> = Lazy::new(|| {
+/// The RSA-2048 challenge number, whose factors nobody knows.
+fn rsa_2048() -> Integer {
     Integer::parse(
         "2519590847565789349402718324004839857142928212620403202777713783604366202070\
            7595556264018525880784406918290641249515082189298559149176184502808489120072\
@@ -19,25 +26,147 @@ static MODULUS: Lazy<Integer> = Lazy::new(|| {
     )
     .unwrap()
     .into()
-});
+}
+
+/// The smallest modulus we'll use (the same floor as `gen-modulus`).
+pub const MIN_MODULUS_BITS: u32 = 1024;
+
+/// Set once, on first use (to RSA-2048 unless [`GroupParameters::install`] ran).
+static MODULUS: OnceCell<Integer> = OnceCell::new();
+
+fn modulus() -> &'static Integer {
+    MODULUS.get_or_init(rsa_2048)
+}
+
+#[derive(Error, Debug)]
+pub enum GroupParametersError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("malformed group parameters: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("the modulus is already in use")]
+    AlreadySet,
+    #[error("invalid modulus: {0}")]
+    InvalidModulus(&'static str),
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("not an element of the RSA group")]
+pub struct InvalidElement;
+
+/// Parameters for [`Rsa2048Group`], for use instead of RSA-2048.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GroupParameters {
+    /// The modulus, as a decimal string.
+    modulus: String,
+}
+
+impl GroupParameters {
+    /// Generate a fresh modulus of (about) `bits` bits.
+    ///
+    /// The factors are dropped as soon as we've multiplied them together, but
+    /// we make no attempt to scrub them from memory; use a real ceremony for
+    /// anything that matters.
+    pub fn generate(bits: u32) -> Self {
+        let mut seed = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut seed);
+        let mut rand = RandState::new();
+        rand.seed(&Integer::from_digits(&seed, Order::Lsf));
+
+        let mut prime = || {
+            let mut candidate = Integer::from(Integer::random_bits(bits / 2, &mut rand));
+            // Top two bits set, so the product has exactly `bits` bits.
+            candidate.set_bit(bits / 2 - 1, true);
+            candidate.set_bit(bits / 2 - 2, true);
+            candidate.next_prime()
+        };
+        let modulus = prime() * prime();
+        Self {
+            modulus: modulus.to_string(),
+        }
+    }
+
+    /// Load parameters from `path`, checking the modulus (see [`Self::install`]).
+    pub fn load(path: &Path) -> Result<Self, GroupParametersError> {
+        let params: Self = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        params.parse()?;
+        Ok(params)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), GroupParametersError> {
+        Ok(serde_json::to_writer_pretty(File::create(path)?, self)?)
+    }
+
+    /// Number of bits in the modulus.
+    pub fn bits(&self) -> Result<u32, GroupParametersError> {
+        Ok(self.parse()?.significant_bits())
+    }
+
+    /// Use these parameters for every group operation from now on.
+    ///
+    /// Fails if any group operation already happened, or if the modulus
+    /// isn't odd and at least [`MIN_MODULUS_BITS`] bits.
+    pub fn install(self) -> Result<(), GroupParametersError> {
+        MODULUS
+            .set(self.parse()?)
+            .map_err(|_| GroupParametersError::AlreadySet)
+    }
+
+    /// The modulus, if it's one we can use.
+    fn parse(&self) -> Result<Integer, GroupParametersError> {
+        let modulus: Integer = Integer::parse(&self.modulus)
+            .map_err(|_| GroupParametersError::InvalidModulus("not a decimal integer"))?
+            .into();
+        if modulus <= 1u8 {
+            return Err(GroupParametersError::InvalidModulus("not greater than 1"));
+        }
+        if modulus.is_even() {
+            return Err(GroupParametersError::InvalidModulus("even"));
+        }
+        if modulus.significant_bits() < MIN_MODULUS_BITS {
+            return Err(GroupParametersError::InvalidModulus("too small"));
+        }
+        Ok(modulus)
+    }
+}
 
 /// The multiplicative group of integers mod RSA-2048.
 ///
 /// A couple of false positives (not co-prime with the modulus), but hitting
 /// them implies that we've factored RSA-2048.
-#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
+///
+/// The modulus can be swapped out with [`GroupParameters::install`].
+///
+/// Elements are really cosets `{x, -x}` (i.e., we work in `Z_N^* / {±1}`),
+/// each represented by whichever of `x` and `N - x` is smaller. Otherwise, a
+/// server could pass off `-x` as a "different" digest from `x` (the low-order
+/// element -1 breaks the adaptive root assumption).
+///
+/// Deserializing goes through [`TryFrom<Integer>`], so it's checked and
+/// normalized like any other element.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(try_from = "Integer")]
 pub struct Rsa2048Group(Integer);
 
 impl Rsa2048Group {
-    /// Check that this is a valid group element.
+    /// Check that this is a valid (normalized) group element.
     fn check_value(&self) -> bool {
-        0u8 < self.0 && &self.0 <= MODULUS.deref()
+        0u8 < self.0 && Integer::from(&self.0 * 2u8) < *modulus()
+    }
+
+    /// Pick the canonical representative of `{x, -x}`.
+    fn normalize(&mut self) {
+        if Integer::from(&self.0 * 2u8) > *modulus() {
+            self.0 = Integer::from(modulus() - &self.0);
+        }
     }
 }
 
+/// Elements go over the wire at a fixed width (see [`Group::bytes`]), so a
+/// small one costs as much as any other.
 impl DataSized for Rsa2048Group {
     fn size(&self) -> Information {
-        self.0.size()
+        Information::new::<byte>(Self::bytes())
     }
 }
 
@@ -48,13 +177,17 @@ impl Default for Rsa2048Group {
 }
 
 impl TryFrom<Integer> for Rsa2048Group {
-    type Error = ();
+    type Error = InvalidElement;
 
     fn try_from(value: Integer) -> Result<Self, Self::Error> {
-        let x = Self(value);
+        if !(0u8 < value && &value < modulus()) {
+            return Err(InvalidElement);
+        }
+        let mut x = Self(value);
+        x.normalize();
         match x.check_value() {
             true => Ok(x),
-            false => Err(()),
+            false => Err(InvalidElement),
         }
     }
 }
@@ -86,8 +219,10 @@ impl Add<Self> for Rsa2048Group {
 
 impl AddAssign<Self> for Rsa2048Group {
     fn add_assign(&mut self, rhs: Self) {
+        counters::record(Op::GroupOp);
         self.0 *= rhs.0;
-        self.0 %= MODULUS.deref();
+        self.0 %= modulus();
+        self.normalize();
         assert!(self.check_value());
     }
 }
@@ -103,9 +238,11 @@ impl Mul<&Integer> for Rsa2048Group {
 
 impl MulAssign<&Integer> for Rsa2048Group {
     fn mul_assign(&mut self, rhs: &Integer) {
+        counters::record(Op::GroupExp);
         self.0
-            .pow_mod_mut(rhs, MODULUS.deref())
+            .pow_mod_mut(rhs, modulus())
             .expect("exp > 0, MODULUS > 0");
+        self.normalize();
         assert!(self.check_value());
     }
 }
@@ -113,7 +250,7 @@ impl MulAssign<&Integer> for Rsa2048Group {
 static ZERO: Lazy<Rsa2048Group> = Lazy::new(|| Integer::from(1).try_into().unwrap());
 static GENERATOR: Lazy<Rsa2048Group> = Lazy::new(|| Integer::from(65337).try_into().unwrap());
 static MAX_VALUE: Lazy<Rsa2048Group> =
-    Lazy::new(|| Integer::from(MODULUS.deref() - 1).try_into().unwrap());
+    Lazy::new(|| Integer::from(modulus() / 2u8).try_into().unwrap());
 
 impl Group for Rsa2048Group {
     fn zero() -> &'static Self {
@@ -131,6 +268,18 @@ impl Group for Rsa2048Group {
     fn bytes() -> usize {
         MAX_VALUE.0.significant_digits::<u8>()
     }
+
+    /// Compare fixed-width encodings, so we don't leak the length of the
+    /// common prefix.
+    #[cfg(feature = "constant-time")]
+    fn ct_eq(&self, other: &Self) -> bool {
+        let encode = |x: &Integer| {
+            let mut digits = vec![0u8; Self::bytes()];
+            x.write_digits(&mut digits, Order::Lsf);
+            digits
+        };
+        crate::ct::bytes_eq(&encode(&self.0), &encode(&other.0))
+    }
 }
 
 impl AdaptiveRootAssumption for Rsa2048Group {}
@@ -141,4 +290,63 @@ mod tests {
     use crate::primitives::group;
 
     group::check_laws!(Rsa2048Group);
+
+    fn element(value: u32) -> Rsa2048Group {
+        Integer::from(value).try_into().unwrap()
+    }
+
+    fn negate(x: &Rsa2048Group) -> Rsa2048Group {
+        Rsa2048Group(Integer::from(modulus() - &x.0))
+    }
+
+    #[test]
+    fn negation_is_normalized_away() {
+        let x = element(65337);
+        let minus_x: Rsa2048Group = Integer::from(modulus() - 65337u32).try_into().unwrap();
+        assert_eq!(minus_x, x);
+    }
+
+    #[test]
+    fn deserialize_checks_elements() {
+        let x = element(65337);
+        let json = serde_json::to_string(&negate(&x)).unwrap();
+        assert_eq!(serde_json::from_str::<Rsa2048Group>(&json).unwrap(), x);
+
+        for invalid in [Integer::from(0), modulus().clone()] {
+            let json = serde_json::to_string(&invalid).unwrap();
+            assert!(serde_json::from_str::<Rsa2048Group>(&json).is_err());
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn sign_flips_dont_change_results(a in 2u32.., b in 2u32.., exp in 1u32..) {
+            let (a, b) = (element(a), element(b));
+            let exp = Integer::from(exp);
+            prop_assert_eq!(negate(&a) + b.clone(), a.clone() + b);
+            prop_assert_eq!(negate(&a) * &exp, a * &exp);
+        }
+    }
+
+    #[test]
+    fn generate_modulus() {
+        let params = GroupParameters::generate(1024);
+        assert_eq!(params.bits().unwrap(), 1024);
+    }
+
+    #[test]
+    fn rejects_bad_moduli() {
+        let even = (Integer::from(Integer::u_pow_u(2, MIN_MODULUS_BITS)) + 2u8).to_string();
+        let negative = format!("-{}", GroupParameters::generate(MIN_MODULUS_BITS).modulus);
+        let small = ["", "abc", "0", "1", "65537"].map(String::from);
+        for modulus in small.into_iter().chain([even, negative]) {
+            let params = GroupParameters { modulus };
+            assert!(
+                matches!(params.bits(), Err(GroupParametersError::InvalidModulus(_))),
+                "accepted {:?}",
+                params.modulus
+            );
+        }
+        assert!(GroupParameters::generate(MIN_MODULUS_BITS).parse().is_ok());
+    }
 }
@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+
 use crate::util::assume_data_size_for_vec;
 use crate::util::DataSized;
 use crate::util::Information;
@@ -23,9 +25,16 @@ pub trait Collector {
     fn to_proof(&self, item: &Self::Item) -> Self::Proof;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "C::Item: Serialize, C::Proof: Serialize",
+    deserialize = "C::Item: Deserialize<'de>, C::Proof: Deserialize<'de>"
+))]
 pub struct SkipList<C: Collector> {
     entries: Vec<SkipListEntry<C::Item, C::Proof>>,
+    /// Number of entries dropped from the front by [`SkipList::prune`].
+    #[serde(default)]
+    pruned: usize,
 }
 
 impl<C: Collector> Default for SkipList<C> {
@@ -49,13 +58,16 @@ where
 //update entry
 impl<C: Collector> SkipList<C> {
     pub fn new() -> Self {
-        Self { entries: vec![] }
+        Self {
+            entries: vec![],
+            pruned: 0,
+        }
     }
 
     pub fn add(&mut self, item: C::Item) {
         let entry = SkipListEntry::<C::Item, C::Proof>::new(item);
         let mut collector = C::init(&entry.item);
-        let max_pow = find_max_pow(self.entries.len());
+        let max_pow = find_max_pow(self.len());
 
         for (e, i) in self.entries.iter_mut().rev().zip(1..=(1 << max_pow)) {
             if i & (i - 1) == 0 {
@@ -68,13 +80,14 @@ impl<C: Collector> SkipList<C> {
 
     pub fn read(&self, start: usize, end: usize) -> Vec<(C::Proof, C::Item)> {
         assert!(start <= end);
-        assert!(end < self.entries.len());
+        assert!(start >= self.pruned, "reading pruned entries");
+        assert!(end < self.len());
 
         let mut cur = start;
         let mut result = vec![];
 
         while cur < end {
-            let cur_entry = &self.entries[cur];
+            let cur_entry = &self.entries[cur - self.pruned];
             let (proof, offset) = cur_entry.find_next(end - cur);
             result.push((proof, cur_entry.item.clone()));
             cur += offset;
@@ -83,16 +96,35 @@ impl<C: Collector> SkipList<C> {
         result
     }
 
+    /// Number of entries ever added (including pruned ones).
     pub fn len(&self) -> usize {
-        self.entries.len()
+        self.pruned + self.entries.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.entries.is_empty()
+        self.len() == 0
+    }
+
+    /// The first index that can still be read.
+    pub fn horizon(&self) -> usize {
+        self.pruned
+    }
+
+    /// Drop all entries before index `horizon`.
+    ///
+    /// Proofs only point forward, so reads starting at or after `horizon` are
+    /// unaffected. Indices don't change.
+    pub fn prune(&mut self, horizon: usize) {
+        let horizon = std::cmp::min(horizon, self.len());
+        if horizon <= self.pruned {
+            return;
+        }
+        self.entries.drain(..horizon - self.pruned);
+        self.pruned = horizon;
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct SkipListEntry<I, P> {
     item: I,
     proofs: Vec<P>,
@@ -109,9 +141,11 @@ where
         }
     }
 
+    /// The longest hop that doesn't overshoot `offset` (entry `k` only has
+    /// hops of the powers of two dividing `k`).
     fn find_next(&self, offset: usize) -> (P, usize) {
         let mut i = 0;
-        while offset >> i > 0 {
+        while offset >> i > 0 && i < self.proofs.len() {
             i += 1
         }
         (self.proofs[i - 1].clone(), 1 << (i - 1))
@@ -194,4 +228,43 @@ mod test {
         assert_eq!(4, proof[1].1);
         assert!(verify_proof(4, 6, proof[1].0));
     }
+
+    #[test]
+    fn test_skip_list_unaligned_start() {
+        let mut list = SkipList::<(usize, usize)>::default();
+        for i in 0..8 {
+            list.add(i);
+        }
+
+        let proof = list.read(1, 7);
+        let hops: Vec<_> = proof.iter().map(|(proof, _)| *proof).collect();
+        assert_eq!(hops, vec![(1, 2), (2, 4), (4, 6), (6, 7)]);
+    }
+
+    #[test]
+    fn test_skip_list_prune() {
+        let mut list = SkipList::<(usize, usize)>::default();
+        let mut unpruned = SkipList::<(usize, usize)>::default();
+        for i in 0..10 {
+            list.add(i);
+            unpruned.add(i);
+        }
+        list.prune(8);
+        assert_eq!(list.horizon(), 8);
+        assert_eq!(list.len(), 10);
+
+        // Pruning backwards does nothing.
+        list.prune(1);
+        assert_eq!(list.horizon(), 8);
+
+        for i in 10..20 {
+            list.add(i);
+            unpruned.add(i);
+        }
+        for end in 8..20 {
+            let expected = unpruned.read(8, end);
+            let actual = list.read(8, end);
+            assert_eq!(format!("{actual:?}"), format!("{expected:?}"));
+        }
+    }
 }
@@ -0,0 +1,854 @@
+//! Sparse Merkle tree over 256-bit keys.
+//!
+//! Trees can also be shorter ([`Tree::with_height`]), in which case keys are
+//! truncated to their first `height` bits, as real deployments do (64 to 128
+//! bits is common). That makes proofs smaller, at the cost of a chance that
+//! two keys collide (see [`collision_probability`]).
+//!
+//! Conceptually, every possible key has a leaf, and almost all of them are
+//! empty. Subtrees holding at most one leaf are collapsed (as in Diem's Jellyfish
+//! Merkle tree): an empty subtree hashes to [`EMPTY`] and a subtree with one
+//! leaf hashes to that leaf, which commits to its full key. So the nodes we
+//! store and the hashes we compute scale with the number of leaves, not with
+//! the height of the tree.
+//!
+//! Beyond lookups, a proof for a key is enough to compute the root after
+//! changing that key's value, which gives cheap [`Update`] proofs: a chain of
+//! them shows how one root turned into another.
+//!
+//! The hash function is a type parameter ([`TreeHash`]): SHA-3 by default, or
+//! BLAKE3, which is several times faster in software. Big batches of leaves
+//! (e.g. importing a whole repository) are hashed on all cores either way.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::ops::RangeInclusive;
+
+use digest::Digest;
+use rayon::prelude::*;
+use serde::Serialize;
+use sha3::Sha3_256;
+use thiserror::Error;
+use uom::ConstZero;
+
+use crate::counters::{self, Op};
+use crate::ct;
+use crate::primitives::merkle_path::{self, PathShape};
+use crate::primitives::Domain;
+use crate::util::{byte, DataSized, Information};
+
+/// Number of levels below the root (for untruncated keys).
+pub const HEIGHT: usize = 256;
+
+/// The shortest tree we'll build: below this, even small repositories are
+/// likely to have colliding keys.
+pub const MIN_HEIGHT: usize = 32;
+
+pub type Key = [u8; 32];
+pub type Hash = [u8; 32];
+
+/// Hash of a subtree with no leaves.
+pub const EMPTY: Hash = [0; 32];
+
+/// Below this many new leaves, [`Tree::extend`] does all its hashing on the
+/// current thread (handing out the work costs more than it saves).
+const PARALLEL_LEAVES: usize = 1 << 12;
+
+/// A 256-bit hash function for the tree (and for keys and values).
+pub trait TreeHash:
+    Debug + Clone + Copy + Default + PartialEq + Eq + Send + Sync + 'static
+{
+    /// Hash `parts` (concatenated) under `domain`, without counting it.
+    fn digest(domain: Domain, parts: &[&[u8]]) -> Hash;
+}
+
+/// SHA3-256.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sha3;
+
+impl TreeHash for Sha3 {
+    fn digest(domain: Domain, parts: &[&[u8]]) -> Hash {
+        let mut hasher = Sha3_256::new();
+        hasher.update(domain.prefix());
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize().into()
+    }
+}
+
+/// BLAKE3 (256-bit output).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Blake3;
+
+impl TreeHash for Blake3 {
+    fn digest(domain: Domain, parts: &[&[u8]]) -> Hash {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&domain.prefix());
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize().into()
+    }
+}
+
+fn hash_size() -> Information {
+    Information::new::<byte>(std::mem::size_of::<Hash>())
+}
+
+fn hash_all<H: TreeHash>(domain: Domain, parts: &[&[u8]]) -> Hash {
+    counters::record(Op::Hash);
+    H::digest(domain, parts)
+}
+
+/// Hash `data` under `domain` with SHA-3 (see [`hash_with`]).
+pub fn hash(domain: Domain, data: &[u8]) -> Hash {
+    hash_with::<Sha3>(domain, data)
+}
+
+pub fn hash_with<H: TreeHash>(domain: Domain, data: &[u8]) -> Hash {
+    hash_all::<H>(domain, &[data])
+}
+
+fn leaf_hash<H: TreeHash>(key: &Key, value: &Hash) -> Hash {
+    hash_all::<H>(Domain::SparseMerkleLeaf, &[key, value])
+}
+
+fn interior_hash<H: TreeHash>(left: &Hash, right: &Hash) -> Hash {
+    hash_all::<H>(Domain::SparseMerkleInterior, &[left, right])
+}
+
+/// Bit `depth` of `key` (most significant first): whether the path to `key`
+/// goes right below `depth`.
+fn bit(key: &Key, depth: usize) -> bool {
+    key[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+/// The first `bits` bits of `key`, with every later byte replaced by `fill`.
+fn fill(key: &Key, bits: usize, fill: u8) -> Key {
+    let mut key = *key;
+    if bits < HEIGHT {
+        let mask = 0xff >> (bits % 8);
+        key[bits / 8] = (key[bits / 8] & !mask) | (fill & mask);
+        key[bits / 8 + 1..].fill(fill);
+    }
+    key
+}
+
+/// The first `bits` bits of `key` (the rest zeroed).
+fn prefix(key: &Key, bits: usize) -> Key {
+    fill(key, bits, 0x00)
+}
+
+/// All keys starting with the first `bits` bits of `key`.
+pub fn subtree(key: &Key, bits: usize) -> RangeInclusive<Key> {
+    fill(key, bits, 0x00)..=fill(key, bits, 0xff)
+}
+
+/// Prefix of the child (at `depth + 1`) of `key`'s subtree at `depth`.
+fn child(key: &Key, depth: usize, right: bool) -> Key {
+    let mut child = prefix(key, depth);
+    if right {
+        child[depth / 8] |= 0x80 >> (depth % 8);
+    }
+    child
+}
+
+/// Bytes needed to send a key truncated to `height` bits.
+fn key_size(height: usize) -> Information {
+    Information::new::<byte>((height + 7) / 8)
+}
+
+/// Probability that some two of `keys` random keys agree on their first
+/// `height` bits (by the birthday bound, so a slight overestimate).
+pub fn collision_probability(keys: usize, height: usize) -> f64 {
+    let pairs = keys as f64 * keys.saturating_sub(1) as f64 / 2.0;
+    (pairs / 2f64.powi(height.try_into().unwrap_or(i32::MAX))).min(1.0)
+}
+
+/// Length of the longest common prefix of `a` and `b`, in bits.
+fn shared_bits(a: &Key, b: &Key) -> usize {
+    match a.iter().zip(b).position(|(x, y)| x != y) {
+        Some(i) => i * 8 + (a[i] ^ b[i]).leading_zeros() as usize,
+        None => HEIGHT,
+    }
+}
+
+/// `node` and its sibling, hashed in the order given by bit `depth` of `key`.
+fn parent_hash<H: TreeHash>(key: &Key, depth: usize, node: &Hash, sibling: &Hash) -> Hash {
+    if bit(key, depth) {
+        interior_hash::<H>(sibling, node)
+    } else {
+        interior_hash::<H>(node, sibling)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Leaf {
+    value: Hash,
+    hash: Hash,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tree<H: TreeHash = Sha3> {
+    /// Levels below the root; keys are truncated to this many bits.
+    height: usize,
+    leaves: BTreeMap<Key, Leaf>,
+    /// Hashes of the subtrees with at least two leaves, by depth and prefix.
+    interior: HashMap<(usize, Key), Hash>,
+    /// Number of nodes hashed so far.
+    hashes: u64,
+    hasher: PhantomData<H>,
+}
+
+impl<H: TreeHash> Default for Tree<H> {
+    fn default() -> Self {
+        Self::empty(HEIGHT)
+    }
+}
+
+/// Trees using SHA-3 (see [`Tree::empty`] for other hash functions).
+impl Tree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An empty tree with `height` levels, keeping the first `height` bits of
+    /// each key.
+    pub fn with_height(height: usize) -> Self {
+        Self::empty(height)
+    }
+
+    pub fn from_leaves(leaves: impl IntoIterator<Item = (Key, Hash)>) -> Self {
+        let mut tree = Self::new();
+        tree.extend(leaves);
+        tree
+    }
+}
+
+impl<H: TreeHash> Tree<H> {
+    /// An empty tree with `height` levels, hashing with `H`.
+    pub fn empty(height: usize) -> Self {
+        assert!(
+            (1..=HEIGHT).contains(&height),
+            "height must be between 1 and {HEIGHT}, not {height}"
+        );
+        Self {
+            height,
+            leaves: BTreeMap::new(),
+            interior: HashMap::new(),
+            hashes: 0,
+            hasher: PhantomData,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// `key`, truncated to this tree's height.
+    fn truncate(&self, key: &Key) -> Key {
+        prefix(key, self.height)
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    pub fn get(&self, key: &Key) -> Option<&Hash> {
+        self.leaves.get(&self.truncate(key)).map(|leaf| &leaf.value)
+    }
+
+    /// Leaf and interior hashes computed so far, over all updates.
+    ///
+    /// Unlike timings, this doesn't depend on the machine. Lookups don't count:
+    /// they only read hashes stored by updates.
+    pub fn hash_count(&self) -> u64 {
+        self.hashes
+    }
+
+    pub fn root(&self) -> Hash {
+        self.node(0, &[0; 32])
+    }
+
+    /// Whether the stored hashes match the leaves, i.e. rebuilding the tree
+    /// from its leaves gives the same leaf hashes and interior nodes.
+    pub fn is_consistent(&self) -> bool {
+        let mut rebuilt = Self::empty(self.height);
+        rebuilt.extend(self.leaves.iter().map(|(key, leaf)| (*key, leaf.value)));
+        rebuilt.leaves == self.leaves && rebuilt.interior == self.interior
+    }
+
+    /// Hash of the subtree at `depth` on the path to `key`.
+    fn node(&self, depth: usize, key: &Key) -> Hash {
+        let mut below = self.leaves.range(subtree(key, depth));
+        match (below.next(), below.next()) {
+            (None, _) => EMPTY,
+            (Some((_, leaf)), None) => leaf.hash,
+            _ => self.interior[&(depth, prefix(key, depth))],
+        }
+    }
+
+    pub fn insert(&mut self, key: Key, value: Hash) {
+        self.extend([(key, value)]);
+    }
+
+    /// Set several leaves at once.
+    ///
+    /// Each node above the new leaves gets rehashed once, however many of them
+    /// are below it. Big batches are hashed in parallel.
+    pub fn extend(&mut self, leaves: impl IntoIterator<Item = (Key, Hash)>) {
+        let leaves: Vec<_> = leaves
+            .into_iter()
+            .map(|(key, value)| (self.truncate(&key), value))
+            .collect();
+        let parallel = leaves.len() >= PARALLEL_LEAVES;
+        let hash = |(key, value): &(Key, Hash)| leaf_hash::<H>(key, value);
+        let hashes: Vec<_> = if parallel {
+            leaves.par_iter().map(hash).collect()
+        } else {
+            leaves.iter().map(hash).collect()
+        };
+        self.hashes += hashes.len() as u64;
+        let mut keys = Vec::with_capacity(leaves.len());
+        for ((key, value), hash) in leaves.into_iter().zip(hashes) {
+            self.leaves.insert(key, Leaf { value, hash });
+            keys.push(key);
+        }
+        if parallel {
+            keys.par_sort_unstable();
+        } else {
+            keys.sort_unstable();
+        }
+        keys.dedup();
+        if !keys.is_empty() {
+            let mut nodes = Vec::new();
+            self.rehash(0, &keys, &mut nodes);
+            self.hashes += nodes.len() as u64;
+            self.interior.extend(nodes);
+        }
+    }
+
+    /// Recompute the nodes on the paths to `keys` (sorted, and all in one
+    /// subtree at `depth`), returning the hash of that subtree.
+    ///
+    /// The new interior hashes go in `nodes` rather than straight into the
+    /// tree, so that big subtrees can be rehashed on separate threads. (Only
+    /// nodes with none of `keys` below them get read, and those don't change.)
+    fn rehash(&self, depth: usize, keys: &[Key], nodes: &mut Vec<((usize, Key), Hash)>) -> Hash {
+        let mut below = self.leaves.range(subtree(&keys[0], depth));
+        if let (Some((_, leaf)), None) = (below.next(), below.next()) {
+            return leaf.hash;
+        }
+        let (left, right) = keys.split_at(keys.partition_point(|key| !bit(key, depth)));
+        let (left, right) = if left.len().min(right.len()) >= PARALLEL_LEAVES / 2 {
+            let mut right_nodes = Vec::new();
+            let hashes = rayon::join(
+                || self.rehash_child(depth, &keys[0], false, left, nodes),
+                || self.rehash_child(depth, &keys[0], true, right, &mut right_nodes),
+            );
+            nodes.append(&mut right_nodes);
+            hashes
+        } else {
+            (
+                self.rehash_child(depth, &keys[0], false, left, nodes),
+                self.rehash_child(depth, &keys[0], true, right, nodes),
+            )
+        };
+        let hash = interior_hash::<H>(&left, &right);
+        nodes.push(((depth, prefix(&keys[0], depth)), hash));
+        hash
+    }
+
+    /// Hash of the (left or `right`) child at `depth + 1` of the subtree
+    /// containing `key`, rehashing it if it has any of `keys` below it.
+    fn rehash_child(
+        &self,
+        depth: usize,
+        key: &Key,
+        right: bool,
+        keys: &[Key],
+        nodes: &mut Vec<((usize, Key), Hash)>,
+    ) -> Hash {
+        match keys {
+            [] => self.node(depth + 1, &child(key, depth, right)),
+            keys => self.rehash(depth + 1, keys, nodes),
+        }
+    }
+
+    /// Proof of the value at `key` (or that there isn't one).
+    pub fn lookup(&self, key: &Key) -> Proof<H> {
+        let key = &self.truncate(key);
+        let mut siblings = Vec::new();
+        for depth in 0..=self.height {
+            let mut below = self.leaves.range(subtree(key, depth));
+            let leaf = match (below.next(), below.next()) {
+                (None, _) => None,
+                (Some((other, leaf)), None) => Some((*other, leaf.value)),
+                _ => {
+                    let sibling = self.node(depth + 1, &child(key, depth, !bit(key, depth)));
+                    siblings.push((sibling != EMPTY).then_some(sibling));
+                    continue;
+                }
+            };
+            return Proof {
+                height: self.height,
+                key: *key,
+                siblings,
+                leaf,
+                hasher: PhantomData,
+            };
+        }
+        unreachable!("at most one key fits in a subtree at the bottom of the tree")
+    }
+
+    /// Proofs for several keys, to be sent together (see [`Proof::batch_size`]).
+    pub fn lookup_batch(&self, keys: &[Key]) -> Vec<Proof<H>> {
+        keys.iter().map(|key| self.lookup(key)).collect()
+    }
+
+    /// Set `key` to `value`, proving how the root changed.
+    pub fn update(&mut self, key: Key, value: Hash) -> Update<H> {
+        let key = self.truncate(&key);
+        let old = self.get(&key).copied();
+        let proof = self.lookup(&key);
+        self.insert(key, value);
+        Update {
+            key,
+            old,
+            new: value,
+            proof,
+        }
+    }
+}
+
+impl<H: TreeHash> DataSized for Tree<H> {
+    fn size(&self) -> Information {
+        // Leaves: key, value, and hash. Interior nodes: depth, prefix, and hash.
+        let leaf_size = key_size(self.height) + Information::new::<byte>(2 * 32);
+        let interior_size = Information::new::<byte>(std::mem::size_of::<usize>() + 2 * 32);
+        leaf_size * self.leaves.len() + interior_size * self.interior.len()
+    }
+}
+
+/// Verification of a sparse Merkle tree proof failed.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum VerificationError {
+    #[error("path has {0} levels, more than the tree")]
+    TooDeep(usize),
+    #[error("leaf {leaf:?} is not in the subtree of {key:?} at depth {depth}")]
+    OffPath { leaf: Key, key: Key, depth: usize },
+    #[error("computed root {computed:?} doesn't match expected root {expected:?}")]
+    RootMismatch { computed: Hash, expected: Hash },
+    #[error("update claims the old value was {claimed:?}, but it was {actual:?}")]
+    OldValueMismatch {
+        claimed: Option<Hash>,
+        actual: Option<Hash>,
+    },
+}
+
+/// Authentication path for a key: its sibling hashes, plus whatever the path
+/// ends at.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Proof<H: TreeHash = Sha3> {
+    /// Height of the tree (not sent: it's fixed for a repository).
+    #[serde(skip)]
+    height: usize,
+    /// The key looked up, truncated (not sent: the client knows it).
+    #[serde(skip)]
+    key: Key,
+    /// Root to leaf. Empty siblings are left out, so this is sent as a
+    /// compressed path.
+    siblings: Vec<Option<Hash>>,
+    /// The only leaf in the subtree where the path ends (if it isn't empty).
+    leaf: Option<(Key, Hash)>,
+    #[serde(skip)]
+    hasher: PhantomData<H>,
+}
+
+impl<H: TreeHash> Proof<H> {
+    /// Recompute the root from `node`, the subtree where the path ends.
+    fn root_from(&self, key: &Key, node: Hash) -> Hash {
+        self.siblings
+            .iter()
+            .enumerate()
+            .rev()
+            .fold(node, |node, (depth, sibling)| {
+                parent_hash::<H>(key, depth, &node, &sibling.unwrap_or(EMPTY))
+            })
+    }
+
+    /// The value at `key`, if this proof checks out against `root`.
+    pub fn verify(&self, root: &Hash, key: &Key) -> Result<Option<Hash>, VerificationError> {
+        let key = &prefix(key, self.height);
+        let depth = self.siblings.len();
+        if depth > self.height {
+            return Err(VerificationError::TooDeep(depth));
+        }
+        let node = match &self.leaf {
+            Some((leaf, value)) => {
+                if shared_bits(leaf, key) < depth {
+                    return Err(VerificationError::OffPath {
+                        leaf: *leaf,
+                        key: *key,
+                        depth,
+                    });
+                }
+                leaf_hash::<H>(leaf, value)
+            }
+            None => EMPTY,
+        };
+
+        let computed = self.root_from(key, node);
+        if !ct::bytes_eq(&computed, root) {
+            return Err(VerificationError::RootMismatch {
+                computed,
+                expected: *root,
+            });
+        }
+
+        Ok(match self.leaf {
+            Some((leaf, value)) if &leaf == key => Some(value),
+            _ => None,
+        })
+    }
+
+    /// The root after setting `key` to `value`, for a proof that checks out
+    /// (for `key`) against the current root.
+    pub fn root_after(&self, key: &Key, value: &Hash) -> Hash {
+        let key = &prefix(key, self.height);
+        let depth = self.siblings.len();
+        let new = leaf_hash::<H>(key, value);
+        let node = match &self.leaf {
+            Some((other, other_value)) if other != key => {
+                // Both leaves end up in this subtree, which is empty down to
+                // where their keys diverge.
+                let shared = shared_bits(key, other);
+                let other = leaf_hash::<H>(other, other_value);
+                let split = parent_hash::<H>(key, shared, &new, &other);
+                (depth..shared).rev().fold(split, |node, depth| {
+                    parent_hash::<H>(key, depth, &node, &EMPTY)
+                })
+            }
+            _ => new,
+        };
+        self.root_from(key, node)
+    }
+
+    pub fn shape(&self) -> PathShape {
+        PathShape {
+            index: self.key[..(self.height + 7) / 8].to_vec(),
+            siblings: self.siblings.iter().map(Option::is_some).collect(),
+        }
+    }
+
+    /// Everything but the path.
+    fn leaf_size(&self) -> Information {
+        match &self.leaf {
+            // A different key and its value hash.
+            Some((leaf, _)) if leaf != &self.key => key_size(self.height) + hash_size(),
+            // The client has the key and the value already.
+            _ => Information::ZERO,
+        }
+    }
+
+    /// Size of `proofs` sent together, sharing their paths.
+    pub fn batch_size(proofs: &[&Self]) -> Information {
+        let shapes: Vec<_> = proofs.iter().map(|proof| proof.shape()).collect();
+        proofs.iter().fold(
+            merkle_path::batch_size(&shapes, hash_size()),
+            |acc, proof| acc + proof.leaf_size(),
+        )
+    }
+}
+
+impl<H: TreeHash> DataSized for Proof<H> {
+    fn size(&self) -> Information {
+        self.shape().compressed_size(hash_size()) + self.leaf_size()
+    }
+}
+
+/// Proof that setting `key` from `old` to `new` takes the tree from one root to
+/// the next.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Update<H: TreeHash = Sha3> {
+    pub key: Key,
+    pub old: Option<Hash>,
+    pub new: Hash,
+    proof: Proof<H>,
+}
+
+impl<H: TreeHash> Update<H> {
+    /// The root after this update, if it applies to `root`.
+    pub fn verify(&self, root: &Hash) -> Result<Hash, VerificationError> {
+        let actual = self.proof.verify(root, &self.key)?;
+        if actual != self.old {
+            return Err(VerificationError::OldValueMismatch {
+                claimed: self.old,
+                actual,
+            });
+        }
+        Ok(self.proof.root_after(&self.key, &self.new))
+    }
+}
+
+impl<H: TreeHash> DataSized for Update<H> {
+    fn size(&self) -> Information {
+        // The key and both values go along with the path.
+        let mut size = key_size(self.proof.height) + hash_size() + self.proof.size();
+        if self.old.is_some() {
+            size += hash_size();
+        }
+        size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Keys differing only in their first and last bytes, so that paths share
+    /// long prefixes.
+    fn key(first: u8, last: u8) -> Key {
+        let mut key = [0; 32];
+        key[0] = first;
+        key[31] = last;
+        key
+    }
+
+    fn value(x: u8) -> Hash {
+        hash(Domain::SparseMerkleValue, &[x])
+    }
+
+    fn insertions() -> impl Strategy<Value = Vec<(Key, Hash)>> {
+        prop::collection::vec(
+            (any::<u8>(), any::<u8>(), any::<u8>()).prop_map(|(a, b, v)| (key(a, b), value(v))),
+            0..20,
+        )
+    }
+
+    #[test]
+    fn test_helpers() {
+        let index = key(0b1010_0000, 0b0000_0001);
+        assert_eq!(prefix(&index, 3), key(0b1010_0000, 0));
+        assert_eq!(prefix(&index, 2), key(0b1000_0000, 0));
+        assert_eq!(prefix(&index, HEIGHT), index);
+        assert_eq!(child(&index, 1, true), key(0b1100_0000, 0));
+        assert_eq!(shared_bits(&index, &index), HEIGHT);
+        assert_eq!(shared_bits(&index, &prefix(&index, 8)), 255);
+        assert_eq!(shared_bits(&index, &child(&index, 1, true)), 1);
+        assert_eq!(*subtree(&index, 4).end(), {
+            let mut end = [0xff; 32];
+            end[0] = 0b1010_1111;
+            end
+        });
+    }
+
+    proptest! {
+        #[test]
+        fn test_lookup(insertions in insertions(), other in any::<(u8, u8)>()) {
+            let mut tree = Tree::new();
+            let mut expected = HashMap::new();
+            for (key, value) in insertions.clone() {
+                tree.insert(key, value);
+                expected.insert(key, value);
+            }
+            prop_assert_eq!(tree.len(), expected.len());
+            let root = tree.root();
+            for (key, value) in &expected {
+                prop_assert_eq!(tree.lookup(key).verify(&root, key), Ok(Some(*value)));
+            }
+            let other = key(other.0, other.1);
+            prop_assert_eq!(
+                tree.lookup(&other).verify(&root, &other),
+                Ok(expected.get(&other).copied())
+            );
+
+            // Inserting everything at once gives the same tree.
+            prop_assert_eq!(Tree::from_leaves(insertions).root(), root);
+        }
+
+        #[test]
+        fn test_update(insertions in insertions()) {
+            let mut tree = Tree::new();
+            for (key, value) in insertions {
+                let root = tree.root();
+                let update = tree.update(key, value);
+                prop_assert_eq!(update.verify(&root), Ok(tree.root()));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_proofs() {
+        let tree = Tree::from_leaves([
+            (key(0, 0), value(0)),
+            (key(0, 1), value(1)),
+            (key(0xff, 0), value(2)),
+        ]);
+        let root = tree.root();
+        let proof = tree.lookup(&key(0, 0));
+        // `key(0, 0)` and `key(0, 1)` only split at the bottom of the tree.
+        assert_eq!(proof.siblings.len(), HEIGHT);
+        assert!(matches!(
+            proof.verify(&root, &key(0, 1)),
+            Err(VerificationError::OffPath { .. })
+        ));
+        assert!(matches!(
+            proof.verify(&EMPTY, &key(0, 0)),
+            Err(VerificationError::RootMismatch { .. })
+        ));
+        let mut tampered = proof.clone();
+        tampered.leaf = Some((key(0, 0), value(1)));
+        assert!(matches!(
+            tampered.verify(&root, &key(0, 0)),
+            Err(VerificationError::RootMismatch { .. })
+        ));
+        // The path to `key(0x80, 0)` ends at `key(0xff, 0)`.
+        let proof = tree.lookup(&key(0x80, 0));
+        assert_eq!(proof.verify(&root, &key(0x80, 0)), Ok(None));
+        assert!(matches!(
+            proof.verify(&root, &key(0x7f, 0)),
+            Err(VerificationError::OffPath { .. })
+        ));
+
+        let update = Tree::new().update(key(0, 0), value(0));
+        assert!(matches!(
+            update.verify(&root),
+            Err(VerificationError::RootMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn truncated_keys() {
+        let mut tree = Tree::with_height(8);
+        tree.insert(key(0x10, 0), value(0));
+        tree.insert(key(0x11, 0), value(1));
+        // Only the first byte counts, so this overwrites the first leaf.
+        tree.insert(key(0x10, 1), value(2));
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree.get(&key(0x10, 0)), Some(&value(2)));
+
+        let root = tree.root();
+        let proof = tree.lookup(&key(0x10, 0xff));
+        assert_eq!(proof.siblings.len(), 8);
+        assert_eq!(proof.verify(&root, &key(0x10, 0)), Ok(Some(value(2))));
+        assert!(matches!(
+            proof.verify(&root, &key(0x11, 0)),
+            Err(VerificationError::OffPath { .. })
+        ));
+        let update = tree.update(key(0x12, 0), value(3));
+        assert_eq!(update.verify(&root), Ok(tree.root()));
+
+        // A path ending at another leaf sends that leaf's (shorter) key.
+        let full = Tree::from_leaves([(key(0x10, 0), value(0))]);
+        let mut short = Tree::with_height(64);
+        short.insert(key(0x10, 0), value(0));
+        let other = key(0x20, 0);
+        assert_eq!(
+            full.lookup(&other).size() - short.lookup(&other).size(),
+            Information::new::<byte>(32 - 8)
+        );
+    }
+
+    #[test]
+    fn collisions() {
+        assert_eq!(collision_probability(0, 8), 0.0);
+        assert_eq!(collision_probability(1, 8), 0.0);
+        assert_eq!(collision_probability(2, 8), 1.0 / 256.0);
+        assert_eq!(collision_probability(1 << 20, 8), 1.0);
+        // A million keys in 64 bits: about 3e-8.
+        let p = collision_probability(1_000_000, 64);
+        assert!(1e-8 < p && p < 1e-7, "{p}");
+        assert!(collision_probability(1_000_000, HEIGHT) < 1e-60);
+    }
+
+    #[test]
+    fn parallel_extend() {
+        let leaves: Vec<_> = (0..2 * PARALLEL_LEAVES as u32)
+            .map(|i| {
+                (
+                    hash(Domain::SparseMerkleIndex, &i.to_be_bytes()),
+                    value(i as u8),
+                )
+            })
+            .collect();
+        let batched = Tree::from_leaves(leaves.clone());
+        let mut chunked = Tree::new();
+        for chunk in leaves.chunks(PARALLEL_LEAVES / 4) {
+            chunked.extend(chunk.iter().copied());
+        }
+        assert_eq!(batched.root(), chunked.root());
+        // Every leaf and interior node, hashed once.
+        assert_eq!(
+            batched.hash_count(),
+            (leaves.len() + batched.interior.len()) as u64
+        );
+    }
+
+    #[test]
+    fn blake3() {
+        let leaves = [(key(0, 0), value(0)), (key(0xff, 0), value(1))];
+        let mut tree = Tree::<Blake3>::default();
+        tree.extend(leaves);
+        assert_ne!(tree.root(), Tree::from_leaves(leaves).root());
+
+        let root = tree.root();
+        let proof = tree.lookup(&key(0, 0));
+        assert_eq!(proof.verify(&root, &key(0, 0)), Ok(Some(value(0))));
+        let update = tree.update(key(0x80, 0), value(2));
+        assert_eq!(update.verify(&root), Ok(tree.root()));
+    }
+
+    #[test]
+    fn batch_shares_hashes() {
+        let initial: Vec<_> = (0..16).map(|i| (key(i * 16, 0), value(i))).collect();
+        let new: Vec<_> = (0..16).map(|i| (key(i * 16 + 8, 0), value(i))).collect();
+        let tree = Tree::from_leaves(initial);
+        let base = tree.hash_count();
+
+        let mut one_at_a_time = tree.clone();
+        for (key, value) in new.clone() {
+            let before = one_at_a_time.hash_count();
+            one_at_a_time.insert(key, value);
+            // The leaf, plus the five interior nodes above it.
+            assert_eq!(one_at_a_time.hash_count() - before, 6);
+        }
+        let mut batched = tree;
+        batched.extend(new);
+        assert_eq!(batched.root(), one_at_a_time.root());
+        // Each of the 31 interior nodes of the top five levels is hashed once.
+        assert_eq!(batched.hash_count() - base, 16 + 31);
+        assert_eq!(one_at_a_time.hash_count() - base, 16 * 6);
+    }
+
+    #[test]
+    fn batch_lookup() {
+        let tree = Tree::from_leaves((0..16).map(|i| (key(i * 16, 0), value(i))));
+        let keys: Vec<_> = (0..16).map(|i| key(i * 16, 0)).collect();
+        let proofs = tree.lookup_batch(&keys);
+        // 16 evenly spread leaves fill the top four levels.
+        for proof in &proofs {
+            assert_eq!(proof.siblings.len(), 4);
+            assert_eq!(
+                proof.verify(&tree.root(), &proof.key),
+                Ok(tree.get(&proof.key).copied())
+            );
+        }
+        let proofs: Vec<_> = proofs.iter().collect();
+        let separate = proofs
+            .iter()
+            .fold(Information::ZERO, |acc, proof| acc + proof.size());
+        assert_eq!(
+            separate,
+            16 * (Information::new::<byte>(1) + 4 * hash_size())
+        );
+        // Every sibling is on some other path.
+        assert_eq!(Proof::batch_size(&proofs), 16 * Information::new::<byte>(1));
+    }
+}
@@ -0,0 +1,108 @@
+//! Structured reference strings, for pairing-based schemes (KZG polynomial
+//! commitments, vector commitments).
+//!
+//! None of our techniques use pairings yet. This loads an SRS generated
+//! elsewhere (e.g. by a powers-of-tau ceremony), so that precompute results can
+//! count what it costs to ship and load one.
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::util::{byte, DataSized, Information};
+
+/// Size of one power: a compressed BLS12-381 G1 point.
+pub const POWER_BYTES: usize = 48;
+
+#[derive(Error, Debug)]
+pub enum SrsError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("SRS is {0} bytes, not a whole number of {POWER_BYTES}-byte powers")]
+    Truncated(usize),
+    #[error("SRS has {powers} powers, but committing to {packages} packages needs {packages}")]
+    TooSmall { powers: usize, packages: usize },
+}
+
+/// The powers `g, g^τ, g^{τ^2}, ...` of a secret `τ`, as compressed points
+/// one after another.
+///
+/// We don't do any group operations on them, so we don't parse the points.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Srs {
+    powers: Vec<u8>,
+}
+
+impl Srs {
+    pub fn load(path: &Path) -> Result<Self, SrsError> {
+        Self::from_bytes(fs::read(path)?)
+    }
+
+    pub fn from_bytes(powers: Vec<u8>) -> Result<Self, SrsError> {
+        if powers.len() % POWER_BYTES != 0 {
+            return Err(SrsError::Truncated(powers.len()));
+        }
+        Ok(Self { powers })
+    }
+
+    /// Number of powers (the maximum degree, plus one).
+    pub fn powers(&self) -> usize {
+        self.powers.len() / POWER_BYTES
+    }
+
+    /// Check that this is big enough to commit to a vector of `packages`
+    /// entries (one power per entry).
+    pub fn check(&self, packages: usize) -> Result<(), SrsError> {
+        if self.powers() < packages {
+            return Err(SrsError::TooSmall {
+                powers: self.powers(),
+                packages,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl DataSized for Srs {
+    fn size(&self) -> Information {
+        Information::new::<byte>(self.powers.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sized_by_powers() {
+        let srs = Srs::from_bytes(vec![0; 10 * POWER_BYTES]).unwrap();
+        assert_eq!(srs.powers(), 10);
+        assert_eq!(srs.size(), Information::new::<byte>(10 * POWER_BYTES));
+        assert!(srs.check(10).is_ok());
+        assert!(matches!(
+            srs.check(11),
+            Err(SrsError::TooSmall {
+                powers: 10,
+                packages: 11
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_partial_powers() {
+        assert!(matches!(
+            Srs::from_bytes(vec![0; POWER_BYTES + 1]),
+            Err(SrsError::Truncated(49))
+        ));
+    }
+
+    #[test]
+    fn load() {
+        let path = std::env::temp_dir().join(format!("sssim-srs-{}", std::process::id()));
+        fs::write(&path, vec![0; 3 * POWER_BYTES]).unwrap();
+        let srs = Srs::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(srs.powers(), 3);
+    }
+}
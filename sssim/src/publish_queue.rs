@@ -0,0 +1,346 @@
+//! Many publishers, one server.
+//!
+//! The simulator applies each publish the moment it's logged and times only
+//! the authenticator's work. A real server applies publishes one at a time
+//! (each one produces the next snapshot), so when several publishers upload
+//! at once, all but one wait. [`PublishQueue`] replays that: each publish
+//! arrives at its log timestamp from some [`PublisherId`], waits until the
+//! server picks it (per an [`OrderingPolicy`]), then takes as long as the
+//! authenticator took. It records the wait (queueing delay) separately from
+//! the work (processing time), since only the wait grows with contention.
+//!
+//! Logs don't say who published what, so each package gets a publisher by
+//! hashing its name (see [`PublishQueue::publisher`]).
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use hdrhistogram::Histogram;
+use serde::Serialize;
+use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+
+use crate::latency::{new_histogram, Percentiles};
+use crate::log::PackageId;
+
+/// Who uploaded a publish.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct PublisherId(pub u32);
+
+/// Which waiting publish the server takes next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum OrderingPolicy {
+    /// One at a time, in order of arrival.
+    #[default]
+    Fifo,
+    /// Everything that's arrived by the time the server is free, as one
+    /// batch (which finishes all at once).
+    BatchByArrival,
+    /// One at a time, highest-priority publisher first, then by arrival.
+    Priority,
+}
+
+#[derive(Error, Debug)]
+#[error("unknown ordering policy {0:?} (expected `fifo`, `batch`, or `priority`)")]
+pub struct UnknownOrderingPolicy(String);
+
+impl FromStr for OrderingPolicy {
+    type Err = UnknownOrderingPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fifo" => Ok(Self::Fifo),
+            "batch" => Ok(Self::BatchByArrival),
+            "priority" => Ok(Self::Priority),
+            _ => Err(UnknownOrderingPolicy(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for OrderingPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fifo => write!(f, "fifo"),
+            Self::BatchByArrival => write!(f, "batch"),
+            Self::Priority => write!(f, "priority"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Pending {
+    publisher: PublisherId,
+    arrival: OffsetDateTime,
+    processing: Duration,
+}
+
+/// Summary of the delays so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct QueueStats {
+    /// From arrival until the server started on it.
+    pub queueing: Percentiles,
+    /// From start to finish (for a batch, the whole batch's time).
+    pub processing: Percentiles,
+    /// Most publishes waiting at once.
+    pub max_depth: usize,
+}
+
+/// Serializes publishes from several publishers through one server.
+#[derive(Debug, Clone)]
+pub struct PublishQueue {
+    policy: OrderingPolicy,
+    publishers: u32,
+    /// Higher goes first (under [`OrderingPolicy::Priority`]); 0 by default.
+    priorities: HashMap<PublisherId, u32>,
+    /// Publishes that have arrived but not started, in order of arrival.
+    waiting: VecDeque<Pending>,
+    /// When the server finishes what it's started.
+    free_at: Option<OffsetDateTime>,
+    queueing: Histogram<u64>,
+    processing: Histogram<u64>,
+    queueing_by_publisher: HashMap<PublisherId, Histogram<u64>>,
+    max_depth: usize,
+}
+
+impl PublishQueue {
+    pub fn new(policy: OrderingPolicy, publishers: u32) -> Self {
+        assert!(publishers > 0, "need at least one publisher");
+        Self {
+            policy,
+            publishers,
+            priorities: HashMap::default(),
+            waiting: VecDeque::new(),
+            free_at: None,
+            queueing: new_histogram(),
+            processing: new_histogram(),
+            queueing_by_publisher: HashMap::default(),
+            max_depth: 0,
+        }
+    }
+
+    /// Give `publisher`'s publishes `priority` (higher goes first).
+    pub fn with_priority(mut self, publisher: PublisherId, priority: u32) -> Self {
+        self.priorities.insert(publisher, priority);
+        self
+    }
+
+    pub fn policy(&self) -> OrderingPolicy {
+        self.policy
+    }
+
+    /// Who publishes `package`: a deterministic function of its name, so runs
+    /// are reproducible and every technique sees the same publishers.
+    pub fn publisher(&self, package: &PackageId) -> PublisherId {
+        let mut hasher = DefaultHasher::new();
+        package.hash(&mut hasher);
+        let publisher = hasher.finish() % u64::from(self.publishers);
+        PublisherId(
+            publisher
+                .try_into()
+                .expect("fewer than u32::MAX publishers"),
+        )
+    }
+
+    /// A publish from `publisher` arrived at `at`, and takes `processing` once
+    /// the server starts on it.
+    ///
+    /// # Panics
+    ///
+    /// If arrivals are out of order.
+    pub fn arrive(&mut self, publisher: PublisherId, at: OffsetDateTime, processing: Duration) {
+        if let Some(last) = self.waiting.back() {
+            assert!(last.arrival <= at, "publishes must arrive in order");
+        }
+        self.run(Some(at));
+        self.waiting.push_back(Pending {
+            publisher,
+            arrival: at,
+            processing,
+        });
+        self.max_depth = self.max_depth.max(self.waiting.len());
+    }
+
+    /// Finish everything that's waiting (e.g. at the end of a log).
+    pub fn finish(&mut self) {
+        self.run(None);
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            queueing: Percentiles::from(&self.queueing),
+            processing: Percentiles::from(&self.processing),
+            max_depth: self.max_depth,
+        }
+    }
+
+    /// Queueing delay for just `publisher`'s publishes, if it has any.
+    pub fn queueing_for(&self, publisher: PublisherId) -> Option<Percentiles> {
+        self.queueing_by_publisher
+            .get(&publisher)
+            .map(Percentiles::from)
+    }
+
+    /// Start whatever the server would have started before `until` (or
+    /// everything, if `None`).
+    ///
+    /// A start at exactly `until` waits: more publishes may arrive then, and
+    /// they should get a say.
+    fn run(&mut self, until: Option<OffsetDateTime>) {
+        while let Some(first) = self.waiting.front() {
+            let start = match self.free_at {
+                Some(free_at) => free_at.max(first.arrival),
+                None => first.arrival,
+            };
+            if matches!(until, Some(until) if start >= until) {
+                return;
+            }
+            let batch = self.take(start);
+            let processing = batch
+                .iter()
+                .fold(Duration::ZERO, |total, pending| total + pending.processing);
+            for pending in batch {
+                let queueing = start - pending.arrival;
+                record(&mut self.queueing, queueing);
+                record(&mut self.processing, processing);
+                record(
+                    self.queueing_by_publisher
+                        .entry(pending.publisher)
+                        .or_insert_with(new_histogram),
+                    queueing,
+                );
+            }
+            self.free_at = Some(start + processing);
+        }
+    }
+
+    /// Remove what the server starts on at `start`.
+    fn take(&mut self, start: OffsetDateTime) -> Vec<Pending> {
+        // Waiting publishes are in order of arrival, so the ones that have
+        // arrived by `start` come first (and there's at least one).
+        let arrived = self
+            .waiting
+            .iter()
+            .take_while(|pending| pending.arrival <= start)
+            .count();
+        match self.policy {
+            OrderingPolicy::Fifo => self.waiting.pop_front().into_iter().collect(),
+            OrderingPolicy::BatchByArrival => self.waiting.drain(..arrived).collect(),
+            OrderingPolicy::Priority => {
+                // The earliest of the highest priority.
+                let (next, _) = self
+                    .waiting
+                    .iter()
+                    .take(arrived)
+                    .enumerate()
+                    .min_by_key(|(i, pending)| {
+                        let priority = self.priorities.get(&pending.publisher).copied();
+                        (std::cmp::Reverse(priority.unwrap_or(0)), *i)
+                    })
+                    .expect("something has arrived");
+                self.waiting.remove(next).into_iter().collect()
+            }
+        }
+    }
+}
+
+/// Unlike `saturating_record`, grows the histogram to fit.
+fn record(histogram: &mut Histogram<u64>, duration: Duration) {
+    let nanos = duration.whole_nanoseconds().try_into().unwrap_or(0);
+    histogram
+        .record(nanos)
+        .expect("auto-resizing histograms fit any u64");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Nanoseconds from a histogram, to the nearest millisecond.
+    fn ms(ns: u64) -> u64 {
+        (ns + 500_000) / 1_000_000
+    }
+
+    /// Three publishes at once, 10ms of work each.
+    fn contended(queue: &mut PublishQueue) {
+        let start = OffsetDateTime::UNIX_EPOCH;
+        for publisher in 0..3 {
+            queue.arrive(PublisherId(publisher), start, Duration::milliseconds(10));
+        }
+        queue.finish();
+    }
+
+    #[test]
+    fn parse_policy() {
+        for policy in [
+            OrderingPolicy::Fifo,
+            OrderingPolicy::BatchByArrival,
+            OrderingPolicy::Priority,
+        ] {
+            assert_eq!(
+                policy.to_string().parse::<OrderingPolicy>().unwrap(),
+                policy
+            );
+        }
+        assert!("lifo".parse::<OrderingPolicy>().is_err());
+    }
+
+    #[test]
+    fn publishers_are_deterministic() {
+        let queue = PublishQueue::new(OrderingPolicy::Fifo, 4);
+        let package = PackageId::from("package");
+        assert_eq!(queue.publisher(&package), queue.publisher(&package));
+        assert!(queue.publisher(&package).0 < 4);
+    }
+
+    #[test]
+    fn fifo() {
+        let mut queue = PublishQueue::new(OrderingPolicy::Fifo, 3);
+        contended(&mut queue);
+        let stats = queue.stats();
+        assert_eq!(stats.queueing.count, 3);
+        assert_eq!(stats.max_depth, 3);
+        // Waits of 0, 10, and 20ms; each takes 10ms.
+        assert_eq!(ms(stats.queueing.max_ns), 20);
+        assert_eq!(ms(stats.processing.max_ns), 10);
+        assert_eq!(queue.queueing_for(PublisherId(0)).unwrap().max_ns, 0);
+    }
+
+    #[test]
+    fn no_contention_no_wait() {
+        let mut queue = PublishQueue::new(OrderingPolicy::Fifo, 1);
+        let start = OffsetDateTime::UNIX_EPOCH;
+        for i in 0..3 {
+            queue.arrive(
+                PublisherId(0),
+                start + Duration::seconds(i),
+                Duration::milliseconds(10),
+            );
+        }
+        queue.finish();
+        assert_eq!(queue.stats().queueing.max_ns, 0);
+    }
+
+    #[test]
+    fn batch_by_arrival() {
+        let mut queue = PublishQueue::new(OrderingPolicy::BatchByArrival, 3);
+        contended(&mut queue);
+        let stats = queue.stats();
+        // Nobody waits, but everyone waits for the whole batch.
+        assert_eq!(stats.queueing.max_ns, 0);
+        assert_eq!(ms(stats.processing.p50_ns), 30);
+    }
+
+    #[test]
+    fn priority() {
+        let mut queue =
+            PublishQueue::new(OrderingPolicy::Priority, 3).with_priority(PublisherId(2), 1);
+        contended(&mut queue);
+        // The last to arrive goes first...
+        assert_eq!(queue.queueing_for(PublisherId(2)).unwrap().max_ns, 0);
+        // ...then the rest in order.
+        let wait = |publisher| ms(queue.queueing_for(PublisherId(publisher)).unwrap().max_ns);
+        assert_eq!((wait(0), wait(1)), (10, 20));
+    }
+}
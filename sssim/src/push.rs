@@ -0,0 +1,126 @@
+//! Push-based (pub/sub) metadata distribution.
+//!
+//! Instead of clients polling with `refresh_metadata`, the server can end an
+//! "epoch" every so often and push the delta since the previous epoch to every
+//! subscriber. Subscribers are then always current, and all share the same
+//! snapshot, so the server computes each delta once.
+//!
+//! The catch is that the server pays for every delta (times the number of
+//! subscribers), whereas a polling client only pays for one diff covering
+//! however long it's been since it last checked. [`Broadcast`] keeps track of
+//! both so they can be compared.
+use uom::ConstZero;
+
+use crate::util::{DataSized, Information};
+use crate::Authenticator;
+
+/// Bandwidth for distributing one epoch's delta.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpochUsage {
+    /// Size of the delta itself.
+    pub delta: Information,
+    /// Bytes sent to all subscribers (`delta` times the subscriber count).
+    pub push: Information,
+}
+
+/// A server pushing per-epoch deltas to a set of subscribers.
+pub struct Broadcast<A: Authenticator> {
+    subscribers: usize,
+    /// The snapshot as of the last epoch (which every subscriber has).
+    snapshot: A::ClientSnapshot,
+    /// The snapshot as of subscription (to compare with a single poll).
+    initial: A::ClientSnapshot,
+    epochs: usize,
+    pushed: Information,
+}
+
+impl<A: Authenticator> Broadcast<A> {
+    /// Subscribe `subscribers` clients to `authenticator`'s current state.
+    pub fn new(authenticator: &A, subscribers: usize) -> Self {
+        let snapshot = authenticator.get_metadata();
+        Self {
+            subscribers,
+            initial: snapshot.clone(),
+            snapshot,
+            epochs: 0,
+            pushed: Information::ZERO,
+        }
+    }
+
+    pub fn subscribers(&self) -> usize {
+        self.subscribers
+    }
+
+    pub fn epochs(&self) -> usize {
+        self.epochs
+    }
+
+    /// Total bytes pushed to subscribers so far.
+    pub fn pushed(&self) -> Information {
+        self.pushed
+    }
+
+    /// End an epoch: push everything since the last one to every subscriber.
+    pub fn epoch(&mut self, authenticator: &A) -> EpochUsage {
+        self.epochs += 1;
+        let delta = match authenticator.refresh_metadata(A::id(&self.snapshot)) {
+            Some(diff) => {
+                let delta = diff.size();
+                assert!(A::check_no_rollback(&self.snapshot, &diff));
+                A::update(&mut self.snapshot, diff);
+                delta
+            }
+            None => Information::ZERO,
+        };
+        let push = delta * self.subscribers;
+        self.pushed += push;
+        EpochUsage { delta, push }
+    }
+
+    /// Bytes to bring every subscriber up to date if they had instead polled
+    /// once, just now.
+    pub fn poll(&self, authenticator: &A) -> Information {
+        authenticator
+            .refresh_metadata(A::id(&self.initial))
+            .as_ref()
+            .map(DataSized::size)
+            .unwrap_or_default()
+            * self.subscribers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authenticator::{Hackage, NoAuth};
+    use crate::log::PackageId;
+    use crate::testing::package_ids;
+
+    fn run<A: Authenticator>() -> (Broadcast<A>, A) {
+        let packages = package_ids(10);
+        let mut authenticator = A::batch_import(packages);
+        let mut broadcast = Broadcast::new(&authenticator, 1000);
+        for epoch in 0..5 {
+            for i in 0..3 {
+                authenticator.publish(PackageId::from(format!("new{epoch}-{i}")));
+            }
+            let usage = broadcast.epoch(&authenticator);
+            assert_eq!(usage.push, usage.delta * 1000);
+        }
+        (broadcast, authenticator)
+    }
+
+    #[test]
+    fn push_totals() {
+        let (broadcast, authenticator) = run::<Hackage>();
+        assert_eq!(broadcast.epochs(), 5);
+        assert!(broadcast.pushed() > Information::ZERO);
+        // Hackage deltas are just the new entries, so pushing them piecemeal
+        // costs the same as one big poll.
+        assert_eq!(broadcast.pushed(), broadcast.poll(&authenticator));
+
+        let (broadcast, authenticator) = run::<NoAuth>();
+        assert_eq!(broadcast.pushed(), Information::ZERO);
+        assert_eq!(broadcast.poll(&authenticator), Information::ZERO);
+    }
+}
@@ -0,0 +1,259 @@
+//! Derived metrics (per package, per day, per publish) for reports.
+//!
+//! Ratios like "bytes per package per day" used to be worked out in
+//! spreadsheets, whose formulas and float rounding differed from sheet to
+//! sheet, so the same run could come out slightly differently. [`Report`] is a
+//! [`Hook`] that sums what the simulator measured as integers (bytes and
+//! nanoseconds), and [`DerivedMetrics`] divides those sums in [`Fixed`] point:
+//! the same run always gives the same digits.
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use serde::{Serialize, Serializer};
+use time::OffsetDateTime;
+
+use crate::hooks::Hook;
+use crate::log::{Action, Entry};
+use crate::simulator::ResourceUsage;
+use crate::util::byte;
+
+/// Digits [`Fixed`] keeps after the decimal point.
+pub const DECIMALS: u32 = 6;
+
+const SCALE: u128 = 10u128.pow(DECIMALS);
+
+const NS_PER_DAY: u128 = 24 * 60 * 60 * 1_000_000_000;
+
+/// A non-negative number with [`DECIMALS`] digits after the decimal point,
+/// stored as a whole number of millionths.
+///
+/// Serializes as its decimal string (e.g. `"12.500000"`), so JSON readers
+/// don't round it through a float either.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(u64);
+
+impl Fixed {
+    pub const ZERO: Self = Self(0);
+
+    /// `numerator / denominator`, rounded half up.
+    ///
+    /// `None` if `denominator` is zero or the result doesn't fit.
+    pub fn ratio(numerator: u128, denominator: u128) -> Option<Self> {
+        if denominator == 0 {
+            return None;
+        }
+        let scaled = numerator.checked_mul(SCALE)?;
+        let rounded = scaled.checked_add(denominator / 2)? / denominator;
+        u64::try_from(rounded).ok().map(Self)
+    }
+
+    pub fn from_millionths(millionths: u64) -> Self {
+        Self(millionths)
+    }
+
+    pub fn millionths(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scale = SCALE as u64;
+        write!(
+            f,
+            "{}.{:0width$}",
+            self.0 / scale,
+            self.0 % scale,
+            width = DECIMALS as usize
+        )
+    }
+}
+
+impl Serialize for Fixed {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+/// Integer totals over a simulated log.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Totals {
+    /// Bytes sent to clients, for every request.
+    pub bandwidth_bytes: u128,
+    pub publishes: u128,
+    /// Server time spent on publishes.
+    pub publish_ns: u128,
+    /// Bytes clients downloaded from refreshes, which publishes cause.
+    pub refresh_bytes: u128,
+    /// Timestamps of the first and last entries.
+    pub span: Option<(OffsetDateTime, OffsetDateTime)>,
+}
+
+impl Totals {
+    /// Add one processed log entry.
+    pub fn add(&mut self, entry: &Entry, usage: &ResourceUsage) {
+        let bandwidth = usage.bandwidth.get::<byte>() as u128;
+        self.bandwidth_bytes += bandwidth;
+        match &entry.action {
+            Action::Publish { .. } => {
+                self.publishes += 1;
+                self.publish_ns += nanos(usage.server_compute);
+            }
+            Action::RefreshMetadata { .. } => self.refresh_bytes += bandwidth,
+            Action::Download { .. } | Action::Goodbye { .. } => {}
+        }
+        let (first, _) = self.span.unwrap_or((entry.timestamp, entry.timestamp));
+        self.span = Some((first, entry.timestamp));
+    }
+
+    /// Nanoseconds between the first and last entries.
+    fn span_ns(&self) -> u128 {
+        self.span.map_or(0, |(first, last)| nanos(last - first))
+    }
+}
+
+/// Clamps negative durations (which the simulator never reports) to zero.
+fn nanos(duration: time::Duration) -> u128 {
+    duration.whole_nanoseconds().try_into().unwrap_or(0)
+}
+
+/// Ratios for comparing runs. Any of them is `None` if its denominator was
+/// zero (e.g. a log with no publishes, or that all happens in one instant).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DerivedMetrics {
+    /// Bandwidth per package in the repository, per day of log.
+    pub bytes_per_package_day: Option<Fixed>,
+    /// Server time per publish.
+    pub amortized_publish_ns: Option<Fixed>,
+    /// Refresh bandwidth per publish: what each publish costs clients.
+    pub amortized_publish_refresh_bytes: Option<Fixed>,
+}
+
+impl DerivedMetrics {
+    /// The metrics for `totals`, in a repository of `packages` packages.
+    pub fn new(totals: &Totals, packages: u64) -> Self {
+        let package_ns = u128::from(packages).checked_mul(totals.span_ns());
+        Self {
+            bytes_per_package_day: totals
+                .bandwidth_bytes
+                .checked_mul(NS_PER_DAY)
+                .zip(package_ns)
+                .and_then(|(bytes, package_ns)| Fixed::ratio(bytes, package_ns)),
+            amortized_publish_ns: Fixed::ratio(totals.publish_ns, totals.publishes),
+            amortized_publish_refresh_bytes: Fixed::ratio(totals.refresh_bytes, totals.publishes),
+        }
+    }
+}
+
+/// Collects [`Totals`] from a [`Simulator`](crate::simulator::Simulator).
+///
+/// Register an `Rc<RefCell<Report>>` (and keep a clone) to read it after the
+/// run.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    packages: u64,
+    totals: Totals,
+}
+
+impl Report {
+    /// For a repository of `packages` packages.
+    pub fn new(packages: u64) -> Self {
+        Self {
+            packages,
+            totals: Totals::default(),
+        }
+    }
+
+    pub fn totals(&self) -> &Totals {
+        &self.totals
+    }
+
+    pub fn metrics(&self) -> DerivedMetrics {
+        DerivedMetrics::new(&self.totals, self.packages)
+    }
+}
+
+impl Hook for Rc<RefCell<Report>> {
+    fn on_event(&mut self, entry: &Entry, usage: &ResourceUsage) {
+        self.borrow_mut().totals.add(entry, usage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use time::Duration;
+
+    use crate::authenticator::{Authenticator, Hackage};
+    use crate::log::{Package, PackageId, UserId};
+    use crate::simulator::Simulator;
+
+    #[test]
+    fn fixed_point() {
+        assert_eq!(Fixed::ratio(1, 3).unwrap().to_string(), "0.333333");
+        assert_eq!(Fixed::ratio(2, 3).unwrap().to_string(), "0.666667");
+        assert_eq!(Fixed::ratio(25, 2).unwrap().to_string(), "12.500000");
+        assert_eq!(Fixed::ratio(1, 0), None);
+        assert_eq!(Fixed::ratio(u128::MAX, 1), None);
+        assert_eq!(
+            serde_json::to_string(&Fixed::from_millionths(1_500_000)).unwrap(),
+            r#""1.500000""#
+        );
+    }
+
+    #[test]
+    fn derived() {
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let totals = Totals {
+            bandwidth_bytes: 3000,
+            publishes: 4,
+            publish_ns: 10,
+            refresh_bytes: 1000,
+            span: Some((start, start + Duration::days(2))),
+        };
+        let metrics = DerivedMetrics::new(&totals, 1000);
+        assert_eq!(metrics.bytes_per_package_day, Fixed::ratio(3, 2));
+        assert_eq!(metrics.amortized_publish_ns, Fixed::ratio(5, 2));
+        assert_eq!(
+            metrics.amortized_publish_refresh_bytes,
+            Fixed::ratio(250, 1)
+        );
+
+        let empty = DerivedMetrics::new(&Totals::default(), 1000);
+        assert_eq!(empty, DerivedMetrics::default());
+    }
+
+    #[test]
+    fn from_simulator() {
+        let package = Package {
+            id: PackageId::from("package".to_string()),
+            length: None,
+        };
+        let report = Rc::new(RefCell::new(Report::new(1)));
+        let mut simulator = Simulator::new(Hackage::batch_import(vec![package.id.clone()]))
+            .with_hook(report.clone());
+
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let user = UserId::from("user".to_string());
+        for (hours, action) in [
+            (0, Action::Publish { package }),
+            (24, Action::RefreshMetadata { user }),
+        ] {
+            simulator.process_entry(&mut Entry::new(start + Duration::hours(hours), action));
+        }
+
+        let report = report.borrow();
+        let totals = report.totals();
+        assert_eq!(totals.publishes, 1);
+        assert!(totals.refresh_bytes > 0);
+        assert_eq!(totals.bandwidth_bytes, totals.refresh_bytes);
+        // One package, one day.
+        let metrics = report.metrics();
+        assert_eq!(
+            metrics.bytes_per_package_day,
+            Fixed::ratio(totals.bandwidth_bytes, 1)
+        );
+    }
+}
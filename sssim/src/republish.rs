@@ -0,0 +1,138 @@
+//! What a publish of an unchanged package does.
+//!
+//! Some registries treat uploading the exact same artifact again as a new
+//! release (bumping the revision, and inserting another copy of the package's
+//! prime into the RSA accumulators); others notice that nothing changed and
+//! drop it. That choice changes how fast accumulators grow, so it's a knob:
+//! [`Republishes`] sits in front of an authenticator and decides which
+//! publishes reach it, so every technique sees the same ones.
+//!
+//! A publish is "unchanged" if its [`Targets`] (artifact length and hash) are
+//! the same as the package's latest ones. Publishes without targets always go
+//! through, since we can't tell.
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::authenticator::Targets;
+use crate::log::PackageId;
+
+/// How to handle publishing a package again, unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum RepublishPolicy {
+    /// Every publish is a new revision.
+    #[default]
+    Count,
+    /// Unchanged re-publishes are dropped.
+    Dedupe,
+}
+
+#[derive(Error, Debug)]
+#[error("unknown republish policy {0:?} (expected `count` or `dedupe`)")]
+pub struct UnknownPolicy(String);
+
+impl FromStr for RepublishPolicy {
+    type Err = UnknownPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "count" => Ok(Self::Count),
+            "dedupe" => Ok(Self::Dedupe),
+            _ => Err(UnknownPolicy(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for RepublishPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Count => write!(f, "count"),
+            Self::Dedupe => write!(f, "dedupe"),
+        }
+    }
+}
+
+/// Applies a [`RepublishPolicy`] to a stream of publishes.
+#[derive(Debug, Clone, Default)]
+pub struct Republishes {
+    policy: RepublishPolicy,
+    /// Latest targets for each package published with them.
+    latest: HashMap<PackageId, Targets>,
+    dropped: usize,
+}
+
+impl Republishes {
+    pub fn new(policy: RepublishPolicy) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    pub fn policy(&self) -> RepublishPolicy {
+        self.policy
+    }
+
+    /// Whether `package` (with `targets`, if known) should be published.
+    pub fn admit(&mut self, package: &PackageId, targets: Option<Targets>) -> bool {
+        let targets = match targets {
+            Some(targets) => targets,
+            None => {
+                // Whatever it is now, it's not what we saw last.
+                self.latest.remove(package);
+                return true;
+            }
+        };
+        let unchanged = self.latest.get(package) == Some(&targets);
+        if unchanged && self.policy == RepublishPolicy::Dedupe {
+            self.dropped += 1;
+            return false;
+        }
+        self.latest.insert(package.clone(), targets);
+        true
+    }
+
+    /// How many publishes were dropped as unchanged.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dedupes_unchanged() {
+        let package = PackageId::from("libc");
+        let v1 = Targets::new(100, Default::default());
+        let v2 = Targets::new(200, Default::default());
+        let publishes = [Some(v1), Some(v1), Some(v2), None, Some(v2), Some(v2)];
+
+        let mut count = Republishes::new(RepublishPolicy::Count);
+        let mut dedupe = Republishes::new(RepublishPolicy::Dedupe);
+        let admitted: Vec<_> = publishes
+            .iter()
+            .map(|targets| {
+                assert!(count.admit(&package, *targets));
+                dedupe.admit(&package, *targets)
+            })
+            .collect();
+        assert_eq!(admitted, [true, false, true, true, true, false]);
+        assert_eq!((count.dropped(), dedupe.dropped()), (0, 2));
+    }
+
+    #[test]
+    fn parses() {
+        for policy in [RepublishPolicy::Count, RepublishPolicy::Dedupe] {
+            assert_eq!(
+                policy.to_string().parse::<RepublishPolicy>().unwrap(),
+                policy
+            );
+        }
+        assert!("sometimes".parse::<RepublishPolicy>().is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! Versioned output formats, for analysis scripts.
+//!
+//! Everything we emit for downstream analysis says which version of these
+//! formats it uses: JSON-lines streams start with a [`Header`] line, and the
+//! results database has a `schema` table. Scripts should check it rather than
+//! guessing from which fields are present.
+//!
+//! Bump [`SCHEMA_VERSION`] whenever a field of an output type (here, or in
+//! [`ResourceUsage`] or [`Entry`]) is renamed, removed, or changes units.
+//! Adding a field doesn't need a bump.
+use std::fmt::Debug;
+use std::io::{self, Write};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::hooks::Hook;
+use crate::log::Entry;
+use crate::simulator::ResourceUsage;
+
+/// The current output schema version.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("output has schema version {found}, but this build uses version {SCHEMA_VERSION}")]
+pub struct SchemaMismatch {
+    pub found: u32,
+}
+
+/// First line of every JSON-lines output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    pub schema_version: u32,
+}
+
+impl Header {
+    pub fn current() -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+        }
+    }
+
+    /// Whether output with this header can be read by this build.
+    pub fn check(&self) -> Result<(), SchemaMismatch> {
+        if self.schema_version != SCHEMA_VERSION {
+            return Err(SchemaMismatch {
+                found: self.schema_version,
+            });
+        }
+        Ok(())
+    }
+
+    /// Write the header as a JSON line.
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        serde_json::to_writer(&mut writer, self)?;
+        writer.write_all(b"\n")
+    }
+}
+
+/// One processed log entry, and what it cost.
+#[derive(Debug, Serialize)]
+pub struct Event<'a> {
+    pub entry: &'a Entry,
+    pub result: &'a ResourceUsage,
+}
+
+/// A [`Hook`] that writes every event as a JSON line, after a [`Header`].
+#[derive(Debug)]
+pub struct EventWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> EventWriter<W> {
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        Header::current().write(&mut writer)?;
+        Ok(Self { writer })
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Debug> Hook for EventWriter<W> {
+    fn on_event(&mut self, entry: &Entry, usage: &ResourceUsage) {
+        let event = Event {
+            entry,
+            result: usage,
+        };
+        serde_json::to_writer(&mut self.writer, &event).expect("writing event");
+        self.writer.write_all(b"\n").expect("writing event");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log::{Action, UserId};
+    use crate::util::{byte, Information};
+    use time::{Duration, OffsetDateTime};
+
+    #[test]
+    fn header_first() {
+        let mut writer = EventWriter::new(Vec::new()).unwrap();
+        let entry = Entry::new(
+            OffsetDateTime::UNIX_EPOCH,
+            Action::RefreshMetadata {
+                user: UserId::from("alice".to_string()),
+            },
+        );
+        let usage = ResourceUsage {
+            server_compute: Duration::ZERO,
+            user_compute: Duration::ZERO,
+            user_structure_compute: Duration::ZERO,
+            user_freshness_compute: Duration::ZERO,
+            bandwidth: Information::new::<byte>(100),
+            origin_bandwidth: Information::new::<byte>(100),
+            cdn_bandwidth: Information::new::<byte>(0),
+            storage: Information::new::<byte>(0),
+            user_witness_state: Information::new::<byte>(0),
+            witness_update_bandwidth: Information::new::<byte>(0),
+            targets_bandwidth: Information::new::<byte>(0),
+            round_trips: 1,
+        };
+        writer.on_event(&entry, &usage);
+
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        let mut lines = output.lines();
+        let header: Header = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(header.check(), Ok(()));
+        let event: serde_json::Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(event["result"]["bandwidth_bytes"], 100);
+        assert!(event["entry"]["action"]["RefreshMetadata"].is_object());
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn result_fields() {
+        // Renaming or removing any of these needs a `SCHEMA_VERSION` bump.
+        let usage = ResourceUsage {
+            server_compute: Duration::ZERO,
+            user_compute: Duration::ZERO,
+            user_structure_compute: Duration::ZERO,
+            user_freshness_compute: Duration::ZERO,
+            bandwidth: Information::new::<byte>(0),
+            origin_bandwidth: Information::new::<byte>(0),
+            cdn_bandwidth: Information::new::<byte>(0),
+            storage: Information::new::<byte>(0),
+            user_witness_state: Information::new::<byte>(0),
+            witness_update_bandwidth: Information::new::<byte>(0),
+            targets_bandwidth: Information::new::<byte>(0),
+            round_trips: 0,
+        };
+        let value = serde_json::to_value(usage).unwrap();
+        let mut fields: Vec<_> = value.as_object().unwrap().keys().cloned().collect();
+        fields.sort();
+        assert_eq!(
+            fields,
+            [
+                "bandwidth_bytes",
+                "cdn_bandwidth_bytes",
+                "origin_bandwidth_bytes",
+                "round_trips",
+                "server_compute_ns",
+                "server_storage_bytes",
+                "targets_bandwidth_bytes",
+                "user_compute_ns",
+                "user_freshness_ns",
+                "user_structure_ns",
+                "user_witness_state_bytes",
+                "witness_update_bandwidth_bytes",
+            ]
+        );
+        assert!(Header { schema_version: 0 }.check().is_err());
+    }
+}
@@ -0,0 +1,389 @@
+//! Proof and diff sizes at each security level, worked out on paper (`sssim
+//! security-table`).
+//!
+//! The sizes a run measures are for one choice of parameters: RSA-2048 (or
+//! whatever `--group-params` installed), SHA-3-256, and 256-bit primes. The
+//! parameters table in the appendix needs them at every security level, and a
+//! full run at each would take days for numbers that follow from a handful of
+//! formulas. For a security level of `λ` bits, we take:
+//!
+//! - the RSA modulus from NIST SP 800-57 (3072 bits for 128-bit security);
+//! - hashes of `2λ` bits, for collision resistance;
+//! - hash-to-prime outputs and PoKE challenges of `2λ` bits, as the
+//!   accumulator uses 256 bits for 128-bit security;
+//! - a sparse Merkle tree just tall enough that two packages' keys collide
+//!   with probability at most `2^-λ`.
+//!
+//! [`sizes`] then works out each technique's digest, download proof, and
+//! refresh the way its `DataSized` impls count them. Signatures aren't
+//! included: every technique signs the same snapshot.
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::util::{byte, Information};
+
+/// Security levels with a NIST modulus size, in bits.
+pub const LEVELS: [u32; 5] = [80, 112, 128, 192, 256];
+
+/// Bits of security against the best known attack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SecurityLevel(u32);
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("unknown security level {0:?} (expected one of 80, 112, 128, 192, 256)")]
+pub struct UnknownSecurityLevel(String);
+
+impl SecurityLevel {
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// RSA modulus size for this level (NIST SP 800-57 Part 1, Table 2).
+    pub fn modulus_bits(self) -> u32 {
+        match self.0 {
+            80 => 1024,
+            112 => 2048,
+            128 => 3072,
+            192 => 7680,
+            256 => 15360,
+            _ => unreachable!("not one of LEVELS"),
+        }
+    }
+
+    pub fn hash_bits(self) -> u32 {
+        2 * self.0
+    }
+
+    pub fn prime_bits(self) -> u32 {
+        2 * self.0
+    }
+
+    /// The shortest sparse Merkle tree in which any two of `packages` keys
+    /// collide with probability at most `2^-λ` (by the birthday bound).
+    pub fn tree_height(self, packages: usize) -> usize {
+        let pairs = packages as f64 * packages.saturating_sub(1) as f64 / 2.0;
+        let height = self.0 as usize + pairs.max(1.0).log2().ceil() as usize;
+        height.max(1)
+    }
+}
+
+impl FromStr for SecurityLevel {
+    type Err = UnknownSecurityLevel;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse() {
+            Ok(bits) if LEVELS.contains(&bits) => Ok(Self(bits)),
+            _ => Err(UnknownSecurityLevel(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for SecurityLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Sizes of the primitives at one security level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parameters {
+    pub level: SecurityLevel,
+    pub modulus_bits: u32,
+    pub hash_bits: u32,
+    pub prime_bits: u32,
+    pub tree_height: usize,
+}
+
+impl Parameters {
+    /// The parameters for `level`, for a repository of `packages` packages.
+    pub fn new(level: SecurityLevel, packages: usize) -> Self {
+        Self {
+            level,
+            modulus_bits: level.modulus_bits(),
+            hash_bits: level.hash_bits(),
+            prime_bits: level.prime_bits(),
+            tree_height: level.tree_height(packages),
+        }
+    }
+
+    /// Use a modulus of `bits` bits instead (e.g. the one a run used).
+    pub fn with_modulus_bits(mut self, bits: u32) -> Self {
+        self.modulus_bits = bits;
+        self
+    }
+}
+
+fn bytes(bits: u32) -> Information {
+    Information::new::<byte>((bits as usize + 7) / 8)
+}
+
+/// One technique's sizes at one security level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sizes {
+    pub technique: &'static str,
+    /// What a client keeps to check everything else against.
+    pub digest: Information,
+    /// Proof of one package's revision, sent with a download.
+    pub proof: Information,
+    /// A refresh for a client that missed some releases.
+    pub refresh: Information,
+}
+
+/// Sizes for `rsa` and `sparse_merkle` with `params`, for a repository of
+/// `packages` packages and a client that missed `missed_releases` releases.
+pub fn sizes(params: &Parameters, packages: usize, missed_releases: usize) -> Vec<Sizes> {
+    let element = bytes(params.modulus_bits);
+    let prime = bytes(params.prime_bits);
+    let hash = bytes(params.hash_bits);
+    let version = Information::new::<byte>(8);
+
+    // The append-only proof walks the skip list by powers of two: one PoKE
+    // (two elements and a challenge residue) plus the digest it ends at for
+    // each set bit.
+    let hops = missed_releases.count_ones() as usize;
+    let rsa = Sizes {
+        technique: "rsa",
+        digest: element,
+        // A membership witness, and a non-membership witness (a Bezout
+        // coefficient about the size of the prime, and an element) for the
+        // next revision.
+        proof: element * 2 + prime,
+        refresh: element + (element * 3 + prime) * hops,
+    };
+
+    // A path ends where the package's subtree has no other leaf, about
+    // log2(n) + 1 levels down; nearly every sibling above that is sent.
+    let depth = (packages.max(1) as f64).log2().ceil() as usize + 1;
+    let depth = depth.min(params.tree_height);
+    let sparse_merkle = Sizes {
+        technique: "sparse_merkle",
+        digest: hash + version,
+        proof: Information::new::<byte>((depth + 7) / 8) + hash * depth,
+        // Just the new root: proofs come with downloads.
+        refresh: hash + version,
+    };
+    vec![rsa, sparse_merkle]
+}
+
+/// How to print a [`SizeTable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TableFormat {
+    /// Aligned columns, for a terminal.
+    #[default]
+    Text,
+    /// A `booktabs` tabular, for the paper.
+    Latex,
+}
+
+#[derive(Error, Debug)]
+#[error("unknown table format {0:?} (expected `text` or `latex`)")]
+pub struct UnknownTableFormat(String);
+
+impl FromStr for TableFormat {
+    type Err = UnknownTableFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "latex" => Ok(Self::Latex),
+            _ => Err(UnknownTableFormat(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for TableFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text => write!(f, "text"),
+            Self::Latex => write!(f, "latex"),
+        }
+    }
+}
+
+/// Every technique's sizes at each of several security levels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeTable {
+    pub packages: usize,
+    pub missed_releases: usize,
+    pub rows: Vec<(Parameters, Sizes)>,
+}
+
+impl SizeTable {
+    pub fn new(params: &[Parameters], packages: usize, missed_releases: usize) -> Self {
+        let rows = params
+            .iter()
+            .flat_map(|params| {
+                sizes(params, packages, missed_releases)
+                    .into_iter()
+                    .map(move |sizes| (*params, sizes))
+            })
+            .collect();
+        Self {
+            packages,
+            missed_releases,
+            rows,
+        }
+    }
+
+    pub fn render(&self, format: TableFormat) -> String {
+        let header = [
+            "security".to_string(),
+            "technique".to_string(),
+            "modulus".to_string(),
+            "hash".to_string(),
+            "height".to_string(),
+            "digest (B)".to_string(),
+            "proof (B)".to_string(),
+            format!("refresh, {} behind (B)", self.missed_releases),
+        ];
+        let rows: Vec<[String; 8]> = self
+            .rows
+            .iter()
+            .map(|(params, sizes)| {
+                [
+                    params.level.to_string(),
+                    sizes.technique.to_string(),
+                    params.modulus_bits.to_string(),
+                    params.hash_bits.to_string(),
+                    params.tree_height.to_string(),
+                    sizes.digest.get::<byte>().to_string(),
+                    sizes.proof.get::<byte>().to_string(),
+                    sizes.refresh.get::<byte>().to_string(),
+                ]
+            })
+            .collect();
+        match format {
+            TableFormat::Text => {
+                let mut widths = header.clone().map(|cell| cell.len());
+                for row in &rows {
+                    for (width, cell) in widths.iter_mut().zip(row) {
+                        *width = (*width).max(cell.len());
+                    }
+                }
+                let line = |cells: &[String; 8]| {
+                    let cells: Vec<_> = cells
+                        .iter()
+                        .zip(widths)
+                        .map(|(cell, width)| format!("{cell:<width$}"))
+                        .collect();
+                    format!("{}\n", cells.join("  ").trim_end())
+                };
+                std::iter::once(line(&header))
+                    .chain(rows.iter().map(line))
+                    .collect()
+            }
+            TableFormat::Latex => {
+                let line = |cells: &[String; 8]| {
+                    let cells: Vec<_> = cells.iter().map(|cell| cell.replace('_', "\\_")).collect();
+                    format!("{} \\\\\n", cells.join(" & "))
+                };
+                let mut out = String::from("\\begin{tabular}{llrrrrrr}\n\\toprule\n");
+                out += &line(&header);
+                out += "\\midrule\n";
+                for row in &rows {
+                    out += &line(row);
+                }
+                out += "\\bottomrule\n\\end{tabular}\n";
+                out
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(bits: u32) -> SecurityLevel {
+        bits.to_string().parse().unwrap()
+    }
+
+    #[test]
+    fn parameters() {
+        let params = Parameters::new(level(128), 1_000_000);
+        assert_eq!(params.modulus_bits, 3072);
+        assert_eq!((params.hash_bits, params.prime_bits), (256, 256));
+        // About 2^39 pairs of packages.
+        assert_eq!(params.tree_height, 128 + 39);
+        assert!("100".parse::<SecurityLevel>().is_err());
+    }
+
+    #[test]
+    fn grows_with_level() {
+        let table = SizeTable::new(
+            &LEVELS.map(|bits| Parameters::new(level(bits), 10_000)),
+            10_000,
+            100,
+        );
+        assert_eq!(table.rows.len(), 2 * LEVELS.len());
+        for technique in ["rsa", "sparse_merkle"] {
+            let sizes: Vec<_> = table
+                .rows
+                .iter()
+                .filter(|(_, sizes)| sizes.technique == technique)
+                .map(|(_, sizes)| *sizes)
+                .collect();
+            assert!(sizes.windows(2).all(|pair| pair[0].proof < pair[1].proof));
+            assert!(sizes.windows(2).all(|pair| pair[0].digest < pair[1].digest));
+        }
+    }
+
+    #[test]
+    fn rsa_sizes() {
+        // What a run measures: RSA-2048 with 256-bit primes.
+        let params = Parameters::new(level(128), 1000).with_modulus_bits(2048);
+        let rsa = sizes(&params, 1000, 0b1011)[0];
+        assert_eq!(rsa.digest.get::<byte>(), 256);
+        assert_eq!(rsa.proof.get::<byte>(), 2 * 256 + 32);
+        // Three hops of the skip list.
+        assert_eq!(rsa.refresh.get::<byte>(), 256 + 3 * (3 * 256 + 32));
+    }
+
+    #[test]
+    fn renders() {
+        let table = SizeTable::new(&[Parameters::new(level(128), 1024)], 1024, 4);
+        let text = table.render(TableFormat::Text);
+        assert_eq!(text.lines().count(), 3);
+        assert!(text
+            .lines()
+            .nth(2)
+            .unwrap()
+            .starts_with("128       sparse_merkle"));
+        let latex = table.render(TableFormat::Latex);
+        assert!(latex.contains("128 & sparse\\_merkle & 3072 & 256 & 147 & 40 & 354 & 40 \\\\"));
+        assert!(latex.ends_with("\\end{tabular}\n"));
+    }
+
+    #[cfg(feature = "smt")]
+    #[test]
+    fn sparse_merkle_matches_tree() {
+        use crate::primitives::sparse_merkle::{self, Tree};
+        use crate::util::DataSized;
+
+        let packages = 4096;
+        let keys: Vec<_> = (0..packages)
+            .map(|i: u32| {
+                sparse_merkle::hash(
+                    crate::primitives::Domain::SparseMerkleIndex,
+                    &i.to_le_bytes(),
+                )
+            })
+            .collect();
+        let mut tree: Tree = Tree::new();
+        tree.extend(keys.iter().map(|key| (*key, *key)));
+        let measured = keys
+            .iter()
+            .map(|key| tree.lookup(key).size().get::<byte>())
+            .sum::<usize>() as f64
+            / packages as f64;
+
+        let params = Parameters::new(level(128), packages as usize);
+        let modeled = sizes(&params, packages as usize, 1)[1].proof.get::<byte>() as f64;
+        assert!(
+            (modeled / measured - 1.0).abs() < 0.1,
+            "{modeled} vs {measured}"
+        );
+    }
+}
@@ -0,0 +1,352 @@
+//! Shadow deployments: trialling a new technique alongside the current one.
+//!
+//! A registry switching schemes wouldn't flip over all at once. It would keep
+//! serving clients with the existing (primary) technique while one or more
+//! shadows process the same publishes and requests, and compare. A
+//! [`ShadowSimulator`] does that: every action goes to every deployment, each
+//! with its own clients, and we record
+//!
+//! - divergences: a shadow serving a different revision than the primary, or
+//!   a shadow client rejecting what its server sent; and
+//! - the cost of each deployment, to compare with the primary's.
+//!
+//! Techniques that don't track revisions (`none`, `insecure`) always serve
+//! the initial revision, so they diverge from any primary that does.
+//!
+//! Techniques are chosen at runtime, so this works with
+//! [`DynAuthenticator`]s rather than being generic like
+//! [`Simulator`](crate::simulator::Simulator).
+use std::collections::HashMap;
+
+use serde::Serialize;
+use time::Duration;
+use uom::ConstZero;
+
+use crate::authenticator::{DynAuthenticator, DynSnapshot, Revision};
+use crate::latency::time_fn;
+use crate::log::{Action, PackageId, UserId};
+use crate::simulator::serialize_ns;
+use crate::util::{byte, DataSized, Information};
+
+/// A way in which a deployment disagreed with the primary (or itself).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    /// The shadow served `shadow` for `package` where the primary served
+    /// `primary`.
+    Revision {
+        package: PackageId,
+        primary: Revision,
+        shadow: Revision,
+    },
+    /// A client rejected a refresh (rollback check failed).
+    RejectedDiff { user: UserId },
+    /// A client rejected a download (membership proof didn't verify).
+    RejectedProof { user: UserId, package: PackageId },
+}
+
+/// A divergence, and which deployment it happened in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DivergenceReport {
+    pub technique: &'static str,
+    pub divergence: Divergence,
+}
+
+/// Total resources used by one deployment.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct DeploymentCosts {
+    pub technique: &'static str,
+    #[serde(rename = "bandwidth_bytes")]
+    pub bandwidth: Information,
+    #[serde(rename = "server_compute_ns", serialize_with = "serialize_ns")]
+    pub server_compute: Duration,
+    #[serde(rename = "user_compute_ns", serialize_with = "serialize_ns")]
+    pub user_compute: Duration,
+    /// Server storage after the last action.
+    #[serde(rename = "server_storage_bytes")]
+    pub storage: Information,
+}
+
+impl DeploymentCosts {
+    /// Bandwidth as a multiple of `primary`'s (`NaN` if the primary used none).
+    pub fn bandwidth_ratio(&self, primary: &DeploymentCosts) -> f64 {
+        self.bandwidth.get::<byte>() as f64 / primary.bandwidth.get::<byte>() as f64
+    }
+
+    /// Server time as a multiple of `primary`'s.
+    pub fn server_compute_ratio(&self, primary: &DeploymentCosts) -> f64 {
+        self.server_compute / primary.server_compute
+    }
+
+    /// Client time as a multiple of `primary`'s.
+    pub fn user_compute_ratio(&self, primary: &DeploymentCosts) -> f64 {
+        self.user_compute / primary.user_compute
+    }
+}
+
+/// One technique's server, plus its clients.
+pub(crate) struct Deployment {
+    pub(crate) authenticator: Box<dyn DynAuthenticator>,
+    pub(crate) snapshots: HashMap<UserId, DynSnapshot>,
+    pub(crate) costs: DeploymentCosts,
+}
+
+impl Deployment {
+    pub(crate) fn new(authenticator: Box<dyn DynAuthenticator>) -> Self {
+        let costs = DeploymentCosts {
+            technique: authenticator.name(),
+            bandwidth: Information::ZERO,
+            server_compute: Duration::ZERO,
+            user_compute: Duration::ZERO,
+            storage: authenticator.size(),
+        };
+        Self {
+            authenticator,
+            snapshots: HashMap::new(),
+            costs,
+        }
+    }
+
+    /// Give `user` the current snapshot (replacing any they had), returning
+    /// its size.
+    pub(crate) fn bootstrap(&mut self, user: &UserId) -> Information {
+        let snapshot = self.authenticator.get_metadata();
+        let size = snapshot.size();
+        self.snapshots.insert(user.clone(), snapshot);
+        size
+    }
+
+    /// Bootstrap `user` if they're new.
+    fn add_user(&mut self, user: &UserId) {
+        if !self.snapshots.contains_key(user) {
+            let size = self.bootstrap(user);
+            self.costs.bandwidth += size;
+        }
+    }
+
+    fn refresh(&mut self, user: &UserId) -> Option<Divergence> {
+        self.add_user(user);
+        let snapshot = self.snapshots.get_mut(user).unwrap();
+        let (server_time, diff) = time_fn(|| self.authenticator.refresh_metadata(snapshot));
+        self.costs.server_compute += server_time;
+        let diff = diff?;
+        self.costs.bandwidth += diff.size();
+        let (user_time, accepted) = time_fn(|| {
+            let accepted = snapshot.check_no_rollback(&diff);
+            if accepted {
+                snapshot.update(diff);
+            }
+            accepted
+        });
+        self.costs.user_compute += user_time;
+        (!accepted).then(|| Divergence::RejectedDiff { user: user.clone() })
+    }
+
+    /// Download `package`, returning the revision served.
+    fn download(&mut self, user: &UserId, package: &PackageId) -> (Revision, Option<Divergence>) {
+        self.add_user(user);
+        let snapshot = &self.snapshots[user];
+        let (server_time, (revision, proof)) =
+            time_fn(|| self.authenticator.request_file(snapshot, package));
+        self.costs.server_compute += server_time;
+        self.costs.bandwidth += proof.size();
+        let (user_time, verified) =
+            time_fn(|| snapshot.verify_membership(package, revision, proof));
+        self.costs.user_compute += user_time;
+        let divergence = (!verified).then(|| Divergence::RejectedProof {
+            user: user.clone(),
+            package: package.clone(),
+        });
+        (revision, divergence)
+    }
+
+    fn publish(&mut self, package: &PackageId) {
+        let (server_time, _) = time_fn(|| {
+            self.authenticator.publish(package.clone());
+            self.authenticator.batch_process();
+        });
+        self.costs.server_compute += server_time;
+        self.costs.storage = self.authenticator.size();
+    }
+
+    /// Handle `action` for this deployment alone.
+    pub(crate) fn process(&mut self, action: &Action) -> Option<Divergence> {
+        match action {
+            Action::Download { user, package } => self.download(user, &package.id).1,
+            Action::RefreshMetadata { user } => self.refresh(user),
+            Action::Publish { package } => {
+                self.publish(&package.id);
+                None
+            }
+            Action::Goodbye { user } => {
+                self.snapshots.remove(user);
+                None
+            }
+        }
+    }
+}
+
+/// Runs the same actions against a primary technique and some shadows.
+pub struct ShadowSimulator {
+    primary: Deployment,
+    shadows: Vec<Deployment>,
+    divergences: Vec<DivergenceReport>,
+}
+
+impl ShadowSimulator {
+    /// All deployments should start from the same packages.
+    pub fn new(
+        primary: Box<dyn DynAuthenticator>,
+        shadows: Vec<Box<dyn DynAuthenticator>>,
+    ) -> Self {
+        Self {
+            primary: Deployment::new(primary),
+            shadows: shadows.into_iter().map(Deployment::new).collect(),
+            divergences: Vec::new(),
+        }
+    }
+
+    fn report(&mut self, technique: &'static str, divergence: Option<Divergence>) {
+        if let Some(divergence) = divergence {
+            self.divergences.push(DivergenceReport {
+                technique,
+                divergence,
+            });
+        }
+    }
+
+    pub fn process(&mut self, action: &Action) {
+        match action {
+            Action::Download { user, package } => {
+                let (primary, divergence) = self.primary.download(user, &package.id);
+                self.report(self.primary.costs.technique, divergence);
+                for idx in 0..self.shadows.len() {
+                    let shadow = &mut self.shadows[idx];
+                    let technique = shadow.costs.technique;
+                    let (revision, divergence) = shadow.download(user, &package.id);
+                    self.report(technique, divergence);
+                    if revision != primary {
+                        let divergence = Divergence::Revision {
+                            package: package.id.clone(),
+                            primary,
+                            shadow: revision,
+                        };
+                        self.report(technique, Some(divergence));
+                    }
+                }
+            }
+            Action::RefreshMetadata { user } => {
+                let divergence = self.primary.refresh(user);
+                self.report(self.primary.costs.technique, divergence);
+                for idx in 0..self.shadows.len() {
+                    let technique = self.shadows[idx].costs.technique;
+                    let divergence = self.shadows[idx].refresh(user);
+                    self.report(technique, divergence);
+                }
+            }
+            Action::Publish { package } => {
+                self.primary.publish(&package.id);
+                for shadow in &mut self.shadows {
+                    shadow.publish(&package.id);
+                }
+            }
+            Action::Goodbye { user } => {
+                self.primary.snapshots.remove(user);
+                for shadow in &mut self.shadows {
+                    shadow.snapshots.remove(user);
+                }
+            }
+        }
+    }
+
+    /// Everything that diverged so far, in order.
+    pub fn divergences(&self) -> &[DivergenceReport] {
+        &self.divergences
+    }
+
+    pub fn primary_costs(&self) -> &DeploymentCosts {
+        &self.primary.costs
+    }
+
+    /// Costs for each shadow, in the order they were given.
+    pub fn shadow_costs(&self) -> impl Iterator<Item = &DeploymentCosts> {
+        self.shadows.iter().map(|shadow| &shadow.costs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authenticator::Authenticators;
+    use crate::log::Package;
+    use crate::testing::package_ids;
+
+    fn import(name: &str, packages: &[PackageId]) -> Box<dyn DynAuthenticator> {
+        Authenticators::by_name(name)
+            .unwrap()
+            .import(packages.to_vec())
+    }
+
+    fn actions(package: &PackageId) -> Vec<Action> {
+        let package = Package {
+            id: package.clone(),
+            length: None,
+        };
+        let user = UserId::from("user".to_string());
+        vec![
+            Action::RefreshMetadata { user: user.clone() },
+            Action::Publish {
+                package: package.clone(),
+            },
+            Action::RefreshMetadata { user: user.clone() },
+            Action::Download {
+                user: user.clone(),
+                package,
+            },
+            Action::Goodbye { user },
+        ]
+    }
+
+    #[test]
+    fn shadows_agree() {
+        let packages: Vec<_> = package_ids(10);
+        let mut simulator = ShadowSimulator::new(
+            import("hackage", &packages),
+            vec![
+                import("mercury", &packages),
+                import("mercury_diff", &packages),
+            ],
+        );
+        for action in actions(&packages[0]) {
+            simulator.process(&action);
+        }
+        assert_eq!(simulator.divergences(), []);
+
+        let primary = *simulator.primary_costs();
+        let shadows: Vec<_> = simulator.shadow_costs().copied().collect();
+        assert!(primary.bandwidth > Information::ZERO);
+        // Diffs are smaller than full snapshots.
+        assert!(shadows[1].bandwidth_ratio(&shadows[0]) < 1.0);
+    }
+
+    #[test]
+    fn reports_diverging_revisions() {
+        let packages = vec![PackageId::from("package".to_string())];
+        // The shadow has seen an extra publish.
+        let mut shadow = import("hackage", &packages);
+        shadow.publish(packages[0].clone());
+        let mut simulator = ShadowSimulator::new(import("hackage", &packages), vec![shadow]);
+        for action in actions(&packages[0]) {
+            simulator.process(&action);
+        }
+        match &simulator.divergences() {
+            [DivergenceReport {
+                technique: "hackage",
+                divergence:
+                    Divergence::Revision {
+                        primary, shadow, ..
+                    },
+            }] => assert!(primary < shadow),
+            divergences => panic!("unexpected divergences: {divergences:?}"),
+        }
+    }
+}
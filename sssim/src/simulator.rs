@@ -1,14 +1,24 @@
 use std::collections::HashMap;
 
-use crate::log::{Action, Package, PackageId, UserId};
+use crate::authenticator::{Revision, Targets, Timestamped};
+use crate::clock::{Pace, VirtualClock};
+use crate::dedup::{DedupStats, ResponseDedup};
+use crate::freshness::{Freshness, FreshnessStats, SignedTimestamp};
+use crate::hooks::{Epochs, Hook};
+use crate::latency::{time_fn, Latencies, Operation};
+use crate::log::{Action, Entry, Log, Package, PackageId, UserId};
+use crate::persistence::StateLoss;
+use crate::publish_queue::PublishQueue;
+use crate::republish::{RepublishPolicy, Republishes};
+use crate::targets::TargetsModel;
 use crate::util::DataSized;
 use crate::util::Information;
 use crate::Authenticator;
 use serde::{Serialize, Serializer};
-use time::Duration;
+use time::{Duration, OffsetDateTime};
 use uom::ConstZero;
 
-fn serialize_ns<S>(duration: &Duration, s: S) -> Result<S::Ok, S::Error>
+pub(crate) fn serialize_ns<S>(duration: &Duration, s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
@@ -20,6 +30,23 @@ where
     )
 }
 
+/// Split `bandwidth` into (origin, CDN) bytes.
+fn route(bandwidth: Information, cacheable: bool) -> (Information, Information) {
+    if cacheable {
+        (Information::ZERO, bandwidth)
+    } else {
+        (bandwidth, Information::ZERO)
+    }
+}
+
+/// What to publish `package` with. Logs don't have artifact hashes; only the
+/// size of one matters.
+fn publish_targets(package: &Package) -> Option<Targets> {
+    package
+        .length
+        .map(|length| Targets::new(length, Default::default()))
+}
+
 #[derive(Debug, Serialize)]
 pub struct ResourceUsage {
     /// Server-side computation time used to handle this request.
@@ -28,10 +55,64 @@ pub struct ResourceUsage {
     /// Client-side computation time used to handle this request.
     #[serde(rename = "user_compute_ns", serialize_with = "serialize_ns")]
     pub user_compute: Duration, // TODO(meh): make optional
+    /// Part of `user_compute` spent checking a diff's proofs (see
+    /// [`Authenticator::verify_diff_structure`]).
+    #[serde(rename = "user_structure_ns", serialize_with = "serialize_ns")]
+    pub user_structure_compute: Duration,
+    /// Part of `user_compute` spent checking that a diff doesn't go backwards
+    /// (see [`Authenticator::verify_freshness`]).
+    #[serde(rename = "user_freshness_ns", serialize_with = "serialize_ns")]
+    pub user_freshness_compute: Duration,
     #[serde(rename = "bandwidth_bytes")]
     pub bandwidth: Information,
+    /// Part of `bandwidth` that the origin has to compute and serve itself.
+    #[serde(rename = "origin_bandwidth_bytes")]
+    pub origin_bandwidth: Information,
+    /// Part of `bandwidth` that a CDN could serve (static files).
+    #[serde(rename = "cdn_bandwidth_bytes")]
+    pub cdn_bandwidth: Information,
     #[serde(rename = "server_storage_bytes")]
     pub storage: Information,
+    /// Size of the membership witnesses the client holds after this request.
+    #[serde(rename = "user_witness_state_bytes")]
+    pub user_witness_state: Information,
+    /// Part of `bandwidth` spent updating client-held witnesses.
+    #[serde(rename = "witness_update_bandwidth_bytes")]
+    pub witness_update_bandwidth: Information,
+    /// Part of `bandwidth` (and `cdn_bandwidth`) spent on the package's
+    /// targets metadata, which every technique needs; zero unless modeled
+    /// (see [`Simulator::with_targets`]).
+    #[serde(rename = "targets_bandwidth_bytes")]
+    pub targets_bandwidth: Information,
+    /// Request/response rounds this took (e.g. fetch a diff, then a proof),
+    /// since each one costs a network round-trip time on top of the transfer.
+    pub round_trips: u32,
+}
+
+impl ResourceUsage {
+    /// Time spent waiting on the network, over a link with round-trip time
+    /// `rtt` (not counting transfer time).
+    pub fn network_latency(&self, rtt: Duration) -> Duration {
+        rtt * self.round_trips
+    }
+}
+
+/// How often downloads were answered from a client's verified-proof cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ProofCacheStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl ProofCacheStats {
+    /// Fraction of downloads that hit the cache (zero if there were none).
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / total as f64
+    }
 }
 
 /// A simulator for a secure software repository.
@@ -44,6 +125,45 @@ pub struct Simulator<A: Authenticator> {
     snapshots: HashMap<UserId, A::ClientSnapshot>,
     /// Keep track of the length of the latest version of each package, if provided.
     package_lengths: HashMap<PackageId, u64>,
+    /// Latency of each operation since the last [`Self::take_latencies`].
+    latencies: Latencies,
+    /// Metadata expiry checks, and the signed timestamps sent with refreshes
+    /// (only for timestamped entries; see [`Self::process_entry`]).
+    freshness: Option<Freshness>,
+    /// The (package, revision) pairs each user has already verified against
+    /// its current snapshot; cleared whenever the snapshot changes.
+    verified: HashMap<UserId, HashMap<PackageId, Revision>>,
+    proof_cache: ProofCacheStats,
+    /// Identical refresh responses (only for timestamped entries).
+    dedup: Option<ResponseDedup>,
+    /// Timestamp of the entry being processed, if known.
+    now: Option<OffsetDateTime>,
+    /// The signed timestamp sent with the entry being processed, if any.
+    timestamp: Option<SignedTimestamp>,
+    /// The latest signed timestamp each user accepted.
+    timestamps: HashMap<UserId, SignedTimestamp>,
+    /// Extra analyses to notify as we go.
+    hooks: Vec<Box<dyn Hook>>,
+    /// Epoch boundaries for [`Hook::on_epoch_end`] (only for timestamped
+    /// entries).
+    epochs: Option<Epochs>,
+    /// The latest timestamp processed.
+    clock: VirtualClock,
+    /// When we processed the first timestamped entry (for pacing).
+    wall_start: Option<std::time::Instant>,
+    /// Which publishes reach the authenticator.
+    republishes: Republishes,
+    /// Check the authenticator's invariants every this many publishes.
+    invariant_checks: Option<usize>,
+    /// Publishes that reached the authenticator.
+    publishes: usize,
+    /// Contention between publishers (only for timestamped entries).
+    publish_queue: Option<PublishQueue>,
+    /// Sizes of the targets metadata fetched with each download, if modeling
+    /// it.
+    targets: Option<TargetsModel>,
+    /// Which returning users lost their state, if modeling it.
+    state_loss: Option<StateLoss>,
 }
 
 // TODO(maybe): investigate the clones, see if you can get rid of them
@@ -56,7 +176,152 @@ where
             authenticator,
             snapshots: HashMap::default(),
             package_lengths: HashMap::default(),
+            latencies: Latencies::default(),
+            freshness: None,
+            verified: HashMap::default(),
+            proof_cache: ProofCacheStats::default(),
+            dedup: None,
+            now: None,
+            timestamp: None,
+            timestamps: HashMap::default(),
+            hooks: Vec::new(),
+            epochs: None,
+            clock: VirtualClock::new(Pace::Unpaced),
+            wall_start: None,
+            republishes: Republishes::default(),
+            invariant_checks: None,
+            publishes: 0,
+            publish_queue: None,
+            targets: None,
+            state_loss: None,
+        }
+    }
+
+    /// Drop or count unchanged re-publishes (see [`crate::republish`]).
+    pub fn with_republish_policy(mut self, policy: RepublishPolicy) -> Self {
+        self.republishes = Republishes::new(policy);
+        self
+    }
+
+    /// How many publishes were dropped as unchanged re-publishes.
+    pub fn dropped_republishes(&self) -> usize {
+        self.republishes.dropped()
+    }
+
+    /// Space out timestamped entries in wall time (see [`crate::clock`]).
+    pub fn with_pace(mut self, pace: Pace) -> Self {
+        self.clock = VirtualClock::new(pace);
+        self
+    }
+
+    /// Notify `hook` of events as they happen (see [`crate::hooks`]).
+    pub fn with_hook(mut self, hook: impl Hook + 'static) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// End an epoch (for [`Hook::on_epoch_end`]) every `length`, starting at
+    /// the first timestamped entry.
+    pub fn with_epochs(mut self, length: Duration) -> Self {
+        self.epochs = Some(Epochs::new(length));
+        self
+    }
+
+    /// Call once the log is done, to end the last epoch (and drain the publish
+    /// queue).
+    pub fn finish(&mut self) {
+        if let Some(queue) = &mut self.publish_queue {
+            queue.finish();
         }
+        if let Some((epoch, end)) = self.epochs.as_mut().and_then(Epochs::finish) {
+            for hook in &mut self.hooks {
+                hook.on_epoch_end(epoch, end);
+            }
+        }
+    }
+
+    /// Track how well refresh responses de-duplicate within `window`.
+    pub fn with_dedup(mut self, window: Duration) -> Self {
+        self.dedup = Some(ResponseDedup::new(window));
+        self
+    }
+
+    /// Check [`Authenticator::check_invariants`] after every `every`
+    /// publishes, panicking if they don't hold.
+    ///
+    /// Slow; for debugging a technique against a real log.
+    pub fn with_invariant_checks(mut self, every: usize) -> Self {
+        assert!(every > 0, "can't check every 0 publishes");
+        self.invariant_checks = Some(every);
+        self
+    }
+
+    /// Queue publishes from several publishers through one server, to measure
+    /// queueing delay (see [`crate::publish_queue`]).
+    pub fn with_publish_queue(mut self, queue: PublishQueue) -> Self {
+        self.publish_queue = Some(queue);
+        self
+    }
+
+    /// The publish queue, if modeling one.
+    pub fn publish_queue(&self) -> Option<&PublishQueue> {
+        self.publish_queue.as_ref()
+    }
+
+    /// Count the targets metadata clients fetch with each download too (see
+    /// [`crate::targets`]).
+    pub fn with_targets(mut self, model: TargetsModel) -> Self {
+        self.targets = Some(model);
+        self
+    }
+
+    /// Have some returning users lose their state, so that their next refresh
+    /// is a bootstrap (see [`crate::persistence`]).
+    pub fn with_state_loss(mut self, loss: StateLoss) -> Self {
+        self.state_loss = Some(loss);
+        self
+    }
+
+    /// Returning users who lost their state (zero unless modeling it).
+    pub fn state_losses(&self) -> usize {
+        self.state_loss.as_ref().map_or(0, StateLoss::losses)
+    }
+
+    /// Model metadata expiry (and client clock skew) too.
+    pub fn with_freshness(mut self, freshness: Freshness) -> Self {
+        if A::metadata_expires() {
+            self.freshness = Some(freshness);
+        }
+        self
+    }
+
+    /// Trace time, as of the latest timestamped entry.
+    pub fn clock(&self) -> &VirtualClock {
+        &self.clock
+    }
+
+    /// The latencies recorded since the last call (e.g. over one phase of a
+    /// run), to persist as [`LatencyResult`](crate::latency::LatencyResult)s.
+    ///
+    /// Server and client times are kept apart: a download records
+    /// [`Operation::RequestFile`] and [`Operation::VerifyMembership`].
+    pub fn take_latencies(&mut self) -> Latencies {
+        std::mem::take(&mut self.latencies)
+    }
+
+    /// Hits and misses for clients' verified-proof caches.
+    pub fn proof_cache_stats(&self) -> &ProofCacheStats {
+        &self.proof_cache
+    }
+
+    /// Unique vs. total refresh bytes, if tracking de-duplication.
+    pub fn dedup_stats(&self) -> Option<&DedupStats> {
+        self.dedup.as_ref().map(ResponseDedup::stats)
+    }
+
+    /// Outcomes of client expiry checks, if modeling freshness.
+    pub fn freshness_stats(&self) -> Option<&FreshnessStats> {
+        self.freshness.as_ref().map(Freshness::stats)
     }
 
     fn process_download(&mut self, user: UserId, package: &mut Package) -> ResourceUsage {
@@ -65,13 +330,46 @@ where
             package.length = self.package_lengths.get(&package.id).copied();
         }
 
+        let verified = self.verified.entry(user.clone()).or_default();
         let user_snapshot = self.snapshots.entry(user).or_insert_with(Default::default);
-        let (server_request_time, (revision, proof)) = Duration::time_fn(|| {
-            self.authenticator
-                .request_file(A::id(user_snapshot), &package.id)
-        });
+        if verified.contains_key(&package.id) {
+            // Same package, same snapshot: nothing new to fetch or check.
+            self.proof_cache.hits += 1;
+            return ResourceUsage {
+                server_compute: Duration::ZERO,
+                user_compute: Duration::ZERO,
+                user_structure_compute: Duration::ZERO,
+                user_freshness_compute: Duration::ZERO,
+                bandwidth: Information::ZERO,
+                origin_bandwidth: Information::ZERO,
+                cdn_bandwidth: Information::ZERO,
+                storage: self.authenticator.size(),
+                user_witness_state: A::witness_state_size(user_snapshot),
+                witness_update_bandwidth: Information::ZERO,
+                targets_bandwidth: Information::ZERO,
+                round_trips: 0,
+            };
+        }
+        self.proof_cache.misses += 1;
+        let id = A::id(user_snapshot);
+        let (server_request_time, (revision, proof)) =
+            time_fn(|| self.authenticator.request_file(id, &package.id));
+        self.latencies
+            .record(Operation::RequestFile, server_request_time);
         let bandwidth = proof.size();
-        let (user_verify_time, _) = Duration::time_fn(|| {
+        let (origin_bandwidth, cdn_bandwidth) = route(bandwidth, A::proof_cacheable(&proof));
+        let mut round_trips = A::download_round_trips(&proof);
+        let targets_bandwidth = match &self.targets {
+            Some(model) => model.download_size::<A>(revision, &proof),
+            None => Information::ZERO,
+        };
+        if targets_bandwidth > Information::ZERO {
+            // The same for every client, and it can be fetched alongside the
+            // proof.
+            round_trips = round_trips.max(1);
+        }
+        let cached = proof.clone();
+        let (user_verify_time, _) = time_fn(|| {
             assert!(A::verify_membership(
                 user_snapshot,
                 &package.id,
@@ -79,72 +377,381 @@ where
                 proof
             ));
         });
+        self.latencies
+            .record(Operation::VerifyMembership, user_verify_time);
+        A::cache_proof(user_snapshot, &package.id, revision, cached);
+        verified.insert(package.id.clone(), revision);
 
         ResourceUsage {
             server_compute: server_request_time,
             user_compute: user_verify_time,
-            bandwidth,
+            user_structure_compute: Duration::ZERO,
+            user_freshness_compute: Duration::ZERO,
+            bandwidth: bandwidth + targets_bandwidth,
+            origin_bandwidth,
+            cdn_bandwidth: cdn_bandwidth + targets_bandwidth,
             storage: self.authenticator.size(),
+            user_witness_state: A::witness_state_size(user_snapshot),
+            witness_update_bandwidth: Information::ZERO,
+            targets_bandwidth,
+            round_trips,
         }
     }
 
     fn process_refresh_metadata(&mut self, user: UserId) -> ResourceUsage {
+        if let Some(loss) = &mut self.state_loss {
+            if self.snapshots.contains_key(&user) && loss.lost() {
+                // Start over, like a new user.
+                self.snapshots.remove(&user);
+                self.verified.remove(&user);
+                self.timestamps.remove(&user);
+            }
+        }
+
         // Get the snapshot ID for the user's current snapshot.
-        let snapshot = self.snapshots.entry(user).or_insert_with(Default::default);
+        let snapshot = self
+            .snapshots
+            .entry(user.clone())
+            .or_insert_with(Default::default);
 
         // Answer the update metadata server-side.
+        let id = A::id(snapshot);
         let (server_compute, maybe_snapshot_diff) =
-            Duration::time_fn(|| self.authenticator.refresh_metadata(A::id(snapshot)));
+            time_fn(|| self.authenticator.refresh_metadata(id));
+        self.latencies.record(Operation::Refresh, server_compute);
 
         let snapshot_size = maybe_snapshot_diff
             .as_ref()
             .map(DataSized::size)
             .unwrap_or_default();
+        let cacheable = matches!(&maybe_snapshot_diff, Some(diff) if A::diff_cacheable(diff));
+        let (origin_bandwidth, cdn_bandwidth) = route(snapshot_size, cacheable);
+        let witness_update_bandwidth = maybe_snapshot_diff
+            .as_ref()
+            .map(A::witness_update_size)
+            .unwrap_or_default();
+        let round_trips = A::refresh_round_trips(maybe_snapshot_diff.as_ref());
 
-        let user_compute = if let Some(snapshot_diff) = maybe_snapshot_diff {
-            // Check the new snapshot for rollbacks and store it.
-            let (user_compute_verify, _) = Duration::time_fn(|| {
-                assert!(A::check_no_rollback(snapshot, &snapshot_diff));
-            });
-            let (user_compute_update, _) = Duration::time_fn(|| {
-                A::update(snapshot, snapshot_diff);
-            });
-            user_compute_verify + user_compute_update
-        } else {
-            Duration::ZERO
+        if maybe_snapshot_diff.is_some() {
+            self.verified.remove(&user);
+        }
+        if let (Some(dedup), Some(now), Some(diff)) =
+            (&mut self.dedup, self.now, &maybe_snapshot_diff)
+        {
+            dedup.observe(now, diff);
+        }
+        // Check the new snapshot (and timestamp, if any) for rollbacks and
+        // store it.
+        let structure = match &maybe_snapshot_diff {
+            Some(diff) => time_fn(|| assert!(A::verify_diff_structure(snapshot, diff))).0,
+            None => Duration::ZERO,
+        };
+        let oldest = match (&self.freshness, self.now) {
+            (Some(freshness), Some(now)) => freshness.oldest_accepted(&user, now),
+            _ => None,
         };
+        let (freshness, fresh, maybe_snapshot_diff) = match self.timestamp {
+            Some(timestamp) => {
+                let held = self.timestamps.get(&user).copied();
+                let response = Timestamped {
+                    diff: maybe_snapshot_diff,
+                    timestamp,
+                };
+                let (freshness, fresh) =
+                    time_fn(|| A::verify_timestamped_freshness(snapshot, &response, held, oldest));
+                (freshness, fresh, response.diff)
+            }
+            None => {
+                let (freshness, fresh) = time_fn(|| match &maybe_snapshot_diff {
+                    Some(diff) => A::verify_freshness(snapshot, diff),
+                    None => true,
+                });
+                (freshness, fresh, maybe_snapshot_diff)
+            }
+        };
+        // The server never goes backwards, so only a client's maximum
+        // staleness should get a response rejected.
+        assert!(
+            fresh || oldest.is_some(),
+            "{}: refresh went backwards",
+            A::name()
+        );
+        let mut update = Duration::ZERO;
+        if fresh {
+            if let Some(snapshot_diff) = maybe_snapshot_diff {
+                update = time_fn(|| A::update(snapshot, snapshot_diff)).0;
+            }
+            if let Some(timestamp) = self.timestamp {
+                self.timestamps.insert(user, timestamp);
+            }
+        }
         ResourceUsage {
             server_compute,
-            user_compute,
+            user_compute: structure + freshness + update,
+            user_structure_compute: structure,
+            user_freshness_compute: freshness,
             bandwidth: snapshot_size,
+            origin_bandwidth,
+            cdn_bandwidth,
             storage: self.authenticator.size(),
+            user_witness_state: A::witness_state_size(snapshot),
+            witness_update_bandwidth,
+            targets_bandwidth: Information::ZERO,
+            round_trips,
         }
     }
 
-    fn process_publish(&mut self, package: Package) -> ResourceUsage {
+    /// Note `package`'s length, and whether it should reach the authenticator
+    /// at all (see [`Self::with_republish_policy`]).
+    fn admit_publish(&mut self, package: &Package) -> bool {
         if let Some(length) = package.length {
             self.package_lengths.insert(package.id.clone(), length);
         }
-        let (server_upload, _) = Duration::time_fn(|| self.authenticator.publish(package.id));
+        self.republishes
+            .admit(&package.id, publish_targets(package))
+    }
+
+    /// Count `n` more publishes, checking invariants if we're due.
+    fn count_publishes(&mut self, n: usize) {
+        let before = self.publishes;
+        self.publishes += n;
+        if let Some(every) = self.invariant_checks {
+            if before / every != self.publishes / every {
+                if let Err(err) = self.authenticator.check_invariants() {
+                    panic!("after {} publishes: {err}", self.publishes);
+                }
+            }
+        }
+    }
+
+    /// A publish costs only server time (and the one upload).
+    fn publish_usage(&self, server_compute: Duration) -> ResourceUsage {
         ResourceUsage {
-            server_compute: server_upload,
+            server_compute,
             user_compute: Duration::ZERO,
+            user_structure_compute: Duration::ZERO,
+            user_freshness_compute: Duration::ZERO,
             bandwidth: Information::ZERO,
+            origin_bandwidth: Information::ZERO,
+            cdn_bandwidth: Information::ZERO,
             storage: self.authenticator.size(),
+            user_witness_state: Information::ZERO,
+            witness_update_bandwidth: Information::ZERO,
+            targets_bandwidth: Information::ZERO,
+            round_trips: 1,
         }
     }
 
+    fn process_publish(&mut self, package: Package) -> ResourceUsage {
+        if !self.admit_publish(&package) {
+            return self.publish_usage(Duration::ZERO);
+        }
+        let (server_upload, _) = time_fn(|| match publish_targets(&package) {
+            Some(targets) => self.authenticator.publish_targets(package.id, targets),
+            None => self.authenticator.publish(package.id),
+        });
+        self.latencies.record(Operation::Publish, server_upload);
+        self.count_publishes(1);
+        self.publish_usage(server_upload)
+    }
+
+    /// Publish `packages` with one [`Authenticator::publish_batch`], splitting
+    /// the server time evenly between them.
+    ///
+    /// Packages with targets go through [`Authenticator::publish_targets`] one
+    /// at a time (a batch is just IDs), but they're timed with the rest.
+    fn process_publish_batch(&mut self, packages: Vec<Package>) -> Vec<ResourceUsage> {
+        let admitted: Vec<bool> = packages
+            .iter()
+            .map(|package| self.admit_publish(package))
+            .collect();
+        let mut with_targets = Vec::new();
+        let mut batch = Vec::new();
+        for (package, _) in packages.into_iter().zip(&admitted).filter(|(_, a)| **a) {
+            match publish_targets(&package) {
+                Some(targets) => with_targets.push((package.id, targets)),
+                None => batch.push(package.id),
+            }
+        }
+        let count = with_targets.len() + batch.len();
+        let (server_upload, _) = time_fn(|| {
+            for (package, targets) in with_targets {
+                self.authenticator.publish_targets(package, targets);
+            }
+            if !batch.is_empty() {
+                self.authenticator.publish_batch(batch);
+            }
+        });
+        let share = match u32::try_from(count) {
+            Ok(0) | Err(_) => Duration::ZERO,
+            Ok(count) => server_upload / count,
+        };
+        for _ in 0..count {
+            self.latencies.record(Operation::Publish, share);
+        }
+        self.count_publishes(count);
+        admitted
+            .into_iter()
+            .map(|admitted| self.publish_usage(if admitted { share } else { Duration::ZERO }))
+            .collect()
+    }
+
     fn process_goodbye(&mut self, user: UserId) -> ResourceUsage {
         self.snapshots.remove(&user);
+        self.verified.remove(&user);
+        self.timestamps.remove(&user);
         ResourceUsage {
             server_compute: Duration::ZERO,
             user_compute: Duration::ZERO,
+            user_structure_compute: Duration::ZERO,
+            user_freshness_compute: Duration::ZERO,
             bandwidth: Information::ZERO,
+            origin_bandwidth: Information::ZERO,
+            cdn_bandwidth: Information::ZERO,
             storage: Information::ZERO,
+            user_witness_state: Information::ZERO,
+            witness_update_bandwidth: Information::ZERO,
+            targets_bandwidth: Information::ZERO,
+            round_trips: 0,
+        }
+    }
+
+    /// Bookkeeping before processing `entry`: pacing, expiry, and epochs.
+    ///
+    /// Returns the signed timestamp the client gets, if any.
+    fn begin_entry(&mut self, entry: &Entry) -> Option<SignedTimestamp> {
+        if let Some(due) = self.clock.advance(entry.timestamp) {
+            let wall_start = *self.wall_start.get_or_insert_with(std::time::Instant::now);
+            if let Some(wait) = due.checked_sub(wall_start.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+        let mut timestamp = None;
+        if let Some(freshness) = &mut self.freshness {
+            let now = entry.timestamp;
+            timestamp = match &entry.action {
+                Action::Download { user, .. } => freshness.on_download(user, now),
+                Action::RefreshMetadata { user } => Some(freshness.on_refresh(user, now)),
+                Action::Publish { .. } => {
+                    freshness.on_publish(now);
+                    None
+                }
+                Action::Goodbye { user } => {
+                    freshness.on_goodbye(user);
+                    None
+                }
+            };
+        }
+        if let Some(epochs) = &mut self.epochs {
+            for (epoch, end) in epochs.advance(entry.timestamp) {
+                for hook in &mut self.hooks {
+                    hook.on_epoch_end(epoch, end);
+                }
+            }
+        }
+        self.now = Some(entry.timestamp);
+        self.timestamp = timestamp;
+        timestamp
+    }
+
+    /// Count `timestamp` (if any) in `usage`, queue a publish, and tell the
+    /// hooks.
+    fn end_entry(
+        &mut self,
+        entry: &Entry,
+        mut usage: ResourceUsage,
+        timestamp: Option<SignedTimestamp>,
+    ) -> ResourceUsage {
+        if let Some(timestamp) = timestamp {
+            // The same for every client until the next signature, so the CDN
+            // can serve it. It's fetched first, to learn whether there's
+            // anything new: one more round.
+            usage.bandwidth += timestamp.size();
+            usage.cdn_bandwidth += timestamp.size();
+            usage.round_trips += 1;
+        }
+        if let (Some(queue), Action::Publish { package }) = (&mut self.publish_queue, &entry.action)
+        {
+            let publisher = queue.publisher(&package.id);
+            queue.arrive(publisher, entry.timestamp, usage.server_compute);
+        }
+        self.now = None;
+        self.timestamp = None;
+        for hook in &mut self.hooks {
+            hook.on_event(entry, &usage);
         }
+        usage
+    }
+
+    /// Process a log entry, checking metadata expiry as of its timestamp.
+    pub fn process_entry(&mut self, entry: &mut Entry) -> ResourceUsage {
+        let timestamp = self.begin_entry(entry);
+        let usage = self.process(&mut entry.action);
+        self.end_entry(entry, usage, timestamp)
+    }
+
+    /// Process publishes that all happened at the same time as one batch
+    /// (see [`Authenticator::publish_batch`]).
+    ///
+    /// # Panics
+    ///
+    /// If any entry isn't a publish, or the timestamps differ.
+    pub fn process_publishes(&mut self, entries: &[Entry]) -> Vec<ResourceUsage> {
+        let mut packages = Vec::with_capacity(entries.len());
+        for entry in entries {
+            assert_eq!(entry.timestamp, entries[0].timestamp, "not simultaneous");
+            match &entry.action {
+                Action::Publish { package } => packages.push(package.clone()),
+                action => panic!("not a publish: {action:?}"),
+            }
+            self.begin_entry(entry);
+        }
+        let usages = self.process_publish_batch(packages);
+        entries
+            .iter()
+            .zip(usages)
+            .map(|(entry, usage)| self.end_entry(entry, usage, None))
+            .collect()
+    }
+
+    /// Process every entry in `log`, then [`Self::finish`].
+    ///
+    /// Consecutive publishes with the same timestamp go through
+    /// [`Self::process_publishes`] together.
+    pub fn replay(&mut self, log: Log) {
+        let mut entries = log.into_iter().peekable();
+        while let Some(mut entry) = entries.next() {
+            if !matches!(entry.action, Action::Publish { .. }) {
+                self.process_entry(&mut entry);
+                continue;
+            }
+            let timestamp = entry.timestamp;
+            let mut batch = vec![entry];
+            while let Some(next) = entries.next_if(|next| {
+                next.timestamp == timestamp && matches!(next.action, Action::Publish { .. })
+            }) {
+                batch.push(next);
+            }
+            match &mut batch[..] {
+                [entry] => {
+                    self.process_entry(entry);
+                }
+                batch => {
+                    self.process_publishes(batch);
+                }
+            }
+        }
+        self.finish();
     }
 
     pub fn process(&mut self, action: &mut Action) -> ResourceUsage {
+        if let Action::Download { user, .. } | Action::RefreshMetadata { user } = action {
+            if !self.snapshots.contains_key(user) {
+                for hook in &mut self.hooks {
+                    hook.on_user_created(user);
+                }
+            }
+        }
         match action {
             Action::Download { user, package } => self.process_download(user.clone(), package),
             Action::RefreshMetadata { user } => self.process_refresh_metadata(user.clone()),
@@ -153,3 +760,606 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authenticator::{Hackage, NoAuth};
+    use crate::freshness::{ClockSkew, FreshnessPolicy};
+    use crate::latency::LatencyResult;
+    use crate::testing::package_ids;
+    use crate::util::byte;
+    use crate::util::FixedDataSized;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn freshness_stats<A>() -> Option<FreshnessStats>
+    where
+        A: Authenticator,
+        A::ClientSnapshot: Default,
+    {
+        let policy = FreshnessPolicy {
+            validity: Duration::hours(1),
+            resign_interval: Duration::hours(1),
+        };
+        let freshness = Freshness::new(policy, ClockSkew::new(Duration::minutes(30)));
+        let packages = vec![PackageId::from("package".to_string())];
+        let mut simulator = Simulator::new(A::batch_import(packages)).with_freshness(freshness);
+
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let package = Package {
+            id: PackageId::from("package".to_string()),
+            length: None,
+        };
+        let mut entries = vec![Entry::new(
+            start,
+            Action::Publish {
+                package: package.clone(),
+            },
+        )];
+        for i in 0..100 {
+            let user = UserId::from(format!("user{i}"));
+            entries.push(Entry::new(
+                start,
+                Action::RefreshMetadata { user: user.clone() },
+            ));
+            entries.push(Entry::new(
+                start + Duration::minutes(59),
+                Action::Download {
+                    user,
+                    package: package.clone(),
+                },
+            ));
+        }
+        for entry in entries.iter_mut() {
+            simulator.process_entry(entry);
+        }
+        simulator.freshness_stats().copied()
+    }
+
+    #[test]
+    fn repeat_downloads_hit_proof_cache() {
+        let package = Package {
+            id: PackageId::from("package".to_string()),
+            length: None,
+        };
+        let user = UserId::from("user".to_string());
+        let mut simulator = Simulator::new(Hackage::batch_import(vec![package.id.clone()]));
+        let mut download = Action::Download {
+            user: user.clone(),
+            package: package.clone(),
+        };
+        let mut refresh = Action::RefreshMetadata { user: user.clone() };
+
+        simulator.process(&mut refresh);
+        simulator.process(&mut download);
+        let usage = simulator.process(&mut download);
+        assert_eq!(usage.bandwidth, Information::ZERO);
+        assert_eq!(usage.user_compute, Duration::ZERO);
+
+        // A new snapshot invalidates the cache.
+        simulator.process(&mut Action::Publish { package });
+        simulator.process(&mut refresh);
+        simulator.process(&mut download);
+
+        let stats = simulator.proof_cache_stats();
+        assert_eq!((stats.hits, stats.misses), (1, 2));
+        assert!((stats.hit_rate() - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn latencies_per_phase() {
+        let package = Package {
+            id: PackageId::from("package".to_string()),
+            length: None,
+        };
+        let user = UserId::from("user".to_string());
+        let mut simulator = Simulator::new(Hackage::batch_import(vec![package.id.clone()]));
+        simulator.process(&mut Action::RefreshMetadata { user: user.clone() });
+        simulator.process(&mut Action::Download {
+            user: user.clone(),
+            package: package.clone(),
+        });
+        let latencies = simulator.take_latencies();
+        let operations: Vec<_> = LatencyResult::all("first", &latencies, 1, 1)
+            .into_iter()
+            .map(|result| (result.operation, result.latency.count))
+            .collect();
+        assert_eq!(operations.len(), 3);
+        for operation in [
+            Operation::Refresh,
+            Operation::RequestFile,
+            Operation::VerifyMembership,
+        ] {
+            assert!(operations.contains(&(operation, 1)), "{operation:?}");
+        }
+
+        // The next phase starts from scratch.
+        simulator.process(&mut Action::Publish { package });
+        let latencies = simulator.take_latencies();
+        assert_eq!(latencies.iter().count(), 1);
+        assert!(latencies.get(Operation::Publish).is_some());
+    }
+
+    #[test]
+    fn counts_round_trips() {
+        let package = Package {
+            id: PackageId::from("package".to_string()),
+            length: None,
+        };
+        let user = UserId::from("user".to_string());
+        let mut simulator = Simulator::new(Hackage::batch_import(vec![package.id.clone()]));
+        let mut download = Action::Download {
+            user: user.clone(),
+            package: package.clone(),
+        };
+
+        let refresh = simulator.process(&mut Action::RefreshMetadata { user });
+        assert_eq!(refresh.round_trips, 1);
+        assert_eq!(
+            refresh.network_latency(Duration::milliseconds(50)),
+            Duration::milliseconds(50)
+        );
+        // Hackage revisions are in the snapshot: no proof to ask for.
+        assert_eq!(simulator.process(&mut download).round_trips, 0);
+        assert_eq!(simulator.process(&mut download).round_trips, 0);
+        assert_eq!(
+            simulator
+                .process(&mut Action::Publish { package })
+                .round_trips,
+            1
+        );
+    }
+
+    #[test]
+    fn lost_state_bootstraps() {
+        let packages: Vec<_> = package_ids(100);
+        let user = UserId::from("user".to_string());
+        let refreshes = |loss: Option<StateLoss>| {
+            let mut simulator = Simulator::new(Hackage::batch_import(packages.clone()));
+            if let Some(loss) = loss {
+                simulator = simulator.with_state_loss(loss);
+            }
+            let mut refresh = Action::RefreshMetadata { user: user.clone() };
+            let first = simulator.process(&mut refresh).bandwidth;
+            simulator.process(&mut Action::Publish {
+                package: Package {
+                    id: packages[0].clone(),
+                    length: None,
+                },
+            });
+            let second = simulator.process(&mut refresh).bandwidth;
+            (first, second, simulator.state_losses())
+        };
+
+        let (first, second, losses) = refreshes(None);
+        assert!(second < first);
+        assert_eq!(losses, 0);
+        // The first refresh is a new user's, so it doesn't count as a loss.
+        let (first, second, losses) = refreshes(Some(StateLoss::new(1.0, 0)));
+        assert!(second >= first);
+        assert_eq!(losses, 1);
+    }
+
+    #[test]
+    fn downloads_fetch_targets() {
+        let package = Package {
+            id: PackageId::from("package".to_string()),
+            length: None,
+        };
+        let user = UserId::from("user".to_string());
+        let model = TargetsModel::default();
+        let mut simulator =
+            Simulator::new(Hackage::batch_import(vec![package.id.clone()])).with_targets(model);
+        simulator.process(&mut Action::Publish {
+            package: package.clone(),
+        });
+        simulator.process(&mut Action::RefreshMetadata { user: user.clone() });
+
+        let usage = simulator.process(&mut Action::Download { user, package });
+        let targets = model.size(Revision::from(2));
+        assert_eq!(usage.targets_bandwidth, targets);
+        assert_eq!(usage.bandwidth, targets);
+        assert_eq!(usage.cdn_bandwidth, targets);
+        assert_eq!(usage.round_trips, 1);
+    }
+
+    #[cfg(feature = "smt")]
+    #[test]
+    fn proofs_cost_a_round_trip() {
+        use crate::authenticator::SparseMerkle;
+
+        let package = Package {
+            id: PackageId::from("package".to_string()),
+            length: None,
+        };
+        let user = UserId::from("user".to_string());
+        let mut simulator = Simulator::new(<SparseMerkle>::batch_import(vec![package.id.clone()]));
+        simulator.process(&mut Action::RefreshMetadata { user: user.clone() });
+        let mut download = Action::Download { user, package };
+
+        assert_eq!(simulator.process(&mut download).round_trips, 1);
+        // Cached proofs don't go over the network.
+        assert_eq!(simulator.process(&mut download).round_trips, 0);
+    }
+
+    #[test]
+    fn refresh_verify_times() {
+        let package = Package {
+            id: PackageId::from("package".to_string()),
+            length: None,
+        };
+        let user = UserId::from("user".to_string());
+        let mut simulator = Simulator::new(Hackage::batch_import(vec![package.id.clone()]));
+        simulator.process(&mut Action::Publish {
+            package: package.clone(),
+        });
+
+        let usage = simulator.process(&mut Action::RefreshMetadata { user: user.clone() });
+        assert!(usage.user_structure_compute + usage.user_freshness_compute <= usage.user_compute);
+
+        let usage = simulator.process(&mut Action::Download { user, package });
+        assert_eq!(usage.user_structure_compute, Duration::ZERO);
+        assert_eq!(usage.user_freshness_compute, Duration::ZERO);
+    }
+
+    #[test]
+    fn republish_policy() {
+        let package = Package {
+            id: PackageId::from("package".to_string()),
+            length: Some(10),
+        };
+        for (policy, expected) in [(RepublishPolicy::Count, 4), (RepublishPolicy::Dedupe, 2)] {
+            let mut simulator = Simulator::new(Hackage::batch_import(vec![package.id.clone()]))
+                .with_republish_policy(policy);
+            for _ in 0..3 {
+                simulator.process(&mut Action::Publish {
+                    package: package.clone(),
+                });
+            }
+            let id = Hackage::id(&simulator.authenticator.get_metadata());
+            let (revision, _) = simulator.authenticator.request_file(id, &package.id);
+            assert_eq!(revision, Revision::from(expected), "{policy}");
+            assert_eq!(simulator.dropped_republishes(), 4 - expected);
+        }
+    }
+
+    #[test]
+    fn identical_refreshes_dedup() {
+        let packages = vec![PackageId::from("package".to_string())];
+        let mut simulator =
+            Simulator::new(Hackage::batch_import(packages)).with_dedup(Duration::minutes(5));
+        let start = OffsetDateTime::UNIX_EPOCH;
+        for i in 0..10 {
+            let user = UserId::from(format!("user{i}"));
+            let mut entry = Entry::new(start, Action::RefreshMetadata { user });
+            simulator.process_entry(&mut entry);
+        }
+        let stats = simulator.dedup_stats().unwrap();
+        assert_eq!(stats.responses, 10);
+        assert_eq!(stats.unique_responses, 1);
+        assert_eq!(stats.total, stats.unique * 10);
+    }
+
+    /// Everything the hooks saw, in order.
+    #[derive(Debug, Default)]
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl Hook for Recorder {
+        fn on_event(&mut self, entry: &Entry, usage: &ResourceUsage) {
+            let kind = match entry.action() {
+                Action::Download { .. } => "download",
+                Action::RefreshMetadata { .. } => "refresh",
+                Action::Publish { .. } => "publish",
+                Action::Goodbye { .. } => "goodbye",
+            };
+            let bytes = usage.bandwidth.get::<byte>();
+            self.0.borrow_mut().push(format!("{kind} {bytes}"));
+        }
+
+        fn on_epoch_end(&mut self, epoch: usize, _end: OffsetDateTime) {
+            self.0.borrow_mut().push(format!("end {epoch}"));
+        }
+
+        fn on_user_created(&mut self, user: &UserId) {
+            self.0.borrow_mut().push(format!("new {}", user.0));
+        }
+    }
+
+    #[test]
+    fn hooks_see_events() {
+        let package = Package {
+            id: PackageId::from("package".to_string()),
+            length: None,
+        };
+        let user = UserId::from("user".to_string());
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut simulator = Simulator::new(NoAuth::batch_import(vec![package.id.clone()]))
+            .with_hook(Recorder(events.clone()))
+            .with_epochs(Duration::hours(1));
+
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let actions = [
+            (0, Action::RefreshMetadata { user: user.clone() }),
+            (
+                30,
+                Action::Publish {
+                    package: package.clone(),
+                },
+            ),
+            (
+                90,
+                Action::Download {
+                    user: user.clone(),
+                    package,
+                },
+            ),
+            (100, Action::Goodbye { user: user.clone() }),
+            (200, Action::RefreshMetadata { user }),
+        ];
+        for (minutes, action) in actions {
+            simulator.process_entry(&mut Entry::new(start + Duration::minutes(minutes), action));
+        }
+        simulator.finish();
+
+        let expected = [
+            "new user",
+            "refresh 0",
+            "publish 0",
+            "end 0",
+            "download 0",
+            "goodbye 0",
+            "end 1",
+            "end 2",
+            "new user",
+            "refresh 0",
+            "end 3",
+        ];
+        assert_eq!(*events.borrow(), expected);
+    }
+
+    #[test]
+    fn replay_follows_trace_time() {
+        let package = Package {
+            id: PackageId::from("package".to_string()),
+            length: None,
+        };
+        let start = OffsetDateTime::UNIX_EPOCH;
+        // A year of hourly refreshes, with a publish each day.
+        let mut entries = Vec::new();
+        for hour in 0..24 * 365 {
+            let now = start + Duration::hours(hour);
+            if hour % 24 == 0 {
+                let package = package.clone();
+                entries.push(Entry::new(now, Action::Publish { package }));
+            }
+            let user = UserId::from(format!("user{}", hour % 10));
+            entries.push(Entry::new(now, Action::RefreshMetadata { user }));
+        }
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut simulator = Simulator::new(Hackage::batch_import(vec![package.id.clone()]))
+            .with_hook(Recorder(events.clone()))
+            .with_epochs(Duration::days(30))
+            .with_pace(Pace::Accelerated(1e9));
+        simulator.replay(Log::from(entries));
+
+        assert_eq!(simulator.clock().elapsed(), Duration::hours(24 * 365 - 1));
+        let epochs = events
+            .borrow()
+            .iter()
+            .filter(|event| event.starts_with("end"))
+            .count();
+        assert_eq!(epochs, 13);
+    }
+
+    #[test]
+    fn invariant_checks() {
+        let packages: Vec<_> = package_ids(4);
+        let mut simulator =
+            Simulator::new(Hackage::batch_import(packages.clone())).with_invariant_checks(1);
+        for package in packages.iter().cycle().take(10) {
+            let package = Package {
+                id: package.clone(),
+                length: None,
+            };
+            simulator.process(&mut Action::Publish { package });
+        }
+        assert_eq!(simulator.publishes, 10);
+    }
+
+    /// Replays a log with simultaneous publishes (so they're batched), and
+    /// checks it ends up where processing one entry at a time does.
+    fn replay_batches_publishes<A>()
+    where
+        A: Authenticator,
+        A::ClientSnapshot: Default,
+    {
+        let ids: Vec<_> = package_ids(3);
+        let user = UserId::from("user".to_string());
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let later = start + Duration::hours(1);
+        let entries = || {
+            let publish = |i: usize, length| Action::Publish {
+                package: Package {
+                    id: ids[i].clone(),
+                    length,
+                },
+            };
+            vec![
+                Entry::new(start, publish(0, None)),
+                Entry::new(start, publish(0, None)),
+                Entry::new(start, publish(1, Some(10))),
+                Entry::new(start, publish(2, None)),
+                Entry::new(later, Action::RefreshMetadata { user: user.clone() }),
+                Entry::new(
+                    later,
+                    Action::Download {
+                        user: user.clone(),
+                        package: Package {
+                            id: ids[0].clone(),
+                            length: None,
+                        },
+                    },
+                ),
+                Entry::new(later, publish(2, None)),
+            ]
+        };
+
+        let mut batched = Simulator::new(A::batch_import(ids.clone()));
+        batched.replay(Log::from(entries()));
+        let mut one_by_one = Simulator::new(A::batch_import(ids.clone()));
+        for mut entry in entries() {
+            one_by_one.process_entry(&mut entry);
+        }
+        assert_eq!(batched.publishes, 5);
+        assert_eq!(
+            batched
+                .authenticator
+                .export_state()
+                .map(|s| s.revisions().clone()),
+            one_by_one
+                .authenticator
+                .export_state()
+                .map(|s| s.revisions().clone())
+        );
+    }
+
+    #[test]
+    fn replay_batches_simultaneous_publishes() {
+        replay_batches_publishes::<Hackage>();
+        #[cfg(feature = "rsa")]
+        replay_batches_publishes::<crate::authenticator::Rsa>();
+        #[cfg(feature = "merkle")]
+        replay_batches_publishes::<crate::authenticator::MerkleBpt>();
+        #[cfg(feature = "smt")]
+        replay_batches_publishes::<crate::authenticator::SparseMerkle>();
+    }
+
+    #[test]
+    fn process_publishes_splits_usage() {
+        let ids: Vec<_> = package_ids(3);
+        let report = Rc::new(RefCell::new(crate::report::Report::new(3)));
+        let mut simulator = Simulator::new(Hackage::batch_import(ids.clone()))
+            .with_republish_policy(RepublishPolicy::Dedupe)
+            .with_hook(report.clone());
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let entries: Vec<_> = [0, 1, 1]
+            .into_iter()
+            .map(|i| {
+                let package = Package {
+                    id: ids[i].clone(),
+                    length: Some(10),
+                };
+                Entry::new(start, Action::Publish { package })
+            })
+            .collect();
+
+        let usages = simulator.process_publishes(&entries);
+        assert_eq!(usages.len(), 3);
+        // The repeat is dropped, so it costs nothing.
+        assert_eq!(usages[2].server_compute, Duration::ZERO);
+        assert_eq!(usages[0].server_compute, usages[1].server_compute);
+        assert_eq!(simulator.publishes, 2);
+        assert_eq!(report.borrow().totals().publishes, 3);
+    }
+
+    #[test]
+    fn queues_contended_publishes() {
+        use crate::publish_queue::OrderingPolicy;
+
+        let ids: Vec<_> = package_ids(4);
+        let mut simulator = Simulator::new(Hackage::batch_import(ids.clone()))
+            .with_publish_queue(PublishQueue::new(OrderingPolicy::Fifo, 4));
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let entries: Vec<_> = ids
+            .iter()
+            .map(|id| {
+                let package = Package {
+                    id: id.clone(),
+                    length: None,
+                };
+                Entry::new(start, Action::Publish { package })
+            })
+            .collect();
+        simulator.replay(Log::from(entries));
+
+        let stats = simulator.publish_queue().unwrap().stats();
+        assert_eq!(stats.queueing.count, 4);
+        assert_eq!(stats.processing.count, 4);
+        assert_eq!(stats.max_depth, 4);
+    }
+
+    #[test]
+    fn skewed_clients_per_technique() {
+        let stats = freshness_stats::<Hackage>().unwrap();
+        assert_eq!(stats.checks, 200);
+        assert_eq!(stats.expired, 0);
+        assert!(stats.spurious_rejections() > 0);
+
+        assert_eq!(freshness_stats::<NoAuth>(), None);
+    }
+
+    #[test]
+    fn stale_refreshes_rejected() {
+        let package = Package {
+            id: PackageId::from("package".to_string()),
+            length: None,
+        };
+        // Re-signed every 12 hours, but clients want under 30 minutes old.
+        let freshness = Freshness::new(
+            FreshnessPolicy::tuf_timestamp(),
+            ClockSkew::new(Duration::ZERO),
+        )
+        .with_max_staleness(Duration::minutes(30));
+        let mut simulator = Simulator::new(Hackage::batch_import(vec![])).with_freshness(freshness);
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let user = UserId::from("user".to_string());
+        let mut publish = Entry::new(start, Action::Publish { package });
+        simulator.process_entry(&mut publish);
+
+        let mut refresh = Entry::new(start, Action::RefreshMetadata { user: user.clone() });
+        simulator.process_entry(&mut refresh);
+        let accepted = simulator.timestamps[&user];
+
+        // Signed at 12h, an hour old: every technique's client rejects it.
+        refresh.timestamp = start + Duration::hours(13);
+        simulator.process_entry(&mut refresh);
+        assert_eq!(simulator.timestamps[&user], accepted);
+        assert_eq!(simulator.freshness_stats().unwrap().stale, 1);
+
+        refresh.timestamp = start + Duration::hours(12) + Duration::minutes(10);
+        simulator.process_entry(&mut refresh);
+        assert!(simulator.timestamps[&user] > accepted);
+    }
+
+    #[test]
+    fn refreshes_carry_signed_timestamps() {
+        let package = PackageId::from("package".to_string());
+        let freshness = Freshness::new(
+            FreshnessPolicy::tuf_timestamp(),
+            ClockSkew::new(Duration::ZERO),
+        );
+        let mut simulator =
+            Simulator::new(Hackage::batch_import(vec![package])).with_freshness(freshness);
+        let start = OffsetDateTime::UNIX_EPOCH;
+        let user = UserId::from("user".to_string());
+        let mut refresh = Entry::new(start, Action::RefreshMetadata { user });
+
+        let first = simulator.process_entry(&mut refresh);
+        assert!(first.bandwidth > SignedTimestamp::fixed_size());
+        // The timestamp, then the diff.
+        assert_eq!(first.round_trips, 2);
+        // Nothing changed, but the client still needs a fresh timestamp.
+        refresh.timestamp = start + Duration::hours(13);
+        let second = simulator.process_entry(&mut refresh);
+        assert_eq!(second.bandwidth, SignedTimestamp::fixed_size());
+        assert_eq!(second.cdn_bandwidth, SignedTimestamp::fixed_size());
+
+        let stats = simulator.freshness_stats().unwrap();
+        assert_eq!(stats.timestamps, 2);
+        assert_eq!(
+            stats.timestamp_bandwidth(),
+            SignedTimestamp::fixed_size() * 2
+        );
+    }
+}
@@ -0,0 +1,483 @@
+//! Writing results rows to sqlite without the writes dominating the run.
+//!
+//! Inserting a row at a time, each in its own transaction, means a sync per
+//! row, which can take longer than a fast authenticator's whole trial. So the
+//! results db is opened in WAL mode (commits don't wait for the disk, and
+//! other runs can read while we write), and rows are buffered in a
+//! [`RowBuffer`] and written in batches, each in one transaction with cached
+//! prepared statements.
+//!
+//! The harness records its typed results rows through a [`ResultsSink`]:
+//! [`SqliteSink`] for the above, or [`MemorySink`] to keep them in memory
+//! (e.g. to check them in tests, without a db on disk).
+//!
+//! A [`RowBuffer`] can also tee each row, as it's inserted, to a JSON line
+//! (`--tee-json`), so long runs can be watched without polling the db.
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt::{self, Debug};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use rusqlite::types::{ToSqlOutput, Value};
+use rusqlite::{Connection, ToSql};
+use serde_json::{Map, Value as Json};
+
+/// Rows to buffer before writing them out.
+pub const BATCH_ROWS: usize = 1000;
+
+/// How long to wait for other processes (e.g. concurrent sweep runs) to finish
+/// writing to the same db.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Open (or create) a results db, set up for lots of small writes.
+pub fn open(path: &Path) -> rusqlite::Result<Connection> {
+    let db = Connection::open(path)?;
+    let _mode: String =
+        db.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get(0))?;
+    // In WAL mode, this is still safe against corruption; a power cut just
+    // loses the last few commits.
+    db.pragma_update(None, "synchronous", "NORMAL")?;
+    db.busy_timeout(BUSY_TIMEOUT)?;
+    // One statement per results table, with room to spare.
+    db.set_prepared_statement_cache_capacity(64);
+    Ok(db)
+}
+
+#[derive(Debug)]
+struct Row {
+    sql: &'static str,
+    values: Vec<Value>,
+}
+
+/// The table and columns of `INSERT INTO <table> (<columns>) VALUES ...`.
+fn insert_columns(sql: &str) -> Option<(&str, Vec<&str>)> {
+    let (_, rest) = sql.split_once("INTO")?;
+    let (table, rest) = rest.split_once('(')?;
+    let table = table.trim();
+    if table.contains(char::is_whitespace) {
+        // No column list (e.g. `INSERT INTO t VALUES (...)`).
+        return None;
+    }
+    let (columns, _) = rest.split_once(')')?;
+    let columns = columns.split(',').map(str::trim).collect();
+    Some((table, columns))
+}
+
+fn to_json(value: &Value) -> Json {
+    match value {
+        Value::Null => Json::Null,
+        Value::Integer(i) => Json::from(*i),
+        Value::Real(f) => Json::from(*f),
+        Value::Text(s) => Json::from(s.as_str()),
+        Value::Blob(b) => Json::from(b.as_slice()),
+    }
+}
+
+impl Row {
+    /// `{"table": ..., <column>: <value>, ...}`, or the raw statement and
+    /// values if we can't make out its columns.
+    fn to_json(&self) -> Json {
+        let values = self.values.iter().map(to_json);
+        let mut object = Map::new();
+        match insert_columns(self.sql) {
+            Some((table, columns)) if columns.len() == self.values.len() => {
+                object.insert("table".to_string(), Json::from(table));
+                for (column, value) in columns.into_iter().zip(values) {
+                    object.insert(column.to_string(), value);
+                }
+            }
+            _ => {
+                object.insert("sql".to_string(), Json::from(self.sql.trim()));
+                object.insert("values".to_string(), values.collect());
+            }
+        }
+        Json::Object(object)
+    }
+}
+
+/// Where [`RowBuffer::with_tee`] copies rows.
+struct Tee(Box<dyn Write>);
+
+impl Debug for Tee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Tee")
+    }
+}
+
+/// Insertions waiting to be written, in order.
+#[derive(Debug)]
+pub struct RowBuffer {
+    rows: Vec<Row>,
+    capacity: usize,
+    tee: Option<Tee>,
+}
+
+impl Default for RowBuffer {
+    fn default() -> Self {
+        Self::new(BATCH_ROWS)
+    }
+}
+
+impl RowBuffer {
+    /// A buffer that writes every `capacity` rows.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "buffer must hold at least a row");
+        Self {
+            rows: Vec::with_capacity(capacity),
+            capacity,
+            tee: None,
+        }
+    }
+
+    /// Also write each row, as it's inserted (not when it's written to the
+    /// db), as a line of JSON to `out`.
+    pub fn with_tee(mut self, out: impl Write + 'static) -> Self {
+        self.tee = Some(Tee(Box::new(out)));
+        self
+    }
+
+    /// Rows buffered, but not yet written.
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Run the insert statement `sql` with `params` (eventually), writing out
+    /// the buffer to `db` if it's full.
+    pub fn insert(
+        &mut self,
+        db: &Connection,
+        sql: &'static str,
+        params: &[&dyn ToSql],
+    ) -> rusqlite::Result<()> {
+        let values = params
+            .iter()
+            .map(|param| match param.to_sql()? {
+                ToSqlOutput::Borrowed(value) => Ok(value.into()),
+                ToSqlOutput::Owned(value) => Ok(value),
+                _ => Err(rusqlite::Error::ToSqlConversionFailure(
+                    "can't buffer this kind of parameter".into(),
+                )),
+            })
+            .collect::<rusqlite::Result<_>>()?;
+        let row = Row { sql, values };
+        if let Some(Tee(out)) = &mut self.tee {
+            // rusqlite has no I/O error of its own; this is the closest.
+            writeln!(out, "{}", row.to_json())
+                .and_then(|()| out.flush())
+                .map_err(|err| rusqlite::Error::ToSqlConversionFailure(err.into()))?;
+        }
+        self.rows.push(row);
+        if self.rows.len() >= self.capacity {
+            self.flush(db)?;
+        }
+        Ok(())
+    }
+
+    /// Write out every buffered row to `db`, in one transaction.
+    ///
+    /// Returns the number of rows written. If it fails, the rows are dropped.
+    pub fn flush(&mut self, db: &Connection) -> rusqlite::Result<usize> {
+        if self.rows.is_empty() {
+            return Ok(0);
+        }
+        let rows = std::mem::take(&mut self.rows);
+        let count = rows.len();
+        let tx = db.unchecked_transaction()?;
+        for Row { sql, values } in rows {
+            tx.prepare_cached(sql)?
+                .execute(rusqlite::params_from_iter(values))?;
+        }
+        tx.commit()?;
+        Ok(count)
+    }
+}
+
+/// Lets [`MemorySink`] get a concrete row back out of a `dyn Record`.
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A typed results row.
+pub trait Record: AsAny + Debug + 'static {
+    /// Insert this row, a result for `technique`, into `db` via `rows`.
+    fn write(
+        &self,
+        technique: &'static str,
+        db: &Connection,
+        rows: &mut RowBuffer,
+    ) -> rusqlite::Result<()>;
+}
+
+/// Somewhere to put a run's results.
+pub trait ResultsSink {
+    /// Record `row`, a result for `technique`.
+    fn record(&self, technique: &'static str, row: Box<dyn Record>) -> rusqlite::Result<()>;
+
+    /// Make sure everything recorded so far is stored.
+    fn flush(&self) -> rusqlite::Result<()>;
+}
+
+/// Results written to a db (in batches).
+#[derive(Debug)]
+pub struct SqliteSink {
+    db: Connection,
+    rows: RefCell<RowBuffer>,
+}
+
+impl SqliteSink {
+    /// Open (or create) the results db at `path` (see [`open`]).
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        Ok(Self {
+            db: open(path)?,
+            rows: RefCell::default(),
+        })
+    }
+
+    /// Also print each row recorded, as a line of JSON, to stdout (see
+    /// [`RowBuffer::with_tee`]).
+    pub fn with_tee_json(mut self) -> Self {
+        let rows = std::mem::take(self.rows.get_mut());
+        *self.rows.get_mut() = rows.with_tee(io::stdout());
+        self
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.db
+    }
+
+    pub fn connection_mut(&mut self) -> &mut Connection {
+        &mut self.db
+    }
+}
+
+impl ResultsSink for SqliteSink {
+    fn record(&self, technique: &'static str, row: Box<dyn Record>) -> rusqlite::Result<()> {
+        row.write(technique, &self.db, &mut self.rows.borrow_mut())
+    }
+
+    fn flush(&self) -> rusqlite::Result<()> {
+        self.rows.borrow_mut().flush(&self.db)?;
+        Ok(())
+    }
+}
+
+/// Results kept in memory, to inspect afterwards.
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    rows: RefCell<Vec<(&'static str, Box<dyn Record>)>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rows recorded (of any type).
+    pub fn len(&self) -> usize {
+        self.rows.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.borrow().is_empty()
+    }
+
+    /// Every row of type `R` recorded so far, with its technique, in order.
+    pub fn rows<R: Record + Clone>(&self) -> Vec<(&'static str, R)> {
+        self.rows
+            .borrow()
+            .iter()
+            .filter_map(|(technique, row)| {
+                // Not `row.as_any()`, which would give us the `Box`.
+                let row = row.as_ref().as_any().downcast_ref::<R>()?;
+                Some((*technique, row.clone()))
+            })
+            .collect()
+    }
+}
+
+impl ResultsSink for MemorySink {
+    fn record(&self, technique: &'static str, row: Box<dyn Record>) -> rusqlite::Result<()> {
+        self.rows.borrow_mut().push((technique, row));
+        Ok(())
+    }
+
+    fn flush(&self) -> rusqlite::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(db: &Connection) -> i64 {
+        db.query_row("SELECT COUNT(*) FROM results", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn batches() {
+        let path = std::env::temp_dir().join(format!("sssim-sink-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db = open(&path).unwrap();
+        let mode: String = db
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+        db.execute("CREATE TABLE results (name TEXT, value INTEGER)", [])
+            .unwrap();
+
+        let mut buffer = RowBuffer::new(3);
+        let sql = "INSERT INTO results (name, value) VALUES (?1, ?2)";
+        for value in 0..4 {
+            let name = format!("row{value}");
+            buffer
+                .insert(&db, sql, rusqlite::params![name, value])
+                .unwrap();
+        }
+        // The first three went out together; the last is still buffered.
+        assert_eq!((count(&db), buffer.len()), (3, 1));
+        assert_eq!(buffer.flush(&db).unwrap(), 1);
+        assert_eq!((count(&db), buffer.len()), (4, 0));
+        assert_eq!(buffer.flush(&db).unwrap(), 0);
+
+        let last: (String, i64) = db
+            .query_row(
+                "SELECT name, value FROM results ORDER BY rowid DESC LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(last, ("row3".to_string(), 3));
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    /// Output shared with the test, for [`RowBuffer::with_tee`].
+    #[derive(Clone, Default)]
+    struct Shared(std::rc::Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn tee() {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute(
+            "CREATE TABLE results (name TEXT, value INTEGER, size REAL)",
+            [],
+        )
+        .unwrap();
+        let out = Shared::default();
+        let mut buffer = RowBuffer::new(10).with_tee(out.clone());
+        let sql = "
+        INSERT INTO results (
+            name,
+            value,
+            size
+        ) VALUES ( ?1, ?2, ?3 ) ";
+        buffer
+            .insert(&db, sql, rusqlite::params!["hackage", 3, 0.5])
+            .unwrap();
+        buffer
+            .insert(&db, sql, rusqlite::params!["mercury", None::<i64>, 1.0])
+            .unwrap();
+        // Teed before it's written to the db.
+        assert_eq!(count(&db), 0);
+
+        let lines: Vec<Json> = String::from_utf8(out.0.borrow().clone())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                serde_json::json!({"table": "results", "name": "hackage", "value": 3, "size": 0.5}),
+                serde_json::json!({"table": "results", "name": "mercury", "value": null, "size": 1.0}),
+            ]
+        );
+    }
+
+    #[test]
+    fn tee_unparsed() {
+        let row = Row {
+            sql: "INSERT INTO results VALUES (?1)",
+            values: vec![Value::Integer(1)],
+        };
+        assert_eq!(
+            row.to_json(),
+            serde_json::json!({"sql": "INSERT INTO results VALUES (?1)", "values": [1]})
+        );
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Size(u64);
+
+    impl Record for Size {
+        fn write(
+            &self,
+            technique: &'static str,
+            db: &Connection,
+            rows: &mut RowBuffer,
+        ) -> rusqlite::Result<()> {
+            rows.insert(
+                db,
+                "INSERT INTO results (name, value) VALUES (?1, ?2)",
+                rusqlite::params![technique, self.0],
+            )
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Other;
+
+    impl Record for Other {
+        fn write(
+            &self,
+            _: &'static str,
+            _: &Connection,
+            _: &mut RowBuffer,
+        ) -> rusqlite::Result<()> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn in_memory() {
+        let sink = MemorySink::new();
+        let results: &dyn ResultsSink = &sink;
+        results.record("hackage", Box::new(Size(1))).unwrap();
+        results.record("hackage", Box::new(Other)).unwrap();
+        results.record("mercury", Box::new(Size(2))).unwrap();
+        results.flush().unwrap();
+
+        assert_eq!(sink.len(), 3);
+        assert_eq!(
+            sink.rows::<Size>(),
+            vec![("hackage", Size(1)), ("mercury", Size(2))]
+        );
+        assert_eq!(sink.rows::<Other>(), vec![("hackage", Other)]);
+    }
+}
@@ -0,0 +1,400 @@
+//! Long-running consistency checks (`sssim soak`).
+//!
+//! A benchmark does each operation a handful of times, so it won't notice
+//! state that goes wrong slowly: a proof cache that drifts from the
+//! accumulator, or a tree node that stops getting rehashed. A soak run drives
+//! one technique with a random mix of publishes, refreshes, and downloads for
+//! as long as you like. Clients check every diff and proof as they go, and
+//! every so often [`run`] checks the server's internal invariants (see
+//! [`Authenticator::check_invariants`](crate::Authenticator::check_invariants))
+//! and that its packages match a plain map of what was published.
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use thiserror::Error;
+
+use crate::authenticator::{DynAuthenticator, DynSnapshot, InvariantError, Revision};
+use crate::log::PackageId;
+
+/// Relative weights of each kind of operation, as `publish:refresh:download`
+/// (e.g. `1:10:20`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mix {
+    pub publish: u32,
+    pub refresh: u32,
+    pub download: u32,
+}
+
+impl Default for Mix {
+    fn default() -> Self {
+        Self {
+            publish: 1,
+            refresh: 10,
+            download: 20,
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("invalid mix {0:?}: expected three weights like `1:10:20`, not all zero")]
+pub struct InvalidMix(String);
+
+impl FromStr for Mix {
+    type Err = InvalidMix;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidMix(s.to_string());
+        let weights = s
+            .split(':')
+            .map(|weight| weight.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+        match weights[..] {
+            [publish, refresh, download] if publish + refresh + download > 0 => Ok(Self {
+                publish,
+                refresh,
+                download,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Mix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.publish, self.refresh, self.download)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    Publish,
+    Refresh,
+    Download,
+}
+
+impl Mix {
+    fn choose(&self, rng: &mut impl Rng) -> Operation {
+        let total = self.publish + self.refresh + self.download;
+        let pick = rng.gen_range(0..total);
+        if pick < self.publish {
+            Operation::Publish
+        } else if pick < self.publish + self.refresh {
+            Operation::Refresh
+        } else {
+            Operation::Download
+        }
+    }
+}
+
+/// Settings for a soak run.
+#[derive(Debug, Clone)]
+pub struct SoakOptions {
+    pub mix: Mix,
+    /// Number of clients, each with its own snapshot.
+    pub clients: usize,
+    /// Probability that a publish is of a brand-new package.
+    pub new_package_fraction: f64,
+    /// Publishes between calls to `batch_process`.
+    pub batch: usize,
+    /// Operations between invariant checks.
+    pub check_every: u64,
+    /// Stop after this long...
+    pub wall_time: Duration,
+    /// ...or after this many operations, whichever comes first.
+    pub max_operations: Option<u64>,
+    /// Seed for the workload, so a failure can be replayed.
+    pub seed: u64,
+}
+
+impl Default for SoakOptions {
+    fn default() -> Self {
+        Self {
+            mix: Mix::default(),
+            clients: 16,
+            new_package_fraction: 0.1,
+            batch: 1,
+            check_every: 10_000,
+            wall_time: Duration::from_secs(60 * 60),
+            max_operations: None,
+            seed: 0,
+        }
+    }
+}
+
+/// What a soak run has done so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SoakStats {
+    pub operations: u64,
+    pub publishes: u64,
+    pub refreshes: u64,
+    pub downloads: u64,
+    /// Completed invariant checks.
+    pub checks: u64,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SoakError {
+    #[error("after {operations} operations: {source}")]
+    Invariant {
+        operations: u64,
+        source: InvariantError,
+    },
+    #[error("after {operations} operations: {message}")]
+    Client { operations: u64, message: String },
+}
+
+struct Client {
+    snapshot: DynSnapshot,
+    /// The latest revision this client has downloaded of each package.
+    seen: HashMap<PackageId, Revision>,
+}
+
+struct Soak<'a> {
+    server: Box<dyn DynAuthenticator>,
+    options: &'a SoakOptions,
+    rng: StdRng,
+    /// What every package's revision should be, once pending publishes are
+    /// processed.
+    model: HashMap<PackageId, Revision>,
+    /// Packages the server has processed (so clients can download them).
+    packages: Vec<PackageId>,
+    /// Publishes since the last `batch_process`.
+    pending: usize,
+    /// New packages among them.
+    pending_new: Vec<PackageId>,
+    clients: Vec<Client>,
+    stats: SoakStats,
+}
+
+impl<'a> Soak<'a> {
+    fn client_error(&self, message: impl Into<String>) -> SoakError {
+        SoakError::Client {
+            operations: self.stats.operations,
+            message: format!("{}: {}", self.server.name(), message.into()),
+        }
+    }
+
+    fn publish(&mut self) {
+        let package =
+            if self.packages.is_empty() || self.rng.gen_bool(self.options.new_package_fraction) {
+                PackageId::from(format!("soak{}", self.model.len()))
+            } else {
+                self.packages[self.rng.gen_range(0..self.packages.len())].clone()
+            };
+        let revision = match self.model.get(&package) {
+            Some(revision) => revision.checked_increment().expect("revision overflow"),
+            None => {
+                self.pending_new.push(package.clone());
+                Revision::default()
+            }
+        };
+        self.model.insert(package.clone(), revision);
+        self.server.publish(package);
+        self.pending += 1;
+        self.stats.publishes += 1;
+        if self.pending >= self.options.batch {
+            self.flush();
+        }
+    }
+
+    /// Process pending publishes, if there are any (an empty batch is an
+    /// error for some pool authenticators).
+    fn flush(&mut self) {
+        if self.pending == 0 {
+            return;
+        }
+        self.server.batch_process();
+        self.pending = 0;
+        self.packages.append(&mut self.pending_new);
+    }
+
+    fn refresh(&mut self, client: usize) -> Result<(), SoakError> {
+        let snapshot = &self.clients[client].snapshot;
+        if let Some(diff) = self.server.refresh_metadata(snapshot) {
+            if !snapshot.check_no_rollback(&diff) {
+                return Err(self.client_error(format!("client {client} rejected a fresh diff")));
+            }
+            self.clients[client].snapshot.update(diff);
+        }
+        Ok(())
+    }
+
+    /// Refresh, then download a package (as a real client would).
+    fn download(&mut self, client: usize) -> Result<(), SoakError> {
+        self.refresh(client)?;
+        if self.packages.is_empty() {
+            return Ok(());
+        }
+        let package = self.packages[self.rng.gen_range(0..self.packages.len())].clone();
+        let (revision, proof) = self
+            .server
+            .request_file(&self.clients[client].snapshot, &package);
+        if !self.clients[client]
+            .snapshot
+            .verify_membership(&package, revision, proof)
+        {
+            return Err(self.client_error(format!(
+                "client {client} rejected {package:?} at revision {}",
+                revision.0
+            )));
+        }
+        let seen = self.clients[client]
+            .seen
+            .entry(package.clone())
+            .or_default();
+        if revision < *seen {
+            let message = format!(
+                "client {client} got {package:?} at revision {} after seeing {}",
+                revision.0, seen.0
+            );
+            return Err(self.client_error(message));
+        }
+        *seen = revision;
+        Ok(())
+    }
+
+    fn check(&mut self) -> Result<(), SoakError> {
+        self.flush();
+        let operations = self.stats.operations;
+        self.server
+            .check_invariants()
+            .map_err(|source| SoakError::Invariant { operations, source })?;
+        if let Some(state) = self.server.export_state() {
+            let exported: HashMap<_, _> = state.revisions().clone().into_iter().collect();
+            if exported != self.model {
+                return Err(self.client_error(format!(
+                    "exported {} packages, but {} were published (or revisions differ)",
+                    exported.len(),
+                    self.model.len()
+                )));
+            }
+        }
+        self.stats.checks += 1;
+        Ok(())
+    }
+}
+
+/// Soak-test `server` (freshly imported with `packages`) until
+/// `options.wall_time` or `options.max_operations`, checking invariants every
+/// `options.check_every` operations and once at the end.
+///
+/// `on_check` gets the stats after each check passes (e.g. to print
+/// progress).
+pub fn run(
+    server: Box<dyn DynAuthenticator>,
+    packages: Vec<PackageId>,
+    options: &SoakOptions,
+    mut on_check: impl FnMut(&SoakStats),
+) -> Result<SoakStats, SoakError> {
+    assert!(options.clients > 0, "need at least one client");
+    assert!(options.check_every > 0, "need to check sometimes");
+    let clients = (0..options.clients)
+        .map(|_| Client {
+            snapshot: server.get_metadata(),
+            seen: HashMap::new(),
+        })
+        .collect();
+    let mut soak = Soak {
+        server,
+        options,
+        rng: StdRng::seed_from_u64(options.seed),
+        model: packages
+            .iter()
+            .map(|package| (package.clone(), Revision::default()))
+            .collect(),
+        packages,
+        pending: 0,
+        pending_new: Vec::new(),
+        clients,
+        stats: SoakStats::default(),
+    };
+
+    let start = Instant::now();
+    loop {
+        let done = start.elapsed() >= options.wall_time
+            || options
+                .max_operations
+                .map_or(false, |max| soak.stats.operations >= max);
+        if done || (soak.stats.operations > 0 && soak.stats.operations % options.check_every == 0) {
+            soak.check()?;
+            on_check(&soak.stats);
+        }
+        if done {
+            return Ok(soak.stats);
+        }
+
+        let client = soak.rng.gen_range(0..options.clients);
+        match options.mix.choose(&mut soak.rng) {
+            Operation::Publish => soak.publish(),
+            Operation::Refresh => {
+                soak.refresh(client)?;
+                soak.stats.refreshes += 1;
+            }
+            Operation::Download => {
+                soak.download(client)?;
+                soak.stats.downloads += 1;
+            }
+        }
+        soak.stats.operations += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::authenticator::{Authenticators, RepositoryState};
+    use crate::testing::package_ids;
+
+    #[test]
+    fn mix() {
+        assert_eq!("1:10:20".parse::<Mix>(), Ok(Mix::default()));
+        assert_eq!(Mix::default().to_string(), "1:10:20");
+        assert!("1:10".parse::<Mix>().is_err());
+        assert!("0:0:0".parse::<Mix>().is_err());
+        assert!("a:b:c".parse::<Mix>().is_err());
+    }
+
+    #[test]
+    fn short_soak() {
+        let packages: Vec<_> = package_ids(8);
+        let state = RepositoryState::new(
+            packages
+                .iter()
+                .map(|package| (package.clone(), Revision::default())),
+        );
+        let options = SoakOptions {
+            mix: Mix {
+                publish: 2,
+                refresh: 1,
+                download: 2,
+            },
+            clients: 3,
+            batch: 2,
+            check_every: 50,
+            max_operations: Some(200),
+            ..SoakOptions::default()
+        };
+        for technique in Authenticators::all() {
+            let name = technique.name();
+            if ["none", "insecure"].contains(&name) {
+                // These don't track revisions, so every download looks stale.
+                continue;
+            }
+            let server = technique.import_state(&state);
+            let mut checks = 0;
+            let stats = run(server, packages.clone(), &options, |_| checks += 1)
+                .unwrap_or_else(|err| panic!("{name}: {err}"));
+            assert_eq!(stats.operations, 200, "{name}");
+            assert_eq!(stats.publishes + stats.refreshes + stats.downloads, 200);
+            // Every 50 operations, and at the end.
+            assert_eq!(stats.checks, 4, "{name}");
+            assert_eq!(checks, 4, "{name}");
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! How far behind clients are when they refresh, taken from real traces.
+//!
+//! What a refresh costs depends mostly on how many releases the client missed
+//! since its last one: an append-only proof or a diff grows with that count.
+//! The synthetic refresh trials just try a fixed ladder of counts (100, 200,
+//! ..., 1000), which says nothing about how often each comes up. A
+//! [`RefreshIntervals`] is the empirical distribution of that count, pulled
+//! from a trace (`sssim refresh-intervals`), that the trials can sample from
+//! instead (`--refresh-intervals`).
+//!
+//! Intervals are counted in publishes, not time, since that's what sizes
+//! depend on. A user's first refresh (and their first after a goodbye) has
+//! nothing to count from, so it doesn't contribute.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::log::{Action, Entry, UserId};
+
+#[derive(Error, Debug)]
+pub enum RefreshIntervalsError {
+    #[error("reading refresh intervals: {0}")]
+    Io(#[from] io::Error),
+    #[error("parsing refresh intervals: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// How many times each number of missed releases was seen at a refresh.
+///
+/// ```json
+/// { "counts": { "0": 12, "3": 40, "250": 1 } }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefreshIntervals {
+    counts: BTreeMap<usize, u64>,
+}
+
+impl RefreshIntervals {
+    /// The intervals between each user's refreshes in `entries` (in order).
+    pub fn from_entries<'a>(entries: impl IntoIterator<Item = &'a Entry>) -> Self {
+        let mut publishes = 0;
+        let mut last_refresh: HashMap<&UserId, usize> = HashMap::new();
+        let mut intervals = Self::default();
+        for entry in entries {
+            match &entry.action {
+                Action::Publish { .. } => publishes += 1,
+                Action::RefreshMetadata { user } => {
+                    if let Some(last) = last_refresh.insert(user, publishes) {
+                        intervals.record(publishes - last);
+                    }
+                }
+                Action::Goodbye { user } => {
+                    last_refresh.remove(user);
+                }
+                Action::Download { .. } => {}
+            }
+        }
+        intervals
+    }
+
+    /// Read from `--refresh-intervals`.
+    pub fn load(path: &Path) -> Result<Self, RefreshIntervalsError> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), RefreshIntervalsError> {
+        let writer = BufWriter::new(File::create(path)?);
+        Ok(serde_json::to_writer_pretty(writer, self)?)
+    }
+
+    pub fn record(&mut self, missed_releases: usize) {
+        *self.counts.entry(missed_releases).or_default() += 1;
+    }
+
+    /// Refreshes seen.
+    pub fn len(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// The smallest interval at least `fraction` of refreshes are within
+    /// (nearest rank), if there are any.
+    pub fn quantile(&self, fraction: f64) -> Option<usize> {
+        let rank = ((fraction * self.len() as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (&interval, &count) in &self.counts {
+            seen += count;
+            if seen >= rank {
+                return Some(interval);
+            }
+        }
+        None
+    }
+
+    /// `n` intervals drawn (with replacement) in proportion to how often they
+    /// were seen, smallest first.
+    ///
+    /// Panics if there are no intervals to draw from.
+    pub fn sample_sorted(&self, n: usize, rng: &mut impl Rng) -> Vec<usize> {
+        let sampler = Sampler::new(self);
+        let mut samples: Vec<usize> = (0..n).map(|_| sampler.sample(rng)).collect();
+        samples.sort_unstable();
+        samples
+    }
+}
+
+impl fmt::Display for RefreshIntervals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let quantile = |fraction| self.quantile(fraction).unwrap_or(0);
+        write!(
+            f,
+            "{} refreshes; missed releases p50 {}, p90 {}, p99 {}, max {}",
+            self.len(),
+            quantile(0.5),
+            quantile(0.9),
+            quantile(0.99),
+            quantile(1.0)
+        )
+    }
+}
+
+/// Draws intervals from a [`RefreshIntervals`].
+struct Sampler {
+    intervals: Vec<usize>,
+    weights: WeightedIndex<u64>,
+}
+
+impl Sampler {
+    fn new(intervals: &RefreshIntervals) -> Self {
+        Self {
+            intervals: intervals.counts.keys().copied().collect(),
+            weights: WeightedIndex::new(intervals.counts.values())
+                .expect("no refresh intervals to sample from"),
+        }
+    }
+}
+
+impl Distribution<usize> for Sampler {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        self.intervals[self.weights.sample(rng)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use time::OffsetDateTime;
+
+    use crate::log::Package;
+
+    fn refresh(user: &str) -> Action {
+        Action::RefreshMetadata {
+            user: UserId::from(user.to_string()),
+        }
+    }
+
+    fn publish() -> Action {
+        Action::Publish {
+            package: Package {
+                id: "package".into(),
+                length: None,
+            },
+        }
+    }
+
+    #[test]
+    fn from_trace() {
+        let actions = vec![
+            refresh("a"),
+            publish(),
+            refresh("b"),
+            publish(),
+            publish(),
+            refresh("a"), // 3
+            refresh("b"), // 2
+            refresh("b"), // 0
+            Action::Goodbye {
+                user: UserId::from("a".to_string()),
+            },
+            publish(),
+            refresh("a"), // first since goodbye
+            refresh("b"), // 1
+        ];
+        let entries: Vec<_> = actions
+            .into_iter()
+            .map(|action| Entry::new(OffsetDateTime::UNIX_EPOCH, action))
+            .collect();
+        let intervals = RefreshIntervals::from_entries(&entries);
+        assert_eq!(
+            intervals.counts,
+            BTreeMap::from([(0, 1), (1, 1), (2, 1), (3, 1)])
+        );
+        assert_eq!(intervals.quantile(0.5), Some(1));
+        assert_eq!(intervals.quantile(1.0), Some(3));
+        assert_eq!(RefreshIntervals::default().quantile(0.5), None);
+    }
+
+    #[test]
+    fn samples_in_proportion() {
+        let mut intervals = RefreshIntervals::default();
+        for _ in 0..9 {
+            intervals.record(10);
+        }
+        intervals.record(1000);
+        let mut rng = StdRng::seed_from_u64(0);
+        let samples = intervals.sample_sorted(1000, &mut rng);
+        assert!(samples.windows(2).all(|pair| pair[0] <= pair[1]));
+        let stale = samples.iter().filter(|&&interval| interval == 1000).count();
+        assert!((50..150).contains(&stale), "{stale}");
+        assert_eq!(
+            samples.len() - stale,
+            samples.iter().filter(|&&i| i == 10).count()
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("sssim-intervals-{}.json", std::process::id()));
+        let mut intervals = RefreshIntervals::default();
+        intervals.record(3);
+        intervals.record(3);
+        intervals.record(250);
+        intervals.save(&path).unwrap();
+        assert_eq!(RefreshIntervals::load(&path).unwrap(), intervals);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
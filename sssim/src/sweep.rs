@@ -0,0 +1,529 @@
+//! Run matrices: every combination of a few settings, in one results db.
+//!
+//! `sssim sweep` expands lists of package counts, techniques, and thread
+//! counts into [`RunConfig`]s and runs each as its own `sssim` process (a
+//! process only gets to size its thread pool once). Alternatively, it writes
+//! the command lines to a job file, one per line, to hand to a cluster.
+//!
+//! Each run gets an ID up front, in the `runs` table, and passes it to the
+//! child as `--run-id`; the child then tags every row it writes with that
+//! `run_id` (see [`tag_rows`]), so results from the same db can be told apart
+//! even if runs happen concurrently.
+//!
+//! For Slurm ([`JobFormat::Slurm`]), each run writes to its own db instead
+//! (many writers on a shared filesystem don't mix well with sqlite), and
+//! [`merge_results`] combines them into the sweep's db afterwards.
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior};
+use thiserror::Error;
+
+use crate::config::TechniqueConfig;
+use crate::schema::{Header, SchemaMismatch};
+
+/// Bookkeeping tables, whose rows aren't results.
+const UNTAGGED_TABLES: &[&str] = &["device_profiles", "runs", "schema", "sqlite_sequence"];
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("invalid count {0:?} (expected e.g. `1000` or `1e3`)")]
+pub struct InvalidCount(String);
+
+/// Parse a count, allowing scientific notation (`1e5`).
+pub fn parse_count(s: &str) -> Result<usize, InvalidCount> {
+    let invalid = || InvalidCount(s.to_string());
+    if let Ok(count) = s.parse() {
+        return Ok(count);
+    }
+    let (mantissa, exponent) = s.split_once(['e', 'E']).ok_or_else(invalid)?;
+    let mantissa: usize = mantissa.parse().map_err(|_| invalid())?;
+    let exponent: u32 = exponent.parse().map_err(|_| invalid())?;
+    10usize
+        .checked_pow(exponent)
+        .and_then(|scale| mantissa.checked_mul(scale))
+        .ok_or_else(invalid)
+}
+
+/// How to write out a sweep's runs, instead of running them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JobFormat {
+    /// One command line per run, all writing to the sweep's db.
+    #[default]
+    Lines,
+    /// Command lines (each run with its own db; see [`job_results`]) plus an
+    /// `sbatch` script running them as a job array.
+    Slurm,
+}
+
+#[derive(Error, Debug)]
+#[error("unknown job format {0:?} (expected `lines` or `slurm`)")]
+pub struct UnknownJobFormat(String);
+
+impl FromStr for JobFormat {
+    type Err = UnknownJobFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lines" => Ok(Self::Lines),
+            "slurm" => Ok(Self::Slurm),
+            _ => Err(UnknownJobFormat(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for JobFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lines => write!(f, "lines"),
+            Self::Slurm => write!(f, "slurm"),
+        }
+    }
+}
+
+/// One point in a sweep.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunConfig {
+    pub packages: usize,
+    pub authenticator: String,
+    pub threads: usize,
+}
+
+impl RunConfig {
+    /// Arguments for an `sssim` process doing this run (then `extra`).
+    pub fn args(&self, results: &Path, run_id: i64, extra: &[String]) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec![
+            "--packages".into(),
+            self.packages.to_string().into(),
+            "--authenticators".into(),
+            self.authenticator.clone().into(),
+            "--threads".into(),
+            self.threads.to_string().into(),
+            "--results".into(),
+            results.into(),
+            "--run-id".into(),
+            run_id.to_string().into(),
+        ];
+        args.extend(extra.iter().map(OsString::from));
+        args
+    }
+}
+
+/// Every combination, smallest package counts first (so that a sweep that
+/// runs out of time has the cheap results).
+pub fn expand(packages: &[usize], authenticators: &[String], threads: &[usize]) -> Vec<RunConfig> {
+    let mut packages = packages.to_vec();
+    packages.sort_unstable();
+    packages.dedup();
+    let mut configs = Vec::new();
+    for &packages in &packages {
+        for authenticator in authenticators {
+            for &threads in threads {
+                configs.push(RunConfig {
+                    packages,
+                    authenticator: authenticator.clone(),
+                    threads,
+                });
+            }
+        }
+    }
+    configs
+}
+
+pub fn create_runs_table(db: &Connection) -> rusqlite::Result<()> {
+    db.execute(
+        "CREATE TABLE IF NOT EXISTS runs (
+            id        INTEGER PRIMARY KEY AUTOINCREMENT,
+            sweep     INTEGER,
+            technique TEXT,
+            packages  INTEGER,
+            cores     INTEGER,
+            extra     TEXT,
+            status    TEXT,
+            config    TEXT
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Assign an ID to a (pending) run in sweep `sweep` (see
+/// [`create_runs_table`]), with `extra` arguments and the technique's
+/// `settings` (if any).
+pub fn record_run(
+    db: &Connection,
+    sweep: i64,
+    config: &RunConfig,
+    extra: &[String],
+    settings: &TechniqueConfig,
+) -> rusqlite::Result<i64> {
+    db.execute(
+        "INSERT INTO runs (sweep, technique, packages, cores, extra, status, config)
+         VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7 )",
+        rusqlite::params![
+            sweep,
+            config.authenticator,
+            config.packages,
+            config.threads,
+            extra.join(" "),
+            RunStatus::Pending.to_string(),
+            (!settings.is_empty()).then(|| settings.to_string()),
+        ],
+    )?;
+    Ok(db.last_insert_rowid())
+}
+
+/// How a run went (the `status` column of `runs`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    /// Not run yet (or handed off in a job file).
+    Pending,
+    Done,
+    Failed,
+    /// Stopped early (see [`crate::interrupt`]); it has partial results.
+    Interrupted,
+}
+
+impl fmt::Display for RunStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pending => write!(f, "pending"),
+            Self::Done => write!(f, "done"),
+            Self::Failed => write!(f, "failed"),
+            Self::Interrupted => write!(f, "interrupted"),
+        }
+    }
+}
+
+pub fn set_status(db: &Connection, run_id: i64, status: RunStatus) -> rusqlite::Result<()> {
+    db.execute(
+        "UPDATE runs SET status = ?1 WHERE id = ?2",
+        rusqlite::params![status.to_string(), run_id],
+    )?;
+    Ok(())
+}
+
+/// A command line for a job file, quoted for a POSIX shell.
+pub fn command_line(program: &Path, args: &[OsString]) -> String {
+    fn quote(arg: &OsStr) -> String {
+        let arg = arg.to_string_lossy();
+        let safe = |c: char| c.is_ascii_alphanumeric() || "_-.,/=:".contains(c);
+        if !arg.is_empty() && arg.chars().all(safe) {
+            return arg.into_owned();
+        }
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+    std::iter::once(program.as_os_str())
+        .chain(args.iter().map(OsString::as_os_str))
+        .map(quote)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Where run `run_id` writes its results, for jobs written to `job_dir`.
+pub fn job_results(job_dir: &Path, run_id: i64) -> PathBuf {
+    job_dir.join("results").join(format!("run-{run_id}.db"))
+}
+
+/// An `sbatch` script running line `$SLURM_ARRAY_TASK_ID` of `job_file` (which
+/// has `jobs` lines) as each task of a job array.
+///
+/// Every task asks for `cpus` CPUs (the most any run uses), and logs to
+/// `logs/` next to `job_file`.
+pub fn sbatch_script(sweep: i64, job_file: &Path, jobs: usize, cpus: usize) -> String {
+    let job_dir = job_file.parent().unwrap_or_else(|| Path::new("."));
+    let logs = job_dir.join("logs").join("%A_%a.out");
+    format!(
+        "#!/bin/bash
+#SBATCH --job-name=sssim-sweep-{sweep}
+#SBATCH --array=1-{jobs}
+#SBATCH --cpus-per-task={cpus}
+#SBATCH --output={logs}
+set -euo pipefail
+eval \"$(sed -n \"${{SLURM_ARRAY_TASK_ID}}p\" {job_file})\"
+",
+        logs = logs.display(),
+        job_file = command_line(job_file, &[]),
+    )
+}
+
+/// Tag every row this connection inserts into a results table with `run_id`.
+///
+/// Adds a `run_id` column to each table that doesn't have one yet, and a
+/// temporary (connection-only) trigger to fill it in, so other processes
+/// writing to the same db aren't affected. Call it after creating the tables.
+pub fn tag_rows(db: &mut Connection, run_id: i64) -> rusqlite::Result<()> {
+    // Immediate, so concurrent runs don't both try to add a column.
+    let tx = db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let tables: Vec<String> = tx
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table'")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    for table in tables {
+        if UNTAGGED_TABLES.contains(&table.as_str()) {
+            continue;
+        }
+        let columns = columns(&tx, "main", &table)?;
+        if !columns.iter().any(|column| column.name == "run_id") {
+            tx.execute(
+                &format!("ALTER TABLE {table} ADD COLUMN run_id INTEGER"),
+                [],
+            )?;
+        }
+        tx.execute(
+            &format!(
+                "CREATE TEMP TRIGGER tag_{table} AFTER INSERT ON main.{table}
+                 BEGIN
+                     UPDATE {table} SET run_id = {run_id} WHERE rowid = NEW.rowid;
+                 END"
+            ),
+            [],
+        )?;
+    }
+    tx.commit()
+}
+
+#[derive(Error, Debug)]
+pub enum MergeError {
+    #[error("reading results: {0}")]
+    Io(#[from] io::Error),
+    #[error("merging results: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error(transparent)]
+    Schema(#[from] SchemaMismatch),
+}
+
+/// Copy every results row from the db at `input` into `db`, returning how
+/// many rows were copied.
+///
+/// Tables and columns `db` is missing are added (e.g. `run_id`, for a db
+/// written by a sweep job), and columns `input` is missing are left `NULL`.
+/// Bookkeeping tables (like `runs`) are skipped: the sweep's own db, which
+/// `db` should be, already has them.
+pub fn merge_results(db: &mut Connection, input: &Path) -> Result<usize, MergeError> {
+    // Otherwise, attaching it creates an empty db.
+    std::fs::metadata(input)?;
+    db.execute("ATTACH DATABASE ?1 AS input", [input.to_string_lossy()])?;
+    let merged = merge_attached(db);
+    db.execute("DETACH DATABASE input", [])?;
+    merged
+}
+
+fn merge_attached(db: &mut Connection) -> Result<usize, MergeError> {
+    let tx = db.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let has_schema: Option<String> = tx
+        .query_row(
+            "SELECT name FROM input.sqlite_master WHERE type = 'table' AND name = 'schema'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if has_schema.is_some() {
+        let found: Option<u32> = tx
+            .query_row("SELECT version FROM input.schema", [], |row| row.get(0))
+            .optional()?;
+        if let Some(found) = found {
+            Header {
+                schema_version: found,
+            }
+            .check()?;
+        }
+    }
+
+    let tables: Vec<(String, String)> = tx
+        .prepare("SELECT name, sql FROM input.sqlite_master WHERE type = 'table'")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    let mut rows = 0;
+    for (table, sql) in tables {
+        if UNTAGGED_TABLES.contains(&table.as_str()) {
+            continue;
+        }
+        let existing = columns(&tx, "main", &table)?;
+        let incoming = columns(&tx, "input", &table)?;
+        if existing.is_empty() {
+            // Unqualified, so it's created in `main`.
+            tx.execute(&sql, [])?;
+        }
+        for column in &incoming {
+            if !existing.is_empty() && !existing.iter().any(|c| c.name == column.name) {
+                tx.execute(
+                    &format!(
+                        "ALTER TABLE main.{table} ADD COLUMN {} {}",
+                        column.name, column.kind
+                    ),
+                    [],
+                )?;
+            }
+        }
+        // Row IDs are per-db, so let `db` assign new ones.
+        let names = incoming
+            .iter()
+            .filter(|column| !column.primary_key)
+            .map(|column| column.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        rows += tx.execute(
+            &format!("INSERT INTO main.{table} ({names}) SELECT {names} FROM input.{table}"),
+            [],
+        )?;
+    }
+    tx.commit()?;
+    Ok(rows)
+}
+
+struct Column {
+    name: String,
+    kind: String,
+    primary_key: bool,
+}
+
+fn columns(db: &Connection, schema: &str, table: &str) -> rusqlite::Result<Vec<Column>> {
+    db.prepare(&format!("PRAGMA {schema}.table_info({table})"))?
+        .query_map([], |row| {
+            Ok(Column {
+                name: row.get(1)?,
+                kind: row.get(2)?,
+                primary_key: row.get::<_, i64>(5)? > 0,
+            })
+        })?
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts() {
+        assert_eq!(parse_count("1000"), Ok(1000));
+        assert_eq!(parse_count("1e3"), Ok(1000));
+        assert_eq!(parse_count("5E4"), Ok(50_000));
+        assert!(parse_count("1.5e3").is_err());
+        assert!(parse_count("1e99").is_err());
+        assert!(parse_count("lots").is_err());
+    }
+
+    #[test]
+    fn cartesian() {
+        let authenticators = vec!["hackage".to_string(), "mercury".to_string()];
+        let configs = expand(&[10_000, 1000, 1000], &authenticators, &[1, 8]);
+        assert_eq!(configs.len(), 2 * 2 * 2);
+        assert_eq!(
+            configs[0],
+            RunConfig {
+                packages: 1000,
+                authenticator: "hackage".to_string(),
+                threads: 1,
+            }
+        );
+        assert_eq!(configs[7].packages, 10_000);
+    }
+
+    #[test]
+    fn quotes() {
+        let args = ["--results", "my results.db", "it's"].map(OsString::from);
+        assert_eq!(
+            command_line(Path::new("/bin/sssim"), &args),
+            "/bin/sssim --results 'my results.db' 'it'\\''s'"
+        );
+    }
+
+    #[test]
+    fn tags_rows() {
+        let path = std::env::temp_dir().join(format!("sssim-sweep-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut db = Connection::open(&path).unwrap();
+        db.execute("CREATE TABLE results (value INTEGER)", [])
+            .unwrap();
+        db.execute("INSERT INTO results (value) VALUES (0)", [])
+            .unwrap();
+        tag_rows(&mut db, 7).unwrap();
+        db.execute("INSERT INTO results (value) VALUES (1)", [])
+            .unwrap();
+        // Another process, tagging as a different run.
+        let mut other = Connection::open(&path).unwrap();
+        tag_rows(&mut other, 8).unwrap();
+        other
+            .execute("INSERT INTO results (value) VALUES (2)", [])
+            .unwrap();
+
+        let rows: Vec<(i64, Option<i64>)> = db
+            .prepare("SELECT value, run_id FROM results ORDER BY value")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows, [(0, None), (1, Some(7)), (2, Some(8))]);
+        drop((db, other));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn sbatch() {
+        let script = sbatch_script(42, Path::new("/sweep/jobs.txt"), 6, 8);
+        assert!(script.starts_with("#!/bin/bash\n"));
+        assert!(script.contains("#SBATCH --array=1-6\n"));
+        assert!(script.contains("#SBATCH --cpus-per-task=8\n"));
+        assert!(script.contains("#SBATCH --output=/sweep/logs/%A_%a.out\n"));
+        assert!(
+            script.ends_with("eval \"$(sed -n \"${SLURM_ARRAY_TASK_ID}p\" /sweep/jobs.txt)\"\n")
+        );
+        assert_eq!(
+            job_results(Path::new("/sweep"), 3),
+            Path::new("/sweep/results/run-3.db")
+        );
+    }
+
+    #[test]
+    fn merges() {
+        let dir = std::env::temp_dir().join(format!("sssim-merge-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("results")).unwrap();
+        let create = "CREATE TABLE results (
+            id    INTEGER PRIMARY KEY AUTOINCREMENT,
+            value INTEGER
+        )";
+        for run_id in [1, 2] {
+            let mut job = Connection::open(job_results(&dir, run_id)).unwrap();
+            job.execute(create, []).unwrap();
+            tag_rows(&mut job, run_id).unwrap();
+            for value in 0..3 {
+                job.execute("INSERT INTO results (value) VALUES (?1)", [value * run_id])
+                    .unwrap();
+            }
+        }
+        // The sweep's db has the table, but (with no runs of its own) no
+        // `run_id` column.
+        let mut db = Connection::open_in_memory().unwrap();
+        db.execute(create, []).unwrap();
+        for run_id in [1, 2] {
+            assert_eq!(
+                merge_results(&mut db, &job_results(&dir, run_id)).unwrap(),
+                3
+            );
+        }
+
+        let rows: Vec<(i64, i64, i64)> = db
+            .prepare("SELECT id, value, run_id FROM results ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            [
+                (1, 0, 1),
+                (2, 1, 1),
+                (3, 2, 1),
+                (4, 0, 2),
+                (5, 2, 2),
+                (6, 4, 2)
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
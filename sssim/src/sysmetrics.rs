@@ -0,0 +1,143 @@
+//! Process resource probes (CPU time, peak memory), per platform.
+//!
+//! Wall-clock timing is portable (see [`latency`](crate::latency)), but asking
+//! the OS how much CPU time and memory we've used isn't. Each probe has Unix
+//! (Linux, macOS) and Windows implementations, and returns `None` anywhere
+//! else, so the harness still runs there (just without these numbers).
+use time::Duration;
+
+use crate::util::{byte, Information};
+
+/// CPU time (user plus system, across all threads) this process has used so
+/// far.
+pub fn cpu_time() -> Option<Duration> {
+    imp::cpu_time()
+}
+
+/// The most memory this process has had resident at once, so far.
+pub fn peak_rss() -> Option<Information> {
+    imp::peak_rss().map(Information::new::<byte>)
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::mem::MaybeUninit;
+
+    use time::Duration;
+
+    fn rusage() -> Option<libc::rusage> {
+        let mut usage = MaybeUninit::<libc::rusage>::zeroed();
+        // SAFETY: `getrusage` only writes a `rusage` to the pointer we give it.
+        if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        // SAFETY: the call succeeded, so it filled in `usage`.
+        Some(unsafe { usage.assume_init() })
+    }
+
+    // The field types are aliases, which are `i64` on some platforms.
+    #[allow(clippy::useless_conversion)]
+    fn duration(time: libc::timeval) -> Duration {
+        Duration::seconds(time.tv_sec.into()) + Duration::microseconds(time.tv_usec.into())
+    }
+
+    pub fn cpu_time() -> Option<Duration> {
+        let usage = rusage()?;
+        Some(duration(usage.ru_utime) + duration(usage.ru_stime))
+    }
+
+    pub fn peak_rss() -> Option<usize> {
+        let max_rss = usize::try_from(rusage()?.ru_maxrss).ok()?;
+        // macOS reports bytes; everyone else, kilobytes.
+        if cfg!(target_os = "macos") {
+            Some(max_rss)
+        } else {
+            Some(max_rss * 1024)
+        }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::mem::{size_of, MaybeUninit};
+
+    use time::Duration;
+    use windows_sys::Win32::Foundation::FILETIME;
+    use windows_sys::Win32::System::ProcessStatus::{
+        GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS,
+    };
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, GetProcessTimes};
+
+    /// A `FILETIME` interval, which counts 100ns ticks.
+    fn duration(time: FILETIME) -> Duration {
+        let ticks = (u64::from(time.dwHighDateTime) << 32) | u64::from(time.dwLowDateTime);
+        Duration::nanoseconds(i64::try_from(ticks).unwrap() * 100)
+    }
+
+    pub fn cpu_time() -> Option<Duration> {
+        let zero = FILETIME {
+            dwLowDateTime: 0,
+            dwHighDateTime: 0,
+        };
+        let (mut creation, mut exit, mut kernel, mut user) = (zero, zero, zero, zero);
+        // SAFETY: `GetCurrentProcess` returns a pseudo-handle that's always
+        // valid, and `GetProcessTimes` only writes to the four `FILETIME`s.
+        let ok = unsafe {
+            GetProcessTimes(
+                GetCurrentProcess(),
+                &mut creation,
+                &mut exit,
+                &mut kernel,
+                &mut user,
+            )
+        };
+        if ok == 0 {
+            return None;
+        }
+        Some(duration(kernel) + duration(user))
+    }
+
+    pub fn peak_rss() -> Option<usize> {
+        let mut counters = MaybeUninit::<PROCESS_MEMORY_COUNTERS>::zeroed();
+        let size = u32::try_from(size_of::<PROCESS_MEMORY_COUNTERS>()).unwrap();
+        // SAFETY: as above; `GetProcessMemoryInfo` writes at most `size`
+        // bytes to `counters`.
+        if unsafe { GetProcessMemoryInfo(GetCurrentProcess(), counters.as_mut_ptr(), size) } == 0 {
+            return None;
+        }
+        // SAFETY: the call succeeded, so it filled in `counters`.
+        Some(unsafe { counters.assume_init() }.PeakWorkingSetSize)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    use time::Duration;
+
+    pub fn cpu_time() -> Option<Duration> {
+        None
+    }
+
+    pub fn peak_rss() -> Option<usize> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uom::ConstZero;
+
+    #[test]
+    fn probes() {
+        if !cfg!(any(unix, windows)) {
+            assert_eq!((cpu_time(), peak_rss()), (None, None));
+            return;
+        }
+        let before = cpu_time().unwrap();
+        let work: u64 = (0..1_000_000u64).map(|i| i * i).sum();
+        assert!(work > 0);
+        assert!(cpu_time().unwrap() >= before);
+        assert!(peak_rss().unwrap() > Information::ZERO);
+    }
+}
@@ -0,0 +1,127 @@
+//! How much targets metadata a client downloads along with a package.
+//!
+//! In TUF, a download needs more than the snapshot (or a proof against it):
+//! the client also fetches the package's signed targets metadata, with the
+//! length and hashes of each of its files. That part is the same for every
+//! technique, so leaving it out makes snapshot savings look bigger than they
+//! are next to the total. We don't have real targets files; a
+//! [`TargetsModel`] estimates their size from how many revisions a package
+//! has, since (as in PEP 458) each package's targets list every file it has
+//! ever released.
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uom::si::information::byte;
+
+use crate::authenticator::{Authenticator, Revision};
+use crate::util::Information;
+
+/// Bytes of targets metadata per package.
+///
+/// ```json
+/// {
+///   "signed_bytes": 500,
+///   "file_bytes": 250,
+///   "files_per_revision": 2
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TargetsModel {
+    /// Signatures and the rest of the envelope, however many files there are.
+    pub signed_bytes: usize,
+    /// One file's entry: its path, length and hashes.
+    pub file_bytes: usize,
+    /// Files released with each revision (e.g. an sdist and a wheel).
+    pub files_per_revision: usize,
+}
+
+impl Default for TargetsModel {
+    /// Rough figures for PyPI-style JSON metadata with one ed25519 signature
+    /// and SHA-256 and BLAKE2b hashes.
+    fn default() -> Self {
+        Self {
+            signed_bytes: 500,
+            file_bytes: 250,
+            files_per_revision: 2,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TargetsModelError {
+    #[error("reading targets model: {0}")]
+    Io(#[from] io::Error),
+    #[error("parsing targets model: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+impl TargetsModel {
+    /// Read from `--targets-model`; missing fields keep their defaults.
+    pub fn load(path: &Path) -> Result<Self, TargetsModelError> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Targets metadata for a package at `revision`.
+    pub fn size(&self, revision: Revision) -> Information {
+        let revisions = revision.0.get() as usize;
+        let files = revisions.saturating_mul(self.files_per_revision);
+        let bytes = files
+            .saturating_mul(self.file_bytes)
+            .saturating_add(self.signed_bytes);
+        Information::new::<byte>(bytes)
+    }
+
+    /// Targets metadata a client fetches to download a package at `revision`
+    /// with `proof`: none if the proof already binds the package's targets
+    /// (they're counted in its size).
+    pub fn download_size<A: Authenticator>(
+        &self,
+        revision: Revision,
+        proof: &A::Proof,
+    ) -> Information {
+        match A::proven_targets(proof) {
+            Some(_) => Information::new::<byte>(0),
+            None => self.size(revision),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::authenticator::Hackage;
+
+    #[test]
+    fn grows_with_revisions() {
+        let model = TargetsModel {
+            signed_bytes: 100,
+            file_bytes: 10,
+            files_per_revision: 2,
+        };
+        assert_eq!(model.size(Revision::from(1)), Information::new::<byte>(120));
+        assert_eq!(model.size(Revision::from(5)), Information::new::<byte>(200));
+        // Hackage proofs don't carry targets.
+        assert_eq!(
+            model.download_size::<Hackage>(Revision::from(5), &()),
+            Information::new::<byte>(200)
+        );
+    }
+
+    #[test]
+    fn partial_config() {
+        let model: TargetsModel = serde_json::from_str(r#"{ "file_bytes": 3 }"#).unwrap();
+        assert_eq!(
+            model,
+            TargetsModel {
+                file_bytes: 3,
+                ..TargetsModel::default()
+            }
+        );
+    }
+}
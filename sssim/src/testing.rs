@@ -0,0 +1,306 @@
+//! Proptest strategies for property tests of authenticators.
+//!
+//! These are what the tests in this crate use to generate package names, log
+//! entries, and publish workloads; they're exported (behind the `testing`
+//! feature) so a new technique can get the same property tests by handing its
+//! [`Technique`] to [`replay`], [`rollback`], and [`latest`].
+//!
+//! Package names come from a small pool so that strategies hit the same
+//! package repeatedly, which is where authenticators get interesting.
+use std::collections::{HashMap, HashSet};
+
+use proptest::prelude::*;
+use proptest::test_runner::TestCaseError;
+use time::{Duration, OffsetDateTime};
+
+use crate::authenticator::{RepositoryState, Revision, Technique};
+use crate::log::{Action, Entry, Package, PackageId, UserId};
+
+/// How many distinct package names [`any_package_id`] draws from.
+pub const PACKAGE_NAMES: usize = 32;
+
+/// How many distinct users [`user_ids`] draws from.
+pub const USERS: usize = 8;
+
+/// `count` distinct packages, named `package0`, `package1`, ....
+pub fn package_ids(count: usize) -> Vec<PackageId> {
+    (0..count).map(package_id).collect()
+}
+
+/// Package IDs, from a pool of [`PACKAGE_NAMES`].
+pub fn any_package_id() -> impl Strategy<Value = PackageId> {
+    (0..PACKAGE_NAMES).prop_map(package_id)
+}
+
+fn package_id(i: usize) -> PackageId {
+    PackageId::from(format!("package{i}"))
+}
+
+/// User IDs, from a pool of [`USERS`].
+pub fn user_ids() -> impl Strategy<Value = UserId> {
+    (0..USERS).prop_map(|i| UserId::from(format!("user{i}")))
+}
+
+/// Packages, with an artifact length some of the time.
+pub fn packages() -> impl Strategy<Value = Package> {
+    (any_package_id(), proptest::option::of(1..1u64 << 30))
+        .prop_map(|(id, length)| Package { id, length })
+}
+
+/// Any [`Action`].
+pub fn actions() -> impl Strategy<Value = Action> {
+    prop_oneof![
+        (user_ids(), packages()).prop_map(|(user, package)| Action::Download { user, package }),
+        user_ids().prop_map(|user| Action::RefreshMetadata { user }),
+        packages().prop_map(|package| Action::Publish { package }),
+        user_ids().prop_map(|user| Action::Goodbye { user }),
+    ]
+}
+
+/// Up to `max_len` entries, in timestamp order (as [`Log`](crate::log::Log)
+/// requires), sometimes several at the same instant.
+pub fn entries(max_len: usize) -> impl Strategy<Value = Vec<Entry>> {
+    proptest::collection::vec((0..60i64, actions()), 0..=max_len).prop_map(|steps| {
+        let mut timestamp = OffsetDateTime::UNIX_EPOCH;
+        steps
+            .into_iter()
+            .map(|(gap, action)| {
+                timestamp += Duration::seconds(gap);
+                Entry::new(timestamp, action)
+            })
+            .collect()
+    })
+}
+
+/// Initial packages, then a sequence of publishes.
+#[derive(Debug, Clone)]
+pub struct Workload {
+    /// Distinct.
+    pub packages: Vec<PackageId>,
+    /// Some of these are new packages; most are re-publishes.
+    pub publishes: Vec<PackageId>,
+}
+
+/// Workloads with up to `max_publishes` publishes.
+pub fn workloads(max_publishes: usize) -> impl Strategy<Value = Workload> {
+    (
+        proptest::collection::hash_set(any_package_id(), 1..PACKAGE_NAMES / 2),
+        proptest::collection::vec(any_package_id(), 0..=max_publishes),
+    )
+        .prop_map(|(packages, publishes)| Workload {
+            packages: packages.into_iter().collect(),
+            publishes,
+        })
+}
+
+/// Small RSA accumulators: a handful of small primes, some repeated.
+#[cfg(feature = "rsa")]
+pub fn accumulators(
+) -> impl Strategy<Value = crate::accumulator::rsa::Accumulator<crate::primitives::RsaGroup>> {
+    use crate::accumulator::Accumulator as _;
+    use crate::primitives::Prime;
+    use rug::Integer;
+
+    proptest::collection::vec(prop::sample::select(vec![2u32, 3, 5, 7, 11, 13]), 0..8).prop_map(
+        |members| {
+            let members: Vec<_> = members
+                .into_iter()
+                .map(|p| Prime::try_from(Integer::from(p)).unwrap())
+                .collect();
+            crate::accumulator::rsa::Accumulator::import(members.into())
+        },
+    )
+}
+
+/// Run `workload` against `technique`, checking that a client following along
+/// never sees a rollback, that every proof verifies, and that no package's
+/// revision goes backwards.
+pub fn replay(technique: &Technique, workload: &Workload) -> Result<(), TestCaseError> {
+    let name = technique.name();
+    let mut server = technique.import(workload.packages.clone());
+    let mut snapshot = server.get_metadata();
+    let mut known: HashSet<_> = workload.packages.iter().cloned().collect();
+    let mut latest: HashMap<PackageId, Revision> = HashMap::new();
+    for package in &workload.publishes {
+        server.publish(package.clone());
+        server.batch_process();
+        known.insert(package.clone());
+        if let Some(diff) = server.refresh_metadata(&snapshot) {
+            prop_assert!(snapshot.check_no_rollback(&diff), "{}: rollback", name);
+            snapshot.update(diff);
+        }
+        for package in &known {
+            let (revision, proof) = server.request_file(&snapshot, package);
+            prop_assert!(
+                snapshot.verify_membership(package, revision, proof),
+                "{}: bad proof for {:?}",
+                name,
+                package
+            );
+            if let Some(last) = latest.insert(package.clone(), revision) {
+                prop_assert!(last <= revision, "{}: {:?} went backwards", name, package);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that a client of `technique` refuses a diff from a stale server.
+///
+/// Two servers run `workload`; the newer one then publishes the first package
+/// again, plus a package the older one has never seen. A client follows the
+/// newer server, then gets the diff the older one would send. Accepting it
+/// would roll back a revision and drop a package, so `check_no_rollback` must
+/// fail.
+///
+/// (Techniques that don't track revisions, like `none`, can't pass this.)
+pub fn rollback(technique: &Technique, workload: &Workload) -> Result<(), TestCaseError> {
+    let name = technique.name();
+    let state = RepositoryState::new(
+        workload
+            .packages
+            .iter()
+            .map(|package| (package.clone(), Revision::default())),
+    );
+    let mut old = technique.import_state(&state);
+    let mut new = technique.import_state(&state);
+    let initial = old.get_metadata();
+    for package in workload.publishes.iter().chain(&workload.packages[..1]) {
+        for server in [&mut old, &mut new] {
+            server.publish(package.clone());
+            server.batch_process();
+        }
+    }
+    new.publish(workload.packages[0].clone());
+    new.publish(PackageId::from("new_package".to_string()));
+    new.batch_process();
+
+    let snapshot = new.get_metadata();
+    let stale = old
+        .refresh_metadata(&initial)
+        .expect("the old server has changed since the initial snapshot");
+    prop_assert!(
+        !snapshot.check_no_rollback(&stale),
+        "{}: accepted a rollback",
+        name
+    );
+    Ok(())
+}
+
+/// Check that `technique` proves *latest* revisions (see
+/// [`Authenticator::verify_latest`](crate::Authenticator::verify_latest)),
+/// not just that a revision was published at some point.
+///
+/// After `workload`, a client must accept each package at its latest revision
+/// and refuse the same proof for the revision before. Then the first package
+/// is published again: its old revision, with the proof that showed it was the
+/// latest, mustn't verify against the new snapshot.
+pub fn latest(technique: &Technique, workload: &Workload) -> Result<(), TestCaseError> {
+    let name = technique.name();
+    let state = RepositoryState::new(
+        workload
+            .packages
+            .iter()
+            .map(|package| (package.clone(), Revision::default())),
+    );
+    let mut server = technique.import_state(&state);
+    let mut expected: HashMap<PackageId, Revision> =
+        state.revisions().clone().into_iter().collect();
+    for package in &workload.publishes {
+        server.publish(package.clone());
+        server.batch_process();
+        expected
+            .entry(package.clone())
+            .and_modify(|revision| *revision = revision.checked_increment().unwrap())
+            .or_default();
+    }
+
+    let snapshot = server.get_metadata();
+    for (package, latest) in &expected {
+        let (revision, proof) = server.request_latest(&snapshot, package);
+        prop_assert_eq!(
+            revision,
+            *latest,
+            "{}: wrong revision of {:?}",
+            name,
+            package
+        );
+        prop_assert!(
+            snapshot.verify_latest(package, revision, proof),
+            "{}: rejected {:?}",
+            name,
+            package
+        );
+        if let Some(older) = revision.checked_decrement() {
+            let (_, proof) = server.request_latest(&snapshot, package);
+            prop_assert!(
+                !snapshot.verify_latest(package, older, proof),
+                "{}: accepted an older revision of {:?}",
+                name,
+                package
+            );
+        }
+    }
+
+    let package = &workload.packages[0];
+    let (superseded, proof) = server.request_latest(&snapshot, package);
+    server.publish(package.clone());
+    server.batch_process();
+    let snapshot = server.get_metadata();
+    prop_assert!(
+        !snapshot.verify_latest(package, superseded, proof),
+        "{}: accepted a superseded revision of {:?}",
+        name,
+        package
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authenticator::Authenticators;
+    use crate::log::Log;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn entries_are_a_log(entries in entries(64)) {
+            let len = entries.len();
+            prop_assert_eq!(Log::from(entries).into_iter().count(), len);
+        }
+
+        #[test]
+        fn replays(workload in workloads(16)) {
+            for name in ["none", "hackage", "mercury_diff", "mercury"] {
+                replay(&Authenticators::by_name(name).unwrap(), &workload)?;
+            }
+        }
+    }
+
+    proptest! {
+        // Every technique, some of them slow.
+        #![proptest_config(ProptestConfig::with_cases(4))]
+
+        #[test]
+        fn refuses_rollbacks(workload in workloads(8)) {
+            for technique in Authenticators::all() {
+                if matches!(technique.name(), "none" | "insecure") {
+                    continue;
+                }
+                rollback(&technique, &workload)?;
+            }
+        }
+
+        #[test]
+        fn proves_latest_revisions(workload in workloads(8)) {
+            for technique in Authenticators::all() {
+                if matches!(technique.name(), "none" | "insecure") {
+                    continue;
+                }
+                latest(&technique, &workload)?;
+            }
+        }
+    }
+}
@@ -0,0 +1,424 @@
+//! Searching a technique's settings for the best trade-off (`sssim tune`).
+//!
+//! Most settings trade publish time against refresh bandwidth: signing
+//! checkpoints less often, or merging the hybrid's hot set later, makes
+//! publishes cheaper and diffs bigger. Rather than guessing, [`tune`] tries
+//! every combination of candidate values (see [`Knob`]) against the same
+//! small synthetic workload, and picks the one with the smallest P95 refresh
+//! bandwidth whose P95 publish latency is within budget.
+//! [`TuneReport::best_section`] writes it out as a section for `--config`
+//! (see [`crate::config`]).
+use std::fmt;
+use std::str::FromStr;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use thiserror::Error;
+use time::Duration;
+
+use crate::authenticator::{DynAuthenticator, Technique};
+use crate::config::TechniqueConfig;
+use crate::latency::time_fn;
+use crate::log::PackageId;
+use crate::util::{byte, DataSized};
+
+/// Candidate values for one setting, as `name=value,value,...` (e.g.
+/// `checkpoint_interval=0,100,1000`).
+///
+/// Values are TOML, as in the config file. A knob with one value just fixes
+/// that setting for every trial (e.g. `precompute_delay_secs=0`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Knob {
+    pub name: String,
+    pub values: Vec<toml::Value>,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("invalid knob {0:?}: expected a setting and its values, like `checkpoint_interval=0,100`")]
+pub struct InvalidKnob(String);
+
+impl FromStr for Knob {
+    type Err = InvalidKnob;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidKnob(s.to_string());
+        let (name, values) = s.split_once('=').ok_or_else(invalid)?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(invalid());
+        }
+        let values = values
+            .split(',')
+            .map(|value| {
+                // A bare value isn't a TOML document, so parse it as one
+                // setting.
+                let table: toml::value::Table = toml::from_str(&format!("value = {value}"))?;
+                Ok(table["value"].clone())
+            })
+            .collect::<Result<Vec<_>, toml::de::Error>>()
+            .map_err(|_| invalid())?;
+        Ok(Self {
+            name: name.to_string(),
+            values,
+        })
+    }
+}
+
+impl fmt::Display for Knob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}=", self.name)?;
+        for (i, value) in self.values.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Every combination of the knobs' values, varying the last knob fastest.
+///
+/// No knobs gives one (empty) config: the technique's defaults.
+pub fn grid(knobs: &[Knob]) -> Vec<TechniqueConfig> {
+    let mut tables = vec![toml::value::Table::new()];
+    for knob in knobs {
+        tables = tables
+            .into_iter()
+            .flat_map(|table| {
+                knob.values.iter().map(move |value| {
+                    let mut table = table.clone();
+                    table.insert(knob.name.clone(), value.clone());
+                    table
+                })
+            })
+            .collect();
+    }
+    tables.into_iter().map(TechniqueConfig::from).collect()
+}
+
+/// Settings for [`tune`].
+#[derive(Debug, Clone)]
+pub struct TuneOptions {
+    /// Rounds of publishes and refreshes to run each config for.
+    pub rounds: usize,
+    /// Publishes in each round.
+    pub publishes_per_round: usize,
+    /// Number of clients, each with its own snapshot.
+    pub clients: usize,
+    /// Probability that a client refreshes in a given round (so some clients
+    /// fall further behind than others).
+    pub refresh_fraction: f64,
+    /// Probability that a publish is of a brand-new package.
+    pub new_package_fraction: f64,
+    /// Rule out configs whose P95 publish latency is above this.
+    pub max_publish_p95: Option<Duration>,
+    /// Seed for the workload (the same for every config).
+    pub seed: u64,
+}
+
+impl Default for TuneOptions {
+    fn default() -> Self {
+        Self {
+            rounds: 200,
+            publishes_per_round: 5,
+            clients: 8,
+            refresh_fraction: 0.5,
+            new_package_fraction: 0.1,
+            max_publish_p95: None,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TuneError {
+    #[error("{technique} doesn't take settings {config:?}: {source}")]
+    Config {
+        technique: &'static str,
+        config: String,
+        source: toml::de::Error,
+    },
+}
+
+/// How one config did.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trial {
+    pub config: TechniqueConfig,
+    /// 95th percentile of bytes per refresh (counting refreshes with nothing
+    /// new as zero).
+    pub refresh_p95_bytes: usize,
+    /// 95th percentile of time per publish (including processing it, for
+    /// pool techniques).
+    pub publish_p95: Duration,
+    /// Whether the publish latency is within budget.
+    pub feasible: bool,
+}
+
+impl fmt::Display for Trial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.config.is_empty() {
+            write!(f, "(defaults)")?;
+        } else {
+            write!(f, "{}", self.config.to_string().replace('\n', ", "))?;
+        }
+        write!(
+            f,
+            ": P95 refresh {} bytes, P95 publish {}",
+            self.refresh_p95_bytes, self.publish_p95
+        )?;
+        if !self.feasible {
+            write!(f, " (over budget)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Every config [`tune`] tried, in order, and which was best.
+#[derive(Debug, Clone)]
+pub struct TuneReport {
+    pub technique: &'static str,
+    pub trials: Vec<Trial>,
+    best: Option<usize>,
+}
+
+impl TuneReport {
+    /// The feasible config with the smallest P95 refresh bandwidth (then the
+    /// fastest publishes, then the earliest tried), if any was feasible.
+    pub fn best(&self) -> Option<&Trial> {
+        self.best.map(|i| &self.trials[i])
+    }
+
+    /// The best config, as a section for `--config`.
+    pub fn best_section(&self) -> Option<String> {
+        let best = self.best()?;
+        let mut section = format!("[technique.{}]\n", self.technique);
+        if !best.config.is_empty() {
+            section.push_str(&format!("{}\n", best.config));
+        }
+        Some(section)
+    }
+}
+
+/// The 95th percentile (nearest rank) of `values`, or the default if there are
+/// none.
+fn p95<T: Ord + Copy + Default>(mut values: Vec<T>) -> T {
+    if values.is_empty() {
+        return T::default();
+    }
+    values.sort_unstable();
+    let rank = (values.len() * 95 + 99) / 100;
+    values[rank - 1]
+}
+
+/// Run one config's workload against `server`.
+fn trial(
+    mut server: Box<dyn DynAuthenticator>,
+    packages: &[PackageId],
+    options: &TuneOptions,
+) -> (Vec<usize>, Vec<Duration>) {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut packages = packages.to_vec();
+    let mut snapshots: Vec<_> = (0..options.clients)
+        .map(|_| server.get_metadata())
+        .collect();
+    let mut refreshes = Vec::new();
+    let mut publishes = Vec::new();
+    let mut published = 0;
+    for _ in 0..options.rounds {
+        for _ in 0..options.publishes_per_round {
+            let package = if packages.is_empty() || rng.gen_bool(options.new_package_fraction) {
+                published += 1;
+                let package = PackageId::from(format!("new_package{published}"));
+                packages.push(package.clone());
+                package
+            } else {
+                packages[rng.gen_range(0..packages.len())].clone()
+            };
+            let (time, _) = time_fn(|| {
+                server.publish(package);
+                server.batch_process();
+            });
+            publishes.push(time);
+        }
+        for snapshot in &mut snapshots {
+            if !rng.gen_bool(options.refresh_fraction) {
+                continue;
+            }
+            match server.refresh_metadata(snapshot) {
+                Some(diff) => {
+                    refreshes.push(diff.size().get::<byte>());
+                    snapshot.update(diff);
+                }
+                None => refreshes.push(0),
+            }
+        }
+    }
+    (refreshes, publishes)
+}
+
+/// Try `technique` (freshly imported with `packages`) with every combination
+/// of `knobs`, and pick the best (see [`TuneReport::best`]).
+///
+/// Checks every config before running any. `on_trial` gets each result as
+/// it comes (e.g. to print progress).
+pub fn tune(
+    technique: &Technique,
+    packages: &[PackageId],
+    knobs: &[Knob],
+    options: &TuneOptions,
+    mut on_trial: impl FnMut(&Trial),
+) -> Result<TuneReport, TuneError> {
+    let configs = grid(knobs);
+    for config in &configs {
+        technique
+            .check_config(config)
+            .map_err(|source| TuneError::Config {
+                technique: technique.name(),
+                config: config.to_string(),
+                source,
+            })?;
+    }
+
+    let mut trials = Vec::with_capacity(configs.len());
+    for config in configs {
+        let server = technique
+            .import_with(packages.to_vec(), &config)
+            .expect("checked config");
+        let (refreshes, publishes) = trial(server, packages, options);
+        let publish_p95 = p95(publishes);
+        let trial = Trial {
+            config,
+            refresh_p95_bytes: p95(refreshes),
+            publish_p95,
+            feasible: options
+                .max_publish_p95
+                .map_or(true, |max| publish_p95 <= max),
+        };
+        on_trial(&trial);
+        trials.push(trial);
+    }
+
+    let best = trials
+        .iter()
+        .enumerate()
+        .filter(|(_, trial)| trial.feasible)
+        .min_by_key(|(i, trial)| (trial.refresh_p95_bytes, trial.publish_p95, *i))
+        .map(|(i, _)| i);
+    Ok(TuneReport {
+        technique: technique.name(),
+        trials,
+        best,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::authenticator::Authenticators;
+    use crate::testing::package_ids;
+
+    fn small() -> TuneOptions {
+        TuneOptions {
+            rounds: 20,
+            clients: 4,
+            ..TuneOptions::default()
+        }
+    }
+
+    #[test]
+    fn knobs() {
+        let knob: Knob = "checkpoint_interval = 0,100, 1000".parse().unwrap();
+        assert_eq!(knob.name, "checkpoint_interval");
+        assert_eq!(
+            knob.values,
+            [0, 100, 1000].map(toml::Value::Integer).to_vec()
+        );
+        assert_eq!(knob.to_string(), "checkpoint_interval=0,100,1000");
+        assert!("checkpoint_interval".parse::<Knob>().is_err());
+        assert!("=1".parse::<Knob>().is_err());
+        assert!("height=".parse::<Knob>().is_err());
+
+        let configs = grid(&[
+            "a=1,2".parse().unwrap(),
+            "b=true".parse().unwrap(),
+            "c=3,4".parse().unwrap(),
+        ]);
+        let configs: Vec<_> = configs.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            configs,
+            [
+                "a = 1\nb = true\nc = 3",
+                "a = 1\nb = true\nc = 4",
+                "a = 2\nb = true\nc = 3",
+                "a = 2\nb = true\nc = 4",
+            ]
+        );
+        assert_eq!(grid(&[]), vec![TechniqueConfig::default()]);
+    }
+
+    #[test]
+    fn percentile() {
+        assert_eq!(p95((1..=100).collect()), 95);
+        assert_eq!(p95(vec![3, 1, 2]), 3);
+        assert_eq!(p95(Vec::<usize>::new()), 0);
+    }
+
+    #[test]
+    fn defaults_without_knobs() {
+        let technique = Authenticators::by_name("hackage").unwrap();
+        let mut seen = 0;
+        let report = tune(&technique, &package_ids(100), &[], &small(), |_| seen += 1).unwrap();
+        assert_eq!(seen, 1);
+        let best = report.best().unwrap();
+        assert!(best.feasible);
+        assert!(best.refresh_p95_bytes > 0);
+        assert_eq!(report.best_section().unwrap(), "[technique.hackage]\n");
+    }
+
+    #[test]
+    fn budget_rules_out_configs() {
+        let technique = Authenticators::by_name("hackage").unwrap();
+        // No publish is that fast.
+        let options = TuneOptions {
+            max_publish_p95: Some(Duration::ZERO - Duration::NANOSECOND),
+            ..small()
+        };
+        let report = tune(&technique, &package_ids(100), &[], &options, |_| {}).unwrap();
+        assert!(!report.trials[0].feasible);
+        assert!(report.best().is_none());
+        assert!(report.best_section().is_none());
+    }
+
+    #[test]
+    fn unknown_settings() {
+        let technique = Authenticators::by_name("hackage").unwrap();
+        let knobs = ["height=64".parse().unwrap()];
+        let result = tune(&technique, &package_ids(10), &knobs, &small(), |_| {
+            panic!("shouldn't run anything")
+        });
+        assert!(matches!(result, Err(TuneError::Config { .. })));
+    }
+
+    #[test]
+    #[cfg(feature = "smt")]
+    fn picks_smallest_refreshes() {
+        let technique = Authenticators::by_name("sparse_merkle").unwrap();
+        let knobs = [
+            "precompute_delay_secs=0".parse().unwrap(),
+            "height=64,256".parse().unwrap(),
+        ];
+        let report = tune(&technique, &package_ids(100), &knobs, &small(), |_| {}).unwrap();
+        assert_eq!(report.trials.len(), 2);
+        let best = report.best().unwrap();
+        assert!(report
+            .trials
+            .iter()
+            .all(|trial| best.refresh_p95_bytes <= trial.refresh_p95_bytes));
+        assert!(report
+            .best_section()
+            .unwrap()
+            .starts_with("[technique.sparse_merkle]\n"));
+    }
+}
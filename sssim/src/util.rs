@@ -1,5 +1,13 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock, RwLockReadGuard};
 
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
 pub use uom::si::information::byte;
 use uom::ConstZero;
 
@@ -51,12 +59,280 @@ where
         }
 }
 
+/// The length of `value`'s bincode encoding.
+pub fn serialized_size<T: Serialize + ?Sized>(value: &T) -> Information {
+    let bytes = bincode::serialized_size(value).expect("serializing to measure size");
+    Information::new::<byte>(bytes as usize)
+}
+
+/// Sizes a value by its bincode encoding (see [`serialized_size`]), rather
+/// than by hand-written [`DataSized`] math.
+///
+/// [`Self::cached`] measures once up front, for values that get sized a lot
+/// but never change.
+#[derive(Debug, Clone)]
+pub struct DataSizeFromSerialize<T> {
+    value: T,
+    cached: Option<Information>,
+}
+
+impl<T: Serialize> DataSizeFromSerialize<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            value,
+            cached: None,
+        }
+    }
+
+    pub fn cached(value: T) -> Self {
+        let size = serialized_size(&value);
+        Self {
+            value,
+            cached: Some(size),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Deref for DataSizeFromSerialize<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: Serialize> DataSized for DataSizeFromSerialize<T> {
+    fn size(&self) -> Information {
+        self.cached.unwrap_or_else(|| serialized_size(&self.value))
+    }
+}
+
+static SIZING_POLICY: OnceCell<SizingPolicy> = OnceCell::new();
+
+/// How to size the nested types that opt in (e.g. the RSA pool's diffs).
+///
+/// Their hand-written sizes are easy to get subtly wrong (say, forgetting a
+/// map's length prefix). Running once with each policy, into separate results
+/// dbs, cross-checks them against a real encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SizingPolicy {
+    /// Hand-written [`DataSized`] math.
+    #[default]
+    Manual,
+    /// The bincode encoding (see [`DataSizeFromSerialize`]).
+    Serialized,
+}
+
+#[derive(Error, Debug)]
+#[error("unknown sizing policy {0:?} (expected `manual` or `serialized`)")]
+pub struct UnknownSizingPolicy(String);
+
+#[derive(Error, Debug)]
+#[error("the sizing policy was already set")]
+pub struct SizingPolicyAlreadySet;
+
+impl SizingPolicy {
+    /// Use this policy from now on.
+    ///
+    /// Fails if a policy was already installed.
+    pub fn install(self) -> Result<(), SizingPolicyAlreadySet> {
+        SIZING_POLICY.set(self).map_err(|_| SizingPolicyAlreadySet)
+    }
+
+    /// The installed policy ([`Self::Manual`] if none was).
+    pub fn current() -> Self {
+        SIZING_POLICY.get().copied().unwrap_or_default()
+    }
+
+    /// The size of `value` under this policy, given its hand-written size.
+    pub fn size<T: Serialize + ?Sized>(self, value: &T, manual: Information) -> Information {
+        match self {
+            Self::Manual => manual,
+            Self::Serialized => serialized_size(value),
+        }
+    }
+}
+
+impl FromStr for SizingPolicy {
+    type Err = UnknownSizingPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "manual" => Ok(Self::Manual),
+            "serialized" => Ok(Self::Serialized),
+            _ => Err(UnknownSizingPolicy(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for SizingPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Manual => write!(f, "manual"),
+            Self::Serialized => write!(f, "serialized"),
+        }
+    }
+}
+
+/// A copy-on-write value: clones share it until one of them writes.
+///
+/// For the big maps in server state, so that cloning an authenticator for
+/// each trial is cheap. Mutable access copies the value if it's shared, so
+/// call [`Shared::unshare`] *before* timing anything that writes.
+#[derive(Debug, Default)]
+pub struct Shared<T>(Arc<T>);
+
+impl<T> Shared<T> {
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(value))
+    }
+}
+
+impl<T: Clone> Shared<T> {
+    /// Make sure no clone shares this value (copying it if needed).
+    pub fn unshare(&mut self) {
+        Arc::make_mut(&mut self.0);
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<T> From<T> for Shared<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T> Deref for Shared<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Clone> DerefMut for Shared<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        Arc::make_mut(&mut self.0)
+    }
+}
+
+impl<T: Serialize> Serialize for Shared<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.as_ref().serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Shared<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Self::new)
+    }
+}
+
+/// A map that can be filled in through a shared reference.
+///
+/// For caches of values that are cheap to look up but expensive to compute,
+/// like nonmembership proofs, so that read paths (e.g.
+/// [`Authenticator::request_file`](crate::authenticator::Authenticator::request_file))
+/// can take `&self` and serve several threads at once. Clones don't share
+/// entries.
+#[derive(Debug)]
+pub struct Cache<K, V>(RwLock<HashMap<K, V>>);
+
+impl<K, V> Cache<K, V> {
+    /// Read-lock the whole map (e.g. to iterate over it).
+    ///
+    /// Don't call [`Cache::get_or_insert_with`] while holding this.
+    pub fn read(&self) -> RwLockReadGuard<'_, HashMap<K, V>> {
+        self.0.read().expect("cache lock poisoned")
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+
+    /// Drop every entry (needs no lock: we have the only reference).
+    pub fn clear(&mut self) {
+        self.get_mut().clear();
+    }
+
+    fn get_mut(&mut self) -> &mut HashMap<K, V> {
+        self.0.get_mut().expect("cache lock poisoned")
+    }
+}
+
+impl<K: Eq + Hash, V: Clone> Cache<K, V> {
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.read().get(key).cloned()
+    }
+
+    /// The cached value for `key`, or else `compute()` (cached if `Some`).
+    ///
+    /// `compute` runs without the lock held, so two threads that miss at once
+    /// may both compute; the values should be the same either way.
+    pub fn get_or_insert_with(&self, key: &K, compute: impl FnOnce() -> Option<V>) -> Option<V>
+    where
+        K: Clone,
+    {
+        if let Some(value) = self.get(key) {
+            return Some(value);
+        }
+        let value = compute()?;
+        self.0
+            .write()
+            .expect("cache lock poisoned")
+            .insert(key.clone(), value.clone());
+        Some(value)
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.get_mut().remove(key)
+    }
+}
+
+impl<K, V> Default for Cache<K, V> {
+    fn default() -> Self {
+        Self(RwLock::new(HashMap::new()))
+    }
+}
+
+impl<K: Clone, V: Clone> Clone for Cache<K, V> {
+    fn clone(&self) -> Self {
+        Self(RwLock::new(self.read().clone()))
+    }
+}
+
+impl<K: Serialize, V: Serialize> Serialize for Cache<K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.read().serialize(serializer)
+    }
+}
+
+impl<'de, K: Deserialize<'de> + Eq + Hash, V: Deserialize<'de>> Deserialize<'de> for Cache<K, V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HashMap::deserialize(deserializer).map(|map| Self(RwLock::new(map)))
+    }
+}
+
 impl DataSized for () {
     fn size(&self) -> Information {
         Information::ZERO
     }
 }
 
+#[cfg(feature = "rsa")]
 impl DataSized for rug::Integer {
     fn size(&self) -> Information {
         Information::new::<byte>(self.significant_digits::<u8>())
@@ -101,3 +377,57 @@ impl<T: DataSized> DataSized for Option<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_copies_on_write() {
+        let original = Shared::new(vec![1, 2, 3]);
+        let mut copy = original.clone();
+        assert!(Arc::ptr_eq(&original.0, &copy.0));
+
+        copy.unshare();
+        assert!(!Arc::ptr_eq(&original.0, &copy.0));
+        copy.push(4);
+        assert_eq!(*original, vec![1, 2, 3]);
+        assert_eq!(*copy, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn cache_fills_through_shared_reference() {
+        let cache: Cache<u32, String> = Cache::default();
+        assert_eq!(cache.get_or_insert_with(&1, || None), None);
+        assert!(cache.is_empty());
+        let value = cache.get_or_insert_with(&1, || Some("one".to_string()));
+        assert_eq!(value.as_deref(), Some("one"));
+        // Cached: doesn't compute again.
+        let value = cache.get_or_insert_with(&1, || unreachable!());
+        assert_eq!(value.as_deref(), Some("one"));
+
+        let mut copy = cache.clone();
+        copy.clear();
+        assert!(copy.is_empty());
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn sized_from_serialize() {
+        let values: Vec<u64> = vec![1, 2, 3];
+        // Hand-written: just the elements. Encoded: plus an 8-byte length.
+        assert_eq!(values.size(), Information::new::<byte>(24));
+        let sized = DataSizeFromSerialize::new(values.clone());
+        assert_eq!(sized.size(), Information::new::<byte>(32));
+        assert_eq!(DataSizeFromSerialize::cached(values).size(), sized.size());
+
+        let manual = Information::new::<byte>(24);
+        assert_eq!(SizingPolicy::Manual.size(&*sized, manual), manual);
+        assert_eq!(SizingPolicy::Serialized.size(&*sized, manual), sized.size());
+        assert_eq!(
+            "serialized".parse::<SizingPolicy>().unwrap(),
+            SizingPolicy::Serialized
+        );
+        assert_eq!(SizingPolicy::Manual.to_string(), "manual");
+    }
+}
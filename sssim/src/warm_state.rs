@@ -0,0 +1,328 @@
+//! Saving and restoring server-side authenticator state.
+//!
+//! Precompute dominates the runtime for some techniques (RSA takes minutes even
+//! for small registries), which makes it painful to smoke-test the later
+//! phases. Instead, we can build small states once, save them as fixtures
+//! (see `fixtures/README.md`), and load them directly after that. None are
+//! checked in: they're a local cache, stamped with [`STATE_VERSION`] so that a
+//! stale one gets rebuilt rather than loaded.
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{authenticator, log::PackageId, Authenticator};
+
+/// Number of packages in the fixtures.
+pub const FIXTURE_PACKAGES: usize = 1000;
+
+/// Version of the saved server states.
+///
+/// Bump this whenever a technique's state changes in a way that an old file
+/// would still deserialize but mean something else (its layout, how packages
+/// are hashed, how group elements are encoded, ...).
+pub const STATE_VERSION: u32 = 4;
+
+/// Start of every file written by [`save`], before the version.
+const MAGIC: [u8; 8] = *b"sssimwst";
+
+/// Server state that can be written out and read back in (with bincode).
+pub trait WarmState: Sized {
+    fn save_state<W: Write>(&self, writer: W) -> bincode::Result<()>;
+
+    fn load_state<R: Read>(reader: R) -> bincode::Result<Self>;
+
+    /// Like [`Self::load_state`], from bytes already in memory. A corrupt
+    /// length then fails to load, rather than allocating whatever it says.
+    fn load_state_from_slice(bytes: &[u8]) -> bincode::Result<Self>;
+}
+
+fn save_serde<T: Serialize, W: Write>(value: &T, writer: W) -> bincode::Result<()> {
+    bincode::serialize_into(writer, value)
+}
+
+fn load_serde<T: DeserializeOwned, R: Read>(reader: R) -> bincode::Result<T> {
+    bincode::deserialize_from(reader)
+}
+
+/// Implement [`WarmState`] by serializing the entire authenticator.
+macro_rules! warm_state_via_serde {
+    ($($type:ty),* $(,)?) => {
+        $(
+            impl WarmState for $type {
+                fn save_state<W: Write>(&self, writer: W) -> bincode::Result<()> {
+                    save_serde(self, writer)
+                }
+
+                fn load_state<R: Read>(reader: R) -> bincode::Result<Self> {
+                    load_serde(reader)
+                }
+
+                fn load_state_from_slice(bytes: &[u8]) -> bincode::Result<Self> {
+                    bincode::deserialize(bytes)
+                }
+            }
+        )*
+    };
+}
+
+warm_state_via_serde!(
+    authenticator::NoAuth,
+    authenticator::Insecure,
+    authenticator::Hackage,
+    authenticator::MercuryDiff,
+    authenticator::VanillaTuf,
+);
+
+#[cfg(feature = "rsa")]
+warm_state_via_serde!(
+    authenticator::Rsa,
+    authenticator::RsaPool,
+    authenticator::RsaCheckpoint,
+    authenticator::RsaPruned,
+    authenticator::RsaOutsourced,
+    authenticator::RsaStateless,
+    authenticator::Hybrid,
+);
+
+/// Directory for the fixtures.
+pub fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures")
+}
+
+/// Where the state for `technique` with `packages` packages lives in `dir`.
+pub fn fixture_path(dir: &Path, technique: &str, packages: usize) -> PathBuf {
+    dir.join(format!("{technique}-{packages}.bincode"))
+}
+
+/// The packages in a fixture of the given size (named as in the benchmark).
+pub fn fixture_packages(packages: usize) -> Vec<PackageId> {
+    (0..packages)
+        .map(|i| format!("package{i}"))
+        .map(PackageId::from)
+        .collect()
+}
+
+/// Save `authenticator` to `path`, stamped with [`STATE_VERSION`].
+pub fn save<A: WarmState>(authenticator: &A, path: &Path) -> bincode::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&STATE_VERSION.to_le_bytes())?;
+    authenticator.save_state(&mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// The version stamped on a saved state (0 if it has no stamp).
+fn read_version<R: Read>(mut reader: R) -> bincode::Result<u32> {
+    let mut magic = [0; MAGIC.len()];
+    let mut version = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Ok(0);
+    }
+    reader.read_exact(&mut version)?;
+    Ok(u32::from_le_bytes(version))
+}
+
+/// The version of the state saved at `path` (see [`STATE_VERSION`]).
+pub fn saved_version(path: &Path) -> bincode::Result<u32> {
+    read_version(File::open(path)?)
+}
+
+/// Load the state saved at `path`, refusing one from another
+/// [`STATE_VERSION`].
+pub fn load<A: WarmState>(path: &Path) -> bincode::Result<A> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let version = read_version(&mut reader)?;
+    if version != STATE_VERSION {
+        return Err(Box::new(bincode::ErrorKind::Custom(format!(
+            "{} has state version {version}, but this build uses version {STATE_VERSION}",
+            path.display()
+        ))));
+    }
+    A::load_state(reader)
+}
+
+/// Load the state at `path`, or import `packages` and save it there if it's
+/// missing or stale.
+pub fn load_or_import<A>(path: &Path, packages: Vec<PackageId>) -> bincode::Result<A>
+where
+    A: Authenticator + WarmState,
+{
+    if path.exists() && saved_version(path).ok() == Some(STATE_VERSION) {
+        return load(path);
+    }
+    let authenticator = A::batch_import(packages);
+    save(&authenticator, path)?;
+    Ok(authenticator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "rsa")]
+    use crate::PoolAuthenticator;
+
+    /// Set to (re)generate missing or stale fixtures instead of importing in
+    /// memory.
+    const BLESS_VAR: &str = "SSSIM_BLESS_FIXTURES";
+
+    fn warm<A: Authenticator + WarmState>() -> A {
+        let path = fixture_path(&fixtures_dir(), A::name(), FIXTURE_PACKAGES);
+        let packages = fixture_packages(FIXTURE_PACKAGES);
+        if std::env::var_os(BLESS_VAR).is_some() {
+            load_or_import(&path, packages).unwrap()
+        } else if path.exists() && saved_version(&path).ok() == Some(STATE_VERSION) {
+            load(&path).unwrap()
+        } else {
+            A::batch_import(packages)
+        }
+    }
+
+    fn check_download<A: Authenticator>(
+        authenticator: &mut A,
+        snapshot: &A::ClientSnapshot,
+        package: &PackageId,
+    ) {
+        let (revision, proof) = authenticator.request_file(A::id(snapshot), package);
+        assert!(A::verify_membership(snapshot, package, revision, proof));
+    }
+
+    /// Run the download and refresh phases against a warm state.
+    fn smoke<A, F>(process: F)
+    where
+        A: Authenticator + WarmState,
+        F: Fn(&mut A),
+    {
+        let authenticator: A = warm();
+
+        // Round-trip through the serialized form, too.
+        let mut buffer = Vec::new();
+        authenticator.save_state(&mut buffer).unwrap();
+        let mut authenticator = A::load_state(buffer.as_slice()).unwrap();
+
+        let mut snapshot = authenticator.get_metadata();
+        for package in fixture_packages(FIXTURE_PACKAGES).iter().step_by(100) {
+            check_download(&mut authenticator, &snapshot, package);
+        }
+
+        let package = PackageId::from("new_package".to_string());
+        authenticator.publish(package.clone());
+        process(&mut authenticator);
+        if let Some(diff) = authenticator.refresh_metadata(A::id(&snapshot)) {
+            assert!(A::check_no_rollback(&snapshot, &diff));
+            A::update(&mut snapshot, diff);
+        }
+        check_download(&mut authenticator, &snapshot, &package);
+    }
+
+    #[test]
+    fn stale_states_rebuilt() {
+        let path = std::env::temp_dir().join(format!("sssim-warm-{}.bincode", std::process::id()));
+        let packages = fixture_packages(10);
+        let hackage = authenticator::Hackage::batch_import(packages.clone());
+        save(&hackage, &path).unwrap();
+        assert_eq!(saved_version(&path).unwrap(), STATE_VERSION);
+        load::<authenticator::Hackage>(&path).unwrap();
+
+        // An unstamped (or older) state doesn't load, and gets rebuilt.
+        let mut unstamped = Vec::new();
+        hackage.save_state(&mut unstamped).unwrap();
+        fs::write(&path, unstamped).unwrap();
+        assert!(load::<authenticator::Hackage>(&path).is_err());
+        load_or_import::<authenticator::Hackage>(&path, packages).unwrap();
+        assert_eq!(saved_version(&path).unwrap(), STATE_VERSION);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn smoke_none() {
+        smoke::<authenticator::NoAuth, _>(|_| {});
+    }
+
+    #[test]
+    fn smoke_insecure() {
+        smoke::<authenticator::Insecure, _>(|_| {});
+    }
+
+    #[test]
+    fn smoke_hackage() {
+        smoke::<authenticator::Hackage, _>(|_| {});
+    }
+
+    #[test]
+    fn smoke_mercury_diff() {
+        smoke::<authenticator::MercuryDiff, _>(|_| {});
+    }
+
+    #[test]
+    fn smoke_vanilla_tuf() {
+        smoke::<authenticator::VanillaTuf, _>(|_| {});
+    }
+
+    #[cfg(feature = "merkle")]
+    #[test]
+    fn smoke_merkle_bpt() {
+        smoke::<authenticator::MerkleBpt, _>(|_| {});
+    }
+
+    #[cfg(feature = "merkle")]
+    #[test]
+    fn smoke_merkle_bpt_private() {
+        smoke::<authenticator::MerkleBptPrivate, _>(|_| {});
+    }
+
+    #[cfg(feature = "smt")]
+    #[test]
+    fn smoke_sparse_merkle() {
+        smoke::<authenticator::SparseMerkle, _>(|_| {});
+    }
+
+    #[cfg(feature = "rsa")]
+    #[test]
+    fn smoke_rsa() {
+        smoke::<authenticator::Rsa, _>(|_| {});
+    }
+
+    #[cfg(feature = "rsa")]
+    #[test]
+    fn smoke_rsa_pool() {
+        smoke::<authenticator::RsaPool, _>(PoolAuthenticator::batch_process);
+    }
+
+    #[cfg(feature = "rsa")]
+    #[test]
+    fn smoke_rsa_checkpoint() {
+        smoke::<authenticator::RsaCheckpoint, _>(|_| {});
+    }
+
+    #[cfg(feature = "rsa")]
+    #[test]
+    fn smoke_rsa_pruned() {
+        smoke::<authenticator::RsaPruned, _>(|_| {});
+    }
+
+    #[cfg(feature = "rsa")]
+    #[test]
+    fn smoke_rsa_outsourced() {
+        smoke::<authenticator::RsaOutsourced, _>(|_| {});
+    }
+
+    #[cfg(feature = "rsa")]
+    #[test]
+    fn smoke_rsa_stateless() {
+        smoke::<authenticator::RsaStateless, _>(|_| {});
+    }
+
+    #[cfg(feature = "rsa")]
+    #[test]
+    fn smoke_hybrid() {
+        smoke::<authenticator::Hybrid, _>(|_| {});
+    }
+}
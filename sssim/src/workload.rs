@@ -0,0 +1,113 @@
+//! Helpers for generating synthetic workloads.
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, Copy, PartialEq)]
+pub enum InvalidZipf {
+    #[error("Zipf distribution needs at least one rank")]
+    NoRanks,
+    #[error("Zipf exponent {0} must be non-negative")]
+    Exponent(f64),
+}
+
+/// Zipf-distributed ranks.
+///
+/// Rank `i` (0-indexed) is drawn with probability proportional to
+/// `1 / (i + 1)^exponent`, so rank 0 is the most popular. Real package
+/// registries are heavily skewed like this.
+#[derive(Debug, Clone)]
+pub struct Zipf {
+    inner: WeightedIndex<f64>,
+}
+
+impl Zipf {
+    pub fn new(n: usize, exponent: f64) -> Result<Self, InvalidZipf> {
+        if n == 0 {
+            return Err(InvalidZipf::NoRanks);
+        }
+        if !(exponent.is_finite() && exponent >= 0.0) {
+            return Err(InvalidZipf::Exponent(exponent));
+        }
+        let weights = (1..=n).map(|rank| 1.0 / (rank as f64).powf(exponent));
+        Ok(Self {
+            inner: WeightedIndex::new(weights).expect("weights are positive"),
+        })
+    }
+}
+
+impl Distribution<usize> for Zipf {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        self.inner.sample(rng)
+    }
+}
+
+/// How to pick which package a user downloads.
+#[derive(Debug, Clone)]
+pub enum Popularity {
+    /// Every package is equally likely.
+    Uniform,
+    /// Earlier packages are more popular.
+    Zipf(Zipf),
+}
+
+impl Popularity {
+    /// Uniform if `zipf_exponent` is `None`.
+    pub fn new(zipf_exponent: Option<f64>, n: usize) -> Result<Self, InvalidZipf> {
+        match zipf_exponent {
+            Some(exponent) => Ok(Popularity::Zipf(Zipf::new(n, exponent)?)),
+            None => Ok(Popularity::Uniform),
+        }
+    }
+
+    /// Choose an item from `items`; they must be in popularity order.
+    pub fn choose<'a, T, R: Rng + ?Sized>(&self, items: &'a [T], rng: &mut R) -> Option<&'a T> {
+        match self {
+            Popularity::Uniform => rand::seq::SliceRandom::choose(items, rng),
+            Popularity::Zipf(zipf) => items.get(zipf.sample(rng)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_zipf_skew() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let zipf = Zipf::new(100, 2.0).unwrap();
+        let mut counts = [0usize; 100];
+        for _ in 0..10_000 {
+            counts[zipf.sample(&mut rng)] += 1;
+        }
+        // P(rank 0) = 1 / zeta(2) ~= 0.61
+        assert!(counts[0] > 5_000);
+        assert!(counts[0] > counts[1]);
+        assert!(counts[1] > counts[99]);
+    }
+
+    #[test]
+    fn test_zipf_zero_exponent_is_uniform() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let popularity = Popularity::new(Some(0.0), 4).unwrap();
+        let items = [0, 1, 2, 3];
+        let mut counts = [0usize; 4];
+        for _ in 0..10_000 {
+            counts[*popularity.choose(&items, &mut rng).unwrap()] += 1;
+        }
+        for count in counts {
+            assert!((2_000..3_000).contains(&count));
+        }
+    }
+    #[test]
+    fn test_zipf_invalid() {
+        assert_eq!(Zipf::new(0, 1.0).unwrap_err(), InvalidZipf::NoRanks);
+        assert_eq!(
+            Zipf::new(10, -1.0).unwrap_err(),
+            InvalidZipf::Exponent(-1.0)
+        );
+        assert!(Zipf::new(10, f64::NAN).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+//! End-to-end bandwidth regression tests.
+//!
+//! Every registered technique runs through the same small workload (100
+//! events from a fixed seed), and the sizes in each event's
+//! [`ResourceUsage`] (not the timings) are compared against
+//! `tests/golden/<technique>.txt`, one line per event.
+//!
+//! When a change is *supposed* to move sizes, regenerate the files with:
+//!
+//! ```text
+//! SSSIM_BLESS_GOLDEN=1 cargo test --release --test golden
+//! ```
+//!
+//! and check the diff. A technique without a golden file fails the test unless
+//! blessing, so new techniques must be blessed before they land.
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sssim::authenticator::{self, RepositoryState};
+use sssim::prelude::*;
+#[cfg(feature = "merkle")]
+use sssim::primitives::merkle::Privacy;
+use sssim::util::byte;
+
+const SEED: u64 = 0;
+const EVENTS: usize = 100;
+const PACKAGES: usize = 10;
+const USERS: usize = 5;
+
+/// The initial packages, then the events.
+fn workload() -> (Vec<PackageId>, Vec<Action>) {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut packages: Vec<_> = (0..PACKAGES)
+        .map(|i| PackageId::from(format!("package{i}")))
+        .collect();
+    let initial = packages.clone();
+    let mut actions = Vec::with_capacity(EVENTS + 1);
+    while actions.len() < EVENTS {
+        let user = UserId::from(format!("user{}", rng.gen_range(0..USERS)));
+        let length = rng.gen_bool(0.5).then(|| rng.gen_range(1..1 << 20));
+        match rng.gen_range(0..20) {
+            0..=9 => {
+                // Clients refresh before they download (or their proofs
+                // might not verify).
+                let id = packages[rng.gen_range(0..packages.len())].clone();
+                actions.push(Action::RefreshMetadata { user: user.clone() });
+                actions.push(Action::Download {
+                    user,
+                    package: Package { id, length: None },
+                });
+            }
+            10..=13 => actions.push(Action::RefreshMetadata { user }),
+            14..=18 => {
+                // Mostly new revisions, sometimes new packages.
+                let id = if rng.gen_bool(0.25) {
+                    packages.push(PackageId::from(format!("package{}", packages.len())));
+                    packages.last().unwrap().clone()
+                } else {
+                    packages[rng.gen_range(0..packages.len())].clone()
+                };
+                actions.push(Action::Publish {
+                    package: Package { id, length },
+                });
+            }
+            _ => actions.push(Action::Goodbye { user }),
+        }
+    }
+    actions.truncate(EVENTS);
+    (initial, actions)
+}
+
+/// The sizes in each event's usage, one line per event.
+fn simulate<A>() -> Vec<String>
+where
+    A: Authenticator,
+    A::ClientSnapshot: Default,
+{
+    simulate_with(A::import_state)
+}
+
+/// [`simulate`], with the server set up by `import`.
+fn simulate_with<A>(import: impl FnOnce(&RepositoryState) -> A) -> Vec<String>
+where
+    A: Authenticator,
+    A::ClientSnapshot: Default,
+{
+    let (packages, actions) = workload();
+    // Importing from a state skips any simulated precompute delay.
+    let state = RepositoryState::new(packages.into_iter().map(|p| (p, Revision::default())));
+    let mut simulator = Simulator::new(import(&state));
+    actions
+        .into_iter()
+        .map(|mut action| {
+            let usage = simulator.process(&mut action);
+            format!(
+                "bandwidth={} origin={} cdn={} storage={} witness_state={} witness_update={}",
+                usage.bandwidth.get::<byte>(),
+                usage.origin_bandwidth.get::<byte>(),
+                usage.cdn_bandwidth.get::<byte>(),
+                usage.storage.get::<byte>(),
+                usage.user_witness_state.get::<byte>(),
+                usage.witness_update_bandwidth.get::<byte>(),
+            )
+        })
+        .collect()
+}
+
+type Simulate = fn() -> Vec<String>;
+
+/// Every technique in [`Authenticators::all`], by type.
+fn techniques() -> Vec<(&'static str, Simulate)> {
+    vec![
+        ("none", simulate::<authenticator::NoAuth>),
+        ("insecure", simulate::<authenticator::Insecure>),
+        ("hackage", simulate::<authenticator::Hackage>),
+        ("mercury_diff", simulate::<authenticator::MercuryDiff>),
+        ("mercury", simulate::<authenticator::VanillaTuf>),
+        #[cfg(feature = "smt")]
+        ("sparse_merkle", simulate::<authenticator::SparseMerkle>),
+        #[cfg(feature = "smt")]
+        (
+            "sparse_merkle_versions",
+            simulate::<authenticator::SparseMerkleVersions>,
+        ),
+        #[cfg(feature = "smt")]
+        (
+            "sparse_merkle_blake3",
+            simulate::<authenticator::SparseMerkleBlake3>,
+        ),
+        #[cfg(feature = "merkle")]
+        ("merkle_bpt", simulate::<authenticator::MerkleBpt>),
+        #[cfg(feature = "merkle")]
+        ("merkle_bpt_private", || {
+            // A random secret would give different commitments every run.
+            let privacy = Privacy::new([1; 32]);
+            simulate_with(|state| {
+                authenticator::MerkleBptPrivate::import_state(state).with_privacy(privacy)
+            })
+        }),
+        #[cfg(feature = "rsa")]
+        ("rsa", simulate::<authenticator::Rsa>),
+        #[cfg(feature = "rsa")]
+        ("rsa_pool", simulate::<authenticator::RsaPool>),
+        #[cfg(feature = "rsa")]
+        ("rsa_checkpoint", simulate::<authenticator::RsaCheckpoint>),
+        #[cfg(feature = "rsa")]
+        ("rsa_pruned", simulate::<authenticator::RsaPruned>),
+        #[cfg(feature = "rsa")]
+        ("rsa_outsourced", simulate::<authenticator::RsaOutsourced>),
+        #[cfg(feature = "rsa")]
+        ("rsa_stateless", simulate::<authenticator::RsaStateless>),
+        #[cfg(feature = "rsa")]
+        ("hybrid", simulate::<authenticator::Hybrid>),
+    ]
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{name}.txt"))
+}
+
+#[test]
+fn covers_registry() {
+    let registered: HashSet<_> = Authenticators::all()
+        .iter()
+        .map(|technique| technique.name())
+        .collect();
+    let tested: HashSet<_> = techniques().into_iter().map(|(name, _)| name).collect();
+    assert_eq!(tested, registered);
+}
+
+#[test]
+fn golden_usage() {
+    let bless = std::env::var_os("SSSIM_BLESS_GOLDEN").is_some();
+    for (name, simulate) in techniques() {
+        let lines = simulate();
+        assert_eq!(lines.len(), EVENTS, "{name}");
+        let path = golden_path(name);
+        if bless {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+            continue;
+        }
+        let golden = std::fs::read_to_string(&path).unwrap_or_else(|err| {
+            panic!(
+                "{name}: can't read {}: {err} (set SSSIM_BLESS_GOLDEN=1)",
+                path.display()
+            )
+        });
+        for (event, (actual, expected)) in lines.iter().zip(golden.lines()).enumerate() {
+            assert_eq!(actual, expected, "{name}: event {event} changed");
+        }
+        assert_eq!(golden.lines().count(), EVENTS, "{name}: stale golden file");
+    }
+}